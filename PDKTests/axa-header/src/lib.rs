@@ -187,6 +187,8 @@ wVIXRfPThZaZmjZRSPiXpXfUU47WlOhStZ7btWhrgRHuntUf3GVT84gv0uHyOSMX
         
         
         
+            // the embedded key is already PKCS#8, left out of the pdk keys migration: this prototype
+            // is replaced by mule-flex-axa-context-header-pdk, which parses its configured key with PemKey
             let key = RS256KeyPair::from_pem(&pem);
             println!("Key Created");
             match key {
@@ -0,0 +1,37 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use pdk::api::expression::Expression;
+use pdk::api::http::baggage::BaggageLimits;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+pub struct Entry {
+    pub key: String,
+    pub value: Expression,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PolicyConfiguration {
+    /// The key of the entry with the organization of the API. Empty to leave it out.
+    #[serde(alias = "tenantKey", default = "default_tenant_key")]
+    pub tenant_key: String,
+    /// The key of the entry with the id of the API. Empty to leave it out.
+    #[serde(alias = "apiIdKey", default = "default_api_id_key")]
+    pub api_id_key: String,
+    /// Entries resolved on each request.
+    #[serde(default)]
+    pub entries: Vec<Entry>,
+    /// The keys of the members sent by the client that are propagated. Empty to propagate
+    /// them all.
+    #[serde(alias = "allowedKeys", default)]
+    pub allowed_keys: Vec<String>,
+    #[serde(flatten)]
+    pub limits: BaggageLimits,
+}
+
+fn default_tenant_key() -> String {
+    "tenant".to_string()
+}
+
+fn default_api_id_key() -> String {
+    "api.id".to_string()
+}
@@ -0,0 +1,236 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use crate::config::PolicyConfiguration;
+use pdk::api::classy::bootstrap::Launcher;
+use pdk::api::classy::event::{EventData, Exchange, HeadersAccessor, RequestHeaders};
+use pdk::api::classy::Configuration;
+use pdk::api::error::PolicyError;
+use pdk::api::http::baggage::{Baggage, BAGGAGE};
+use pdk::api::logger::{debug, info, warn};
+use pdk::api::policy_context::PolicyContext;
+
+mod config;
+
+/// The entries taken from the metadata of the API, the same for every request.
+fn gateway_entries(config: &PolicyConfiguration) -> Vec<(String, String)> {
+    let metadata = <dyn PolicyContext>::default().policy_metadata();
+    let tenant = metadata
+        .anypoint_environment()
+        .map(|environment| environment.organization_id().to_string());
+    let api_id = metadata.api_info().map(|api| api.id().to_string());
+
+    vec![(&config.tenant_key, tenant), (&config.api_id_key, api_id)]
+        .into_iter()
+        .filter(|(key, _)| !key.is_empty())
+        .filter_map(|(key, value)| match value {
+            Some(value) => Some((key.clone(), value)),
+            None => {
+                warn!(
+                    "No value in the API metadata for the {} baggage entry.",
+                    key
+                );
+                None
+            }
+        })
+        .collect()
+}
+
+/// Drops the members whose key is not one of the `allowed` ones.
+fn allow(baggage: &mut Baggage, allowed: &[String]) {
+    let denied: Vec<String> = baggage
+        .entries()
+        .iter()
+        .map(|entry| entry.key().to_string())
+        .filter(|key| !allowed.contains(key))
+        .collect();
+    if !denied.is_empty() {
+        debug!("Dropped the baggage members not allowed: {:?}.", denied);
+    }
+    for key in &denied {
+        baggage.remove(key);
+    }
+}
+
+fn propagate(
+    event: &EventData<RequestHeaders>,
+    config: &PolicyConfiguration,
+    gateway: &[(String, String)],
+) {
+    let values: Vec<String> = event
+        .headers()
+        .into_iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case(BAGGAGE))
+        .map(|(_, value)| value)
+        .collect();
+    let mut baggage = Baggage::parse(values.iter().map(String::as_str));
+    if baggage.invalid() > 0 {
+        debug!("Dropped {} invalid baggage members.", baggage.invalid());
+    }
+    if !config.allowed_keys.is_empty() {
+        allow(&mut baggage, &config.allowed_keys);
+    }
+
+    // The entries of the gateway replace the ones sent by the client with the same key.
+    for entry in &config.entries {
+        let value = entry.value.resolve_on_request_headers(event);
+        match value.as_ref().map(|value| value.as_str()) {
+            Ok(Some(value)) => {
+                if let Err(err) = baggage.set(&entry.key, value) {
+                    warn!("Error setting the baggage entry. {}.", err);
+                }
+            }
+            Ok(None) => warn!("Baggage entry {} did not resolve to a string.", entry.key),
+            Err(err) => warn!("Error resolving the baggage entry {}. {}.", entry.key, err),
+        }
+    }
+    for (key, value) in gateway {
+        if let Err(err) = baggage.set(key, value) {
+            warn!("Error setting the baggage entry. {}.", err);
+        }
+    }
+
+    let dropped = baggage.enforce(&config.limits);
+    if dropped > 0 {
+        info!("Dropped {} baggage members above the limits.", dropped);
+    }
+
+    if baggage.is_empty() {
+        event.remove_header(BAGGAGE);
+    } else {
+        event.set_header(BAGGAGE, &baggage.to_string());
+    }
+}
+
+async fn filter(
+    exchange: Exchange<RequestHeaders>,
+    config: &PolicyConfiguration,
+    gateway: &[(String, String)],
+) {
+    if let Some(event) = exchange.event_data() {
+        propagate(&event, config, gateway);
+    }
+}
+
+#[pdk::api::entrypoint]
+async fn configure(
+    launcher: Launcher,
+    Configuration(bytes): Configuration,
+) -> Result<(), PolicyError> {
+    let config: PolicyConfiguration = serde_json::from_slice(&bytes)?;
+    let gateway = gateway_entries(&config);
+
+    launcher
+        .launch(|exchange| filter(exchange, &config, &gateway))
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{allow, configure};
+    use pdk::api::classy::testing::{SimulatedHost, Simulator};
+    use pdk::api::http::baggage::Baggage;
+
+    // The entry of the gateway is the literal #['gold'].
+    const CONFIG: &str = r#"{
+        "tenantKey": "",
+        "apiIdKey": "",
+        "entries": [{"key": "userTier", "value": "P[[\":str\", \"0-6\", \"gold\"]]"}]
+    }"#;
+
+    fn config(extra: &str) -> String {
+        CONFIG.replacen('{', &format!("{{{}", extra), 1)
+    }
+
+    // The baggage the upstream gets for the `baggage` headers of the client.
+    fn propagated(config: &str, baggage: &[&str]) -> Option<String> {
+        let host = SimulatedHost::new().with_configuration(config.as_bytes());
+        let mut simulator = Simulator::new(host, configure);
+        let mut exchange = simulator.exchange();
+
+        let mut headers = vec![(":path", "/")];
+        headers.extend(baggage.iter().map(|value| ("baggage", *value)));
+        exchange.request_headers(headers);
+        assert_eq!(simulator.host().local_response(), None);
+
+        let baggage: Vec<String> = simulator
+            .host()
+            .request_headers()
+            .into_iter()
+            .filter(|(name, _)| name == "baggage")
+            .map(|(_, value)| value)
+            .collect();
+        assert!(baggage.len() <= 1, "one baggage header expected");
+        baggage.into_iter().next()
+    }
+
+    #[test]
+    fn valid_members_are_propagated() {
+        let baggage = propagated(
+            CONFIG,
+            &["userId=alice, city=S%C3%A3o%20Paulo;ttl=60", "session=a1"],
+        );
+
+        assert_eq!(
+            baggage.as_deref(),
+            Some("userTier=gold,userId=alice,city=S%C3%A3o%20Paulo;ttl=60,session=a1")
+        );
+    }
+
+    #[test]
+    fn invalid_members_are_dropped() {
+        let baggage = propagated(CONFIG, &["user id=alice,=empty,quoted=\"a\",userId=bob,,"]);
+
+        assert_eq!(baggage.as_deref(), Some("userTier=gold,userId=bob"));
+    }
+
+    #[test]
+    fn entries_of_the_gateway_replace_the_ones_of_the_client() {
+        let baggage = propagated(CONFIG, &["userTier=platinum,userId=alice"]);
+
+        assert_eq!(baggage.as_deref(), Some("userTier=gold,userId=alice"));
+    }
+
+    #[test]
+    fn members_above_the_limits_are_dropped() {
+        let baggage = propagated(&config(r#""maxEntries": 2,"#), &["a=1,b=2,c=3"]);
+        assert_eq!(baggage.as_deref(), Some("userTier=gold,a=1"));
+
+        // "userTier=gold,a=1" is 17 bytes
+        let baggage = propagated(&config(r#""maxBytes": 20,"#), &["a=1,b=2,c=3"]);
+        assert_eq!(baggage.as_deref(), Some("userTier=gold,a=1"));
+
+        let baggage = propagated(&config(r#""maxBytes": 10,"#), &["a=1"]);
+        assert_eq!(baggage, None);
+    }
+
+    #[test]
+    fn members_not_allowed_are_dropped() {
+        let config = config(r#""allowedKeys": ["userId", "session"],"#);
+        let baggage = propagated(&config, &["userId=alice,UserId=bob,debug=true,session=a1"]);
+
+        // the entries of the gateway are always propagated
+        assert_eq!(
+            baggage.as_deref(),
+            Some("userTier=gold,userId=alice,session=a1")
+        );
+    }
+
+    #[test]
+    fn allow_keeps_the_order_of_the_members() {
+        let mut baggage = Baggage::parse(vec!["a=1,b=2,c=3,b=4"]);
+
+        allow(&mut baggage, &["c".to_string(), "b".to_string()]);
+        assert_eq!(baggage.to_string(), "b=2,c=3,b=4");
+
+        allow(&mut baggage, &[]);
+        assert!(baggage.is_empty());
+    }
+
+    #[test]
+    fn empty_baggage_is_removed() {
+        let config = r#"{"tenantKey": "", "apiIdKey": ""}"#;
+
+        assert_eq!(propagated(config, &["not a member"]), None);
+        assert_eq!(propagated(config, &[]), None);
+    }
+}
@@ -0,0 +1,69 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use serde::Deserialize;
+
+/// The share of the requests sent to green from `after_seconds` on.
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct Step {
+    #[serde(alias = "afterSeconds")]
+    pub after_seconds: u64,
+    #[serde(alias = "greenPercent")]
+    pub green_percent: u8,
+}
+
+/// The control headers forcing the slot of a request, honored with the secret only.
+#[derive(Deserialize, Debug)]
+pub struct Override {
+    #[serde(default = "default_override_header")]
+    pub header: String,
+    #[serde(alias = "secretHeader", default = "default_secret_header")]
+    pub secret_header: String,
+    pub secret: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PolicyConfiguration {
+    /// The header the routes of the API match to send the request to the upstream of a slot.
+    #[serde(alias = "routingHeader", default = "default_routing_header")]
+    pub routing_header: String,
+    /// The value of the routing header for each slot.
+    #[serde(default = "default_blue")]
+    pub blue: String,
+    #[serde(default = "default_green")]
+    pub green: String,
+    /// When the schedule starts, in seconds since the epoch, the same for every worker.
+    #[serde(alias = "startTime")]
+    pub start_time: u64,
+    /// Every request goes to blue before the first step.
+    #[serde(default)]
+    pub schedule: Vec<Step>,
+    /// The header whose value keeps a client in the same slot while the share doesn't change,
+    /// like a user id. The requests without a sticky key are spread evenly by the share.
+    #[serde(alias = "stickyHeader", default)]
+    pub sticky_header: Option<String>,
+    /// The query parameter keeping a client in the same slot when the sticky header is
+    /// missing, like a token in the links sent to it.
+    #[serde(alias = "stickyQueryParam", default)]
+    pub sticky_query_param: Option<String>,
+    #[serde(rename = "override", default)]
+    pub manual_override: Option<Override>,
+}
+
+fn default_routing_header() -> String {
+    "x-deployment-slot".to_string()
+}
+
+fn default_blue() -> String {
+    "blue".to_string()
+}
+
+fn default_green() -> String {
+    "green".to_string()
+}
+
+fn default_override_header() -> String {
+    "x-deployment-slot-override".to_string()
+}
+
+fn default_secret_header() -> String {
+    "x-deployment-slot-secret".to_string()
+}
@@ -0,0 +1,306 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use crate::config::{Override, PolicyConfiguration, Step};
+use pdk::api::classy::bootstrap::Launcher;
+use pdk::api::classy::event::{EventData, Exchange, HeadersAccessor, RequestHeaders};
+use pdk::api::classy::{Configuration, Host};
+use pdk::api::error::{codes, PolicyError};
+use pdk::api::hash;
+use pdk::api::http::query::{QueryDecoding, QueryParams};
+use pdk::api::logger::{debug, info, warn};
+use pdk::api::property::PropertyAccessor;
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+mod config;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Slot {
+    Blue,
+    Green,
+}
+
+/// The share of green `elapsed` after the start, the one of the last step reached.
+fn green_percent(schedule: &[Step], elapsed: Duration) -> u8 {
+    schedule
+        .iter()
+        .filter(|step| Duration::from_secs(step.after_seconds) <= elapsed)
+        .max_by_key(|step| step.after_seconds)
+        .map_or(0, |step| step.green_percent)
+}
+
+/// The bucket of a request among 100, the same for the same key on every worker, and the one
+/// `hashBucket(key, 100)` gives in the expressions.
+fn bucket(key: &str) -> u32 {
+    hash::bucket(key, 100, 0)
+}
+
+/// Sends the share of green of the requests without a sticky key, spread evenly over them
+/// rather than at random, so every 100 of them send the share to green.
+struct Spreader {
+    credit: Cell<u32>,
+}
+
+impl Spreader {
+    fn new() -> Self {
+        Self {
+            credit: Cell::new(0),
+        }
+    }
+
+    fn green(&self, percent: u8) -> bool {
+        let credit = self.credit.get() + percent as u32;
+        let green = credit >= 100;
+        self.credit.set(if green { credit - 100 } else { credit });
+        green
+    }
+}
+
+/// Compares the secrets without telling through the time taken how much of them matched.
+fn same_secret(received: &str, secret: &str) -> bool {
+    received.len() == secret.len()
+        && received
+            .bytes()
+            .zip(secret.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// The slot forced by the control headers, which are never forwarded to the upstream.
+fn overridden(
+    event: &EventData<RequestHeaders>,
+    config: &PolicyConfiguration,
+    manual: &Override,
+) -> Option<Slot> {
+    let slot = event.header(&manual.header);
+    let secret = event.header(&manual.secret_header);
+    event.remove_header(&manual.header);
+    event.remove_header(&manual.secret_header);
+
+    let slot = slot?;
+    if !secret.is_some_and(|secret| same_secret(&secret, &manual.secret)) {
+        warn!("Ignoring the slot override of a request without the secret.");
+        return None;
+    }
+    match slot.trim() {
+        slot if slot.eq_ignore_ascii_case(&config.blue) => Some(Slot::Blue),
+        slot if slot.eq_ignore_ascii_case(&config.green) => Some(Slot::Green),
+        slot => {
+            warn!("Ignoring the override to the unknown slot {}.", slot);
+            None
+        }
+    }
+}
+
+/// The value of the sticky query parameter. Decoded strictly, so a base64 token sent with a
+/// `+` or a `%2B` lands in the same bucket, where form decoding would make it a space.
+fn sticky_param(event: &EventData<RequestHeaders>, config: &PolicyConfiguration) -> Option<String> {
+    let name = config.sticky_query_param.as_deref()?;
+    QueryParams::parse_with(&event.path(), QueryDecoding::Strict).get(name)
+}
+
+fn route(
+    event: &EventData<RequestHeaders>,
+    config: &PolicyConfiguration,
+    properties: &dyn PropertyAccessor,
+    spreader: &Spreader,
+    start: SystemTime,
+    now: SystemTime,
+) {
+    let forced = config
+        .manual_override
+        .as_ref()
+        .and_then(|manual| overridden(event, config, manual));
+
+    // Sticky exchanges must reach the upstream they started with, the default route.
+    if let Some(reason) = properties.filter_state().sticky() {
+        debug!("Leaving the {} exchange on the default route.", reason);
+        event.remove_header(&config.routing_header);
+        return;
+    }
+
+    let slot = match forced {
+        Some(slot) => {
+            info!("Request sent to {:?} by override.", slot);
+            slot
+        }
+        None => {
+            let elapsed = now.duration_since(start).unwrap_or_default();
+            let percent = green_percent(&config.schedule, elapsed);
+            let key = config
+                .sticky_header
+                .as_deref()
+                .and_then(|header| event.header(header))
+                .or_else(|| sticky_param(event, config));
+            let green = match key {
+                Some(key) => bucket(&key) < percent as u32,
+                None => spreader.green(percent),
+            };
+            if green {
+                Slot::Green
+            } else {
+                Slot::Blue
+            }
+        }
+    };
+
+    let value = match slot {
+        Slot::Blue => &config.blue,
+        Slot::Green => &config.green,
+    };
+    debug!("Routing the request to the {} slot.", value);
+    event.set_header(&config.routing_header, value);
+}
+
+async fn filter(
+    exchange: Exchange<RequestHeaders>,
+    config: &PolicyConfiguration,
+    host: Rc<dyn Host>,
+    properties: &'static dyn PropertyAccessor,
+    spreader: &Spreader,
+    start: SystemTime,
+) {
+    if let Some(event) = exchange.event_data() {
+        route(
+            &event,
+            config,
+            properties,
+            spreader,
+            start,
+            host.get_current_time(),
+        );
+    }
+}
+
+fn validate(config: &PolicyConfiguration) -> Result<(), PolicyError> {
+    if config.blue.eq_ignore_ascii_case(&config.green) {
+        return Err(PolicyError::new(
+            codes::INVALID_CONFIGURATION,
+            "The blue and green slots must have different values",
+        ));
+    }
+    if let Some(step) = config.schedule.iter().find(|step| step.green_percent > 100) {
+        return Err(PolicyError::new(
+            codes::INVALID_CONFIGURATION,
+            format!(
+                "The step after {} seconds sends {}% to green, over 100%",
+                step.after_seconds, step.green_percent
+            ),
+        ));
+    }
+    Ok(())
+}
+
+#[pdk::api::entrypoint]
+async fn configure(
+    launcher: Launcher,
+    Configuration(bytes): Configuration,
+) -> Result<(), PolicyError> {
+    let config: PolicyConfiguration = serde_json::from_slice(&bytes)?;
+    validate(&config)?;
+    let start = UNIX_EPOCH + Duration::from_secs(config.start_time);
+    let spreader = Spreader::new();
+
+    launcher
+        .launch(|exchange, host, properties| {
+            filter(exchange, &config, host, properties, &spreader, start)
+        })
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bucket, green_percent, same_secret, validate, Spreader};
+    use crate::config::{PolicyConfiguration, Step};
+    use pdk::api::hash;
+    use std::time::Duration;
+
+    fn config(json: &str) -> PolicyConfiguration {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn step(after_seconds: u64, green_percent: u8) -> Step {
+        Step {
+            after_seconds,
+            green_percent,
+        }
+    }
+
+    #[test]
+    fn green_percent_is_the_one_of_the_last_step_reached() {
+        let schedule = [step(7200, 100), step(60, 10), step(3600, 50)];
+        let at = |seconds| green_percent(&schedule, Duration::from_secs(seconds));
+
+        assert_eq!(at(0), 0);
+        assert_eq!(at(59), 0);
+        assert_eq!(at(60), 10);
+        assert_eq!(at(3599), 10);
+        assert_eq!(at(3600), 50);
+        assert_eq!(at(7200), 100);
+        assert_eq!(at(u64::MAX), 100);
+        assert_eq!(green_percent(&[], Duration::from_secs(3600)), 0);
+    }
+
+    #[test]
+    fn bucket_is_stable() {
+        assert_eq!(bucket("user-1"), bucket("user-1"));
+        assert_eq!(bucket("user-1"), hash::bucket("user-1", 100, 0));
+    }
+
+    #[test]
+    fn buckets_are_spread() {
+        let buckets: Vec<u32> = (0..10_000)
+            .map(|i| bucket(&format!("user-{}", i)))
+            .collect();
+
+        assert!(buckets.iter().all(|bucket| *bucket < 100));
+        let green = buckets.iter().filter(|bucket| **bucket < 10).count();
+        assert!((900..1100).contains(&green), "{} keys under 10%", green);
+        let green = buckets.iter().filter(|bucket| **bucket < 50).count();
+        assert!((4800..5200).contains(&green), "{} keys under 50%", green);
+    }
+
+    #[test]
+    fn spreader_sends_the_share_to_green() {
+        let spreader = Spreader::new();
+        let green: Vec<bool> = (0..8).map(|_| spreader.green(25)).collect();
+        assert_eq!(
+            green,
+            [false, false, false, true, false, false, false, true]
+        );
+
+        let spreader = Spreader::new();
+        assert_eq!((0..100).filter(|_| spreader.green(10)).count(), 10);
+        assert!((0..100).all(|_| !spreader.green(0)));
+        assert!((0..100).all(|_| spreader.green(100)));
+    }
+
+    #[test]
+    fn secrets_are_compared_whole() {
+        assert!(same_secret("change-me", "change-me"));
+        assert!(!same_secret("change-it", "change-me"));
+        assert!(!same_secret("change", "change-me"));
+        assert!(!same_secret("change-me-too", "change-me"));
+        assert!(!same_secret("", "change-me"));
+    }
+
+    #[test]
+    fn configuration_is_validated() {
+        let valid =
+            config(r#"{"startTime": 0, "schedule": [{"afterSeconds": 0, "greenPercent": 100}]}"#);
+        assert!(validate(&valid).is_ok());
+
+        let same_slots = config(r#"{"startTime": 0, "blue": "live", "green": "LIVE"}"#);
+        assert!(validate(&same_slots).is_err());
+
+        let over =
+            config(r#"{"startTime": 0, "schedule": [{"afterSeconds": 60, "greenPercent": 101}]}"#);
+        assert!(validate(&over).is_err());
+    }
+
+    #[test]
+    fn start_time_is_required() {
+        assert!(serde_json::from_str::<PolicyConfiguration>(r#"{"schedule": []}"#).is_err());
+    }
+}
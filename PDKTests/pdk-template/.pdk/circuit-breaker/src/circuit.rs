@@ -0,0 +1,405 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! The circuit of a route, its state and its counters kept in shared data so all the workers
+//! open and close it together.
+use pdk::api::shared_data::SharedDataAccessor;
+use pdk::api::window::{SharedSlidingWindowCounter, SlidingWindowCounter};
+use std::convert::TryInto;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const MAX_CAS_ATTEMPTS: usize = 8;
+const BUCKETS: usize = 10;
+
+const CLOSED: u8 = 0;
+const OPEN: u8 = 1;
+const HALF_OPEN: u8 = 2;
+
+/// The state of a circuit, the times in milliseconds since the epoch.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum State {
+    /// The requests reach the upstream.
+    Closed,
+    /// The requests are short-circuited since `since`.
+    Open { since: u64 },
+    /// `probes` requests are reaching the upstream since `since`, to tell whether it recovered.
+    HalfOpen { since: u64, probes: u32 },
+}
+
+impl State {
+    fn to_bytes(self) -> Vec<u8> {
+        let (kind, since, probes) = match self {
+            State::Closed => (CLOSED, 0, 0),
+            State::Open { since } => (OPEN, since, 0),
+            State::HalfOpen { since, probes } => (HALF_OPEN, since, probes),
+        };
+        let mut bytes = vec![kind];
+        bytes.extend_from_slice(&since.to_le_bytes());
+        bytes.extend_from_slice(&probes.to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != 13 {
+            return None;
+        }
+        let since = u64::from_le_bytes(bytes[1..9].try_into().ok()?);
+        let probes = u32::from_le_bytes(bytes[9..13].try_into().ok()?);
+        match bytes[0] {
+            CLOSED => Some(State::Closed),
+            OPEN => Some(State::Open { since }),
+            HALF_OPEN => Some(State::HalfOpen { since, probes }),
+            _ => None,
+        }
+    }
+}
+
+/// Whether a request reaches the upstream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Admission {
+    Allowed,
+    /// The request reaches the upstream to probe it, the circuit being half open.
+    Probe,
+    /// The request is short-circuited, the circuit letting requests through again after
+    /// `retry_after`.
+    Rejected {
+        retry_after: Duration,
+    },
+}
+
+/// When the circuits open and close.
+#[derive(Clone, Debug)]
+pub struct Thresholds {
+    pub failure_rate: f64,
+    pub minimum_requests: u64,
+    pub window: Duration,
+    pub open: Duration,
+    pub half_open_probes: u32,
+}
+
+pub struct Circuit<'a> {
+    shared_data: &'a dyn SharedDataAccessor,
+    key: String,
+    thresholds: &'a Thresholds,
+}
+
+impl<'a> Circuit<'a> {
+    pub fn new(
+        shared_data: &'a dyn SharedDataAccessor,
+        key: String,
+        thresholds: &'a Thresholds,
+    ) -> Self {
+        Self {
+            shared_data,
+            key,
+            thresholds,
+        }
+    }
+
+    fn state_key(&self) -> String {
+        format!("{}:state", self.key)
+    }
+
+    fn counter(&self, name: &str) -> SharedSlidingWindowCounter<'a> {
+        let key = format!("{}:{}", self.key, name);
+        SharedSlidingWindowCounter::new(self.shared_data, key, self.thresholds.window, BUCKETS)
+    }
+
+    /// Moves the circuit to the state `transition` returns, with the result of the transition.
+    /// None when the state kept changing concurrently.
+    fn update<T>(&self, transition: impl Fn(State) -> (Option<State>, T)) -> Option<T> {
+        let key = self.state_key();
+        for _ in 0..MAX_CAS_ATTEMPTS {
+            let (bytes, cas) = self.shared_data.read_shared_data(&key);
+            let state = bytes
+                .and_then(|bytes| State::from_bytes(&bytes))
+                .unwrap_or(State::Closed);
+
+            match transition(state) {
+                (None, result) => return Some(result),
+                (Some(next), result) => {
+                    if self
+                        .shared_data
+                        .write_shared_data(&key, &next.to_bytes(), cas)
+                    {
+                        return Some(result);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Whether the request reaches the upstream. Once the circuit was open long enough, it is
+    /// half open and lets some probes through, and the probes that never completed are replaced
+    /// after as long.
+    pub fn admit(&self, now: SystemTime) -> Admission {
+        let now = millis(now);
+        let open = self.thresholds.open.as_millis() as u64;
+        let max_probes = self.thresholds.half_open_probes;
+
+        let admission = self.update(|state| match state {
+            State::Closed => (None, Admission::Allowed),
+            State::Open { since } | State::HalfOpen { since, .. }
+                if now >= since.saturating_add(open) =>
+            {
+                let next = State::HalfOpen {
+                    since: now,
+                    probes: 1,
+                };
+                (Some(next), Admission::Probe)
+            }
+            State::HalfOpen { since, probes } if probes < max_probes => {
+                let next = State::HalfOpen {
+                    since,
+                    probes: probes + 1,
+                };
+                (Some(next), Admission::Probe)
+            }
+            State::Open { since } | State::HalfOpen { since, .. } => {
+                let retry_after = Duration::from_millis(since.saturating_add(open) - now);
+                (None, Admission::Rejected { retry_after })
+            }
+        });
+        // The circuits contended for let the requests through rather than rejecting them.
+        admission.unwrap_or(Admission::Allowed)
+    }
+
+    /// Records the response of a request, the new state of the circuit when it changed.
+    pub fn record(&self, probe: bool, failed: bool, now: SystemTime) -> Option<State> {
+        if probe {
+            return self.record_probe(failed, now);
+        }
+
+        let requests = self.counter("requests").add(1, now)?;
+        let failures = self.counter("failures");
+        let failures = if failed {
+            failures.add(1, now)?
+        } else {
+            failures.count(now)
+        };
+        if !failed
+            || requests < self.thresholds.minimum_requests
+            || (failures as f64) < self.thresholds.failure_rate * requests as f64
+        {
+            return None;
+        }
+
+        let since = millis(now);
+        self.update(|state| match state {
+            State::Closed => (Some(State::Open { since }), Some(State::Open { since })),
+            _ => (None, None),
+        })
+        .flatten()
+    }
+
+    /// A failed probe opens the circuit again, a successful one closes it with fresh counters.
+    fn record_probe(&self, failed: bool, now: SystemTime) -> Option<State> {
+        let since = millis(now);
+        let next = self
+            .update(|state| match state {
+                State::HalfOpen { .. } if failed => {
+                    (Some(State::Open { since }), Some(State::Open { since }))
+                }
+                State::HalfOpen { .. } => (Some(State::Closed), Some(State::Closed)),
+                // Another probe completed first.
+                _ => (None, None),
+            })
+            .flatten();
+
+        if next == Some(State::Closed) {
+            let fresh = SlidingWindowCounter::new(self.thresholds.window, BUCKETS).to_bytes();
+            for name in ["requests", "failures"] {
+                let key = format!("{}:{}", self.key, name);
+                self.shared_data.write_shared_data(&key, &fresh, None);
+            }
+        }
+        next
+    }
+}
+
+fn millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Admission, Circuit, State, Thresholds};
+    use pdk::api::shared_data::SharedDataAccessor;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    #[derive(Default)]
+    struct MockSharedData {
+        data: RefCell<HashMap<String, (Vec<u8>, u32)>>,
+    }
+
+    impl SharedDataAccessor for MockSharedData {
+        fn read_shared_data(&self, key: &str) -> (Option<Vec<u8>>, Option<u32>) {
+            match self.data.borrow().get(key) {
+                Some((value, cas)) => (Some(value.clone()), Some(*cas)),
+                None => (None, None),
+            }
+        }
+
+        fn write_shared_data(&self, key: &str, value: &[u8], cas: Option<u32>) -> bool {
+            let mut data = self.data.borrow_mut();
+            let current = data.get(key).map(|(_, cas)| *cas);
+            if cas.is_some() && cas != current {
+                return false;
+            }
+            let next = current.unwrap_or_default() + 1;
+            data.insert(key.to_string(), (value.to_vec(), next));
+            true
+        }
+    }
+
+    fn at(millis: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(millis)
+    }
+
+    /// Opens once half of 4 requests or more failed, for a second, probing with 2 requests.
+    fn thresholds() -> Thresholds {
+        Thresholds {
+            failure_rate: 0.5,
+            minimum_requests: 4,
+            window: Duration::from_secs(10),
+            open: Duration::from_secs(1),
+            half_open_probes: 2,
+        }
+    }
+
+    fn state(shared_data: &MockSharedData) -> Option<State> {
+        let bytes = shared_data.read_shared_data("route:state").0?;
+        State::from_bytes(&bytes)
+    }
+
+    /// Opens the circuit at `now` with failed requests.
+    fn open(circuit: &Circuit, now: SystemTime) {
+        for _ in 0..3 {
+            assert_eq!(circuit.record(false, true, now), None);
+        }
+        let since = now.duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+        assert_eq!(
+            circuit.record(false, true, now),
+            Some(State::Open { since })
+        );
+    }
+
+    #[test]
+    fn states_are_decoded_as_encoded() {
+        for state in [
+            State::Closed,
+            State::Open { since: 1_000 },
+            State::HalfOpen {
+                since: u64::MAX,
+                probes: 3,
+            },
+        ] {
+            assert_eq!(State::from_bytes(&state.to_bytes()), Some(state));
+        }
+
+        let mut unknown = State::Closed.to_bytes();
+        unknown[0] = 3;
+        assert_eq!(State::from_bytes(&unknown), None);
+        assert_eq!(State::from_bytes(&State::Closed.to_bytes()[..12]), None);
+        assert_eq!(State::from_bytes(&[]), None);
+    }
+
+    #[test]
+    fn circuit_opens_on_the_failure_rate() {
+        let shared_data = MockSharedData::default();
+        let thresholds = thresholds();
+        let circuit = Circuit::new(&shared_data, "route".to_string(), &thresholds);
+
+        assert_eq!(circuit.admit(at(0)), Admission::Allowed);
+        // Successes never open it, and the failures only once there were enough requests.
+        for _ in 0..4 {
+            assert_eq!(circuit.record(false, false, at(0)), None);
+        }
+        for _ in 0..3 {
+            assert_eq!(circuit.record(false, true, at(0)), None);
+        }
+        assert_eq!(
+            circuit.record(false, true, at(100)),
+            Some(State::Open { since: 100 })
+        );
+        assert_eq!(state(&shared_data), Some(State::Open { since: 100 }));
+    }
+
+    #[test]
+    fn open_circuit_rejects_until_probed() {
+        let shared_data = MockSharedData::default();
+        let thresholds = thresholds();
+        let circuit = Circuit::new(&shared_data, "route".to_string(), &thresholds);
+        open(&circuit, at(1_000));
+
+        assert_eq!(
+            circuit.admit(at(1_400)),
+            Admission::Rejected {
+                retry_after: Duration::from_millis(600)
+            }
+        );
+
+        // Half open, it lets the probes through, and rejects the other requests.
+        assert_eq!(circuit.admit(at(2_000)), Admission::Probe);
+        assert_eq!(circuit.admit(at(2_100)), Admission::Probe);
+        assert_eq!(
+            state(&shared_data),
+            Some(State::HalfOpen {
+                since: 2_000,
+                probes: 2
+            })
+        );
+        assert_eq!(
+            circuit.admit(at(2_500)),
+            Admission::Rejected {
+                retry_after: Duration::from_millis(500)
+            }
+        );
+
+        // The probes that never completed are replaced.
+        assert_eq!(circuit.admit(at(3_000)), Admission::Probe);
+        assert_eq!(
+            state(&shared_data),
+            Some(State::HalfOpen {
+                since: 3_000,
+                probes: 1
+            })
+        );
+    }
+
+    #[test]
+    fn successful_probe_closes_with_fresh_counters() {
+        let shared_data = MockSharedData::default();
+        let thresholds = thresholds();
+        let circuit = Circuit::new(&shared_data, "route".to_string(), &thresholds);
+        open(&circuit, at(0));
+
+        assert_eq!(circuit.admit(at(1_000)), Admission::Probe);
+        assert_eq!(circuit.record(true, false, at(1_100)), Some(State::Closed));
+        assert_eq!(circuit.admit(at(1_200)), Admission::Allowed);
+
+        // The failures before it opened are forgotten.
+        assert_eq!(circuit.record(false, true, at(1_300)), None);
+        assert_eq!(state(&shared_data), Some(State::Closed));
+    }
+
+    #[test]
+    fn failed_probe_opens_again() {
+        let shared_data = MockSharedData::default();
+        let thresholds = thresholds();
+        let circuit = Circuit::new(&shared_data, "route".to_string(), &thresholds);
+        open(&circuit, at(0));
+
+        assert_eq!(circuit.admit(at(1_000)), Admission::Probe);
+        assert_eq!(circuit.admit(at(1_000)), Admission::Probe);
+        assert_eq!(
+            circuit.record(true, true, at(1_100)),
+            Some(State::Open { since: 1_100 })
+        );
+        // The other probe completing once it opened again changes nothing.
+        assert_eq!(circuit.record(true, false, at(1_200)), None);
+        assert_eq!(state(&shared_data), Some(State::Open { since: 1_100 }));
+    }
+}
@@ -0,0 +1,56 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use serde::Deserialize;
+
+/// The requests of the paths starting with the prefix, tracked by a circuit of their own.
+#[derive(Deserialize, Debug)]
+pub struct Route {
+    pub name: String,
+    #[serde(alias = "pathPrefix")]
+    pub path_prefix: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PolicyConfiguration {
+    /// The routes with a circuit of their own, the first one matching applies. The requests
+    /// of the other paths share the `default` circuit.
+    #[serde(default)]
+    pub routes: Vec<Route>,
+    /// The share of failed responses opening the circuit, from 0 to 1.
+    #[serde(alias = "failureRate", default = "default_failure_rate")]
+    pub failure_rate: f64,
+    /// The responses within the window below which the circuit does not open.
+    #[serde(alias = "minimumRequests", default = "default_minimum_requests")]
+    pub minimum_requests: u64,
+    /// The window the failures are counted over.
+    #[serde(alias = "windowMillis", default = "default_window_millis")]
+    pub window_millis: u64,
+    /// How long the circuit stays open before probing the upstream again.
+    #[serde(alias = "openMillis", default = "default_open_millis")]
+    pub open_millis: u64,
+    /// The requests reaching the upstream at once while probing it.
+    #[serde(alias = "halfOpenProbes", default = "default_half_open_probes")]
+    pub half_open_probes: u32,
+    /// The status codes of the failed responses, the `5xx` ones when empty.
+    #[serde(alias = "failureStatusCodes", default)]
+    pub failure_status_codes: Vec<u32>,
+}
+
+fn default_failure_rate() -> f64 {
+    0.5
+}
+
+fn default_minimum_requests() -> u64 {
+    20
+}
+
+fn default_window_millis() -> u64 {
+    30_000
+}
+
+fn default_open_millis() -> u64 {
+    30_000
+}
+
+fn default_half_open_probes() -> u32 {
+    1
+}
@@ -0,0 +1,168 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use crate::circuit::{Admission, Circuit, State, Thresholds};
+use crate::config::{PolicyConfiguration, Route};
+use pdk::api::classy::bootstrap::Launcher;
+use pdk::api::classy::event::{Exchange, RequestHeaders};
+use pdk::api::classy::{Configuration, Host};
+use pdk::api::error::{codes, PolicyError};
+use pdk::api::logger::{info, warn};
+use pdk::api::metrics::MetricsAccessor;
+use pdk::api::policy_context::decisions::{Decision, Outcome};
+use pdk::api::policy_context::PolicyContext;
+use pdk::api::property::{record_decision, retry_after_seconds};
+use pdk::api::shared_data::SharedDataAccessor;
+use std::rc::Rc;
+use std::time::Duration;
+
+mod circuit;
+mod config;
+
+const POLICY_NAME: &str = "circuit-breaker";
+const DECISION_KIND: &str = "circuitBreaker";
+const SHORT_CIRCUITED_COUNTER: &str = "circuit_breaker_short_circuited";
+const OPENED_COUNTER: &str = "circuit_breaker_opened";
+
+const SERVICE_UNAVAILABLE: u32 = 503;
+const SHORT_CIRCUITED_BODY: &[u8] = br#"{"error":"The upstream is unavailable"}"#;
+
+/// The circuit of the requests of the paths no route matches.
+const DEFAULT_ROUTE: &str = "default";
+
+/// The routes and the thresholds of their circuits.
+struct Breaker {
+    prefix: String,
+    routes: Vec<Route>,
+    thresholds: Thresholds,
+    failure_status_codes: Vec<u32>,
+}
+
+impl Breaker {
+    /// The name of the route of the `path`.
+    fn route(&self, path: &str) -> &str {
+        self.routes
+            .iter()
+            .find(|route| path.starts_with(&route.path_prefix))
+            .map_or(DEFAULT_ROUTE, |route| route.name.as_str())
+    }
+
+    fn circuit<'a>(&'a self, shared_data: &'a dyn SharedDataAccessor, route: &str) -> Circuit<'a> {
+        let key = format!("{}:{}", self.prefix, route);
+        Circuit::new(shared_data, key, &self.thresholds)
+    }
+
+    fn is_failure(&self, status: u32) -> bool {
+        if self.failure_status_codes.is_empty() {
+            (500..600).contains(&status)
+        } else {
+            self.failure_status_codes.contains(&status)
+        }
+    }
+}
+
+async fn filter(
+    exchange: Exchange<RequestHeaders>,
+    breaker: &Breaker,
+    host: Rc<dyn Host>,
+    shared_data: &'static dyn SharedDataAccessor,
+    metrics: &'static dyn MetricsAccessor,
+) {
+    let path = match exchange.event_data() {
+        Some(event) => event.path(),
+        None => return,
+    };
+    let route = breaker.route(&path);
+    let circuit = breaker.circuit(shared_data, route);
+
+    let probe = match circuit.admit(host.get_current_time()) {
+        Admission::Allowed => false,
+        Admission::Probe => {
+            info!("Probing the upstream of the route {}.", route);
+            true
+        }
+        Admission::Rejected { retry_after } => {
+            let retry_after = retry_after_seconds(retry_after);
+            metrics.increment_counter(SHORT_CIRCUITED_COUNTER, 1);
+            record_decision(
+                Decision::new(POLICY_NAME, DECISION_KIND, Outcome::Deny)
+                    .with_reason("circuit open")
+                    .with_detail("route", route)
+                    .with_detail("retryAfter", retry_after)
+                    .with_detail("status", SERVICE_UNAVAILABLE),
+            );
+
+            let retry_after = retry_after.to_string();
+            let headers = vec![
+                ("retry-after", retry_after.as_str()),
+                ("content-type", "application/json"),
+            ];
+            exchange.send_response(SERVICE_UNAVAILABLE, headers, Some(SHORT_CIRCUITED_BODY));
+            return;
+        }
+    };
+    record_decision(Decision::new(POLICY_NAME, DECISION_KIND, Outcome::Allow));
+
+    let exchange = exchange.wait_for_response_headers().await;
+    let failed = match exchange.event_data() {
+        Some(event) => breaker.is_failure(event.status_code()),
+        // A probe without a response did not show the upstream recovered.
+        None if probe => true,
+        None => return,
+    };
+
+    match circuit.record(probe, failed, host.get_current_time()) {
+        Some(State::Open { .. }) => {
+            warn!("Opening the circuit of the route {}.", route);
+            metrics.increment_counter(OPENED_COUNTER, 1);
+        }
+        Some(State::Closed) => info!("Closing the circuit of the route {}.", route),
+        _ => {}
+    }
+}
+
+fn breaker(config: PolicyConfiguration) -> Result<Breaker, PolicyError> {
+    let invalid = |message: &str| Err(PolicyError::new(codes::INVALID_CONFIGURATION, message));
+    if config.failure_rate <= 0.0 || config.failure_rate > 1.0 {
+        return invalid("The failure rate must be above 0 and at most 1");
+    }
+    if config.window_millis == 0 || config.open_millis == 0 {
+        return invalid("The window and the open time must be at least a millisecond");
+    }
+    if config.half_open_probes == 0 {
+        return invalid("At least one probe must reach the upstream while half open");
+    }
+
+    let metadata = <dyn PolicyContext>::default().policy_metadata();
+    Ok(Breaker {
+        prefix: format!("{}:{}", POLICY_NAME, metadata.policy_id()),
+        routes: config.routes,
+        thresholds: Thresholds {
+            failure_rate: config.failure_rate,
+            minimum_requests: config.minimum_requests.max(1),
+            window: Duration::from_millis(config.window_millis),
+            open: Duration::from_millis(config.open_millis),
+            half_open_probes: config.half_open_probes,
+        },
+        failure_status_codes: config.failure_status_codes,
+    })
+}
+
+#[pdk::api::entrypoint]
+async fn configure(
+    launcher: Launcher,
+    Configuration(bytes): Configuration,
+) -> Result<(), PolicyError> {
+    let config: PolicyConfiguration = serde_json::from_slice(&bytes)?;
+    let breaker = breaker(config)?;
+    info!(
+        "Breaking the circuits of {} routes above a failure rate of {}.",
+        breaker.routes.len() + 1,
+        breaker.thresholds.failure_rate
+    );
+
+    launcher
+        .launch(|exchange, host, shared_data, metrics| {
+            filter(exchange, &breaker, host, shared_data, metrics)
+        })
+        .await?;
+    Ok(())
+}
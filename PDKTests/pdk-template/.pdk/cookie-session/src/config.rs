@@ -0,0 +1,49 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use pdk::api::http::cookies::{CookieKey, SameSite};
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+pub struct PolicyConfiguration {
+    #[serde(alias = "cookieName", default = "default_cookie_name")]
+    pub cookie_name: String,
+    /// The first key signs the issued cookies, the others only verify them while rotating.
+    pub keys: Vec<CookieKey>,
+    #[serde(alias = "ttlSeconds", default = "default_ttl_seconds")]
+    pub ttl_seconds: u64,
+    #[serde(default)]
+    pub encrypt: bool,
+    /// The header giving the session state to the upstream.
+    #[serde(alias = "requestHeader", default = "default_request_header")]
+    pub request_header: String,
+    /// The header the upstream replaces the session state with.
+    #[serde(alias = "responseHeader", default = "default_response_header")]
+    pub response_header: String,
+    #[serde(default = "default_path")]
+    pub path: String,
+    #[serde(alias = "sameSite", default = "default_same_site")]
+    pub same_site: SameSite,
+}
+
+fn default_cookie_name() -> String {
+    "session".to_string()
+}
+
+fn default_ttl_seconds() -> u64 {
+    1800
+}
+
+fn default_request_header() -> String {
+    "x-session".to_string()
+}
+
+fn default_response_header() -> String {
+    "x-session-update".to_string()
+}
+
+fn default_path() -> String {
+    "/".to_string()
+}
+
+fn default_same_site() -> SameSite {
+    SameSite::Lax
+}
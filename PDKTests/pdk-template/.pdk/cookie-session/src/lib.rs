@@ -0,0 +1,386 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use crate::config::PolicyConfiguration;
+use pdk::api::classy::bootstrap::Launcher;
+use pdk::api::classy::event::{Exchange, HeadersAccessor, RequestHeaders};
+use pdk::api::classy::{Configuration, Host};
+use pdk::api::error::PolicyError;
+use pdk::api::http::cookies::{cookie, SetCookie, SignedCookies, VerifiedCookie};
+use pdk::api::logger::{debug, warn};
+use std::rc::Rc;
+use std::time::{Duration, SystemTime};
+
+mod config;
+
+const COOKIE: &str = "cookie";
+const SET_COOKIE: &str = "set-cookie";
+
+/// What the response does to the session cookie.
+#[derive(Debug, PartialEq)]
+enum Update {
+    Keep,
+    Issue(Vec<u8>),
+    Remove,
+}
+
+/// The session of the request, when its cookie is valid.
+fn session(
+    config: &PolicyConfiguration,
+    cookies: &SignedCookies,
+    header: Option<String>,
+    now: SystemTime,
+) -> Option<VerifiedCookie> {
+    let header = header?;
+    let value = cookie(&header, &config.cookie_name)?;
+
+    match cookies.verify(&config.cookie_name, value, now) {
+        Ok(session) => Some(session),
+        Err(err) => {
+            debug!("Ignoring the session cookie. {}.", err);
+            None
+        }
+    }
+}
+
+/// The session state set by the upstream replaces the current one, and an empty or `null`
+/// state ends the session. Otherwise the cookie is issued again after half its lifetime, or
+/// when it was signed with a rotated key.
+fn update(
+    config: &PolicyConfiguration,
+    session: Option<VerifiedCookie>,
+    state: Option<String>,
+    now: SystemTime,
+) -> Update {
+    match (state, session) {
+        (Some(state), _) if state.trim().is_empty() || state.trim() == "null" => Update::Remove,
+        (Some(state), _) => match serde_json::from_str::<serde_json::Value>(&state) {
+            Ok(_) => Update::Issue(state.into_bytes()),
+            Err(err) => {
+                warn!("Ignoring the session state set by the upstream. {}.", err);
+                Update::Keep
+            }
+        },
+        (None, Some(session)) => {
+            let remaining = session.expires.duration_since(now).unwrap_or_default();
+            if session.reissue || remaining.as_secs() * 2 < config.ttl_seconds {
+                Update::Issue(session.payload)
+            } else {
+                Update::Keep
+            }
+        }
+        (None, None) => Update::Keep,
+    }
+}
+
+async fn filter(
+    exchange: Exchange<RequestHeaders>,
+    config: &PolicyConfiguration,
+    cookies: &SignedCookies,
+    host: Rc<dyn Host>,
+) {
+    let session = match exchange.event_data() {
+        Some(event) => {
+            let session = session(
+                config,
+                cookies,
+                event.header(COOKIE),
+                host.get_current_time(),
+            );
+
+            // The session state only comes from a valid cookie, never from the client.
+            event.remove_header(config.request_header.as_str());
+            if let Some(session) = &session {
+                let state = String::from_utf8_lossy(&session.payload);
+                event.set_header(config.request_header.as_str(), &state);
+            }
+            session
+        }
+        None => return,
+    };
+
+    let exchange = exchange.wait_for_response_headers().await;
+    let event = match exchange.event_data() {
+        Some(event) => event,
+        None => return,
+    };
+
+    let state = event.header(config.response_header.as_str());
+    if state.is_some() {
+        event.remove_header(config.response_header.as_str());
+    }
+
+    let now = host.get_current_time();
+    let ttl = Duration::from_secs(config.ttl_seconds);
+    let set_cookie = match update(config, session, state, now) {
+        Update::Keep => return,
+        Update::Remove => SetCookie::removal(&config.cookie_name),
+        Update::Issue(state) => match cookies.issue(&config.cookie_name, &state, now + ttl) {
+            Ok(value) => SetCookie::new(&config.cookie_name, &value).max_age(ttl),
+            Err(err) => {
+                warn!("Error issuing the session cookie. {}.", err);
+                return;
+            }
+        },
+    };
+
+    let set_cookie = set_cookie
+        .path(&config.path)
+        .same_site(config.same_site)
+        .to_string();
+    event.add_header(SET_COOKIE, &set_cookie);
+}
+
+#[pdk::api::entrypoint]
+async fn configure(
+    launcher: Launcher,
+    Configuration(bytes): Configuration,
+) -> Result<(), PolicyError> {
+    let config: PolicyConfiguration = serde_json::from_slice(&bytes)?;
+
+    let mut cookies = SignedCookies::new(&config.keys)?;
+    if config.encrypt {
+        cookies = cookies.encrypted();
+    }
+
+    launcher
+        .launch(|exchange, host| filter(exchange, &config, &cookies, host))
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{configure, session, update, Update};
+    use crate::config::PolicyConfiguration;
+    use pdk::api::classy::testing::{SimulatedHost, Simulator};
+    use pdk::api::http::cookies::{CookieKey, SignedCookies};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    const CONFIG: &str =
+        r#"{"keys": [{"id": "k1", "secret": "0123456789abcdef0123456789abcdef"}]}"#;
+    const STATE: &[u8] = br#"{"user":1}"#;
+
+    fn config(json: &str) -> PolicyConfiguration {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn key(id: &str) -> CookieKey {
+        CookieKey {
+            id: id.to_string(),
+            secret: format!("{}-0123456789abcdef0123456789abcdef", id),
+        }
+    }
+
+    fn at(seconds: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    fn header(name: &str, value: &str) -> (String, String) {
+        (name.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn session_is_read_from_the_cookie_header() {
+        let config = config(CONFIG);
+        let cookies = SignedCookies::new(&config.keys).unwrap();
+        let value = cookies.issue("session", STATE, at(2000)).unwrap();
+        let header = format!("theme=dark; session={}; lang=en", value);
+
+        let session = session(&config, &cookies, Some(header), at(1000)).unwrap();
+        assert_eq!(session.payload, STATE);
+        assert_eq!(session.expires, at(2000));
+        assert!(!session.reissue);
+    }
+
+    #[test]
+    fn requests_without_the_cookie_have_no_session() {
+        let config = config(CONFIG);
+        let cookies = SignedCookies::new(&config.keys).unwrap();
+        let session =
+            |header: Option<&str>| session(&config, &cookies, header.map(String::from), at(1000));
+
+        assert_eq!(session(None), None);
+        assert_eq!(session(Some("")), None);
+        assert_eq!(session(Some("theme=dark; lang=en")), None);
+        assert_eq!(session(Some("session=")), None);
+    }
+
+    #[test]
+    fn tampered_cookies_are_ignored() {
+        let config = config(CONFIG);
+        let cookies = SignedCookies::new(&config.keys).unwrap();
+        let value = cookies.issue("session", STATE, at(2000)).unwrap();
+        let session = |value: &str| {
+            session(
+                &config,
+                &cookies,
+                Some(format!("session={}", value)),
+                at(1000),
+            )
+        };
+        let parts: Vec<&str> = value.split('.').collect();
+        let with_part = |index: usize, part: &str| {
+            let mut parts = parts.clone();
+            parts[index] = part;
+            parts.join(".")
+        };
+
+        assert!(session(&value).is_some());
+        // a payload of the attacker, a later expiry, an unknown key or a forged signature
+        assert_eq!(session(&with_part(3, "eyJ1c2VyIjoyfQ")), None);
+        assert_eq!(session(&with_part(2, "9999999999")), None);
+        assert_eq!(session(&with_part(1, "k2")), None);
+        assert_eq!(session(&with_part(4, "AAAA")), None);
+        // the signature binds the value to the name of the cookie
+        let other = cookies.issue("other", STATE, at(2000)).unwrap();
+        assert_eq!(session(&other), None);
+    }
+
+    #[test]
+    fn cookies_missing_parts_are_ignored() {
+        let config = config(CONFIG);
+        let cookies = SignedCookies::new(&config.keys).unwrap();
+        let value = cookies.issue("session", STATE, at(2000)).unwrap();
+        let session = |value: &str| {
+            session(
+                &config,
+                &cookies,
+                Some(format!("session={}", value)),
+                at(1000),
+            )
+        };
+        let (unsigned, _) = value.rsplit_once('.').unwrap();
+
+        assert_eq!(session(unsigned), None);
+        assert_eq!(session(&format!("{}.", unsigned)), None);
+        assert_eq!(session("eyJ1c2VyIjoxfQ"), None);
+    }
+
+    #[test]
+    fn expired_cookies_are_ignored() {
+        let config = config(CONFIG);
+        let cookies = SignedCookies::new(&config.keys).unwrap();
+        let header = format!(
+            "session={}",
+            cookies.issue("session", STATE, at(2000)).unwrap()
+        );
+
+        assert!(session(&config, &cookies, Some(header.clone()), at(1999)).is_some());
+        assert_eq!(
+            session(&config, &cookies, Some(header.clone()), at(2000)),
+            None
+        );
+        assert_eq!(session(&config, &cookies, Some(header), at(3000)), None);
+    }
+
+    #[test]
+    fn cookies_of_a_rotated_key_are_issued_again() {
+        let config = config(CONFIG);
+        let old = SignedCookies::new(&[key("old")]).unwrap();
+        let rotated = SignedCookies::new(&[key("new"), key("old")]).unwrap();
+        let header = format!("session={}", old.issue("session", STATE, at(2000)).unwrap());
+
+        let session = session(&config, &rotated, Some(header), at(1000)).unwrap();
+        assert!(session.reissue);
+        assert_eq!(
+            update(&config, Some(session), None, at(1000)),
+            Update::Issue(STATE.to_vec())
+        );
+    }
+
+    #[test]
+    fn state_of_the_upstream_updates_the_session() {
+        let config = config(CONFIG);
+        let state = |state: &str| update(&config, None, Some(state.to_string()), at(1000));
+
+        assert_eq!(
+            state(r#"{"user":2}"#),
+            Update::Issue(br#"{"user":2}"#.to_vec())
+        );
+        assert_eq!(state(" null "), Update::Remove);
+        assert_eq!(state(""), Update::Remove);
+        assert_eq!(state("{not json"), Update::Keep);
+        assert_eq!(update(&config, None, None, at(1000)), Update::Keep);
+    }
+
+    #[test]
+    fn session_is_issued_again_after_half_its_lifetime() {
+        let config = config(CONFIG);
+        let cookies = SignedCookies::new(&config.keys).unwrap();
+        let header = format!(
+            "session={}",
+            cookies.issue("session", STATE, at(1800)).unwrap()
+        );
+        let session = |now| session(&config, &cookies, Some(header.clone()), now);
+
+        assert_eq!(
+            update(&config, session(at(900)), None, at(900)),
+            Update::Keep
+        );
+        assert_eq!(
+            update(&config, session(at(901)), None, at(901)),
+            Update::Issue(STATE.to_vec())
+        );
+    }
+
+    #[test]
+    fn keys_are_required() {
+        assert!(serde_json::from_str::<PolicyConfiguration>("{}").is_err());
+        assert!(SignedCookies::new(&config(r#"{"keys": []}"#).keys).is_err());
+    }
+
+    #[test]
+    fn session_goes_through_the_cookie_only() {
+        let host = SimulatedHost::new().with_configuration(CONFIG.as_bytes());
+        let mut simulator = Simulator::new(host, configure);
+        simulator.host().set_time(at(1000));
+
+        // the upstream starts the session, the client can't set its state
+        let mut exchange = simulator.exchange();
+        exchange.request_headers(vec![(":path", "/"), ("x-session", r#"{"user":0}"#)]);
+        assert_eq!(
+            simulator.host().request_headers(),
+            vec![header(":path", "/")]
+        );
+        exchange.response_headers(vec![
+            (":status", "200"),
+            ("x-session-update", r#"{"user":1}"#),
+        ]);
+        let headers = simulator.host().response_headers();
+        assert_eq!(headers.len(), 2);
+        let (name, set_cookie) = &headers[1];
+        assert_eq!(name, "set-cookie");
+        let (value, attributes) = set_cookie
+            .strip_prefix("session=")
+            .and_then(|set_cookie| set_cookie.split_once("; "))
+            .unwrap();
+        assert_eq!(
+            attributes,
+            "Path=/; Max-Age=1800; SameSite=Lax; Secure; HttpOnly"
+        );
+        drop(exchange);
+
+        // the next request of the client gives the state to the upstream
+        let cookie = format!("session={}", value);
+        let mut exchange = simulator.exchange();
+        exchange.request_headers(vec![(":path", "/"), ("cookie", &cookie)]);
+        assert_eq!(
+            simulator.host().request_headers(),
+            vec![
+                header(":path", "/"),
+                header("cookie", &cookie),
+                header("x-session", r#"{"user":1}"#),
+            ]
+        );
+        exchange.response_headers(vec![(":status", "200"), ("x-session-update", "null")]);
+        assert_eq!(
+            simulator.host().response_headers(),
+            vec![
+                header(":status", "200"),
+                header(
+                    "set-cookie",
+                    "session=; Path=/; Max-Age=0; SameSite=Lax; Secure; HttpOnly"
+                ),
+            ]
+        );
+    }
+}
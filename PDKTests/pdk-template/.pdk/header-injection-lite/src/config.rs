@@ -1,5 +1,6 @@
 // Copyright 2023 Salesforce, Inc. All rights reserved.
 use pdk::api::expression::Expression;
+use pdk::api::http::header_transform::HeaderTransform;
 use serde::Deserialize;
 
 #[derive(Deserialize, Debug)]
@@ -16,4 +17,12 @@ pub struct Config {
 
     #[serde(alias = "outboundHeaders")]
     pub outbound_headers: Vec<Header>,
+
+    // removes or renames the headers of the requests, before the inbound headers are injected
+    #[serde(alias = "inboundHeaderRules", default)]
+    pub inbound_header_rules: HeaderTransform,
+
+    // removes or renames the headers of the responses, before the outbound headers are injected
+    #[serde(alias = "outboundHeaderRules", default)]
+    pub outbound_header_rules: HeaderTransform,
 }
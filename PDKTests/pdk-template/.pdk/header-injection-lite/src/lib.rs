@@ -35,6 +35,7 @@ async fn filter(exchange: Exchange<RequestHeaders>, config: &Config) {
         // request headers event was read
         logger::info!("Applying header-injection-lite filter for request");
 
+        config.inbound_header_rules.apply(&event);
         let resolve_on_request = |e: &Expression| e.resolve_on_request_headers(&event).ok();
         inject_headers(&config.inbound_headers, &event, resolve_on_request);
     }
@@ -46,6 +47,7 @@ async fn filter(exchange: Exchange<RequestHeaders>, config: &Config) {
         // response headers event was read
         logger::info!("Applying header-injection-lite filter for response");
 
+        config.outbound_header_rules.apply(&event);
         let resolve_on_response = |e: &Expression| e.resolve_on_response_headers(&event).ok();
         inject_headers(&config.outbound_headers, &event, resolve_on_response);
     }
@@ -61,3 +63,98 @@ async fn configure(launcher: Launcher, Configuration(config_bytes): Configuratio
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::configure;
+    use pdk::api::classy::proxy_wasm::types::Action;
+    use pdk::api::classy::testing::{SimulatedHost, Simulator};
+
+    // The headers are injected from the compiled expressions:
+    // #[attributes.headers['x-user']] and #['gateway'].
+    const CONFIG: &[u8] = br#"{
+        "inboundHeaders": [
+            {
+                "key": "x-forwarded-user",
+                "value": "P[[\".\", \"0-26\", [\".\", \"0-18\", [\":ref\", \"0-10\", \"attributes\"], [\":str\", \"11-18\", \"headers\"]], [\":str\", \"19-26\", \"x-user\"]]]"
+            }
+        ],
+        "outboundHeaders": [
+            {"key": "x-served-by", "value": "P[[\":str\", \"0-9\", \"gateway\"]]"}
+        ],
+        "inboundHeaderRules": [{"prefix": "x-internal-"}],
+        "outboundHeaderRules": [{"prefix": "x-legacy-", "renameTo": "x-new-"}]
+    }"#;
+
+    fn header(name: &str, value: &str) -> (String, String) {
+        (name.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn headers_are_injected_on_request_and_response() {
+        let mut simulator =
+            Simulator::new(SimulatedHost::new().with_configuration(CONFIG), configure);
+        let mut exchange = simulator.exchange();
+
+        let action = exchange.request_headers(vec![
+            (":path", "/orders"),
+            ("x-user", "alice"),
+            ("x-internal-token", "secret"),
+        ]);
+        assert_eq!(action, Action::Continue);
+        assert_eq!(
+            simulator.host().request_headers(),
+            vec![
+                header(":path", "/orders"),
+                header("x-user", "alice"),
+                header("x-forwarded-user", "alice"),
+            ]
+        );
+
+        let action =
+            exchange.response_headers(vec![(":status", "200"), ("x-legacy-cache", "miss")]);
+        assert_eq!(action, Action::Continue);
+        assert_eq!(
+            simulator.host().response_headers(),
+            vec![
+                header(":status", "200"),
+                header("x-new-cache", "miss"),
+                header("x-served-by", "gateway"),
+            ]
+        );
+        assert_eq!(simulator.host().local_response(), None);
+    }
+
+    #[test]
+    fn header_without_a_value_is_not_injected() {
+        let mut simulator =
+            Simulator::new(SimulatedHost::new().with_configuration(CONFIG), configure);
+        let mut exchange = simulator.exchange();
+
+        exchange.request_headers(vec![(":path", "/orders")]);
+
+        assert_eq!(
+            simulator.host().request_headers(),
+            vec![header(":path", "/orders")]
+        );
+    }
+
+    #[test]
+    fn invalid_configuration_rejects_the_requests() {
+        let host = SimulatedHost::new().with_configuration(br#"{"inboundHeaders": []}"#);
+        let mut simulator = Simulator::new(host, configure);
+        let mut exchange = simulator.exchange();
+
+        assert_eq!(
+            exchange.request_headers(vec![(":path", "/orders")]),
+            Action::Pause
+        );
+        assert_eq!(
+            simulator
+                .host()
+                .local_response()
+                .map(|response| response.status_code),
+            Some(503)
+        );
+    }
+}
@@ -0,0 +1,30 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+pub struct PolicyConfiguration {
+    /// The authority sent to the upstream, like `my-bucket.s3.amazonaws.com`, with the port
+    /// when it is not the default one.
+    pub host: String,
+    /// The SNI of the upstream connection. The name of `host` when missing, none when empty.
+    #[serde(default)]
+    pub sni: Option<String>,
+    /// Validates the certificate of the upstream against the SNI instead of the names of the
+    /// service.
+    #[serde(alias = "validateSubjectAltName", default = "default_true")]
+    pub validate_subject_alt_name: bool,
+    /// The header keeping the authority of the client, none when empty.
+    #[serde(
+        alias = "forwardedHostHeader",
+        default = "default_forwarded_host_header"
+    )]
+    pub forwarded_host_header: String,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_forwarded_host_header() -> String {
+    "x-forwarded-host".to_string()
+}
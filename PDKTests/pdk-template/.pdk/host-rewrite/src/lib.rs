@@ -0,0 +1,232 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use crate::config::PolicyConfiguration;
+use pdk::api::classy::bootstrap::Launcher;
+use pdk::api::classy::event::{EventData, Exchange, HeadersAccessor, RequestHeaders};
+use pdk::api::classy::Configuration;
+use pdk::api::error::PolicyError;
+use pdk::api::logger::{debug, warn};
+use pdk::api::property::PropertyAccessor;
+use std::net::IpAddr;
+
+mod config;
+
+const AUTHORITY: &str = ":authority";
+
+/// The host of an authority, without the port nor the brackets of IPv6 addresses.
+fn host_name(authority: &str) -> &str {
+    if let Some(rest) = authority.strip_prefix('[') {
+        return rest.split(']').next().unwrap_or_default();
+    }
+    authority.split(':').next().unwrap_or_default()
+}
+
+/// The SNI of the upstream connection, `None` to leave the one of the service.
+fn server_name(config: &PolicyConfiguration) -> Option<String> {
+    let name = match &config.sni {
+        Some(sni) if sni.is_empty() => return None,
+        Some(sni) => sni.as_str(),
+        None => host_name(&config.host),
+    };
+
+    // Addresses are never sent as SNI.
+    if name.parse::<IpAddr>().is_ok() {
+        warn!(
+            "The upstream host {} is an address, the SNI is not rewritten.",
+            name
+        );
+        return None;
+    }
+    Some(name.to_ascii_lowercase())
+}
+
+fn rewrite(
+    event: &EventData<RequestHeaders>,
+    config: &PolicyConfiguration,
+    properties: &dyn PropertyAccessor,
+    server_name: Option<&str>,
+) {
+    let authority = event.authority();
+    debug!("Rewriting the authority {} to {}.", authority, config.host);
+    if !config.forwarded_host_header.is_empty() && !authority.is_empty() {
+        event.set_header(config.forwarded_host_header.as_str(), &authority);
+    }
+    event.set_header(AUTHORITY, &config.host);
+
+    // The certificate of a virtual hosted upstream is issued for the rewritten host, the
+    // handshake must ask for it and validate it the same way.
+    if let Some(name) = server_name {
+        let filter_state = properties.filter_state();
+        filter_state.set_upstream_server_name(name);
+        if config.validate_subject_alt_name {
+            filter_state.set_upstream_subject_alt_names(&[name]);
+        }
+    }
+}
+
+async fn filter(
+    exchange: Exchange<RequestHeaders>,
+    config: &PolicyConfiguration,
+    properties: &'static dyn PropertyAccessor,
+    server_name: Option<&str>,
+) {
+    if let Some(event) = exchange.event_data() {
+        rewrite(&event, config, properties, server_name);
+    }
+}
+
+#[pdk::api::entrypoint]
+async fn configure(
+    launcher: Launcher,
+    Configuration(bytes): Configuration,
+) -> Result<(), PolicyError> {
+    let config: PolicyConfiguration = serde_json::from_slice(&bytes)?;
+    let server_name = server_name(&config);
+
+    launcher
+        .launch(|exchange, properties| {
+            filter(exchange, &config, properties, server_name.as_deref())
+        })
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{configure, host_name, server_name};
+    use crate::config::PolicyConfiguration;
+    use pdk::api::classy::testing::{SimulatedHost, Simulator};
+
+    fn config(json: &str) -> PolicyConfiguration {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn header(name: &str, value: &str) -> (String, String) {
+        (name.to_string(), value.to_string())
+    }
+
+    // The headers the upstream gets for the ones of the client.
+    fn rewritten(config: &str, headers: Vec<(&str, &str)>) -> Vec<(String, String)> {
+        let host = SimulatedHost::new().with_configuration(config.as_bytes());
+        let mut simulator = Simulator::new(host, configure);
+        let mut exchange = simulator.exchange();
+
+        exchange.request_headers(headers);
+        assert_eq!(simulator.host().local_response(), None);
+        simulator.host().request_headers()
+    }
+
+    #[test]
+    fn authority_is_rewritten() {
+        let headers = rewritten(
+            r#"{"host": "my-bucket.s3.amazonaws.com"}"#,
+            vec![(":authority", "api.example.com"), (":path", "/")],
+        );
+
+        assert_eq!(
+            headers,
+            vec![
+                header(":path", "/"),
+                header("x-forwarded-host", "api.example.com"),
+                header(":authority", "my-bucket.s3.amazonaws.com"),
+            ]
+        );
+    }
+
+    #[test]
+    fn ports_are_preserved() {
+        let headers = rewritten(
+            r#"{"host": "storage.internal:8443"}"#,
+            vec![(":authority", "api.example.com:8081"), (":path", "/")],
+        );
+
+        assert_eq!(
+            headers,
+            vec![
+                header(":path", "/"),
+                header("x-forwarded-host", "api.example.com:8081"),
+                header(":authority", "storage.internal:8443"),
+            ]
+        );
+    }
+
+    #[test]
+    fn forwarded_host_of_the_client_is_replaced() {
+        let headers = rewritten(
+            r#"{"host": "upstream.internal"}"#,
+            vec![
+                (":authority", "api.example.com"),
+                ("x-forwarded-host", "spoofed.example.com"),
+            ],
+        );
+
+        assert_eq!(
+            headers,
+            vec![
+                header("x-forwarded-host", "api.example.com"),
+                header(":authority", "upstream.internal"),
+            ]
+        );
+    }
+
+    #[test]
+    fn forwarded_host_goes_in_the_configured_header_or_nowhere() {
+        let headers = rewritten(
+            r#"{"host": "upstream.internal", "forwardedHostHeader": "x-original-host"}"#,
+            vec![(":authority", "api.example.com")],
+        );
+        assert_eq!(
+            headers,
+            vec![
+                header("x-original-host", "api.example.com"),
+                header(":authority", "upstream.internal"),
+            ]
+        );
+
+        let headers = rewritten(
+            r#"{"host": "upstream.internal", "forwardedHostHeader": ""}"#,
+            vec![(":authority", "api.example.com")],
+        );
+        assert_eq!(headers, vec![header(":authority", "upstream.internal")]);
+    }
+
+    #[test]
+    fn request_without_an_authority_gets_no_forwarded_host() {
+        let headers = rewritten(r#"{"host": "upstream.internal"}"#, vec![(":path", "/")]);
+
+        assert_eq!(
+            headers,
+            vec![
+                header(":path", "/"),
+                header(":authority", "upstream.internal")
+            ]
+        );
+    }
+
+    #[test]
+    fn host_name_has_no_port() {
+        assert_eq!(host_name("example.com"), "example.com");
+        assert_eq!(host_name("example.com:8443"), "example.com");
+        assert_eq!(host_name("[2001:db8::1]:8443"), "2001:db8::1");
+        assert_eq!(host_name("[2001:db8::1]"), "2001:db8::1");
+    }
+
+    #[test]
+    fn server_name_is_the_name_of_the_host() {
+        let server_name = |json: &str| server_name(&config(json));
+
+        assert_eq!(
+            server_name(r#"{"host": "My-Bucket.S3.amazonaws.com:443"}"#).as_deref(),
+            Some("my-bucket.s3.amazonaws.com")
+        );
+        assert_eq!(
+            server_name(r#"{"host": "upstream.internal", "sni": "cdn.example.com"}"#).as_deref(),
+            Some("cdn.example.com")
+        );
+        assert_eq!(
+            server_name(r#"{"host": "upstream.internal", "sni": ""}"#),
+            None
+        );
+        assert_eq!(server_name(r#"{"host": "10.0.0.1:8443"}"#), None);
+        assert_eq!(server_name(r#"{"host": "[2001:db8::1]:8443"}"#), None);
+    }
+}
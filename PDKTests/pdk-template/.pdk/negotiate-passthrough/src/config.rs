@@ -0,0 +1,28 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use pdk::api::http::negotiate::AuthScheme;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+pub struct PolicyConfiguration {
+    #[serde(default = "default_schemes")]
+    pub schemes: Vec<AuthScheme>,
+    /// Asks the proxy not to retry the legs of a handshake.
+    #[serde(alias = "disableRetries", default = "default_disable_retries")]
+    pub disable_retries: bool,
+    /// The header with the client address, for a consistent hash load balancing to keep the
+    /// legs of a handshake on the same upstream. Empty to leave it out.
+    #[serde(alias = "affinityHeader", default = "default_affinity_header")]
+    pub affinity_header: String,
+}
+
+fn default_schemes() -> Vec<AuthScheme> {
+    vec![AuthScheme::Negotiate, AuthScheme::Ntlm]
+}
+
+fn default_disable_retries() -> bool {
+    true
+}
+
+fn default_affinity_header() -> String {
+    "x-affinity-key".to_string()
+}
@@ -36,6 +36,9 @@ pub struct Launcher {
     host: Rc<dyn Host>,
 }
 
+/// The response header carrying the time a filter took.
+pub const POLICY_DURATION_HEADER: &str = "x-policy-duration-ms";
+
 #[derive(thiserror::Error, Debug)]
 #[error("Launch Error")]
 pub struct LaunchError {}
@@ -45,6 +48,33 @@ impl Launcher {
         Self { reactor, host }
     }
 
+    /// Adds the time the filter took to the response headers, as a
+    /// [`POLICY_DURATION_HEADER`] with the `<plugin name>=<milliseconds>` value, or just the
+    /// milliseconds when the proxy does not tell the plugin name. Every policy of
+    /// a chain adds its own, telling which one adds latency. The time of a filter still running
+    /// when the response headers arrive is measured until then.
+    pub fn with_duration_header(self, enabled: bool) -> Self {
+        self.reactor.set_duration_header(enabled);
+        self
+    }
+
+    /// Runs `hook` when the proxy drains the plugin, usually as a new configuration is
+    /// deployed, before new exchanges stop reaching the filter. Policies buffering state, like
+    /// telemetry batches or audit queues, flush it here. The hook runs synchronously: HTTP
+    /// calls dispatched from it are sent, but their responses are not awaited.
+    pub fn on_drain(self, hook: impl FnOnce() + 'static) -> Self {
+        self.reactor.add_drain_hook(Box::new(hook));
+        self
+    }
+
+    /// Runs `hook` when the proxy deletes the plugin, as the VM is torn down. Unlike
+    /// [`on_drain`](Self::on_drain), it also runs when the proxy skips draining, but the VM
+    /// may be gone before the HTTP calls dispatched from it are sent.
+    pub fn on_vm_shutdown(self, hook: impl FnOnce() + 'static) -> Self {
+        self.reactor.add_shutdown_hook(Box::new(hook));
+        self
+    }
+
     pub async fn launch<H, T, E>(self, filter: H) -> Result<(), LaunchError>
     where
         H: Handler<Exchange<E>, T, Result = ()>,
@@ -53,6 +83,7 @@ impl Launcher {
     {
         let reactor = self.reactor.clone();
         let host = &self.host;
+        let filter = &filter;
 
         let contexts = ContextCreateStream::new(reactor.clone());
 
@@ -61,9 +92,15 @@ impl Launcher {
                 let context =
                     FilterContext::new(host.clone(), reactor.clone(), http_reactor.clone());
                 let exchange: Exchange<Start> = Exchange::new(http_reactor.clone(), host.clone());
-                let extraction_result = T::from_context(&context).map(|args| async {
+                let timer = (http_reactor.clone(), host.clone());
+                let extraction_result = T::from_context(&context).map(|args| async move {
                     let exchange = exchange.wait_for_event::<E>().await;
-                    filter.call(exchange, args).await
+                    let (http_reactor, host) = timer;
+
+                    http_reactor.start_filter(host.get_current_time());
+                    filter.call(exchange, args).await;
+                    let duration = http_reactor.complete_filter(host.get_current_time());
+                    log::debug!("Filter completed in {} ms", duration.as_millis());
                 });
                 (http_reactor, extraction_result)
             })
@@ -5,7 +5,7 @@ use std::{
     marker::PhantomData,
     rc::Rc,
     task::{Poll, Waker},
-    time::Duration,
+    time::{Duration, SystemTime},
 };
 
 use proxy_wasm::types::{Bytes, Status};
@@ -22,6 +22,14 @@ use crate::{
     types::{Cid, RequestId},
 };
 
+mod retry;
+mod single_flight;
+mod target;
+
+pub use retry::{is_retriable, Retry, RetryPolicy};
+pub use single_flight::{SingleFlight, SingleFlightError};
+pub use target::{CalloutTarget, Endpoint, Selection};
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct HttpCallResponse {
     pub request_id: RequestId,
@@ -59,6 +67,23 @@ impl HttpClient {
     ) -> RequestBuilder<'a, EmptyResponseExtractor> {
         RequestBuilder::new(self, upstream, authority, EmptyResponseExtractor)
     }
+
+    /// Starts a request to the given endpoint, usually obtained from [`CalloutTarget::select`].
+    pub fn request_to<'a>(
+        &'a self,
+        endpoint: &'a Endpoint,
+    ) -> RequestBuilder<'a, EmptyResponseExtractor> {
+        let builder = self.request(endpoint.name(), endpoint.authority());
+        match endpoint.timeout() {
+            Some(timeout) => builder.timeout(timeout),
+            None => builder,
+        }
+    }
+
+    /// Selects the healthiest endpoint of `target` at the current host time.
+    pub fn select<'a>(&self, target: &'a CalloutTarget) -> &'a Endpoint {
+        target.select(self.host.get_current_time())
+    }
 }
 
 impl<C> FromContext<C> for HttpClient
@@ -267,7 +292,11 @@ where
             .map_err(HttpClientRequestError::Status)?
             .into();
 
-        let extractor = boxed_extractor(self.client.host.clone(), self.extractor);
+        let sent = Callout {
+            upstream: self.upstream.to_string(),
+            time: self.client.host.get_current_time(),
+        };
+        let extractor = boxed_extractor(self.client.host.clone(), sent, self.extractor);
 
         self.client.reactor.insert_extractor(request_id, extractor);
 
@@ -283,12 +312,31 @@ impl<'a, E: ResponseExtractor> ResponseExtractor for RequestBuilder<'a, E> {
     }
 }
 
-fn boxed_extractor<E>(buffers: Rc<dyn Host>, extractor: E) -> BoxedExtractor
+/// Where and when a call was sent, to log how long it took.
+struct Callout {
+    upstream: String,
+    time: SystemTime,
+}
+
+fn boxed_extractor<E>(buffers: Rc<dyn Host>, sent: Callout, extractor: E) -> BoxedExtractor
 where
     E: ResponseExtractor + 'static,
     E::Output: 'static,
 {
-    Box::new(move |event| Box::new(extractor.extract(event, &buffers)))
+    Box::new(move |event| {
+        let duration = log::log_enabled!(log::Level::Debug)
+            .then(|| buffers.get_current_time().duration_since(sent.time).ok())
+            .flatten();
+        if let Some(duration) = duration {
+            log::debug!(
+                "Call to {} completed with status {} in {} ms",
+                sent.upstream,
+                buffers.status_code(),
+                duration.as_millis()
+            );
+        }
+        Box::new(extractor.extract(event, &buffers))
+    })
 }
 
 pub struct EmptyResponseExtractor;
@@ -0,0 +1,282 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use std::{
+    fmt,
+    future::Future,
+    rc::Rc,
+    time::{Duration, SystemTime},
+};
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(10);
+const DEFAULT_MULTIPLIER: u32 = 2;
+
+type StatusPredicate = Rc<dyn Fn(Option<u32>) -> bool>;
+
+/// Whether a call ending with `status` is retried by default: when the upstream was not
+/// reached, answered `429 Too Many Requests` or failed with a `5xx`.
+pub fn is_retriable(status: Option<u32>) -> bool {
+    match status {
+        None | Some(0) => true,
+        Some(status) => status == 429 || (500..600).contains(&status),
+    }
+}
+
+/// What to do once an attempt of a call completed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Retry {
+    /// The attempt succeeded, or failed with a status not retried.
+    Done,
+    /// The call is attempted again, not before the given time.
+    At(SystemTime),
+    /// The attempt failed and it was the last one.
+    GiveUp,
+}
+
+/// How the calls failing with a retriable status are attempted again.
+///
+/// The wait before the attempt `n + 1` is `initial_backoff * multiplier^(n - 1)`, capped at
+/// `max_backoff`. The policy does not depend on the host, so raw proxy-wasm contexts can use
+/// [`RetryPolicy::next`] with their own clock and dispatch the next attempt when it is due.
+///
+/// Classy has no timers, so the policy only tells when the next attempt is due. The async
+/// filters wait through [`RetryPolicy::run`] with a delay of their own, while the raw contexts
+/// keep the attempts and dispatch them once due, like the contexts retrying on their tick:
+///
+/// ```ignore
+/// match retry.next(attempt, Some(response.status_code()), host.get_current_time()) {
+///     Retry::Done => {}
+///     Retry::At(due) => pending.push((due, attempt + 1)),
+///     Retry::GiveUp => log::warn!("Giving up after {attempt} attempts"),
+/// }
+/// ```
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    multiplier: u32,
+    retry_on: StatusPredicate,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_ATTEMPTS)
+    }
+}
+
+impl fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("initial_backoff", &self.initial_backoff)
+            .field("max_backoff", &self.max_backoff)
+            .field("multiplier", &self.multiplier)
+            .finish()
+    }
+}
+
+impl RetryPolicy {
+    /// Creates a policy making up to `max_attempts` attempts of a call, the first one included.
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+            multiplier: DEFAULT_MULTIPLIER,
+            retry_on: Rc::new(is_retriable),
+        }
+    }
+
+    /// The wait before the second attempt.
+    pub fn initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// The longest wait between two attempts.
+    pub fn max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// How much the wait grows after each failed attempt.
+    pub fn multiplier(mut self, multiplier: u32) -> Self {
+        self.multiplier = multiplier.max(1);
+        self
+    }
+
+    /// Replaces [`is_retriable`] as the statuses retried. The status is none when the upstream
+    /// was not reached.
+    pub fn retry_on<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(Option<u32>) -> bool + 'static,
+    {
+        self.retry_on = Rc::new(predicate);
+        self
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// The wait after the failed `attempt`, counted from 1.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1);
+        let factor = self.multiplier.checked_pow(exponent).unwrap_or(u32::MAX);
+        self.initial_backoff
+            .checked_mul(factor)
+            .map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff))
+    }
+
+    /// Whether the call is attempted again after the `attempt` ended with `status`.
+    pub fn should_retry(&self, attempt: u32, status: Option<u32>) -> bool {
+        attempt < self.max_attempts && (self.retry_on)(status)
+    }
+
+    /// What to do once the `attempt`, counted from 1, ended with `status` at `now`.
+    pub fn next(&self, attempt: u32, status: Option<u32>, now: SystemTime) -> Retry {
+        if !(self.retry_on)(status) {
+            Retry::Done
+        } else if attempt < self.max_attempts {
+            Retry::At(now + self.backoff(attempt))
+        } else {
+            Retry::GiveUp
+        }
+    }
+
+    /// Runs the `call` until it ends with a status not retried or the attempts are exhausted,
+    /// and returns the outcome of the last attempt. The `call` gets the number of the attempt,
+    /// `status` reads the status of its outcome, and `wait` resolves once the backoff before
+    /// the next attempt elapsed.
+    ///
+    /// ```ignore
+    /// let response = retry
+    ///     .run(
+    ///         |_| async { client.request("upstream", "authority").get()?.await },
+    ///         |response| response.as_ref().ok().map(|response| response.status_code()),
+    ///         |backoff| delay.wait(backoff),
+    ///     )
+    ///     .await;
+    /// ```
+    pub async fn run<F, Fut, S, T, W, WFut>(&self, mut call: F, status: S, wait: W) -> T
+    where
+        F: FnMut(u32) -> Fut,
+        Fut: Future<Output = T>,
+        S: Fn(&T) -> Option<u32>,
+        W: Fn(Duration) -> WFut,
+        WFut: Future<Output = ()>,
+    {
+        let mut attempt = 1;
+        loop {
+            let outcome = call(attempt).await;
+            let status = status(&outcome);
+            if !self.should_retry(attempt, status) {
+                return outcome;
+            }
+            let backoff = self.backoff(attempt);
+            log::debug!(
+                "Retrying the call in {backoff:?} after attempt {attempt} ended with {status:?}"
+            );
+            wait(backoff).await;
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::{Cell, RefCell},
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    use futures::executor::block_on;
+
+    use super::{is_retriable, Retry, RetryPolicy};
+
+    fn at(millis: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn default_retriable_statuses() {
+        assert!(is_retriable(None));
+        assert!(is_retriable(Some(0)));
+        assert!(is_retriable(Some(429)));
+        assert!(is_retriable(Some(503)));
+        assert!(!is_retriable(Some(200)));
+        assert!(!is_retriable(Some(404)));
+    }
+
+    #[test]
+    fn backoff_grows_exponentially_up_to_the_maximum() {
+        let retry = RetryPolicy::new(10)
+            .initial_backoff(Duration::from_millis(100))
+            .multiplier(3)
+            .max_backoff(Duration::from_secs(2));
+
+        assert_eq!(retry.backoff(1), Duration::from_millis(100));
+        assert_eq!(retry.backoff(2), Duration::from_millis(300));
+        assert_eq!(retry.backoff(3), Duration::from_millis(900));
+        assert_eq!(retry.backoff(4), Duration::from_secs(2));
+        assert_eq!(retry.backoff(40), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn next_attempt_is_scheduled_until_exhausted() {
+        let retry = RetryPolicy::new(3).initial_backoff(Duration::from_millis(100));
+
+        assert_eq!(retry.next(1, Some(200), at(0)), Retry::Done);
+        assert_eq!(retry.next(1, Some(503), at(0)), Retry::At(at(100)));
+        assert_eq!(retry.next(2, None, at(1000)), Retry::At(at(1200)));
+        assert_eq!(retry.next(3, Some(503), at(2000)), Retry::GiveUp);
+    }
+
+    #[test]
+    fn custom_predicate_replaces_the_default() {
+        let retry = RetryPolicy::new(3).retry_on(|status| status == Some(409));
+
+        assert!(retry.should_retry(1, Some(409)));
+        assert!(!retry.should_retry(1, Some(503)));
+        assert!(!retry.should_retry(3, Some(409)));
+    }
+
+    #[test]
+    fn run_waits_the_backoff_between_attempts() {
+        let calls = Cell::new(0);
+        let waits = RefCell::new(Vec::new());
+        let statuses = [503, 429, 200];
+        let retry = RetryPolicy::new(5).initial_backoff(Duration::from_millis(100));
+        let status = block_on(retry.run(
+            |attempt| {
+                calls.set(attempt);
+                async move { statuses[attempt as usize - 1] }
+            },
+            |status| Some(*status),
+            |backoff| {
+                waits.borrow_mut().push(backoff);
+                async {}
+            },
+        ));
+        assert_eq!((status, calls.get()), (200, 3));
+        assert_eq!(
+            waits.take(),
+            [Duration::from_millis(100), Duration::from_millis(200)]
+        );
+
+        let status = block_on(RetryPolicy::new(2).run(
+            |attempt| {
+                calls.set(attempt);
+                async { 503 }
+            },
+            |status| Some(*status),
+            |backoff| {
+                waits.borrow_mut().push(backoff);
+                async {}
+            },
+        ));
+        assert_eq!((status, calls.get()), (503, 2));
+        assert_eq!(waits.take().len(), 1);
+    }
+}
@@ -0,0 +1,371 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    convert::Infallible,
+    future::Future,
+    hash::Hash,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+};
+
+use crate::{extract::FromContext, reactor::root::RootReactor};
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum SingleFlightError {
+    #[error("In-flight callout was dropped before completion")]
+    Abandoned,
+}
+
+enum State<V> {
+    InFlight(Vec<Waker>),
+    Completed(V),
+    Abandoned,
+}
+
+type Flight<V> = Rc<RefCell<State<V>>>;
+type Flights<K, V> = Rc<RefCell<HashMap<K, Flight<V>>>>;
+
+/// Coalesces identical concurrent callouts.
+///
+/// The first caller for a given key runs the callout, while any caller arriving with the
+/// same key before it completes is parked and receives a clone of the same result. The
+/// callouts in flight are kept by the root context, so every `SingleFlight` of the same key
+/// and value types extracted by its filters shares them.
+pub struct SingleFlight<K, V> {
+    reactor: Rc<RootReactor>,
+    flights: Flights<K, V>,
+}
+
+impl<K, V> SingleFlight<K, V>
+where
+    K: Hash + Eq + Clone + 'static,
+    V: Clone + 'static,
+{
+    pub(crate) fn new(reactor: Rc<RootReactor>) -> Self {
+        let flights = reactor.shared::<RefCell<HashMap<K, Flight<V>>>>();
+        Self { reactor, flights }
+    }
+
+    /// Returns the number of callouts currently in flight.
+    pub fn in_flight(&self) -> usize {
+        self.flights.borrow().len()
+    }
+
+    pub async fn run<F, Fut>(&self, key: K, call: F) -> Result<V, SingleFlightError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V>,
+    {
+        let existing = self.flights.borrow().get(&key).cloned();
+
+        if let Some(flight) = existing {
+            return Follower {
+                reactor: Rc::clone(&self.reactor),
+                flight,
+                slot: None,
+            }
+            .await;
+        }
+
+        let flight = Rc::new(RefCell::new(State::InFlight(Vec::new())));
+        self.flights
+            .borrow_mut()
+            .insert(key.clone(), Rc::clone(&flight));
+
+        let mut guard = LeaderGuard {
+            flights: Rc::clone(&self.flights),
+            key: Some(key),
+            flight,
+        };
+
+        let value = call().await;
+        guard.complete(value.clone());
+        Ok(value)
+    }
+}
+
+impl<K, V, C> FromContext<C> for SingleFlight<K, V>
+where
+    K: Hash + Eq + Clone + 'static,
+    V: Clone + 'static,
+    Rc<RootReactor>: FromContext<C, Error = Infallible>,
+{
+    type Error = Infallible;
+
+    fn from_context(context: &C) -> Result<Self, Self::Error> {
+        Ok(Self::new(Rc::<RootReactor>::from_context(context)?))
+    }
+}
+
+struct LeaderGuard<K: Hash + Eq, V> {
+    flights: Flights<K, V>,
+    key: Option<K>,
+    flight: Flight<V>,
+}
+
+impl<K: Hash + Eq, V> LeaderGuard<K, V> {
+    fn finish(&mut self, state: State<V>) {
+        if let Some(key) = self.key.take() {
+            self.flights.borrow_mut().remove(&key);
+            let previous = self.flight.replace(state);
+            if let State::InFlight(wakers) = previous {
+                wakers.into_iter().for_each(Waker::wake);
+            }
+        }
+    }
+
+    fn complete(&mut self, value: V) {
+        self.finish(State::Completed(value))
+    }
+}
+
+impl<K: Hash + Eq, V> Drop for LeaderGuard<K, V> {
+    fn drop(&mut self) {
+        self.finish(State::Abandoned)
+    }
+}
+
+struct Follower<V> {
+    reactor: Rc<RootReactor>,
+    flight: Flight<V>,
+    // The index of its waker among the ones of the flight, once registered.
+    slot: Option<usize>,
+}
+
+impl<V: Clone> Future for Follower<V> {
+    type Output = Result<V, SingleFlightError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        match &mut *this.flight.borrow_mut() {
+            State::Completed(value) => Poll::Ready(Ok(value.clone())),
+            State::Abandoned => Poll::Ready(Err(SingleFlightError::Abandoned)),
+            State::InFlight(wakers) => {
+                match this.slot {
+                    None => {
+                        // Pause the waiting exchange as if it had dispatched the callout itself.
+                        this.reactor.set_paused(this.reactor.active_cid(), true);
+                        this.slot = Some(wakers.len());
+                        wakers.push(cx.waker().clone());
+                    }
+                    Some(slot) if !wakers[slot].will_wake(cx.waker()) => {
+                        wakers[slot] = cx.waker().clone();
+                    }
+                    Some(_) => {}
+                }
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use futures::{channel::oneshot, executor::LocalPool, task::LocalSpawnExt};
+
+    use super::{Follower, SingleFlight, SingleFlightError, State};
+    use crate::{reactor::root::RootReactor, types::RootCid};
+
+    fn single_flight() -> Rc<SingleFlight<&'static str, u32>> {
+        Rc::new(SingleFlight::new(Rc::new(RootReactor::new(RootCid::from(
+            1,
+        )))))
+    }
+
+    #[test]
+    fn coalesces_concurrent_calls() {
+        let mut pool = LocalPool::new();
+        let flights = single_flight();
+        let calls = Rc::new(Cell::new(0));
+        let results = Rc::new(Cell::new(0));
+        let (sender, receiver) = oneshot::channel::<u32>();
+        let receiver = Rc::new(Cell::new(Some(receiver)));
+
+        for _ in 0..3 {
+            let flights = Rc::clone(&flights);
+            let calls = Rc::clone(&calls);
+            let results = Rc::clone(&results);
+            let receiver = Rc::clone(&receiver);
+            pool.spawner()
+                .spawn_local(async move {
+                    let value = flights
+                        .run("jwks", || async move {
+                            calls.set(calls.get() + 1);
+                            receiver.take().unwrap().await.unwrap()
+                        })
+                        .await
+                        .unwrap();
+                    results.set(results.get() + value);
+                })
+                .unwrap();
+        }
+
+        pool.run_until_stalled();
+        assert_eq!(flights.in_flight(), 1);
+
+        sender.send(7).unwrap();
+        pool.run_until_stalled();
+
+        assert_eq!(calls.get(), 1);
+        assert_eq!(results.get(), 21);
+        assert_eq!(flights.in_flight(), 0);
+    }
+
+    #[test]
+    fn flights_are_shared_by_the_root_context() {
+        let reactor = Rc::new(RootReactor::new(RootCid::from(1)));
+        let first = SingleFlight::<&'static str, u32>::new(Rc::clone(&reactor));
+        let second = SingleFlight::<&'static str, u32>::new(Rc::clone(&reactor));
+        let other = SingleFlight::<&'static str, String>::new(Rc::clone(&reactor));
+        let mut pool = LocalPool::new();
+        let (sender, receiver) = oneshot::channel::<u32>();
+        let calls = Rc::new(Cell::new(0));
+
+        pool.spawner()
+            .spawn_local(async move {
+                let _ = first
+                    .run("jwks", || async move { receiver.await.unwrap() })
+                    .await;
+            })
+            .unwrap();
+        pool.run_until_stalled();
+
+        {
+            let calls = Rc::clone(&calls);
+            pool.spawner()
+                .spawn_local(async move {
+                    let value = second.run("jwks", || async { 0 }).await;
+                    assert_eq!(value, Ok(7));
+                    calls.set(calls.get() + 1);
+                })
+                .unwrap();
+        }
+        pool.run_until_stalled();
+        assert_eq!(other.in_flight(), 0);
+
+        sender.send(7).unwrap();
+        pool.run_until_stalled();
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn different_keys_are_not_coalesced() {
+        let mut pool = LocalPool::new();
+        let flights = single_flight();
+        let calls = Rc::new(Cell::new(0));
+
+        for key in ["a", "b"] {
+            let flights = Rc::clone(&flights);
+            let calls = Rc::clone(&calls);
+            pool.spawner()
+                .spawn_local(async move {
+                    let _ = flights
+                        .run(key, || async move {
+                            calls.set(calls.get() + 1);
+                            1
+                        })
+                        .await;
+                })
+                .unwrap();
+        }
+
+        pool.run_until_stalled();
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[test]
+    fn followers_fail_when_leader_is_dropped() {
+        let mut pool = LocalPool::new();
+        let flights = single_flight();
+        let (_sender, receiver) = oneshot::channel::<u32>();
+        let outcome = Rc::new(Cell::new(None));
+
+        let leader = {
+            let flights = Rc::clone(&flights);
+            pool.spawner()
+                .spawn_local_with_handle(async move {
+                    let _ = flights
+                        .run("key", || async move { receiver.await.unwrap() })
+                        .await;
+                })
+                .unwrap()
+        };
+
+        {
+            let flights = Rc::clone(&flights);
+            let outcome = Rc::clone(&outcome);
+            pool.spawner()
+                .spawn_local(async move {
+                    outcome.set(Some(flights.run("key", || async { 0 }).await));
+                })
+                .unwrap();
+        }
+
+        pool.run_until_stalled();
+        drop(leader);
+        pool.run_until_stalled();
+
+        assert_eq!(outcome.take(), Some(Err(SingleFlightError::Abandoned)));
+        assert_eq!(flights.in_flight(), 0);
+    }
+
+    #[test]
+    fn followers_keep_one_waker() {
+        use std::{
+            cell::RefCell,
+            future::Future,
+            pin::Pin,
+            sync::{
+                atomic::{AtomicUsize, Ordering},
+                Arc,
+            },
+            task::{Context, Poll},
+        };
+
+        use futures::task::{waker, ArcWake};
+
+        struct CountingWaker(AtomicUsize);
+
+        impl ArcWake for CountingWaker {
+            fn wake_by_ref(arc_self: &Arc<Self>) {
+                arc_self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let flight = Rc::new(RefCell::new(State::<u32>::InFlight(Vec::new())));
+        let mut follower = Follower {
+            reactor: Rc::new(RootReactor::new(RootCid::from(1))),
+            flight: Rc::clone(&flight),
+            slot: None,
+        };
+        let first = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let second = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let mut poll = |wake: &Arc<CountingWaker>| {
+            let waker = waker(Arc::clone(wake));
+            Pin::new(&mut follower).poll(&mut Context::from_waker(&waker))
+        };
+        let wakers = |flight: &RefCell<State<u32>>| match &*flight.borrow() {
+            State::InFlight(wakers) => wakers.len(),
+            _ => unreachable!(),
+        };
+
+        assert_eq!(poll(&first), Poll::Pending);
+        assert_eq!(poll(&first), Poll::Pending);
+        assert_eq!(wakers(&flight), 1);
+
+        // a follower moved to another task is woken through the new waker only
+        assert_eq!(poll(&second), Poll::Pending);
+        assert_eq!(wakers(&flight), 1);
+
+        if let State::InFlight(wakers) = flight.replace(State::Completed(7)) {
+            wakers.into_iter().for_each(|waker| waker.wake());
+        }
+        assert_eq!(first.0.load(Ordering::SeqCst), 0);
+        assert_eq!(second.0.load(Ordering::SeqCst), 1);
+        assert_eq!(poll(&second), Poll::Ready(Ok(7)));
+    }
+}
@@ -0,0 +1,354 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use std::{
+    cell::RefCell,
+    time::{Duration, SystemTime},
+};
+
+const DEFAULT_FAILURE_THRESHOLD: u32 = 3;
+const DEFAULT_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// A single upstream a callout can be dispatched to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Endpoint {
+    name: String,
+    host: String,
+    port: Option<u16>,
+    authority: String,
+    weight: u32,
+    timeout: Option<Duration>,
+}
+
+impl Endpoint {
+    /// Creates an endpoint for the upstream service `name` answering to `host`.
+    pub fn new(name: impl Into<String>, host: impl Into<String>) -> Self {
+        let host = host.into();
+        Self {
+            name: name.into(),
+            authority: host.clone(),
+            host,
+            port: None,
+            weight: 1,
+            timeout: None,
+        }
+    }
+
+    pub fn with_port(mut self, port: u16) -> Self {
+        self.authority = format!("{}:{}", self.host, port);
+        self.port = Some(port);
+        self
+    }
+
+    /// Relative share of the calls this endpoint receives with [`Selection::RoundRobin`].
+    pub fn with_weight(mut self, weight: u32) -> Self {
+        self.weight = weight.max(1);
+        self
+    }
+
+    /// Overrides the request timeout for calls dispatched to this endpoint.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// The upstream service name the call is dispatched to.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    pub fn port(&self) -> Option<u16> {
+        self.port
+    }
+
+    /// The `:authority` header value for requests sent to this endpoint.
+    pub fn authority(&self) -> &str {
+        &self.authority
+    }
+
+    pub fn weight(&self) -> u32 {
+        self.weight
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+}
+
+/// How [`CalloutTarget::select`] distributes calls among the healthy endpoints.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Selection {
+    /// Always the first healthy endpoint in declaration order.
+    #[default]
+    Failover,
+    /// Smooth weighted round-robin.
+    RoundRobin,
+}
+
+#[derive(Default, Clone, Debug)]
+struct Health {
+    consecutive_failures: u32,
+    unhealthy_since: Option<SystemTime>,
+    current_weight: i64,
+}
+
+/// A callout destination composed of a primary [`Endpoint`] and optional alternates.
+///
+/// Endpoints are marked unhealthy after `failure_threshold` consecutive failures and
+/// are skipped by [`CalloutTarget::select`] until `cooldown` elapses.
+#[derive(Debug)]
+pub struct CalloutTarget {
+    endpoints: Vec<Endpoint>,
+    health: RefCell<Vec<Health>>,
+    failure_threshold: u32,
+    cooldown: Duration,
+    selection: Selection,
+}
+
+impl CalloutTarget {
+    pub fn new(primary: Endpoint) -> Self {
+        Self {
+            endpoints: vec![primary],
+            health: RefCell::new(vec![Health::default()]),
+            failure_threshold: DEFAULT_FAILURE_THRESHOLD,
+            cooldown: DEFAULT_COOLDOWN,
+            selection: Selection::default(),
+        }
+    }
+
+    pub fn with_alternate(mut self, alternate: Endpoint) -> Self {
+        self.endpoints.push(alternate);
+        self.health.get_mut().push(Health::default());
+        self
+    }
+
+    pub fn with_alternates(self, alternates: impl IntoIterator<Item = Endpoint>) -> Self {
+        alternates
+            .into_iter()
+            .fold(self, |target, alternate| target.with_alternate(alternate))
+    }
+
+    pub fn failure_threshold(mut self, failure_threshold: u32) -> Self {
+        self.failure_threshold = failure_threshold.max(1);
+        self
+    }
+
+    pub fn cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    pub fn selection(mut self, selection: Selection) -> Self {
+        self.selection = selection;
+        self
+    }
+
+    pub fn primary(&self) -> &Endpoint {
+        &self.endpoints[0]
+    }
+
+    pub fn endpoints(&self) -> &[Endpoint] {
+        &self.endpoints
+    }
+
+    /// Returns a healthy endpoint according to the configured [`Selection`]. When every
+    /// endpoint is unhealthy, the one that has been failing for the longest time is returned.
+    pub fn select(&self, now: SystemTime) -> &Endpoint {
+        let mut health = self.health.borrow_mut();
+
+        let available = (0..self.endpoints.len())
+            .filter(|&index| self.available(&health[index], now))
+            .collect::<Vec<_>>();
+
+        let index = match self.selection {
+            Selection::Failover => available.first().copied(),
+            Selection::RoundRobin => self.next_weighted(&mut health, &available),
+        };
+
+        let index = index
+            .or_else(|| {
+                health
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, h)| h.unhealthy_since)
+                    .map(|(index, _)| index)
+            })
+            .unwrap_or_default();
+
+        &self.endpoints[index]
+    }
+
+    pub fn is_healthy(&self, endpoint: &Endpoint, now: SystemTime) -> bool {
+        self.index_of(endpoint)
+            .map(|index| self.available(&self.health.borrow()[index], now))
+            .unwrap_or_default()
+    }
+
+    pub fn report_success(&self, endpoint: &Endpoint) {
+        if let Some(index) = self.index_of(endpoint) {
+            let health = &mut self.health.borrow_mut()[index];
+            health.consecutive_failures = 0;
+            health.unhealthy_since = None;
+        }
+    }
+
+    pub fn report_failure(&self, endpoint: &Endpoint, now: SystemTime) {
+        if let Some(index) = self.index_of(endpoint) {
+            let health = &mut self.health.borrow_mut()[index];
+            health.consecutive_failures = health.consecutive_failures.saturating_add(1);
+            if health.consecutive_failures >= self.failure_threshold {
+                health.unhealthy_since = Some(now);
+            }
+        }
+    }
+
+    fn next_weighted(&self, health: &mut [Health], available: &[usize]) -> Option<usize> {
+        let total: i64 = available
+            .iter()
+            .map(|&index| self.endpoints[index].weight as i64)
+            .sum();
+
+        available.iter().for_each(|&index| {
+            health[index].current_weight += self.endpoints[index].weight as i64;
+        });
+
+        let selected = available
+            .iter()
+            .copied()
+            .max_by_key(|&index| (health[index].current_weight, -(index as i64)))?;
+
+        health[selected].current_weight -= total;
+        Some(selected)
+    }
+
+    fn index_of(&self, endpoint: &Endpoint) -> Option<usize> {
+        self.endpoints.iter().position(|e| e == endpoint)
+    }
+
+    fn available(&self, health: &Health, now: SystemTime) -> bool {
+        match health.unhealthy_since {
+            None => true,
+            Some(since) => now
+                .duration_since(since)
+                .map(|elapsed| elapsed >= self.cooldown)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use super::{CalloutTarget, Endpoint, Selection};
+
+    fn at(seconds: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    fn target() -> CalloutTarget {
+        CalloutTarget::new(Endpoint::new("primary", "primary.example.com").with_port(8443))
+            .with_alternate(Endpoint::new("secondary", "secondary.example.com"))
+            .failure_threshold(2)
+            .cooldown(Duration::from_secs(10))
+    }
+
+    #[test]
+    fn authority_includes_port() {
+        let endpoint = Endpoint::new("service", "host.example.com");
+        assert_eq!(endpoint.authority(), "host.example.com");
+        assert_eq!(endpoint.with_port(80).authority(), "host.example.com:80");
+    }
+
+    #[test]
+    fn selects_primary_when_healthy() {
+        let target = target();
+        assert_eq!(target.select(at(0)).name(), "primary");
+    }
+
+    #[test]
+    fn fails_over_after_threshold() {
+        let target = target();
+        let primary = target.primary().clone();
+
+        target.report_failure(&primary, at(0));
+        assert_eq!(target.select(at(0)).name(), "primary");
+
+        target.report_failure(&primary, at(1));
+        assert_eq!(target.select(at(1)).name(), "secondary");
+        assert!(!target.is_healthy(&primary, at(1)));
+    }
+
+    #[test]
+    fn recovers_after_cooldown() {
+        let target = target();
+        let primary = target.primary().clone();
+
+        target.report_failure(&primary, at(0));
+        target.report_failure(&primary, at(0));
+
+        assert_eq!(target.select(at(9)).name(), "secondary");
+        assert_eq!(target.select(at(10)).name(), "primary");
+    }
+
+    #[test]
+    fn success_resets_failures() {
+        let target = target();
+        let primary = target.primary().clone();
+
+        target.report_failure(&primary, at(0));
+        target.report_success(&primary);
+        target.report_failure(&primary, at(1));
+
+        assert_eq!(target.select(at(1)).name(), "primary");
+    }
+
+    #[test]
+    fn all_unhealthy_selects_oldest_failure() {
+        let target = target();
+        let primary = target.primary().clone();
+        let secondary = target.endpoints()[1].clone();
+
+        target.report_failure(&secondary, at(0));
+        target.report_failure(&secondary, at(0));
+        target.report_failure(&primary, at(5));
+        target.report_failure(&primary, at(5));
+
+        assert_eq!(target.select(at(6)).name(), "secondary");
+    }
+
+    #[test]
+    fn round_robin_honors_weights() {
+        let target = CalloutTarget::new(Endpoint::new("a", "a.example.com").with_weight(2))
+            .with_alternate(Endpoint::new("b", "b.example.com"))
+            .selection(Selection::RoundRobin);
+
+        let names = (0..6)
+            .map(|_| target.select(at(0)).name().to_string())
+            .collect::<Vec<_>>();
+
+        assert_eq!(names, ["a", "b", "a", "a", "b", "a"]);
+    }
+
+    #[test]
+    fn round_robin_skips_unhealthy() {
+        let target = CalloutTarget::new(Endpoint::new("a", "a.example.com"))
+            .with_alternate(Endpoint::new("b", "b.example.com"))
+            .selection(Selection::RoundRobin)
+            .failure_threshold(1);
+
+        target.report_failure(&target.endpoints()[1].clone(), at(0));
+
+        assert_eq!(target.select(at(1)).name(), "a");
+        assert_eq!(target.select(at(1)).name(), "a");
+    }
+
+    #[test]
+    fn endpoint_timeout() {
+        let endpoint = Endpoint::new("a", "a.example.com").with_timeout(Duration::from_secs(2));
+        assert_eq!(endpoint.timeout(), Some(Duration::from_secs(2)));
+        assert_eq!(Endpoint::new("b", "b.example.com").timeout(), None);
+    }
+}
@@ -3,6 +3,7 @@ use std::cell::RefCell;
 use std::rc::Rc;
 
 use crate::{
+    bootstrap::POLICY_DURATION_HEADER,
     client::HttpCallResponse,
     event::{Event, EventData, EventKind, Exchange, RequestHeaders, ResponseHeaders},
     middleware::{EventHandlerDispatch, EventHandlerStack},
@@ -72,12 +73,31 @@ impl AsyncHttpContext {
 
         self.executor.borrow_mut().run_until_stalled();
 
+        if event == EventKind::ResponseHeaders && self.config_reactor.duration_header() {
+            self.add_duration_header();
+        }
+
         if self.reactor.paused() {
             Action::Pause
         } else {
             Action::Continue
         }
     }
+
+    fn add_duration_header(&self) {
+        let duration = match self.reactor.filter_duration(self.host.get_current_time()) {
+            Some(duration) => duration,
+            None => return,
+        };
+
+        let millis = duration.as_millis();
+        let value = match self.host.get_property(vec!["plugin_name"]) {
+            Some(name) => format!("{}={}", String::from_utf8_lossy(&name), millis),
+            None => millis.to_string(),
+        };
+        self.host
+            .add_http_response_header(POLICY_DURATION_HEADER, &value);
+    }
 }
 
 impl Context for AsyncHttpContext {
@@ -106,11 +126,14 @@ impl Context for AsyncHttpContext {
 }
 
 impl HttpContext for AsyncHttpContext {
-    fn on_http_request_headers(&mut self, _num_headers: usize, _end_of_stream: bool) -> Action {
+    fn on_http_request_headers(&mut self, _num_headers: usize, end_of_stream: bool) -> Action {
+        self.reactor.set_end_of_stream(end_of_stream);
         self.notify(EventKind::RequestHeaders)
     }
 
-    fn on_http_request_body(&mut self, _body_size: usize, _end_of_stream: bool) -> Action {
+    fn on_http_request_body(&mut self, body_size: usize, end_of_stream: bool) -> Action {
+        self.reactor.set_body_size(body_size);
+        self.reactor.set_end_of_stream(end_of_stream);
         self.notify(EventKind::RequestBody)
     }
 
@@ -118,11 +141,14 @@ impl HttpContext for AsyncHttpContext {
         self.notify(EventKind::RequestTrailers)
     }
 
-    fn on_http_response_headers(&mut self, _num_headers: usize, _end_of_stream: bool) -> Action {
+    fn on_http_response_headers(&mut self, _num_headers: usize, end_of_stream: bool) -> Action {
+        self.reactor.set_end_of_stream(end_of_stream);
         self.notify(EventKind::ResponseHeaders)
     }
 
-    fn on_http_response_body(&mut self, _body_size: usize, _end_of_stream: bool) -> Action {
+    fn on_http_response_body(&mut self, body_size: usize, end_of_stream: bool) -> Action {
+        self.reactor.set_body_size(body_size);
+        self.reactor.set_end_of_stream(end_of_stream);
         self.notify(EventKind::ResponseBody)
     }
 
@@ -79,11 +79,24 @@ impl<C, T> Context for AsyncRootContext<C, T> {
     }
 
     fn on_done(&mut self) -> bool {
+        self.reactor.set_active_cid(self.context_id.into());
+        for hook in self.reactor.take_drain_hooks() {
+            hook();
+        }
         self.reactor.set_done();
         true
     }
 }
 
+impl<C, T> Drop for AsyncRootContext<C, T> {
+    fn drop(&mut self) {
+        self.reactor.set_active_cid(self.context_id.into());
+        for hook in self.reactor.take_shutdown_hooks() {
+            hook();
+        }
+    }
+}
+
 impl<C, T> RootContext for AsyncRootContext<C, T>
 where
     C: Handler<Launcher, T> + 'static,
@@ -0,0 +1,159 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Parses and writes the `Cookie` header of the requests and the `Set-Cookie` headers of the
+//! responses, through the [`CookiesAccessor`](crate::event::CookiesAccessor) of the events.
+
+use std::fmt;
+
+/// A cookie of the `Cookie` header of a request, or of a `Set-Cookie` header of a response with
+/// its attributes. Names and values are kept as they came, without decoding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    attributes: Vec<(String, String)>,
+}
+
+impl Cookie {
+    pub fn new(name: &str, value: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            value: value.to_string(),
+            attributes: Vec::new(),
+        }
+    }
+
+    /// Adds an attribute, written only in `Set-Cookie` headers. Attributes without a value,
+    /// like `HttpOnly`, have an empty one.
+    pub fn with_attribute(mut self, name: &str, value: &str) -> Self {
+        self.attributes.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// The value of the attribute, its name compared ignoring case, empty for the ones without
+    /// a value.
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn attributes(&self) -> &[(String, String)] {
+        &self.attributes
+    }
+
+    /// The value of a `Set-Cookie` header for this cookie, attributes included.
+    pub fn to_set_cookie(&self) -> String {
+        self.attributes
+            .iter()
+            .fold(self.to_string(), |mut header, (name, value)| {
+                header.push_str("; ");
+                header.push_str(name);
+                if !value.is_empty() {
+                    header.push('=');
+                    header.push_str(value);
+                }
+                header
+            })
+    }
+}
+
+/// The `name=value` pair of the cookie, as written in `Cookie` headers.
+impl fmt::Display for Cookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)
+    }
+}
+
+fn pair(pair: &str) -> Option<(&str, &str)> {
+    let (name, value) = pair.split_once('=')?;
+    let name = name.trim();
+    (!name.is_empty()).then(|| (name, value.trim()))
+}
+
+/// The names and values of the cookies of a `Cookie` header, in order, as they came. Pairs
+/// without a name are skipped.
+pub fn cookie_pairs(header: &str) -> impl Iterator<Item = (&str, &str)> {
+    header.split(';').filter_map(pair)
+}
+
+/// The cookies of a `Cookie` header, in order. Pairs without a name are skipped.
+pub(crate) fn parse_cookie(header: &str) -> Vec<Cookie> {
+    cookie_pairs(header)
+        .map(|(name, value)| Cookie::new(name, value))
+        .collect()
+}
+
+/// The cookie of a `Set-Cookie` header, none without a name.
+pub(crate) fn parse_set_cookie(header: &str) -> Option<Cookie> {
+    let mut parts = header.split(';');
+    let (name, value) = parts.next().and_then(pair)?;
+
+    let cookie = parts
+        .map(str::trim)
+        .filter(|attribute| !attribute.is_empty())
+        .fold(
+            Cookie::new(name, value),
+            |cookie, attribute| match attribute.split_once('=') {
+                Some((name, value)) => cookie.with_attribute(name.trim(), value.trim()),
+                None => cookie.with_attribute(attribute, ""),
+            },
+        );
+    Some(cookie)
+}
+
+/// The value of the `Cookie` header of the cookies, none without cookies.
+pub(crate) fn cookie_header(cookies: &[Cookie]) -> Option<String> {
+    (!cookies.is_empty()).then(|| {
+        cookies
+            .iter()
+            .map(Cookie::to_string)
+            .collect::<Vec<_>>()
+            .join("; ")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cookie_header, parse_cookie, parse_set_cookie, Cookie};
+
+    #[test]
+    fn cookie_header_is_parsed_in_order() {
+        let cookies = parse_cookie("session=abc; theme=dark;;=orphan; flag; token=a=b");
+
+        assert_eq!(
+            cookies,
+            vec![
+                Cookie::new("session", "abc"),
+                Cookie::new("theme", "dark"),
+                Cookie::new("token", "a=b"),
+            ]
+        );
+        assert_eq!(
+            cookie_header(&cookies).as_deref(),
+            Some("session=abc; theme=dark; token=a=b")
+        );
+        assert_eq!(cookie_header(&[]), None);
+    }
+
+    #[test]
+    fn set_cookie_keeps_its_attributes() {
+        let header = "session=abc; Path=/; Max-Age=3600; HttpOnly; Secure";
+        let cookie = parse_set_cookie(header).unwrap();
+
+        assert_eq!((cookie.name(), cookie.value()), ("session", "abc"));
+        assert_eq!(cookie.attribute("path"), Some("/"));
+        assert_eq!(cookie.attribute("HttpOnly"), Some(""));
+        assert_eq!(cookie.attribute("Domain"), None);
+        assert_eq!(cookie.to_set_cookie(), header);
+        assert_eq!(parse_set_cookie("; Path=/"), None);
+    }
+}
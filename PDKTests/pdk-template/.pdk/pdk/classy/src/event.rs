@@ -15,12 +15,17 @@ mod private {
 
 use private::Sealed;
 
+pub use crate::cookie::Cookie;
 use crate::http_constants::{
-    DEFAULT_PATH, HEADER_AUTHORITY, HEADER_METHOD, HEADER_PATH, HEADER_SCHEME, HEADER_STATUS,
+    DEFAULT_PATH, HEADER_AUTHORITY, HEADER_COOKIE, HEADER_METHOD, HEADER_PATH, HEADER_SCHEME,
+    HEADER_SET_COOKIE, HEADER_STATUS,
 };
+
 use crate::{
+    cookie,
     extract::FromContext,
     host::Host,
+    query::QueryParams,
     reactor::http::{ExchangePhase, HttpReactor, WakerId},
     types::HttpCid,
 };
@@ -200,6 +205,22 @@ pub trait HeadersAccessor {
 
     fn headers(&self) -> Vec<(String, String)>;
 
+    /// Returns the value of each header in `names`, in the same order. All the headers are
+    /// fetched from the host at once, which is cheaper than a [`header`](Self::header) call
+    /// per name when reading several headers.
+    fn get_headers(&self, names: &[&str]) -> Vec<Option<String>> {
+        let headers = self.headers();
+        names
+            .iter()
+            .map(|name| {
+                headers
+                    .iter()
+                    .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                    .map(|(_, value)| value.clone())
+            })
+            .collect()
+    }
+
     fn add_header(&self, name: &str, value: &str);
 
     fn set_header(&self, name: &str, value: &str);
@@ -264,6 +285,295 @@ impl<'a> HeadersAccessor for EventData<'a, RequestHeaders> {
     }
 }
 
+/// The query parameters of the request, read and written through its `:path` header.
+///
+/// Names and values are percent-decoded when read and percent-encoded when written. The
+/// parameters left alone keep their encoding and order, so removing an API key from the query
+/// string forwards the rest of it upstream as it came.
+pub trait QueryParamsAccessor: HeadersAccessor {
+    /// The query parameters of the request.
+    fn query_params(&self) -> QueryParams {
+        QueryParams::parse(&self.header(HEADER_PATH).unwrap_or_default())
+    }
+
+    /// Replaces the URI of the request with the one of `params`.
+    fn set_query_params(&self, params: &QueryParams) {
+        self.set_header(HEADER_PATH, &params.to_uri());
+    }
+
+    /// The value of the first query parameter of the name.
+    fn query_param(&self, name: &str) -> Option<String> {
+        self.query_params().get(name)
+    }
+
+    /// Appends the query parameter, after the existing ones of the same name.
+    fn add_query_param(&self, name: &str, value: &str) {
+        let mut params = path_params(self);
+        params.append(name, value);
+        self.set_query_params(&params);
+    }
+
+    /// Sets the query parameter. The first one of the name keeps its place, the others are
+    /// removed.
+    fn set_query_param(&self, name: &str, value: &str) {
+        let mut params = path_params(self);
+        params.set(name, value);
+        self.set_query_params(&params);
+    }
+
+    /// Removes all the query parameters of the name, and the `?` when none is left.
+    fn remove_query_param(&self, name: &str) {
+        let mut params = self.query_params();
+        if params.remove(name) {
+            self.set_query_params(&params);
+        }
+    }
+}
+
+/// The query parameters of the request, of the default path when it has none.
+fn path_params<A: QueryParamsAccessor + ?Sized>(accessor: &A) -> QueryParams {
+    let path = accessor
+        .header(HEADER_PATH)
+        .unwrap_or_else(|| DEFAULT_PATH.to_string());
+    QueryParams::parse(&path)
+}
+
+impl<'a> QueryParamsAccessor for EventData<'a, RequestHeaders> {}
+
+/// Like its headers, the query of the request only reaches the upstream when the headers were
+/// paused waiting for the body.
+impl<'a> QueryParamsAccessor for EventData<'a, RequestBody> {}
+
+/// The cookies of the request, in its `Cookie` headers, or of the response, in its
+/// `Set-Cookie` headers. Cookies are matched by their exact name.
+pub trait CookiesAccessor: HeadersAccessor {
+    /// The cookies, in order.
+    fn cookies(&self) -> Vec<Cookie>;
+
+    /// Replaces all the cookies. Requests get a single `Cookie` header, responses a
+    /// `Set-Cookie` header per cookie, after the ones that could not be parsed.
+    fn set_cookies(&self, cookies: &[Cookie]);
+
+    /// The first cookie of the name.
+    fn cookie(&self, name: &str) -> Option<Cookie> {
+        self.cookies()
+            .into_iter()
+            .find(|cookie| cookie.name() == name)
+    }
+
+    /// Appends the cookie, after the existing ones of the same name.
+    fn add_cookie(&self, cookie: Cookie) {
+        let mut cookies = self.cookies();
+        cookies.push(cookie);
+        self.set_cookies(&cookies);
+    }
+
+    /// Replaces all the cookies of the same name with the cookie.
+    fn set_cookie(&self, cookie: Cookie) {
+        let mut cookies = self.cookies();
+        cookies.retain(|existing| existing.name() != cookie.name());
+        cookies.push(cookie);
+        self.set_cookies(&cookies);
+    }
+
+    /// Removes all the cookies of the name.
+    fn remove_cookie(&self, name: &str) {
+        let mut cookies = self.cookies();
+        let count = cookies.len();
+        cookies.retain(|cookie| cookie.name() != name);
+        if cookies.len() != count {
+            self.set_cookies(&cookies);
+        }
+    }
+}
+
+fn request_cookies(headers: &dyn HeadersAccessor) -> Vec<Cookie> {
+    headers
+        .headers()
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case(HEADER_COOKIE))
+        .flat_map(|(_, value)| cookie::parse_cookie(value))
+        .collect()
+}
+
+fn set_request_cookies(headers: &dyn HeadersAccessor, cookies: &[Cookie]) {
+    match cookie::cookie_header(cookies) {
+        Some(value) => headers.set_header(HEADER_COOKIE, &value),
+        None => headers.remove_header(HEADER_COOKIE),
+    }
+}
+
+fn response_cookies(headers: &dyn HeadersAccessor) -> Vec<Cookie> {
+    headers
+        .headers()
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case(HEADER_SET_COOKIE))
+        .filter_map(|(_, value)| cookie::parse_set_cookie(value))
+        .collect()
+}
+
+/// The `Set-Cookie` headers that are not cookies are kept as they are, as they can not be
+/// written back from the cookies.
+fn set_response_cookies(headers: &dyn HeadersAccessor, cookies: &[Cookie]) {
+    let unparsed: Vec<String> = headers
+        .headers()
+        .into_iter()
+        .filter(|(name, value)| {
+            name.eq_ignore_ascii_case(HEADER_SET_COOKIE)
+                && cookie::parse_set_cookie(value).is_none()
+        })
+        .map(|(_, value)| value)
+        .collect();
+
+    headers.remove_header(HEADER_SET_COOKIE);
+    for value in unparsed {
+        headers.add_header(HEADER_SET_COOKIE, &value);
+    }
+    for cookie in cookies {
+        headers.add_header(HEADER_SET_COOKIE, &cookie.to_set_cookie());
+    }
+}
+
+impl<'a> CookiesAccessor for EventData<'a, RequestHeaders> {
+    fn cookies(&self) -> Vec<Cookie> {
+        request_cookies(self)
+    }
+
+    fn set_cookies(&self, cookies: &[Cookie]) {
+        set_request_cookies(self, cookies);
+    }
+}
+
+/// Like its headers, the cookies of the request only reach the upstream when the headers were
+/// paused waiting for the body.
+impl<'a> CookiesAccessor for EventData<'a, RequestBody> {
+    fn cookies(&self) -> Vec<Cookie> {
+        request_cookies(self)
+    }
+
+    fn set_cookies(&self, cookies: &[Cookie]) {
+        set_request_cookies(self, cookies);
+    }
+}
+
+impl<'a> CookiesAccessor for EventData<'a, ResponseHeaders> {
+    fn cookies(&self) -> Vec<Cookie> {
+        response_cookies(self)
+    }
+
+    fn set_cookies(&self, cookies: &[Cookie]) {
+        set_response_cookies(self, cookies);
+    }
+}
+
+impl<'a> CookiesAccessor for EventData<'a, ResponseBody> {
+    fn cookies(&self) -> Vec<Cookie> {
+        response_cookies(self)
+    }
+
+    fn set_cookies(&self, cookies: &[Cookie]) {
+        set_response_cookies(self, cookies);
+    }
+}
+
+/// The body of the current event, as buffered by the proxy so far.
+pub trait BodyAccessor {
+    fn body(&self) -> Vec<u8>;
+
+    /// Replaces the whole body buffered so far. The `content-length` header is left to the
+    /// caller, and only reaches the peer when the headers were held waiting for the body.
+    fn set_body(&self, body: &[u8]);
+}
+
+impl<'a> BodyAccessor for EventData<'a, RequestBody> {
+    fn body(&self) -> Vec<u8> {
+        let size = self.exchange.reactor.body_size();
+        self.exchange
+            .host
+            .get_http_request_body(0, size)
+            .unwrap_or_default()
+    }
+
+    fn set_body(&self, body: &[u8]) {
+        let size = self.exchange.reactor.body_size();
+        self.exchange.host.set_http_request_body(0, size, body);
+    }
+}
+
+impl<'a> BodyAccessor for EventData<'a, ResponseBody> {
+    fn body(&self) -> Vec<u8> {
+        let size = self.exchange.reactor.body_size();
+        self.exchange
+            .host
+            .get_http_response_body(0, size)
+            .unwrap_or_default()
+    }
+
+    fn set_body(&self, body: &[u8]) {
+        let size = self.exchange.reactor.body_size();
+        self.exchange.host.set_http_response_body(0, size, body);
+    }
+}
+
+/// The headers of the request are still readable along its body. Changes only reach the
+/// upstream when the headers were paused waiting for the body.
+impl<'a> HeadersAccessor for EventData<'a, RequestBody> {
+    fn header(&self, name: &str) -> Option<String> {
+        self.exchange.host.get_http_request_header(name)
+    }
+
+    fn headers(&self) -> Vec<(String, String)> {
+        self.exchange.host.get_http_request_headers()
+    }
+
+    fn add_header(&self, name: &str, value: &str) {
+        self.exchange.host.add_http_request_header(name, value);
+    }
+
+    fn set_header(&self, name: &str, value: &str) {
+        self.exchange
+            .host
+            .set_http_request_header(name, Some(value));
+    }
+
+    fn set_headers(&self, headers: Vec<(&str, &str)>) {
+        self.exchange.host.set_http_request_headers(headers);
+    }
+
+    fn remove_header(&self, name: &str) {
+        self.exchange.host.set_http_request_header(name, None);
+    }
+}
+
+/// The headers of the response are still readable along its body, see the request ones.
+impl<'a> HeadersAccessor for EventData<'a, ResponseBody> {
+    fn header(&self, name: &str) -> Option<String> {
+        self.exchange.host.get_http_response_header(name)
+    }
+
+    fn headers(&self) -> Vec<(String, String)> {
+        self.exchange.host.get_http_response_headers()
+    }
+
+    fn add_header(&self, name: &str, value: &str) {
+        self.exchange.host.add_http_response_header(name, value);
+    }
+
+    fn set_header(&self, name: &str, value: &str) {
+        self.exchange
+            .host
+            .set_http_response_header(name, Some(value));
+    }
+
+    fn set_headers(&self, headers: Vec<(&str, &str)>) {
+        self.exchange.host.set_http_response_headers(headers);
+    }
+
+    fn remove_header(&self, name: &str) {
+        self.exchange.host.set_http_response_header(name, None);
+    }
+}
+
 impl<'a> EventData<'a, RequestTrailers> {
     pub fn header(&self, name: &str) -> Option<String> {
         self.exchange.host.get_http_request_trailer(name)
@@ -388,6 +698,23 @@ where
     }
 }
 
+impl Exchange<RequestHeaders> {
+    /// Waits for the whole body of the request, holding its headers meanwhile so they can still
+    /// be changed along the body. The proxy buffers the body, within its buffer limits. Requests
+    /// without a body resolve right away, with an empty one.
+    pub async fn wait_for_request_body(self) -> Exchange<RequestBody> {
+        BufferedBodyFuture::<RequestHeaders, RequestBody>::new(self.reactor, self.host).await
+    }
+}
+
+impl Exchange<ResponseHeaders> {
+    /// Waits for the whole body of the response, holding its headers meanwhile, like
+    /// [`wait_for_request_body`](Exchange::wait_for_request_body) for the request.
+    pub async fn wait_for_response_body(self) -> Exchange<ResponseBody> {
+        BufferedBodyFuture::<ResponseHeaders, ResponseBody>::new(self.reactor, self.host).await
+    }
+}
+
 pub struct ExchangeFuture<S: Event> {
     _context_id: HttpCid,
     reactor: Rc<HttpReactor>,
@@ -456,6 +783,64 @@ impl<S: Event> Future for ExchangeFuture<S> {
     }
 }
 
+/// Resolves once the body of the `B` event ended, pausing the request or the response until
+/// then. `H` is the event of their headers.
+struct BufferedBodyFuture<H: Event, B: Event> {
+    reactor: Rc<HttpReactor>,
+    host: Rc<dyn Host>,
+    waker_id: Option<WakerId>,
+    _phantom: PhantomData<(H, B)>,
+}
+
+impl<H: Event, B: Event> BufferedBodyFuture<H, B> {
+    fn new(reactor: Rc<HttpReactor>, host: Rc<dyn Host>) -> Self {
+        Self {
+            reactor,
+            host,
+            waker_id: None,
+            _phantom: PhantomData::default(),
+        }
+    }
+}
+
+impl<H: Event, B: Event> Unpin for BufferedBodyFuture<H, B> {}
+
+impl<H: Event, B: Event> Future for BufferedBodyFuture<H, B> {
+    type Output = Exchange<B>;
+
+    fn poll(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Self::Output> {
+        let reactor = Rc::clone(&self.reactor);
+
+        // Headers ending the stream have no body to wait for, the empty one is current.
+        if reactor.current_event() == H::kind() && reactor.end_of_stream() {
+            reactor.set_body_size(0);
+            reactor.notify(B::kind());
+        }
+
+        let ended = reactor.current_event() > B::kind()
+            || (reactor.current_event() == B::kind() && reactor.end_of_stream());
+        if ended {
+            if let Some(id) = self.waker_id.take() {
+                reactor.remove_waker(B::kind(), id);
+            }
+            // The body and the held headers go on once the current event is handled.
+            reactor.set_paused(false);
+            return Poll::Ready(Exchange::new(reactor, Rc::clone(&self.host)));
+        }
+
+        // The proxy buffers the body while the request or the response is paused.
+        reactor.set_paused(true);
+        if let Some(id) = self.waker_id.take() {
+            reactor.remove_waker(B::kind(), id);
+        }
+        self.waker_id = Some(reactor.insert_waker(B::kind(), cx.waker().clone()));
+        Poll::Pending
+    }
+}
+
 pub struct BodyChunk {
     size: usize,
 }
@@ -525,3 +910,61 @@ impl<'a, S: Body> Stream for BodyBytesStream<'a, S> {
         todo!()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::HeadersAccessor;
+
+    struct Headers {
+        headers: Vec<(String, String)>,
+        fetches: Cell<usize>,
+    }
+
+    impl HeadersAccessor for Headers {
+        fn header(&self, _name: &str) -> Option<String> {
+            unreachable!("headers are read at once")
+        }
+
+        fn headers(&self) -> Vec<(String, String)> {
+            self.fetches.set(self.fetches.get() + 1);
+            self.headers.clone()
+        }
+
+        fn add_header(&self, _name: &str, _value: &str) {}
+
+        fn set_header(&self, _name: &str, _value: &str) {}
+
+        fn set_headers(&self, _headers: Vec<(&str, &str)>) {}
+
+        fn remove_header(&self, _name: &str) {}
+    }
+
+    #[test]
+    fn get_headers_in_order_with_one_fetch() {
+        let headers = Headers {
+            headers: vec![
+                (":path".to_string(), "/orders".to_string()),
+                ("Content-Type".to_string(), "application/json".to_string()),
+                ("x-tag".to_string(), "a".to_string()),
+                ("x-tag".to_string(), "b".to_string()),
+            ],
+            fetches: Cell::new(0),
+        };
+
+        let values = headers.get_headers(&["content-type", "missing", "x-tag", ":path"]);
+
+        assert_eq!(
+            values,
+            [
+                Some("application/json".to_string()),
+                None,
+                Some("a".to_string()),
+                Some("/orders".to_string()),
+            ]
+        );
+        assert_eq!(headers.fetches.get(), 1);
+        assert!(headers.get_headers(&[]).is_empty());
+    }
+}
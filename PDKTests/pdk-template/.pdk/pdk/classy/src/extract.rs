@@ -3,5 +3,6 @@ mod from_context;
 
 pub mod config;
 pub mod context;
+pub mod shared;
 
 pub use from_context::{Extract, FromContext};
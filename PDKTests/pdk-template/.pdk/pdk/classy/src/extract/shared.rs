@@ -0,0 +1,24 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use std::{convert::Infallible, rc::Rc};
+
+use super::FromContext;
+use crate::reactor::root::RootReactor;
+
+/// State of type `T` shared by the configuration and the filters of a root context, created
+/// the first time it is extracted.
+///
+/// Unlike a thread local, the state is not seen by the other root contexts of the VM, so
+/// the configure function can hand state over to the filters of its own root only.
+pub struct Shared<T>(pub Rc<T>);
+
+impl<T, C> FromContext<C> for Shared<T>
+where
+    T: Default + 'static,
+    Rc<RootReactor>: FromContext<C, Error = Infallible>,
+{
+    type Error = Infallible;
+
+    fn from_context(context: &C) -> Result<Self, Self::Error> {
+        Ok(Self(Rc::<RootReactor>::from_context(context)?.shared()))
+    }
+}
@@ -7,6 +7,8 @@ pub const HEADER_AUTHORITY: &str = ":authority";
 pub const HEADER_PATH: &str = ":path";
 pub const DEFAULT_PATH: &str = "/";
 pub const HEADER_STATUS: &str = ":status";
+pub const HEADER_COOKIE: &str = "cookie";
+pub const HEADER_SET_COOKIE: &str = "set-cookie";
 
 pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
 pub const METHOD_POST: &str = "POST";
@@ -9,10 +9,14 @@ mod types;
 
 pub mod bootstrap;
 pub mod client;
+pub mod cookie;
 pub mod event;
 pub mod extract;
 pub mod middleware;
 pub mod plugin;
+pub mod query;
+#[cfg(feature = "testing")]
+pub mod testing;
 
 pub(crate) mod http_constants;
 pub(crate) mod macros;
@@ -0,0 +1,396 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Query string editing that keeps the parameters it does not touch byte for byte.
+//!
+//! Upstreams verifying request signatures compute them over the query string as sent, so
+//! re-encoding a `;`, a `+` or the order of the parameters that were not changed would break
+//! them. [`QueryParams`] keeps every parameter in its raw form and only encodes the ones it
+//! sets or appends. The events read and rewrite their query with it through
+//! [`QueryParamsAccessor`](crate::event::QueryParamsAccessor).
+//!
+//! Components are decoded as forms are by default, `+` standing for a space. Base64 tokens
+//! sent in the query need the [`QueryDecoding::Strict`] mode instead, which leaves `+` alone.
+use std::fmt::Write;
+
+use serde::Deserialize;
+
+/// Which value of a repeated parameter a single valued view of the query keeps, the first one
+/// by default. Some authentication schemes sign the first occurrence, which the upstream then
+/// reads.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DuplicateParams {
+    #[default]
+    FirstWins,
+    LastWins,
+}
+
+/// How the names and values of the query are decoded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum QueryDecoding {
+    /// As `application/x-www-form-urlencoded`, `+` standing for a space.
+    #[default]
+    Form,
+    /// As RFC 3986, only percent-encoded octets are decoded and `+` stays a `+`.
+    Strict,
+}
+
+impl QueryDecoding {
+    /// Decodes a name or a value of the query. Components that are malformed, or not UTF-8
+    /// once decoded, are kept as they are.
+    pub fn decode(self, component: &str) -> String {
+        match self {
+            Self::Form => {
+                let component = component.replace('+', " ");
+                percent_decode(&component).unwrap_or(component)
+            }
+            Self::Strict => percent_decode(component).unwrap_or_else(|| component.to_string()),
+        }
+    }
+}
+
+/// The decoded names and values of the parameters of `query`, in order. Parameters without
+/// `=` have an empty value and empty parameters are skipped.
+pub fn query_pairs(query: &str, decoding: QueryDecoding) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|param| !param.is_empty())
+        .map(|param| {
+            let (name, value) = param.split_once('=').unwrap_or((param, ""));
+            (decoding.decode(name), decoding.decode(value))
+        })
+        .collect()
+}
+
+/// Decodes every percent-encoded octet of `value`, failing when it is malformed or when
+/// the result is not valid UTF-8.
+pub fn percent_decode(value: &str) -> Option<String> {
+    if !value.contains('%') {
+        return Some(value.to_string());
+    }
+
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let byte = match bytes[index] {
+            b'%' => {
+                let hex = value.get(index + 1..index + 3)?;
+                index += 2;
+                u8::from_str_radix(hex, 16).ok()?
+            }
+            byte => byte,
+        };
+        decoded.push(byte);
+        index += 1;
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+/// A parameter of the query string, as it was sent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Param {
+    raw: String,
+    name: String,
+}
+
+impl Param {
+    fn parse(raw: &str, decoding: QueryDecoding) -> Self {
+        let name = raw.split('=').next().unwrap_or_default();
+        Self {
+            raw: raw.to_string(),
+            name: decoding.decode(name),
+        }
+    }
+
+    fn encoded(name: &str, value: &str) -> Self {
+        Self {
+            raw: format!("{}={}", encode_component(name), encode_component(value)),
+            name: name.to_string(),
+        }
+    }
+
+    fn raw_name(&self) -> &str {
+        self.raw.split('=').next().unwrap_or_default()
+    }
+
+    fn value(&self, decoding: QueryDecoding) -> String {
+        self.raw
+            .split_once('=')
+            .map(|(_, value)| decoding.decode(value))
+            .unwrap_or_default()
+    }
+}
+
+/// Percent-encodes everything but the unreserved characters.
+fn encode_component(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => {
+                let _ = write!(encoded, "%{:02X}", byte);
+            }
+        }
+    }
+    encoded
+}
+
+/// The path and query parameters of a request URI.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueryParams {
+    path: String,
+    // None when the URI has no `?`.
+    params: Option<Vec<Param>>,
+    decoding: QueryDecoding,
+}
+
+impl QueryParams {
+    /// Splits a request URI, like the `:path` pseudo header, in its path and query.
+    pub fn parse(uri: &str) -> Self {
+        Self::parse_with(uri, QueryDecoding::default())
+    }
+
+    /// Splits a request URI in its path and query, decoding the query as told.
+    pub fn parse_with(uri: &str, decoding: QueryDecoding) -> Self {
+        match uri.split_once('?') {
+            Some((path, query)) => Self {
+                path: path.to_string(),
+                params: Some(
+                    query
+                        .split('&')
+                        .map(|param| Param::parse(param, decoding))
+                        .collect(),
+                ),
+                decoding,
+            },
+            None => Self {
+                path: uri.to_string(),
+                params: None,
+                decoding,
+            },
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The query string, without the `?`. The parameters that were not changed keep their
+    /// original encoding and order.
+    pub fn raw_query(&self) -> Option<String> {
+        self.params.as_ref().map(|params| {
+            params
+                .iter()
+                .map(|param| param.raw.as_str())
+                .collect::<Vec<_>>()
+                .join("&")
+        })
+    }
+
+    /// The decoded value of the first parameter called `name`.
+    pub fn get(&self, name: &str) -> Option<String> {
+        self.iter_named(name)
+            .next()
+            .map(|param| param.value(self.decoding))
+    }
+
+    /// The decoded value of the parameter called `name`, picking among the repeated ones as
+    /// told by `duplicates`.
+    pub fn get_with(&self, name: &str, duplicates: DuplicateParams) -> Option<String> {
+        let param = match duplicates {
+            DuplicateParams::FirstWins => self.iter_named(name).next(),
+            DuplicateParams::LastWins => self.iter_named(name).last(),
+        };
+        param.map(|param| param.value(self.decoding))
+    }
+
+    /// The decoded values of every parameter called `name`, in order.
+    pub fn get_all(&self, name: &str) -> Vec<String> {
+        self.iter_named(name)
+            .map(|param| param.value(self.decoding))
+            .collect()
+    }
+
+    /// The decoded names and values of the parameters, in order.
+    pub fn pairs(&self) -> Vec<(String, String)> {
+        self.params
+            .iter()
+            .flatten()
+            .filter(|param| !param.raw.is_empty())
+            .map(|param| (param.name.clone(), param.value(self.decoding)))
+            .collect()
+    }
+
+    /// Sets the parameter `name` to `value`. The first parameter with that name keeps its
+    /// place and its encoded name, the rest are removed. It is appended when missing.
+    pub fn set(&mut self, name: &str, value: &str) {
+        let params = self.params.get_or_insert_with(Vec::new);
+        match params.iter().position(|param| param.name == name) {
+            Some(index) => {
+                let raw = format!("{}={}", params[index].raw_name(), encode_component(value));
+                params[index].raw = raw;
+                let mut kept = false;
+                params.retain(|param| {
+                    if param.name != name {
+                        return true;
+                    }
+                    let keep = !kept;
+                    kept = true;
+                    keep
+                });
+            }
+            None => params.push(Param::encoded(name, value)),
+        }
+    }
+
+    /// Adds a parameter after all the others, even if there are others with the same name.
+    pub fn append(&mut self, name: &str, value: &str) {
+        self.params
+            .get_or_insert_with(Vec::new)
+            .push(Param::encoded(name, value));
+    }
+
+    /// Removes every parameter called `name`. Returns whether there was any.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let params = match self.params.as_mut() {
+            Some(params) => params,
+            None => return false,
+        };
+        let len = params.len();
+        params.retain(|param| param.name != name);
+        if params.len() == len {
+            return false;
+        }
+        if params.iter().all(|param| param.raw.is_empty()) {
+            self.params = None;
+        }
+        true
+    }
+
+    /// The request URI, with the query string when there is one.
+    pub fn to_uri(&self) -> String {
+        match self.raw_query() {
+            Some(query) => format!("{}?{}", self.path, query),
+            None => self.path.clone(),
+        }
+    }
+
+    fn iter_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Param> {
+        self.params
+            .iter()
+            .flatten()
+            .filter(move |param| !param.raw.is_empty() && param.name == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{percent_decode, query_pairs, DuplicateParams, QueryDecoding, QueryParams};
+
+    #[test]
+    fn untouched_uri() {
+        for uri in ["/a", "/a?", "/a?x=1;y=2&b=c+d&&e", "/a?k=%7e&k=%2B"] {
+            assert_eq!(QueryParams::parse(uri).to_uri(), uri);
+        }
+    }
+
+    #[test]
+    fn decoded_values() {
+        let params = QueryParams::parse("/a?q=a+b%20c&list=1&list=2&flag&bad=%zz");
+
+        assert_eq!(params.get("q").as_deref(), Some("a b c"));
+        assert_eq!(params.get_all("list"), ["1", "2"]);
+        assert_eq!(params.get("flag").as_deref(), Some(""));
+        assert_eq!(params.get("bad").as_deref(), Some("%zz"));
+        assert_eq!(params.get("missing"), None);
+    }
+
+    #[test]
+    fn duplicated_params() {
+        let params = QueryParams::parse("/a?k=1&other=x&k=2");
+
+        assert_eq!(
+            params.get_with("k", DuplicateParams::FirstWins).as_deref(),
+            Some("1")
+        );
+        assert_eq!(
+            params.get_with("k", DuplicateParams::LastWins).as_deref(),
+            Some("2")
+        );
+    }
+
+    #[test]
+    fn strict_decoding() {
+        let uri = "/a?token=ab+c%2Bd==&name=a%20b&bad=%FF%FE";
+        let form = QueryParams::parse(uri);
+        let strict = QueryParams::parse_with(uri, QueryDecoding::Strict);
+
+        assert_eq!(form.get("token").as_deref(), Some("ab c+d=="));
+        assert_eq!(strict.get("token").as_deref(), Some("ab+c+d=="));
+        assert_eq!(strict.get("name").as_deref(), Some("a b"));
+        assert_eq!(strict.get("bad").as_deref(), Some("%FF%FE"));
+    }
+
+    #[test]
+    fn pairs_of_query() {
+        assert_eq!(
+            query_pairs("a+b=1+2&&flag&c=%2B", QueryDecoding::Strict),
+            [
+                ("a+b".to_string(), "1+2".to_string()),
+                ("flag".to_string(), String::new()),
+                ("c".to_string(), "+".to_string()),
+            ]
+        );
+        assert_eq!(
+            query_pairs("a+b=1+2", QueryDecoding::Form),
+            [("a b".to_string(), "1 2".to_string())]
+        );
+    }
+
+    #[test]
+    fn set_keeps_other_params() {
+        let mut params = QueryParams::parse("/a?sig=a%2Bb;c&key=old&page=2&key=dup");
+        params.set("key", "new value");
+        params.set("added", "1/2");
+
+        assert_eq!(
+            params.to_uri(),
+            "/a?sig=a%2Bb;c&key=new%20value&page=2&added=1%2F2"
+        );
+    }
+
+    #[test]
+    fn decode_all() {
+        assert_eq!(percent_decode("ball%20red").as_deref(), Some("ball red"));
+        assert_eq!(percent_decode("%E2%9C%93").as_deref(), Some("✓"));
+        assert_eq!(percent_decode("%zz"), None);
+        assert_eq!(percent_decode("%FF"), None);
+    }
+
+    #[test]
+    fn remove_keeps_the_other_params_as_they_are() {
+        let mut params = QueryParams::parse("/orders?api_key=secret&q=a%20b&api_key=again");
+        assert!(params.remove("api_key"));
+        assert_eq!(params.to_uri(), "/orders?q=a%20b");
+
+        let mut params = QueryParams::parse("/orders?api%5Fkey=secret");
+        assert!(params.remove("api_key"));
+        assert_eq!(params.to_uri(), "/orders");
+    }
+
+    #[test]
+    fn append_and_remove() {
+        let mut params = QueryParams::parse("/a");
+        params.append("k", "1");
+        params.append("k", "2");
+        assert_eq!(params.to_uri(), "/a?k=1&k=2");
+
+        assert!(params.remove("k"));
+        assert!(!params.remove("k"));
+        assert_eq!(params.to_uri(), "/a");
+    }
+}
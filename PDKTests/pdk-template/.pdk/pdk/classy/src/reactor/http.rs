@@ -1,5 +1,10 @@
 // Copyright 2023 Salesforce, Inc. All rights reserved.
-use std::{cell::RefCell, collections::BTreeMap, task::Waker};
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    task::Waker,
+    time::{Duration, SystemTime},
+};
 
 use crate::{event::EventKind, types::HttpCid};
 
@@ -49,6 +54,10 @@ struct RawHttpReactor {
     paused_response: bool,
     current_event: EventKind,
     wakers: BTreeMap<(EventKind, WakerId), Waker>,
+    filter_start: Option<SystemTime>,
+    filter_duration: Option<Duration>,
+    body_size: usize,
+    end_of_stream: bool,
 }
 
 impl RawHttpReactor {
@@ -89,6 +98,10 @@ impl HttpReactor {
                 paused_response: false,
                 current_event: EventKind::Start,
                 wakers: BTreeMap::new(),
+                filter_start: None,
+                filter_duration: None,
+                body_size: 0,
+                end_of_stream: false,
             }),
         }
     }
@@ -135,6 +148,45 @@ impl HttpReactor {
         self.raw.borrow_mut().remove_waker(event, id)
     }
 
+    /// Records the filter started handling the exchange at `now`.
+    pub fn start_filter(&self, now: SystemTime) {
+        self.raw.borrow_mut().filter_start = Some(now);
+    }
+
+    /// Records the filter completed at `now`, returning the time it took.
+    pub fn complete_filter(&self, now: SystemTime) -> Duration {
+        let mut raw = self.raw.borrow_mut();
+        let duration = elapsed(raw.filter_start, now);
+        raw.filter_duration = Some(duration);
+        duration
+    }
+
+    /// The time the filter took, or has taken until `now` while it is still running.
+    pub fn filter_duration(&self, now: SystemTime) -> Option<Duration> {
+        let raw = self.raw.borrow();
+        raw.filter_duration
+            .or_else(|| raw.filter_start.map(|start| elapsed(Some(start), now)))
+    }
+
+    /// Records the size of the body buffered by the proxy for the current body event.
+    pub fn set_body_size(&self, body_size: usize) {
+        self.raw.borrow_mut().body_size = body_size;
+    }
+
+    pub fn body_size(&self) -> usize {
+        self.raw.borrow().body_size
+    }
+
+    /// Records whether the current event ends the stream of its phase, so no body follows
+    /// headers ending it and no more body follows a body ending it.
+    pub fn set_end_of_stream(&self, end_of_stream: bool) {
+        self.raw.borrow_mut().end_of_stream = end_of_stream;
+    }
+
+    pub fn end_of_stream(&self) -> bool {
+        self.raw.borrow().end_of_stream
+    }
+
     pub fn phase(&self) -> ExchangePhase {
         match self.current_event() {
             EventKind::Start
@@ -148,3 +200,9 @@ impl HttpReactor {
         }
     }
 }
+
+fn elapsed(start: Option<SystemTime>, now: SystemTime) -> Duration {
+    start
+        .and_then(|start| now.duration_since(start).ok())
+        .unwrap_or_default()
+}
@@ -1,5 +1,11 @@
 // Copyright 2023 Salesforce, Inc. All rights reserved.
-use std::{any::Any, cell::RefCell, collections::BTreeMap, rc::Rc, task::Waker};
+use std::{
+    any::{Any, TypeId},
+    cell::RefCell,
+    collections::BTreeMap,
+    rc::Rc,
+    task::Waker,
+};
 
 use crate::{
     client::HttpCallResponse,
@@ -11,6 +17,7 @@ use super::http::HttpReactor;
 
 pub type ResponseContent = Box<dyn Any>;
 pub type BoxedExtractor = Box<dyn FnOnce(&HttpCallResponse) -> Box<dyn Any>>;
+pub type ShutdownHook = Box<dyn FnOnce()>;
 
 struct RawRootReactor {
     context_id: RootCid,
@@ -22,6 +29,10 @@ struct RawRootReactor {
     clients: BTreeMap<RequestId, Waker>,
     responses: BTreeMap<RequestId, (HttpCallResponse, Option<ResponseContent>)>,
     done: bool,
+    duration_header: bool,
+    drain_hooks: Vec<ShutdownHook>,
+    shutdown_hooks: Vec<ShutdownHook>,
+    shared: BTreeMap<TypeId, Rc<dyn Any>>,
 }
 
 impl RawRootReactor {
@@ -151,6 +162,10 @@ impl RootReactor {
                 clients: BTreeMap::new(),
                 responses: BTreeMap::new(),
                 done: false,
+                duration_header: false,
+                drain_hooks: Vec::new(),
+                shutdown_hooks: Vec::new(),
+                shared: BTreeMap::new(),
             }),
         }
     }
@@ -199,10 +214,50 @@ impl RootReactor {
         self.raw.borrow().done()
     }
 
+    pub fn duration_header(&self) -> bool {
+        self.raw.borrow().duration_header
+    }
+
+    pub fn set_duration_header(&self, enabled: bool) {
+        self.raw.borrow_mut().duration_header = enabled;
+    }
+
     pub fn set_done(&self) {
         self.raw.borrow_mut().set_done();
     }
 
+    pub fn add_drain_hook(&self, hook: ShutdownHook) {
+        self.raw.borrow_mut().drain_hooks.push(hook);
+    }
+
+    pub fn add_shutdown_hook(&self, hook: ShutdownHook) {
+        self.raw.borrow_mut().shutdown_hooks.push(hook);
+    }
+
+    /// The hooks are taken out, so they run once and can use the reactor.
+    pub fn take_drain_hooks(&self) -> Vec<ShutdownHook> {
+        std::mem::take(&mut self.raw.borrow_mut().drain_hooks)
+    }
+
+    pub fn take_shutdown_hooks(&self) -> Vec<ShutdownHook> {
+        std::mem::take(&mut self.raw.borrow_mut().shutdown_hooks)
+    }
+
+    /// The state of type `T` shared by the contexts of this root, created the first time it
+    /// is asked for.
+    pub fn shared<T: Default + 'static>(&self) -> Rc<T> {
+        let shared = Rc::clone(
+            self.raw
+                .borrow_mut()
+                .shared
+                .entry(TypeId::of::<T>())
+                .or_insert_with(|| Rc::new(T::default())),
+        );
+        shared
+            .downcast()
+            .unwrap_or_else(|_| unreachable!("shared state stored under its own type"))
+    }
+
     pub fn notify_response(&self, response: HttpCallResponse) {
         self.raw.borrow_mut().notify_response(response)
     }
@@ -0,0 +1,783 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! A simulation of the proxy, to walk filters through whole exchanges in tests.
+//!
+//! [`SimulatedHost`] keeps the headers, bodies, properties and shared data in memory, and
+//! records the local responses, HTTP calls and logs of the filter. [`Simulator`] drives the
+//! configuration and the exchanges of a filter through it, the way the proxy would.
+//!
+//! Only the calls going through [`Host`] are simulated: APIs calling the proxy-wasm hostcalls
+//! directly still need the proxy.
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+use std::time::{Duration, SystemTime};
+
+use proxy_wasm::traits::{HttpContext, RootContext};
+use proxy_wasm::types::{Action, Bytes, LogLevel, Status};
+
+use crate::bootstrap::Launcher;
+use crate::context::root::AsyncRootContext;
+use crate::extract::context::ConfigureContext;
+use crate::extract::FromContext;
+use crate::handler::Handler;
+use crate::middleware::EventHandlerStack;
+use crate::types::RootCid;
+use crate::Host;
+
+const ROOT_CONTEXT_ID: u32 = 1;
+
+/// An HTTP call dispatched by a filter.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HttpCall {
+    pub id: u32,
+    pub upstream: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Bytes>,
+    pub trailers: Vec<(String, String)>,
+    pub timeout: Duration,
+}
+
+/// A response sent by a filter instead of the upstream one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LocalResponse {
+    pub status_code: u32,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Bytes>,
+}
+
+#[derive(Default)]
+struct Message {
+    headers: Vec<(String, Bytes)>,
+    body: Bytes,
+    trailers: Vec<(String, Bytes)>,
+}
+
+struct State {
+    time: SystemTime,
+    configuration: Option<Bytes>,
+    properties: HashMap<Vec<String>, Bytes>,
+    shared_data: HashMap<String, (Bytes, u32)>,
+    queues: Vec<(String, VecDeque<Bytes>)>,
+    request: Message,
+    response: Message,
+    call_response: Message,
+    calls: Vec<HttpCall>,
+    local_response: Option<LocalResponse>,
+    resumed_requests: usize,
+    resumed_responses: usize,
+    logs: Vec<(LogLevel, String)>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            time: SystemTime::UNIX_EPOCH,
+            configuration: None,
+            properties: HashMap::new(),
+            shared_data: HashMap::new(),
+            queues: Vec::new(),
+            request: Message::default(),
+            response: Message::default(),
+            call_response: Message::default(),
+            calls: Vec::new(),
+            local_response: None,
+            resumed_requests: 0,
+            resumed_responses: 0,
+            logs: Vec::new(),
+        }
+    }
+}
+
+/// A [`Host`] keeping its state in memory.
+#[derive(Default)]
+pub struct SimulatedHost {
+    state: RefCell<State>,
+}
+
+impl SimulatedHost {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the configuration read by the filter when configured.
+    pub fn with_configuration(self, configuration: &[u8]) -> Self {
+        self.state.borrow_mut().configuration = Some(configuration.to_vec());
+        self
+    }
+
+    pub fn with_property(self, path: &[&str], value: &[u8]) -> Self {
+        self.set_property(path.to_vec(), Some(value));
+        self
+    }
+
+    /// Sets the time returned by the host, the epoch by default.
+    pub fn set_time(&self, time: SystemTime) {
+        self.state.borrow_mut().time = time;
+    }
+
+    pub fn request_headers(&self) -> Vec<(String, String)> {
+        to_strings(&self.state.borrow().request.headers)
+    }
+
+    pub fn request_body(&self) -> Bytes {
+        self.state.borrow().request.body.clone()
+    }
+
+    pub fn response_headers(&self) -> Vec<(String, String)> {
+        to_strings(&self.state.borrow().response.headers)
+    }
+
+    pub fn response_body(&self) -> Bytes {
+        self.state.borrow().response.body.clone()
+    }
+
+    /// Returns the HTTP calls dispatched so far.
+    pub fn http_calls(&self) -> Vec<HttpCall> {
+        self.state.borrow().calls.clone()
+    }
+
+    /// Returns the last response sent by the filter, if any.
+    pub fn local_response(&self) -> Option<LocalResponse> {
+        self.state.borrow().local_response.clone()
+    }
+
+    /// Returns how many times the request and the response were resumed.
+    pub fn resumed(&self) -> (usize, usize) {
+        let state = self.state.borrow();
+        (state.resumed_requests, state.resumed_responses)
+    }
+
+    pub fn logs(&self) -> Vec<(LogLevel, String)> {
+        self.state.borrow().logs.clone()
+    }
+}
+
+fn to_strings(map: &[(String, Bytes)]) -> Vec<(String, String)> {
+    map.iter()
+        .map(|(name, value)| (name.clone(), String::from_utf8_lossy(value).into_owned()))
+        .collect()
+}
+
+fn to_bytes(map: Vec<(&str, &str)>) -> Vec<(String, Bytes)> {
+    map.into_iter()
+        .map(|(name, value)| (name.to_string(), value.as_bytes().to_vec()))
+        .collect()
+}
+
+fn bytes_to_bytes(map: Vec<(&str, &[u8])>) -> Vec<(String, Bytes)> {
+    map.into_iter()
+        .map(|(name, value)| (name.to_string(), value.to_vec()))
+        .collect()
+}
+
+fn get_value(map: &[(String, Bytes)], name: &str) -> Option<Bytes> {
+    map.iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.clone())
+}
+
+fn get_string(map: &[(String, Bytes)], name: &str) -> Option<String> {
+    get_value(map, name).map(|value| String::from_utf8_lossy(&value).into_owned())
+}
+
+fn set_value(map: &mut Vec<(String, Bytes)>, name: &str, value: Option<&[u8]>) {
+    map.retain(|(key, _)| !key.eq_ignore_ascii_case(name));
+    if let Some(value) = value {
+        map.push((name.to_string(), value.to_vec()));
+    }
+}
+
+fn get_buffer(buffer: &[u8], start: usize, max_size: usize) -> Option<Bytes> {
+    if start >= buffer.len() {
+        return None;
+    }
+    let end = start.saturating_add(max_size).min(buffer.len());
+    Some(buffer[start..end].to_vec())
+}
+
+fn set_buffer(buffer: &mut Bytes, start: usize, size: usize, value: &[u8]) {
+    let start = start.min(buffer.len());
+    let end = start.saturating_add(size).min(buffer.len());
+    buffer.splice(start..end, value.iter().copied());
+}
+
+impl Host for SimulatedHost {
+    fn get_current_time(&self) -> SystemTime {
+        self.state.borrow().time
+    }
+
+    fn get_plugin_configuration(&self) -> Option<Bytes> {
+        self.state.borrow().configuration.clone()
+    }
+
+    fn get_property(&self, path: Vec<&str>) -> Option<Bytes> {
+        let path: Vec<String> = path.into_iter().map(String::from).collect();
+        self.state.borrow().properties.get(&path).cloned()
+    }
+
+    fn set_property(&self, path: Vec<&str>, value: Option<&[u8]>) {
+        let path: Vec<String> = path.into_iter().map(String::from).collect();
+        let properties = &mut self.state.borrow_mut().properties;
+        match value {
+            Some(value) => properties.insert(path, value.to_vec()),
+            None => properties.remove(&path),
+        };
+    }
+
+    fn get_shared_data(&self, key: &str) -> (Option<Bytes>, Option<u32>) {
+        match self.state.borrow().shared_data.get(key) {
+            Some((value, cas)) => (Some(value.clone()), Some(*cas)),
+            None => (None, None),
+        }
+    }
+
+    fn set_shared_data(
+        &self,
+        key: &str,
+        value: Option<&[u8]>,
+        cas: Option<u32>,
+    ) -> Result<(), Status> {
+        let shared_data = &mut self.state.borrow_mut().shared_data;
+        let current = shared_data.get(key).map(|(_, cas)| *cas);
+        if cas.is_some() && cas != current {
+            return Err(Status::CasMismatch);
+        }
+        match value {
+            Some(value) => {
+                let cas = current.unwrap_or_default() + 1;
+                shared_data.insert(key.to_string(), (value.to_vec(), cas));
+            }
+            None => {
+                shared_data.remove(key);
+            }
+        }
+        Ok(())
+    }
+
+    fn register_shared_queue(&self, name: &str) -> u32 {
+        if let Some(id) = self.resolve_shared_queue("", name) {
+            return id;
+        }
+        let queues = &mut self.state.borrow_mut().queues;
+        queues.push((name.to_string(), VecDeque::new()));
+        queues.len() as u32
+    }
+
+    fn resolve_shared_queue(&self, _vm_id: &str, name: &str) -> Option<u32> {
+        self.state
+            .borrow()
+            .queues
+            .iter()
+            .position(|(queue, _)| queue == name)
+            .map(|index| index as u32 + 1)
+    }
+
+    fn dequeue_shared_queue(&self, queue_id: u32) -> Result<Option<Bytes>, Status> {
+        let queues = &mut self.state.borrow_mut().queues;
+        match queues.get_mut((queue_id as usize).wrapping_sub(1)) {
+            Some((_, queue)) => Ok(queue.pop_front()),
+            None => Err(Status::NotFound),
+        }
+    }
+
+    fn enqueue_shared_queue(&self, queue_id: u32, value: Option<&[u8]>) -> Result<(), Status> {
+        let queues = &mut self.state.borrow_mut().queues;
+        match queues.get_mut((queue_id as usize).wrapping_sub(1)) {
+            Some((_, queue)) => {
+                queue.push_back(value.unwrap_or_default().to_vec());
+                Ok(())
+            }
+            None => Err(Status::NotFound),
+        }
+    }
+
+    fn dispatch_http_call(
+        &self,
+        upstream: &str,
+        headers: Vec<(&str, &str)>,
+        body: Option<&[u8]>,
+        trailers: Vec<(&str, &str)>,
+        timeout: Duration,
+    ) -> Result<u32, Status> {
+        let calls = &mut self.state.borrow_mut().calls;
+        let id = calls.len() as u32 + 1;
+        let to_strings = |map: Vec<(&str, &str)>| {
+            map.into_iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect()
+        };
+        calls.push(HttpCall {
+            id,
+            upstream: upstream.to_string(),
+            headers: to_strings(headers),
+            body: body.map(<[u8]>::to_vec),
+            trailers: to_strings(trailers),
+            timeout,
+        });
+        Ok(id)
+    }
+
+    fn get_http_call_response_headers(&self) -> Vec<(String, String)> {
+        to_strings(&self.state.borrow().call_response.headers)
+    }
+
+    fn get_http_call_response_headers_bytes(&self) -> Vec<(String, Bytes)> {
+        self.state.borrow().call_response.headers.clone()
+    }
+
+    fn get_http_call_response_header(&self, name: &str) -> Option<String> {
+        get_string(&self.state.borrow().call_response.headers, name)
+    }
+
+    fn get_http_call_response_header_bytes(&self, name: &str) -> Option<Bytes> {
+        get_value(&self.state.borrow().call_response.headers, name)
+    }
+
+    fn get_http_call_response_body(&self, start: usize, max_size: usize) -> Option<Bytes> {
+        get_buffer(&self.state.borrow().call_response.body, start, max_size)
+    }
+
+    fn get_http_call_response_trailers(&self) -> Vec<(String, String)> {
+        to_strings(&self.state.borrow().call_response.trailers)
+    }
+
+    fn get_http_call_response_trailers_bytes(&self) -> Vec<(String, Bytes)> {
+        self.state.borrow().call_response.trailers.clone()
+    }
+
+    fn get_http_call_response_trailer(&self, name: &str) -> Option<String> {
+        get_string(&self.state.borrow().call_response.trailers, name)
+    }
+
+    fn get_http_call_response_trailer_bytes(&self, name: &str) -> Option<Bytes> {
+        get_value(&self.state.borrow().call_response.trailers, name)
+    }
+
+    fn call_foreign_function(
+        &self,
+        _function_name: &str,
+        _arguments: Option<&[u8]>,
+    ) -> Result<Option<Bytes>, Status> {
+        Err(Status::NotFound)
+    }
+
+    fn get_http_request_headers(&self) -> Vec<(String, String)> {
+        self.request_headers()
+    }
+
+    fn get_http_request_headers_bytes(&self) -> Vec<(String, Bytes)> {
+        self.state.borrow().request.headers.clone()
+    }
+
+    fn set_http_request_headers(&self, headers: Vec<(&str, &str)>) {
+        self.state.borrow_mut().request.headers = to_bytes(headers);
+    }
+
+    fn set_http_request_headers_bytes(&self, headers: Vec<(&str, &[u8])>) {
+        self.state.borrow_mut().request.headers = bytes_to_bytes(headers);
+    }
+
+    fn get_http_request_header(&self, name: &str) -> Option<String> {
+        get_string(&self.state.borrow().request.headers, name)
+    }
+
+    fn get_http_request_header_bytes(&self, name: &str) -> Option<Bytes> {
+        get_value(&self.state.borrow().request.headers, name)
+    }
+
+    fn set_http_request_header(&self, name: &str, value: Option<&str>) {
+        let value = value.map(str::as_bytes);
+        set_value(&mut self.state.borrow_mut().request.headers, name, value);
+    }
+
+    fn set_http_request_header_bytes(&self, name: &str, value: Option<&[u8]>) {
+        set_value(&mut self.state.borrow_mut().request.headers, name, value);
+    }
+
+    fn add_http_request_header(&self, name: &str, value: &str) {
+        self.add_http_request_header_bytes(name, value.as_bytes());
+    }
+
+    fn add_http_request_header_bytes(&self, name: &str, value: &[u8]) {
+        let headers = &mut self.state.borrow_mut().request.headers;
+        headers.push((name.to_string(), value.to_vec()));
+    }
+
+    fn get_http_request_body(&self, start: usize, max_size: usize) -> Option<Bytes> {
+        get_buffer(&self.state.borrow().request.body, start, max_size)
+    }
+
+    fn set_http_request_body(&self, start: usize, size: usize, value: &[u8]) {
+        set_buffer(
+            &mut self.state.borrow_mut().request.body,
+            start,
+            size,
+            value,
+        );
+    }
+
+    fn get_http_request_trailers(&self) -> Vec<(String, String)> {
+        to_strings(&self.state.borrow().request.trailers)
+    }
+
+    fn get_http_request_trailers_bytes(&self) -> Vec<(String, Bytes)> {
+        self.state.borrow().request.trailers.clone()
+    }
+
+    fn set_http_request_trailers(&self, trailers: Vec<(&str, &str)>) {
+        self.state.borrow_mut().request.trailers = to_bytes(trailers);
+    }
+
+    fn set_http_request_trailers_bytes(&self, trailers: Vec<(&str, &[u8])>) {
+        self.state.borrow_mut().request.trailers = bytes_to_bytes(trailers);
+    }
+
+    fn get_http_request_trailer(&self, name: &str) -> Option<String> {
+        get_string(&self.state.borrow().request.trailers, name)
+    }
+
+    fn get_http_request_trailer_bytes(&self, name: &str) -> Option<Bytes> {
+        get_value(&self.state.borrow().request.trailers, name)
+    }
+
+    fn set_http_request_trailer(&self, name: &str, value: Option<&str>) {
+        let value = value.map(str::as_bytes);
+        set_value(&mut self.state.borrow_mut().request.trailers, name, value);
+    }
+
+    fn set_http_request_trailer_bytes(&self, name: &str, value: Option<&[u8]>) {
+        set_value(&mut self.state.borrow_mut().request.trailers, name, value);
+    }
+
+    fn add_http_request_trailer(&self, name: &str, value: &str) {
+        self.add_http_request_trailer_bytes(name, value.as_bytes());
+    }
+
+    fn add_http_request_trailer_bytes(&self, name: &str, value: &[u8]) {
+        let trailers = &mut self.state.borrow_mut().request.trailers;
+        trailers.push((name.to_string(), value.to_vec()));
+    }
+
+    fn resume_http_request(&self) {
+        self.state.borrow_mut().resumed_requests += 1;
+    }
+
+    fn get_http_response_headers(&self) -> Vec<(String, String)> {
+        self.response_headers()
+    }
+
+    fn get_http_response_headers_bytes(&self) -> Vec<(String, Bytes)> {
+        self.state.borrow().response.headers.clone()
+    }
+
+    fn set_http_response_headers(&self, headers: Vec<(&str, &str)>) {
+        self.state.borrow_mut().response.headers = to_bytes(headers);
+    }
+
+    fn set_http_response_headers_bytes(&self, headers: Vec<(&str, &[u8])>) {
+        self.state.borrow_mut().response.headers = bytes_to_bytes(headers);
+    }
+
+    fn get_http_response_header(&self, name: &str) -> Option<String> {
+        get_string(&self.state.borrow().response.headers, name)
+    }
+
+    fn get_http_response_header_bytes(&self, name: &str) -> Option<Bytes> {
+        get_value(&self.state.borrow().response.headers, name)
+    }
+
+    fn set_http_response_header(&self, name: &str, value: Option<&str>) {
+        let value = value.map(str::as_bytes);
+        set_value(&mut self.state.borrow_mut().response.headers, name, value);
+    }
+
+    fn set_http_response_header_bytes(&self, name: &str, value: Option<&[u8]>) {
+        set_value(&mut self.state.borrow_mut().response.headers, name, value);
+    }
+
+    fn add_http_response_header(&self, name: &str, value: &str) {
+        self.add_http_response_header_bytes(name, value.as_bytes());
+    }
+
+    fn add_http_response_header_bytes(&self, name: &str, value: &[u8]) {
+        let headers = &mut self.state.borrow_mut().response.headers;
+        headers.push((name.to_string(), value.to_vec()));
+    }
+
+    fn get_http_response_body(&self, start: usize, max_size: usize) -> Option<Bytes> {
+        get_buffer(&self.state.borrow().response.body, start, max_size)
+    }
+
+    fn set_http_response_body(&self, start: usize, size: usize, value: &[u8]) {
+        set_buffer(
+            &mut self.state.borrow_mut().response.body,
+            start,
+            size,
+            value,
+        );
+    }
+
+    fn get_http_response_trailers(&self) -> Vec<(String, String)> {
+        to_strings(&self.state.borrow().response.trailers)
+    }
+
+    fn get_http_response_trailers_bytes(&self) -> Vec<(String, Bytes)> {
+        self.state.borrow().response.trailers.clone()
+    }
+
+    fn set_http_response_trailers(&self, trailers: Vec<(&str, &str)>) {
+        self.state.borrow_mut().response.trailers = to_bytes(trailers);
+    }
+
+    fn set_http_response_trailers_bytes(&self, trailers: Vec<(&str, &[u8])>) {
+        self.state.borrow_mut().response.trailers = bytes_to_bytes(trailers);
+    }
+
+    fn get_http_response_trailer(&self, name: &str) -> Option<String> {
+        get_string(&self.state.borrow().response.trailers, name)
+    }
+
+    fn get_http_response_trailer_bytes(&self, name: &str) -> Option<Bytes> {
+        get_value(&self.state.borrow().response.trailers, name)
+    }
+
+    fn set_http_response_trailer(&self, name: &str, value: Option<&str>) {
+        let value = value.map(str::as_bytes);
+        set_value(&mut self.state.borrow_mut().response.trailers, name, value);
+    }
+
+    fn set_http_response_trailer_bytes(&self, name: &str, value: Option<&[u8]>) {
+        set_value(&mut self.state.borrow_mut().response.trailers, name, value);
+    }
+
+    fn add_http_response_trailer(&self, name: &str, value: &str) {
+        self.add_http_response_trailer_bytes(name, value.as_bytes());
+    }
+
+    fn add_http_response_trailer_bytes(&self, name: &str, value: &[u8]) {
+        let trailers = &mut self.state.borrow_mut().response.trailers;
+        trailers.push((name.to_string(), value.to_vec()));
+    }
+
+    fn resume_http_response(&self) {
+        self.state.borrow_mut().resumed_responses += 1;
+    }
+
+    fn send_http_response(
+        &self,
+        status_code: u32,
+        headers: Vec<(&str, &str)>,
+        body: Option<&[u8]>,
+    ) {
+        self.state.borrow_mut().local_response = Some(LocalResponse {
+            status_code,
+            headers: to_strings(&to_bytes(headers)),
+            body: body.map(<[u8]>::to_vec),
+        });
+    }
+
+    fn log(&self, level: LogLevel, message: &str) {
+        self.state
+            .borrow_mut()
+            .logs
+            .push((level, message.to_string()));
+    }
+}
+
+/// Drives a filter through its configuration and exchanges, on a [`SimulatedHost`].
+///
+/// # Example
+///
+/// Doc tests are linked without the host functions of the proxy, so filters are driven like
+/// this from the integration tests, as `tests/scenarios.rs` does:
+///
+/// ```ignore
+/// use classy::bootstrap::Launcher;
+/// use classy::event::{Exchange, HeadersAccessor, RequestHeaders};
+/// use classy::testing::{SimulatedHost, Simulator};
+///
+/// async fn filter(exchange: Exchange<RequestHeaders>) {
+///     if let Some(event) = exchange.event_data() {
+///         event.add_header("x-filtered", "true");
+///     }
+/// }
+///
+/// async fn configure(launcher: Launcher) {
+///     launcher.launch(filter).await.unwrap();
+/// }
+///
+/// let mut simulator = Simulator::new(SimulatedHost::new(), configure);
+/// let mut exchange = simulator.exchange();
+/// exchange.request_headers(vec![(":path", "/")]);
+///
+/// assert_eq!(
+///     simulator.host().request_headers(),
+///     vec![(":path".to_string(), "/".to_string()), ("x-filtered".to_string(), "true".to_string())]
+/// );
+/// ```
+pub struct Simulator {
+    host: Rc<SimulatedHost>,
+    root: Box<dyn RootContext>,
+    next_context_id: u32,
+}
+
+impl Simulator {
+    /// Creates the root context of the filter and configures it.
+    pub fn new<C, T>(host: SimulatedHost, configure: C) -> Self
+    where
+        C: Handler<Launcher, T> + 'static,
+        T: FromContext<ConfigureContext> + 'static,
+    {
+        let host = Rc::new(host);
+        let mut root: Box<dyn RootContext> = Box::new(AsyncRootContext::new(
+            RootCid::from(ROOT_CONTEXT_ID),
+            host.clone(),
+            EventHandlerStack::default(),
+            configure,
+        ));
+        let configuration_size = host.get_plugin_configuration().unwrap_or_default().len();
+        root.on_configure(configuration_size);
+
+        Self {
+            host,
+            root,
+            next_context_id: ROOT_CONTEXT_ID + 1,
+        }
+    }
+
+    pub fn host(&self) -> &SimulatedHost {
+        &self.host
+    }
+
+    /// Drains the plugin, as the proxy does before deleting it. Dropping the simulator
+    /// deletes it.
+    pub fn drain(&mut self) {
+        self.root.on_done();
+    }
+
+    /// Starts a new exchange, with empty headers, bodies and trailers.
+    pub fn exchange(&mut self) -> SimulatedExchange {
+        {
+            let mut state = self.host.state.borrow_mut();
+            state.request = Message::default();
+            state.response = Message::default();
+            state.local_response = None;
+        }
+
+        let context_id = self.next_context_id;
+        self.next_context_id += 1;
+        let context = self
+            .root
+            .create_http_context(context_id)
+            .expect("the root context creates http contexts");
+
+        SimulatedExchange {
+            host: self.host.clone(),
+            context,
+        }
+    }
+}
+
+/// An exchange of a [`Simulator`], where every method delivers an event to the filter.
+pub struct SimulatedExchange {
+    host: Rc<SimulatedHost>,
+    context: Box<dyn HttpContext>,
+}
+
+impl SimulatedExchange {
+    pub fn request_headers(&mut self, headers: Vec<(&str, &str)>) -> Action {
+        self.host.set_http_request_headers(headers);
+        let count = self.host.state.borrow().request.headers.len();
+        self.context.on_http_request_headers(count, false)
+    }
+
+    pub fn request_body(&mut self, body: &[u8]) -> Action {
+        self.host.state.borrow_mut().request.body = body.to_vec();
+        self.context.on_http_request_body(body.len(), true)
+    }
+
+    /// Delivers a chunk of the request body, with the size of the whole body buffered so far,
+    /// as the proxy does while the request is paused.
+    pub fn request_body_chunk(&mut self, chunk: &[u8], end_of_stream: bool) -> Action {
+        let size = {
+            let mut state = self.host.state.borrow_mut();
+            state.request.body.extend_from_slice(chunk);
+            state.request.body.len()
+        };
+        self.context.on_http_request_body(size, end_of_stream)
+    }
+
+    pub fn response_headers(&mut self, headers: Vec<(&str, &str)>) -> Action {
+        self.host.set_http_response_headers(headers);
+        let count = self.host.state.borrow().response.headers.len();
+        self.context.on_http_response_headers(count, false)
+    }
+
+    pub fn response_body(&mut self, body: &[u8]) -> Action {
+        self.host.state.borrow_mut().response.body = body.to_vec();
+        self.context.on_http_response_body(body.len(), true)
+    }
+
+    /// Delivers the response of the HTTP call `id` dispatched by the filter.
+    pub fn http_call_response(&mut self, id: u32, headers: Vec<(&str, &str)>, body: &[u8]) {
+        let (num_headers, body_size) = {
+            let mut state = self.host.state.borrow_mut();
+            state.call_response = Message {
+                headers: to_bytes(headers),
+                body: body.to_vec(),
+                trailers: Vec::new(),
+            };
+            (state.call_response.headers.len(), body.len())
+        };
+        self.context
+            .on_http_call_response(id, num_headers, body_size, 0);
+    }
+}
+
+impl Drop for SimulatedExchange {
+    fn drop(&mut self) {
+        self.context.on_done();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_buffer, set_buffer, SimulatedHost};
+    use crate::Host;
+
+    #[test]
+    fn headers_are_case_insensitive() {
+        let host = SimulatedHost::new();
+        host.set_http_request_headers(vec![("Content-Type", "text/plain")]);
+
+        assert_eq!(
+            host.get_http_request_header("content-type"),
+            Some("text/plain".to_string())
+        );
+
+        host.set_http_request_header("CONTENT-TYPE", None);
+        assert!(host.get_http_request_headers().is_empty());
+    }
+
+    #[test]
+    fn buffers_are_sliced_and_spliced() {
+        let mut buffer = b"hello world".to_vec();
+
+        assert_eq!(get_buffer(&buffer, 6, 100), Some(b"world".to_vec()));
+        assert_eq!(get_buffer(&buffer, 11, 1), None);
+
+        set_buffer(&mut buffer, 0, 5, b"bye");
+        assert_eq!(buffer, b"bye world".to_vec());
+    }
+
+    #[test]
+    fn shared_data_checks_cas() {
+        let host = SimulatedHost::new();
+        host.set_shared_data("key", Some(b"first"), None).unwrap();
+        let (_, cas) = host.get_shared_data("key");
+
+        assert!(host.set_shared_data("key", Some(b"second"), cas).is_ok());
+        assert!(host.set_shared_data("key", Some(b"third"), cas).is_err());
+        assert_eq!(host.get_shared_data("key").0, Some(b"second".to_vec()));
+    }
+}
@@ -0,0 +1,361 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Walks a filter through whole exchanges on the simulated host.
+//! Run with `cargo test -p classy --features testing`.
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, UNIX_EPOCH};
+
+use classy::bootstrap::Launcher;
+use classy::client::HttpClient;
+use classy::event::{
+    BodyAccessor, Cookie, CookiesAccessor, Exchange, HeadersAccessor, QueryParamsAccessor,
+    RequestHeaders,
+};
+use classy::proxy_wasm::types::Action;
+use classy::testing::{SimulatedHost, Simulator};
+use classy::Configuration;
+
+const TELEMETRY_UPSTREAM: &str = "telemetry";
+
+// Adds the configured header, short-circuits the blocked requests and reports the others
+// to the telemetry upstream before tagging their responses.
+async fn filter(exchange: Exchange<RequestHeaders>, client: HttpClient, header: Rc<String>) {
+    if let Some(event) = exchange.event_data() {
+        if event.header("x-blocked").is_some() {
+            exchange.send_response(403, vec![("x-reason", "blocked")], Some(b"blocked"));
+            return;
+        }
+        event.add_header("x-configured", header.as_str());
+    }
+
+    let reported = client
+        .request(TELEMETRY_UPSTREAM, "telemetry.local")
+        .path("/report")
+        .timeout(Duration::from_secs(1))
+        .post();
+
+    let reported = match reported {
+        Ok(request) => request.await.is_ok(),
+        Err(_) => false,
+    };
+
+    let exchange = exchange.wait_for_response_headers().await;
+    if let Some(event) = exchange.event_data() {
+        event.add_header("x-reported", if reported { "true" } else { "false" });
+    }
+}
+
+async fn configure(launcher: Launcher, Configuration(configuration): Configuration) {
+    let header = Rc::new(String::from_utf8(configuration).unwrap());
+    launcher
+        .launch(|exchange, client| filter(exchange, client, header.clone()))
+        .await
+        .unwrap();
+}
+
+async fn configure_timed(launcher: Launcher, Configuration(configuration): Configuration) {
+    let header = Rc::new(String::from_utf8(configuration).unwrap());
+    launcher
+        .with_duration_header(true)
+        .launch(|exchange, client| filter(exchange, client, header.clone()))
+        .await
+        .unwrap();
+}
+
+thread_local! {
+    static SHUTDOWN: RefCell<Vec<&'static str>> = RefCell::new(Vec::new());
+}
+
+fn record_shutdown(hook: &'static str) {
+    SHUTDOWN.with(|shutdown| shutdown.borrow_mut().push(hook));
+}
+
+async fn configure_hooked(launcher: Launcher, Configuration(configuration): Configuration) {
+    let header = Rc::new(String::from_utf8(configuration).unwrap());
+    launcher
+        .on_drain(|| record_shutdown("drain"))
+        .on_vm_shutdown(|| record_shutdown("vm_shutdown"))
+        .launch(|exchange, client| filter(exchange, client, header.clone()))
+        .await
+        .unwrap();
+}
+
+// Tags the requests with the size of their whole body, once it is buffered.
+async fn buffering_filter(exchange: Exchange<RequestHeaders>) {
+    let exchange = exchange.wait_for_request_body().await;
+    if let Some(event) = exchange.event_data() {
+        event.add_header("x-body-size", &event.body().len().to_string());
+        event.set_body(b"replaced");
+    }
+}
+
+async fn configure_buffering(launcher: Launcher) {
+    launcher.launch(buffering_filter).await.unwrap();
+}
+
+// Tags the responses with the size of their whole body, once it is buffered.
+async fn response_buffering_filter(exchange: Exchange<RequestHeaders>) {
+    let exchange = exchange
+        .wait_for_response_headers()
+        .await
+        .wait_for_response_body()
+        .await;
+    if let Some(event) = exchange.event_data() {
+        event.add_header("x-body-size", &event.body().len().to_string());
+        event.set_body(b"replaced");
+    }
+}
+
+async fn configure_response_buffering(launcher: Launcher) {
+    launcher.launch(response_buffering_filter).await.unwrap();
+}
+
+// Moves the api key of the query string to a header, so it is not forwarded in the url.
+async fn api_key_filter(exchange: Exchange<RequestHeaders>) {
+    if let Some(event) = exchange.event_data() {
+        if let Some(api_key) = event.query_param("api_key") {
+            event.remove_query_param("api_key");
+            event.set_header("x-api-key", &api_key);
+        }
+    }
+}
+
+async fn configure_api_key(launcher: Launcher) {
+    launcher.launch(api_key_filter).await.unwrap();
+}
+
+// Keeps the tracking cookie from the upstream and marks the session cookie it sets secure.
+async fn cookie_filter(exchange: Exchange<RequestHeaders>) {
+    if let Some(event) = exchange.event_data() {
+        event.remove_cookie("tracking");
+    }
+
+    let exchange = exchange.wait_for_response_headers().await;
+    if let Some(event) = exchange.event_data() {
+        if let Some(session) = event.cookie("session") {
+            event.set_cookie(Cookie::new("session", session.value()).with_attribute("Secure", ""));
+        }
+    }
+}
+
+async fn configure_cookies(launcher: Launcher) {
+    launcher.launch(cookie_filter).await.unwrap();
+}
+
+fn simulator() -> Simulator {
+    Simulator::new(
+        SimulatedHost::new().with_configuration(b"configured"),
+        configure,
+    )
+}
+
+fn header(name: &str, value: &str) -> (String, String) {
+    (name.to_string(), value.to_string())
+}
+
+#[test]
+fn forwarded_request_is_reported() {
+    let mut simulator = simulator();
+    let mut exchange = simulator.exchange();
+
+    assert_eq!(
+        exchange.request_headers(vec![(":path", "/")]),
+        Action::Pause
+    );
+    assert_eq!(
+        simulator.host().request_headers(),
+        vec![header(":path", "/"), header("x-configured", "configured")]
+    );
+
+    let calls = simulator.host().http_calls();
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].upstream, TELEMETRY_UPSTREAM);
+    assert!(calls[0].headers.contains(&header(":path", "/report")));
+    assert_eq!(calls[0].timeout, Duration::from_secs(1));
+
+    exchange.http_call_response(calls[0].id, vec![(":status", "202")], b"");
+    assert_eq!(simulator.host().resumed(), (1, 0));
+
+    assert_eq!(exchange.request_body(b"payload"), Action::Continue);
+    assert_eq!(
+        exchange.response_headers(vec![(":status", "200")]),
+        Action::Continue
+    );
+    assert_eq!(
+        simulator.host().response_headers(),
+        vec![header(":status", "200"), header("x-reported", "true")]
+    );
+    assert_eq!(exchange.response_body(b"response"), Action::Continue);
+    assert_eq!(simulator.host().local_response(), None);
+}
+
+#[test]
+fn blocked_request_is_short_circuited() {
+    let mut simulator = simulator();
+    let mut exchange = simulator.exchange();
+
+    exchange.request_headers(vec![(":path", "/"), ("x-blocked", "true")]);
+
+    let response = simulator.host().local_response().unwrap();
+    assert_eq!(response.status_code, 403);
+    assert_eq!(response.headers, vec![header("x-reason", "blocked")]);
+    assert_eq!(response.body, Some(b"blocked".to_vec()));
+    assert!(simulator.host().http_calls().is_empty());
+}
+
+#[test]
+fn exchanges_are_independent() {
+    let mut simulator = simulator();
+
+    let mut blocked = simulator.exchange();
+    blocked.request_headers(vec![("x-blocked", "true")]);
+    drop(blocked);
+
+    let mut forwarded = simulator.exchange();
+    forwarded.request_headers(vec![(":path", "/")]);
+
+    assert_eq!(simulator.host().local_response(), None);
+    assert_eq!(simulator.host().http_calls().len(), 1);
+}
+
+#[test]
+fn filter_duration_is_reported() {
+    let mut simulator = Simulator::new(
+        SimulatedHost::new()
+            .with_configuration(b"configured")
+            .with_property(&["plugin_name"], b"timed"),
+        configure_timed,
+    );
+    let mut exchange = simulator.exchange();
+    let at = |millis| UNIX_EPOCH + Duration::from_millis(millis);
+
+    simulator.host().set_time(at(100));
+    exchange.request_headers(vec![(":path", "/")]);
+
+    simulator.host().set_time(at(115));
+    let calls = simulator.host().http_calls();
+    exchange.http_call_response(calls[0].id, vec![(":status", "202")], b"");
+
+    simulator.host().set_time(at(125));
+    exchange.response_headers(vec![(":status", "200")]);
+
+    assert_eq!(
+        simulator.host().response_headers(),
+        vec![
+            header(":status", "200"),
+            header("x-reported", "true"),
+            header("x-policy-duration-ms", "timed=25"),
+        ]
+    );
+}
+
+#[test]
+fn shutdown_hooks_run_once() {
+    let mut simulator = Simulator::new(
+        SimulatedHost::new().with_configuration(b"configured"),
+        configure_hooked,
+    );
+    let mut exchange = simulator.exchange();
+    exchange.request_headers(vec![(":path", "/")]);
+    drop(exchange);
+
+    assert!(SHUTDOWN.with(|shutdown| shutdown.borrow().is_empty()));
+
+    simulator.drain();
+    simulator.drain();
+    assert_eq!(
+        SHUTDOWN.with(|shutdown| shutdown.borrow().clone()),
+        ["drain"]
+    );
+
+    drop(simulator);
+    assert_eq!(
+        SHUTDOWN.with(|shutdown| shutdown.borrow().clone()),
+        ["drain", "vm_shutdown"]
+    );
+}
+
+#[test]
+fn request_body_is_buffered_before_the_headers_go_on() {
+    let mut simulator = Simulator::new(SimulatedHost::new(), configure_buffering);
+    let mut exchange = simulator.exchange();
+
+    assert_eq!(
+        exchange.request_headers(vec![(":path", "/")]),
+        Action::Pause
+    );
+    assert_eq!(exchange.request_body_chunk(b"pay", false), Action::Pause);
+    assert_eq!(exchange.request_body_chunk(b"load", true), Action::Continue);
+
+    assert_eq!(
+        simulator.host().request_headers(),
+        vec![header(":path", "/"), header("x-body-size", "7")]
+    );
+    assert_eq!(simulator.host().resumed(), (0, 0));
+}
+
+#[test]
+fn response_body_is_buffered_before_the_headers_go_on() {
+    let mut simulator = Simulator::new(SimulatedHost::new(), configure_response_buffering);
+    let mut exchange = simulator.exchange();
+
+    assert_eq!(
+        exchange.request_headers(vec![(":path", "/")]),
+        Action::Continue
+    );
+    assert_eq!(
+        exchange.response_headers(vec![(":status", "200")]),
+        Action::Pause
+    );
+    assert_eq!(exchange.response_body(b"payload"), Action::Continue);
+
+    assert_eq!(
+        simulator.host().response_headers(),
+        vec![header(":status", "200"), header("x-body-size", "7")]
+    );
+    assert_eq!(simulator.host().response_body(), b"replaced".to_vec());
+}
+
+#[test]
+fn api_key_is_removed_from_the_query_string() {
+    let mut simulator = Simulator::new(SimulatedHost::new(), configure_api_key);
+    let mut exchange = simulator.exchange();
+
+    exchange.request_headers(vec![(":path", "/orders?api_key=s%3Dcret&q=a+b")]);
+
+    assert_eq!(
+        simulator.host().request_headers(),
+        vec![
+            header(":path", "/orders?q=a+b"),
+            header("x-api-key", "s=cret")
+        ]
+    );
+}
+
+#[test]
+fn cookies_are_rewritten() {
+    let mut simulator = Simulator::new(SimulatedHost::new(), configure_cookies);
+    let mut exchange = simulator.exchange();
+
+    exchange.request_headers(vec![(":path", "/"), ("cookie", "tracking=1; session=abc")]);
+    assert_eq!(
+        simulator.host().request_headers(),
+        vec![header(":path", "/"), header("cookie", "session=abc")]
+    );
+
+    exchange.response_headers(vec![
+        (":status", "200"),
+        ("set-cookie", "theme=dark"),
+        ("set-cookie", "; Path=/"),
+        ("set-cookie", "session=def; HttpOnly"),
+    ]);
+    assert_eq!(
+        simulator.host().response_headers(),
+        vec![
+            header(":status", "200"),
+            header("set-cookie", "; Path=/"),
+            header("set-cookie", "theme=dark"),
+            header("set-cookie", "session=def; Secure"),
+        ]
+    );
+}
@@ -0,0 +1,199 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Versioned configurations, upgraded from the older shapes of their documents when the policy
+//! is configured, so the policies deployed with an older configuration keep working.
+//!
+//! The version of a document is its `configVersion` field, `0` when absent. The migration from
+//! the version `n` upgrades the documents of that version to the version `n + 1`, and the
+//! document is migrated up to the current version, the number of migrations, before being
+//! deserialized:
+//!
+//! ```ignore
+//! impl Versioned for PolicyConfiguration {
+//!     fn migrations() -> Migrations {
+//!         Migrations::new().with("rename maxSize to maxBytes", |document| {
+//!             if let Some(size) = document.remove("maxSize") {
+//!                 document.insert("maxBytes".to_string(), size);
+//!             }
+//!             Ok(())
+//!         })
+//!     }
+//! }
+//!
+//! #[entrypoint]
+//! async fn configure(
+//!     launcher: Launcher,
+//!     VersionedConfiguration(config): VersionedConfiguration<PolicyConfiguration>,
+//! ) -> Result<(), PolicyError> {
+//! ```
+use classy::extract::config::Configuration;
+use classy::extract::context::ConfigureContext;
+use classy::extract::FromContext;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+
+use crate::error::{codes, PolicyError};
+
+/// The field of the version of the configuration documents.
+pub const CONFIG_VERSION: &str = "configVersion";
+
+type Upgrade = fn(&mut Map<String, Value>) -> Result<(), String>;
+
+struct Migration {
+    description: &'static str,
+    upgrade: Upgrade,
+}
+
+/// The migrations of a configuration, in the order of the versions they upgrade from.
+#[derive(Default)]
+pub struct Migrations {
+    migrations: Vec<Migration>,
+}
+
+impl Migrations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the migration from the current version to the next one.
+    pub fn with(mut self, description: &'static str, upgrade: Upgrade) -> Self {
+        self.migrations.push(Migration {
+            description,
+            upgrade,
+        });
+        self
+    }
+
+    /// The version of the documents once migrated.
+    pub fn current_version(&self) -> u64 {
+        self.migrations.len() as u64
+    }
+
+    /// Upgrades the `document` to the current version, with the descriptions of the migrations
+    /// applied.
+    pub fn migrate(&self, document: Value) -> Result<(Value, Vec<&'static str>), PolicyError> {
+        let invalid = |message: String| PolicyError::new(codes::INVALID_CONFIGURATION, message);
+        let mut document = match document {
+            Value::Object(document) => document,
+            _ => return Err(invalid("The configuration is not an object".to_string())),
+        };
+
+        let version = match document.get(CONFIG_VERSION) {
+            None => 0,
+            Some(version) => version
+                .as_u64()
+                .ok_or_else(|| invalid(format!("Invalid {} {}", CONFIG_VERSION, version)))?,
+        };
+        if version > self.current_version() {
+            return Err(invalid(format!(
+                "The configuration version {} is newer than the version {} of the policy",
+                version,
+                self.current_version()
+            )));
+        }
+
+        let mut applied = Vec::new();
+        for migration in &self.migrations[version as usize..] {
+            (migration.upgrade)(&mut document).map_err(|message| {
+                invalid(format!(
+                    "Migration '{}' failed: {}",
+                    migration.description, message
+                ))
+            })?;
+            applied.push(migration.description);
+        }
+        document.insert(
+            CONFIG_VERSION.to_string(),
+            Value::from(self.current_version()),
+        );
+        Ok((Value::Object(document), applied))
+    }
+
+    /// Deserializes the configuration document of the `bytes`, migrated to the current version.
+    /// The migrations applied are logged.
+    pub fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, PolicyError> {
+        let document: Value = serde_json::from_slice(bytes)?;
+        let (document, applied) = self.migrate(document)?;
+        for description in applied {
+            log::info!("Migrated the configuration: {}.", description);
+        }
+        Ok(serde_json::from_value(document)?)
+    }
+}
+
+/// A configuration whose older documents can be migrated.
+pub trait Versioned: DeserializeOwned {
+    fn migrations() -> Migrations;
+}
+
+/// Extracts the configuration of the policy, migrated to its current version.
+#[derive(Clone, Debug)]
+pub struct VersionedConfiguration<T>(pub T);
+
+impl<T: Versioned> FromContext<ConfigureContext> for VersionedConfiguration<T> {
+    type Error = PolicyError;
+
+    fn from_context(context: &ConfigureContext) -> Result<Self, Self::Error> {
+        let Configuration(bytes) = Configuration::from_context(context).unwrap_or_default();
+        T::migrations()
+            .deserialize(&bytes)
+            .map(VersionedConfiguration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Migrations, CONFIG_VERSION};
+    use serde_json::json;
+
+    fn migrations() -> Migrations {
+        Migrations::new()
+            .with("rename maxSize to maxBytes", |document| {
+                if let Some(size) = document.remove("maxSize") {
+                    document.insert("maxBytes".to_string(), size);
+                }
+                Ok(())
+            })
+            .with("parse maxBytes", |document| {
+                match document.get("maxBytes").and_then(|size| size.as_str()) {
+                    Some(size) => {
+                        let size: u64 = size.parse().map_err(|_| format!("{:?}", size))?;
+                        document.insert("maxBytes".to_string(), size.into());
+                        Ok(())
+                    }
+                    None => Ok(()),
+                }
+            })
+    }
+
+    #[test]
+    fn older_documents_are_migrated() {
+        let (document, applied) = migrations().migrate(json!({"maxSize": "512"})).unwrap();
+        assert_eq!(document, json!({"maxBytes": 512, CONFIG_VERSION: 2}));
+        assert_eq!(
+            applied,
+            vec!["rename maxSize to maxBytes", "parse maxBytes"]
+        );
+
+        let (document, applied) = migrations()
+            .migrate(json!({"maxBytes": "512", CONFIG_VERSION: 1}))
+            .unwrap();
+        assert_eq!(document, json!({"maxBytes": 512, CONFIG_VERSION: 2}));
+        assert_eq!(applied, vec!["parse maxBytes"]);
+    }
+
+    #[test]
+    fn current_documents_are_kept() {
+        let current = json!({"maxBytes": 512, CONFIG_VERSION: 2});
+        let (document, applied) = migrations().migrate(current.clone()).unwrap();
+        assert_eq!(document, current);
+        assert!(applied.is_empty());
+    }
+
+    #[test]
+    fn newer_and_invalid_documents_are_rejected() {
+        assert!(migrations().migrate(json!({CONFIG_VERSION: 3})).is_err());
+        assert!(migrations().migrate(json!({CONFIG_VERSION: "1"})).is_err());
+        assert!(migrations().migrate(json!({"maxSize": "big"})).is_err());
+        assert!(migrations().migrate(json!([])).is_err());
+    }
+}
@@ -0,0 +1,256 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Errors shared by the policies, each with a stable code operators can alert on.
+//!
+//! Codes read `FLTR-<number>`, where the hundreds of the number name the category of the error:
+//! 1xx configuration, 2xx host, 3xx crypto, 4xx upstream and 5xx expression. The [`codes`] of
+//! the pdk use the numbers below 50 of every category, policies define theirs from 50 on.
+//!
+//! ```ignore
+//! const UNKNOWN_ISSUER: ErrorCode = ErrorCode::new(ErrorCategory::Crypto, 50);
+//!
+//! fn issuer(config: &Config, name: &str) -> Result<&Issuer, PolicyError> {
+//!     config.issuers.get(name).ok_or_else(|| {
+//!         PolicyError::new(UNKNOWN_ISSUER, format!("issuer {} is not configured", name))
+//!     })
+//! }
+//! ```
+use std::convert::Infallible;
+use std::fmt::{self, Display, Formatter};
+
+use classy::bootstrap::LaunchError;
+use classy::client::{HttpClientRequestError, HttpClientResponseError};
+
+use crate::http::template::UrlTemplateError;
+
+/// The category of a [`PolicyError`], which gives the hundreds of its code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCategory {
+    /// Invalid policy configuration.
+    Config,
+    /// Failed calls to the proxy: properties, shared data, launching the filter.
+    Host,
+    /// Keys, signatures and tokens.
+    Crypto,
+    /// Failed calls to upstream services and unexpected responses.
+    Upstream,
+    /// Parsing and evaluation of expressions.
+    Expression,
+}
+
+impl ErrorCategory {
+    const fn hundreds(self) -> u16 {
+        match self {
+            ErrorCategory::Config => 100,
+            ErrorCategory::Host => 200,
+            ErrorCategory::Crypto => 300,
+            ErrorCategory::Upstream => 400,
+            ErrorCategory::Expression => 500,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorCategory::Config => "config",
+            ErrorCategory::Host => "host",
+            ErrorCategory::Crypto => "crypto",
+            ErrorCategory::Upstream => "upstream",
+            ErrorCategory::Expression => "expression",
+        }
+    }
+}
+
+impl Display for ErrorCategory {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A stable error code, displayed as `FLTR-201`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ErrorCode {
+    category: ErrorCategory,
+    number: u16,
+}
+
+impl ErrorCode {
+    /// The code `number` of the `category`, with `number` below 100.
+    pub const fn new(category: ErrorCategory, number: u8) -> Self {
+        assert!(number < 100, "error numbers are below 100");
+        Self {
+            category,
+            number: category.hundreds() + number as u16,
+        }
+    }
+
+    pub fn category(&self) -> ErrorCategory {
+        self.category
+    }
+
+    /// The number of the code, 201 for `FLTR-201`.
+    pub fn number(&self) -> u16 {
+        self.number
+    }
+}
+
+impl Display for ErrorCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "FLTR-{}", self.number)
+    }
+}
+
+/// The codes of the errors raised by the pdk.
+pub mod codes {
+    use super::{ErrorCategory::*, ErrorCode};
+
+    pub const INVALID_CONFIGURATION: ErrorCode = ErrorCode::new(Config, 1);
+    pub const INVALID_TEMPLATE: ErrorCode = ErrorCode::new(Config, 2);
+    pub const INVALID_PATTERN: ErrorCode = ErrorCode::new(Config, 3);
+
+    pub const LAUNCH_FAILED: ErrorCode = ErrorCode::new(Host, 1);
+    pub const PROPERTY_UNAVAILABLE: ErrorCode = ErrorCode::new(Host, 2);
+    pub const SHARED_DATA_FAILED: ErrorCode = ErrorCode::new(Host, 3);
+
+    pub const INVALID_KEY: ErrorCode = ErrorCode::new(Crypto, 1);
+    pub const MALFORMED_TOKEN: ErrorCode = ErrorCode::new(Crypto, 2);
+    pub const INVALID_SIGNATURE: ErrorCode = ErrorCode::new(Crypto, 3);
+    pub const SIGNING_FAILED: ErrorCode = ErrorCode::new(Crypto, 4);
+    pub const EXPIRED_TOKEN: ErrorCode = ErrorCode::new(Crypto, 5);
+
+    pub const REQUEST_FAILED: ErrorCode = ErrorCode::new(Upstream, 1);
+    pub const UNEXPECTED_STATUS: ErrorCode = ErrorCode::new(Upstream, 2);
+    pub const MALFORMED_RESPONSE: ErrorCode = ErrorCode::new(Upstream, 3);
+
+    pub const EXPRESSION_PARSING: ErrorCode = ErrorCode::new(Expression, 1);
+    pub const EXPRESSION_EVALUATION: ErrorCode = ErrorCode::new(Expression, 2);
+}
+
+/// An error of a policy, displayed as `FLTR-201 host: <message>`.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum PolicyError {
+    #[error("{0} config: {1}")]
+    Config(ErrorCode, String),
+    #[error("{0} host: {1}")]
+    Host(ErrorCode, String),
+    #[error("{0} crypto: {1}")]
+    Crypto(ErrorCode, String),
+    #[error("{0} upstream: {1}")]
+    Upstream(ErrorCode, String),
+    #[error("{0} expression: {1}")]
+    Expression(ErrorCode, String),
+}
+
+impl PolicyError {
+    /// An error of the category of the `code`.
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        let message = message.into();
+        match code.category() {
+            ErrorCategory::Config => PolicyError::Config(code, message),
+            ErrorCategory::Host => PolicyError::Host(code, message),
+            ErrorCategory::Crypto => PolicyError::Crypto(code, message),
+            ErrorCategory::Upstream => PolicyError::Upstream(code, message),
+            ErrorCategory::Expression => PolicyError::Expression(code, message),
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            PolicyError::Config(code, _)
+            | PolicyError::Host(code, _)
+            | PolicyError::Crypto(code, _)
+            | PolicyError::Upstream(code, _)
+            | PolicyError::Expression(code, _) => *code,
+        }
+    }
+
+    pub fn category(&self) -> ErrorCategory {
+        self.code().category()
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            PolicyError::Config(_, message)
+            | PolicyError::Host(_, message)
+            | PolicyError::Crypto(_, message)
+            | PolicyError::Upstream(_, message)
+            | PolicyError::Expression(_, message) => message,
+        }
+    }
+
+    /// Logs the error at error level, in the format every policy shares.
+    pub fn log(&self) {
+        log::error!("{}", self);
+    }
+
+    /// Prefixes the message with the `context` of the error.
+    pub fn context(self, context: impl Display) -> Self {
+        let message = format!("{}: {}", context, self.message());
+        Self::new(self.code(), message)
+    }
+}
+
+impl From<Infallible> for PolicyError {
+    fn from(never: Infallible) -> Self {
+        match never {}
+    }
+}
+
+impl From<serde_json::Error> for PolicyError {
+    fn from(error: serde_json::Error) -> Self {
+        Self::new(codes::INVALID_CONFIGURATION, error.to_string())
+    }
+}
+
+impl From<UrlTemplateError> for PolicyError {
+    fn from(error: UrlTemplateError) -> Self {
+        Self::new(codes::INVALID_TEMPLATE, error.to_string())
+    }
+}
+
+impl From<LaunchError> for PolicyError {
+    fn from(error: LaunchError) -> Self {
+        Self::new(codes::LAUNCH_FAILED, error.to_string())
+    }
+}
+
+impl From<HttpClientRequestError> for PolicyError {
+    fn from(error: HttpClientRequestError) -> Self {
+        Self::new(codes::REQUEST_FAILED, error.to_string())
+    }
+}
+
+impl From<HttpClientResponseError> for PolicyError {
+    fn from(error: HttpClientResponseError) -> Self {
+        Self::new(codes::REQUEST_FAILED, error.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{codes, ErrorCategory, ErrorCode, PolicyError};
+
+    #[test]
+    fn display_code_and_category() {
+        let error = PolicyError::new(codes::PROPERTY_UNAVAILABLE, "request.path is missing");
+
+        assert_eq!(error.code().to_string(), "FLTR-202");
+        assert_eq!(error.category(), ErrorCategory::Host);
+        assert_eq!(error.to_string(), "FLTR-202 host: request.path is missing");
+    }
+
+    #[test]
+    fn policy_codes_follow_their_category() {
+        let code = ErrorCode::new(ErrorCategory::Crypto, 50);
+        let error = PolicyError::new(code, "unknown issuer").context("access token");
+
+        assert_eq!(error.code().number(), 350);
+        assert!(matches!(error, PolicyError::Crypto(..)));
+        assert_eq!(error.message(), "access token: unknown issuer");
+    }
+
+    #[test]
+    fn configuration_errors_from_serde() {
+        let error: PolicyError = serde_json::from_str::<u32>("\"text\"").unwrap_err().into();
+
+        assert_eq!(error.code(), codes::INVALID_CONFIGURATION);
+    }
+}
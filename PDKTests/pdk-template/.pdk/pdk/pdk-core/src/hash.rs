@@ -0,0 +1,160 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Stable hashing utilities to assign keys to shards or buckets.
+//!
+//! Results only depend on the key, the seed and the number of buckets, so they are the same
+//! across workers, restarts and platforms.
+use std::collections::BTreeMap;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+const JUMP_MULTIPLIER: u64 = 2_862_933_555_777_941_757;
+
+/// 64 bits FNV-1a hash of `bytes`, with the offset basis perturbed by `seed`.
+pub fn fnv1a(bytes: &[u8], seed: u64) -> u64 {
+    bytes.iter().fold(FNV_OFFSET_BASIS ^ seed, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// Jump consistent hash: maps `key` to a bucket in `0..buckets`, moving only `1/buckets`
+/// of the keys when a bucket is added. A `buckets` value of 0 is treated as 1.
+pub fn jump_hash(mut key: u64, buckets: u32) -> u32 {
+    let buckets = buckets.max(1) as i64;
+    let mut bucket: i64 = -1;
+    let mut next: i64 = 0;
+
+    while next < buckets {
+        bucket = next;
+        key = key.wrapping_mul(JUMP_MULTIPLIER).wrapping_add(1);
+        next = ((bucket + 1) as f64 * ((1u64 << 31) as f64 / ((key >> 33) + 1) as f64)) as i64;
+    }
+
+    bucket as u32
+}
+
+/// Assigns a string key to a bucket in `0..buckets`.
+pub fn bucket(key: &str, buckets: u32, seed: u64) -> u32 {
+    jump_hash(fnv1a(key.as_bytes(), seed), buckets)
+}
+
+/// A consistent hash ring of named nodes, each placed at several virtual points.
+pub struct HashRing<T> {
+    seed: u64,
+    replicas: usize,
+    ring: BTreeMap<u64, (String, T)>,
+}
+
+impl<T: Clone> HashRing<T> {
+    pub fn new(replicas: usize, seed: u64) -> Self {
+        Self {
+            seed,
+            replicas: replicas.max(1),
+            ring: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_node(mut self, name: &str, node: T) -> Self {
+        self.add(name, node);
+        self
+    }
+
+    pub fn add(&mut self, name: &str, node: T) {
+        for point in self.points(name) {
+            self.ring.insert(point, (name.to_string(), node.clone()));
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        for point in self.points(name) {
+            if matches!(self.ring.get(&point), Some((owner, _)) if owner == name) {
+                self.ring.remove(&point);
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+
+    /// Returns the node owning `key`, if the ring is not empty.
+    pub fn get(&self, key: &str) -> Option<&T> {
+        let hash = fnv1a(key.as_bytes(), self.seed);
+        self.ring
+            .range(hash..)
+            .next()
+            .or_else(|| self.ring.iter().next())
+            .map(|(_, (_, node))| node)
+    }
+
+    fn points(&self, name: &str) -> Vec<u64> {
+        (0..self.replicas)
+            .map(|replica| fnv1a(format!("{name}#{replica}").as_bytes(), self.seed))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bucket, fnv1a, jump_hash, HashRing};
+
+    #[test]
+    fn fnv1a_reference_values() {
+        assert_eq!(fnv1a(b"", 0), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a", 0), 0xaf63_dc4c_8601_ec8c);
+        assert_ne!(fnv1a(b"a", 1), fnv1a(b"a", 0));
+    }
+
+    #[test]
+    fn jump_hash_in_range() {
+        for key in 0..1_000u64 {
+            assert!(jump_hash(fnv1a(&key.to_le_bytes(), 0), 7) < 7);
+        }
+        assert_eq!(jump_hash(42, 0), 0);
+        assert_eq!(jump_hash(42, 1), 0);
+    }
+
+    #[test]
+    fn jump_hash_only_moves_to_new_bucket() {
+        for key in 0..1_000u64 {
+            let key = fnv1a(&key.to_le_bytes(), 0);
+            let before = jump_hash(key, 10);
+            let after = jump_hash(key, 11);
+            assert!(after == before || after == 10);
+        }
+    }
+
+    #[test]
+    fn bucket_is_deterministic() {
+        assert_eq!(bucket("client-1", 100, 7), bucket("client-1", 100, 7));
+        let distinct = (0..100)
+            .map(|i| bucket(&format!("client-{i}"), 4, 0))
+            .collect::<std::collections::BTreeSet<_>>();
+        assert_eq!(distinct.len(), 4);
+    }
+
+    #[test]
+    fn ring_keeps_keys_when_node_is_added() {
+        let ring = HashRing::new(50, 0).with_node("a", 1).with_node("b", 2);
+        let mut grown = HashRing::new(50, 0)
+            .with_node("a", 1)
+            .with_node("b", 2)
+            .with_node("c", 3);
+
+        for i in 0..200 {
+            let key = format!("key-{i}");
+            let owner = *grown.get(&key).unwrap();
+            assert!(owner == 3 || owner == *ring.get(&key).unwrap());
+        }
+
+        grown.remove("c");
+        for i in 0..200 {
+            let key = format!("key-{i}");
+            assert_eq!(grown.get(&key), ring.get(&key));
+        }
+    }
+
+    #[test]
+    fn empty_ring() {
+        assert_eq!(HashRing::<u32>::new(10, 0).get("key"), None);
+    }
+}
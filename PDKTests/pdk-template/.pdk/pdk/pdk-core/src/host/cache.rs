@@ -0,0 +1,365 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! A bounded, least recently used cache whose entries expire after a time to live.
+//!
+//! The cache does not read the clock by itself: the current time is passed to every
+//! operation, so it can be used from PDK policies as well as from raw proxy-wasm filters.
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::time::{Duration, SystemTime};
+
+/// The reason an entry left the cache.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EvictionReason {
+    /// The cache was full and the entry was the least recently used.
+    Capacity,
+    /// The entry outlived its time to live.
+    Expired,
+    /// The entry was explicitly removed or replaced.
+    Removed,
+}
+
+/// Instrumentation hooks invoked by [`LruTtlCache`] operations.
+pub trait CacheObserver<K> {
+    fn on_hit(&self, _key: &K) {}
+
+    fn on_miss(&self, _key: &K) {}
+
+    fn on_insert(&self, _key: &K) {}
+
+    fn on_evict(&self, _key: &K, _reason: EvictionReason) {}
+}
+
+/// Counters maintained by every [`LruTtlCache`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub inserts: u64,
+    pub evictions: u64,
+    pub expirations: u64,
+}
+
+struct Entry<V> {
+    value: V,
+    // None when the time to live goes past what the clock represents.
+    expires_at: Option<SystemTime>,
+    tick: u64,
+}
+
+impl<V> Entry<V> {
+    fn expired(&self, now: SystemTime) -> bool {
+        self.expires_at
+            .map_or(false, |expires_at| expires_at <= now)
+    }
+}
+
+pub struct LruTtlCache<K, V> {
+    capacity: usize,
+    ttl: Duration,
+    entries: HashMap<K, Entry<V>>,
+    order: BTreeMap<u64, K>,
+    tick: u64,
+    stats: CacheStats,
+    observer: Option<Box<dyn CacheObserver<K>>>,
+}
+
+impl<K: Hash + Eq + Clone, V> LruTtlCache<K, V> {
+    /// Creates a cache holding at most `capacity` entries, each living for `ttl`.
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            ttl,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+            stats: CacheStats::default(),
+            observer: None,
+        }
+    }
+
+    pub fn with_observer(mut self, observer: impl CacheObserver<K> + 'static) -> Self {
+        self.observer = Some(Box::new(observer));
+        self
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Returns the number of stored entries, including the expired ones not yet purged.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// Returns the value for `key` if present and not expired, marking it as recently used.
+    pub fn get(&mut self, key: &K, now: SystemTime) -> Option<&V> {
+        let expired = match self.entries.get(key) {
+            None => {
+                self.miss(key);
+                return None;
+            }
+            Some(entry) => entry.expired(now),
+        };
+
+        if expired {
+            self.evict(key, EvictionReason::Expired);
+            self.miss(key);
+            return None;
+        }
+
+        self.stats.hits += 1;
+        if let Some(observer) = &self.observer {
+            observer.on_hit(key);
+        }
+
+        let tick = self.next_tick();
+        let entry = self.entries.get_mut(key)?;
+        self.order.remove(&entry.tick);
+        self.order.insert(tick, key.clone());
+        entry.tick = tick;
+        Some(&entry.value)
+    }
+
+    pub fn contains(&self, key: &K, now: SystemTime) -> bool {
+        self.entries
+            .get(key)
+            .map(|entry| !entry.expired(now))
+            .unwrap_or_default()
+    }
+
+    /// Stores `value` with the default time to live.
+    pub fn insert(&mut self, key: K, value: V, now: SystemTime) {
+        let ttl = self.ttl;
+        self.insert_with_ttl(key, value, ttl, now)
+    }
+
+    /// Stores `value` for `ttl`, for good when `now + ttl` overflows the clock.
+    pub fn insert_with_ttl(&mut self, key: K, value: V, ttl: Duration, now: SystemTime) {
+        if self.entries.contains_key(&key) {
+            self.evict(&key, EvictionReason::Removed);
+        } else if self.entries.len() >= self.capacity {
+            self.purge_expired(now);
+            if self.entries.len() >= self.capacity {
+                if let Some(oldest) = self.order.values().next().cloned() {
+                    self.evict(&oldest, EvictionReason::Capacity);
+                }
+            }
+        }
+
+        self.stats.inserts += 1;
+        if let Some(observer) = &self.observer {
+            observer.on_insert(&key);
+        }
+
+        let tick = self.next_tick();
+        self.order.insert(tick, key.clone());
+        self.entries.insert(
+            key,
+            Entry {
+                value,
+                expires_at: now.checked_add(ttl),
+                tick,
+            },
+        );
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.evict(key, EvictionReason::Removed)
+    }
+
+    /// Drops every expired entry.
+    pub fn purge_expired(&mut self, now: SystemTime) {
+        let expired = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.expired(now))
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+
+        for key in expired {
+            self.evict(&key, EvictionReason::Expired);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        let keys = self.order.values().cloned().collect::<Vec<_>>();
+        for key in keys {
+            self.evict(&key, EvictionReason::Removed);
+        }
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    fn miss(&mut self, key: &K) {
+        self.stats.misses += 1;
+        if let Some(observer) = &self.observer {
+            observer.on_miss(key);
+        }
+    }
+
+    fn evict(&mut self, key: &K, reason: EvictionReason) -> Option<V> {
+        let entry = self.entries.remove(key)?;
+        self.order.remove(&entry.tick);
+
+        match reason {
+            EvictionReason::Capacity => self.stats.evictions += 1,
+            EvictionReason::Expired => self.stats.expirations += 1,
+            EvictionReason::Removed => {}
+        }
+        if let Some(observer) = &self.observer {
+            observer.on_evict(key, reason);
+        }
+
+        Some(entry.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use super::{CacheObserver, EvictionReason, LruTtlCache};
+
+    fn at(seconds: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    fn cache() -> LruTtlCache<&'static str, u32> {
+        LruTtlCache::new(2, Duration::from_secs(10))
+    }
+
+    #[test]
+    fn get_inserted_value() {
+        let mut cache = cache();
+        cache.insert("a", 1, at(0));
+
+        assert_eq!(cache.get(&"a", at(1)), Some(&1));
+        assert_eq!(cache.get(&"b", at(1)), None);
+        assert_eq!(cache.stats().hits, 1);
+        assert_eq!(cache.stats().misses, 1);
+    }
+
+    #[test]
+    fn entries_expire() {
+        let mut cache = cache();
+        cache.insert("a", 1, at(0));
+        cache.insert_with_ttl("b", 2, Duration::from_secs(20), at(0));
+
+        assert_eq!(cache.get(&"a", at(10)), None);
+        assert_eq!(cache.get(&"b", at(10)), Some(&2));
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.stats().expirations, 1);
+    }
+
+    #[test]
+    fn overflowing_ttl_never_expires() {
+        let mut cache = cache();
+        cache.insert_with_ttl("a", 1, Duration::MAX, at(0));
+        cache.purge_expired(at(u32::MAX as u64));
+
+        assert_eq!(cache.get(&"a", at(u32::MAX as u64)), Some(&1));
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = cache();
+        cache.insert("a", 1, at(0));
+        cache.insert("b", 2, at(0));
+        cache.get(&"a", at(1));
+        cache.insert("c", 3, at(2));
+
+        assert!(cache.contains(&"a", at(2)));
+        assert!(!cache.contains(&"b", at(2)));
+        assert!(cache.contains(&"c", at(2)));
+        assert_eq!(cache.stats().evictions, 1);
+    }
+
+    #[test]
+    fn expired_entries_are_evicted_before_live_ones() {
+        let mut cache = cache();
+        cache.insert_with_ttl("a", 1, Duration::from_secs(1), at(0));
+        cache.insert("b", 2, at(0));
+        cache.insert("c", 3, at(5));
+
+        assert!(cache.contains(&"b", at(5)));
+        assert!(cache.contains(&"c", at(5)));
+        assert_eq!(cache.stats().evictions, 0);
+        assert_eq!(cache.stats().expirations, 1);
+    }
+
+    #[test]
+    fn replace_keeps_single_entry() {
+        let mut cache = cache();
+        cache.insert("a", 1, at(0));
+        cache.insert("a", 2, at(0));
+
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.remove(&"a"), Some(2));
+        assert!(cache.is_empty());
+    }
+
+    #[derive(Default, Clone)]
+    struct RecordingObserver {
+        events: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl CacheObserver<&'static str> for RecordingObserver {
+        fn on_hit(&self, key: &&'static str) {
+            self.events.borrow_mut().push(format!("hit {key}"));
+        }
+
+        fn on_miss(&self, key: &&'static str) {
+            self.events.borrow_mut().push(format!("miss {key}"));
+        }
+
+        fn on_insert(&self, key: &&'static str) {
+            self.events.borrow_mut().push(format!("insert {key}"));
+        }
+
+        fn on_evict(&self, key: &&'static str, reason: EvictionReason) {
+            self.events
+                .borrow_mut()
+                .push(format!("evict {key} {reason:?}"));
+        }
+    }
+
+    #[test]
+    fn observer_receives_events() {
+        let observer = RecordingObserver::default();
+        let mut cache =
+            LruTtlCache::new(1, Duration::from_secs(10)).with_observer(observer.clone());
+
+        cache.insert("a", 1, at(0));
+        cache.get(&"a", at(0));
+        cache.insert("b", 2, at(0));
+        cache.get(&"a", at(0));
+
+        assert_eq!(
+            *observer.events.borrow(),
+            [
+                "insert a",
+                "hit a",
+                "evict a Capacity",
+                "insert b",
+                "miss a"
+            ]
+        );
+    }
+}
@@ -1,4 +1,7 @@
 // Copyright 2023 Salesforce, Inc. All rights reserved.
+use crate::host::property::PropertyAccessor;
+use crate::log::capture;
+use crate::policy_context::authentication::AuthenticationCache;
 use crate::policy_context::metadata::PolicyMetadata;
 use crate::policy_context::static_policy_context_cache::StaticPolicyContextCache;
 use classy::proxy_wasm::traits::{Context, HttpContext};
@@ -9,6 +12,7 @@ pub struct HttpContextAdapter {
     http_context: Box<dyn HttpContext>,
     policy_metadata: Rc<PolicyMetadata>,
     plugin_name_api_id: Rc<String>,
+    authentication: Rc<AuthenticationCache>,
 }
 
 impl HttpContextAdapter {
@@ -21,6 +25,7 @@ impl HttpContextAdapter {
             http_context,
             policy_metadata,
             plugin_name_api_id,
+            authentication: Rc::default(),
         }
     }
 
@@ -31,6 +36,7 @@ impl HttpContextAdapter {
     fn fix_current_context(&self) {
         StaticPolicyContextCache::fix_metadata(&self.policy_metadata);
         StaticPolicyContextCache::fix_plugin_name_api_id(&self.plugin_name_api_id);
+        StaticPolicyContextCache::fix_authentication_cache(Some(&self.authentication));
     }
 }
 
@@ -49,7 +55,9 @@ impl Context for HttpContextAdapter {
 
     fn on_done(&mut self) -> bool {
         self.fix_current_context();
-        self.http_context.on_done()
+        let done = self.http_context.on_done();
+        capture::done(<dyn PropertyAccessor>::default());
+        done
     }
 }
 
@@ -1,6 +1,6 @@
 // Copyright 2023 Salesforce, Inc. All rights reserved.
 use crate::host::context::http::HttpContextAdapter;
-use crate::host::property::PropertyAccessor;
+use crate::host::property::{CachedPropertyAccessor, PropertyAccessor};
 use crate::policy_context::metadata::{read_api_name_from_plugin_name, PolicyMetadata};
 use crate::policy_context::static_policy_context_cache::StaticPolicyContextCache;
 use classy::proxy_wasm::traits::{Context, HttpContext, RootContext};
@@ -15,11 +15,12 @@ pub struct RootContextAdapter {
 
 impl RootContextAdapter {
     pub fn new(context: Box<dyn RootContext>) -> Self {
-        let property_accessor = <dyn PropertyAccessor>::default();
+        // Both the metadata and the api id are read from the plugin name.
+        let property_accessor = &CachedPropertyAccessor::new(<dyn PropertyAccessor>::default());
 
         Self {
             root_context: context,
-            policy_metadata: Rc::new(PolicyMetadata::from(property_accessor)),
+            policy_metadata: StaticPolicyContextCache::load_metadata(property_accessor),
             plugin_name_api_id: Rc::new(read_api_name_from_plugin_name(property_accessor)),
         }
     }
@@ -31,6 +32,7 @@ impl RootContextAdapter {
     fn fix_current_context(&self) {
         StaticPolicyContextCache::fix_metadata(&self.policy_metadata);
         StaticPolicyContextCache::fix_plugin_name_api_id(&self.plugin_name_api_id);
+        StaticPolicyContextCache::fix_authentication_cache(None);
     }
 }
 
@@ -55,6 +57,11 @@ impl Context for RootContextAdapter {
 
 impl RootContext for RootContextAdapter {
     fn on_configure(&mut self, plugin_configuration_size: usize) -> bool {
+        // The filter metadata may have changed with the new configuration.
+        StaticPolicyContextCache::invalidate();
+        self.policy_metadata = StaticPolicyContextCache::load_metadata(
+            &CachedPropertyAccessor::new(<dyn PropertyAccessor>::default()),
+        );
         self.fix_current_context();
         self.root_context.on_configure(plugin_configuration_size)
     }
@@ -0,0 +1,68 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Counters reported to the proxy, so they are exposed along with the rest of its stats.
+use classy::extract::FromContext;
+use std::convert::Infallible;
+
+/// Access to the metrics of the proxy.
+pub trait MetricsAccessor {
+    /// Adds `amount` to the counter `name`, defining it the first time it is used.
+    fn increment_counter(&self, name: &str, amount: u64);
+}
+
+impl dyn MetricsAccessor {
+    pub fn default() -> &'static dyn MetricsAccessor {
+        &impls::Host
+    }
+}
+
+impl<C> FromContext<C> for &'static dyn MetricsAccessor {
+    type Error = Infallible;
+
+    fn from_context(_: &C) -> Result<Self, Self::Error> {
+        Ok(<dyn MetricsAccessor>::default())
+    }
+}
+
+mod impls {
+    use super::MetricsAccessor;
+    use classy::proxy_wasm::hostcalls;
+    use classy::proxy_wasm::types::MetricType;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    thread_local! {
+        static COUNTERS: RefCell<HashMap<String, u32>> = RefCell::new(HashMap::new());
+    }
+
+    pub(super) struct Host;
+
+    impl Host {
+        fn counter_id(name: &str) -> Option<u32> {
+            COUNTERS.with(|counters| {
+                if let Some(id) = counters.borrow().get(name) {
+                    return Some(*id);
+                }
+                match hostcalls::define_metric(MetricType::Counter, name) {
+                    Ok(id) => {
+                        counters.borrow_mut().insert(name.to_string(), id);
+                        Some(id)
+                    }
+                    Err(status) => {
+                        log::warn!("Unable to define counter {name}: {status:?}.");
+                        None
+                    }
+                }
+            })
+        }
+    }
+
+    impl MetricsAccessor for Host {
+        fn increment_counter(&self, name: &str, amount: u64) {
+            if let Some(id) = Self::counter_id(name) {
+                if let Err(status) = hostcalls::increment_metric(id, amount as i64) {
+                    log::warn!("Unable to increment counter {name}: {status:?}.");
+                }
+            }
+        }
+    }
+}
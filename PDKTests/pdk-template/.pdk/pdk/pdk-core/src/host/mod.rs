@@ -1,5 +1,9 @@
 // Copyright 2023 Salesforce, Inc. All rights reserved.
+pub mod cache;
 pub mod context;
+pub mod metrics;
 pub mod property;
+pub mod shared_data;
+pub mod window;
 
 pub use anyhow::Result;
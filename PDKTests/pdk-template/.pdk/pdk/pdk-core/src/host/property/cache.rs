@@ -0,0 +1,119 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use classy::proxy_wasm::types::Bytes;
+
+use super::PropertyAccessor;
+
+/// A [`PropertyAccessor`] that remembers the properties read through it, so reading the same
+/// path again does not reach the host.
+///
+/// Properties can change between request phases, so a cache is meant to live for a single
+/// phase: create a new one for each phase, or call [`invalidate`](Self::invalidate) when the
+/// phase changes. Properties set through the cache are also cached.
+pub struct CachedPropertyAccessor<'a> {
+    inner: &'a dyn PropertyAccessor,
+    properties: RefCell<HashMap<Vec<String>, Option<Bytes>>>,
+}
+
+impl<'a> CachedPropertyAccessor<'a> {
+    pub fn new(inner: &'a dyn PropertyAccessor) -> Self {
+        Self {
+            inner,
+            properties: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Forgets every cached property.
+    pub fn invalidate(&self) {
+        self.properties.borrow_mut().clear();
+    }
+
+    fn key(path: &[&str]) -> Vec<String> {
+        path.iter().map(|segment| segment.to_string()).collect()
+    }
+}
+
+impl PropertyAccessor for CachedPropertyAccessor<'_> {
+    fn read_property(&self, path: &[&str]) -> Option<Bytes> {
+        let key = Self::key(path);
+        if let Some(value) = self.properties.borrow().get(&key) {
+            return value.clone();
+        }
+
+        let value = self.inner.read_property(path);
+        self.properties.borrow_mut().insert(key, value.clone());
+        value
+    }
+
+    fn set_property(&self, path: &[&str], value: &[u8]) {
+        self.inner.set_property(path, value);
+        self.properties
+            .borrow_mut()
+            .insert(Self::key(path), Some(value.to_vec()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use classy::proxy_wasm::types::Bytes;
+
+    use super::CachedPropertyAccessor;
+    use crate::host::property::{InMemoryPropertyAccessor, PropertyAccessor};
+
+    struct CountingPropertyAccessor {
+        inner: InMemoryPropertyAccessor,
+        reads: Cell<usize>,
+    }
+
+    impl PropertyAccessor for CountingPropertyAccessor {
+        fn read_property(&self, path: &[&str]) -> Option<Bytes> {
+            self.reads.set(self.reads.get() + 1);
+            self.inner.read_property(path)
+        }
+
+        fn set_property(&self, path: &[&str], value: &[u8]) {
+            self.inner.set_property(path, value)
+        }
+    }
+
+    #[test]
+    fn reads_each_path_once() {
+        let host = CountingPropertyAccessor {
+            inner: InMemoryPropertyAccessor::new().with_property(&["request", "scheme"], b"http"),
+            reads: Cell::new(0),
+        };
+        let cache = CachedPropertyAccessor::new(&host);
+
+        assert_eq!(
+            cache.read_property(&["request", "scheme"]),
+            Some(b"http".to_vec())
+        );
+        assert_eq!(
+            cache.read_property(&["request", "scheme"]),
+            Some(b"http".to_vec())
+        );
+        assert_eq!(cache.read_property(&["missing"]), None);
+        assert_eq!(cache.read_property(&["missing"]), None);
+        assert_eq!(host.reads.get(), 2);
+
+        cache.invalidate();
+        cache.read_property(&["request", "scheme"]);
+        assert_eq!(host.reads.get(), 3);
+    }
+
+    #[test]
+    fn set_writes_through() {
+        let host = InMemoryPropertyAccessor::new();
+        let cache = CachedPropertyAccessor::new(&host);
+
+        cache.read_property(&["descriptor"]);
+        cache.set_property(&["descriptor"], b"gold");
+
+        assert_eq!(cache.read_property(&["descriptor"]), Some(b"gold".to_vec()));
+        assert_eq!(host.read_property(&["descriptor"]), Some(b"gold".to_vec()));
+    }
+}
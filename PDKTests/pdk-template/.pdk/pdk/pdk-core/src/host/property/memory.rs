@@ -0,0 +1,40 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use classy::proxy_wasm::types::Bytes;
+
+use super::PropertyAccessor;
+
+/// A [`PropertyAccessor`] backed by an in-memory map, meant to be used in tests.
+#[derive(Default, Debug)]
+pub struct InMemoryPropertyAccessor {
+    properties: RefCell<HashMap<Vec<String>, Bytes>>,
+}
+
+impl InMemoryPropertyAccessor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_property(self, path: &[&str], value: &[u8]) -> Self {
+        self.set_property(path, value);
+        self
+    }
+
+    fn key(path: &[&str]) -> Vec<String> {
+        path.iter().map(|segment| segment.to_string()).collect()
+    }
+}
+
+impl PropertyAccessor for InMemoryPropertyAccessor {
+    fn read_property(&self, path: &[&str]) -> Option<Bytes> {
+        self.properties.borrow().get(&Self::key(path)).cloned()
+    }
+
+    fn set_property(&self, path: &[&str], value: &[u8]) {
+        self.properties
+            .borrow_mut()
+            .insert(Self::key(path), value.to_vec());
+    }
+}
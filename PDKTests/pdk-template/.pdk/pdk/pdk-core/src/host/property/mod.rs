@@ -1,14 +1,23 @@
 // Copyright 2023 Salesforce, Inc. All rights reserved.
 use classy::extract::FromContext;
 use classy::proxy_wasm::types::Bytes;
-use std::convert::Infallible;
+use std::convert::{Infallible, TryInto};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 pub use self::properties::*;
 use anyhow::format_err;
 use crate::host::{self};
+use crate::http::request_class::RequestClass;
+use crate::http::server_timing::{Metric, ServerTiming};
+use crate::policy_context::decisions::{Decision, PolicyDecisions};
 
+mod cache;
+mod memory;
 mod properties;
 
+pub use self::cache::CachedPropertyAccessor;
+pub use self::memory::InMemoryPropertyAccessor;
+
 pub trait PropertyAccessor {
     /// Returns a property, if not missing
     fn read_property(&self, path: &[&str]) -> Option<Bytes>;
@@ -32,6 +41,22 @@ impl<'a>  PropertyMapper<'a>{
         }
     }
 
+    /// Timestamps are nanoseconds since the epoch, as a little endian 64 bits integer.
+    fn time_property(&self, path: &[&str]) -> host::Result<Option<SystemTime>> {
+        if let Some(bytes) = self.property_accessor.read_property(path) {
+            let nanos: [u8; 8] = bytes
+                .try_into()
+                .map_err(|e| format_err!("Retrieved value for property {:?} was not a timestamp: {:?}", path, e))?;
+            Ok(Some(UNIX_EPOCH + Duration::from_nanos(u64::from_le_bytes(nanos))))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn set_bytes_property(&self, path: &[&str], value: &[u8]) {
+        self.property_accessor.set_property(path, value)
+    }
+
     pub fn from(property_accessor: &'a dyn PropertyAccessor) -> Self {
         Self { property_accessor }
     }
@@ -56,6 +81,12 @@ impl<'a> dyn PropertyAccessor + 'a {
         }
     }
 
+    pub fn tls(&'a self) -> TlsInfo<'a> {
+        TlsInfo {
+            mapper: PropertyMapper::from(self)
+        }
+    }
+
     pub fn destination(&'a self) -> DestinationInfo<'a> {
         DestinationInfo {
             mapper: PropertyMapper::from(self)
@@ -67,6 +98,12 @@ impl<'a> dyn PropertyAccessor + 'a {
             mapper: PropertyMapper::from(self)
         }
     }
+
+    pub fn filter_state(&'a self) -> FilterState<'a> {
+        FilterState {
+            mapper: PropertyMapper::from(self)
+        }
+    }
 }
 
 pub struct RequestInfo<'a> {
@@ -85,6 +122,11 @@ impl<'a> RequestInfo<'a> {
     pub fn scheme(&self) -> host::Result<Option<String>> {
         self.mapper.string_property(REQUEST_SCHEME)
     }
+
+    /// When the first byte of the request was received by the gateway.
+    pub fn time(&self) -> host::Result<Option<SystemTime>> {
+        self.mapper.time_property(REQUEST_TIME)
+    }
 }
 
 pub struct SourceInfo<'a> {
@@ -97,6 +139,51 @@ impl<'a> SourceInfo<'a> {
     }
 }
 
+/// The TLS attributes of the downstream connection. The proxy reports the empty ones, like the
+/// certificate of a client without mTLS, as empty strings, read as none.
+pub struct TlsInfo<'a> {
+    mapper: PropertyMapper<'a>,
+}
+
+impl<'a> TlsInfo<'a> {
+    fn non_empty(&self, path: &[&str]) -> host::Result<Option<String>> {
+        Ok(self.mapper.string_property(path)?.filter(|value| !value.is_empty()))
+    }
+
+    /// The TLS version, like `TLSv1.3`, none when the connection is not over TLS.
+    pub fn tls_version(&self) -> host::Result<Option<String>> {
+        self.non_empty(CONNECTION_TLS_VERSION)
+    }
+
+    /// The server name requested with SNI.
+    pub fn requested_server_name(&self) -> host::Result<Option<String>> {
+        self.non_empty(CONNECTION_REQUESTED_SERVER_NAME)
+    }
+
+    /// Whether the client presented a certificate the proxy validated.
+    pub fn mtls(&self) -> bool {
+        self.mapper
+            .property_accessor
+            .read_property(CONNECTION_MTLS)
+            .map_or(false, |bytes| bytes.first() == Some(&1))
+    }
+
+    /// The subject of the certificate of the client, like `CN=orders,O=Acme`.
+    pub fn subject_peer_certificate(&self) -> host::Result<Option<String>> {
+        self.non_empty(CONNECTION_SUBJECT_PEER_CERTIFICATE)
+    }
+
+    /// The first URI subject alt name of the certificate of the client, like a SPIFFE id.
+    pub fn uri_san_peer_certificate(&self) -> host::Result<Option<String>> {
+        self.non_empty(CONNECTION_URI_SAN_PEER_CERTIFICATE)
+    }
+
+    /// The first DNS subject alt name of the certificate of the client.
+    pub fn dns_san_peer_certificate(&self) -> host::Result<Option<String>> {
+        self.non_empty(CONNECTION_DNS_SAN_PEER_CERTIFICATE)
+    }
+}
+
 pub struct DestinationInfo<'a> {
     mapper: PropertyMapper<'a>,
 }
@@ -115,6 +202,193 @@ impl<'a> TracingInfo<'a> {
     pub fn id(&self) -> host::Result<Option<String>> {
         self.mapper.string_property(TRACING_ID_PATH)
     }
+
+    /// Whether the debug records of the request are captured, see [`crate::log::capture`].
+    pub fn debug_capture(&self) -> bool {
+        self.mapper.property_accessor.read_property(DEBUG_CAPTURE_PATH).is_some()
+    }
+
+    pub(crate) fn set_debug_capture(&self) {
+        self.mapper.set_bytes_property(DEBUG_CAPTURE_PATH, b"true")
+    }
+}
+
+/// The filter state key marking a sticky exchange, see [`FilterState::set_sticky`].
+pub const STICKY: &str = "exchange.sticky";
+
+/// The filter state key of the class of the request, see [`FilterState::set_request_class`].
+pub const REQUEST_CLASS: &str = "exchange.request_class";
+
+/// The filter state key collecting the time of the policies, see
+/// [`FilterState::add_server_timing`].
+pub const SERVER_TIMING: &str = "exchange.server_timing";
+
+/// The filter state key collecting the decisions of the policies, see
+/// [`FilterState::add_policy_decision`].
+pub const POLICY_DECISIONS: &str = pdk_decisions::POLICY_DECISIONS;
+
+/// The filter state object of the proxy overriding the SNI of the upstream connection.
+pub const UPSTREAM_SERVER_NAME: &str = "envoy.network.upstream_server_name";
+
+/// The filter state object of the proxy overriding the names the certificate of the upstream
+/// is validated against, comma separated.
+pub const UPSTREAM_SUBJECT_ALT_NAMES: &str = "envoy.network.upstream_subject_alt_names";
+
+/// Typed access to the Envoy filter state of the current stream.
+///
+/// Values written here are visible to subsequent native filters and to access logs
+/// through `%FILTER_STATE(wasm.<key>)%`.
+pub struct FilterState<'a> {
+    mapper: PropertyMapper<'a>,
+}
+
+impl<'a> FilterState<'a> {
+    pub fn set_bytes(&self, key: &str, value: &[u8]) {
+        self.mapper.set_bytes_property(&[key], value)
+    }
+
+    pub fn set_string(&self, key: &str, value: &str) {
+        self.set_bytes(key, value.as_bytes())
+    }
+
+    pub fn set_bool(&self, key: &str, value: bool) {
+        self.set_string(key, if value { "true" } else { "false" })
+    }
+
+    pub fn set_number(&self, key: &str, value: f64) {
+        self.set_string(key, &value.to_string())
+    }
+
+    pub fn string(&self, key: &str) -> host::Result<Option<String>> {
+        self.mapper.string_property(&[key])
+    }
+
+    pub fn bool(&self, key: &str) -> host::Result<Option<bool>> {
+        self.string(key)?
+            .map(|value| {
+                value
+                    .parse::<bool>()
+                    .map_err(|e| format_err!("Filter state {:?} is not a bool: {:?}", key, e))
+            })
+            .transpose()
+    }
+
+    pub fn number(&self, key: &str) -> host::Result<Option<f64>> {
+        self.string(key)?
+            .map(|value| {
+                value
+                    .parse::<f64>()
+                    .map_err(|e| format_err!("Filter state {:?} is not a number: {:?}", key, e))
+            })
+            .transpose()
+    }
+
+    /// Marks the exchange as bound to its upstream connection, for the `reason`. Policies
+    /// retrying, mirroring or splitting the traffic leave sticky exchanges alone, as a
+    /// connection oriented handshake like NTLM breaks when its legs reach different upstreams.
+    pub fn set_sticky(&self, reason: &str) {
+        self.set_string(STICKY, reason)
+    }
+
+    /// The reason the exchange is sticky, `None` when it is not.
+    pub fn sticky(&self) -> Option<String> {
+        self.string(STICKY).ok().flatten()
+    }
+
+    /// Keeps the class of the request for the next policies, see
+    /// [`ClassifyRequest`](crate::http::request_class::ClassifyRequest).
+    pub fn set_request_class(&self, class: RequestClass) {
+        self.set_string(REQUEST_CLASS, class.name())
+    }
+
+    /// The class of the request kept by a previous policy.
+    pub fn request_class(&self) -> Option<RequestClass> {
+        self.string(REQUEST_CLASS)
+            .ok()
+            .flatten()
+            .and_then(|name| RequestClass::from_name(&name))
+    }
+
+    /// Records the time a policy spent on the exchange, for the policy emitting the
+    /// `Server-Timing` header. Metrics are kept in the order they are added.
+    pub fn add_server_timing(&self, metric: Metric) {
+        let mut timing = self.server_timing();
+        timing.add(metric);
+        self.set_string(SERVER_TIMING, &timing.to_string())
+    }
+
+    /// The time recorded by the policies so far.
+    pub fn server_timing(&self) -> ServerTiming {
+        self.string(SERVER_TIMING)
+            .ok()
+            .flatten()
+            .map(|value| ServerTiming::parse(&value))
+            .unwrap_or_default()
+    }
+
+    /// Records the decision a policy took on the exchange, for the access log. Decisions are
+    /// kept in the order they are added.
+    pub fn add_policy_decision(&self, decision: Decision) {
+        let mut decisions = self.policy_decisions();
+        decisions.add(decision);
+        self.set_string(POLICY_DECISIONS, &decisions.to_json())
+    }
+
+    /// The decisions recorded by the policies so far.
+    pub fn policy_decisions(&self) -> PolicyDecisions {
+        self.string(POLICY_DECISIONS)
+            .ok()
+            .flatten()
+            .map(|value| PolicyDecisions::parse(&value))
+            .unwrap_or_default()
+    }
+
+    /// Sends `name` as the SNI of the TLS connection to the upstream. The proxy stores keys
+    /// of its own objects as those objects instead of `wasm.<key>`, so the TLS transport
+    /// socket of the cluster reads it. Connections are pooled by SNI.
+    pub fn set_upstream_server_name(&self, name: &str) {
+        self.set_string(UPSTREAM_SERVER_NAME, name)
+    }
+
+    pub fn upstream_server_name(&self) -> Option<String> {
+        self.string(UPSTREAM_SERVER_NAME).ok().flatten()
+    }
+
+    /// Validates the certificate of the upstream against `names` instead of the subject alt
+    /// names of the cluster, usually the same as the SNI.
+    pub fn set_upstream_subject_alt_names(&self, names: &[&str]) {
+        self.set_string(UPSTREAM_SUBJECT_ALT_NAMES, &names.join(","))
+    }
+
+    pub fn upstream_subject_alt_names(&self) -> Vec<String> {
+        self.string(UPSTREAM_SUBJECT_ALT_NAMES)
+            .ok()
+            .flatten()
+            .map(|names| {
+                names
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Records the decision a policy took on the exchange in the filter state of the host, as
+/// [`FilterState::add_policy_decision`] does.
+pub fn record_decision(decision: Decision) {
+    <dyn PropertyAccessor>::default()
+        .filter_state()
+        .add_policy_decision(decision)
+}
+
+/// The `Retry-After` of a request rejected for `retry_after`, in whole seconds rounded up, at
+/// least one, as recorded in the `retryAfter` detail of the decisions.
+pub fn retry_after_seconds(retry_after: Duration) -> u64 {
+    let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    seconds.max(1)
 }
 
 impl<C> FromContext<C> for &'static dyn PropertyAccessor {
@@ -142,3 +416,149 @@ mod impls {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{retry_after_seconds, InMemoryPropertyAccessor, PropertyAccessor};
+    use crate::http::server_timing::Metric;
+    use crate::policy_context::decisions::{Decision, Outcome};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn filter_state_round_trip() {
+        let accessor = InMemoryPropertyAccessor::new();
+        let properties: &dyn PropertyAccessor = &accessor;
+
+        properties.filter_state().set_string("descriptor", "gold");
+        properties.filter_state().set_bool("cached", true);
+        properties.filter_state().set_number("latency", 12.5);
+
+        let filter_state = properties.filter_state();
+        assert_eq!(filter_state.string("descriptor").unwrap().as_deref(), Some("gold"));
+        assert_eq!(filter_state.bool("cached").unwrap(), Some(true));
+        assert_eq!(filter_state.number("latency").unwrap(), Some(12.5));
+        assert_eq!(filter_state.string("missing").unwrap(), None);
+        assert_eq!(accessor.read_property(&["descriptor"]), Some(b"gold".to_vec()));
+    }
+
+    #[test]
+    fn sticky_exchange() {
+        let properties: &dyn PropertyAccessor = &InMemoryPropertyAccessor::new();
+
+        assert_eq!(properties.filter_state().sticky(), None);
+
+        properties.filter_state().set_sticky("ntlm");
+        assert_eq!(properties.filter_state().sticky().as_deref(), Some("ntlm"));
+    }
+
+    #[test]
+    fn server_timing_of_policies() {
+        let properties: &dyn PropertyAccessor = &InMemoryPropertyAccessor::new();
+
+        assert!(properties.filter_state().server_timing().is_empty());
+
+        let filter_state = properties.filter_state();
+        filter_state.add_server_timing(Metric::new("auth", Duration::from_millis(3)));
+        filter_state.add_server_timing(Metric::new("quota", Duration::from_micros(1500)));
+        assert_eq!(
+            filter_state.server_timing().to_string(),
+            "auth;dur=3, quota;dur=1.5"
+        );
+    }
+
+    #[test]
+    fn policy_decisions_of_the_chain() {
+        let properties: &dyn PropertyAccessor = &InMemoryPropertyAccessor::new();
+
+        let filter_state = properties.filter_state();
+        filter_state.add_policy_decision(Decision::new("jwt", "authentication", Outcome::Allow));
+        filter_state.add_policy_decision(
+            Decision::new("size", "size", Outcome::Deny).with_detail("maxKb", 64),
+        );
+
+        let decisions = filter_state.policy_decisions();
+        assert_eq!(decisions.decisions().len(), 2);
+        assert_eq!(decisions.decisions()[1].outcome(), Outcome::Deny);
+        assert!(decisions.denied());
+    }
+
+    #[test]
+    fn retry_after_is_rounded_up_to_a_second() {
+        assert_eq!(retry_after_seconds(Duration::ZERO), 1);
+        assert_eq!(retry_after_seconds(Duration::from_millis(1)), 1);
+        assert_eq!(retry_after_seconds(Duration::from_secs(2)), 2);
+        assert_eq!(retry_after_seconds(Duration::from_millis(2001)), 3);
+    }
+
+    #[test]
+    fn upstream_tls_names() {
+        let accessor = InMemoryPropertyAccessor::new();
+        let properties: &dyn PropertyAccessor = &accessor;
+
+        let filter_state = properties.filter_state();
+        assert_eq!(filter_state.upstream_server_name(), None);
+        assert!(filter_state.upstream_subject_alt_names().is_empty());
+
+        filter_state.set_upstream_server_name("bucket.s3.amazonaws.com");
+        filter_state
+            .set_upstream_subject_alt_names(&["bucket.s3.amazonaws.com", "*.s3.amazonaws.com"]);
+        assert_eq!(
+            accessor.read_property(&["envoy.network.upstream_server_name"]),
+            Some(b"bucket.s3.amazonaws.com".to_vec())
+        );
+        assert_eq!(
+            filter_state.upstream_subject_alt_names(),
+            ["bucket.s3.amazonaws.com", "*.s3.amazonaws.com"]
+        );
+    }
+
+    #[test]
+    fn tls_connection_info() {
+        let accessor = InMemoryPropertyAccessor::new()
+            .with_property(&["connection", "tls_version"], b"TLSv1.3")
+            .with_property(&["connection", "mtls"], &[1])
+            .with_property(&["connection", "subject_peer_certificate"], b"CN=orders,O=Acme")
+            .with_property(&["connection", "uri_san_peer_certificate"], b"spiffe://acme/orders")
+            .with_property(&["connection", "dns_san_peer_certificate"], b"");
+        let properties: &dyn PropertyAccessor = &accessor;
+
+        let tls = properties.tls();
+        assert_eq!(tls.tls_version().unwrap().as_deref(), Some("TLSv1.3"));
+        assert!(tls.mtls());
+        assert_eq!(
+            tls.subject_peer_certificate().unwrap().as_deref(),
+            Some("CN=orders,O=Acme")
+        );
+        assert_eq!(
+            tls.uri_san_peer_certificate().unwrap().as_deref(),
+            Some("spiffe://acme/orders")
+        );
+        assert_eq!(tls.dns_san_peer_certificate().unwrap(), None);
+
+        let plain: &dyn PropertyAccessor = &InMemoryPropertyAccessor::new();
+        assert_eq!(plain.tls().tls_version().unwrap(), None);
+        assert!(!plain.tls().mtls());
+    }
+
+    #[test]
+    fn request_time() {
+        let accessor = InMemoryPropertyAccessor::new();
+        let properties: &dyn PropertyAccessor = &accessor;
+        accessor.set_property(&["request", "time"], &1_500_000_000u64.to_le_bytes());
+
+        assert_eq!(
+            properties.request().time().unwrap(),
+            Some(UNIX_EPOCH + Duration::from_millis(1500))
+        );
+    }
+
+    #[test]
+    fn filter_state_invalid_type() {
+        let properties: &dyn PropertyAccessor = &InMemoryPropertyAccessor::new();
+
+        properties.filter_state().set_string("cached", "maybe");
+
+        assert!(properties.filter_state().bool("cached").is_err());
+        assert!(properties.filter_state().number("cached").is_err());
+    }
+}
@@ -1,7 +1,15 @@
 // Copyright 2023 Salesforce, Inc. All rights reserved.
 pub const TRACING_ID_PATH: &[&str] = &["anypoint/mulesoft/tracing_id"];
+pub const DEBUG_CAPTURE_PATH: &[&str] = &["anypoint/mulesoft/debug_capture"];
 pub const SOURCE_ADDRESS: &[&str] = &["source", "address"];
 pub const DESTINATION_ADDRESS: &[&str] = &["destination", "address"];
 pub const REQUEST_SCHEME: &[&str] = &["request", "scheme"];
 pub const REQUEST_PROTOCOL: &[&str] = &["request", "protocol"];
 pub const REQUEST_ID: &[&str] = &["request", "id"];
+pub const REQUEST_TIME: &[&str] = &["request", "time"];
+pub const CONNECTION_TLS_VERSION: &[&str] = &["connection", "tls_version"];
+pub const CONNECTION_REQUESTED_SERVER_NAME: &[&str] = &["connection", "requested_server_name"];
+pub const CONNECTION_MTLS: &[&str] = &["connection", "mtls"];
+pub const CONNECTION_SUBJECT_PEER_CERTIFICATE: &[&str] = &["connection", "subject_peer_certificate"];
+pub const CONNECTION_URI_SAN_PEER_CERTIFICATE: &[&str] = &["connection", "uri_san_peer_certificate"];
+pub const CONNECTION_DNS_SAN_PEER_CERTIFICATE: &[&str] = &["connection", "dns_san_peer_certificate"];
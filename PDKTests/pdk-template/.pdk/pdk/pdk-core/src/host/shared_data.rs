@@ -0,0 +1,46 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use classy::extract::FromContext;
+use classy::proxy_wasm::types::Bytes;
+use std::convert::Infallible;
+
+/// Access to the data shared among all the workers of the proxy.
+pub trait SharedDataAccessor {
+    /// Returns the value stored for `key`, if any, along with its compare-and-swap token.
+    fn read_shared_data(&self, key: &str) -> (Option<Bytes>, Option<u32>);
+
+    /// Stores `value` for `key`. When `cas` is given, the write only succeeds if the stored
+    /// value was not modified since it was read. Returns whether the value was written.
+    fn write_shared_data(&self, key: &str, value: &[u8], cas: Option<u32>) -> bool;
+}
+
+impl dyn SharedDataAccessor {
+    pub fn default() -> &'static dyn SharedDataAccessor {
+        &impls::Host
+    }
+}
+
+impl<C> FromContext<C> for &'static dyn SharedDataAccessor {
+    type Error = Infallible;
+
+    fn from_context(_: &C) -> Result<Self, Self::Error> {
+        Ok(<dyn SharedDataAccessor>::default())
+    }
+}
+
+mod impls {
+    use super::SharedDataAccessor;
+    use classy::proxy_wasm::types::Bytes;
+    use classy::Host as ClassyHost;
+
+    pub(super) struct Host;
+
+    impl SharedDataAccessor for Host {
+        fn read_shared_data(&self, key: &str) -> (Option<Bytes>, Option<u32>) {
+            crate::Host.get_shared_data(key)
+        }
+
+        fn write_shared_data(&self, key: &str, value: &[u8], cas: Option<u32>) -> bool {
+            crate::Host.set_shared_data(key, Some(value), cas).is_ok()
+        }
+    }
+}
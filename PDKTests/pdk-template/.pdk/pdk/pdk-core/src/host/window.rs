@@ -0,0 +1,357 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Time-bucketed sliding window counters, kept in local memory or in shared data.
+use std::convert::TryInto;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::host::shared_data::SharedDataAccessor;
+
+const MAX_CAS_ATTEMPTS: usize = 8;
+
+/// Counts events over the last `window`, split into fixed-width buckets.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SlidingWindowCounter {
+    bucket_width: u64,
+    head: u64,
+    counts: Vec<u64>,
+}
+
+impl SlidingWindowCounter {
+    /// Creates a counter covering `window` with `buckets` buckets.
+    pub fn new(window: Duration, buckets: usize) -> Self {
+        let buckets = buckets.max(1);
+        let bucket_width = (window.as_millis() as u64 / buckets as u64).max(1);
+        Self {
+            bucket_width,
+            head: 0,
+            counts: vec![0; buckets],
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        Duration::from_millis(self.bucket_width * self.counts.len() as u64)
+    }
+
+    pub fn buckets(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// Records a single event.
+    pub fn increment(&mut self, now: SystemTime) -> u64 {
+        self.add(1, now)
+    }
+
+    /// Records `amount` events and returns the updated count for the window.
+    pub fn add(&mut self, amount: u64, now: SystemTime) -> u64 {
+        let bucket = self.bucket_of(now);
+        self.advance(bucket);
+        if self.in_window(bucket) {
+            let index = self.index(bucket);
+            self.counts[index] = self.counts[index].saturating_add(amount);
+        }
+        self.count(now)
+    }
+
+    /// Returns the number of events recorded in the window ending at `now`.
+    pub fn count(&self, now: SystemTime) -> u64 {
+        let current = self.bucket_of(now);
+        let len = self.counts.len() as u64;
+        let from = current
+            .saturating_sub(len - 1)
+            .max(self.head.saturating_sub(len - 1));
+        let to = current.min(self.head);
+
+        (from..=to)
+            .map(|bucket| self.counts[self.index(bucket)])
+            .sum()
+    }
+
+    /// Returns the time until the oldest bucket holding events leaves the window.
+    pub fn reset_after(&self, now: SystemTime) -> Duration {
+        let current = self.bucket_of(now);
+        let len = self.counts.len() as u64;
+        let oldest = (current.saturating_sub(len - 1)..=current.min(self.head))
+            .find(|&bucket| self.counts[self.index(bucket)] > 0);
+
+        match oldest {
+            None => Duration::ZERO,
+            Some(bucket) => {
+                let expires_at = (bucket + len) * self.bucket_width;
+                Duration::from_millis(expires_at.saturating_sub(millis(now)))
+            }
+        }
+    }
+
+    /// Adds the events recorded by `other` to this counter. Counters with a different
+    /// layout are not merged.
+    pub fn merge(&mut self, other: &SlidingWindowCounter) -> bool {
+        if self.bucket_width != other.bucket_width || self.counts.len() != other.counts.len() {
+            return false;
+        }
+
+        self.advance(other.head);
+        let len = other.counts.len() as u64;
+        for bucket in other.head.saturating_sub(len - 1)..=other.head {
+            if self.in_window(bucket) {
+                let index = self.index(bucket);
+                self.counts[index] = self.counts[index].saturating_add(other.counts[index]);
+            }
+        }
+        true
+    }
+
+    /// Serializes the counter to be stored as shared data.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(16 + 8 * self.counts.len());
+        bytes.extend_from_slice(&self.bucket_width.to_le_bytes());
+        bytes.extend_from_slice(&self.head.to_le_bytes());
+        self.counts
+            .iter()
+            .for_each(|count| bytes.extend_from_slice(&count.to_le_bytes()));
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 24 || bytes.len() % 8 != 0 {
+            return None;
+        }
+
+        let mut words = bytes
+            .chunks_exact(8)
+            .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap_or_default()));
+
+        let bucket_width = words.next().filter(|width| *width > 0)?;
+        let head = words.next()?;
+        Some(Self {
+            bucket_width,
+            head,
+            counts: words.collect(),
+        })
+    }
+
+    fn bucket_of(&self, now: SystemTime) -> u64 {
+        millis(now) / self.bucket_width
+    }
+
+    fn index(&self, bucket: u64) -> usize {
+        (bucket % self.counts.len() as u64) as usize
+    }
+
+    fn in_window(&self, bucket: u64) -> bool {
+        bucket <= self.head && bucket + (self.counts.len() as u64) > self.head
+    }
+
+    fn advance(&mut self, bucket: u64) {
+        if bucket <= self.head {
+            return;
+        }
+
+        let len = self.counts.len() as u64;
+        let stale = (bucket - self.head).min(len);
+        for offset in 1..=stale {
+            let index = self.index(self.head + offset);
+            self.counts[index] = 0;
+        }
+        self.head = bucket;
+    }
+}
+
+/// A [`SlidingWindowCounter`] stored in shared data, so all the workers contribute to it.
+pub struct SharedSlidingWindowCounter<'a> {
+    accessor: &'a dyn SharedDataAccessor,
+    key: String,
+    window: Duration,
+    buckets: usize,
+}
+
+impl<'a> SharedSlidingWindowCounter<'a> {
+    pub fn new(
+        accessor: &'a dyn SharedDataAccessor,
+        key: impl Into<String>,
+        window: Duration,
+        buckets: usize,
+    ) -> Self {
+        Self {
+            accessor,
+            key: key.into(),
+            window,
+            buckets,
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Returns the locally decoded counter as currently stored.
+    pub fn snapshot(&self) -> SlidingWindowCounter {
+        self.accessor
+            .read_shared_data(&self.key)
+            .0
+            .and_then(|bytes| SlidingWindowCounter::from_bytes(&bytes))
+            .unwrap_or_else(|| SlidingWindowCounter::new(self.window, self.buckets))
+    }
+
+    pub fn count(&self, now: SystemTime) -> u64 {
+        self.snapshot().count(now)
+    }
+
+    /// Records `amount` events and returns the updated count, or `None` when the shared
+    /// value kept changing concurrently and could not be updated.
+    pub fn add(&self, amount: u64, now: SystemTime) -> Option<u64> {
+        self.update(|counter| counter.add(amount, now))
+    }
+
+    /// Merges a locally accumulated counter into the shared one.
+    pub fn merge(&self, local: &SlidingWindowCounter, now: SystemTime) -> Option<u64> {
+        self.update(|counter| {
+            counter.merge(local);
+            counter.count(now)
+        })
+    }
+
+    fn update(&self, operation: impl Fn(&mut SlidingWindowCounter) -> u64) -> Option<u64> {
+        for _ in 0..MAX_CAS_ATTEMPTS {
+            let (bytes, cas) = self.accessor.read_shared_data(&self.key);
+            let mut counter = bytes
+                .and_then(|bytes| SlidingWindowCounter::from_bytes(&bytes))
+                .unwrap_or_else(|| SlidingWindowCounter::new(self.window, self.buckets));
+
+            let count = operation(&mut counter);
+
+            if self
+                .accessor
+                .write_shared_data(&self.key, &counter.to_bytes(), cas)
+            {
+                return Some(count);
+            }
+        }
+        None
+    }
+}
+
+fn millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use super::{SharedSlidingWindowCounter, SlidingWindowCounter};
+    use crate::host::shared_data::SharedDataAccessor;
+
+    fn at(millis: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(millis)
+    }
+
+    fn counter() -> SlidingWindowCounter {
+        SlidingWindowCounter::new(Duration::from_secs(10), 10)
+    }
+
+    #[test]
+    fn counts_within_window() {
+        let mut counter = counter();
+        counter.increment(at(0));
+        counter.add(2, at(4_000));
+
+        assert_eq!(counter.count(at(4_000)), 3);
+        assert_eq!(counter.count(at(9_999)), 3);
+        assert_eq!(counter.count(at(10_000)), 2);
+        assert_eq!(counter.count(at(14_000)), 0);
+    }
+
+    #[test]
+    fn stale_buckets_are_cleared() {
+        let mut counter = counter();
+        counter.add(5, at(1_000));
+
+        assert_eq!(counter.add(1, at(11_000)), 1);
+        assert_eq!(counter.add(1, at(100_000)), 1);
+    }
+
+    #[test]
+    fn reset_after_oldest_bucket() {
+        let mut counter = counter();
+        assert_eq!(counter.reset_after(at(0)), Duration::ZERO);
+
+        counter.add(1, at(2_500));
+        counter.add(1, at(5_000));
+
+        assert_eq!(counter.reset_after(at(6_000)), Duration::from_secs(6));
+    }
+
+    #[test]
+    fn merge_aligns_buckets() {
+        let mut first = counter();
+        let mut second = counter();
+        first.add(1, at(1_000));
+        second.add(2, at(8_000));
+
+        assert!(first.merge(&second));
+        assert_eq!(first.count(at(8_000)), 3);
+        assert_eq!(first.count(at(11_000)), 2);
+    }
+
+    #[test]
+    fn merge_rejects_different_layouts() {
+        let mut first = counter();
+        let second = SlidingWindowCounter::new(Duration::from_secs(60), 6);
+
+        assert!(!first.merge(&second));
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let mut counter = counter();
+        counter.add(3, at(7_000));
+
+        let decoded = SlidingWindowCounter::from_bytes(&counter.to_bytes()).unwrap();
+
+        assert_eq!(decoded, counter);
+        assert_eq!(SlidingWindowCounter::from_bytes(&[1, 2, 3]), None);
+    }
+
+    #[derive(Default)]
+    struct MockSharedData {
+        data: RefCell<HashMap<String, (Vec<u8>, u32)>>,
+    }
+
+    impl SharedDataAccessor for MockSharedData {
+        fn read_shared_data(&self, key: &str) -> (Option<Vec<u8>>, Option<u32>) {
+            match self.data.borrow().get(key) {
+                Some((value, cas)) => (Some(value.clone()), Some(*cas)),
+                None => (None, None),
+            }
+        }
+
+        fn write_shared_data(&self, key: &str, value: &[u8], cas: Option<u32>) -> bool {
+            let mut data = self.data.borrow_mut();
+            let current = data.get(key).map(|(_, cas)| *cas);
+            if cas.is_some() && cas != current {
+                return false;
+            }
+            let next = current.unwrap_or_default() + 1;
+            data.insert(key.to_string(), (value.to_vec(), next));
+            true
+        }
+    }
+
+    #[test]
+    fn shared_counter_accumulates() {
+        let shared_data = MockSharedData::default();
+        let counter =
+            SharedSlidingWindowCounter::new(&shared_data, "requests", Duration::from_secs(10), 10);
+
+        assert_eq!(counter.add(1, at(0)), Some(1));
+        assert_eq!(counter.add(2, at(1_000)), Some(3));
+        assert_eq!(counter.count(at(10_500)), 2);
+
+        let mut local = SlidingWindowCounter::new(Duration::from_secs(10), 10);
+        local.add(4, at(10_500));
+        assert_eq!(counter.merge(&local, at(10_500)), Some(6));
+    }
+}
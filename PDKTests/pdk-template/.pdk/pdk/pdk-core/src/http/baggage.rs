@@ -0,0 +1,274 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! The W3C `baggage` header, the key-value pairs propagated along a trace, like
+//! `tenant=acme,userTier=gold;ttl=60`.
+//!
+//! Members that are not valid are dropped when parsing. Values are percent-decoded, and
+//! encoded again when the header is written.
+use std::fmt::{self, Display, Formatter, Write};
+
+use serde::Deserialize;
+
+use super::path::percent_decode;
+
+pub const BAGGAGE: &str = "baggage";
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum BaggageError {
+    #[error("Invalid baggage key '{0}'")]
+    InvalidKey(String),
+}
+
+/// The limits of the propagated baggage, the minimum every platform must support by default.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BaggageLimits {
+    #[serde(default = "default_max_entries")]
+    pub max_entries: usize,
+    #[serde(default = "default_max_bytes")]
+    pub max_bytes: usize,
+}
+
+fn default_max_entries() -> usize {
+    64
+}
+
+fn default_max_bytes() -> usize {
+    8192
+}
+
+impl Default for BaggageLimits {
+    fn default() -> Self {
+        Self {
+            max_entries: default_max_entries(),
+            max_bytes: default_max_bytes(),
+        }
+    }
+}
+
+/// A member of the baggage.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BaggageEntry {
+    key: String,
+    value: String,
+    // The metadata after the value, like `;ttl=60`, as it was sent.
+    properties: String,
+}
+
+impl BaggageEntry {
+    fn parse(member: &str) -> Option<Self> {
+        let (pair, properties) = match member.find(';') {
+            Some(index) => member.split_at(index),
+            None => (member, ""),
+        };
+        let (key, value) = pair.split_once('=')?;
+        let (key, value) = (key.trim(), value.trim());
+        if !is_key(key) || !value.chars().all(is_value_char) {
+            return None;
+        }
+
+        Some(Self {
+            key: key.to_string(),
+            value: percent_decode(value)?,
+            properties: properties.trim_end().to_string(),
+        })
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+}
+
+impl Display for BaggageEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}=", self.key)?;
+        for c in self.value.chars() {
+            if is_value_char(c) && c != '%' {
+                f.write_char(c)?;
+            } else {
+                let mut bytes = [0; 4];
+                for byte in c.encode_utf8(&mut bytes).bytes() {
+                    write!(f, "%{:02X}", byte)?;
+                }
+            }
+        }
+        f.write_str(&self.properties)
+    }
+}
+
+fn is_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c))
+}
+
+/// The baggage octets, which exclude controls, whitespace, `"`, `,`, `;` and `\`.
+fn is_value_char(c: char) -> bool {
+    c.is_ascii_graphic() && !matches!(c, '"' | ',' | ';' | '\\')
+}
+
+/// The members of the `baggage` headers of a request, in order.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Baggage {
+    entries: Vec<BaggageEntry>,
+    invalid: usize,
+}
+
+impl Baggage {
+    /// Parses the values of the `baggage` headers, skipping the members that are not valid.
+    pub fn parse<'a>(values: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut baggage = Self::default();
+        let members = values
+            .into_iter()
+            .flat_map(|value| value.split(','))
+            .filter(|member| !member.trim().is_empty());
+        for member in members {
+            match BaggageEntry::parse(member) {
+                Some(entry) => baggage.entries.push(entry),
+                None => baggage.invalid += 1,
+            }
+        }
+        baggage
+    }
+
+    /// How many members were dropped for not being valid.
+    pub fn invalid(&self) -> usize {
+        self.invalid
+    }
+
+    pub fn entries(&self) -> &[BaggageEntry] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The value of the first member with the `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|entry| entry.key == key)
+            .map(BaggageEntry::value)
+    }
+
+    /// Sets the member with the `key`, replacing the ones already present. Members set here
+    /// go first, so [`enforce`](Self::enforce) drops the received ones before them.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), BaggageError> {
+        if !is_key(key) {
+            return Err(BaggageError::InvalidKey(key.to_string()));
+        }
+        self.remove(key);
+        self.entries.insert(
+            0,
+            BaggageEntry {
+                key: key.to_string(),
+                value: value.to_string(),
+                properties: String::new(),
+            },
+        );
+        Ok(())
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.entries.retain(|entry| entry.key != key);
+    }
+
+    /// Drops the last members until the baggage fits in the `limits`. Returns how many were
+    /// dropped.
+    pub fn enforce(&mut self, limits: &BaggageLimits) -> usize {
+        let mut bytes = 0;
+        let mut kept = 0;
+        for entry in &self.entries {
+            // The members are joined by a comma.
+            let size = entry.to_string().len() + usize::from(kept > 0);
+            if kept == limits.max_entries || bytes + size > limits.max_bytes {
+                break;
+            }
+            bytes += size;
+            kept += 1;
+        }
+
+        let dropped = self.entries.len() - kept;
+        self.entries.truncate(kept);
+        dropped
+    }
+}
+
+impl Display for Baggage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (index, entry) in self.entries.iter().enumerate() {
+            if index > 0 {
+                f.write_char(',')?;
+            }
+            write!(f, "{}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Baggage, BaggageError, BaggageLimits};
+
+    #[test]
+    fn parse_members() {
+        let baggage = Baggage::parse(vec![
+            "tenant=acme, user name=x, city=S%C3%A3o%20Paulo;ttl=60",
+            "bad value=a b,=empty,,tier=gold",
+        ]);
+
+        assert_eq!(baggage.len(), 3);
+        assert_eq!(baggage.invalid(), 3);
+        assert_eq!(baggage.get("tenant"), Some("acme"));
+        assert_eq!(baggage.get("city"), Some("São Paulo"));
+        assert_eq!(
+            baggage.to_string(),
+            "tenant=acme,city=S%C3%A3o%20Paulo;ttl=60,tier=gold"
+        );
+    }
+
+    #[test]
+    fn set_entries_first() {
+        let mut baggage = Baggage::parse(vec!["tenant=spoofed,tier=gold"]);
+        baggage.set("tenant", "acme").unwrap();
+        baggage.set("api.id", "a,b;c").unwrap();
+
+        assert_eq!(
+            baggage.to_string(),
+            "api.id=a%2Cb%3Bc,tenant=acme,tier=gold"
+        );
+        assert_eq!(
+            baggage.set("bad key", "x"),
+            Err(BaggageError::InvalidKey("bad key".to_string()))
+        );
+    }
+
+    #[test]
+    fn enforce_limits() {
+        let mut baggage = Baggage::parse(vec!["a=1,b=2,c=3,d=4"]);
+
+        let count = BaggageLimits {
+            max_entries: 3,
+            max_bytes: 8192,
+        };
+        assert_eq!(baggage.enforce(&count), 1);
+        assert_eq!(baggage.to_string(), "a=1,b=2,c=3");
+
+        // "a=1,b=2" is 7 bytes.
+        let size = BaggageLimits {
+            max_entries: 64,
+            max_bytes: 8,
+        };
+        assert_eq!(baggage.enforce(&size), 1);
+        assert_eq!(baggage.to_string(), "a=1,b=2");
+    }
+}
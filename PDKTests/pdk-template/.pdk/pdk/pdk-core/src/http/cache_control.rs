@@ -0,0 +1,311 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Typed representations of the `Cache-Control` and `Vary` headers.
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+
+use super::header::{split_list, unquote};
+
+/// The directives of a `Cache-Control` header. Directives not modeled by a field are kept
+/// in `extensions`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CacheControl {
+    pub no_cache: bool,
+    pub no_store: bool,
+    pub no_transform: bool,
+    pub must_revalidate: bool,
+    pub proxy_revalidate: bool,
+    pub public: bool,
+    pub private: bool,
+    pub immutable: bool,
+    pub only_if_cached: bool,
+    pub max_age: Option<Duration>,
+    pub s_maxage: Option<Duration>,
+    pub max_stale: Option<Duration>,
+    pub min_fresh: Option<Duration>,
+    pub stale_while_revalidate: Option<Duration>,
+    pub stale_if_error: Option<Duration>,
+    pub extensions: Vec<(String, Option<String>)>,
+}
+
+impl CacheControl {
+    /// Parses a `Cache-Control` header value. Directive names are case insensitive, and
+    /// when a directive is repeated its first occurrence wins.
+    pub fn parse(value: &str) -> Self {
+        let mut cache_control = Self::default();
+        let mut seen = Vec::new();
+
+        for directive in split_list(value) {
+            let (name, argument) = match directive.split_once('=') {
+                Some((name, argument)) => (name.trim(), Some(unquote(argument))),
+                None => (directive, None),
+            };
+            let name = name.to_ascii_lowercase();
+            if seen.contains(&name) {
+                continue;
+            }
+            seen.push(name.clone());
+            cache_control.apply(name, argument);
+        }
+
+        cache_control
+    }
+
+    fn apply(&mut self, name: String, argument: Option<String>) {
+        let seconds = || {
+            argument
+                .as_deref()
+                .and_then(|seconds| seconds.parse::<u64>().ok())
+                .map(Duration::from_secs)
+        };
+
+        match name.as_str() {
+            "no-cache" => self.no_cache = true,
+            "no-store" => self.no_store = true,
+            "no-transform" => self.no_transform = true,
+            "must-revalidate" => self.must_revalidate = true,
+            "proxy-revalidate" => self.proxy_revalidate = true,
+            "public" => self.public = true,
+            "private" => self.private = true,
+            "immutable" => self.immutable = true,
+            "only-if-cached" => self.only_if_cached = true,
+            "max-age" => self.max_age = seconds(),
+            "s-maxage" => self.s_maxage = seconds(),
+            // A max-stale without argument accepts a response of any staleness.
+            "max-stale" => self.max_stale = seconds().or(Some(Duration::MAX)),
+            "min-fresh" => self.min_fresh = seconds(),
+            "stale-while-revalidate" => self.stale_while_revalidate = seconds(),
+            "stale-if-error" => self.stale_if_error = seconds(),
+            _ => self.extensions.push((name, argument)),
+        }
+    }
+
+    /// Returns the time a response can be served from a cache without revalidation.
+    pub fn freshness(&self, shared: bool) -> Option<Duration> {
+        if shared {
+            self.s_maxage.or(self.max_age)
+        } else {
+            self.max_age
+        }
+    }
+
+    /// Returns whether a response with these directives may be stored by a private or a
+    /// shared cache.
+    pub fn is_storable(&self, shared: bool) -> bool {
+        !(self.no_store || shared && self.private)
+    }
+}
+
+impl Display for CacheControl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let flags = [
+            (self.public, "public"),
+            (self.private, "private"),
+            (self.no_cache, "no-cache"),
+            (self.no_store, "no-store"),
+            (self.no_transform, "no-transform"),
+            (self.must_revalidate, "must-revalidate"),
+            (self.proxy_revalidate, "proxy-revalidate"),
+            (self.immutable, "immutable"),
+            (self.only_if_cached, "only-if-cached"),
+        ];
+        let durations = [
+            (self.max_age, "max-age"),
+            (self.s_maxage, "s-maxage"),
+            (self.max_stale, "max-stale"),
+            (self.min_fresh, "min-fresh"),
+            (self.stale_while_revalidate, "stale-while-revalidate"),
+            (self.stale_if_error, "stale-if-error"),
+        ];
+
+        let mut directives = flags
+            .iter()
+            .filter(|(present, _)| *present)
+            .map(|(_, name)| name.to_string())
+            .collect::<Vec<_>>();
+
+        for (duration, name) in durations {
+            match duration {
+                Some(Duration::MAX) => directives.push(name.to_string()),
+                Some(duration) => directives.push(format!("{name}={}", duration.as_secs())),
+                None => {}
+            }
+        }
+
+        for (name, argument) in &self.extensions {
+            match argument {
+                Some(argument) if needs_quotes(argument) => {
+                    directives.push(format!("{name}=\"{}\"", argument.replace('"', "\\\"")))
+                }
+                Some(argument) => directives.push(format!("{name}={argument}")),
+                None => directives.push(name.clone()),
+            }
+        }
+
+        f.write_str(&directives.join(", "))
+    }
+}
+
+fn needs_quotes(argument: &str) -> bool {
+    argument.is_empty()
+        || argument
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, ',' | ';' | '"' | '=' | '\\'))
+}
+
+/// The `Vary` header: either every request header (`*`) or a set of header names.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Vary {
+    Any,
+    Headers(Vec<String>),
+}
+
+impl Default for Vary {
+    fn default() -> Self {
+        Vary::Headers(Vec::new())
+    }
+}
+
+impl Vary {
+    /// Parses a `Vary` header value. Header names are lowercased and deduplicated.
+    pub fn parse(value: &str) -> Self {
+        let mut vary = Vary::default();
+        for name in split_list(value) {
+            vary.add(name);
+        }
+        vary
+    }
+
+    /// Adds a header name, keeping the first occurrence when it is already present.
+    pub fn add(&mut self, name: &str) {
+        if name.trim() == "*" {
+            *self = Vary::Any;
+            return;
+        }
+
+        if let Vary::Headers(headers) = self {
+            let name = name.trim().to_ascii_lowercase();
+            if !name.is_empty() && !headers.contains(&name) {
+                headers.push(name);
+            }
+        }
+    }
+
+    pub fn merge(&mut self, other: &Vary) {
+        match other {
+            Vary::Any => *self = Vary::Any,
+            Vary::Headers(headers) => headers.iter().for_each(|name| self.add(name)),
+        }
+    }
+
+    /// Returns whether responses vary on the header `name`.
+    pub fn contains(&self, name: &str) -> bool {
+        match self {
+            Vary::Any => true,
+            Vary::Headers(headers) => headers.iter().any(|h| h.eq_ignore_ascii_case(name)),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        matches!(self, Vary::Headers(headers) if headers.is_empty())
+    }
+}
+
+impl Display for Vary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Vary::Any => f.write_str("*"),
+            Vary::Headers(headers) => f.write_str(&headers.join(", ")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{CacheControl, Vary};
+
+    #[test]
+    fn parse_cache_control() {
+        let cache_control = CacheControl::parse(
+            r#"Public, max-age=60, s-maxage="120", max-age=5, no-transform, community="UCI""#,
+        );
+
+        assert!(cache_control.public);
+        assert!(cache_control.no_transform);
+        assert_eq!(cache_control.max_age, Some(Duration::from_secs(60)));
+        assert_eq!(cache_control.s_maxage, Some(Duration::from_secs(120)));
+        assert_eq!(
+            cache_control.extensions,
+            [("community".to_string(), Some("UCI".to_string()))]
+        );
+    }
+
+    #[test]
+    fn malformed_durations_are_ignored() {
+        let cache_control = CacheControl::parse("max-age=soon, stale-if-error=-1");
+
+        assert_eq!(cache_control.max_age, None);
+        assert_eq!(cache_control.stale_if_error, None);
+    }
+
+    #[test]
+    fn freshness_and_storability() {
+        let cache_control = CacheControl::parse("private, max-age=10, s-maxage=20");
+
+        assert_eq!(
+            cache_control.freshness(false),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(cache_control.freshness(true), Some(Duration::from_secs(20)));
+        assert!(cache_control.is_storable(false));
+        assert!(!cache_control.is_storable(true));
+        assert!(!CacheControl::parse("no-store").is_storable(false));
+    }
+
+    #[test]
+    fn format_cache_control() {
+        let cache_control = CacheControl {
+            no_cache: true,
+            max_age: Some(Duration::from_secs(0)),
+            max_stale: Some(Duration::MAX),
+            extensions: vec![
+                ("ext".to_string(), Some("a b".to_string())),
+                ("flag".to_string(), None),
+            ],
+            ..Default::default()
+        };
+
+        let formatted = cache_control.to_string();
+
+        assert_eq!(
+            formatted,
+            r#"no-cache, max-age=0, max-stale, ext="a b", flag"#
+        );
+        assert_eq!(CacheControl::parse(&formatted), cache_control);
+    }
+
+    #[test]
+    fn parse_vary() {
+        let vary = Vary::parse("Accept-Encoding, origin,accept-encoding");
+
+        assert_eq!(
+            vary,
+            Vary::Headers(vec!["accept-encoding".to_string(), "origin".to_string()])
+        );
+        assert!(vary.contains("Origin"));
+        assert!(!vary.contains("Cookie"));
+        assert_eq!(vary.to_string(), "accept-encoding, origin");
+    }
+
+    #[test]
+    fn vary_any() {
+        let mut vary = Vary::parse("origin");
+        vary.merge(&Vary::parse("*"));
+
+        assert_eq!(vary, Vary::Any);
+        assert!(vary.contains("anything"));
+        assert_eq!(vary.to_string(), "*");
+        assert!(Vary::parse("").is_empty());
+    }
+}
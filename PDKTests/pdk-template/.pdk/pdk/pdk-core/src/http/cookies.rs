@@ -0,0 +1,601 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Parsing of the `Cookie` header, building of `Set-Cookie` headers, and cookies signed with
+//! HMAC-SHA256 so policies can keep small state on the client side.
+//!
+//! A signed cookie value reads `<format>.<key id>.<expires>.<payload>.<mac>`. The format is `s`
+//! for a payload in clear and `e` for an encrypted one, the expiry is in seconds since the
+//! epoch, and payload and mac are base64url encoded. The mac covers the cookie name too, so a
+//! cookie can not be replayed under another name.
+//!
+//! Keys are rotated by configuring the new key first: cookies are always issued with the first
+//! key, and verified with any of them. Cookies verified with a key other than the first are
+//! reported to be reissued.
+//!
+//! ```ignore
+//! let cookies = SignedCookies::new(&config.keys)?;
+//!
+//! let session = cookie(&header, "session")
+//!     .and_then(|value| cookies.verify("session", value, now).ok());
+//!
+//! let value = cookies.issue("session", b"{\"cart\":3}", now + ttl)?;
+//! let header = SetCookie::new("session", &value).max_age(ttl).to_string();
+//! ```
+use std::fmt::{self, Display, Formatter};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use classy::cookie::cookie_pairs;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+
+use crate::error::{codes, PolicyError};
+
+type HmacSha256 = Hmac<Sha256>;
+
+const SIGNED: &str = "s";
+const ENCRYPTED: &str = "e";
+const SEPARATOR: char = '.';
+
+/// The shortest secret accepted, in bytes.
+pub const MIN_SECRET_LENGTH: usize = 32;
+
+/// The longest cookie value browsers are expected to keep, in bytes.
+pub const MAX_COOKIE_LENGTH: usize = 4096;
+
+/// The cookies of a `Cookie` header, as name and value pairs, parsed as the
+/// [`CookiesAccessor`](classy::event::CookiesAccessor) of the events does. Quoted values are
+/// unquoted.
+pub fn parse_cookies(header: &str) -> Vec<(&str, &str)> {
+    cookie_pairs(header)
+        .map(|(name, value)| {
+            let value = value
+                .strip_prefix('"')
+                .and_then(|value| value.strip_suffix('"'))
+                .unwrap_or(value);
+            (name, value)
+        })
+        .collect()
+}
+
+/// The value of the first cookie called `name` in a `Cookie` header.
+pub fn cookie<'a>(header: &'a str, name: &str) -> Option<&'a str> {
+    parse_cookies(header)
+        .into_iter()
+        .find(|(cookie, _)| *cookie == name)
+        .map(|(_, value)| value)
+}
+
+/// The `SameSite` attribute of a cookie.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+/// A `Set-Cookie` header value, displayed with its attributes. Cookies are `Secure` and
+/// `HttpOnly` unless told otherwise.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SetCookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    domain: Option<String>,
+    max_age: Option<Duration>,
+    same_site: Option<SameSite>,
+    secure: bool,
+    http_only: bool,
+}
+
+impl SetCookie {
+    pub fn new(name: &str, value: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            value: value.to_string(),
+            path: None,
+            domain: None,
+            max_age: None,
+            same_site: None,
+            secure: true,
+            http_only: true,
+        }
+    }
+
+    /// A cookie removing the cookie called `name` from the client.
+    pub fn removal(name: &str) -> Self {
+        Self::new(name, "").max_age(Duration::ZERO)
+    }
+
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = Some(path.to_string());
+        self
+    }
+
+    pub fn domain(mut self, domain: &str) -> Self {
+        self.domain = Some(domain.to_string());
+        self
+    }
+
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+}
+
+impl Display for SetCookie {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+        if let Some(path) = &self.path {
+            write!(f, "; Path={}", path)?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={}", domain)?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age.as_secs())?;
+        }
+        if let Some(same_site) = self.same_site {
+            write!(f, "; SameSite={:?}", same_site)?;
+        }
+        if self.secure {
+            f.write_str("; Secure")?;
+        }
+        if self.http_only {
+            f.write_str("; HttpOnly")?;
+        }
+        Ok(())
+    }
+}
+
+/// A key signing the cookies, usually part of the policy configuration:
+///
+/// ```json
+/// "keys": [{ "id": "2024-06", "secret": "..." }, { "id": "2024-01", "secret": "..." }]
+/// ```
+#[derive(Clone, Deserialize)]
+pub struct CookieKey {
+    pub id: String,
+    pub secret: String,
+}
+
+impl fmt::Debug for CookieKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CookieKey")
+            .field("id", &self.id)
+            .field("secret", &"<redacted>")
+            .finish()
+    }
+}
+
+/// Why a signed cookie was not issued or verified.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum CookieError {
+    #[error("no cookie keys are configured")]
+    NoKeys,
+    #[error("cookie key {0} is invalid: {1}")]
+    InvalidKey(String, &'static str),
+    #[error("cookie value is malformed")]
+    Malformed,
+    #[error("cookie is signed with the unknown key {0}")]
+    UnknownKey(String),
+    #[error("cookie signature is invalid")]
+    InvalidSignature,
+    #[error("cookie expired")]
+    Expired,
+    #[error("cookie is encrypted, which is not enabled")]
+    EncryptionDisabled,
+    #[error("cookie can not be encrypted or decrypted")]
+    Encryption,
+    #[error("cookie of {0} bytes is too long")]
+    TooLong(usize),
+}
+
+impl From<CookieError> for PolicyError {
+    fn from(error: CookieError) -> Self {
+        let code = match error {
+            CookieError::NoKeys | CookieError::InvalidKey(..) => codes::INVALID_KEY,
+            CookieError::InvalidSignature => codes::INVALID_SIGNATURE,
+            CookieError::Expired => codes::EXPIRED_TOKEN,
+            CookieError::Encryption | CookieError::TooLong(_) => codes::SIGNING_FAILED,
+            CookieError::Malformed
+            | CookieError::UnknownKey(_)
+            | CookieError::EncryptionDisabled => codes::MALFORMED_TOKEN,
+        };
+        PolicyError::new(code, error.to_string())
+    }
+}
+
+/// The payload of a verified cookie.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VerifiedCookie {
+    pub payload: Vec<u8>,
+    pub expires: SystemTime,
+    /// Whether the cookie was signed with a key other than the current one, and should be
+    /// issued again.
+    pub reissue: bool,
+}
+
+struct SigningKey {
+    id: String,
+    secret: Vec<u8>,
+}
+
+impl SigningKey {
+    fn mac(&self, parts: &[&[u8]]) -> HmacSha256 {
+        // Hmac accepts keys of any length.
+        let mut mac = HmacSha256::new_from_slice(&self.secret).expect("any key length");
+        for (index, part) in parts.iter().enumerate() {
+            if index > 0 {
+                mac.update(&[SEPARATOR as u8]);
+            }
+            mac.update(part);
+        }
+        mac
+    }
+
+    /// A key derived from the secret for the `purpose`, so signing and encryption never share
+    /// their key.
+    #[cfg_attr(not(feature = "cookie-encryption"), allow(dead_code))]
+    fn derive(&self, purpose: &str) -> [u8; 32] {
+        self.mac(&[purpose.as_bytes()])
+            .finalize()
+            .into_bytes()
+            .into()
+    }
+}
+
+/// Issues and verifies the signed cookies of a policy.
+pub struct SignedCookies {
+    keys: Vec<SigningKey>,
+    encrypt: bool,
+}
+
+impl SignedCookies {
+    /// Cookies signed with the first of the `keys`, and verified with any of them.
+    pub fn new(keys: &[CookieKey]) -> Result<Self, CookieError> {
+        if keys.is_empty() {
+            return Err(CookieError::NoKeys);
+        }
+
+        let keys = keys
+            .iter()
+            .map(|key| {
+                if key.id.is_empty() || key.id.contains(SEPARATOR) {
+                    Err(CookieError::InvalidKey(
+                        key.id.clone(),
+                        "ids are not empty and have no dots",
+                    ))
+                } else if key.secret.len() < MIN_SECRET_LENGTH {
+                    Err(CookieError::InvalidKey(
+                        key.id.clone(),
+                        "secrets are 32 bytes at least",
+                    ))
+                } else {
+                    Ok(SigningKey {
+                        id: key.id.clone(),
+                        secret: key.secret.as_bytes().to_vec(),
+                    })
+                }
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            keys,
+            encrypt: false,
+        })
+    }
+
+    /// Encrypts the payload of the issued cookies with AES-256-GCM, besides signing them.
+    #[cfg(feature = "cookie-encryption")]
+    pub fn encrypted(mut self) -> Self {
+        self.encrypt = true;
+        self
+    }
+
+    fn current(&self) -> &SigningKey {
+        &self.keys[0]
+    }
+
+    /// The value of the cookie called `name` holding the `payload` until `expires`.
+    pub fn issue(
+        &self,
+        name: &str,
+        payload: &[u8],
+        expires: SystemTime,
+    ) -> Result<String, CookieError> {
+        let key = self.current();
+        let expires = expires
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+            .to_string();
+
+        let (format, payload) = if self.encrypt {
+            (ENCRYPTED, encrypt(key, expires.as_bytes(), payload)?)
+        } else {
+            (SIGNED, payload.to_vec())
+        };
+        let payload = URL_SAFE_NO_PAD.encode(payload);
+
+        let mac = key.mac(&[
+            name.as_bytes(),
+            format.as_bytes(),
+            key.id.as_bytes(),
+            expires.as_bytes(),
+            payload.as_bytes(),
+        ]);
+        let mac = URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+
+        let value = [format, &key.id, &expires, &payload, &mac].join(".");
+        if value.len() > MAX_COOKIE_LENGTH {
+            return Err(CookieError::TooLong(value.len()));
+        }
+        Ok(value)
+    }
+
+    /// The payload of the cookie called `name` with the `value`, when its signature is valid
+    /// and it did not expire at `now`.
+    pub fn verify(
+        &self,
+        name: &str,
+        value: &str,
+        now: SystemTime,
+    ) -> Result<VerifiedCookie, CookieError> {
+        let parts: Vec<&str> = value.split(SEPARATOR).collect();
+        let (format, id, expires, payload, mac) = match parts.as_slice() {
+            [format, id, expires, payload, mac] => (*format, *id, *expires, *payload, *mac),
+            _ => return Err(CookieError::Malformed),
+        };
+
+        let (index, key) = self
+            .keys
+            .iter()
+            .enumerate()
+            .find(|(_, key)| key.id == id)
+            .ok_or_else(|| CookieError::UnknownKey(id.to_string()))?;
+
+        let mac = URL_SAFE_NO_PAD
+            .decode(mac)
+            .map_err(|_| CookieError::Malformed)?;
+        key.mac(&[
+            name.as_bytes(),
+            format.as_bytes(),
+            id.as_bytes(),
+            expires.as_bytes(),
+            payload.as_bytes(),
+        ])
+        .verify_slice(&mac)
+        .map_err(|_| CookieError::InvalidSignature)?;
+
+        let expiry = expires.parse::<u64>().map_err(|_| CookieError::Malformed)?;
+        let expires_at = UNIX_EPOCH + Duration::from_secs(expiry);
+        if expires_at <= now {
+            return Err(CookieError::Expired);
+        }
+
+        let payload = URL_SAFE_NO_PAD
+            .decode(payload)
+            .map_err(|_| CookieError::Malformed)?;
+        let payload = match format {
+            SIGNED => payload,
+            ENCRYPTED => decrypt(key, expires.as_bytes(), &payload)?,
+            _ => return Err(CookieError::Malformed),
+        };
+
+        Ok(VerifiedCookie {
+            payload,
+            expires: expires_at,
+            reissue: index > 0 || (format == SIGNED && self.encrypt),
+        })
+    }
+}
+
+#[cfg(feature = "cookie-encryption")]
+const NONCE_LENGTH: usize = 12;
+
+/// Encrypts the `payload` into the nonce followed by the ciphertext. The nonce is derived from
+/// the expiry and the payload, which the host gives no randomness for, so only equal payloads
+/// issued for the same expiry share a nonce.
+#[cfg(feature = "cookie-encryption")]
+fn encrypt(key: &SigningKey, expires: &[u8], payload: &[u8]) -> Result<Vec<u8>, CookieError> {
+    use aes_gcm::aead::{Aead, KeyInit, Payload};
+    use aes_gcm::{Aes256Gcm, Nonce};
+
+    let cipher = Aes256Gcm::new(&key.derive("encryption").into());
+    let nonce = key
+        .mac(&[b"nonce", expires, payload])
+        .finalize()
+        .into_bytes();
+    let nonce = Nonce::from_slice(&nonce[..NONCE_LENGTH]);
+
+    let ciphertext = cipher
+        .encrypt(
+            nonce,
+            Payload {
+                msg: payload,
+                aad: expires,
+            },
+        )
+        .map_err(|_| CookieError::Encryption)?;
+
+    Ok([nonce.as_slice(), &ciphertext].concat())
+}
+
+#[cfg(feature = "cookie-encryption")]
+fn decrypt(key: &SigningKey, expires: &[u8], sealed: &[u8]) -> Result<Vec<u8>, CookieError> {
+    use aes_gcm::aead::{Aead, KeyInit, Payload};
+    use aes_gcm::{Aes256Gcm, Nonce};
+
+    if sealed.len() < NONCE_LENGTH {
+        return Err(CookieError::Malformed);
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LENGTH);
+
+    Aes256Gcm::new(&key.derive("encryption").into())
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: expires,
+            },
+        )
+        .map_err(|_| CookieError::Encryption)
+}
+
+#[cfg(not(feature = "cookie-encryption"))]
+fn encrypt(_: &SigningKey, _: &[u8], _: &[u8]) -> Result<Vec<u8>, CookieError> {
+    Err(CookieError::EncryptionDisabled)
+}
+
+#[cfg(not(feature = "cookie-encryption"))]
+fn decrypt(_: &SigningKey, _: &[u8], _: &[u8]) -> Result<Vec<u8>, CookieError> {
+    Err(CookieError::EncryptionDisabled)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{cookie, CookieError, CookieKey, SameSite, SetCookie, SignedCookies};
+
+    fn key(id: &str) -> CookieKey {
+        CookieKey {
+            id: id.to_string(),
+            secret: format!("{:0>32}", id),
+        }
+    }
+
+    #[test]
+    fn parse_cookie_header() {
+        let header = "theme=dark; session=\"abc.def\"; =ignored; flag";
+
+        assert_eq!(cookie(header, "session"), Some("abc.def"));
+        assert_eq!(cookie(header, "theme"), Some("dark"));
+        assert_eq!(cookie(header, "flag"), None);
+    }
+
+    #[test]
+    fn display_set_cookie() {
+        let cookie = SetCookie::new("session", "abc")
+            .path("/")
+            .max_age(Duration::from_secs(60))
+            .same_site(SameSite::Lax);
+
+        assert_eq!(
+            cookie.to_string(),
+            "session=abc; Path=/; Max-Age=60; SameSite=Lax; Secure; HttpOnly"
+        );
+        assert_eq!(
+            SetCookie::removal("session").http_only(false).to_string(),
+            "session=; Max-Age=0; Secure"
+        );
+    }
+
+    #[test]
+    fn issue_and_verify() {
+        let cookies = SignedCookies::new(&[key("current")]).unwrap();
+        let now = UNIX_EPOCH + Duration::from_secs(1_000);
+        let expires = now + Duration::from_secs(60);
+
+        let value = cookies.issue("session", b"{\"cart\":3}", expires).unwrap();
+        let verified = cookies.verify("session", &value, now).unwrap();
+
+        assert!(value.starts_with("s.current.1060."));
+        assert_eq!(verified.payload, b"{\"cart\":3}");
+        assert_eq!(verified.expires, expires);
+        assert!(!verified.reissue);
+
+        assert_eq!(
+            cookies.verify("session", &value, expires),
+            Err(CookieError::Expired)
+        );
+        assert_eq!(
+            cookies.verify("affinity", &value, now),
+            Err(CookieError::InvalidSignature)
+        );
+
+        let tampered = value.replacen(".1060.", ".9999.", 1);
+        assert_eq!(
+            cookies.verify("session", &tampered, now),
+            Err(CookieError::InvalidSignature)
+        );
+        assert_eq!(
+            cookies.verify("session", "s.current.1060", now),
+            Err(CookieError::Malformed)
+        );
+    }
+
+    #[test]
+    fn rotate_keys() {
+        let now = UNIX_EPOCH;
+        let expires = now + Duration::from_secs(60);
+        let old = SignedCookies::new(&[key("old")]).unwrap();
+        let rotated = SignedCookies::new(&[key("new"), key("old")]).unwrap();
+        let value = old.issue("session", b"state", expires).unwrap();
+
+        let verified = rotated.verify("session", &value, now).unwrap();
+        assert_eq!(verified.payload, b"state");
+        assert!(verified.reissue);
+
+        let value = rotated.issue("session", b"state", expires).unwrap();
+        assert_eq!(
+            old.verify("session", &value, now),
+            Err(CookieError::UnknownKey("new".to_string()))
+        );
+    }
+
+    #[test]
+    fn invalid_keys() {
+        let short = CookieKey {
+            id: "short".to_string(),
+            secret: "secret".to_string(),
+        };
+
+        assert!(matches!(SignedCookies::new(&[]), Err(CookieError::NoKeys)));
+        assert!(matches!(
+            SignedCookies::new(&[short]),
+            Err(CookieError::InvalidKey(..))
+        ));
+        assert!(matches!(
+            SignedCookies::new(&[key("a.b")]),
+            Err(CookieError::InvalidKey(..))
+        ));
+        assert!(!format!("{:?}", key("a")).contains("0000"));
+    }
+
+    #[cfg(feature = "cookie-encryption")]
+    #[test]
+    fn encrypted_cookies() {
+        let now = UNIX_EPOCH;
+        let expires = now + Duration::from_secs(60);
+        let cookies = SignedCookies::new(&[key("current")]).unwrap().encrypted();
+
+        let value = cookies.issue("session", b"secret state", expires).unwrap();
+        let verified = cookies.verify("session", &value, now).unwrap();
+
+        assert!(value.starts_with("e.current.60."));
+        assert!(!value.contains(&base64::Engine::encode(
+            &base64::engine::general_purpose::URL_SAFE_NO_PAD,
+            b"secret"
+        )));
+        assert_eq!(verified.payload, b"secret state");
+        assert!(!verified.reissue);
+    }
+}
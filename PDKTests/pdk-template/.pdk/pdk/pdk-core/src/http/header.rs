@@ -0,0 +1,324 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Parsing of structured header values: comma separated lists, parameters, quality values and
+//! dates.
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const QUALITY: &str = "q";
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Splits `value` on `separator`, ignoring separators inside quoted strings. Elements are
+/// trimmed and empty elements are dropped.
+fn split_unquoted(value: &str, separator: char) -> Vec<&str> {
+    let mut elements = Vec::new();
+    let mut quoted = false;
+    let mut escaped = false;
+    let mut start = 0;
+
+    for (index, c) in value.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if quoted && c == '\\' {
+            escaped = true;
+        } else if c == '"' {
+            quoted = !quoted;
+        } else if !quoted && c == separator {
+            elements.push(&value[start..index]);
+            start = index + c.len_utf8();
+        }
+    }
+    elements.push(&value[start..]);
+
+    elements
+        .into_iter()
+        .map(str::trim)
+        .filter(|element| !element.is_empty())
+        .collect()
+}
+
+/// Splits a comma separated header value into its elements, honoring quoted strings.
+pub fn split_list(value: &str) -> Vec<&str> {
+    split_unquoted(value, ',')
+}
+
+/// Removes the surrounding quotes and escapes of a quoted string. Values which are not
+/// quoted are returned as they are.
+pub fn unquote(value: &str) -> String {
+    let value = value.trim();
+    match value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+    {
+        None => value.to_string(),
+        Some(inner) => {
+            let mut unquoted = String::with_capacity(inner.len());
+            let mut chars = inner.chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => unquoted.extend(chars.next()),
+                    c => unquoted.push(c),
+                }
+            }
+            unquoted
+        }
+    }
+}
+
+/// A header element followed by `;` separated parameters, like
+/// `text/html; charset="utf-8"; q=0.8`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParameterizedValue {
+    value: String,
+    parameters: Vec<(String, String)>,
+}
+
+impl ParameterizedValue {
+    pub fn parse(element: &str) -> Self {
+        let mut parts = split_unquoted(element, ';').into_iter();
+        let value = parts.next().unwrap_or_default().to_string();
+        let parameters = parts
+            .map(|parameter| match parameter.split_once('=') {
+                Some((name, value)) => (name.trim().to_ascii_lowercase(), unquote(value)),
+                None => (parameter.to_ascii_lowercase(), String::new()),
+            })
+            .collect();
+
+        Self { value, parameters }
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Returns the value of the parameter `name`. Parameter names are case insensitive.
+    pub fn parameter(&self, name: &str) -> Option<&str> {
+        self.parameters
+            .iter()
+            .find(|(parameter, _)| parameter.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn parameters(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.parameters
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// Returns the `q` parameter clamped to `0.0..=1.0`, defaulting to `1.0` when it is
+    /// absent or malformed.
+    pub fn quality(&self) -> f32 {
+        self.parameter(QUALITY)
+            .and_then(|quality| quality.parse::<f32>().ok())
+            .filter(|quality| quality.is_finite())
+            .map(|quality| quality.clamp(0.0, 1.0))
+            .unwrap_or(1.0)
+    }
+}
+
+/// Parses every element of a comma separated header value.
+pub fn parse_list(value: &str) -> Vec<ParameterizedValue> {
+    split_list(value)
+        .into_iter()
+        .map(ParameterizedValue::parse)
+        .collect()
+}
+
+/// Returns how specifically `range` matches `candidate`, or `None` when it does not match.
+/// Media ranges like `text/*` are supported along with the `*` wildcard.
+fn specificity(range: &str, candidate: &str) -> Option<u8> {
+    if range.eq_ignore_ascii_case(candidate) {
+        return Some(3);
+    }
+    if range == "*" || range == "*/*" {
+        return Some(1);
+    }
+    let prefix = range.strip_suffix("/*")?;
+    let (kind, _) = candidate.split_once('/')?;
+    kind.eq_ignore_ascii_case(prefix).then_some(2)
+}
+
+/// Returns the quality the `accepted` elements assign to `candidate`, taken from the most
+/// specific matching element.
+pub fn quality_of(accepted: &[ParameterizedValue], candidate: &str) -> Option<f32> {
+    accepted
+        .iter()
+        .filter_map(|element| {
+            specificity(element.value(), candidate).map(|specificity| (specificity, element))
+        })
+        .max_by_key(|(specificity, _)| *specificity)
+        .map(|(_, element)| element.quality())
+}
+
+/// Picks the preferred value among `available` according to a header value like
+/// `Accept` or `Accept-Encoding`. Ties are resolved in favor of the first available value,
+/// and values with a quality of 0 are never selected.
+pub fn negotiate<'a>(header: &str, available: &[&'a str]) -> Option<&'a str> {
+    let accepted = parse_list(header);
+    let mut best: Option<(&'a str, f32)> = None;
+
+    for candidate in available {
+        let quality = match quality_of(&accepted, candidate) {
+            Some(quality) if quality > 0.0 => quality,
+            _ => continue,
+        };
+        if best.map(|(_, best)| quality > best).unwrap_or(true) {
+            best = Some((candidate, quality));
+        }
+    }
+
+    best.map(|(candidate, _)| candidate)
+}
+
+/// Parses an HTTP date in the preferred format of RFC 9110, like
+/// `Sun, 06 Nov 1994 08:49:37 GMT`. The obsolete formats and the dates before the epoch are
+/// none.
+pub fn parse_http_date(value: &str) -> Option<SystemTime> {
+    let mut fields = value.split_ascii_whitespace();
+    let _weekday = fields.next().filter(|weekday| weekday.ends_with(','))?;
+    let day: u32 = fields.next().filter(|day| day.len() == 2)?.parse().ok()?;
+    let month = fields.next()?;
+    let month = MONTHS.iter().position(|name| *name == month)? as u32 + 1;
+    let year: i64 = fields.next().filter(|year| year.len() == 4)?.parse().ok()?;
+    let time: Vec<u64> = fields
+        .next()
+        .filter(|time| time.len() == 8)?
+        .split(':')
+        .map(|field| field.parse().ok())
+        .collect::<Option<_>>()?;
+    if fields.next() != Some("GMT") || fields.next().is_some() {
+        return None;
+    }
+    let (hour, minute, second) = match time.as_slice() {
+        [hour, minute, second] if *hour < 24 && *minute < 60 && *second <= 60 => {
+            (*hour, *minute, *second)
+        }
+        _ => return None,
+    };
+    if !(1..=31).contains(&day) || year < 1970 {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day) as u64;
+    let seconds = days * 86_400 + hour * 3600 + minute * 60 + second;
+    Some(UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+/// The days from the epoch to the date of the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month as i64 + 9) % 12) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{negotiate, parse_http_date, parse_list, split_list, unquote, ParameterizedValue};
+
+    #[test]
+    fn split_list_honors_quotes() {
+        assert_eq!(
+            split_list(r#"a, "b, c" ,, d;x="1,2""#),
+            ["a", r#""b, c""#, r#"d;x="1,2""#]
+        );
+        assert!(split_list(" , ").is_empty());
+    }
+
+    #[test]
+    fn unquote_escapes() {
+        assert_eq!(unquote(r#""a \"quoted\" value""#), r#"a "quoted" value"#);
+        assert_eq!(unquote(" plain "), "plain");
+    }
+
+    #[test]
+    fn parse_parameters() {
+        let value = ParameterizedValue::parse(r#"text/html; Charset="utf-8"; q=0.8; flag"#);
+
+        assert_eq!(value.value(), "text/html");
+        assert_eq!(value.parameter("charset"), Some("utf-8"));
+        assert_eq!(value.parameter("flag"), Some(""));
+        assert_eq!(value.quality(), 0.8);
+    }
+
+    #[test]
+    fn quality_defaults_and_clamps() {
+        let qualities = parse_list("gzip, br;q=2, deflate;q=nope, identity;q=0")
+            .iter()
+            .map(ParameterizedValue::quality)
+            .collect::<Vec<_>>();
+
+        assert_eq!(qualities, [1.0, 1.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn negotiate_encoding() {
+        let available = ["br", "gzip", "identity"];
+
+        assert_eq!(negotiate("gzip;q=0.5, br;q=0.9", &available), Some("br"));
+        assert_eq!(negotiate("deflate, gzip", &available), Some("gzip"));
+        assert_eq!(negotiate("*;q=0.1, br;q=0", &available), Some("gzip"));
+        assert_eq!(negotiate("deflate", &available), None);
+        assert_eq!(negotiate("", &available), None);
+    }
+
+    #[test]
+    fn negotiate_media_type_by_specificity() {
+        let available = ["application/json", "text/html", "text/plain"];
+
+        assert_eq!(
+            negotiate("text/*;q=0.5, text/plain, */*;q=0.1", &available),
+            Some("text/plain")
+        );
+        assert_eq!(
+            negotiate("text/*, text/html;q=0.2", &available),
+            Some("text/plain")
+        );
+        assert_eq!(
+            negotiate("*/*;q=0.3, application/json;q=0", &available),
+            Some("text/html")
+        );
+    }
+
+    #[test]
+    fn http_dates() {
+        let at = |seconds| Some(UNIX_EPOCH + Duration::from_secs(seconds));
+        assert_eq!(
+            parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"),
+            at(784_111_777)
+        );
+        assert_eq!(
+            parse_http_date("Tue, 15 Oct 2024 10:00:00 GMT"),
+            at(1_728_986_400)
+        );
+        assert_eq!(
+            parse_http_date("Thu, 29 Feb 2024 23:59:59 GMT"),
+            at(1_709_251_199)
+        );
+        assert_eq!(parse_http_date("Thu, 01 Jan 1970 00:00:00 GMT"), at(0));
+    }
+
+    #[test]
+    fn invalid_http_dates() {
+        for date in [
+            "",
+            "Sunday, 06-Nov-94 08:49:37 GMT",
+            "Sun Nov  6 08:49:37 1994",
+            "Sun, 06 Nov 1994 08:49:37 UTC",
+            "Sun, 06 Nov 1994 08:49:37 GMT extra",
+            "Sun, 6 Nov 1994 08:49:37 GMT",
+            "Sun, 06 nov 1994 08:49:37 GMT",
+            "Sun, 32 Nov 1994 08:49:37 GMT",
+            "Sun, 06 Nov 1994 24:00:00 GMT",
+            "Sun, 06 Nov 1994 8:49:370 GMT",
+            "Wed, 31 Dec 1969 23:59:59 GMT",
+        ] {
+            assert_eq!(parse_http_date(date), None, "{}", date);
+        }
+    }
+}
@@ -0,0 +1,321 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Removal and renaming of whole families of headers, matched by prefix or by regular
+//! expression, like the `x-internal-*` headers to strip before the upstream or the
+//! `x-legacy-*` ones to rename to `x-new-*`.
+//!
+//! The rules are applied in a single pass over the headers, read and written back with one host
+//! call each, and only written back when a header matched. The first rule matching a header
+//! applies. Pseudo-headers, like `:path`, are never matched.
+//!
+//! ```json
+//! [
+//!   { "prefix": "x-internal-" },
+//!   { "prefix": "x-legacy-", "renameTo": "x-new-" },
+//!   { "regex": "^x-(.+)-debug$", "renameTo": "x-debug-$1" }
+//! ]
+//! ```
+//!
+//! Rules with a `regex` require the `regex-cache` feature. Their `renameTo` can refer to the
+//! groups of the expression as `$1` or `${name}`.
+use std::borrow::Cow;
+use std::convert::TryFrom;
+#[cfg(feature = "regex-cache")]
+use std::rc::Rc;
+
+use classy::event::HeadersAccessor;
+#[cfg(feature = "regex-cache")]
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::error::{codes, PolicyError};
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum HeaderRuleError {
+    #[error("A header rule needs either a prefix or a regex")]
+    MissingMatcher,
+    #[error("A header rule has both a prefix and a regex")]
+    AmbiguousMatcher,
+    #[error("Invalid header name prefix '{0}'")]
+    InvalidPrefix(String),
+    #[error("Header rules with a regex require the regex-cache feature")]
+    RegexUnsupported,
+    #[error("Invalid header name regex: {0}")]
+    InvalidRegex(String),
+}
+
+impl From<HeaderRuleError> for PolicyError {
+    fn from(error: HeaderRuleError) -> Self {
+        Self::new(codes::INVALID_CONFIGURATION, error.to_string())
+    }
+}
+
+/// What the names of the headers are matched with.
+#[derive(Clone, Debug)]
+pub enum HeaderMatcher {
+    /// The names starting with the prefix, compared ignoring case.
+    Prefix(String),
+    /// The names the expression finds a match in, the names being lowercase.
+    #[cfg(feature = "regex-cache")]
+    Regex(Rc<Regex>),
+}
+
+impl HeaderMatcher {
+    pub fn prefix(prefix: &str) -> Result<Self, HeaderRuleError> {
+        if prefix.is_empty() || !is_token(prefix) {
+            return Err(HeaderRuleError::InvalidPrefix(prefix.to_string()));
+        }
+        Ok(Self::Prefix(prefix.to_ascii_lowercase()))
+    }
+
+    /// The expression, compiled once in the [`regex_cache`](crate::regex_cache).
+    #[cfg(feature = "regex-cache")]
+    pub fn regex(pattern: &str) -> Result<Self, HeaderRuleError> {
+        crate::regex_cache::compile(pattern)
+            .map(Self::Regex)
+            .map_err(|error| HeaderRuleError::InvalidRegex(error.to_string()))
+    }
+
+    /// Whether the lowercase `name` matches.
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            Self::Prefix(prefix) => name.starts_with(prefix.as_str()),
+            #[cfg(feature = "regex-cache")]
+            Self::Regex(regex) => regex.is_match(name),
+        }
+    }
+
+    /// The `name` with its match replaced by `replacement`, the lowercase `name` matching.
+    fn replace<'a>(&self, name: &'a str, replacement: &str) -> Cow<'a, str> {
+        match self {
+            Self::Prefix(prefix) => Cow::Owned(format!("{}{}", replacement, &name[prefix.len()..])),
+            #[cfg(feature = "regex-cache")]
+            Self::Regex(regex) => regex.replace(name, replacement),
+        }
+    }
+}
+
+/// What is done with the headers matched.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HeaderOperation {
+    Remove,
+    /// Renames the headers, replacing the prefix matched, or the match of the expression, with
+    /// the replacement.
+    Rename(String),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawHeaderRule {
+    #[serde(default)]
+    prefix: Option<String>,
+    #[serde(default)]
+    regex: Option<String>,
+    #[serde(default)]
+    rename_to: Option<String>,
+}
+
+/// Removes or renames the headers matched. Rules are parsed from a `prefix` or a `regex`, and a
+/// `renameTo` for the renaming ones.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "RawHeaderRule")]
+pub struct HeaderRule {
+    matcher: HeaderMatcher,
+    operation: HeaderOperation,
+}
+
+impl TryFrom<RawHeaderRule> for HeaderRule {
+    type Error = HeaderRuleError;
+
+    fn try_from(raw: RawHeaderRule) -> Result<Self, Self::Error> {
+        let matcher = match (raw.prefix, raw.regex) {
+            (Some(prefix), None) => HeaderMatcher::prefix(&prefix)?,
+            #[cfg(feature = "regex-cache")]
+            (None, Some(regex)) => HeaderMatcher::regex(&regex)?,
+            #[cfg(not(feature = "regex-cache"))]
+            (None, Some(_)) => return Err(HeaderRuleError::RegexUnsupported),
+            (Some(_), Some(_)) => return Err(HeaderRuleError::AmbiguousMatcher),
+            (None, None) => return Err(HeaderRuleError::MissingMatcher),
+        };
+        let operation = match raw.rename_to {
+            Some(name) => HeaderOperation::Rename(name),
+            None => HeaderOperation::Remove,
+        };
+        Ok(Self::new(matcher, operation))
+    }
+}
+
+impl HeaderRule {
+    pub fn new(matcher: HeaderMatcher, operation: HeaderOperation) -> Self {
+        Self { matcher, operation }
+    }
+
+    pub fn remove(matcher: HeaderMatcher) -> Self {
+        Self::new(matcher, HeaderOperation::Remove)
+    }
+
+    pub fn rename(matcher: HeaderMatcher, replacement: &str) -> Self {
+        Self::new(matcher, HeaderOperation::Rename(replacement.to_string()))
+    }
+
+    pub fn matcher(&self) -> &HeaderMatcher {
+        &self.matcher
+    }
+
+    pub fn operation(&self) -> &HeaderOperation {
+        &self.operation
+    }
+}
+
+/// The rules applied to the headers of a request or a response.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct HeaderTransform {
+    rules: Vec<HeaderRule>,
+}
+
+impl HeaderTransform {
+    pub fn new(rules: Vec<HeaderRule>) -> Self {
+        Self { rules }
+    }
+
+    pub fn rules(&self) -> &[HeaderRule] {
+        &self.rules
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// The headers with the rules applied, in order, none when no header matched. Headers
+    /// renamed to an invalid name are kept as they are.
+    pub fn transform(&self, headers: &[(String, String)]) -> Option<Vec<(String, String)>> {
+        let mut matched = false;
+        let mut transformed = Vec::with_capacity(headers.len());
+
+        for (name, value) in headers {
+            let lowercase = name.to_ascii_lowercase();
+            let rule = if lowercase.starts_with(':') {
+                None
+            } else {
+                self.rules
+                    .iter()
+                    .find(|rule| rule.matcher.matches(&lowercase))
+            };
+            let rule = match rule {
+                Some(rule) => rule,
+                None => {
+                    transformed.push((name.clone(), value.clone()));
+                    continue;
+                }
+            };
+
+            match &rule.operation {
+                HeaderOperation::Remove => matched = true,
+                HeaderOperation::Rename(replacement) => {
+                    let renamed = rule.matcher.replace(&lowercase, replacement);
+                    if renamed.is_empty() || !is_token(&renamed) {
+                        log::warn!("Not renaming the header {} to {:?}.", name, renamed);
+                        transformed.push((name.clone(), value.clone()));
+                    } else {
+                        matched = true;
+                        transformed.push((renamed.to_ascii_lowercase(), value.clone()));
+                    }
+                }
+            }
+        }
+
+        matched.then_some(transformed)
+    }
+
+    /// Applies the rules to the headers of the `accessor`, written back only when a header
+    /// matched. Returns whether they were.
+    pub fn apply(&self, accessor: &dyn HeadersAccessor) -> bool {
+        if self.rules.is_empty() {
+            return false;
+        }
+        match self.transform(&accessor.headers()) {
+            Some(headers) => {
+                accessor.set_headers(
+                    headers
+                        .iter()
+                        .map(|(name, value)| (name.as_str(), value.as_str()))
+                        .collect(),
+                );
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Whether `name` only has the characters of header names.
+fn is_token(name: &str) -> bool {
+    name.bytes()
+        .all(|byte| byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HeaderMatcher, HeaderRule, HeaderRuleError, HeaderTransform};
+
+    fn headers(headers: &[(&str, &str)]) -> Vec<(String, String)> {
+        headers
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn headers_are_removed_and_renamed_by_prefix() {
+        let transform = HeaderTransform::new(vec![
+            HeaderRule::remove(HeaderMatcher::prefix("X-Internal-").unwrap()),
+            HeaderRule::rename(HeaderMatcher::prefix("x-legacy-").unwrap(), "x-new-"),
+        ]);
+
+        let transformed = transform.transform(&headers(&[
+            (":path", "/orders"),
+            ("X-Internal-Trace", "1"),
+            ("x-internal-user", "alice"),
+            ("X-Legacy-Tenant", "acme"),
+            ("accept", "*/*"),
+        ]));
+
+        assert_eq!(
+            transformed,
+            Some(headers(&[
+                (":path", "/orders"),
+                ("x-new-tenant", "acme"),
+                ("accept", "*/*"),
+            ]))
+        );
+        assert_eq!(transform.transform(&headers(&[("accept", "*/*")])), None);
+    }
+
+    #[test]
+    fn rules_are_parsed() {
+        let transform: HeaderTransform = serde_json::from_str(
+            r#"[{"prefix": "x-internal-"}, {"prefix": "x-legacy-", "renameTo": "x-new-"}]"#,
+        )
+        .unwrap();
+        assert_eq!(transform.rules().len(), 2);
+
+        assert!(serde_json::from_str::<HeaderTransform>(r#"[{"renameTo": "x-new-"}]"#).is_err());
+        assert_eq!(
+            HeaderMatcher::prefix(":authority").err(),
+            Some(HeaderRuleError::InvalidPrefix(":authority".to_string()))
+        );
+    }
+
+    #[cfg(feature = "regex-cache")]
+    #[test]
+    fn headers_are_renamed_by_regex() {
+        let transform = HeaderTransform::new(vec![HeaderRule::rename(
+            HeaderMatcher::regex("^x-(.+)-debug$").unwrap(),
+            "x-debug-$1",
+        )]);
+
+        let transformed = transform.transform(&headers(&[("X-Orders-Debug", "on")]));
+
+        assert_eq!(transformed, Some(headers(&[("x-debug-orders", "on")])));
+    }
+}
@@ -0,0 +1,121 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Detection of the health probes of load balancers and orchestrators, so telemetry,
+//! rate limiting or auditing can leave them out.
+//!
+//! A request is a probe when its path, without the query, is one of the probe paths or its
+//! `User-Agent` starts with one of the probe agents. The defaults cover the usual
+//! `/healthz`-like paths and the Kubernetes, AWS, Google Cloud, Consul and Envoy probes.
+use classy::event::{EventData, HeadersAccessor, RequestHeaders};
+use serde::Deserialize;
+
+const PATH: &str = ":path";
+const USER_AGENT: &str = "user-agent";
+
+/// The probe paths used when none is configured.
+pub const DEFAULT_PATHS: &[&str] = &["/health", "/healthz", "/livez", "/readyz", "/ping"];
+
+/// The prefixes of the probe user agents used when none is configured.
+pub const DEFAULT_USER_AGENTS: &[&str] = &[
+    "kube-probe/",
+    "ELB-HealthChecker/",
+    "GoogleHC/",
+    "Consul Health Check",
+    "Envoy/HC",
+];
+
+/// Which requests are health probes, usually part of the policy configuration:
+///
+/// ```json
+/// "healthChecks": { "paths": ["/status"], "userAgents": ["kube-probe/"] }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct HealthChecks {
+    /// Paths matched exactly, ignoring the query and a trailing slash. [`DEFAULT_PATHS`] when
+    /// missing.
+    #[serde(default)]
+    pub paths: Option<Vec<String>>,
+
+    /// Prefixes of the `User-Agent` header. [`DEFAULT_USER_AGENTS`] when missing.
+    #[serde(default, alias = "userAgents")]
+    pub user_agents: Option<Vec<String>>,
+}
+
+impl HealthChecks {
+    /// Whether a request with the `path` and `user_agent` is a health probe.
+    pub fn matches(&self, path: &str, user_agent: Option<&str>) -> bool {
+        let path = path
+            .split(['?', '#'])
+            .next()
+            .unwrap_or_default();
+        let path = match path.strip_suffix('/') {
+            Some(trimmed) if !trimmed.is_empty() => trimmed,
+            _ => path,
+        };
+
+        let path_matches = match &self.paths {
+            Some(paths) => paths.iter().any(|probe| probe == path),
+            None => DEFAULT_PATHS.contains(&path),
+        };
+
+        path_matches
+            || user_agent.map_or(false, |agent| match &self.user_agents {
+                Some(agents) => agents.iter().any(|probe| agent.starts_with(probe.as_str())),
+                None => DEFAULT_USER_AGENTS
+                    .iter()
+                    .any(|probe| agent.starts_with(probe)),
+            })
+    }
+}
+
+/// Tells health probes apart from regular requests.
+pub trait HealthCheck {
+    /// Whether the request is a health probe, as told by the default [`HealthChecks`].
+    fn is_health_check(&self) -> bool {
+        self.is_health_check_with(&HealthChecks::default())
+    }
+
+    /// Whether the request is a health probe, as told by the `checks`.
+    fn is_health_check_with(&self, checks: &HealthChecks) -> bool;
+}
+
+impl HealthCheck for EventData<'_, RequestHeaders> {
+    fn is_health_check_with(&self, checks: &HealthChecks) -> bool {
+        // A single host call for both headers.
+        let mut headers = self.get_headers(&[PATH, USER_AGENT]).into_iter();
+        let path = headers.next().flatten().unwrap_or_default();
+        let user_agent = headers.next().flatten();
+
+        checks.matches(&path, user_agent.as_deref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HealthChecks;
+
+    #[test]
+    fn default_probes() {
+        let checks = HealthChecks::default();
+
+        assert!(checks.matches("/healthz", None));
+        assert!(checks.matches("/healthz/?verbose", None));
+        assert!(checks.matches("/orders", Some("kube-probe/1.27")));
+        assert!(checks.matches("/", Some("ELB-HealthChecker/2.0")));
+
+        assert!(!checks.matches("/healthz/details", None));
+        assert!(!checks.matches("/", None));
+        assert!(!checks.matches("/orders", Some("curl/8.0")));
+    }
+
+    #[test]
+    fn configured_probes() {
+        let checks: HealthChecks =
+            serde_json::from_str(r#"{"paths": ["/status"], "userAgents": ["monitor/"]}"#).unwrap();
+
+        assert!(checks.matches("/status", None));
+        assert!(checks.matches("/orders", Some("monitor/1.0")));
+
+        assert!(!checks.matches("/healthz", None));
+        assert!(!checks.matches("/orders", Some("kube-probe/1.27")));
+    }
+}
@@ -0,0 +1,119 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Matrix parameters, the `;key=value` pairs some REST APIs append to path segments, like
+//! `/cars;color=red/audi;year=2012`.
+//!
+//! Templates are matched against the path without them, see [`MatrixPath::path`].
+use super::path::percent_decode;
+
+/// A matrix parameter, percent-decoded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MatrixParam {
+    /// The index of the path segment the parameter belongs to.
+    pub segment: usize,
+    pub name: String,
+    pub value: String,
+}
+
+/// A request path split from its matrix parameters.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MatrixPath {
+    path: String,
+    params: Vec<MatrixParam>,
+}
+
+impl MatrixPath {
+    /// Splits the path of a request URI like the `:path` pseudo header. The query string
+    /// and the fragment are dropped.
+    pub fn parse(uri: &str) -> Self {
+        let path = uri
+            .split(['?', '#'])
+            .next()
+            .unwrap_or_default();
+
+        let mut params = Vec::new();
+        let segments: Vec<&str> = path
+            .split('/')
+            .enumerate()
+            .map(|(index, segment)| {
+                let mut parts = segment.split(';');
+                let segment = parts.next().unwrap_or_default();
+                // The leading slash makes the first segment empty, segments count from the
+                // first one after it.
+                let index = index.saturating_sub(1);
+                params.extend(parts.filter(|part| !part.is_empty()).map(|part| {
+                    let (name, value) = part.split_once('=').unwrap_or((part, ""));
+                    MatrixParam {
+                        segment: index,
+                        name: decode(name),
+                        value: decode(value),
+                    }
+                }));
+                segment
+            })
+            .collect();
+
+        Self {
+            path: segments.join("/"),
+            params,
+        }
+    }
+
+    /// The path without the matrix parameters, still percent-encoded.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The matrix parameters, in order.
+    pub fn params(&self) -> &[MatrixParam] {
+        &self.params
+    }
+
+    /// The value of the last matrix parameter called `name`, in any segment.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .rev()
+            .find(|param| param.name == name)
+            .map(|param| param.value.as_str())
+    }
+}
+
+fn decode(component: &str) -> String {
+    percent_decode(component).unwrap_or_else(|| component.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MatrixPath;
+
+    #[test]
+    fn split_matrix_params() {
+        let matrix = MatrixPath::parse("/cars;color=red;used/audi;year=2012;color=%20blue?x=1");
+
+        assert_eq!(matrix.path(), "/cars/audi");
+        let params: Vec<_> = matrix
+            .params()
+            .iter()
+            .map(|param| (param.segment, param.name.as_str(), param.value.as_str()))
+            .collect();
+        assert_eq!(
+            params,
+            [
+                (0, "color", "red"),
+                (0, "used", ""),
+                (1, "year", "2012"),
+                (1, "color", " blue"),
+            ]
+        );
+        assert_eq!(matrix.get("color"), Some(" blue"));
+        assert_eq!(matrix.get("missing"), None);
+    }
+
+    #[test]
+    fn path_without_matrix_params() {
+        let matrix = MatrixPath::parse("/pets/12");
+
+        assert_eq!(matrix.path(), "/pets/12");
+        assert!(matrix.params().is_empty());
+    }
+}
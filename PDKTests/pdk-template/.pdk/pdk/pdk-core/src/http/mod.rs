@@ -0,0 +1,17 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! HTTP utilities shared among policies.
+pub mod baggage;
+pub mod cache_control;
+pub mod cookies;
+pub mod header;
+pub mod header_transform;
+pub mod health;
+pub mod matrix;
+pub mod negotiate;
+pub mod path;
+pub mod query;
+pub mod request_class;
+pub mod server_timing;
+pub mod signature;
+pub mod template;
+pub mod tls_fingerprint;
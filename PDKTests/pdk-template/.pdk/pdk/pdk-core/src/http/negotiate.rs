@@ -0,0 +1,133 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Detection of the connection oriented authentication handshakes, `Negotiate` (Kerberos or
+//! SPNEGO) and `NTLM`.
+//!
+//! Their legs authenticate the connection rather than the request, so every leg must reach
+//! the same upstream connection. A request is part of a handshake when its `Authorization`
+//! header uses one of the schemes, and a response starts one when it is a `401` offering one
+//! of them in its `WWW-Authenticate` headers.
+use serde::Deserialize;
+
+use super::header::split_list;
+
+pub const AUTHORIZATION: &str = "authorization";
+pub const WWW_AUTHENTICATE: &str = "www-authenticate";
+
+const UNAUTHORIZED: u32 = 401;
+
+/// A connection oriented authentication scheme.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+pub enum AuthScheme {
+    Negotiate,
+    #[serde(rename = "NTLM")]
+    Ntlm,
+}
+
+impl AuthScheme {
+    /// The scheme of a challenge or credentials token, case insensitive.
+    pub fn parse(scheme: &str) -> Option<Self> {
+        if scheme.eq_ignore_ascii_case("negotiate") {
+            Some(Self::Negotiate)
+        } else if scheme.eq_ignore_ascii_case("ntlm") {
+            Some(Self::Ntlm)
+        } else {
+            None
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Negotiate => "Negotiate",
+            Self::Ntlm => "NTLM",
+        }
+    }
+
+    /// The scheme of the credentials of an `Authorization` header value.
+    pub fn from_authorization(value: &str) -> Option<Self> {
+        value.split_whitespace().next().and_then(Self::parse)
+    }
+
+    /// The schemes offered by the challenges of the `WWW-Authenticate` header values, in
+    /// order and without repetitions.
+    pub fn offered<'a>(values: impl IntoIterator<Item = &'a str>) -> Vec<Self> {
+        let mut schemes = Vec::new();
+
+        // Challenges and their parameters share the comma separator, only challenges start
+        // with a token not followed by `=`.
+        let challenges = values.into_iter().flat_map(split_list);
+        for challenge in challenges {
+            let token = challenge.split_whitespace().next().unwrap_or_default();
+            if token.contains('=') {
+                continue;
+            }
+            if let Some(scheme) = Self::parse(token) {
+                if !schemes.contains(&scheme) {
+                    schemes.push(scheme);
+                }
+            }
+        }
+
+        schemes
+    }
+}
+
+/// The scheme of the handshake a response with the `status` and the `WWW-Authenticate` header
+/// `values` starts or continues, among the `accepted` ones.
+pub fn challenged<'a>(
+    status: u32,
+    values: impl IntoIterator<Item = &'a str>,
+    accepted: &[AuthScheme],
+) -> Option<AuthScheme> {
+    if status != UNAUTHORIZED {
+        return None;
+    }
+    AuthScheme::offered(values)
+        .into_iter()
+        .find(|scheme| accepted.contains(scheme))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{challenged, AuthScheme};
+
+    #[test]
+    fn schemes_from_authorization() {
+        assert_eq!(
+            AuthScheme::from_authorization("Negotiate YIIGhgYGKwYBBQUCoII"),
+            Some(AuthScheme::Negotiate)
+        );
+        assert_eq!(
+            AuthScheme::from_authorization("ntlm TlRMTVNTUAABAAAA"),
+            Some(AuthScheme::Ntlm)
+        );
+        assert_eq!(AuthScheme::from_authorization("Bearer eyJ"), None);
+        assert_eq!(AuthScheme::from_authorization(""), None);
+    }
+
+    #[test]
+    fn offered_schemes() {
+        let offered = AuthScheme::offered(vec![
+            r#"Basic realm="a, NTLM", charset="UTF-8", Negotiate"#,
+            "NTLM",
+            "negotiate",
+        ]);
+
+        assert_eq!(offered, [AuthScheme::Negotiate, AuthScheme::Ntlm]);
+    }
+
+    #[test]
+    fn challenged_responses() {
+        let both = [AuthScheme::Negotiate, AuthScheme::Ntlm];
+
+        assert_eq!(
+            challenged(401, vec!["NTLM TlRMTVNTUAACAAAA"], &both),
+            Some(AuthScheme::Ntlm)
+        );
+        assert_eq!(
+            challenged(401, vec!["Negotiate, NTLM"], &[AuthScheme::Ntlm]),
+            Some(AuthScheme::Ntlm)
+        );
+        assert_eq!(challenged(200, vec!["Negotiate"], &both), None);
+        assert_eq!(challenged(401, vec!["Basic realm=\"x\""], &both), None);
+    }
+}
@@ -0,0 +1,263 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Request path normalization, to be applied before matching paths in routing or
+//! authorization decisions.
+//!
+//! Normalization decodes percent-encoded unreserved characters, collapses repeated slashes
+//! and resolves `.` and `..` segments. Everything that could be used to make two different
+//! raw paths look alike to the gateway and the upstream is reported as a [`Finding`].
+pub use classy::query::percent_decode;
+
+/// Something noteworthy found while normalizing a path.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Finding {
+    /// Repeated slashes were collapsed.
+    DuplicateSlash,
+    /// A `.` or `..` segment was resolved.
+    DotSegment,
+    /// A dot segment was percent-encoded, like `%2e%2e`.
+    EncodedDotSegment,
+    /// A percent-encoded `/` or `\`, kept encoded in the normalized path.
+    EncodedSeparator,
+    /// A literal `\`, which some upstreams treat as a separator.
+    Backslash,
+    /// A `..` segment tried to go above the root.
+    EscapesRoot,
+    /// A `%` not followed by two hexadecimal digits.
+    InvalidEncoding,
+    /// A NUL character, either literal or encoded.
+    NullCharacter,
+    /// A percent-encoded sequence decoded to something that is not valid UTF-8.
+    InvalidUtf8,
+}
+
+impl Finding {
+    /// Returns whether the finding is a usual sign of path traversal or filter evasion,
+    /// rather than a harmless difference in spelling.
+    pub fn is_suspicious(self) -> bool {
+        !matches!(self, Finding::DuplicateSlash | Finding::DotSegment)
+    }
+}
+
+/// The result of normalizing a path.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NormalizedPath {
+    path: String,
+    query: Option<String>,
+    findings: Vec<Finding>,
+}
+
+impl NormalizedPath {
+    /// Returns the normalized path, without the query string.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn query(&self) -> Option<&str> {
+        self.query.as_deref()
+    }
+
+    pub fn findings(&self) -> &[Finding] {
+        &self.findings
+    }
+
+    pub fn is_suspicious(&self) -> bool {
+        self.findings.iter().any(|finding| finding.is_suspicious())
+    }
+
+    /// Returns the normalized path followed by the original query string.
+    pub fn to_uri(&self) -> String {
+        match &self.query {
+            Some(query) => format!("{}?{query}", self.path),
+            None => self.path.clone(),
+        }
+    }
+}
+
+/// Normalizes the path of a request URI like the `:path` pseudo-header. The query string
+/// is kept as it is and a fragment, if any, is dropped.
+pub fn normalize(uri: &str) -> NormalizedPath {
+    let uri = uri.split('#').next().unwrap_or_default();
+    let (raw, query) = match uri.split_once('?') {
+        Some((path, query)) => (path, Some(query.to_string())),
+        None => (uri, None),
+    };
+
+    let mut findings = Vec::new();
+    let mut note = |finding| {
+        if !findings.contains(&finding) {
+            findings.push(finding);
+        }
+    };
+
+    let mut segments: Vec<String> = Vec::new();
+    let mut raw_segments = raw.split('/').peekable();
+    let mut trailing_slash = false;
+
+    // Skip the root.
+    if raw.starts_with('/') {
+        raw_segments.next();
+    }
+
+    while let Some(raw_segment) = raw_segments.next() {
+        let last = raw_segments.peek().is_none();
+        trailing_slash = false;
+
+        if raw_segment.is_empty() {
+            if last {
+                trailing_slash = true;
+            } else {
+                note(Finding::DuplicateSlash);
+            }
+            continue;
+        }
+
+        if raw_segment.contains('\\') {
+            note(Finding::Backslash);
+        }
+
+        let segment = decode_unreserved(raw_segment, &mut note);
+        match segment.as_str() {
+            "." | ".." => {
+                if segment != raw_segment {
+                    note(Finding::EncodedDotSegment);
+                }
+                note(Finding::DotSegment);
+                if segment == ".." && segments.pop().is_none() {
+                    note(Finding::EscapesRoot);
+                }
+                trailing_slash = last;
+            }
+            _ => segments.push(segment),
+        }
+    }
+
+    let mut path = format!("/{}", segments.join("/"));
+    if trailing_slash && !segments.is_empty() {
+        path.push('/');
+    }
+
+    NormalizedPath {
+        path,
+        query,
+        findings,
+    }
+}
+
+/// Decodes the percent-encoded unreserved characters of a segment, as allowed by
+/// RFC 3986 section 6.2.2.2, and uppercases the remaining percent-encodings.
+fn decode_unreserved(segment: &str, note: &mut impl FnMut(Finding)) -> String {
+    if segment.contains('\0') {
+        note(Finding::NullCharacter);
+    }
+    if !segment.contains('%') {
+        return segment.to_string();
+    }
+
+    let mut decoded = String::with_capacity(segment.len());
+    let mut valid = true;
+    let mut rest = segment;
+    while let Some(index) = rest.find('%') {
+        decoded.push_str(&rest[..index]);
+        let encoded = &rest[index..];
+
+        match encoded
+            .get(1..3)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            None => {
+                note(Finding::InvalidEncoding);
+                valid = false;
+                decoded.push('%');
+                rest = &encoded[1..];
+                continue;
+            }
+            Some(byte) if is_unreserved(byte) => decoded.push(byte as char),
+            Some(byte) => {
+                match byte {
+                    b'/' | b'\\' => note(Finding::EncodedSeparator),
+                    0 => note(Finding::NullCharacter),
+                    _ => {}
+                }
+                decoded.push_str(&format!("%{byte:02X}"));
+            }
+        }
+        rest = &encoded[3..];
+    }
+    decoded.push_str(rest);
+
+    if valid && percent_decode(&decoded).is_none() {
+        note(Finding::InvalidUtf8);
+    }
+    decoded
+}
+
+fn is_unreserved(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize, Finding};
+
+    #[test]
+    fn unchanged_path() {
+        let normalized = normalize("/pets/12?sort=asc");
+
+        assert_eq!(normalized.path(), "/pets/12");
+        assert_eq!(normalized.query(), Some("sort=asc"));
+        assert!(normalized.findings().is_empty());
+        assert_eq!(normalized.to_uri(), "/pets/12?sort=asc");
+    }
+
+    #[test]
+    fn collapse_slashes_and_resolve_dots() {
+        let normalized = normalize("//pets/./12/../13/");
+
+        assert_eq!(normalized.path(), "/pets/13/");
+        assert_eq!(
+            normalized.findings(),
+            [Finding::DuplicateSlash, Finding::DotSegment]
+        );
+        assert!(!normalized.is_suspicious());
+    }
+
+    #[test]
+    fn encoded_traversal_is_suspicious() {
+        let normalized = normalize("/public/%2e%2E/admin");
+
+        assert_eq!(normalized.path(), "/admin");
+        assert!(normalized.findings().contains(&Finding::EncodedDotSegment));
+        assert!(normalized.is_suspicious());
+    }
+
+    #[test]
+    fn escaping_root_is_suspicious() {
+        let normalized = normalize("/../etc/passwd");
+
+        assert_eq!(normalized.path(), "/etc/passwd");
+        assert!(normalized.findings().contains(&Finding::EscapesRoot));
+    }
+
+    #[test]
+    fn decode_only_unreserved() {
+        let normalized = normalize("/%7Euser/a%2fb/c%3a");
+
+        assert_eq!(normalized.path(), "/~user/a%2Fb/c%3A");
+        assert_eq!(normalized.findings(), [Finding::EncodedSeparator]);
+    }
+
+    #[test]
+    fn malformed_encodings() {
+        assert_eq!(normalize("/a%zz").findings(), [Finding::InvalidEncoding]);
+        assert_eq!(normalize("/a%00").findings(), [Finding::NullCharacter]);
+        assert_eq!(normalize("/a%ff").findings(), [Finding::InvalidUtf8]);
+        assert_eq!(normalize("/a\\..\\b").findings(), [Finding::Backslash]);
+    }
+
+    #[test]
+    fn root_paths() {
+        assert_eq!(normalize("/").path(), "/");
+        assert_eq!(normalize("").path(), "/");
+        assert_eq!(normalize("/a/..").path(), "/");
+    }
+}
@@ -0,0 +1,5 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Query string editing that keeps the parameters it does not touch byte for byte. The
+//! implementation lives in [`classy::query`], so the events of classy share it.
+pub use classy::event::QueryParamsAccessor;
+pub use classy::query::{query_pairs, DuplicateParams, QueryDecoding, QueryParams};
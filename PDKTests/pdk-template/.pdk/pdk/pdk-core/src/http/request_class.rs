@@ -0,0 +1,302 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Classification of requests by the kind of their body, so the policies handling bodies share
+//! a single content type check.
+//!
+//! The class is taken from the `Content-Type` header. Requests without one are told apart by
+//! their `Accept` header for event streams, and by sniffing the start of their body when it is
+//! available.
+//!
+//! The class told by the headers is kept in the filter state, so the policies of the chain
+//! classify a request once.
+use std::fmt::{self, Display, Formatter};
+
+use classy::event::{EventData, HeadersAccessor, RequestHeaders};
+use serde::Deserialize;
+
+use super::header::ParameterizedValue;
+use crate::host::property::PropertyAccessor;
+
+const CONTENT_TYPE: &str = "content-type";
+const ACCEPT: &str = "accept";
+
+const EVENT_STREAM: &str = "text/event-stream";
+
+/// The kind of body of a request, usually configured as the classes a policy handles:
+///
+/// ```json
+/// "classes": ["json", "xml"]
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RequestClass {
+    /// `application/json`, `text/json` and the `+json` media types.
+    Json,
+    /// `application/xml`, `text/xml` and the `+xml` media types.
+    Xml,
+    /// `application/x-www-form-urlencoded`.
+    Form,
+    /// Any `multipart/` media type.
+    Multipart,
+    /// `application/grpc` and its `+proto`, `+json` and `-web` variants.
+    Grpc,
+    /// `text/event-stream`, or a request accepting only event streams.
+    Sse,
+    /// The remaining `text/` media types.
+    Text,
+    /// Any other media type.
+    Binary,
+    /// No content type and nothing to sniff.
+    Unknown,
+}
+
+impl RequestClass {
+    /// The class of a `Content-Type` header value, `None` when it has no media type.
+    pub fn from_content_type(value: &str) -> Option<Self> {
+        let media_type = ParameterizedValue::parse(value)
+            .value()
+            .to_ascii_lowercase();
+
+        let class = match media_type.as_str() {
+            "" => return None,
+            "application/json" | "text/json" => Self::Json,
+            "application/xml" | "text/xml" => Self::Xml,
+            "application/x-www-form-urlencoded" => Self::Form,
+            EVENT_STREAM => Self::Sse,
+            grpc if grpc.starts_with("application/grpc") => Self::Grpc,
+            json if json.ends_with("+json") => Self::Json,
+            xml if xml.ends_with("+xml") => Self::Xml,
+            multipart if multipart.starts_with("multipart/") => Self::Multipart,
+            text if text.starts_with("text/") => Self::Text,
+            _ => Self::Binary,
+        };
+
+        Some(class)
+    }
+
+    /// The class of a body without content type, from its first bytes.
+    pub fn sniff(body: &[u8]) -> Self {
+        let body = body.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(body);
+        let first = body.iter().find(|byte| !byte.is_ascii_whitespace());
+
+        match first {
+            None => Self::Unknown,
+            Some(b'{') | Some(b'[') => Self::Json,
+            Some(b'<') => Self::Xml,
+            // A cut body may end in the middle of a character.
+            _ => match std::str::from_utf8(body) {
+                Ok(text) if !text.contains('\0') => Self::Text,
+                Err(error) if error.error_len().is_none() => Self::Text,
+                _ => Self::Binary,
+            },
+        }
+    }
+
+    /// The class of a request with the `content_type` and `accept` headers and the optional
+    /// start of its `body`, which is only sniffed when the request has no content type.
+    pub fn detect(content_type: Option<&str>, accept: Option<&str>, body: Option<&[u8]>) -> Self {
+        if let Some(class) = content_type.and_then(Self::from_content_type) {
+            return class;
+        }
+
+        let accepts_events = accept.map_or(false, |accept| {
+            ParameterizedValue::parse(accept)
+                .value()
+                .eq_ignore_ascii_case(EVENT_STREAM)
+        });
+
+        match body {
+            _ if accepts_events => Self::Sse,
+            Some(body) => Self::sniff(body),
+            None => Self::Unknown,
+        }
+    }
+
+    /// The class of a [`RequestClass::name`].
+    pub fn from_name(name: &str) -> Option<Self> {
+        let class = match name {
+            "json" => Self::Json,
+            "xml" => Self::Xml,
+            "form" => Self::Form,
+            "multipart" => Self::Multipart,
+            "grpc" => Self::Grpc,
+            "sse" => Self::Sse,
+            "text" => Self::Text,
+            "binary" => Self::Binary,
+            "unknown" => Self::Unknown,
+            _ => return None,
+        };
+
+        Some(class)
+    }
+
+    /// The name of the class, as configured and given to expressions.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Xml => "xml",
+            Self::Form => "form",
+            Self::Multipart => "multipart",
+            Self::Grpc => "grpc",
+            Self::Sse => "sse",
+            Self::Text => "text",
+            Self::Binary => "binary",
+            Self::Unknown => "unknown",
+        }
+    }
+
+    /// Whether the body has a structure policies can parse and transform.
+    pub fn is_structured(self) -> bool {
+        matches!(self, Self::Json | Self::Xml | Self::Form)
+    }
+
+    /// Whether the body is a stream of messages, which must not be buffered.
+    pub fn is_streaming(self) -> bool {
+        matches!(self, Self::Grpc | Self::Sse)
+    }
+}
+
+impl Display for RequestClass {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The class kept in the filter state by a previous policy, or the one told by `classify`,
+/// which is then kept for the next ones.
+pub fn cached_class<F>(properties: &dyn PropertyAccessor, classify: F) -> RequestClass
+where
+    F: FnOnce() -> RequestClass,
+{
+    let filter_state = properties.filter_state();
+    if let Some(class) = filter_state.request_class() {
+        return class;
+    }
+
+    let class = classify();
+    filter_state.set_request_class(class);
+    class
+}
+
+/// Tells the class of a request.
+pub trait ClassifyRequest {
+    /// The class of the request, as told by its headers and kept in the filter state of the
+    /// host.
+    fn request_class(&self) -> RequestClass {
+        self.request_class_with(<dyn PropertyAccessor>::default())
+    }
+
+    /// The class of the request, as told by its headers and kept in the filter state of the
+    /// `properties`.
+    fn request_class_with(&self, properties: &dyn PropertyAccessor) -> RequestClass;
+}
+
+impl ClassifyRequest for EventData<'_, RequestHeaders> {
+    fn request_class_with(&self, properties: &dyn PropertyAccessor) -> RequestClass {
+        cached_class(properties, || {
+            // Only the headers the class depends on are read.
+            let mut headers = self.get_headers(&[CONTENT_TYPE, ACCEPT]).into_iter();
+            let content_type = headers.next().flatten();
+            let accept = headers.next().flatten();
+
+            RequestClass::detect(content_type.as_deref(), accept.as_deref(), None)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cached_class, RequestClass};
+    use crate::host::property::{InMemoryPropertyAccessor, PropertyAccessor};
+
+    #[test]
+    fn classes_from_content_type() {
+        let class = |value| RequestClass::from_content_type(value).unwrap();
+
+        assert_eq!(class("application/json; charset=utf-8"), RequestClass::Json);
+        assert_eq!(class("application/problem+json"), RequestClass::Json);
+        assert_eq!(class("Text/XML"), RequestClass::Xml);
+        assert_eq!(class("application/soap+xml"), RequestClass::Xml);
+        assert_eq!(
+            class("application/x-www-form-urlencoded"),
+            RequestClass::Form
+        );
+        assert_eq!(
+            class("multipart/form-data; boundary=\"a;b\""),
+            RequestClass::Multipart
+        );
+        assert_eq!(class("application/grpc+proto"), RequestClass::Grpc);
+        assert_eq!(class("application/grpc-web"), RequestClass::Grpc);
+        assert_eq!(class("text/event-stream"), RequestClass::Sse);
+        assert_eq!(class("text/csv"), RequestClass::Text);
+        assert_eq!(class("image/png"), RequestClass::Binary);
+
+        assert_eq!(RequestClass::from_content_type(" "), None);
+    }
+
+    #[test]
+    fn sniffed_classes() {
+        assert_eq!(
+            RequestClass::sniff(b"\xEF\xBB\xBF  {\"a\": 1}"),
+            RequestClass::Json
+        );
+        assert_eq!(RequestClass::sniff(b"\n<?xml?>"), RequestClass::Xml);
+        assert_eq!(
+            RequestClass::sniff("name=año".as_bytes()),
+            RequestClass::Text
+        );
+        assert_eq!(
+            RequestClass::sniff(&"año".as_bytes()[..2]),
+            RequestClass::Text
+        );
+        assert_eq!(RequestClass::sniff(b"\x89PNG\0\0"), RequestClass::Binary);
+        assert_eq!(RequestClass::sniff(b" \t"), RequestClass::Unknown);
+    }
+
+    #[test]
+    fn detected_classes() {
+        let body = Some(&b"[1, 2]"[..]);
+
+        assert_eq!(
+            RequestClass::detect(Some("application/xml"), None, body),
+            RequestClass::Xml
+        );
+        assert_eq!(RequestClass::detect(None, None, body), RequestClass::Json);
+        assert_eq!(
+            RequestClass::detect(None, Some("text/event-stream"), None),
+            RequestClass::Sse
+        );
+        assert_eq!(
+            RequestClass::detect(None, Some("*/*"), None),
+            RequestClass::Unknown
+        );
+    }
+
+    #[test]
+    fn configured_classes() {
+        let classes: Vec<RequestClass> = serde_json::from_str(r#"["json", "sse"]"#).unwrap();
+
+        assert_eq!(classes, [RequestClass::Json, RequestClass::Sse]);
+        assert!(RequestClass::Form.is_structured());
+        assert!(RequestClass::Grpc.is_streaming());
+    }
+
+    #[test]
+    fn classified_once_per_request() {
+        let properties: &dyn PropertyAccessor = &InMemoryPropertyAccessor::new();
+
+        assert_eq!(
+            cached_class(properties, || RequestClass::Xml),
+            RequestClass::Xml
+        );
+        assert_eq!(
+            cached_class(properties, || unreachable!("classified again")),
+            RequestClass::Xml
+        );
+        assert_eq!(
+            properties.filter_state().request_class(),
+            Some(RequestClass::Xml)
+        );
+        assert_eq!(RequestClass::from_name("grpc"), Some(RequestClass::Grpc));
+        assert_eq!(RequestClass::from_name("JSON"), None);
+    }
+}
@@ -0,0 +1,258 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! The `Server-Timing` response header, which tells client side performance tooling where
+//! the latency of a response was spent, like `gateway;dur=12.5, upstream;dur=10`.
+//!
+//! Policies record their own time in the filter state with
+//! [`FilterState::add_server_timing`](crate::host::property::FilterState::add_server_timing),
+//! and the policy emitting the header collects them.
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+
+pub const SERVER_TIMING: &str = "server-timing";
+
+/// A metric of the header, its name made of token characters only.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Metric {
+    name: String,
+    duration: Option<Duration>,
+    description: Option<String>,
+}
+
+impl Metric {
+    /// The characters of `name` not allowed in a token are replaced by `-`.
+    pub fn new(name: &str, duration: Duration) -> Self {
+        let name = name
+            .chars()
+            .map(|c| if is_token(c) { c } else { '-' })
+            .collect();
+        Self {
+            name,
+            duration: Some(duration),
+            description: None,
+        }
+    }
+
+    pub fn with_description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    /// Parses a metric, `name *( OWS ";" OWS param )`. Parameters that are malformed or
+    /// unknown are ignored, as the header wants.
+    fn parse(value: &str) -> Option<Self> {
+        let mut params = split_unquoted(value, ';').into_iter().map(str::trim);
+        let name = params.next().filter(|name| !name.is_empty())?;
+        if !name.chars().all(is_token) {
+            return None;
+        }
+
+        let mut metric = Self {
+            name: name.to_string(),
+            duration: None,
+            description: None,
+        };
+        for param in params {
+            let (key, value) = match param.split_once('=') {
+                Some((key, value)) => (key.trim_end(), value.trim_start()),
+                None => continue,
+            };
+            let value = match param_value(value) {
+                Some(value) => value,
+                None => continue,
+            };
+            // The first occurrence of a parameter wins.
+            if key.eq_ignore_ascii_case("dur") && metric.duration.is_none() {
+                metric.duration = value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|dur| dur.is_finite() && *dur >= 0.0)
+                    .map(|dur| Duration::from_micros((dur * 1000.0).round() as u64));
+            } else if key.eq_ignore_ascii_case("desc") && metric.description.is_none() {
+                metric.description = Some(value);
+            }
+        }
+        Some(metric)
+    }
+}
+
+/// Splits `value` on the `separator`s outside quoted strings, which can hold it escaped or not.
+fn split_unquoted(value: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (index, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            c if c == separator && !quoted => {
+                parts.push(&value[start..index]);
+                start = index + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&value[start..]);
+    parts
+}
+
+/// The value of a parameter, a token or a quoted string whose escapes are removed. None when
+/// it is neither, like a quoted string without its closing quote.
+fn param_value(value: &str) -> Option<String> {
+    let quoted = match value.strip_prefix('"') {
+        Some(quoted) => quoted,
+        None if !value.is_empty() && value.chars().all(is_token) => return Some(value.to_string()),
+        None => return None,
+    };
+
+    let mut unescaped = String::with_capacity(quoted.len());
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.push(chars.next()?),
+            // Nothing is allowed after the closing quote.
+            '"' => return chars.as_str().is_empty().then_some(unescaped),
+            c => unescaped.push(c),
+        }
+    }
+    None
+}
+
+impl Display for Metric {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)?;
+        if let Some(duration) = self.duration {
+            // Milliseconds, with a precision of microseconds.
+            let millis = (duration.as_micros() as f64) / 1000.0;
+            write!(f, ";dur={}", millis)?;
+        }
+        if let Some(description) = &self.description {
+            let escaped = description.replace('\\', "\\\\").replace('"', "\\\"");
+            write!(f, ";desc=\"{}\"", escaped)?;
+        }
+        Ok(())
+    }
+}
+
+fn is_token(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c)
+}
+
+/// The metrics of a `Server-Timing` header, in order.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ServerTiming {
+    metrics: Vec<Metric>,
+}
+
+impl ServerTiming {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The metrics of a header value. Malformed metrics are skipped, and the commas and
+    /// semicolons of quoted descriptions do not split them.
+    pub fn parse(value: &str) -> Self {
+        Self {
+            metrics: split_unquoted(value, ',')
+                .into_iter()
+                .filter_map(Metric::parse)
+                .collect(),
+        }
+    }
+
+    pub fn add(&mut self, metric: Metric) {
+        self.metrics.push(metric);
+    }
+
+    pub fn with(mut self, metric: Metric) -> Self {
+        self.add(metric);
+        self
+    }
+
+    /// Adds the metrics of `other` after the ones already present.
+    pub fn extend(&mut self, other: ServerTiming) {
+        self.metrics.extend(other.metrics);
+    }
+
+    pub fn metrics(&self) -> &[Metric] {
+        &self.metrics
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.metrics.is_empty()
+    }
+}
+
+impl Display for ServerTiming {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (index, metric) in self.metrics.iter().enumerate() {
+            if index > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}", metric)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Metric, ServerTiming};
+    use std::time::Duration;
+
+    #[test]
+    fn header_value() {
+        let timing = ServerTiming::new()
+            .with(Metric::new("gateway", Duration::from_micros(12_500)))
+            .with(Metric::new("rate limit", Duration::from_millis(2)).with_description("a \"b\""));
+
+        assert_eq!(
+            timing.to_string(),
+            r#"gateway;dur=12.5, rate-limit;dur=2;desc="a \"b\"""#
+        );
+    }
+
+    #[test]
+    fn parse_header_value() {
+        let timing = ServerTiming::parse("db;dur=53.2, cache;desc=\"hit\", bad name;dur=1, ;dur=2");
+
+        assert_eq!(timing.metrics().len(), 2);
+        assert_eq!(timing.metrics()[0].name(), "db");
+        assert_eq!(
+            timing.metrics()[0].duration(),
+            Some(Duration::from_micros(53_200))
+        );
+        assert_eq!(timing.metrics()[1].duration(), None);
+    }
+
+    #[test]
+    fn parse_quoted_descriptions() {
+        let timing = ServerTiming::parse(
+            r#"cdn;desc="hit, edge; \"fra\"";dur=1, db ; DUR = 2 ; dur=3;desc=token, x;desc="open"#,
+        );
+
+        assert_eq!(
+            timing.metrics(),
+            &[
+                Metric::new("cdn", Duration::from_millis(1))
+                    .with_description(r#"hit, edge; "fra""#),
+                Metric::new("db", Duration::from_millis(2)).with_description("token"),
+                Metric {
+                    name: "x".to_string(),
+                    duration: None,
+                    description: None,
+                },
+            ]
+        );
+        assert_eq!(ServerTiming::parse(&timing.to_string()), timing);
+    }
+}
@@ -0,0 +1,406 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! HMAC-SHA256 signatures of requests, over the elements of the request both ends agree on.
+//!
+//! The string signed has a line per element, in the order configured: the uppercase method for
+//! `@method`, the path with its query for `@path`, the lowercase hex SHA-256 of the body for
+//! `@body`, and the value of the header for any other name, empty when the header is absent.
+//! The signature is the base64 of the HMAC-SHA256 of the string:
+//!
+//! ```ignore
+//! let signer = RequestSigner::new(&config.secret, config.elements.clone())?;
+//!
+//! let signature = signer.sign(&event, &body);
+//! signer.verify(&event, &body, &signature)?;
+//! signer.verify_date(&event, now, Duration::from_secs(300))?;
+//! ```
+use std::convert::TryFrom;
+use std::fmt::{self, Write};
+use std::time::{Duration, SystemTime};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use classy::event::HeadersAccessor;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::error::{codes, PolicyError};
+use crate::http::header::parse_http_date;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The shortest secret accepted, in bytes.
+pub const MIN_SECRET_LENGTH: usize = 32;
+
+const METHOD: &str = "@method";
+const PATH: &str = "@path";
+const BODY: &str = "@body";
+const DATE: &str = "date";
+
+/// Why a request was not signed or verified.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum SignatureError {
+    #[error("the signing secret is shorter than {0} bytes")]
+    ShortSecret(usize),
+    #[error("no request element is signed")]
+    NoElements,
+    #[error("invalid signed element '{0}'")]
+    InvalidElement(String),
+    #[error("request signature is missing")]
+    Missing,
+    #[error("request signature is malformed")]
+    Malformed,
+    #[error("request signature is invalid")]
+    InvalidSignature,
+    #[error("signed request date is missing or malformed")]
+    MissingDate,
+    #[error("signed request date is outside the allowed skew")]
+    StaleDate,
+}
+
+impl From<SignatureError> for PolicyError {
+    fn from(error: SignatureError) -> Self {
+        let code = match error {
+            SignatureError::ShortSecret(_) => codes::INVALID_KEY,
+            SignatureError::NoElements | SignatureError::InvalidElement(_) => {
+                codes::INVALID_CONFIGURATION
+            }
+            SignatureError::Missing | SignatureError::Malformed | SignatureError::MissingDate => {
+                codes::MALFORMED_TOKEN
+            }
+            SignatureError::InvalidSignature => codes::INVALID_SIGNATURE,
+            SignatureError::StaleDate => codes::EXPIRED_TOKEN,
+        };
+        PolicyError::new(code, error.to_string())
+    }
+}
+
+/// An element of the request covered by the signature, parsed from `@method`, `@path`, `@body`
+/// or the name of a header.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum SignedElement {
+    Method,
+    Path,
+    /// The SHA-256 of the body, so the body can not be swapped.
+    BodyHash,
+    Header(String),
+}
+
+impl TryFrom<String> for SignedElement {
+    type Error = SignatureError;
+
+    fn try_from(element: String) -> Result<Self, Self::Error> {
+        match element.to_ascii_lowercase().as_str() {
+            METHOD => Ok(Self::Method),
+            PATH => Ok(Self::Path),
+            BODY => Ok(Self::BodyHash),
+            name if !name.is_empty() && !name.starts_with(['@', ':']) => {
+                Ok(Self::Header(name.to_string()))
+            }
+            _ => Err(SignatureError::InvalidElement(element)),
+        }
+    }
+}
+
+impl fmt::Display for SignedElement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Method => f.write_str(METHOD),
+            Self::Path => f.write_str(PATH),
+            Self::BodyHash => f.write_str(BODY),
+            Self::Header(name) => f.write_str(name),
+        }
+    }
+}
+
+/// Signs and verifies the requests with a secret shared with the other end.
+#[derive(Clone)]
+pub struct RequestSigner {
+    secret: Vec<u8>,
+    elements: Vec<SignedElement>,
+}
+
+impl fmt::Debug for RequestSigner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequestSigner")
+            .field("secret", &"<redacted>")
+            .field("elements", &self.elements)
+            .finish()
+    }
+}
+
+impl RequestSigner {
+    pub fn new(secret: &str, elements: Vec<SignedElement>) -> Result<Self, SignatureError> {
+        if secret.len() < MIN_SECRET_LENGTH {
+            return Err(SignatureError::ShortSecret(MIN_SECRET_LENGTH));
+        }
+        if elements.is_empty() {
+            return Err(SignatureError::NoElements);
+        }
+        Ok(Self {
+            secret: secret.as_bytes().to_vec(),
+            elements,
+        })
+    }
+
+    pub fn elements(&self) -> &[SignedElement] {
+        &self.elements
+    }
+
+    /// Whether the body is signed, so it has to be received before signing.
+    pub fn signs_body(&self) -> bool {
+        self.elements.contains(&SignedElement::BodyHash)
+    }
+
+    /// The string signed for the request with the `headers` and the `body`.
+    pub fn string_to_sign(&self, headers: &dyn HeadersAccessor, body: &[u8]) -> String {
+        let lines: Vec<String> = self
+            .elements
+            .iter()
+            .map(|element| match element {
+                SignedElement::Method => headers
+                    .header(":method")
+                    .unwrap_or_default()
+                    .to_ascii_uppercase(),
+                SignedElement::Path => headers.header(":path").unwrap_or_default(),
+                SignedElement::BodyHash => hex(&Sha256::digest(body)),
+                SignedElement::Header(name) => headers.header(name).unwrap_or_default(),
+            })
+            .collect();
+        lines.join("\n")
+    }
+
+    fn mac(&self, string_to_sign: &str) -> HmacSha256 {
+        // Hmac accepts keys of any length.
+        let mut mac = HmacSha256::new_from_slice(&self.secret).expect("any key length");
+        mac.update(string_to_sign.as_bytes());
+        mac
+    }
+
+    /// The base64 signature of the request.
+    pub fn sign(&self, headers: &dyn HeadersAccessor, body: &[u8]) -> String {
+        let mac = self.mac(&self.string_to_sign(headers, body));
+        STANDARD.encode(mac.finalize().into_bytes())
+    }
+
+    /// Whether the base64 `signature` is the one of the request, compared in constant time.
+    pub fn verify(
+        &self,
+        headers: &dyn HeadersAccessor,
+        body: &[u8],
+        signature: &str,
+    ) -> Result<(), SignatureError> {
+        let signature = STANDARD
+            .decode(signature.trim())
+            .map_err(|_| SignatureError::Malformed)?;
+        self.mac(&self.string_to_sign(headers, body))
+            .verify_slice(&signature)
+            .map_err(|_| SignatureError::InvalidSignature)
+    }
+
+    /// Whether the `date` header is signed, so its age can be checked.
+    pub fn signs_date(&self) -> bool {
+        self.elements
+            .iter()
+            .any(|element| matches!(element, SignedElement::Header(name) if name == DATE))
+    }
+
+    /// Whether the signed `date` of the request is within `max_skew` of `now`, in either
+    /// direction, so a captured request can only be replayed for so long. Requests are not
+    /// checked when the date is not signed.
+    pub fn verify_date(
+        &self,
+        headers: &dyn HeadersAccessor,
+        now: SystemTime,
+        max_skew: Duration,
+    ) -> Result<(), SignatureError> {
+        if !self.signs_date() {
+            return Ok(());
+        }
+        let date = headers
+            .header(DATE)
+            .and_then(|date| parse_http_date(&date))
+            .ok_or(SignatureError::MissingDate)?;
+        let skew = now
+            .duration_since(date)
+            .or_else(|_| date.duration_since(now))
+            .unwrap_or_default();
+        if skew > max_skew {
+            return Err(SignatureError::StaleDate);
+        }
+        Ok(())
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .fold(String::with_capacity(bytes.len() * 2), |mut hex, byte| {
+            let _ = write!(hex, "{:02x}", byte);
+            hex
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RequestSigner, SignatureError, SignedElement};
+    use classy::event::HeadersAccessor;
+    use std::cell::RefCell;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    const SECRET: &str = "0123456789abcdef0123456789abcdef";
+
+    struct Headers(RefCell<Vec<(String, String)>>);
+
+    impl Headers {
+        fn new(headers: &[(&str, &str)]) -> Self {
+            Self(RefCell::new(
+                headers
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect(),
+            ))
+        }
+    }
+
+    impl HeadersAccessor for Headers {
+        fn header(&self, name: &str) -> Option<String> {
+            self.0
+                .borrow()
+                .iter()
+                .find(|(header, _)| header.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.clone())
+        }
+
+        fn headers(&self) -> Vec<(String, String)> {
+            self.0.borrow().clone()
+        }
+
+        fn add_header(&self, name: &str, value: &str) {
+            self.0
+                .borrow_mut()
+                .push((name.to_string(), value.to_string()));
+        }
+
+        fn set_header(&self, name: &str, value: &str) {
+            self.remove_header(name);
+            self.add_header(name, value);
+        }
+
+        fn set_headers(&self, headers: Vec<(&str, &str)>) {
+            *self.0.borrow_mut() = headers
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect();
+        }
+
+        fn remove_header(&self, name: &str) {
+            self.0
+                .borrow_mut()
+                .retain(|(header, _)| !header.eq_ignore_ascii_case(name));
+        }
+    }
+
+    fn signer() -> RequestSigner {
+        let elements = ["@method", "@path", "Date", "@body"]
+            .iter()
+            .map(|element| serde_json::from_value(serde_json::json!(element)).unwrap())
+            .collect();
+        RequestSigner::new(SECRET, elements).unwrap()
+    }
+
+    fn request() -> Headers {
+        Headers::new(&[
+            (":method", "post"),
+            (":path", "/orders?page=2"),
+            ("date", "Tue, 15 Oct 2024 10:00:00 GMT"),
+        ])
+    }
+
+    #[test]
+    fn string_to_sign_has_a_line_per_element() {
+        assert_eq!(
+            signer().string_to_sign(&request(), b"{}"),
+            "POST\n/orders?page=2\nTue, 15 Oct 2024 10:00:00 GMT\n\
+             44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a"
+        );
+    }
+
+    #[test]
+    fn signatures_are_verified() {
+        let signer = signer();
+        let signature = signer.sign(&request(), b"{}");
+        assert_eq!(signer.verify(&request(), b"{}", &signature), Ok(()));
+
+        assert_eq!(
+            signer.verify(&request(), b"{\"total\":1}", &signature),
+            Err(SignatureError::InvalidSignature)
+        );
+        let other = Headers::new(&[(":method", "POST"), (":path", "/orders")]);
+        assert_eq!(
+            signer.verify(&other, b"{}", &signature),
+            Err(SignatureError::InvalidSignature)
+        );
+        assert_eq!(
+            signer.verify(&request(), b"{}", "not base64!"),
+            Err(SignatureError::Malformed)
+        );
+    }
+
+    #[test]
+    fn signers_are_validated() {
+        assert_eq!(
+            RequestSigner::new("short", vec![SignedElement::Method]).err(),
+            Some(SignatureError::ShortSecret(32))
+        );
+        assert_eq!(
+            RequestSigner::new(SECRET, vec![]).err(),
+            Some(SignatureError::NoElements)
+        );
+        assert!(serde_json::from_str::<SignedElement>(r#"":authority""#).is_err());
+        assert!(serde_json::from_str::<SignedElement>(r#""@query""#).is_err());
+    }
+
+    #[test]
+    fn signed_dates_are_within_the_skew() {
+        let signer = signer();
+        let date = UNIX_EPOCH + Duration::from_secs(1_728_986_400);
+        let skew = Duration::from_secs(300);
+
+        assert_eq!(signer.verify_date(&request(), date, skew), Ok(()));
+        assert_eq!(signer.verify_date(&request(), date + skew, skew), Ok(()));
+        assert_eq!(signer.verify_date(&request(), date - skew, skew), Ok(()));
+        assert_eq!(
+            signer.verify_date(&request(), date + skew + Duration::from_secs(1), skew),
+            Err(SignatureError::StaleDate)
+        );
+        assert_eq!(
+            signer.verify_date(&request(), date - skew - Duration::from_secs(1), skew),
+            Err(SignatureError::StaleDate)
+        );
+    }
+
+    #[test]
+    fn signed_dates_are_required() {
+        let signer = signer();
+        let now = UNIX_EPOCH + Duration::from_secs(1_728_986_400);
+        let skew = Duration::from_secs(300);
+
+        let undated = Headers::new(&[(":method", "POST"), (":path", "/orders")]);
+        assert_eq!(
+            signer.verify_date(&undated, now, skew),
+            Err(SignatureError::MissingDate)
+        );
+        let malformed = Headers::new(&[("date", "yesterday")]);
+        assert_eq!(
+            signer.verify_date(&malformed, now, skew),
+            Err(SignatureError::MissingDate)
+        );
+
+        // The requests whose date is not signed are not checked.
+        let unsigned = RequestSigner::new(SECRET, vec![SignedElement::Method]).unwrap();
+        assert!(!unsigned.signs_date());
+        assert_eq!(unsigned.verify_date(&undated, now, skew), Ok(()));
+    }
+}
@@ -0,0 +1,487 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! URL templates like `/pets/{petId:int}/toys/{toyId}` matched against request paths.
+//!
+//! Templates are compiled once, typically when the policy is configured, and matched
+//! segment by segment without allocating for the literal parts.
+use std::convert::TryFrom;
+use std::fmt::{self, Display, Formatter};
+
+use serde::Deserialize;
+
+use super::path::percent_decode;
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum UrlTemplateError {
+    #[error("Unclosed capture in segment '{0}'")]
+    UnclosedCapture(String),
+    #[error("Segment '{0}' has more than one capture")]
+    MultipleCaptures(String),
+    #[error("Invalid capture name '{0}'")]
+    InvalidName(String),
+    #[error("Unknown type '{1}' for capture '{0}'")]
+    UnknownType(String, String),
+    #[error("Capture '{0}' is defined more than once")]
+    DuplicatedCapture(String),
+    #[error("Path capture '{0}' must be the last segment")]
+    MisplacedPath(String),
+}
+
+/// The type a captured segment must conform to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptureType {
+    String,
+    Integer,
+    Number,
+    Bool,
+    Uuid,
+    /// Captures the remaining segments of the path, slashes included.
+    Path,
+}
+
+impl CaptureType {
+    fn parse(name: &str, kind: &str) -> Result<Self, UrlTemplateError> {
+        match kind {
+            "string" => Ok(CaptureType::String),
+            "int" => Ok(CaptureType::Integer),
+            "number" => Ok(CaptureType::Number),
+            "bool" => Ok(CaptureType::Bool),
+            "uuid" => Ok(CaptureType::Uuid),
+            "path" => Ok(CaptureType::Path),
+            _ => Err(UrlTemplateError::UnknownType(
+                name.to_string(),
+                kind.to_string(),
+            )),
+        }
+    }
+
+    fn convert(self, value: String) -> Option<CaptureValue> {
+        match self {
+            CaptureType::String | CaptureType::Path => Some(CaptureValue::String(value)),
+            CaptureType::Integer => value.parse().ok().map(CaptureValue::Integer),
+            CaptureType::Number => value
+                .parse::<f64>()
+                .ok()
+                .filter(|number| number.is_finite())
+                .map(CaptureValue::Number),
+            CaptureType::Bool => value.parse().ok().map(CaptureValue::Bool),
+            CaptureType::Uuid => is_uuid(&value).then_some(CaptureValue::String(value)),
+        }
+    }
+}
+
+fn is_uuid(value: &str) -> bool {
+    value.len() == 36
+        && value.char_indices().all(|(index, c)| match index {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        })
+}
+
+/// A value captured from a path, converted to the type declared by the template.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CaptureValue {
+    String(String),
+    Integer(i64),
+    Number(f64),
+    Bool(bool),
+}
+
+/// The values captured when a path matches a [`UrlTemplate`], in template order.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Captures {
+    values: Vec<(String, CaptureValue)>,
+}
+
+impl Captures {
+    pub fn get(&self, name: &str) -> Option<&CaptureValue> {
+        self.values
+            .iter()
+            .find(|(capture, _)| capture == name)
+            .map(|(_, value)| value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &CaptureValue)> {
+        self.values
+            .iter()
+            .map(|(name, value)| (name.as_str(), value))
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+impl IntoIterator for Captures {
+    type Item = (String, CaptureValue);
+    type IntoIter = std::vec::IntoIter<(String, CaptureValue)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.values.into_iter()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Capture {
+        prefix: String,
+        name: String,
+        kind: CaptureType,
+        suffix: String,
+    },
+}
+
+impl Segment {
+    fn parse(segment: &str) -> Result<Self, UrlTemplateError> {
+        let start = match segment.find('{') {
+            None => return Ok(Segment::Literal(segment.to_string())),
+            Some(start) => start,
+        };
+        let end = segment[start..]
+            .find('}')
+            .map(|end| start + end)
+            .ok_or_else(|| UrlTemplateError::UnclosedCapture(segment.to_string()))?;
+
+        let (prefix, suffix) = (&segment[..start], &segment[end + 1..]);
+        if suffix.contains('{') || suffix.contains('}') || prefix.contains('}') {
+            return Err(UrlTemplateError::MultipleCaptures(segment.to_string()));
+        }
+
+        let capture = &segment[start + 1..end];
+        let (name, kind) = match capture.split_once(':') {
+            Some((name, kind)) => (name.trim(), CaptureType::parse(name, kind.trim())?),
+            None => (capture.trim(), CaptureType::String),
+        };
+        let valid_name = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if !valid_name {
+            return Err(UrlTemplateError::InvalidName(name.to_string()));
+        }
+
+        Ok(Segment::Capture {
+            prefix: prefix.to_string(),
+            name: name.to_string(),
+            kind,
+            suffix: suffix.to_string(),
+        })
+    }
+}
+
+/// A compiled URL template.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "String")]
+pub struct UrlTemplate {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+impl UrlTemplate {
+    pub fn parse(template: &str) -> Result<Self, UrlTemplateError> {
+        let segments = split_path(template)
+            .map(Segment::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut names = Vec::new();
+        for (index, segment) in segments.iter().enumerate() {
+            if let Segment::Capture { name, kind, .. } = segment {
+                if names.contains(&name) {
+                    return Err(UrlTemplateError::DuplicatedCapture(name.clone()));
+                }
+                if *kind == CaptureType::Path && index + 1 != segments.len() {
+                    return Err(UrlTemplateError::MisplacedPath(name.clone()));
+                }
+                names.push(name);
+            }
+        }
+
+        Ok(Self {
+            source: template.to_string(),
+            segments,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Returns the names of the captures, in template order.
+    pub fn captures(&self) -> impl Iterator<Item = &str> {
+        self.segments.iter().filter_map(|segment| match segment {
+            Segment::Capture { name, .. } => Some(name.as_str()),
+            Segment::Literal(_) => None,
+        })
+    }
+
+    /// Matches `path` against the template, ignoring its query string, its fragment and a
+    /// trailing slash. Captured values are percent-decoded before being converted.
+    pub fn matches(&self, path: &str) -> Option<Captures> {
+        let path = path
+            .split(['?', '#'])
+            .next()
+            .unwrap_or_default();
+        let path = path.strip_prefix('/').unwrap_or(path);
+        let mut remaining = Some(path.strip_suffix('/').unwrap_or(path));
+        let mut values = Vec::new();
+
+        for segment in &self.segments {
+            let current = remaining?;
+
+            if let Segment::Capture {
+                prefix,
+                name,
+                kind: CaptureType::Path,
+                ..
+            } = segment
+            {
+                let value = current
+                    .strip_prefix(prefix.as_str())
+                    .filter(|value| !value.is_empty())?;
+                let value = CaptureType::Path.convert(percent_decode(value)?)?;
+                values.push((name.clone(), value));
+                return Some(Captures { values });
+            }
+
+            let (current, rest) = match current.split_once('/') {
+                Some((current, rest)) => (current, Some(rest)),
+                None => (current, None),
+            };
+            remaining = rest;
+
+            match segment {
+                Segment::Literal(literal) => {
+                    if current != literal {
+                        return None;
+                    }
+                }
+                Segment::Capture {
+                    prefix,
+                    name,
+                    kind,
+                    suffix,
+                } => {
+                    let value = current
+                        .strip_prefix(prefix.as_str())?
+                        .strip_suffix(suffix.as_str())
+                        .filter(|value| !value.is_empty())?;
+                    values.push((name.clone(), kind.convert(percent_decode(value)?)?));
+                }
+            }
+        }
+
+        remaining.is_none().then_some(Captures { values })
+    }
+
+    fn literals(&self) -> usize {
+        self.segments
+            .iter()
+            .filter(|segment| matches!(segment, Segment::Literal(_)))
+            .count()
+    }
+
+    /// Orders templates so that the most specific is tried first: literal segments take
+    /// precedence over captures, and path captures come last.
+    fn specificity(&self) -> (usize, usize, bool) {
+        let path = self.segments.iter().any(|segment| {
+            matches!(
+                segment,
+                Segment::Capture {
+                    kind: CaptureType::Path,
+                    ..
+                }
+            )
+        });
+        (self.literals(), self.segments.len(), !path)
+    }
+}
+
+impl TryFrom<String> for UrlTemplate {
+    type Error = UrlTemplateError;
+
+    fn try_from(template: String) -> Result<Self, Self::Error> {
+        UrlTemplate::parse(&template)
+    }
+}
+
+impl Display for UrlTemplate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl PartialEq for UrlTemplate {
+    fn eq(&self, other: &Self) -> bool {
+        self.segments == other.segments
+    }
+}
+
+/// A set of templates, each associated to a value like a route or a per-route config.
+pub struct UrlTemplates<T> {
+    templates: Vec<(UrlTemplate, T)>,
+}
+
+impl<T> Default for UrlTemplates<T> {
+    fn default() -> Self {
+        Self {
+            templates: Vec::new(),
+        }
+    }
+}
+
+impl<T> UrlTemplates<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_template(mut self, template: UrlTemplate, value: T) -> Self {
+        self.add(template, value);
+        self
+    }
+
+    pub fn add(&mut self, template: UrlTemplate, value: T) {
+        let specificity = template.specificity();
+        let position = self
+            .templates
+            .iter()
+            .position(|(existing, _)| existing.specificity() < specificity)
+            .unwrap_or(self.templates.len());
+        self.templates.insert(position, (template, value));
+    }
+
+    pub fn len(&self) -> usize {
+        self.templates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.templates.is_empty()
+    }
+
+    /// Returns the most specific template matching `path`, with its value and captures.
+    pub fn matches(&self, path: &str) -> Option<(&UrlTemplate, &T, Captures)> {
+        self.templates.iter().find_map(|(template, value)| {
+            template
+                .matches(path)
+                .map(|captures| (template, value, captures))
+        })
+    }
+}
+
+fn split_path(path: &str) -> impl Iterator<Item = &str> {
+    let path = path.strip_prefix('/').unwrap_or(path);
+    let path = path.strip_suffix('/').unwrap_or(path);
+    path.split('/')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CaptureValue, UrlTemplate, UrlTemplateError, UrlTemplates};
+
+    #[test]
+    fn match_typed_captures() {
+        let template = UrlTemplate::parse("/pets/{petId:int}/toys/{toyId}").unwrap();
+
+        let captures = template
+            .matches("/pets/12/toys/ball%20red?sort=asc")
+            .unwrap();
+
+        assert_eq!(captures.get("petId"), Some(&CaptureValue::Integer(12)));
+        assert_eq!(
+            captures.get("toyId"),
+            Some(&CaptureValue::String("ball red".to_string()))
+        );
+        assert_eq!(template.captures().collect::<Vec<_>>(), ["petId", "toyId"]);
+    }
+
+    #[test]
+    fn reject_mismatches() {
+        let template = UrlTemplate::parse("/pets/{petId:int}/toys").unwrap();
+
+        assert!(template.matches("/pets/12/toys").is_some());
+        assert!(template.matches("/pets/12/toys/").is_some());
+        assert!(template.matches("/pets/rex/toys").is_none());
+        assert!(template.matches("/pets/12").is_none());
+        assert!(template.matches("/pets//toys").is_none());
+        assert!(template.matches("/pets/12/toys/1").is_none());
+        assert!(template.matches("/cats/12/toys").is_none());
+    }
+
+    #[test]
+    fn match_prefix_suffix_and_path() {
+        let file = UrlTemplate::parse("/files/v{version:number}/{name}.json").unwrap();
+        let captures = file.matches("/files/v1.5/report.json").unwrap();
+        assert_eq!(captures.get("version"), Some(&CaptureValue::Number(1.5)));
+        assert_eq!(
+            captures.get("name"),
+            Some(&CaptureValue::String("report".to_string()))
+        );
+        assert!(file.matches("/files/v1.5/report.xml").is_none());
+
+        let static_files = UrlTemplate::parse("/static/{file:path}").unwrap();
+        let captures = static_files.matches("/static/css/site.css").unwrap();
+        assert_eq!(
+            captures.get("file"),
+            Some(&CaptureValue::String("css/site.css".to_string()))
+        );
+        assert!(static_files.matches("/static").is_none());
+    }
+
+    #[test]
+    fn match_uuid_and_bool() {
+        let template = UrlTemplate::parse("/orders/{id:uuid}/{paid:bool}").unwrap();
+
+        assert!(template
+            .matches("/orders/123e4567-e89b-12d3-a456-426614174000/true")
+            .is_some());
+        assert!(template.matches("/orders/123e4567/true").is_none());
+        assert!(template
+            .matches("/orders/123e4567-e89b-12d3-a456-426614174000/yes")
+            .is_none());
+    }
+
+    #[test]
+    fn invalid_templates() {
+        assert_eq!(
+            UrlTemplate::parse("/pets/{petId").unwrap_err(),
+            UrlTemplateError::UnclosedCapture("{petId".to_string())
+        );
+        assert_eq!(
+            UrlTemplate::parse("/pets/{a}{b}").unwrap_err(),
+            UrlTemplateError::MultipleCaptures("{a}{b}".to_string())
+        );
+        assert_eq!(
+            UrlTemplate::parse("/pets/{}").unwrap_err(),
+            UrlTemplateError::InvalidName("".to_string())
+        );
+        assert_eq!(
+            UrlTemplate::parse("/pets/{id:date}").unwrap_err(),
+            UrlTemplateError::UnknownType("id".to_string(), "date".to_string())
+        );
+        assert_eq!(
+            UrlTemplate::parse("/{id}/{id}").unwrap_err(),
+            UrlTemplateError::DuplicatedCapture("id".to_string())
+        );
+        assert_eq!(
+            UrlTemplate::parse("/{rest:path}/more").unwrap_err(),
+            UrlTemplateError::MisplacedPath("rest".to_string())
+        );
+    }
+
+    #[test]
+    fn most_specific_template_wins() {
+        let templates = UrlTemplates::new()
+            .with_template(UrlTemplate::parse("/{rest:path}").unwrap(), "fallback")
+            .with_template(UrlTemplate::parse("/pets/{petId}").unwrap(), "pet")
+            .with_template(UrlTemplate::parse("/pets/mine").unwrap(), "mine");
+
+        let route = |path| templates.matches(path).map(|(_, route, _)| *route);
+
+        assert_eq!(route("/pets/mine"), Some("mine"));
+        assert_eq!(route("/pets/rex"), Some("pet"));
+        assert_eq!(route("/stores/1"), Some("fallback"));
+    }
+}
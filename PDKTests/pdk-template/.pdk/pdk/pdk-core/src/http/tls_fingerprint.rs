@@ -0,0 +1,174 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Fingerprint of the TLS clients, telling apart the client stacks of a botnet from the
+//! browsers and SDKs of the legit users.
+//!
+//! The host doesn't give the cipher suites and extensions of the `ClientHello` to policies, so
+//! the fingerprint is made of the handshake attributes it does give, like the first section of
+//! a [JA4](https://github.com/FoxIO-LLC/ja4) fingerprint without the counts of ciphers and
+//! extensions:
+//!
+//! ```text
+//! t13dh2
+//! │││ └ the first and last characters of the negotiated ALPN, 00 without one
+//! ││└── d with a server name (SNI), i without one
+//! │└─── the TLS version, 13 for TLS 1.3
+//! └──── t over TCP, q over QUIC
+//! ```
+//!
+//! Gateways behind a TLS terminator computing a full JA3 or JA4 fingerprint should trust the
+//! one it forwards instead.
+use std::fmt::{self, Display, Formatter};
+use std::net::IpAddr;
+
+use crate::host::property::PropertyAccessor;
+
+/// The TLS handshake attributes of the connection of a request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TlsFingerprint {
+    version: String,
+    server_name: Option<String>,
+    alpn: Option<String>,
+    quic: bool,
+    mtls: bool,
+}
+
+impl TlsFingerprint {
+    /// The attributes of a handshake, from the TLS version, the requested server name, the
+    /// HTTP protocol of the request and whether the client presented a certificate.
+    pub fn new(
+        version: &str,
+        server_name: Option<&str>,
+        protocol: Option<&str>,
+        mtls: bool,
+    ) -> Self {
+        let protocol = protocol.map(str::to_ascii_uppercase);
+        let alpn = match protocol.as_deref() {
+            Some("HTTP/1.0") => Some("http/1.0"),
+            Some("HTTP/1.1") => Some("http/1.1"),
+            Some("HTTP/2") => Some("h2"),
+            Some("HTTP/3") => Some("h3"),
+            _ => None,
+        };
+
+        Self {
+            version: version.to_string(),
+            server_name: server_name
+                .filter(|name| !name.is_empty())
+                .map(str::to_string),
+            alpn: alpn.map(str::to_string),
+            quic: protocol.as_deref() == Some("HTTP/3"),
+            mtls,
+        }
+    }
+
+    /// The attributes of the connection of the current request, `None` when it is not over TLS.
+    pub fn read(properties: &dyn PropertyAccessor) -> Option<Self> {
+        let tls = properties.tls();
+        let version = tls.tls_version().ok().flatten()?;
+        let server_name = tls.requested_server_name().ok().flatten();
+        let protocol = properties.request().protocol().ok().flatten();
+
+        Some(Self::new(
+            &version,
+            server_name.as_deref(),
+            protocol.as_deref(),
+            tls.mtls(),
+        ))
+    }
+
+    /// The TLS version, like `TLSv1.3`.
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// The server name requested with SNI.
+    pub fn server_name(&self) -> Option<&str> {
+        self.server_name.as_deref()
+    }
+
+    /// The protocol negotiated with ALPN, taken from the HTTP protocol of the request.
+    pub fn alpn(&self) -> Option<&str> {
+        self.alpn.as_deref()
+    }
+
+    /// Whether the client presented a certificate.
+    pub fn mtls(&self) -> bool {
+        self.mtls
+    }
+
+    /// The fingerprint of the handshake, like `t13dh2`.
+    pub fn fingerprint(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Display for TlsFingerprint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let transport = if self.quic { 'q' } else { 't' };
+        let version = match self.version.as_str() {
+            "TLSv1.3" => "13",
+            "TLSv1.2" => "12",
+            "TLSv1.1" => "11",
+            "TLSv1" | "TLSv1.0" => "10",
+            _ => "00",
+        };
+        // Clients sending an IP address as server name are fingerprinted as sending none.
+        let sni = match &self.server_name {
+            Some(name) if name.parse::<IpAddr>().is_err() => 'd',
+            _ => 'i',
+        };
+        let alpn = self
+            .alpn
+            .as_deref()
+            .and_then(|alpn| Some((alpn.chars().next()?, alpn.chars().last()?)));
+
+        write!(f, "{}{}{}", transport, version, sni)?;
+        match alpn {
+            Some((first, last)) => write!(f, "{}{}", first, last),
+            None => write!(f, "00"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TlsFingerprint;
+    use crate::host::property::InMemoryPropertyAccessor;
+
+    #[test]
+    fn fingerprints() {
+        let browser =
+            TlsFingerprint::new("TLSv1.3", Some("api.example.com"), Some("HTTP/2"), false);
+        assert_eq!(browser.fingerprint(), "t13dh2");
+        assert_eq!(browser.alpn(), Some("h2"));
+
+        let script = TlsFingerprint::new("TLSv1.2", Some("10.0.0.1"), Some("HTTP/1.1"), false);
+        assert_eq!(script.fingerprint(), "t12ih1");
+
+        let quic = TlsFingerprint::new("TLSv1.3", None, Some("HTTP/3"), true);
+        assert_eq!(quic.fingerprint(), "q13ih3");
+        assert!(quic.mtls());
+
+        let unknown = TlsFingerprint::new("SSLv3", Some(""), None, false);
+        assert_eq!(unknown.fingerprint(), "t00i00");
+        assert_eq!(unknown.server_name(), None);
+    }
+
+    #[test]
+    fn read_from_properties() {
+        let plaintext =
+            InMemoryPropertyAccessor::new().with_property(&["request", "protocol"], b"HTTP/1.1");
+        assert_eq!(TlsFingerprint::read(&plaintext), None);
+
+        let properties = InMemoryPropertyAccessor::new()
+            .with_property(&["connection", "tls_version"], b"TLSv1.3")
+            .with_property(&["connection", "requested_server_name"], b"api.example.com")
+            .with_property(&["connection", "mtls"], &[1])
+            .with_property(&["request", "protocol"], b"HTTP/1.1");
+
+        let fingerprint = TlsFingerprint::read(&properties).unwrap();
+        assert_eq!(fingerprint.fingerprint(), "t13dh1");
+        assert_eq!(fingerprint.version(), "TLSv1.3");
+        assert!(fingerprint.mtls());
+    }
+}
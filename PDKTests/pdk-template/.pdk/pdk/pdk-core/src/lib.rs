@@ -1,10 +1,17 @@
 // Copyright 2023 Salesforce, Inc. All rights reserved.
 mod middleware;
 
+pub mod config;
+pub mod error;
+pub mod hash;
 pub mod host;
+pub mod http;
 pub mod init;
+pub mod keys;
 pub mod log;
 pub mod policy_context;
+#[cfg(feature = "regex-cache")]
+pub mod regex_cache;
 
 pub use crate::log as logger;
 pub use classy;
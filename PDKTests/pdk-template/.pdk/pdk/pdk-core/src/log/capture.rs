@@ -0,0 +1,310 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Debug capture of single requests, so operators can troubleshoot a request in production
+//! without lowering the log level of the whole policy.
+//!
+//! A request carrying a debug header signed with the capture secret has its debug and trace
+//! records, like the expression traces and the callout timings, logged at info level. The
+//! other requests keep the configured level. The header reads `<expires>.<mac>`, the expiry in
+//! seconds since the epoch and the mac the base64url HMAC-SHA256 of the expiry, and must expire
+//! within [`MAX_TOKEN_LIFETIME`]:
+//!
+//! ```ignore
+//! let header = capture::sign(secret.as_bytes(), now + 60);
+//! ```
+//!
+//! The secret is taken from the `debugCapture` of the logging configuration of the policy, or
+//! given by the policy itself with [`enable`].
+//!
+//! The verified header is removed so it does not reach the upstream. The next policies of the
+//! chain capture the request from its debug capture property instead.
+use std::cell::{Cell, RefCell};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use classy::event::{EventData, HeadersAccessor, RequestHeaders};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::error::{codes, PolicyError};
+use crate::host::property::PropertyAccessor;
+use crate::log::logger;
+use crate::HostTrait;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The header of the requests to capture, unless configured otherwise.
+pub const DEBUG_CAPTURE_HEADER: &str = "x-debug-capture";
+
+/// How long a debug header can be valid for, so a leaked one is soon useless.
+pub const MAX_TOKEN_LIFETIME: Duration = Duration::from_secs(900);
+
+const SEPARATOR: char = '.';
+
+thread_local! {
+    static CAPTURE: RefCell<Option<DebugCapture>> = RefCell::new(None);
+    // Until when, in seconds since the epoch, the records above the configured level are
+    // produced, to be logged for the captured requests only.
+    static OPEN_UNTIL: Cell<u64> = Cell::new(0);
+    // The captured requests not done yet.
+    static IN_FLIGHT: Cell<u32> = Cell::new(0);
+}
+
+/// Why a debug header was not accepted.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum CaptureError {
+    #[error("debug header is malformed")]
+    Malformed,
+    #[error("debug header signature is invalid")]
+    InvalidSignature,
+    #[error("debug header expired")]
+    Expired,
+    #[error("debug header is valid for longer than {} seconds", MAX_TOKEN_LIFETIME.as_secs())]
+    TooLong,
+}
+
+impl From<CaptureError> for PolicyError {
+    fn from(error: CaptureError) -> Self {
+        let code = match error {
+            CaptureError::Malformed | CaptureError::TooLong => codes::MALFORMED_TOKEN,
+            CaptureError::InvalidSignature => codes::INVALID_SIGNATURE,
+            CaptureError::Expired => codes::EXPIRED_TOKEN,
+        };
+        PolicyError::new(code, error.to_string())
+    }
+}
+
+/// Verifies the debug headers of the requests.
+pub struct DebugCapture {
+    secret: Vec<u8>,
+    header: String,
+}
+
+impl DebugCapture {
+    pub fn new(secret: &str) -> Self {
+        Self {
+            secret: secret.as_bytes().to_vec(),
+            header: DEBUG_CAPTURE_HEADER.to_string(),
+        }
+    }
+
+    pub fn with_header(mut self, header: &str) -> Self {
+        self.header = header.to_string();
+        self
+    }
+
+    /// The expiry of the debug header, in seconds since the epoch.
+    pub fn verify(&self, value: &str, now: SystemTime) -> Result<u64, CaptureError> {
+        let (expires, mac) = value
+            .trim()
+            .split_once(SEPARATOR)
+            .ok_or(CaptureError::Malformed)?;
+        let mac = URL_SAFE_NO_PAD
+            .decode(mac)
+            .map_err(|_| CaptureError::Malformed)?;
+        mac_of(&self.secret, expires)
+            .verify_slice(&mac)
+            .map_err(|_| CaptureError::InvalidSignature)?;
+
+        let expires = expires
+            .parse::<u64>()
+            .map_err(|_| CaptureError::Malformed)?;
+        let now = seconds(now);
+        if expires <= now {
+            Err(CaptureError::Expired)
+        } else if expires - now > MAX_TOKEN_LIFETIME.as_secs() {
+            Err(CaptureError::TooLong)
+        } else {
+            Ok(expires)
+        }
+    }
+}
+
+fn mac_of(secret: &[u8], expires: &str) -> HmacSha256 {
+    // Hmac accepts keys of any length.
+    let mut mac = HmacSha256::new_from_slice(secret).expect("any key length");
+    mac.update(expires.as_bytes());
+    mac
+}
+
+fn seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// The debug header capturing the requests until `expires`, in seconds since the epoch.
+pub fn sign(secret: &[u8], expires: u64) -> String {
+    let expires = expires.to_string();
+    let mac = mac_of(secret, &expires).finalize().into_bytes();
+    format!("{}{}{}", expires, SEPARATOR, URL_SAFE_NO_PAD.encode(mac))
+}
+
+/// Captures the requests with a debug header verified by `capture`, replacing the one of the
+/// logging configuration.
+pub fn enable(capture: DebugCapture) {
+    CAPTURE.with(|current| *current.borrow_mut() = Some(capture));
+}
+
+/// Marks the request captured when its debug header is valid, or when an earlier policy of
+/// the chain captured it. The verified header is removed.
+pub(crate) fn start(event: &EventData<RequestHeaders>, properties: &dyn PropertyAccessor) {
+    CAPTURE.with(|capture| {
+        let capture = capture.borrow();
+        let capture = match capture.as_ref() {
+            Some(capture) => capture,
+            None => return,
+        };
+
+        let now = crate::Host.get_current_time();
+        if properties.tracing().debug_capture() {
+            open(seconds(now), seconds(now));
+            return;
+        }
+        let value = match event.header(&capture.header) {
+            Some(value) => value,
+            None => return,
+        };
+
+        match capture.verify(&value, now) {
+            Ok(expires) => {
+                event.remove_header(&capture.header);
+                properties.tracing().set_debug_capture();
+                open(seconds(now), expires);
+                log::info!("Capturing the debug records of the request.");
+            }
+            Err(err) => log::warn!("Ignoring the debug capture header: {}.", err),
+        }
+    });
+}
+
+/// Ends the capture of the request, if captured.
+pub(crate) fn done(properties: &dyn PropertyAccessor) {
+    if OPEN_UNTIL.with(Cell::get) != 0 && properties.tracing().debug_capture() {
+        finish();
+    }
+}
+
+/// Produces the records of every level while captured requests are in flight, at most
+/// [`MAX_TOKEN_LIFETIME`] after the last one started in case it is never done.
+fn open(now: u64, expires: u64) {
+    let until = now.max(expires) + MAX_TOKEN_LIFETIME.as_secs();
+    OPEN_UNTIL.with(|open_until| open_until.set(open_until.get().max(until)));
+    IN_FLIGHT.with(|in_flight| in_flight.set(in_flight.get().saturating_add(1)));
+    log::set_max_level(log::LevelFilter::Trace);
+}
+
+/// Ends one captured request, closing once none is in flight.
+fn finish() {
+    let left = IN_FLIGHT.with(|in_flight| {
+        let left = in_flight.get().saturating_sub(1);
+        in_flight.set(left);
+        left
+    });
+    if left == 0 {
+        close();
+    }
+}
+
+/// Back to the records of the configured level.
+fn close() {
+    OPEN_UNTIL.with(|open_until| open_until.set(0));
+    IN_FLIGHT.with(|in_flight| in_flight.set(0));
+    log::set_max_level(logger::configured_level());
+}
+
+/// Whether the records above the configured level are produced at `now`.
+fn is_open(now: u64) -> bool {
+    let until = OPEN_UNTIL.with(Cell::get);
+    if until == 0 {
+        return false;
+    }
+    if now > until {
+        close();
+        return false;
+    }
+    true
+}
+
+/// Whether a record above the configured level belongs to a captured request.
+pub(crate) fn captured(properties: &dyn PropertyAccessor) -> bool {
+    is_open(seconds(crate::Host.get_current_time())) && properties.tracing().debug_capture()
+}
+
+/// Whether the current request is captured, for the callers producing costly records only
+/// for the captured requests, like expression traces.
+pub fn is_captured() -> bool {
+    captured(<dyn PropertyAccessor>::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{close, finish, is_open, open, sign, CaptureError, DebugCapture, IN_FLIGHT};
+
+    const SECRET: &str = "a secret shared with the operators";
+
+    #[test]
+    fn signed_header_is_verified() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let capture = DebugCapture::new(SECRET);
+
+        let header = sign(SECRET.as_bytes(), 1_700_000_060);
+        assert_eq!(capture.verify(&header, now), Ok(1_700_000_060));
+
+        let forged = sign(b"another secret", 1_700_000_060);
+        assert_eq!(
+            capture.verify(&forged, now),
+            Err(CaptureError::InvalidSignature)
+        );
+        let tampered = header.replacen("1700000060", "1700000070", 1);
+        assert_eq!(
+            capture.verify(&tampered, now),
+            Err(CaptureError::InvalidSignature)
+        );
+        assert_eq!(
+            capture.verify("1700000060", now),
+            Err(CaptureError::Malformed)
+        );
+    }
+
+    #[test]
+    fn header_is_short_lived() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let capture = DebugCapture::new(SECRET);
+
+        assert_eq!(
+            capture.verify(&sign(SECRET.as_bytes(), 1_699_999_999), now),
+            Err(CaptureError::Expired)
+        );
+        assert_eq!(
+            capture.verify(&sign(SECRET.as_bytes(), 1_700_086_400), now),
+            Err(CaptureError::TooLong)
+        );
+    }
+
+    #[test]
+    fn open_while_captured_requests_are_in_flight() {
+        close();
+        assert!(!is_open(1_700_000_000));
+
+        open(1_700_000_000, 1_700_000_060);
+        open(1_700_000_010, 1_700_000_030);
+        assert!(is_open(1_700_000_020));
+
+        finish();
+        assert!(is_open(1_700_000_020));
+        finish();
+        assert!(!is_open(1_700_000_020));
+        assert_eq!(IN_FLIGHT.with(|in_flight| in_flight.get()), 0);
+    }
+
+    #[test]
+    fn closed_once_the_header_lifetime_passed() {
+        close();
+        open(1_700_000_000, 1_700_000_060);
+        assert!(is_open(1_700_000_960));
+        assert!(!is_open(1_700_000_961));
+        assert_eq!(IN_FLIGHT.with(|in_flight| in_flight.get()), 0);
+    }
+}
@@ -23,21 +23,21 @@ impl Clone for LogMetadata {
 
 impl LogMetadata {
     fn load_metadata() -> LogMetadata {
-        let metadata = StaticPolicyContextCache::read_metadata();
+        StaticPolicyContextCache::with_metadata(|metadata| {
+            let api_name = metadata
+                .api_info()
+                .map(|api| api.name().to_string())
+                .unwrap_or_else(|| StaticPolicyContextCache::read_plugin_name_api_id().to_string());
 
-        let api_name = metadata
-            .api_info()
-            .map(|api| api.name().to_string())
-            .unwrap_or_else(|| StaticPolicyContextCache::read_plugin_name_api_id().to_string());
+            let policy_name = metadata.policy_id();
+            let policy_namespace = metadata.policy_namespace();
 
-        let policy_name = metadata.policy_id();
-        let policy_namespace = metadata.policy_namespace();
-
-        LogMetadata {
-            api_id: Rc::new(api_name),
-            policy_name: Rc::new(format!("{}.{}", policy_name, policy_namespace)),
-            req_id: None,
-        }
+            LogMetadata {
+                api_id: Rc::new(api_name),
+                policy_name: Rc::new(format!("{}.{}", policy_name, policy_namespace)),
+                req_id: None,
+            }
+        })
     }
 }
 
@@ -1,4 +1,5 @@
 // Copyright 2023 Salesforce, Inc. All rights reserved.
+use std::cell::Cell;
 use std::panic;
 use std::sync::atomic::{AtomicBool, Ordering};
 
@@ -6,6 +7,7 @@ use crate::HostTrait;
 use classy::proxy_wasm::types::LogLevel;
 
 use crate::host::property::PropertyAccessor;
+use crate::log::capture;
 use crate::log::log_metadata::LogMetadata;
 
 struct Logger;
@@ -13,6 +15,11 @@ struct Logger;
 static LOGGER: Logger = Logger;
 static INITIALIZED: AtomicBool = AtomicBool::new(false);
 
+thread_local! {
+    // The max level of the log crate is raised while requests are captured.
+    static CONFIGURED_LEVEL: Cell<log::LevelFilter> = Cell::new(log::LevelFilter::Info);
+}
+
 pub fn set_log_level(level: LogLevel) {
     if !INITIALIZED.load(Ordering::Relaxed) {
         let _ = log::set_logger(&LOGGER);
@@ -21,7 +28,13 @@ pub fn set_log_level(level: LogLevel) {
         }));
         INITIALIZED.store(true, Ordering::Relaxed);
     }
-    log::set_max_level(to_log_lib_level(level));
+    let level = to_log_lib_level(level);
+    CONFIGURED_LEVEL.with(|configured| configured.set(level));
+    log::set_max_level(level);
+}
+
+pub(crate) fn configured_level() -> log::LevelFilter {
+    CONFIGURED_LEVEL.with(Cell::get)
 }
 
 impl log::Log for Logger {
@@ -34,15 +47,39 @@ impl log::Log for Logger {
             return;
         }
 
-        let metadata = LogMetadata::from(<dyn PropertyAccessor>::default());
-        let message = format!("{} {}", metadata, &record.args());
-        let level = to_proxy_level(record.level());
+        let properties = <dyn PropertyAccessor>::default();
+        let (level, captured) = match gate(record.level(), configured_level(), || {
+            capture::captured(properties)
+        }) {
+            Some(gated) => gated,
+            None => return,
+        };
+
+        let metadata = LogMetadata::from(properties);
+        let message = format!("{} {}{}", metadata, captured, &record.args());
         crate::Host.log(level, &message);
     }
 
     fn flush(&self) {}
 }
 
+/// The proxy level and prefix of a record, none when it is not logged. The records above the
+/// configured level are only logged for the captured requests, at info level so they get
+/// through the proxy log level too.
+fn gate(
+    level: log::Level,
+    configured: log::LevelFilter,
+    captured: impl FnOnce() -> bool,
+) -> Option<(LogLevel, &'static str)> {
+    if level <= configured {
+        Some((to_proxy_level(level), ""))
+    } else if captured() {
+        Some((LogLevel::Info, "[debug capture] "))
+    } else {
+        None
+    }
+}
+
 pub fn to_log_lib_level(level: LogLevel) -> log::LevelFilter {
     match level {
         LogLevel::Trace => log::LevelFilter::Trace,
@@ -63,3 +100,33 @@ pub fn to_proxy_level(level: log::Level) -> LogLevel {
         log::Level::Error => LogLevel::Error,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use classy::proxy_wasm::types::LogLevel;
+    use log::{Level, LevelFilter};
+
+    use super::gate;
+
+    #[test]
+    fn records_of_the_configured_level_are_logged() {
+        assert_eq!(
+            gate(Level::Warn, LevelFilter::Info, || unreachable!()),
+            Some((LogLevel::Warn, ""))
+        );
+        assert_eq!(
+            gate(Level::Info, LevelFilter::Info, || unreachable!()),
+            Some((LogLevel::Info, ""))
+        );
+    }
+
+    #[test]
+    fn records_above_the_configured_level_are_logged_when_captured() {
+        assert_eq!(gate(Level::Debug, LevelFilter::Info, || false), None);
+        assert_eq!(
+            gate(Level::Trace, LevelFilter::Info, || true),
+            Some((LogLevel::Info, "[debug capture] "))
+        );
+        assert_eq!(gate(Level::Info, LevelFilter::Off, || false), None);
+    }
+}
@@ -1,11 +1,13 @@
 // Copyright 2023 Salesforce, Inc. All rights reserved.
 use classy::proxy_wasm::types::LogLevel;
 
+pub mod capture;
 mod log_metadata;
 pub mod logger;
 
-use crate::host::property::PropertyAccessor;
+use crate::log::capture::DebugCapture;
 use crate::policy_context::metadata::PolicyMetadata;
+use crate::policy_context::static_policy_context_cache::StaticPolicyContextCache;
 pub use log::{debug, error, info, trace, warn};
 
 pub const DEFAULT_LOG_LEVEL: LogLevel = LogLevel::Info;
@@ -21,8 +23,23 @@ const LOG_LEVELS: [(&str, LogLevel); 7] = [
 ];
 
 pub fn configure_logger() {
-    let metadata = PolicyMetadata::from(<dyn PropertyAccessor>::default());
-    logger::set_log_level(get_min_level_from_context(&metadata));
+    let level = StaticPolicyContextCache::with_metadata(get_min_level_from_context);
+    logger::set_log_level(level);
+    if let Some(capture) = StaticPolicyContextCache::with_metadata(get_debug_capture_from_context) {
+        capture::enable(capture);
+    }
+}
+
+fn get_debug_capture_from_context(metadata: &PolicyMetadata) -> Option<DebugCapture> {
+    let config = metadata
+        .policy_config()
+        .and_then(|config| config.logging())
+        .and_then(|logging| logging.debug_capture())?;
+    let capture = DebugCapture::new(config.secret());
+    Some(match config.header() {
+        Some(header) => capture.with_header(header),
+        None => capture,
+    })
 }
 
 fn get_min_level_from_context(metadata: &PolicyMetadata) -> LogLevel {
@@ -1,11 +1,19 @@
 // Copyright 2023 Salesforce, Inc. All rights reserved.
 use crate::host::property::{PropertyAccessor, TRACING_ID_PATH};
+use crate::log::capture;
 use classy::event::{EventData, RequestHeaders};
 use classy::extract::FromContext;
 use classy::BoxError;
 
 pub fn for_request_headers(event: &EventData<RequestHeaders>) -> Result<(), BoxError> {
-    load_request_id(event)
+    load_request_id(event)?;
+    start_debug_capture(event)
+}
+
+fn start_debug_capture(event: &EventData<RequestHeaders>) -> Result<(), BoxError> {
+    let accessor: &dyn PropertyAccessor = FromContext::from_context(event)?;
+    capture::start(event, accessor);
+    Ok(())
 }
 
 fn load_request_id(event: &EventData<RequestHeaders>) -> Result<(), BoxError> {
@@ -1,4 +1,5 @@
 // Copyright 2023 Salesforce, Inc. All rights reserved.
+use std::cell::RefCell;
 use std::collections::HashMap;
 
 use log::warn;
@@ -215,6 +216,29 @@ impl<'a> AuthenticationUpdater<'a> {
     }
 }
 
+/// The authentication of an exchange, so it is read and deserialized at most once until it
+/// gets set again.
+#[derive(Default)]
+pub(crate) struct AuthenticationCache {
+    authentication: RefCell<Option<Option<Authentication>>>,
+}
+
+impl AuthenticationCache {
+    pub(crate) fn get_or_read(
+        &self,
+        read: impl FnOnce() -> Option<Authentication>,
+    ) -> Option<Authentication> {
+        self.authentication
+            .borrow_mut()
+            .get_or_insert_with(read)
+            .clone()
+    }
+
+    pub(crate) fn invalidate(&self) {
+        self.authentication.replace(None);
+    }
+}
+
 /// An interface of the `Flex` `Policy Context`.
 ///
 /// [`AuthenticationHandler`] is responsible for
@@ -247,17 +271,30 @@ impl dyn AuthenticationHandler {
 
 mod impls {
     use super::*;
+    use crate::host::metrics::MetricsAccessor;
     use crate::host::property::PropertyAccessor;
+    use crate::policy_context::static_policy_context_cache::StaticPolicyContextCache;
     use crate::policy_context::AUTHENTICATION_PROPERTY;
 
+    /// The maximum size, in bytes, of a serialized authentication object.
+    const MAX_SIZE: usize = 64 * 1024;
+
+    /// The maximum nesting of arrays and maps in a serialized authentication object.
+    const MAX_DEPTH: usize = 32;
+
+    /// Counts the authentication objects rejected for exceeding the limits.
+    const REJECTED_COUNTER: &str = "pdk_authentication_rejected";
+
     struct DefaultAuthenticationHandler<'a> {
         property_accessor: &'a dyn PropertyAccessor,
+        metrics: &'a dyn MetricsAccessor,
     }
 
     impl Default for DefaultAuthenticationHandler<'static> {
         fn default() -> Self {
             Self {
                 property_accessor: <dyn PropertyAccessor>::default(),
+                metrics: <dyn MetricsAccessor>::default(),
             }
         }
     }
@@ -265,6 +302,11 @@ mod impls {
     impl<'a> DefaultAuthenticationHandler<'a> {
         fn read_authentication(&self) -> Option<Authentication> {
             let bytes = self.property_accessor.read_property(AUTHENTICATION_PROPERTY)?;
+            if let Err(reason) = AuthenticationStreamSerializer::check_limits(bytes.as_slice()) {
+                warn!("Rejected Authentication object: {}", reason);
+                self.metrics.increment_counter(REJECTED_COUNTER, 1);
+                return None;
+            }
             AuthenticationStreamSerializer::deserialize(bytes.as_slice())
         }
 
@@ -296,6 +338,88 @@ mod impls {
     struct AuthenticationStreamSerializer;
 
     impl AuthenticationStreamSerializer {
+        /// Checks that the serialized object is within [`MAX_SIZE`] and [`MAX_DEPTH`], and that
+        /// no length it declares goes beyond its end, before anything gets allocated for it.
+        pub fn check_limits(bytes: &[u8]) -> Result<(), String> {
+            if bytes.len() > MAX_SIZE {
+                return Err(format!(
+                    "{} bytes exceed the limit of {}",
+                    bytes.len(),
+                    MAX_SIZE
+                ));
+            }
+
+            // The number of values left to read at each nesting level.
+            let mut pending: Vec<u64> = vec![1];
+            let mut offset = 0;
+            let read = |offset: usize, size: usize| -> Result<u64, String> {
+                let field = bytes
+                    .get(offset..offset + size)
+                    .ok_or_else(|| "truncated object".to_string())?;
+                Ok(field
+                    .iter()
+                    .fold(0, |value, byte| value << 8 | *byte as u64))
+            };
+
+            while let Some(remaining) = pending.last_mut() {
+                if *remaining == 0 {
+                    pending.pop();
+                    continue;
+                }
+                *remaining -= 1;
+
+                let marker = *bytes
+                    .get(offset)
+                    .ok_or_else(|| "truncated object".to_string())?;
+                offset += 1;
+
+                // Skipped bytes and the number of nested values of the marker.
+                let (skip, nested) = match marker {
+                    0x00..=0x7f | 0xc0 | 0xc2 | 0xc3 | 0xe0..=0xff => (0, 0),
+                    0x80..=0x8f => (0, 2 * (marker & 0x0f) as u64),
+                    0x90..=0x9f => (0, (marker & 0x0f) as u64),
+                    0xa0..=0xbf => ((marker & 0x1f) as u64, 0),
+                    0xc4 | 0xd9 => (read(offset, 1)? + 1, 0),
+                    0xc5 | 0xda => (read(offset, 2)? + 2, 0),
+                    0xc6 | 0xdb => (read(offset, 4)? + 4, 0),
+                    0xc7 => (read(offset, 1)? + 2, 0),
+                    0xc8 => (read(offset, 2)? + 3, 0),
+                    0xc9 => (read(offset, 4)? + 5, 0),
+                    0xcc | 0xd0 => (1, 0),
+                    0xcd | 0xd1 => (2, 0),
+                    0xca | 0xce | 0xd2 => (4, 0),
+                    0xcb | 0xcf | 0xd3 => (8, 0),
+                    0xd4 => (2, 0),
+                    0xd5 => (3, 0),
+                    0xd6 => (5, 0),
+                    0xd7 => (9, 0),
+                    0xd8 => (17, 0),
+                    0xdc => (2, read(offset, 2)?),
+                    0xdd => (4, read(offset, 4)?),
+                    0xde => (2, 2 * read(offset, 2)?),
+                    0xdf => (4, 2 * read(offset, 4)?),
+                    _ => return Err(format!("invalid marker {:#x}", marker)),
+                };
+
+                offset = offset.saturating_add(skip as usize);
+                if offset > bytes.len() {
+                    return Err("truncated object".to_string());
+                }
+                if nested > 0 {
+                    // Every value takes at least one byte.
+                    if nested > (bytes.len() - offset) as u64 {
+                        return Err("truncated object".to_string());
+                    }
+                    if pending.len() > MAX_DEPTH {
+                        return Err(format!("nesting exceeds the limit of {}", MAX_DEPTH));
+                    }
+                    pending.push(nested);
+                }
+            }
+
+            Ok(())
+        }
+
         pub fn deserialize(bytes: &[u8]) -> Option<Authentication> {
             match rmp_serde::decode::from_read(bytes) {
                 Ok(authentication) => Some(authentication),
@@ -330,11 +454,18 @@ mod impls {
 
     impl AuthenticationHandler for Host {
         fn authentication(&self) -> Option<Authentication> {
-            DefaultAuthenticationHandler::default().authentication()
+            let read = || DefaultAuthenticationHandler::default().authentication();
+            match StaticPolicyContextCache::read_authentication_cache() {
+                Some(cache) => cache.get_or_read(read),
+                None => read(),
+            }
         }
 
         fn set_authentication(&self, authentication: &Authentication) {
-            DefaultAuthenticationHandler::default().set_authentication(authentication)
+            DefaultAuthenticationHandler::default().set_authentication(authentication);
+            if let Some(cache) = StaticPolicyContextCache::read_authentication_cache() {
+                cache.invalidate();
+            }
         }
 
         fn update_authentication(&self) -> AuthenticationUpdater {
@@ -368,10 +499,36 @@ mod impls {
 
         impl MockPropertyAccessor {
             fn mock_handler(&self) -> DefaultAuthenticationHandler {
-                DefaultAuthenticationHandler { property_accessor: self }
+                DefaultAuthenticationHandler {
+                    property_accessor: self,
+                    metrics: &NO_METRICS,
+                }
+            }
+        }
+
+        #[derive(Default)]
+        struct MockMetricsAccessor {
+            counters: RefCell<HashMap<String, u64>>,
+        }
+
+        impl MetricsAccessor for MockMetricsAccessor {
+            fn increment_counter(&self, name: &str, amount: u64) {
+                *self
+                    .counters
+                    .borrow_mut()
+                    .entry(name.to_string())
+                    .or_default() += amount;
             }
         }
 
+        struct NoMetrics;
+
+        impl MetricsAccessor for NoMetrics {
+            fn increment_counter(&self, _name: &str, _amount: u64) {}
+        }
+
+        const NO_METRICS: NoMetrics = NoMetrics;
+
         impl PropertyAccessor for MockPropertyAccessor {
             fn read_property(&self, path: &[&str]) -> Option<Bytes> {
                 let path: Vec<String> = path.to_vec().iter().map(|x| x.to_string()).collect();
@@ -398,6 +555,66 @@ mod impls {
             assert_eq!(auth.unwrap().properties().len(), 2);
         }
 
+        #[test]
+        fn serialized_authentication_is_within_limits() {
+            let bytes =
+                AuthenticationStreamSerializer::serialize(&create_authentication()).unwrap();
+
+            assert_eq!(AuthenticationStreamSerializer::check_limits(&bytes), Ok(()));
+        }
+
+        #[test]
+        fn oversized_authentication_is_rejected() {
+            let property_accessor = MockPropertyAccessor::default();
+            let metrics = MockMetricsAccessor::default();
+            let handler = DefaultAuthenticationHandler {
+                property_accessor: &property_accessor,
+                metrics: &metrics,
+            };
+            let huge = "a".repeat(MAX_SIZE);
+            handler.set_authentication(&AuthenticationBuilder::new().principal(&huge).build());
+
+            assert!(handler.authentication().is_none());
+            assert_eq!(metrics.counters.borrow().get(REJECTED_COUNTER), Some(&1));
+        }
+
+        #[test]
+        fn deeply_nested_authentication_is_rejected() {
+            // 40 nested single element arrays.
+            let mut bytes = vec![0x91; 40];
+            bytes.push(0xc0);
+
+            assert!(AuthenticationStreamSerializer::check_limits(&bytes).is_err());
+            assert!(AuthenticationStreamSerializer::check_limits(&bytes[8..]).is_ok());
+        }
+
+        #[test]
+        fn truncated_authentication_is_rejected() {
+            // An array declaring 65535 elements, followed by a single one.
+            let bytes = [0xdc, 0xff, 0xff, 0xc0];
+
+            assert!(AuthenticationStreamSerializer::check_limits(&bytes).is_err());
+            assert!(AuthenticationStreamSerializer::check_limits(&[0xdb, 0x00]).is_err());
+        }
+
+        #[test]
+        fn cache_reads_once_until_invalidated() {
+            let cache = AuthenticationCache::default();
+            let reads = std::cell::Cell::new(0);
+            let read = || {
+                reads.set(reads.get() + 1);
+                Some(create_authentication())
+            };
+
+            assert_authentication(cache.get_or_read(read));
+            assert_authentication(cache.get_or_read(read));
+            assert_eq!(reads.get(), 1);
+
+            cache.invalidate();
+            assert_authentication(cache.get_or_read(read));
+            assert_eq!(reads.get(), 2);
+        }
+
         #[test]
         fn handler_get_empty() {
             let property_accessor = MockPropertyAccessor::default();
@@ -0,0 +1,10 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! The decisions the enforcement policies took on an exchange, like the authentication, rate
+//! limit, size and threat protection ones, so the access log can tell in a single field what
+//! the policy chain did to a request.
+//!
+//! Policies record their decision in the filter state with
+//! [`FilterState::add_policy_decision`](crate::host::property::FilterState::add_policy_decision).
+//! The format is defined by `pdk-decisions`, shared with the policies built on the proxy-wasm
+//! SDK.
+pub use pdk_decisions::{Decision, Outcome, PolicyDecisions, MAX_DECISIONS};
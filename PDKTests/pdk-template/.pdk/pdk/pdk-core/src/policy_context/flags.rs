@@ -0,0 +1,126 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Feature flags, to enable experimental behaviors per environment without new builds.
+//!
+//! Flags come from the `platformPolicyIDs` of the API context, as `flag.<name>` entries, over
+//! the defaults of a map usually part of the policy configuration:
+//!
+//! ```json
+//! "platformPolicyIDs": { "flag.streamingBody": "true" }
+//! ```
+//!
+//! Policies check them with [`FeatureFlags::is_enabled`], expressions read them as
+//! `vars.flags.<name>` once given to the resolver.
+use std::collections::BTreeMap;
+
+use crate::policy_context::metadata::PolicyMetadata;
+use crate::policy_context::static_policy_context_cache::StaticPolicyContextCache;
+
+/// The prefix of the `platformPolicyIDs` entries holding flags.
+pub const FLAG_PREFIX: &str = "flag.";
+
+/// The enabled and disabled flags of a policy.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FeatureFlags {
+    flags: BTreeMap<String, bool>,
+}
+
+impl FeatureFlags {
+    /// The flags of the active policy metadata over the `defaults`.
+    pub fn load(defaults: &BTreeMap<String, bool>) -> Self {
+        StaticPolicyContextCache::with_metadata(|metadata| Self::from_metadata(metadata, defaults))
+    }
+
+    /// The flags of the `metadata` over the `defaults`.
+    pub fn from_metadata(metadata: &PolicyMetadata, defaults: &BTreeMap<String, bool>) -> Self {
+        let mut flags = defaults.clone();
+
+        let entries = metadata.platform_policy_ids().into_iter().flatten();
+        for (key, value) in entries {
+            let name = match key.strip_prefix(FLAG_PREFIX) {
+                Some(name) if !name.is_empty() => name,
+                _ => continue,
+            };
+            match parse_flag(value) {
+                Some(enabled) => {
+                    flags.insert(name.to_string(), enabled);
+                }
+                None => log::warn!("Ignoring flag {} with value {:?}.", name, value),
+            }
+        }
+
+        Self { flags }
+    }
+
+    /// Whether the flag `name` is enabled, disabled when unknown.
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.flags.get(name).copied().unwrap_or_default()
+    }
+
+    /// The known flags, enabled or not.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, bool)> {
+        self.flags
+            .iter()
+            .map(|(name, enabled)| (name.as_str(), *enabled))
+    }
+}
+
+fn parse_flag(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "true" | "on" | "1" | "enabled" => Some(true),
+        "false" | "off" | "0" | "disabled" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::FeatureFlags;
+    use crate::policy_context::metadata::{ApiContext, PolicyMetadata};
+
+    fn metadata(entries: &[(&str, &str)]) -> PolicyMetadata {
+        let platform_policy_ids = entries
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        let context = ApiContext::new(None, None, None, None, None, Some(platform_policy_ids));
+        PolicyMetadata::new(
+            "flex".to_string(),
+            "policy".to_string(),
+            "namespace".to_string(),
+            context,
+        )
+    }
+
+    #[test]
+    fn metadata_flags_override_defaults() {
+        let defaults = BTreeMap::from([
+            ("streamingBody".to_string(), false),
+            ("retries".to_string(), true),
+        ]);
+        let metadata = metadata(&[
+            ("flag.streamingBody", "on"),
+            ("flag.broken", "maybe"),
+            ("rate-limiting", "42"),
+        ]);
+
+        let flags = FeatureFlags::from_metadata(&metadata, &defaults);
+
+        assert!(flags.is_enabled("streamingBody"));
+        assert!(flags.is_enabled("retries"));
+        assert!(!flags.is_enabled("broken"));
+        assert!(!flags.is_enabled("rate-limiting"));
+        assert_eq!(
+            flags.iter().collect::<Vec<_>>(),
+            vec![("retries", true), ("streamingBody", true)]
+        );
+    }
+
+    #[test]
+    fn no_flags_without_metadata() {
+        let flags = FeatureFlags::from_metadata(&PolicyMetadata::default(), &BTreeMap::new());
+
+        assert_eq!(flags, FeatureFlags::default());
+    }
+}
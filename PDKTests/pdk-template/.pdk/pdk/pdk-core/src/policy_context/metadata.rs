@@ -2,6 +2,7 @@
 use std::collections::BTreeMap;
 
 use crate::host::property::PropertyAccessor;
+use crate::http::query::{DuplicateParams, QueryDecoding};
 use serde::Deserialize;
 use url::{Host, Url};
 
@@ -124,23 +125,80 @@ impl ApiContext {
 #[derive(Deserialize, Debug, Default, Clone, Hash)]
 pub struct PolicyConfig {
     logging: Option<Logging>,
+    expressions: Option<Expressions>,
 }
 
 impl PolicyConfig {
     pub fn logging(&self) -> Option<&Logging> {
         self.logging.as_ref()
     }
+
+    pub fn expressions(&self) -> Option<&Expressions> {
+        self.expressions.as_ref()
+    }
+}
+
+/// How the expressions of the policy read the query, the defaults of the pdk when absent.
+#[derive(Deserialize, Debug, Default, Clone, Hash)]
+pub struct Expressions {
+    #[serde(rename = "duplicateQueryParams")]
+    duplicate_query_params: Option<DuplicateParams>,
+    #[serde(rename = "queryDecoding")]
+    query_decoding: Option<QueryDecoding>,
+}
+
+impl Expressions {
+    pub fn duplicate_query_params(&self) -> Option<DuplicateParams> {
+        self.duplicate_query_params
+    }
+
+    pub fn query_decoding(&self) -> Option<QueryDecoding> {
+        self.query_decoding
+    }
 }
 
 #[derive(Deserialize, Debug, Default, Clone, Hash)]
 pub struct Logging {
     level: String,
+    #[serde(rename = "debugCapture")]
+    debug_capture: Option<DebugCaptureConfig>,
 }
 
 impl Logging {
     pub fn level(&self) -> &str {
         self.level.as_ref()
     }
+
+    pub fn debug_capture(&self) -> Option<&DebugCaptureConfig> {
+        self.debug_capture.as_ref()
+    }
+}
+
+/// The secret verifying the debug headers of the requests captured, see
+/// [`crate::log::capture`].
+#[derive(Deserialize, Clone, Hash)]
+pub struct DebugCaptureConfig {
+    secret: String,
+    header: Option<String>,
+}
+
+impl DebugCaptureConfig {
+    pub fn secret(&self) -> &str {
+        self.secret.as_ref()
+    }
+
+    pub fn header(&self) -> Option<&str> {
+        self.header.as_deref()
+    }
+}
+
+impl std::fmt::Debug for DebugCaptureConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DebugCaptureConfig")
+            .field("secret", &"<redacted>")
+            .field("header", &self.header)
+            .finish()
+    }
 }
 
 #[derive(Deserialize, Debug, Default, Clone, Hash)]
@@ -384,8 +442,12 @@ fn read_string(property_accessor: &dyn PropertyAccessor, coordinate: &[&str]) ->
     })
 }
 
-pub fn read_api_name_from_plugin_name(property_accessor: &dyn PropertyAccessor) -> String {
+pub(crate) fn read_plugin_name(property_accessor: &dyn PropertyAccessor) -> Option<String> {
     read_string(property_accessor, PLUGIN_NAME)
+}
+
+pub fn read_api_name_from_plugin_name(property_accessor: &dyn PropertyAccessor) -> String {
+    read_plugin_name(property_accessor)
         .map(|name| PolicyMetadata::split_plugin_name(&name).unwrap_or_default())
         .map(|(api_id, _, _)| api_id)
         .unwrap_or_default()
@@ -556,8 +618,9 @@ mod tests {
     use classy::proxy_wasm::types::Bytes;
     use mockall::mock;
 
+    use crate::http::query::{DuplicateParams, QueryDecoding};
     use crate::policy_context::metadata::{
-        PolicyMetadata, DEFAULT_POLICY_ID, DEFAULT_POLICY_NAMESPACE,
+        PolicyConfig, PolicyMetadata, DEFAULT_POLICY_ID, DEFAULT_POLICY_NAMESPACE,
     };
 
     mock! {
@@ -1405,4 +1468,21 @@ mod tests {
                "second_policy": "second_policy_platform_id"
               }
             }"#;
+
+    #[test]
+    fn expressions_settings() {
+        let config: PolicyConfig = serde_json::from_str(
+            r#"{"expressions": {"duplicateQueryParams": "lastWins", "queryDecoding": "strict"}}"#,
+        )
+        .unwrap();
+        let expressions = config.expressions().unwrap();
+        assert_eq!(
+            expressions.duplicate_query_params(),
+            Some(DuplicateParams::LastWins)
+        );
+        assert_eq!(expressions.query_decoding(), Some(QueryDecoding::Strict));
+
+        let config: PolicyConfig = serde_json::from_str(r#"{"expressions": {}}"#).unwrap();
+        assert_eq!(config.expressions().unwrap().duplicate_query_params(), None);
+    }
 }
@@ -6,7 +6,10 @@ use crate::policy_context::metadata::PolicyMetadata;
 use std::rc::Rc;
 
 pub mod authentication;
+pub mod decisions;
+pub mod flags;
 pub mod metadata;
+pub mod partition;
 pub mod static_policy_context_cache;
 
 const AUTHENTICATION_PROPERTY: &[&str] = &["authentication"];
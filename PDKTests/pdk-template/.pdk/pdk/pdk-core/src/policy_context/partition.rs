@@ -0,0 +1,291 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Configuration partitioned by API, for a single policy deployment serving many APIs.
+//!
+//! The configuration holds a `default` section and one section per API id, each holding
+//! only what differs from the default:
+//!
+//! ```json
+//! {
+//!   "default": { "limit": 100, "header": "x-tenant" },
+//!   "apis": { "c0ffee-1234": { "limit": 1000 } }
+//! }
+//! ```
+//!
+//! The sections are merged over the default when parsing, so a malformed partition fails the
+//! deployment instead of the requests of its API. The configure function extracts the whole
+//! configuration with [`PartitionedConfiguration`], and the filters extract the partition of
+//! the API of each request with [`ApiConfiguration`]:
+//!
+//! ```ignore
+//! #[entrypoint]
+//! async fn configure(
+//!     launcher: Launcher,
+//!     _: PartitionedConfiguration<PolicyConfiguration>,
+//! ) -> Result<(), PolicyError> {
+//!     launcher
+//!         .launch(|exchange, ApiConfiguration(config)| filter(exchange, config))
+//!         .await?;
+//!     Ok(())
+//! }
+//! ```
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::{Infallible, TryFrom};
+use std::rc::Rc;
+
+use classy::extract::config::Configuration;
+use classy::extract::context::ConfigureContext;
+use classy::extract::shared::Shared;
+use classy::extract::FromContext;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::error::{codes, PolicyError};
+use crate::policy_context::metadata::PolicyMetadata;
+use crate::policy_context::static_policy_context_cache::StaticPolicyContextCache;
+
+#[derive(Deserialize)]
+struct RawPartitions {
+    #[serde(default)]
+    default: Option<Value>,
+    #[serde(default)]
+    apis: Map<String, Value>,
+}
+
+/// A configuration of type `T` for each API, and the one of the APIs without a section.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "RawPartitions", bound = "T: DeserializeOwned")]
+pub struct Partitioned<T> {
+    default: Option<Rc<T>>,
+    apis: HashMap<String, Rc<T>>,
+}
+
+impl<T: DeserializeOwned> TryFrom<RawPartitions> for Partitioned<T> {
+    type Error = serde_json::Error;
+
+    fn try_from(raw: RawPartitions) -> Result<Self, Self::Error> {
+        let RawPartitions { default, apis } = raw;
+        let apis = apis
+            .into_iter()
+            .map(|(api, section)| {
+                let section = match &default {
+                    Some(default) => merge(default.clone(), section),
+                    None => section,
+                };
+                Ok((api, Rc::new(serde_json::from_value(section)?)))
+            })
+            .collect::<Result<_, Self::Error>>()?;
+        let default = default
+            .map(serde_json::from_value)
+            .transpose()?
+            .map(Rc::new);
+
+        Ok(Self { default, apis })
+    }
+}
+
+/// Merges the objects of `section` over the ones of `default`, the other values replace the
+/// default ones.
+fn merge(default: Value, section: Value) -> Value {
+    match (default, section) {
+        (Value::Object(mut default), Value::Object(section)) => {
+            for (key, value) in section {
+                let value = match default.remove(&key) {
+                    Some(previous) => merge(previous, value),
+                    None => value,
+                };
+                default.insert(key, value);
+            }
+            Value::Object(default)
+        }
+        (_, section) => section,
+    }
+}
+
+impl<T> Partitioned<T> {
+    /// The configuration of the API with the `api_id`, or the default one.
+    pub fn get(&self, api_id: &str) -> Option<&T> {
+        self.apis
+            .get(api_id)
+            .or(self.default.as_ref())
+            .map(Rc::as_ref)
+    }
+
+    /// The configuration of the API described by the `metadata`, looked up by its id and then
+    /// by its legacy id, or the default one.
+    pub fn select(&self, metadata: &PolicyMetadata) -> Option<&T> {
+        self.partition(metadata).map(Rc::as_ref)
+    }
+
+    /// The configuration of the API the policy is serving now, none outside of exchanges and
+    /// configure when the API has no section and there is no default.
+    pub fn current(&self) -> Option<&T> {
+        StaticPolicyContextCache::with_metadata(|metadata| self.select(metadata))
+    }
+
+    pub fn default_partition(&self) -> Option<&T> {
+        self.default.as_deref()
+    }
+
+    /// The ids of the APIs with a section of their own.
+    pub fn api_ids(&self) -> impl Iterator<Item = &str> {
+        self.apis.keys().map(String::as_str)
+    }
+
+    fn partition(&self, metadata: &PolicyMetadata) -> Option<&Rc<T>> {
+        metadata
+            .api_info()
+            .and_then(|api| {
+                self.apis
+                    .get(api.id())
+                    .or_else(|| self.apis.get(api.legacy_api_id()))
+            })
+            .or(self.default.as_ref())
+    }
+}
+
+/// The configuration extracted by the configure function, handed over to the filters of its
+/// root context.
+pub struct Partitions<T>(RefCell<Option<Rc<Partitioned<T>>>>);
+
+impl<T> Default for Partitions<T> {
+    fn default() -> Self {
+        Self(RefCell::new(None))
+    }
+}
+
+/// Extracts the configuration of the policy partitioned by API, in the configure function. The
+/// filters of the policy can then extract the partition of their API with
+/// [`ApiConfiguration`].
+#[derive(Clone, Debug)]
+pub struct PartitionedConfiguration<T>(pub Rc<Partitioned<T>>);
+
+impl<T: DeserializeOwned + 'static> FromContext<ConfigureContext> for PartitionedConfiguration<T> {
+    type Error = PolicyError;
+
+    fn from_context(context: &ConfigureContext) -> Result<Self, Self::Error> {
+        let Configuration(bytes) = Configuration::from_context(context).unwrap_or_default();
+        let partitioned = Rc::new(serde_json::from_slice::<Partitioned<T>>(&bytes)?);
+
+        let Shared(partitions) = Shared::<Partitions<T>>::from_context(context)?;
+        partitions.0.replace(Some(Rc::clone(&partitioned)));
+        Ok(Self(partitioned))
+    }
+}
+
+/// Extracts the partition of the configuration of the API of the request, in the filters. It
+/// fails when the configure function did not extract the [`PartitionedConfiguration`], or
+/// when the API has no section and there is no default one.
+#[derive(Debug)]
+pub struct ApiConfiguration<T>(pub Rc<T>);
+
+impl<T, C> FromContext<C> for ApiConfiguration<T>
+where
+    T: 'static,
+    Shared<Partitions<T>>: FromContext<C, Error = Infallible>,
+{
+    type Error = PolicyError;
+
+    fn from_context(context: &C) -> Result<Self, Self::Error> {
+        let Shared(partitions) = Shared::<Partitions<T>>::from_context(context)?;
+        let partitions = partitions.0.borrow();
+        let partitioned = partitions.as_ref().ok_or_else(|| {
+            PolicyError::new(
+                codes::INVALID_CONFIGURATION,
+                "The partitioned configuration was not extracted",
+            )
+        })?;
+
+        StaticPolicyContextCache::with_metadata(|metadata| partitioned.partition(metadata).cloned())
+            .map(ApiConfiguration)
+            .ok_or_else(|| {
+                PolicyError::new(codes::INVALID_CONFIGURATION, "No configuration for the API")
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::Partitioned;
+    use crate::policy_context::metadata::{Api, ApiContext, PolicyMetadata};
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Config {
+        limit: u64,
+        header: String,
+        #[serde(default)]
+        tags: Vec<String>,
+    }
+
+    fn metadata(id: &str, legacy_id: &str) -> PolicyMetadata {
+        let api = Api::new(
+            id.to_string(),
+            "api".to_string(),
+            legacy_id.to_string(),
+            "v1".to_string(),
+        );
+        let context = ApiContext::new(None, Some(api), None, None, None, None);
+        PolicyMetadata::new(
+            "flex".to_string(),
+            "policy".to_string(),
+            "namespace".to_string(),
+            context,
+        )
+    }
+
+    #[test]
+    fn sections_override_default() {
+        let config: Partitioned<Config> = serde_json::from_value(json!({
+            "default": { "limit": 100, "header": "x-tenant", "tags": ["a"] },
+            "apis": {
+                "api-1": { "limit": 1000 },
+                "12345": { "header": "x-legacy", "tags": [] }
+            }
+        }))
+        .unwrap();
+
+        let first = config.select(&metadata("api-1", "1")).unwrap();
+        assert_eq!(first.limit, 1000);
+        assert_eq!(first.header, "x-tenant");
+        assert_eq!(first.tags, vec!["a".to_string()]);
+
+        let legacy = config.select(&metadata("api-2", "12345")).unwrap();
+        assert_eq!(legacy.limit, 100);
+        assert_eq!(legacy.header, "x-legacy");
+        assert!(legacy.tags.is_empty());
+
+        assert_eq!(
+            config.select(&metadata("api-3", "3")),
+            config.default_partition()
+        );
+        assert_eq!(
+            config.select(&PolicyMetadata::default()).map(|c| c.limit),
+            Some(100)
+        );
+    }
+
+    #[test]
+    fn no_default_section() {
+        let config: Partitioned<Config> = serde_json::from_value(json!({
+            "apis": { "api-1": { "limit": 1, "header": "x-a" } }
+        }))
+        .unwrap();
+
+        assert_eq!(config.get("api-1").map(|c| c.limit), Some(1));
+        assert_eq!(config.get("api-2"), None);
+    }
+
+    #[test]
+    fn invalid_section_fails() {
+        let config = serde_json::from_value::<Partitioned<Config>>(json!({
+            "default": { "limit": 100, "header": "x-tenant" },
+            "apis": { "api-1": { "limit": "many" } }
+        }));
+
+        assert!(config.is_err());
+    }
+}
@@ -1,26 +1,60 @@
 // Copyright 2023 Salesforce, Inc. All rights reserved.
-use crate::host::property::PropertyAccessor;
-use crate::policy_context::metadata::{read_api_name_from_plugin_name, PolicyMetadata};
+use crate::host::property::{CachedPropertyAccessor, PropertyAccessor};
+use crate::policy_context::authentication::AuthenticationCache;
+use crate::policy_context::metadata::{
+    read_api_name_from_plugin_name, read_plugin_name, PolicyMetadata,
+};
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::rc::Rc;
 
 thread_local! {
     static ACTIVE_POLICY_METADATA: RefCell<Option<Rc<PolicyMetadata>>> = RefCell::new(None);
     static ACTIVE_PLUGIN_NAME_API_ID: RefCell<Option<Rc<String>>> = RefCell::new(None);
+    static ACTIVE_AUTHENTICATION_CACHE: RefCell<Option<Rc<AuthenticationCache>>> =
+        RefCell::new(None);
+    static METADATA_BY_PLUGIN_NAME: RefCell<HashMap<String, Rc<PolicyMetadata>>> =
+        RefCell::new(HashMap::new());
 }
 
 pub struct StaticPolicyContextCache;
 
 impl StaticPolicyContextCache {
     pub fn fresh_reload() {
-        let property_accessor = <dyn PropertyAccessor>::default();
+        let property_accessor = &CachedPropertyAccessor::new(<dyn PropertyAccessor>::default());
 
-        StaticPolicyContextCache::fix_metadata(&Rc::new(PolicyMetadata::from(property_accessor)));
+        StaticPolicyContextCache::invalidate();
+        StaticPolicyContextCache::fix_metadata(&StaticPolicyContextCache::load_metadata(
+            property_accessor,
+        ));
         StaticPolicyContextCache::fix_plugin_name_api_id(&Rc::new(read_api_name_from_plugin_name(
             property_accessor,
         )))
     }
 
+    /// Returns the metadata of the plugin the properties belong to. The filter metadata is
+    /// parsed only the first time a plugin name is seen, until the cache is invalidated.
+    pub fn load_metadata(property_accessor: &dyn PropertyAccessor) -> Rc<PolicyMetadata> {
+        let plugin_name = match read_plugin_name(property_accessor) {
+            Some(plugin_name) => plugin_name,
+            None => return Rc::new(PolicyMetadata::from(property_accessor)),
+        };
+
+        METADATA_BY_PLUGIN_NAME.with(|cell| {
+            let mut cache = cell.borrow_mut();
+            let metadata = cache
+                .entry(plugin_name)
+                .or_insert_with(|| Rc::new(PolicyMetadata::from(property_accessor)));
+            Rc::clone(metadata)
+        })
+    }
+
+    /// Forgets the metadata loaded for every plugin name. Meant to be called when the policy
+    /// gets reconfigured.
+    pub fn invalidate() {
+        METADATA_BY_PLUGIN_NAME.with(|cell| cell.borrow_mut().clear());
+    }
+
     pub fn fix_metadata(metadata: &Rc<PolicyMetadata>) {
         ACTIVE_POLICY_METADATA.with(|cell| cell.replace(Some(Rc::clone(metadata))));
     }
@@ -37,6 +71,25 @@ impl StaticPolicyContextCache {
         Rc::clone(&metadata)
     }
 
+    /// Gives access to the active metadata without cloning it, for readers on every
+    /// request, like the logger.
+    pub fn with_metadata<R>(f: impl FnOnce(&PolicyMetadata) -> R) -> R {
+        ACTIVE_POLICY_METADATA.with(|cell| match cell.borrow().as_deref() {
+            Some(metadata) => f(metadata),
+            None => f(&PolicyMetadata::default()),
+        })
+    }
+
+    /// Fixes the authentication cache of the exchange being processed, or none outside of
+    /// exchanges.
+    pub(crate) fn fix_authentication_cache(cache: Option<&Rc<AuthenticationCache>>) {
+        ACTIVE_AUTHENTICATION_CACHE.with(|cell| cell.replace(cache.map(Rc::clone)));
+    }
+
+    pub(crate) fn read_authentication_cache() -> Option<Rc<AuthenticationCache>> {
+        ACTIVE_AUTHENTICATION_CACHE.with(|cell| cell.borrow().clone())
+    }
+
     pub fn read_plugin_name_api_id() -> Rc<String> {
         let metadata = ACTIVE_PLUGIN_NAME_API_ID
             .with(|cell| cell.borrow().clone())
@@ -45,3 +98,41 @@ impl StaticPolicyContextCache {
         Rc::clone(&metadata)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::host::property::InMemoryPropertyAccessor;
+    use std::rc::Rc;
+
+    use super::StaticPolicyContextCache;
+
+    #[test]
+    fn metadata_is_cached_by_plugin_name() {
+        let properties = InMemoryPropertyAccessor::new()
+            .with_property(&["plugin_name"], b"policy.namespace.api-1");
+
+        let first = StaticPolicyContextCache::load_metadata(&properties);
+        let second = StaticPolicyContextCache::load_metadata(&properties);
+        assert!(Rc::ptr_eq(&first, &second));
+        assert_eq!(first.policy_id(), "policy");
+
+        StaticPolicyContextCache::invalidate();
+        let third = StaticPolicyContextCache::load_metadata(&properties);
+        assert!(!Rc::ptr_eq(&first, &third));
+    }
+
+    #[test]
+    fn with_metadata_reads_active_metadata() {
+        let properties = InMemoryPropertyAccessor::new()
+            .with_property(&["plugin_name"], b"policy.namespace.api-1");
+        StaticPolicyContextCache::fix_metadata(&StaticPolicyContextCache::load_metadata(
+            &properties,
+        ));
+
+        let namespace = StaticPolicyContextCache::with_metadata(|metadata| {
+            metadata.policy_namespace().to_string()
+        });
+
+        assert_eq!(namespace, "namespace");
+    }
+}
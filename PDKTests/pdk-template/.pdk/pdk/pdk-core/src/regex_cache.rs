@@ -0,0 +1,237 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! A cache of compiled regular expressions, keyed by pattern, shared by the whole VM.
+//!
+//! Compiling a pattern costs far more than matching it, so policies compile each pattern once
+//! with [`compile`] instead of on every request. The patterns are guarded before being cached:
+//! [`RegexLimits`] bound their length, nesting and compiled size, so a pathological pattern
+//! fails to compile instead of stalling the VM. Matching itself always runs in linear time.
+//!
+//! ```ignore
+//! let regex = regex_cache::compile(r"^/orders/\d+$")?;
+//! if regex.is_match(&path) { ... }
+//! ```
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use ::regex::{Regex, RegexBuilder};
+
+use crate::error::{codes, PolicyError};
+
+/// The number of patterns the global cache holds before evicting the least recently used.
+pub const DEFAULT_CAPACITY: usize = 256;
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum RegexError {
+    #[error("Pattern of {0} bytes exceeds the limit of {1}")]
+    TooLong(usize, usize),
+    #[error("Pattern exceeds the complexity limits: {0}")]
+    TooComplex(String),
+    #[error("Invalid pattern: {0}")]
+    Invalid(String),
+}
+
+impl From<RegexError> for PolicyError {
+    fn from(error: RegexError) -> Self {
+        Self::new(codes::INVALID_PATTERN, error.to_string())
+    }
+}
+
+/// The bounds a pattern must stay within to be compiled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RegexLimits {
+    /// The maximum length of the pattern, in bytes.
+    pub max_length: usize,
+    /// The maximum nesting of groups and repetitions.
+    pub max_nesting: u32,
+    /// The maximum size of the compiled program, in bytes, which grows with counted repetitions.
+    pub max_size: usize,
+}
+
+impl Default for RegexLimits {
+    fn default() -> Self {
+        Self {
+            max_length: 1024,
+            max_nesting: 32,
+            max_size: 256 * 1024,
+        }
+    }
+}
+
+impl RegexLimits {
+    /// Compiles the `pattern` within the limits.
+    pub fn compile(&self, pattern: &str) -> Result<Regex, RegexError> {
+        if pattern.len() > self.max_length {
+            return Err(RegexError::TooLong(pattern.len(), self.max_length));
+        }
+
+        RegexBuilder::new(pattern)
+            .nest_limit(self.max_nesting)
+            .size_limit(self.max_size)
+            .dfa_size_limit(self.max_size)
+            .build()
+            .map_err(|error| match error {
+                ::regex::Error::CompiledTooBig(_) => RegexError::TooComplex(error.to_string()),
+                ::regex::Error::Syntax(ref message) if message.contains("nest") => {
+                    RegexError::TooComplex(error.to_string())
+                }
+                _ => RegexError::Invalid(error.to_string()),
+            })
+    }
+}
+
+struct Entry {
+    regex: Rc<Regex>,
+    tick: u64,
+}
+
+/// Compiled patterns by their source, bounded to a capacity.
+pub struct RegexCache {
+    capacity: usize,
+    limits: RegexLimits,
+    entries: HashMap<String, Entry>,
+    tick: u64,
+}
+
+impl RegexCache {
+    pub fn new(capacity: usize, limits: RegexLimits) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            limits,
+            entries: HashMap::new(),
+            tick: 0,
+        }
+    }
+
+    pub fn limits(&self) -> RegexLimits {
+        self.limits
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The compiled `pattern`, compiled and cached on its first use. Patterns failing to
+    /// compile are not cached.
+    pub fn get(&mut self, pattern: &str) -> Result<Rc<Regex>, RegexError> {
+        self.tick += 1;
+        if let Some(entry) = self.entries.get_mut(pattern) {
+            entry.tick = self.tick;
+            return Ok(entry.regex.clone());
+        }
+
+        let regex = Rc::new(self.limits.compile(pattern)?);
+        if self.entries.len() >= self.capacity {
+            self.evict();
+        }
+        self.entries.insert(
+            pattern.to_string(),
+            Entry {
+                regex: regex.clone(),
+                tick: self.tick,
+            },
+        );
+        Ok(regex)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    // The capacity is small, finding the least recently used entry does not need an index.
+    fn evict(&mut self) {
+        let oldest = self
+            .entries
+            .iter()
+            .min_by_key(|(_, entry)| entry.tick)
+            .map(|(pattern, _)| pattern.clone());
+        if let Some(oldest) = oldest {
+            self.entries.remove(&oldest);
+        }
+    }
+}
+
+thread_local! {
+    static GLOBAL: RefCell<RegexCache> =
+        RefCell::new(RegexCache::new(DEFAULT_CAPACITY, RegexLimits::default()));
+}
+
+/// The compiled `pattern` from the cache shared by the whole VM, within the default limits.
+pub fn compile(pattern: &str) -> Result<Rc<Regex>, RegexError> {
+    GLOBAL.with(|cache| cache.borrow_mut().get(pattern))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::{compile, RegexCache, RegexError, RegexLimits};
+    use crate::error::{codes, PolicyError};
+
+    #[test]
+    fn compiles_each_pattern_once() {
+        let mut cache = RegexCache::new(4, RegexLimits::default());
+
+        let first = cache.get(r"^/orders/\d+$").unwrap();
+        let second = cache.get(r"^/orders/\d+$").unwrap();
+
+        assert!(Rc::ptr_eq(&first, &second));
+        assert!(first.is_match("/orders/42"));
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = RegexCache::new(2, RegexLimits::default());
+        let a = cache.get("a").unwrap();
+        cache.get("b").unwrap();
+
+        // "a" is used again, so "b" is the one evicted.
+        cache.get("a").unwrap();
+        cache.get("c").unwrap();
+
+        assert_eq!(cache.len(), 2);
+        assert!(Rc::ptr_eq(&a, &cache.get("a").unwrap()));
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn rejects_long_patterns() {
+        let limits = RegexLimits {
+            max_length: 8,
+            ..RegexLimits::default()
+        };
+        let mut cache = RegexCache::new(4, limits);
+
+        assert_eq!(cache.get("a{1,1000}").unwrap_err(), RegexError::TooLong(9, 8));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn rejects_complex_patterns() {
+        let limits = RegexLimits {
+            max_size: 10 * 1024,
+            ..RegexLimits::default()
+        };
+
+        let error = limits.compile(r"(\w{100}){100}").unwrap_err();
+        assert!(matches!(error, RegexError::TooComplex(_)), "{:?}", error);
+
+        let nested = format!("{}a{}", "(".repeat(64), ")".repeat(64));
+        let error = RegexLimits::default().compile(&nested).unwrap_err();
+        assert!(matches!(error, RegexError::TooComplex(_)), "{:?}", error);
+    }
+
+    #[test]
+    fn invalid_patterns_are_configuration_errors() {
+        let error = compile("(unclosed").unwrap_err();
+        assert!(matches!(error, RegexError::Invalid(_)));
+
+        let error: PolicyError = error.into();
+        assert_eq!(error.code(), codes::INVALID_PATTERN);
+    }
+}
@@ -0,0 +1,180 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! The decisions the enforcement policies took on an exchange, like the authentication, rate
+//! limit, size and threat protection ones, so the access log can tell in a single field what
+//! the policy chain did to a request.
+//!
+//! Policies record their decisions as a JSON array in the [`POLICY_DECISIONS`] filter state,
+//! in the order they were taken, and the access log reads the whole chain with
+//! `%FILTER_STATE(wasm.exchange.policy_decisions:PLAIN)%`:
+//!
+//! ```json
+//! [{"policy":"jwt-validation","kind":"authentication","outcome":"allow"},
+//!  {"policy":"rate-limiting","kind":"rateLimit","outcome":"deny","reason":"quota exceeded","details":{"status":429}}]
+//! ```
+//!
+//! The PDK policies record them through `pdk-core`, the ones built on the proxy-wasm SDK
+//! read and write the filter state themselves with [`PolicyDecisions::parse`] and
+//! [`PolicyDecisions::to_json`].
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The filter state key collecting the decisions of the policies.
+pub const POLICY_DECISIONS: &str = "exchange.policy_decisions";
+
+/// The decisions kept for an exchange, the first ones are dropped past it so the access log
+/// field stays bounded.
+pub const MAX_DECISIONS: usize = 32;
+
+/// What a policy did to the exchange.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    /// The exchange goes on as is.
+    Allow,
+    /// The exchange is rejected by the policy.
+    Deny,
+    /// The exchange goes on, changed by the policy.
+    Modify,
+    /// The policy does not apply to the exchange.
+    Skip,
+}
+
+/// The summary of the decision of a policy.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Decision {
+    policy: String,
+    kind: String,
+    outcome: Outcome,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    details: BTreeMap<String, Value>,
+}
+
+impl Decision {
+    /// The decision of the `policy` about the `kind` of enforcement, like `authentication`,
+    /// `rateLimit`, `size` or `threat`.
+    pub fn new(policy: &str, kind: &str, outcome: Outcome) -> Self {
+        Self {
+            policy: policy.to_string(),
+            kind: kind.to_string(),
+            outcome,
+            reason: None,
+            details: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_reason(mut self, reason: &str) -> Self {
+        self.reason = Some(reason.to_string());
+        self
+    }
+
+    /// Adds a detail of the decision, like the limit exceeded. Details are meant for
+    /// operators, keep personal data out of them.
+    pub fn with_detail(mut self, name: &str, value: impl Into<Value>) -> Self {
+        self.details.insert(name.to_string(), value.into());
+        self
+    }
+
+    pub fn policy(&self) -> &str {
+        &self.policy
+    }
+
+    pub fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    pub fn outcome(&self) -> Outcome {
+        self.outcome
+    }
+
+    pub fn reason(&self) -> Option<&str> {
+        self.reason.as_deref()
+    }
+
+    pub fn detail(&self, name: &str) -> Option<&Value> {
+        self.details.get(name)
+    }
+}
+
+/// The decisions of the policy chain, in the order they were taken.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PolicyDecisions {
+    decisions: Vec<Decision>,
+}
+
+impl PolicyDecisions {
+    /// The decisions of the JSON array, none when it is not valid.
+    pub fn parse(value: &str) -> Self {
+        let decisions = serde_json::from_str(value).unwrap_or_default();
+        Self { decisions }
+    }
+
+    pub fn add(&mut self, decision: Decision) {
+        if self.decisions.len() >= MAX_DECISIONS {
+            self.decisions.remove(0);
+        }
+        self.decisions.push(decision);
+    }
+
+    pub fn decisions(&self) -> &[Decision] {
+        &self.decisions
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.decisions.is_empty()
+    }
+
+    /// Whether any policy rejected the exchange.
+    pub fn denied(&self) -> bool {
+        self.decisions
+            .iter()
+            .any(|decision| decision.outcome == Outcome::Deny)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&self.decisions).unwrap_or_else(|_| "[]".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Decision, Outcome, PolicyDecisions, MAX_DECISIONS};
+
+    #[test]
+    fn decisions_round_trip_as_json() {
+        let mut decisions = PolicyDecisions::default();
+        decisions.add(Decision::new(
+            "jwt-validation",
+            "authentication",
+            Outcome::Allow,
+        ));
+        decisions.add(
+            Decision::new("rate-limiting", "rateLimit", Outcome::Deny)
+                .with_reason("quota exceeded")
+                .with_detail("status", 429),
+        );
+
+        let json = decisions.to_json();
+        assert_eq!(
+            json,
+            r#"[{"policy":"jwt-validation","kind":"authentication","outcome":"allow"},{"policy":"rate-limiting","kind":"rateLimit","outcome":"deny","reason":"quota exceeded","details":{"status":429}}]"#
+        );
+        assert_eq!(PolicyDecisions::parse(&json), decisions);
+        assert!(decisions.denied());
+        assert!(PolicyDecisions::parse("not json").is_empty());
+    }
+
+    #[test]
+    fn oldest_decisions_are_dropped() {
+        let mut decisions = PolicyDecisions::default();
+        for index in 0..=MAX_DECISIONS {
+            decisions.add(Decision::new(&index.to_string(), "size", Outcome::Skip));
+        }
+
+        assert_eq!(decisions.decisions().len(), MAX_DECISIONS);
+        assert_eq!(decisions.decisions()[0].policy(), "1");
+    }
+}
@@ -1,15 +1,39 @@
 // Copyright 2023 Salesforce, Inc. All rights reserved.
 pub mod api {
     pub use classy;
+    pub use pdk_core::config;
+    pub use pdk_core::error;
+    pub use pdk_core::hash;
+    pub use pdk_core::host::cache;
+    pub use pdk_core::host::metrics;
+    pub use pdk_core::host::property;
+    pub use pdk_core::host::shared_data;
+    pub use pdk_core::host::window;
+    pub use pdk_core::http;
+    pub use pdk_core::keys;
+    pub use pdk_core::policy_context::flags;
+    pub use pdk_core::policy_context::partition;
+    pub use pdk_core::policy_context;
+    #[cfg(feature = "regex-cache")]
+    pub use pdk_core::regex_cache;
     pub use pdk_macros::entrypoint;
     pub use pel_binding as expression;
 
     pub mod logger {
+        pub use pdk_core::logger::capture;
         pub use pdk_core::logger::{debug, error, info, trace, warn};
     }
 }
 
 pub mod __internal {
+    use pdk_core::policy_context::static_policy_context_cache::StaticPolicyContextCache;
+
     pub use pdk_core::host::context::root::RootContextAdapter;
-    pub use pdk_core::init::configure;
+
+    /// Configures the pdk, then the expressions from the policy configuration.
+    pub fn configure(id: u32) -> classy::Plugin {
+        let plugin = pdk_core::init::configure(id);
+        StaticPolicyContextCache::with_metadata(pel_binding::configure);
+        plugin
+    }
 }
@@ -1,5 +1,8 @@
 // Copyright 2023 Salesforce, Inc. All rights reserved.
+use pdk_core::http::request_class::RequestClass;
+use pdk_core::http::template::{CaptureValue, Captures};
 use pdk_core::policy_context::authentication;
+use pdk_core::policy_context::flags::FeatureFlags;
 use pel::runtime::value::Value;
 
 pub trait IntoValue {
@@ -48,6 +51,52 @@ impl<T: IntoValue> IntoValue for Vec<T> {
     }
 }
 
+impl IntoValue for &CaptureValue {
+    fn into_value(self) -> Value {
+        match self {
+            CaptureValue::String(s) => Value::string(s.clone()),
+            CaptureValue::Integer(i) => Value::number(*i as f64),
+            CaptureValue::Number(n) => Value::number(*n),
+            CaptureValue::Bool(b) => Value::bool(*b),
+        }
+    }
+}
+
+impl IntoValue for CaptureValue {
+    fn into_value(self) -> Value {
+        match self {
+            CaptureValue::String(s) => Value::string(s),
+            other => (&other).into_value(),
+        }
+    }
+}
+
+impl IntoValue for &Captures {
+    fn into_value(self) -> Value {
+        Value::object(
+            self.iter()
+                .map(|(name, value)| (name.to_string(), value.into_value()))
+                .collect(),
+        )
+    }
+}
+
+impl IntoValue for &FeatureFlags {
+    fn into_value(self) -> Value {
+        Value::object(
+            self.iter()
+                .map(|(name, enabled)| (name.to_string(), Value::bool(enabled)))
+                .collect(),
+        )
+    }
+}
+
+impl IntoValue for RequestClass {
+    fn into_value(self) -> Value {
+        Value::string(self.name().to_string())
+    }
+}
+
 pub(crate) fn authentication_object_to_value(o: &authentication::Object) -> Value {
     Value::object(o.iter().map(|(k, v)| (k.clone(), v.into_value())).collect())
 }
@@ -91,7 +140,10 @@ impl IntoValue for serde_json::Value {
             serde_json::Value::Bool(b) => Value::bool(b),
             serde_json::Value::String(s) => Value::string(s),
             serde_json::Value::Number(n) => {
-                n.as_f64().map(Value::number).unwrap_or_else(Value::null)
+                n.as_i64()
+                    .map(Value::integer)
+                    .or_else(|| n.as_f64().map(Value::number))
+                    .unwrap_or_else(Value::null)
             }
             serde_json::Value::Array(a) => {
                 Value::array(a.into_iter().map(|v| v.into_value()).collect())
@@ -110,7 +162,10 @@ impl IntoValue for &serde_json::Value {
             serde_json::Value::Bool(b) => Value::bool(*b),
             serde_json::Value::String(s) => Value::string(s.clone()),
             serde_json::Value::Number(n) => {
-                n.as_f64().map(Value::number).unwrap_or_else(Value::null)
+                n.as_i64()
+                    .map(Value::integer)
+                    .or_else(|| n.as_f64().map(Value::number))
+                    .unwrap_or_else(Value::null)
             }
             serde_json::Value::Array(a) => Value::array(a.iter().map(|v| v.into_value()).collect()),
             serde_json::Value::Object(o) => {
@@ -158,6 +213,28 @@ mod tests {
         );
     }
 
+    #[test]
+    fn feature_flags_into_value() {
+        use pdk_core::policy_context::metadata::PolicyMetadata;
+        use std::collections::BTreeMap;
+
+        let defaults = BTreeMap::from([("retries".to_string(), true)]);
+        let flags = FeatureFlags::from_metadata(&PolicyMetadata::default(), &defaults);
+
+        assert_eq!(
+            Value::object(Object::from([("retries".into(), Value::bool(true))])),
+            (&flags).into_value()
+        );
+    }
+
+    #[test]
+    fn request_class_into_value() {
+        assert_eq!(
+            Value::string("json".into()),
+            RequestClass::Json.into_value()
+        );
+    }
+
     #[test]
     fn vec_into_value() {
         assert_eq!(
@@ -283,6 +360,7 @@ mod tests {
         assert_eq!(Value::null(), json!(null).into_value());
         assert_eq!(Value::bool(true), json!(true).into_value());
         assert_eq!(Value::number(11.5), json!(11.5f64).into_value());
+        assert_eq!(Some(42), json!(42).into_value().as_i64());
         assert_eq!(
             Value::string("peregrine".into()),
             json!("peregrine").into_value()
@@ -1,4 +1,5 @@
 // Copyright 2023 Salesforce, Inc. All rights reserved.
+use pdk_core::error::{codes, PolicyError};
 use pel::{parser::ParsingError, runtime::RuntimeError, Location};
 
 #[derive(thiserror::Error, Debug)]
@@ -29,6 +30,16 @@ impl ExpressionError {
     }
 }
 
+impl From<ExpressionError> for PolicyError {
+    fn from(error: ExpressionError) -> Self {
+        let code = match error {
+            ExpressionError::ParsingError(_) => codes::EXPRESSION_PARSING,
+            _ => codes::EXPRESSION_EVALUATION,
+        };
+        PolicyError::new(code, error.to_string())
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Span {
     line: usize,
@@ -37,13 +48,11 @@ pub struct Span {
 
 impl Span {
     fn new(source: &str, index: usize) -> Self {
-        assert!(source.len() > index, "source len is lower than index");
-
         let mut line = 1usize;
         let mut column = 1usize;
 
-        for (pos, c) in source.chars().enumerate() {
-            if pos == index {
+        for (pos, c) in source.char_indices() {
+            if pos >= index {
                 break;
             }
 
@@ -62,18 +71,45 @@ impl Span {
 #[derive(Debug)]
 pub struct Snippet {
     span: Span,
-    text: String,
+    line: String,
+    marker: String,
 }
 
 impl Snippet {
     fn new(source: &str, location: Location) -> Self {
+        // The location comes with the expression, which may not match its source.
+        let start = char_boundary(source, location.start);
+        let end = char_boundary(source, location.end).max(start);
+
+        let line_start = source[..start].rfind('\n').map_or(0, |index| index + 1);
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |index| start + index);
+
+        // Tabs are kept so the marker stays aligned with the line.
+        let indentation: String = source[line_start..start]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let marked = source[start..end.min(line_end)].chars().count().max(1);
+
         Self {
-            span: Span::new(source, location.start),
-            text: source[location.start..location.end].to_string(),
+            span: Span::new(source, start),
+            line: source[line_start..line_end].to_string(),
+            marker: format!("{indentation}{}", "^".repeat(marked)),
         }
     }
 }
 
+/// Returns the closest char boundary of `source` at or before `index`.
+fn char_boundary(source: &str, index: usize) -> usize {
+    let mut index = index.min(source.len());
+    while !source.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
 #[derive(thiserror::Error, Debug)]
 pub struct DetailedRuntimeError {
     snippet: Snippet,
@@ -99,7 +135,15 @@ impl std::fmt::Display for DetailedRuntimeError {
             "\tline: {}, column: {}",
             self.snippet.span.line, self.snippet.span.column
         )?;
-        writeln!(f, "{}| {}", self.snippet.span.line, self.snippet.text)?;
+        let line_number = self.snippet.span.line.to_string();
+        writeln!(f, "{line_number}| {}", self.snippet.line)?;
+        writeln!(
+            f,
+            "{:width$}| {}",
+            "",
+            self.snippet.marker,
+            width = line_number.len()
+        )?;
         Ok(())
     }
 }
@@ -160,10 +204,29 @@ mod tests {
 
         let snippet = Snippet::new(source, Location::new(80, 91));
 
-        assert_eq!(snippet.text, "1 * 'hello'");
+        assert_eq!(snippet.line, "                    1 * 'hello'");
+        assert_eq!(
+            snippet.marker,
+            format!("{}{}", " ".repeat(20), "^".repeat(11))
+        );
         assert_eq!(snippet.span, Span::new(source, 80));
     }
 
+    #[test]
+    fn new_snippet_out_of_source() {
+        let source = "lower(\"año\")";
+
+        let snippet = Snippet::new(source, Location::new(9, 100));
+        assert_eq!(
+            snippet.marker,
+            format!("{}{}", " ".repeat(8), "^".repeat(4))
+        );
+
+        let snippet = Snippet::new(source, Location::new(100, 3));
+        assert_eq!(snippet.marker, format!("{}^", " ".repeat(12)));
+        assert_eq!(snippet.line, source);
+    }
+
     #[test]
     fn display_detailed_error() {
         let location = Location::new(80, 91);
@@ -180,8 +243,24 @@ mod tests {
         let pel_error = ExpressionError::with_optional_source(cause, Some(source));
 
         let actual = format!("{pel_error}");
-        let expected =
-            "Runtime error:\n\tType mismatch\nLocation:\n\tline: 5, column: 20\n5| 1 * 'hello'\n";
+        let expected = "Runtime error:\n\tType mismatch\nLocation:\n\tline: 5, column: 20\n\
+            5|                     1 * 'hello'\n \
+             |                     ^^^^^^^^^^^\n";
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn display_detailed_error_spanning_lines() {
+        let location = Location::new(0, 21);
+        let source = "upper(\n\tpayload.items)";
+
+        let cause = RuntimeError::new(location, pel::runtime::RuntimeErrorKind::TypeMismatch);
+        let pel_error = ExpressionError::with_optional_source(cause, Some(source));
+
+        let actual = format!("{pel_error}");
+        let expected = "Runtime error:\n\tType mismatch\nLocation:\n\tline: 1, column: 1\n\
+            1| upper(\n \
+             | ^^^^^^\n";
         assert_eq!(actual, expected);
     }
 }
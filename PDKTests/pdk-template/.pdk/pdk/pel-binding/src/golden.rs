@@ -0,0 +1,182 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Resolves the golden cases of pel (see pel/tests/golden.rs) and the ones of
+//! `tests/golden`, which can also set the `headers` of the request, or of the response when
+//! `on` is `"response"`. The authentication and connection properties are the ones of
+//! [`MockPolicyContext`].
+use std::{cell::RefCell, fs, path::Path};
+
+use classy::event::HeadersAccessor;
+use pel::runtime::value::Value;
+
+use crate::{resolver::Expression, tests::MockPolicyContext, ExpressionError};
+
+const PEL_GOLDEN_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../pel/tests/golden");
+const GOLDEN_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden");
+
+struct Headers(RefCell<Vec<(String, String)>>);
+
+impl HeadersAccessor for Headers {
+    fn header(&self, name: &str) -> Option<String> {
+        self.0
+            .borrow()
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.clone())
+    }
+
+    fn headers(&self) -> Vec<(String, String)> {
+        self.0.borrow().clone()
+    }
+
+    fn add_header(&self, name: &str, value: &str) {
+        self.0
+            .borrow_mut()
+            .push((name.to_string(), value.to_string()));
+    }
+
+    fn set_header(&self, name: &str, value: &str) {
+        self.remove_header(name);
+        self.add_header(name, value);
+    }
+
+    fn set_headers(&self, headers: Vec<(&str, &str)>) {
+        *self.0.borrow_mut() = headers
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+    }
+
+    fn remove_header(&self, name: &str) {
+        self.0
+            .borrow_mut()
+            .retain(|(key, _)| !key.eq_ignore_ascii_case(name));
+    }
+}
+
+// Numbers and decimals match by their f64 value.
+fn matches(actual: &Value, expected: &serde_json::Value) -> bool {
+    match expected {
+        serde_json::Value::Null => actual.is_null(),
+        serde_json::Value::Bool(b) => actual.as_bool() == Some(*b),
+        serde_json::Value::Number(n) => actual.as_f64() == n.as_f64(),
+        serde_json::Value::String(s) => actual.as_str() == Some(s.as_str()),
+        serde_json::Value::Array(expected) => actual.as_slice().map_or(false, |actual| {
+            actual.len() == expected.len()
+                && actual.iter().zip(expected).all(|(a, e)| matches(a, e))
+        }),
+        serde_json::Value::Object(expected) => actual.as_object().map_or(false, |actual| {
+            actual.len() == expected.len()
+                && expected
+                    .iter()
+                    .all(|(k, e)| actual.get(k).map_or(false, |a| matches(a, e)))
+        }),
+    }
+}
+
+// The arms are all true when every feature is enabled, which does not make it a `matches!`.
+#[allow(clippy::match_like_matches_macro)]
+fn feature_enabled(feature: &str) -> bool {
+    match feature {
+        "prelude-decimals" => cfg!(feature = "prelude-decimals"),
+        "prelude-hash" => cfg!(feature = "prelude-hash"),
+        "prelude-strings" => cfg!(feature = "prelude-strings"),
+        "prelude-uuid" => cfg!(feature = "prelude-uuid"),
+        "graphemes" => cfg!(feature = "graphemes"),
+        "integer-numbers" => cfg!(feature = "integer-numbers"),
+        _ => false,
+    }
+}
+
+fn error_message(error: ExpressionError) -> String {
+    match error {
+        ExpressionError::ParsingError(e) => e.to_string(),
+        ExpressionError::RuntimeError(e) => e.kind().to_string(),
+        e => e.to_string(),
+    }
+}
+
+fn resolve(case: &serde_json::Value) -> Result<Value, ExpressionError> {
+    let expression = Expression::parse(&case["expression"].to_string())?;
+
+    let headers = case["headers"]
+        .as_array()
+        .map(|headers| {
+            headers
+                .iter()
+                .map(|header| {
+                    let name = header[0].as_str().unwrap_or_default().to_string();
+                    let value = header[1].as_str().unwrap_or_default().to_string();
+                    (name, value)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let headers = Headers(RefCell::new(headers));
+
+    let empty = serde_json::Map::new();
+    let vars = case["vars"].as_object().unwrap_or(&empty);
+    let resolver = expression.with_vars(vars.iter().map(|(name, value)| (name.as_str(), value)));
+
+    match case["on"].as_str() {
+        Some("response") => resolver.__resolve_on_response_headers(&MockPolicyContext, &headers),
+        _ => resolver.__resolve_on_request_headers(&MockPolicyContext, &headers),
+    }
+}
+
+// Returns the failure of the case, if any.
+fn check(case: &serde_json::Value) -> Option<String> {
+    let result = resolve(case).map_err(error_message);
+
+    match (result, case.get("expected"), case.get("error")) {
+        (Ok(actual), Some(expected), None) if matches(&actual, expected) => None,
+        (Err(actual), None, Some(expected)) if expected.as_str() == Some(actual.as_str()) => None,
+        (actual, expected, error) => Some(format!(
+            "expected {:?}, error {:?}, got {:?}",
+            expected, error, actual
+        )),
+    }
+}
+
+fn check_dir(dir: &str) {
+    let mut evaluated = 0;
+    let mut failures = vec![];
+
+    let mut files: Vec<_> = fs::read_dir(Path::new(dir))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |e| e == "json"))
+        .collect();
+    files.sort();
+
+    for file in &files {
+        let content = fs::read_to_string(file).unwrap();
+        let cases: Vec<serde_json::Value> = serde_json::from_str(&content)
+            .unwrap_or_else(|e| panic!("Bad formed golden file {}: {}", file.display(), e));
+
+        for case in &cases {
+            let required = case.get("requires").and_then(serde_json::Value::as_str);
+            if !required.map_or(true, feature_enabled) {
+                continue;
+            }
+
+            evaluated += 1;
+            if let Some(failure) = check(case) {
+                let name = file.file_name().unwrap().to_string_lossy();
+                failures.push(format!("{} / {}: {}", name, case["name"], failure));
+            }
+        }
+    }
+
+    assert!(evaluated > 0, "No golden case evaluated in {}", dir);
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn pel_golden_files() {
+    check_dir(PEL_GOLDEN_DIR);
+}
+
+#[test]
+fn binding_golden_files() {
+    check_dir(GOLDEN_DIR);
+}
@@ -1,6 +1,15 @@
 // Copyright 2023 Salesforce, Inc. All rights reserved.
-use classy::event::HeadersAccessor;
+use classy::event::{BodyAccessor, HeadersAccessor};
+use convert::IntoValue;
 use pdk_core::{
+    host::property::{CachedPropertyAccessor, PropertyAccessor},
+    http::baggage::{self, Baggage},
+    http::cookies::{cookie, parse_cookies},
+    http::matrix::MatrixPath,
+    http::query::{self, DuplicateParams},
+    http::request_class::RequestClass,
+    http::template::UrlTemplate,
+    http::tls_fingerprint::TlsFingerprint,
     log::trace,
     policy_context::{authentication::Authentication, PolicyContext},
 };
@@ -12,31 +21,48 @@ use pel::{
 use std::{
     cell::{RefCell, RefMut},
     collections::HashMap,
+    rc::Rc,
 };
 
 pub mod convert;
 mod custom_getrandom;
 mod error;
+#[cfg(test)]
+mod golden;
 mod resolver;
 
 pub use error::ExpressionError;
 pub use pel::runtime::value::Value;
-pub use resolver::{Expression, ExpressionResolver};
+pub use pel::runtime::{FunctionRegistry, RegistryError};
+use resolver::{duplicate_query_params, query_decoding};
+
+pub use resolver::{
+    configure, register_function, set_duplicate_query_params, set_query_decoding, set_tracing,
+    Expression, ExpressionResolver,
+};
 
 // Keys
 const ATTRIBUTES: &str = "attributes";
 const AUTHENTICATION: &str = "authentication";
+const BAGGAGE: &str = "baggage";
+const COOKIES: &str = "cookies";
+const FILTER_STATE: &str = "filterState";
 const HEADERS: &str = "headers";
 const METHOD: &str = "method";
 const PAYLOAD: &str = "payload";
 const QUERY_PARAMS: &str = "queryParams";
+const QUERY_PARAMS_MULTI: &str = "queryParamsMulti";
 const REQUEST_PATH: &str = "requestPath";
 const REQUEST_URI: &str = "requestUri";
 const REMOTE_ADDRESS: &str = "remoteAddress";
 const STATUS_CODE: &str = "statusCode";
 const LOCAL_ADDRESS: &str = "localAddress";
+const MATRIX_PARAMS: &str = "matrixParams";
+const PATH_PARAMS: &str = "pathParams";
 const QUERY_STRING: &str = "queryString";
+const RAW_QUERY_STRING: &str = "rawQueryString";
 const SCHEME: &str = "scheme";
+const TLS: &str = "tls";
 const VARS: &str = "vars";
 const VERSION: &str = "version";
 
@@ -46,6 +72,12 @@ const CLIENT_NAME: &str = "clientName";
 const PRINCIPAL: &str = "principal";
 const PROPERTIES: &str = "properties";
 
+// TLS Keys
+const ALPN: &str = "alpn";
+const FINGERPRINT: &str = "fingerprint";
+const MTLS: &str = "mtls";
+const SERVER_NAME: &str = "serverName";
+
 // References
 const CONTEXT_ID: ContextId = ContextId::new(module_path!());
 const ATTRIBUTES_REFERENCE: Reference = CONTEXT_ID.first_reference();
@@ -53,8 +85,14 @@ const AUTHENTICATION_REFERENCE: Reference = ATTRIBUTES_REFERENCE.next();
 const HEADERS_REFERENCE: Reference = AUTHENTICATION_REFERENCE.next();
 const QUERY_PARAMS_REFERENCE: Reference = HEADERS_REFERENCE.next();
 const VARS_REFERENCE: Reference = QUERY_PARAMS_REFERENCE.next();
+const FILTER_STATE_REFERENCE: Reference = VARS_REFERENCE.next();
+const QUERY_PARAMS_MULTI_REFERENCE: Reference = FILTER_STATE_REFERENCE.next();
+const PAYLOAD_REFERENCE: Reference = QUERY_PARAMS_MULTI_REFERENCE.next();
+const COOKIES_REFERENCE: Reference = PAYLOAD_REFERENCE.next();
 
 // Headers
+const CONTENT_TYPE_HEADER: &str = "content-type";
+const COOKIE_HEADER: &str = "cookie";
 const METHOD_HEADER: &str = ":method";
 const PATH_HEADER: &str = ":path";
 const STATUS_CODE_HEADER: &str = ":status";
@@ -62,19 +100,143 @@ const STATUS_CODE_HEADER: &str = ":status";
 pub(crate) type Vars<'a> = &'a HashMap<&'a str, Value>;
 
 struct OnPayloadContext {
-    payload: String,
+    payload: PayloadHandler<'static>,
+}
+
+impl OnPayloadContext {
+    fn new(payload: String) -> Self {
+        Self {
+            payload: PayloadHandler::text(payload),
+        }
+    }
 }
 
 impl Context for OnPayloadContext {
     fn resolve(&self, symbol: &Symbol) -> Binding {
         match symbol.as_str() {
-            PAYLOAD => Binding::Available(Value::string(self.payload.clone())),
+            PAYLOAD => Binding::Available(Value::reference(PAYLOAD_REFERENCE)),
             _ => Binding::Unknown,
         }
     }
 
-    fn value_handler(&self, _reference: Reference) -> Option<&dyn ValueHandler> {
-        None
+    fn value_handler(&self, reference: Reference) -> Option<&dyn ValueHandler> {
+        match reference {
+            PAYLOAD_REFERENCE => Some(&self.payload),
+            _ => None,
+        }
+    }
+}
+
+enum PayloadSource<'a> {
+    Text(String),
+    Body {
+        body: &'a dyn BodyAccessor,
+        headers: &'a dyn HeadersAccessor,
+    },
+}
+
+/// The body of an exchange as `payload`. The body is read from the host the first time an
+/// expression uses it, and parsed once when it is JSON content, so expressions select into it
+/// like `payload.items[0].sku`.
+struct PayloadHandler<'a> {
+    source: PayloadSource<'a>,
+    text: RefCell<Option<Rc<String>>>,
+    // None when the payload is not JSON content, or not valid JSON.
+    json: RefCell<Option<Option<Rc<serde_json::Value>>>>,
+}
+
+impl<'a> PayloadHandler<'a> {
+    fn text(payload: String) -> Self {
+        Self::new(PayloadSource::Text(payload))
+    }
+
+    fn body(body: &'a dyn BodyAccessor, headers: &'a dyn HeadersAccessor) -> Self {
+        Self::new(PayloadSource::Body { body, headers })
+    }
+
+    fn new(source: PayloadSource<'a>) -> Self {
+        Self {
+            source,
+            text: RefCell::new(None),
+            json: RefCell::new(None),
+        }
+    }
+
+    fn as_text(&self) -> Rc<String> {
+        self.text
+            .borrow_mut()
+            .get_or_insert_with(|| {
+                Rc::new(match &self.source {
+                    PayloadSource::Text(payload) => payload.clone(),
+                    PayloadSource::Body { body, .. } => {
+                        String::from_utf8_lossy(&body.body()).into_owned()
+                    }
+                })
+            })
+            .clone()
+    }
+
+    /// The payload parsed, when it is JSON content. None for the rest, which is never selected
+    /// into even when it looks like JSON.
+    fn json(&self) -> Option<Rc<serde_json::Value>> {
+        if let Some(json) = self.json.borrow().as_ref() {
+            return json.clone();
+        }
+        let json = self
+            .is_json_content()
+            .then(|| serde_json::from_str(&self.as_text()).ok().map(Rc::new))
+            .flatten();
+        *self.json.borrow_mut() = Some(json.clone());
+        json
+    }
+
+    /// Whether the content type tells the body is JSON, like `application/problem+json`.
+    /// Payloads given as a string have none, and are JSON when they parse.
+    fn is_json_content(&self) -> bool {
+        let content_type = match &self.source {
+            PayloadSource::Text(_) => return true,
+            PayloadSource::Body { headers, .. } => headers.header(CONTENT_TYPE_HEADER),
+        };
+        let class = content_type
+            .as_deref()
+            .and_then(RequestClass::from_content_type);
+        class == Some(RequestClass::Json)
+    }
+}
+
+impl ValueHandler for PayloadHandler<'_> {
+    /// JSON content is detached parsed, and the rest, or JSON content not valid, as a string.
+    fn detach(&self) -> Option<Value> {
+        match self.json() {
+            Some(json) => Some(json.as_ref().into_value()),
+            None => Some(Value::string(self.as_text().as_ref().clone())),
+        }
+    }
+
+    fn select_by_key(&self, key: &str) -> Option<Value> {
+        self.json()?
+            .as_object()?
+            .get(key)
+            .map(IntoValue::into_value)
+    }
+
+    fn select_by_index(&self, index: usize) -> Option<Value> {
+        let json = self.json()?;
+        let items = json.as_array()?;
+        Some(
+            items
+                .get(index)
+                .map(IntoValue::into_value)
+                .unwrap_or_else(Value::null),
+        )
+    }
+
+    fn size(&self) -> Option<usize> {
+        match self.json().as_deref() {
+            Some(serde_json::Value::Array(items)) => Some(items.len()),
+            Some(serde_json::Value::Object(fields)) => Some(fields.len()),
+            _ => Some(self.as_text().chars().count()),
+        }
     }
 }
 
@@ -84,6 +246,11 @@ trait OpsContext: Clone {
     fn headers(&self) -> Vec<(String, String)>;
 
     fn policy_context(&self) -> &dyn PolicyContext;
+
+    fn connection_properties(&self) -> &dyn PropertyAccessor;
+
+    /// The template of the route of the request, the source of `attributes.pathParams`.
+    fn route(&self) -> Option<&UrlTemplate>;
 }
 
 pub enum EvaluationMode {
@@ -104,6 +271,27 @@ impl EvaluationMode {
 struct HeadersOpsContext<'a> {
     policy_context: &'a dyn PolicyContext,
     accessor: &'a dyn HeadersAccessor,
+    // Shared by the handlers, so each property is read from the host once per resolution.
+    properties: Rc<CachedPropertyAccessor<'a>>,
+    route: Option<&'a UrlTemplate>,
+}
+
+impl<'a> HeadersOpsContext<'a> {
+    fn new(policy_context: &'a dyn PolicyContext, accessor: &'a dyn HeadersAccessor) -> Self {
+        Self {
+            policy_context,
+            accessor,
+            properties: Rc::new(CachedPropertyAccessor::new(
+                policy_context.connection_properties(),
+            )),
+            route: None,
+        }
+    }
+
+    fn with_route(mut self, route: Option<&'a UrlTemplate>) -> Self {
+        self.route = route;
+        self
+    }
 }
 
 impl<'a> OpsContext for HeadersOpsContext<'a> {
@@ -118,6 +306,14 @@ impl<'a> OpsContext for HeadersOpsContext<'a> {
     fn policy_context(&self) -> &dyn PolicyContext {
         self.policy_context
     }
+
+    fn connection_properties(&self) -> &dyn PropertyAccessor {
+        self.properties.as_ref()
+    }
+
+    fn route(&self) -> Option<&UrlTemplate> {
+        self.route
+    }
 }
 
 struct RequestOpsContextWrapper<'a, C: OpsContext> {
@@ -125,6 +321,7 @@ struct RequestOpsContextWrapper<'a, C: OpsContext> {
     attributes: RequestAttributesHandler<C>,
     authentication: AuthenticationHandler<C>,
     vars: VarsHandler<'a>,
+    payload: Option<PayloadHandler<'a>>,
 }
 
 impl<'a, C: OpsContext> RequestOpsContextWrapper<'a, C> {
@@ -134,8 +331,14 @@ impl<'a, C: OpsContext> RequestOpsContextWrapper<'a, C> {
             attributes: RequestAttributesHandler::new(source.clone()),
             authentication: AuthenticationHandler::new(source),
             vars: VarsHandler::new(vars),
+            payload: None,
         }
     }
+
+    fn with_payload(mut self, payload: PayloadHandler<'a>) -> Self {
+        self.payload = Some(payload);
+        self
+    }
 }
 
 struct ResponseOpsContextWrapper<'a, C: OpsContext> {
@@ -143,6 +346,7 @@ struct ResponseOpsContextWrapper<'a, C: OpsContext> {
     attributes: ResponseAttributesHandler<C>,
     authentication: AuthenticationHandler<C>,
     vars: VarsHandler<'a>,
+    payload: Option<PayloadHandler<'a>>,
 }
 
 impl<'a, C: OpsContext> ResponseOpsContextWrapper<'a, C> {
@@ -152,25 +356,62 @@ impl<'a, C: OpsContext> ResponseOpsContextWrapper<'a, C> {
             attributes: ResponseAttributesHandler::new(source.clone()),
             authentication: AuthenticationHandler::new(source),
             vars: VarsHandler::new(vars),
+            payload: None,
         }
     }
+
+    fn with_payload(mut self, payload: PayloadHandler<'a>) -> Self {
+        self.payload = Some(payload);
+        self
+    }
 }
 
 fn fake_url(uri: &str) -> Option<url::Url> {
     url::Url::parse("http://fake_base").ok()?.join(uri).ok()
 }
 
-fn extract_query_param(uri: &str, name: &str) -> Option<String> {
-    fake_url(uri)?
-        .query_pairs()
-        .find_map(|(key, value)| (key == name).then(|| value.to_string()))
+/// The decoded query parameters of `uri`, as told by [`set_query_decoding`].
+fn query_pairs(uri: &str) -> Vec<(String, String)> {
+    let query = extract_raw_query_string(uri).unwrap_or_default();
+    query::query_pairs(&query, query_decoding())
+}
+
+fn extract_query_param(uri: &str, name: &str, duplicates: DuplicateParams) -> Option<String> {
+    let mut values = query_pairs(uri)
+        .into_iter()
+        .filter_map(|(key, value)| (key == name).then_some(value));
+    match duplicates {
+        DuplicateParams::FirstWins => values.next(),
+        DuplicateParams::LastWins => values.next_back(),
+    }
+}
+
+fn extract_query_params(uri: &str, duplicates: DuplicateParams) -> Option<Object> {
+    let mut params = Object::new();
+    for (k, v) in query_pairs(uri) {
+        let value = Value::string(v);
+        match duplicates {
+            DuplicateParams::FirstWins => {
+                params.entry(k).or_insert(value);
+            }
+            DuplicateParams::LastWins => {
+                params.insert(k, value);
+            }
+        }
+    }
+    Some(params)
 }
 
-fn extract_query_params(uri: &str) -> Option<Object> {
+/// Every value of each query parameter, in order.
+fn extract_query_params_multi(uri: &str) -> Option<Object> {
+    let mut params: HashMap<String, Vec<Value>> = HashMap::new();
+    for (k, v) in query_pairs(uri) {
+        params.entry(k).or_default().push(Value::string(v));
+    }
     Some(
-        fake_url(uri)?
-            .query_pairs()
-            .map(|(k, v)| (k.to_string(), Value::string(v.to_string())))
+        params
+            .into_iter()
+            .map(|(k, values)| (k, Value::array(values)))
             .collect(),
     )
 }
@@ -179,6 +420,49 @@ fn extract_query_string(uri: &str) -> Option<String> {
     fake_url(uri)?.query().map(|s| s.to_string())
 }
 
+/// The query string exactly as sent, without the normalization of the parsed view.
+fn extract_raw_query_string(uri: &str) -> Option<String> {
+    let uri = uri.split('#').next().unwrap_or_default();
+    uri.split_once('?').map(|(_, query)| query.to_string())
+}
+
+/// The captures of `route` in the path of `uri`, matched without its matrix parameters.
+fn extract_path_params(uri: &str, route: &UrlTemplate) -> Option<Value> {
+    let captures = route.matches(MatrixPath::parse(uri).path())?;
+    Some((&captures).into_value())
+}
+
+/// The valid members of the `baggage` header, the first one winning when repeated.
+fn extract_baggage(header: Option<&str>) -> Object {
+    let mut entries = Object::new();
+    for entry in Baggage::parse(header).entries() {
+        entries
+            .entry(entry.key().to_string())
+            .or_insert_with(|| Value::string(entry.value().to_string()));
+    }
+    entries
+}
+
+/// The cookies of the `Cookie` header, the first one winning when repeated.
+fn extract_cookies(header: Option<&str>) -> Object {
+    let mut cookies = Object::new();
+    for (name, value) in parse_cookies(header.unwrap_or_default()) {
+        cookies
+            .entry(name.to_string())
+            .or_insert_with(|| Value::string(value.to_string()));
+    }
+    cookies
+}
+
+/// The matrix parameters of every segment, the last one winning when repeated.
+fn extract_matrix_params(uri: &str) -> Object {
+    MatrixPath::parse(uri)
+        .params()
+        .iter()
+        .map(|param| (param.name.clone(), Value::string(param.value.clone())))
+        .collect()
+}
+
 fn extract_path(uri: &str) -> Option<String> {
     let mut url = fake_url(uri)?;
     url.set_query(None);
@@ -188,7 +472,10 @@ fn extract_path(uri: &str) -> Option<String> {
 struct RequestAttributesHandler<C> {
     source: C,
     headers: HeadersHandler<C>,
+    cookies: CookiesHandler<C>,
     query_params: QueryParamsHandler<C>,
+    query_params_multi: QueryParamsMultiHandler<C>,
+    filter_state: FilterStateHandler<C>,
 }
 
 impl<C: OpsContext> RequestAttributesHandler<C> {
@@ -198,7 +485,16 @@ impl<C: OpsContext> RequestAttributesHandler<C> {
             headers: HeadersHandler {
                 source: source.clone(),
             },
-            query_params: QueryParamsHandler { source },
+            cookies: CookiesHandler {
+                source: source.clone(),
+            },
+            query_params: QueryParamsHandler {
+                source: source.clone(),
+            },
+            query_params_multi: QueryParamsMultiHandler {
+                source: source.clone(),
+            },
+            filter_state: FilterStateHandler { source },
         }
     }
 
@@ -221,10 +517,24 @@ impl<C: OpsContext> RequestAttributesHandler<C> {
             .map(Value::string)
     }
 
+    /// Null when there is no route, or when the request does not match it.
+    fn path_params(&self, uri: Option<&str>) -> Option<Value> {
+        let route = self.source.route()?;
+        uri.and_then(|uri| extract_path_params(uri, route))
+    }
+
+    fn baggage(&self) -> Option<Value> {
+        let header = self.source.header(baggage::BAGGAGE);
+        Some(Value::object(extract_baggage(header.as_deref())))
+    }
+
+    fn matrix_params(&self, uri: Option<&str>) -> Option<Value> {
+        uri.map(|uri| Value::object(extract_matrix_params(uri)))
+    }
+
     fn remote_address(&self) -> Option<Value> {
         let address = self
             .source
-            .policy_context()
             .connection_properties()
             .source()
             .address()
@@ -237,7 +547,6 @@ impl<C: OpsContext> RequestAttributesHandler<C> {
     fn local_address(&self) -> Option<Value> {
         let address = self
             .source
-            .policy_context()
             .connection_properties()
             .destination()
             .address()
@@ -255,10 +564,17 @@ impl<C: OpsContext> RequestAttributesHandler<C> {
         Some(query_string)
     }
 
+    fn raw_query_string(&self) -> Option<Value> {
+        let path = self.source.header(PATH_HEADER)?;
+        let query_string = extract_raw_query_string(&path)
+            .map(Value::string)
+            .unwrap_or_else(Value::null);
+        Some(query_string)
+    }
+
     fn scheme(&self) -> Option<Value> {
         let address = self
             .source
-            .policy_context()
             .connection_properties()
             .request()
             .scheme()
@@ -271,7 +587,6 @@ impl<C: OpsContext> RequestAttributesHandler<C> {
     fn version(&self) -> Option<Value> {
         let address = self
             .source
-            .policy_context()
             .connection_properties()
             .request()
             .protocol()
@@ -280,20 +595,95 @@ impl<C: OpsContext> RequestAttributesHandler<C> {
             .unwrap_or_else(Value::null);
         Some(address)
     }
+
+    /// Null when the connection is not over TLS.
+    fn tls(&self) -> Option<Value> {
+        let tls = match TlsFingerprint::read(self.source.connection_properties()) {
+            Some(tls) => tls,
+            None => return Some(Value::null()),
+        };
+        let optional = |value: Option<&str>| {
+            value
+                .map(|value| Value::string(value.to_string()))
+                .unwrap_or_else(Value::null)
+        };
+
+        let values = [
+            (ALPN, optional(tls.alpn())),
+            (FINGERPRINT, Value::string(tls.fingerprint())),
+            (MTLS, Value::bool(tls.mtls())),
+            (SERVER_NAME, optional(tls.server_name())),
+            (VERSION, Value::string(tls.version().to_string())),
+        ]
+        .map(|(k, v)| (k.to_string(), v));
+
+        Some(Value::object(values.into()))
+    }
 }
 
 impl<C: OpsContext> ValueHandler for RequestAttributesHandler<C> {
     fn detach(&self) -> Option<Value> {
+        // The headers are read with a single host call, for both the headers attribute and the
+        // ones most attributes derive from.
+        let headers = self.source.headers();
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.clone())
+        };
+        let method = header(METHOD_HEADER);
+        let uri = header(PATH_HEADER);
+        let baggage = header(baggage::BAGGAGE);
+        let cookies = header(COOKIE_HEADER);
+
+        let query_string = uri.as_deref().map(|uri| {
+            extract_query_string(uri)
+                .map(Value::string)
+                .unwrap_or_else(Value::null)
+        });
+        let raw_query_string = uri.as_deref().map(|uri| {
+            extract_raw_query_string(uri)
+                .map(Value::string)
+                .unwrap_or_else(Value::null)
+        });
+
         let values = [
-            (HEADERS, self.headers.detach()),
-            (METHOD, self.method()),
-            (QUERY_PARAMS, self.query_params.detach()),
-            (REQUEST_PATH, self.path()),
-            (REQUEST_URI, self.uri()),
+            (
+                BAGGAGE,
+                Some(Value::object(extract_baggage(baggage.as_deref()))),
+            ),
+            (
+                COOKIES,
+                Some(Value::object(extract_cookies(cookies.as_deref()))),
+            ),
+            (HEADERS, Some(headers_object(headers))),
+            (METHOD, method.map(Value::string)),
+            (
+                QUERY_PARAMS,
+                uri.as_deref()
+                    .and_then(|uri| extract_query_params(uri, duplicate_query_params()))
+                    .map(Value::object),
+            ),
+            (
+                QUERY_PARAMS_MULTI,
+                uri.as_deref()
+                    .and_then(extract_query_params_multi)
+                    .map(Value::object),
+            ),
+            (MATRIX_PARAMS, self.matrix_params(uri.as_deref())),
+            (PATH_PARAMS, self.path_params(uri.as_deref())),
+            (
+                REQUEST_PATH,
+                uri.as_deref().and_then(extract_path).map(Value::string),
+            ),
+            (REQUEST_URI, uri.map(Value::string)),
             (REMOTE_ADDRESS, self.remote_address()),
             (LOCAL_ADDRESS, self.local_address()),
-            (QUERY_STRING, self.query_string()),
+            (QUERY_STRING, query_string),
+            (RAW_QUERY_STRING, raw_query_string),
             (SCHEME, self.scheme()),
+            (TLS, self.tls()),
             (VERSION, self.version()),
         ]
         .map(|(k, v)| (k.to_string(), v.unwrap_or_else(Value::null)));
@@ -303,16 +693,24 @@ impl<C: OpsContext> ValueHandler for RequestAttributesHandler<C> {
 
     fn select_by_key(&self, key: &str) -> Option<Value> {
         let selection = match key {
+            BAGGAGE => self.baggage(),
+            COOKIES => Some(Value::reference(COOKIES_REFERENCE)),
             HEADERS => Some(Value::reference(HEADERS_REFERENCE)),
             METHOD => self.method(),
             QUERY_PARAMS => Some(Value::reference(QUERY_PARAMS_REFERENCE)),
+            QUERY_PARAMS_MULTI => Some(Value::reference(QUERY_PARAMS_MULTI_REFERENCE)),
+            MATRIX_PARAMS => self.matrix_params(self.source.header(PATH_HEADER).as_deref()),
+            PATH_PARAMS => self.path_params(self.source.header(PATH_HEADER).as_deref()),
             REQUEST_PATH => self.path(),
             REQUEST_URI => self.uri(),
             REMOTE_ADDRESS => self.remote_address(),
             LOCAL_ADDRESS => self.local_address(),
             QUERY_STRING => self.query_string(),
+            RAW_QUERY_STRING => self.raw_query_string(),
             SCHEME => self.scheme(),
+            TLS => self.tls(),
             VERSION => self.version(),
+            FILTER_STATE => Some(Value::reference(FILTER_STATE_REFERENCE)),
             _ => None,
         };
         Some(selection.unwrap_or_else(Value::null))
@@ -322,13 +720,17 @@ impl<C: OpsContext> ValueHandler for RequestAttributesHandler<C> {
 struct ResponseAttributesHandler<C> {
     source: C,
     headers: HeadersHandler<C>,
+    filter_state: FilterStateHandler<C>,
 }
 
 impl<C: OpsContext> ResponseAttributesHandler<C> {
     fn new(source: C) -> Self {
         Self {
             source: source.clone(),
-            headers: HeadersHandler { source },
+            headers: HeadersHandler {
+                source: source.clone(),
+            },
+            filter_state: FilterStateHandler { source },
         }
     }
 
@@ -344,7 +746,7 @@ impl<C: OpsContext> ResponseAttributesHandler<C> {
                     trace!("Unexpected error parsing status code: {:?}", err);
                     Value::null()
                 }
-                Some(Ok(number)) => Value::number(number as f64),
+                Some(Ok(number)) => Value::integer(number.into()),
             }
         })
     }
@@ -365,6 +767,7 @@ impl<C: OpsContext> ValueHandler for ResponseAttributesHandler<C> {
         let selection = match key {
             HEADERS => Some(Value::reference(HEADERS_REFERENCE)),
             STATUS_CODE => self.status_code(),
+            FILTER_STATE => Some(Value::reference(FILTER_STATE_REFERENCE)),
             _ => None,
         };
 
@@ -462,15 +865,18 @@ struct HeadersHandler<C> {
     source: C,
 }
 
+fn headers_object(headers: Vec<(String, String)>) -> Value {
+    Value::object(
+        headers
+            .into_iter()
+            .map(|(k, v)| (k, Value::string(v)))
+            .collect(),
+    )
+}
+
 impl<C: OpsContext> ValueHandler for HeadersHandler<C> {
     fn detach(&self) -> Option<Value> {
-        Some(Value::object(
-            self.source
-                .headers()
-                .into_iter()
-                .map(|(k, v)| (k, Value::string(v)))
-                .collect(),
-        ))
+        Some(headers_object(self.source.headers()))
     }
 
     fn select_by_key(&self, key: &str) -> Option<Value> {
@@ -483,6 +889,28 @@ impl<C: OpsContext> ValueHandler for HeadersHandler<C> {
     }
 }
 
+/// The cookies of the request, parsed from its `Cookie` header when they are selected.
+struct CookiesHandler<C> {
+    source: C,
+}
+
+impl<C: OpsContext> ValueHandler for CookiesHandler<C> {
+    fn detach(&self) -> Option<Value> {
+        let header = self.source.header(COOKIE_HEADER);
+        Some(Value::object(extract_cookies(header.as_deref())))
+    }
+
+    fn select_by_key(&self, key: &str) -> Option<Value> {
+        let value = self
+            .source
+            .header(COOKIE_HEADER)
+            .and_then(|header| cookie(&header, key).map(str::to_string))
+            .map(Value::string)
+            .unwrap_or_else(Value::null);
+        Some(value)
+    }
+}
+
 struct QueryParamsHandler<S> {
     source: S,
 }
@@ -491,15 +919,58 @@ impl<C: OpsContext> ValueHandler for QueryParamsHandler<C> {
     fn detach(&self) -> Option<Value> {
         self.source
             .header(PATH_HEADER)
-            .and_then(|path| extract_query_params(&path))
+            .and_then(|path| extract_query_params(&path, duplicate_query_params()))
             .map(Value::object)
     }
 
     fn select_by_key(&self, key: &str) -> Option<Value> {
         self.source
             .header(PATH_HEADER)
-            .and_then(|path| extract_query_param(&path, key))
+            .and_then(|path| extract_query_param(&path, key, duplicate_query_params()))
+            .map(Value::string)
+    }
+}
+
+struct QueryParamsMultiHandler<S> {
+    source: S,
+}
+
+impl<C: OpsContext> ValueHandler for QueryParamsMultiHandler<C> {
+    fn detach(&self) -> Option<Value> {
+        self.source
+            .header(PATH_HEADER)
+            .and_then(|path| extract_query_params_multi(&path))
+            .map(Value::object)
+    }
+
+    fn select_by_key(&self, key: &str) -> Option<Value> {
+        self.source
+            .header(PATH_HEADER)
+            .and_then(|path| extract_query_params_multi(&path))
+            .and_then(|mut params| params.remove(key))
+    }
+}
+
+struct FilterStateHandler<C> {
+    source: C,
+}
+
+impl<C: OpsContext> ValueHandler for FilterStateHandler<C> {
+    fn detach(&self) -> Option<Value> {
+        // Filter state entries can not be listed, only selected by key.
+        None
+    }
+
+    fn select_by_key(&self, key: &str) -> Option<Value> {
+        let value = self
+            .source
+            .connection_properties()
+            .filter_state()
+            .string(key)
+            .ok()?
             .map(Value::string)
+            .unwrap_or_else(Value::null);
+        Some(value)
     }
 }
 
@@ -538,7 +1009,10 @@ impl<C: OpsContext> Context for RequestOpsContextWrapper<'_, C> {
         match symbol.as_str() {
             ATTRIBUTES => Binding::Available(Value::reference(ATTRIBUTES_REFERENCE)),
             AUTHENTICATION => Binding::Available(Value::reference(AUTHENTICATION_REFERENCE)),
-            PAYLOAD => self.evaluation_mode.resolve_pending(),
+            PAYLOAD => match &self.payload {
+                Some(_) => Binding::Available(Value::reference(PAYLOAD_REFERENCE)),
+                None => self.evaluation_mode.resolve_pending(),
+            },
             VARS => Binding::Available(Value::reference(VARS_REFERENCE)),
             _ => Binding::Unknown,
         }
@@ -549,8 +1023,15 @@ impl<C: OpsContext> Context for RequestOpsContextWrapper<'_, C> {
             ATTRIBUTES_REFERENCE => Some(&self.attributes),
             AUTHENTICATION_REFERENCE => Some(&self.authentication),
             HEADERS_REFERENCE => Some(&self.attributes.headers),
+            COOKIES_REFERENCE => Some(&self.attributes.cookies),
             QUERY_PARAMS_REFERENCE => Some(&self.attributes.query_params),
+            QUERY_PARAMS_MULTI_REFERENCE => Some(&self.attributes.query_params_multi),
             VARS_REFERENCE => Some(&self.vars),
+            FILTER_STATE_REFERENCE => Some(&self.attributes.filter_state),
+            PAYLOAD_REFERENCE => self
+                .payload
+                .as_ref()
+                .map(|payload| payload as &dyn ValueHandler),
             _ => None,
         }
     }
@@ -561,7 +1042,10 @@ impl<'a, C: OpsContext> Context for ResponseOpsContextWrapper<'a, C> {
         match symbol.as_str() {
             ATTRIBUTES => Binding::Available(Value::reference(ATTRIBUTES_REFERENCE)),
             AUTHENTICATION => Binding::Available(Value::reference(AUTHENTICATION_REFERENCE)),
-            PAYLOAD => self.evaluation_mode.resolve_pending(),
+            PAYLOAD => match &self.payload {
+                Some(_) => Binding::Available(Value::reference(PAYLOAD_REFERENCE)),
+                None => self.evaluation_mode.resolve_pending(),
+            },
             VARS => Binding::Available(Value::reference(VARS_REFERENCE)),
             _ => Binding::Unknown,
         }
@@ -573,6 +1057,11 @@ impl<'a, C: OpsContext> Context for ResponseOpsContextWrapper<'a, C> {
             AUTHENTICATION_REFERENCE => Some(&self.authentication),
             HEADERS_REFERENCE => Some(&self.attributes.headers),
             VARS_REFERENCE => Some(&self.vars),
+            FILTER_STATE_REFERENCE => Some(&self.attributes.filter_state),
+            PAYLOAD_REFERENCE => self
+                .payload
+                .as_ref()
+                .map(|payload| payload as &dyn ValueHandler),
             _ => None,
         }
     }
@@ -583,13 +1072,11 @@ fn request_headers_context<'a>(
     accessor: &'a dyn HeadersAccessor,
     evaluation_mode: EvaluationMode,
     vars: Vars<'a>,
+    route: Option<&'a UrlTemplate>,
 ) -> impl Context + 'a {
     RequestOpsContextWrapper::new(
         evaluation_mode,
-        HeadersOpsContext {
-            policy_context,
-            accessor,
-        },
+        HeadersOpsContext::new(policy_context, accessor).with_route(route),
         vars,
     )
 }
@@ -602,14 +1089,42 @@ fn response_headers_context<'a>(
 ) -> impl Context + 'a {
     ResponseOpsContextWrapper::new(
         evaluation_mode,
-        HeadersOpsContext {
-            policy_context,
-            accessor,
-        },
+        HeadersOpsContext::new(policy_context, accessor),
         vars,
     )
 }
 
+fn request_body_context<'a>(
+    policy_context: &'a dyn PolicyContext,
+    accessor: &'a dyn HeadersAccessor,
+    body: &'a dyn BodyAccessor,
+    evaluation_mode: EvaluationMode,
+    vars: Vars<'a>,
+    route: Option<&'a UrlTemplate>,
+) -> impl Context + 'a {
+    RequestOpsContextWrapper::new(
+        evaluation_mode,
+        HeadersOpsContext::new(policy_context, accessor).with_route(route),
+        vars,
+    )
+    .with_payload(PayloadHandler::body(body, accessor))
+}
+
+fn response_body_context<'a>(
+    policy_context: &'a dyn PolicyContext,
+    accessor: &'a dyn HeadersAccessor,
+    body: &'a dyn BodyAccessor,
+    evaluation_mode: EvaluationMode,
+    vars: Vars<'a>,
+) -> impl Context + 'a {
+    ResponseOpsContextWrapper::new(
+        evaluation_mode,
+        HeadersOpsContext::new(policy_context, accessor),
+        vars,
+    )
+    .with_payload(PayloadHandler::body(body, accessor))
+}
+
 #[cfg(test)]
 pub(crate) mod tests {
     use crate::convert::IntoValue;
@@ -644,6 +1159,11 @@ pub(crate) mod tests {
                 ["request", "scheme"] => Some("http".as_bytes().to_vec()),
                 ["request", "protocol"] => Some("HTTP/1.1".as_bytes().to_vec()),
                 ["source", "address"] => Some("172.18.0.1:60686".as_bytes().to_vec()),
+                ["connection", "tls_version"] => Some("TLSv1.3".as_bytes().to_vec()),
+                ["connection", "requested_server_name"] => {
+                    Some("api.example.com".as_bytes().to_vec())
+                }
+                ["rate_limit_descriptor"] => Some("gold".as_bytes().to_vec()),
                 _ => None,
             }
         }
@@ -706,6 +1226,8 @@ pub(crate) mod tests {
     fn value_to_json(value: &Value) -> serde_json::Value {
         if let Some(b) = value.as_bool() {
             b.into()
+        } else if let Some(n) = value.as_i64() {
+            n.into()
         } else if let Some(n) = value.as_f64() {
             n.into()
         } else if let Some(s) = value.as_str() {
@@ -812,6 +1334,7 @@ pub(crate) mod tests {
             &ops.request,
             EvaluationMode::Complete,
             &HashMap::default(),
+            None,
         ));
     }
 
@@ -838,6 +1361,7 @@ pub(crate) mod tests {
             &ops.request,
             EvaluationMode::Complete,
             vars,
+            None,
         ));
         test(&response_headers_context(
             &MockPolicyContext,
@@ -995,7 +1519,7 @@ pub(crate) mod tests {
         let expression = parser.parse_str(pel).unwrap();
 
         let expected = serde_json::json!({
-            "foo": 100.0
+            "foo": 100
         });
 
         foreach_context(&lazy_mock_ops(), |context| {
@@ -1026,7 +1550,7 @@ pub(crate) mod tests {
             "clientName": "CLIENT_NAME",
             "principal": "PRINCIPAL",
             "properties": {
-                "foo": 100.0
+                "foo": 100
             }
         });
 
@@ -1184,6 +1708,34 @@ pub(crate) mod tests {
         });
     }
 
+    #[test]
+    fn attributes_query_params_multi() {
+        let parser = Parser::new();
+        let runtime = Runtime::new();
+
+        foreach_request_context(&lazy_mock_ops(), |context| {
+            // DW: attributes.queryParamsMulti["foo"]
+            let pel = r#"
+                [".", "0-33",
+                    [".", "0-27",
+                        [":ref", "0-10", "attributes"],
+                        [":str", "11-27", "queryParamsMulti"]
+                    ],
+                    [":str", "28-33", "foo"]
+                ]
+            "#;
+
+            let expression = parser.parse_str(pel).unwrap();
+            let query_param = runtime
+                .eval_with_context(&expression, context)
+                .unwrap()
+                .complete()
+                .unwrap();
+
+            assert_eq!(value_to_json(&query_param), serde_json::json!(["bar"]));
+        });
+    }
+
     #[test]
     fn attributes_query_param_inexistent() {
         let parser = Parser::new();
@@ -1239,6 +1791,207 @@ pub(crate) mod tests {
         });
     }
 
+    #[test]
+    fn attributes_raw_query_string() {
+        let parser = Parser::new();
+        let runtime = Runtime::new();
+
+        foreach_request_context(&lazy_mock_ops(), |context| {
+            // DW: attributes.rawQueryString
+            let pel = r#"
+                [".", "0-25",
+                    [":ref", "0-10", "attributes"],
+                    [":str", "12-25", "rawQueryString"]
+                ]
+            "#;
+
+            let expression = parser.parse_str(pel).unwrap();
+            let query_string = runtime
+                .eval_with_context(&expression, context)
+                .unwrap()
+                .complete()
+                .unwrap();
+
+            let query_string = query_string.as_str().unwrap();
+
+            assert_eq!(query_string, "baz=bal&foo=bar");
+        });
+    }
+
+    #[test]
+    fn duplicated_query_params() {
+        let uri = "/sign?k=1&other=x&k=2";
+        let first = DuplicateParams::FirstWins;
+        let last = DuplicateParams::LastWins;
+
+        assert_eq!(extract_query_param(uri, "k", first).as_deref(), Some("1"));
+        assert_eq!(extract_query_param(uri, "k", last).as_deref(), Some("2"));
+        assert_eq!(
+            extract_query_params(uri, first).unwrap()["k"].as_str(),
+            Some("1")
+        );
+        assert_eq!(
+            extract_query_params(uri, last).unwrap()["k"].as_str(),
+            Some("2")
+        );
+
+        let multi = Value::object(extract_query_params_multi(uri).unwrap());
+        assert_eq!(
+            value_to_json(&multi),
+            serde_json::json!({"k": ["1", "2"], "other": ["x"]})
+        );
+    }
+
+    #[test]
+    fn query_params_decoding() {
+        let uri = "/sign?token=ab+c%2Bd==&bad=%FF%FE";
+        let token = || extract_query_param(uri, "token", DuplicateParams::LastWins);
+
+        assert_eq!(token().as_deref(), Some("ab c+d=="));
+        assert_eq!(
+            extract_query_param(uri, "bad", DuplicateParams::LastWins).as_deref(),
+            Some("%FF%FE")
+        );
+
+        let _strict = Decoding::set(query::QueryDecoding::Strict);
+        assert_eq!(token().as_deref(), Some("ab+c+d=="));
+    }
+
+    /// Sets the decoding of the query while alive, the default one again once dropped, even
+    /// when an assertion fails.
+    struct Decoding;
+
+    impl Decoding {
+        fn set(decoding: query::QueryDecoding) -> Self {
+            set_query_decoding(decoding);
+            Self
+        }
+    }
+
+    impl Drop for Decoding {
+        fn drop(&mut self) {
+            set_query_decoding(query::QueryDecoding::default());
+        }
+    }
+
+    #[test]
+    fn query_params_form_decoding() {
+        let uri = "/sign?a=b+c%20d&e=%2B&flag&&g=x+%FF&h=%zz#i=j";
+        let params = extract_query_params(uri, DuplicateParams::FirstWins).unwrap();
+
+        // Values not decodable are kept as sent, only their `+` standing for spaces, where
+        // the form decoding used before replaced the octets with U+FFFD.
+        assert_eq!(
+            value_to_json(&Value::object(params)),
+            serde_json::json!({
+                "a": "b c d",
+                "e": "+",
+                "flag": "",
+                "g": "x %FF",
+                "h": "%zz",
+            })
+        );
+    }
+
+    #[test]
+    fn attributes_path_params() {
+        let parser = Parser::new();
+        let runtime = Runtime::new();
+        let ops = lazy_mock_ops();
+        let route = UrlTemplate::parse("/{resource}").unwrap();
+        let vars = HashMap::default();
+        let context = request_headers_context(
+            &MockPolicyContext,
+            &ops.request,
+            EvaluationMode::Complete,
+            &vars,
+            Some(&route),
+        );
+
+        // DW: attributes.pathParams
+        let pel = r#"
+            [".", "0-21",
+                [":ref", "0-10", "attributes"],
+                [":str", "11-21", "pathParams"]
+            ]
+        "#;
+
+        let expression = parser.parse_str(pel).unwrap();
+        let path_params = runtime
+            .eval_with_context(&expression, &context)
+            .unwrap()
+            .complete()
+            .unwrap();
+
+        assert_eq!(
+            value_to_json(&path_params),
+            serde_json::json!({"resource": "something"})
+        );
+    }
+
+    #[test]
+    fn path_and_matrix_params() {
+        let uri = "/cars;color=red/12;year=2012;color=blue?sort=asc";
+        let route = UrlTemplate::parse("/cars/{id:int}").unwrap();
+
+        assert_eq!(
+            value_to_json(&extract_path_params(uri, &route).unwrap()),
+            serde_json::json!({"id": 12})
+        );
+        assert_eq!(
+            value_to_json(&Value::object(extract_matrix_params(uri))),
+            serde_json::json!({"color": "blue", "year": "2012"})
+        );
+        assert!(extract_path_params("/boats/12", &route).is_none());
+    }
+
+    #[test]
+    fn attributes_baggage() {
+        let parser = Parser::new();
+        let runtime = Runtime::new();
+        let mut ops = lazy_mock_ops();
+        mock_request_header(
+            &mut ops,
+            "baggage",
+            Some("tenant=acme,bad key=x,city=S%C3%A3o%20Paulo;ttl=60,tenant=other"),
+        );
+
+        foreach_request_context(&ops, |context| {
+            // DW: attributes.baggage
+            let pel = r#"
+                [".", "0-18",
+                    [":ref", "0-10", "attributes"],
+                    [":str", "11-18", "baggage"]
+                ]
+            "#;
+
+            let expression = parser.parse_str(pel).unwrap();
+            let baggage = runtime
+                .eval_with_context(&expression, context)
+                .unwrap()
+                .complete()
+                .unwrap();
+
+            let expected = serde_json::json!({"tenant": "acme", "city": "São Paulo"});
+            assert_eq!(value_to_json(&baggage), expected);
+        });
+    }
+
+    #[test]
+    fn raw_query_string_is_not_normalized() {
+        let uri = "/sign?b=a b&a=%7e;x#fragment";
+
+        assert_eq!(
+            super::extract_raw_query_string(uri).as_deref(),
+            Some("b=a b&a=%7e;x")
+        );
+        assert_eq!(
+            super::extract_query_string(uri).as_deref(),
+            Some("b=a%20b&a=%7e;x")
+        );
+        assert_eq!(super::extract_raw_query_string("/sign"), None);
+    }
+
     #[test]
     fn attributes_request_path() {
         let parser = Parser::new();
@@ -1449,9 +2202,7 @@ pub(crate) mod tests {
                 .complete()
                 .unwrap();
 
-            let request_uri = status_code.as_f64().unwrap();
-
-            assert_eq!(request_uri, 207_f64);
+            assert_eq!(status_code.as_i64(), Some(207));
         });
     }
 
@@ -1652,6 +2403,8 @@ pub(crate) mod tests {
             let actual = value_to_json(&attributes);
 
             let expected = serde_json::json!({
+                "baggage": {},
+                "cookies": {},
                 "headers": {
                     "Content-Length": "1024",
                     "Content-Type": "text/html",
@@ -1660,16 +2413,30 @@ pub(crate) mod tests {
                     ":status": "207"
                 },
                 "localAddress": "172.25.0.7:7890",
+                "matrixParams": {},
                 "method": "GET",
+                "pathParams": null,
                 "queryParams": {
                     "baz": "bal",
                     "foo": "bar"
                 },
+                "queryParamsMulti": {
+                    "baz": ["bal"],
+                    "foo": ["bar"]
+                },
                 "queryString": "baz=bal&foo=bar",
+                "rawQueryString": "baz=bal&foo=bar",
                 "remoteAddress": "172.18.0.1:60686",
                 "requestPath": "/something",
                 "requestUri": "/something?baz=bal&foo=bar",
                 "scheme": "http",
+                "tls": {
+                    "alpn": "http/1.1",
+                    "fingerprint": "t13dh1",
+                    "mtls": false,
+                    "serverName": "api.example.com",
+                    "version": "TLSv1.3"
+                },
                 "version": "HTTP/1.1",
             });
 
@@ -1677,6 +2444,36 @@ pub(crate) mod tests {
         });
     }
 
+    #[test]
+    fn request_attributes_detach_reads_the_headers_once() {
+        let parser = Parser::new();
+        let runtime = Runtime::new();
+
+        // Reading a single header panics, as it is not expected.
+        let mut ops = Ops::new();
+        ops.request.expect_headers().times(1).returning(header_map);
+
+        foreach_request_context(&ops, |context| {
+            // DW: attributes
+            let pel = r#"
+                [":ref", "0-10", "attributes"]
+            "#;
+
+            let expression = parser.parse_str(pel).unwrap();
+            let attributes = runtime
+                .eval_with_context(&expression, context)
+                .unwrap()
+                .complete()
+                .unwrap();
+
+            let actual = value_to_json(&attributes);
+
+            assert_eq!(actual["method"], "GET");
+            assert_eq!(actual["headers"][":method"], "GET");
+            assert_eq!(actual["requestPath"], "/something");
+        });
+    }
+
     #[test]
     fn response_attributes_detach() {
         let parser = Parser::new();
@@ -1705,14 +2502,95 @@ pub(crate) mod tests {
                     ":method": "GET",
                     ":status": "207"
                 },
-                // TODO: AGW-5356 - Improve number coercion
-                "statusCode": 207.0
+                "statusCode": 207
             });
 
             assert_eq!(actual, expected);
         });
     }
 
+    #[test]
+    fn attributes_filter_state() {
+        let parser = Parser::new();
+        let runtime = Runtime::new();
+
+        foreach_context(&lazy_mock_ops(), |context| {
+            // DW: attributes.filterState.rate_limit_descriptor
+            let pel = r#"
+                [".", "0-46",
+                    [".", "0-22",
+                        [":ref", "0-10", "attributes"],
+                        [":str", "11-22", "filterState"]
+                    ],
+                    [":str", "23-46", "rate_limit_descriptor"]
+                ]
+            "#;
+
+            let expression = parser.parse_str(pel).unwrap();
+            let result = runtime
+                .eval_with_context(&expression, context)
+                .unwrap()
+                .complete()
+                .unwrap();
+
+            assert_eq!(result.as_str().unwrap(), "gold");
+        });
+    }
+
+    #[cfg(feature = "prelude-hash")]
+    #[test]
+    fn hash_bucket_matches_pdk_core() {
+        let parser = Parser::new();
+        let runtime = Runtime::new();
+
+        for key in ["client-1", "client-2", "", "ünïcödé"] {
+            // DW: hashBucket(key, 16)
+            let pel = format!(
+                r#"
+                [":apply", "0-10",
+                    [":ref", "0-10", "hashBucket"],
+                    [":str", "11-20", "{key}"],
+                    [":nbr", "21-23", "16"]
+                ]
+            "#
+            );
+
+            let expression = parser.parse_str(&pel).unwrap();
+            let result = runtime.eval(&expression).unwrap().complete().unwrap();
+
+            let expected = pdk_core::hash::bucket(key, 16, 0) as f64;
+            assert_eq!(result.as_f64().unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn attributes_filter_state_inexistent() {
+        let parser = Parser::new();
+        let runtime = Runtime::new();
+
+        foreach_context(&lazy_mock_ops(), |context| {
+            // DW: attributes.filterState.inexistent
+            let pel = r#"
+                [".", "0-33",
+                    [".", "0-22",
+                        [":ref", "0-10", "attributes"],
+                        [":str", "11-22", "filterState"]
+                    ],
+                    [":str", "23-33", "inexistent"]
+                ]
+            "#;
+
+            let expression = parser.parse_str(pel).unwrap();
+            let result = runtime
+                .eval_with_context(&expression, context)
+                .unwrap()
+                .complete()
+                .unwrap();
+
+            assert!(result.is_null());
+        });
+    }
+
     #[test]
     fn vars_detach() {
         let parser = Parser::new();
@@ -1,5 +1,6 @@
 // Copyright 2023 Salesforce, Inc. All rights reserved.
 use std::{
+    cell::{Cell, RefCell},
     collections::HashMap,
     fmt::{Debug, Formatter},
 };
@@ -9,24 +10,136 @@ use serde::{
     {Deserialize, Deserializer},
 };
 
-use classy::event::{EventData, RequestHeaders, ResponseHeaders};
-use pdk_core::log::debug;
+use classy::event::{
+    BodyAccessor, EventData, RequestBody, RequestHeaders, ResponseBody, ResponseHeaders,
+};
+use pdk_core::http::query::{DuplicateParams, QueryDecoding};
+use pdk_core::http::request_class::RequestClass;
+use pdk_core::http::template::UrlTemplate;
+use pdk_core::log::{capture, debug};
+use pdk_core::policy_context::flags::FeatureFlags;
+use pdk_core::policy_context::metadata::PolicyMetadata;
 use pdk_core::policy_context::PolicyContext;
 
 use pel::{
     expression::Expression as InnerExpression,
     parser::{Parser, ParsingUnitError},
-    runtime::{value::Value, Context, Evaluation, Runtime},
+    runtime::{
+        value::Value, Context, Evaluation, FunctionRegistry, RegistryError, Runtime, RuntimeError,
+    },
+    Location,
 };
 
 use crate::{
-    convert::IntoValue, request_headers_context, response_headers_context, EvaluationMode,
-    HeadersAccessor, OnPayloadContext, ExpressionError,
+    convert::IntoValue, request_body_context, request_headers_context, response_body_context,
+    response_headers_context, EvaluationMode, ExpressionError, HeadersAccessor, OnPayloadContext,
+    ATTRIBUTES, AUTHENTICATION, PAYLOAD, VARS,
 };
 
+/// The var holding the feature flags given to [`CompleteResolver::with_flags`].
+pub const FLAGS_VAR: &str = "flags";
+
+/// The var holding the request class given to [`CompleteResolver::with_request_class`].
+pub const REQUEST_CLASS_VAR: &str = "requestClass";
+
+/// The var holding the claims of a token given to [`CompleteResolver::with_claim_set`].
+pub const CLAIM_SET_VAR: &str = "claimSet";
+
 thread_local! {
     static PARSER: Parser = Parser::new();
-    static RUNTIME: Runtime = Default::default();
+    static RUNTIME: RefCell<Runtime<FunctionRegistry>> = RefCell::new(Runtime::with_prelude(
+        FunctionRegistry::new().with_reserved(&[ATTRIBUTES, AUTHENTICATION, PAYLOAD, VARS]),
+    ));
+    static TRACING: Cell<bool> = Cell::new(false);
+    static DUPLICATE_QUERY_PARAMS: Cell<DuplicateParams> = Cell::new(DuplicateParams::default());
+    static QUERY_DECODING: Cell<QueryDecoding> = Cell::new(QueryDecoding::default());
+}
+
+/// Makes a function available to every expression resolved afterwards. Meant to be called
+/// at configure time, before any expression gets resolved.
+///
+/// Fails when the name is already taken by a prelude function, a previously registered
+/// function or a variable of the expressions context.
+pub fn register_function<F>(
+    name: &'static str,
+    arity: usize,
+    function: F,
+) -> Result<(), RegistryError>
+where
+    F: 'static + Fn(Location, &dyn Context, &[Value]) -> Result<Value, RuntimeError>,
+{
+    RUNTIME.with(|runtime| {
+        runtime
+            .borrow_mut()
+            .prelude_mut()
+            .register(name, arity, function)
+    })
+}
+
+/// Enables logging, at debug level, what every part of the resolved expressions evaluated
+/// to. Meant for finding out why an expression does not yield the expected value.
+pub fn set_tracing(enabled: bool) {
+    TRACING.with(|tracing| tracing.set(enabled));
+}
+
+/// Tells which value of a repeated query parameter `attributes.queryParams` keeps, the first
+/// one by default. Every value stays available in `attributes.queryParamsMulti`.
+pub fn set_duplicate_query_params(duplicates: DuplicateParams) {
+    DUPLICATE_QUERY_PARAMS.with(|cell| cell.set(duplicates));
+}
+
+pub(crate) fn duplicate_query_params() -> DuplicateParams {
+    DUPLICATE_QUERY_PARAMS.with(Cell::get)
+}
+
+/// Tells how `attributes.queryParams` and `attributes.queryParamsMulti` decode the query, as
+/// forms by default. The strict mode keeps the `+` of base64 tokens.
+pub fn set_query_decoding(decoding: QueryDecoding) {
+    QUERY_DECODING.with(|cell| cell.set(decoding));
+}
+
+pub(crate) fn query_decoding() -> QueryDecoding {
+    QUERY_DECODING.with(Cell::get)
+}
+
+/// Applies the `expressions` of the policy configuration, resetting what it leaves out to the
+/// defaults. Called by the pdk before the entrypoint of the policy, which can still override
+/// them.
+pub fn configure(metadata: &PolicyMetadata) {
+    let expressions = metadata
+        .policy_config()
+        .and_then(|config| config.expressions());
+    set_duplicate_query_params(
+        expressions
+            .and_then(|expressions| expressions.duplicate_query_params())
+            .unwrap_or_default(),
+    );
+    set_query_decoding(
+        expressions
+            .and_then(|expressions| expressions.query_decoding())
+            .unwrap_or_default(),
+    );
+}
+
+fn eval(
+    expression: &InnerExpression,
+    source: Option<&str>,
+    context: &dyn Context,
+) -> Result<Evaluation, RuntimeError> {
+    RUNTIME.with(|runtime| {
+        let runtime = runtime.borrow();
+        // The expressions of the captured requests are traced too, see `pdk_core::log::capture`.
+        if !TRACING.with(Cell::get) && !capture::is_captured() {
+            return runtime.eval_with_context(expression, context);
+        }
+
+        let (result, trace) = runtime.eval_with_trace(expression, context);
+        debug!(
+            "Trace of expression {}:\n{trace}",
+            source.unwrap_or("without source")
+        );
+        result
+    })
 }
 
 //TODO: [AGW-5617] - Improve Expression display in log messages
@@ -100,6 +213,50 @@ impl Expression {
             .__resolve_on_response_headers(policy_context, accessor)
     }
 
+    /// Resolves with the buffered body of the request as `payload`, read only when the
+    /// expression uses it.
+    pub fn resolve_on_request_body(
+        &self,
+        event_data: &EventData<RequestBody>,
+    ) -> Result<Value, ExpressionError> {
+        self.__resolve_on_request_body(<dyn PolicyContext>::default(), event_data, event_data)
+    }
+
+    pub(crate) fn __resolve_on_request_body(
+        &self,
+        policy_context: &dyn PolicyContext,
+        accessor: &dyn HeadersAccessor,
+        body: &dyn BodyAccessor,
+    ) -> Result<Value, ExpressionError> {
+        CompleteResolver::from_expression(self).__resolve_on_request_body(
+            policy_context,
+            accessor,
+            body,
+        )
+    }
+
+    /// Resolves with the buffered body of the response as `payload`, read only when the
+    /// expression uses it.
+    pub fn resolve_on_response_body(
+        &self,
+        event_data: &EventData<ResponseBody>,
+    ) -> Result<Value, ExpressionError> {
+        self.__resolve_on_response_body(<dyn PolicyContext>::default(), event_data, event_data)
+    }
+
+    pub(crate) fn __resolve_on_response_body(
+        &self,
+        policy_context: &dyn PolicyContext,
+        accessor: &dyn HeadersAccessor,
+        body: &dyn BodyAccessor,
+    ) -> Result<Value, ExpressionError> {
+        CompleteResolver::from_expression(self).__resolve_on_response_body(
+            policy_context,
+            accessor,
+            body,
+        )
+    }
+
     #[allow(dead_code)]
     pub(crate) fn resolve_on_payload(&self, payload: String) -> Result<Value, ExpressionError> {
         CompleteResolver::from_expression(self).resolve_on_payload(payload)
@@ -116,12 +273,34 @@ impl Expression {
     {
         CompleteResolver::from_expression(self).with_vars(vars)
     }
+
+    /// Makes the `flags` available as `vars.flags`.
+    pub fn with_flags<'a>(&'a self, flags: &FeatureFlags) -> CompleteResolver<'a> {
+        CompleteResolver::from_expression(self).with_flags(flags)
+    }
+
+    /// Makes the name of the `class` available as `vars.requestClass`.
+    pub fn with_request_class<'a>(&'a self, class: RequestClass) -> CompleteResolver<'a> {
+        CompleteResolver::from_expression(self).with_request_class(class)
+    }
+
+    /// Makes the `claims` of a validated token available as `vars.claimSet`.
+    pub fn with_claim_set<'a>(&'a self, claims: &serde_json::Value) -> CompleteResolver<'a> {
+        CompleteResolver::from_expression(self).with_claim_set(claims)
+    }
+
+    /// Makes the captures of `route` in the request path available as
+    /// `attributes.pathParams`.
+    pub fn with_route<'a>(&'a self, route: &'a UrlTemplate) -> CompleteResolver<'a> {
+        CompleteResolver::from_expression(self).with_route(route)
+    }
 }
 
 pub struct CompleteResolver<'a> {
     expression: &'a InnerExpression,
     source: Option<&'a str>,
     vars: HashMap<&'a str, Value>,
+    route: Option<&'a UrlTemplate>,
 }
 
 impl<'a> CompleteResolver<'a> {
@@ -130,6 +309,7 @@ impl<'a> CompleteResolver<'a> {
             expression: &expression.expression,
             source: expression.source.as_deref(),
             vars: HashMap::default(),
+            route: None,
         }
     }
 
@@ -148,6 +328,28 @@ impl<'a> CompleteResolver<'a> {
         self
     }
 
+    /// Makes the `flags` available as `vars.flags`.
+    pub fn with_flags(self, flags: &FeatureFlags) -> Self {
+        self.with_var(FLAGS_VAR, flags)
+    }
+
+    /// Makes the name of the `class` available as `vars.requestClass`.
+    pub fn with_request_class(self, class: RequestClass) -> Self {
+        self.with_var(REQUEST_CLASS_VAR, class)
+    }
+
+    /// Makes the `claims` of a validated token available as `vars.claimSet`.
+    pub fn with_claim_set(self, claims: &serde_json::Value) -> Self {
+        self.with_var(CLAIM_SET_VAR, claims)
+    }
+
+    /// Makes the captures of `route` in the request path available as
+    /// `attributes.pathParams`.
+    pub fn with_route(mut self, route: &'a UrlTemplate) -> Self {
+        self.route = Some(route);
+        self
+    }
+
     pub fn resolve_on_request_headers(
         &self,
         event_data: &EventData<RequestHeaders>,
@@ -165,6 +367,7 @@ impl<'a> CompleteResolver<'a> {
             accessor,
             EvaluationMode::Complete,
             &self.vars,
+            self.route,
         ))
     }
 
@@ -188,14 +391,58 @@ impl<'a> CompleteResolver<'a> {
         ))
     }
 
+    pub fn resolve_on_request_body(
+        &self,
+        event_data: &EventData<RequestBody>,
+    ) -> Result<Value, ExpressionError> {
+        self.__resolve_on_request_body(<dyn PolicyContext>::default(), event_data, event_data)
+    }
+
+    pub(crate) fn __resolve_on_request_body(
+        &self,
+        policy_context: &dyn PolicyContext,
+        accessor: &dyn HeadersAccessor,
+        body: &dyn BodyAccessor,
+    ) -> Result<Value, ExpressionError> {
+        self.resolve(&request_body_context(
+            policy_context,
+            accessor,
+            body,
+            EvaluationMode::Complete,
+            &self.vars,
+            self.route,
+        ))
+    }
+
+    pub fn resolve_on_response_body(
+        &self,
+        event_data: &EventData<ResponseBody>,
+    ) -> Result<Value, ExpressionError> {
+        self.__resolve_on_response_body(<dyn PolicyContext>::default(), event_data, event_data)
+    }
+
+    pub(crate) fn __resolve_on_response_body(
+        &self,
+        policy_context: &dyn PolicyContext,
+        accessor: &dyn HeadersAccessor,
+        body: &dyn BodyAccessor,
+    ) -> Result<Value, ExpressionError> {
+        self.resolve(&response_body_context(
+            policy_context,
+            accessor,
+            body,
+            EvaluationMode::Complete,
+            &self.vars,
+        ))
+    }
+
     #[allow(dead_code)]
     pub(crate) fn resolve_on_payload(&self, payload: String) -> Result<Value, ExpressionError> {
-        self.resolve(&OnPayloadContext { payload })
+        self.resolve(&OnPayloadContext::new(payload))
     }
 
     fn resolve(&self, context: &dyn Context) -> Result<Value, ExpressionError> {
-        let evaluation = RUNTIME
-            .with(|runtime| runtime.eval_with_context(self.expression, context))
+        let evaluation = eval(self.expression, self.source, context)
             .map_err(|cause| ExpressionError::with_optional_source(cause, self.source))?;
         match evaluation {
             Evaluation::Complete(_, value) => Ok(value),
@@ -230,6 +477,7 @@ impl PartialResolver {
             accessor,
             EvaluationMode::Partial,
             &HashMap::default(),
+            None,
         ))
     }
 
@@ -253,14 +501,58 @@ impl PartialResolver {
         ))
     }
 
+    pub fn resolve_on_request_body(
+        &mut self,
+        accessor: &EventData<RequestBody>,
+    ) -> Result<Option<Value>, ExpressionError> {
+        self.__resolve_on_request_body(<dyn PolicyContext>::default(), accessor, accessor)
+    }
+
+    pub(crate) fn __resolve_on_request_body(
+        &mut self,
+        policy_context: &dyn PolicyContext,
+        accessor: &dyn HeadersAccessor,
+        body: &dyn BodyAccessor,
+    ) -> Result<Option<Value>, ExpressionError> {
+        self.resolve(&request_body_context(
+            policy_context,
+            accessor,
+            body,
+            EvaluationMode::Partial,
+            &HashMap::default(),
+            None,
+        ))
+    }
+
+    pub fn resolve_on_response_body(
+        &mut self,
+        accessor: &EventData<ResponseBody>,
+    ) -> Result<Option<Value>, ExpressionError> {
+        self.__resolve_on_response_body(<dyn PolicyContext>::default(), accessor, accessor)
+    }
+
+    pub(crate) fn __resolve_on_response_body(
+        &mut self,
+        policy_context: &dyn PolicyContext,
+        accessor: &dyn HeadersAccessor,
+        body: &dyn BodyAccessor,
+    ) -> Result<Option<Value>, ExpressionError> {
+        self.resolve(&response_body_context(
+            policy_context,
+            accessor,
+            body,
+            EvaluationMode::Partial,
+            &HashMap::default(),
+        ))
+    }
+
     pub fn resolve_on_payload(&mut self, payload: String) -> Result<Option<Value>, ExpressionError> {
-        self.resolve(&OnPayloadContext { payload })
+        self.resolve(&OnPayloadContext::new(payload))
     }
 
     fn resolve(&mut self, context: &dyn Context) -> Result<Option<Value>, ExpressionError> {
         let expression = self.expression.as_ref().ok_or(ExpressionError::AlreadyResolved)?;
-        let evaluation = RUNTIME
-            .with(|runtime| runtime.eval_with_context(expression, context))
+        let evaluation = eval(expression, self.source.as_deref(), context)
             .map_err(|cause| ExpressionError::with_optional_source(cause, self.source.as_deref()))?;
         match evaluation {
             Evaluation::Complete(_, value) => {
@@ -318,14 +610,21 @@ impl<'de> Deserialize<'de> for Expression {
 mod tests {
 
     use crate::tests::{MockAccessor, MockPolicyContext};
+    use classy::event::BodyAccessor;
     use mockall::predicate::eq;
+    use pdk_core::http::template::UrlTemplate;
     use pel::runtime::value::Value;
     use serde::Deserialize;
+    use std::cell::Cell;
 
     use crate::resolver::PARSER;
     use pel::expression::Expression as InnerExpression;
 
-    use super::{PartialResolver, ExpressionError, Expression};
+    use super::{register_function, set_tracing, PartialResolver, ExpressionError, Expression};
+    use pel::{
+        runtime::{Context, RegistryError},
+        Location,
+    };
 
     #[derive(Deserialize)]
     struct TestStruct {
@@ -489,7 +788,7 @@ mod tests {
             .unwrap_err();
 
         let expected =
-            "Runtime error:\n\tType mismatch\nLocation:\n\tline: 1, column: 1\n1| null ++ ' bye'\n";
+            "Runtime error:\n\tType mismatch\nLocation:\n\tline: 1, column: 1\n1| null ++ ' bye'\n | ^^^^^^^^^^^^^^\n";
 
         assert_eq!(error.to_string(), expected);
     }
@@ -517,6 +816,208 @@ mod tests {
         assert_eq!(result.unwrap().as_str().unwrap(), "1024");
     }
 
+    struct BufferedBody {
+        body: &'static str,
+        reads: Cell<usize>,
+    }
+
+    impl BufferedBody {
+        fn new(body: &'static str) -> Self {
+            Self {
+                body,
+                reads: Cell::new(0),
+            }
+        }
+    }
+
+    impl BodyAccessor for BufferedBody {
+        fn body(&self) -> Vec<u8> {
+            self.reads.set(self.reads.get() + 1);
+            self.body.as_bytes().to_vec()
+        }
+
+        fn set_body(&self, _: &[u8]) {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn resolve_on_request_body() {
+        // DW: payload.user.id
+        let pel = r#"
+                [".", "0-15",
+                    [".", "0-12",
+                        [":ref", "0-7", "payload"],
+                        [":str", "8-12", "user"]
+                    ],
+                    [":str", "13-15", "id"]
+                ]
+            "#;
+        let resolver = Expression::new(parse(pel));
+        let mut ops = MockAccessor::new();
+        ops.expect_header()
+            .with(eq("content-type"))
+            .returning(|_| Some("application/json; charset=utf-8".to_string()));
+        let body = BufferedBody::new(r#"{"user": {"id": "alice"}}"#);
+
+        let result = resolver.__resolve_on_request_body(&MockPolicyContext, &ops, &body);
+
+        assert_eq!(result.unwrap().as_str(), Some("alice"));
+        assert_eq!(body.reads.get(), 1);
+    }
+
+    #[test]
+    fn resolve_on_response_body_as_string() {
+        // DW: payload
+        let pel = r#"[":ref", "0-7", "payload"]"#;
+        let resolver = Expression::new(parse(pel));
+        let mut ops = MockAccessor::new();
+        ops.expect_header()
+            .with(eq("content-type"))
+            .returning(|_| Some("text/plain".to_string()));
+        let body = BufferedBody::new(r#"{"user": {"id": "alice"}}"#);
+
+        let result = resolver.__resolve_on_response_body(&MockPolicyContext, &ops, &body);
+
+        assert_eq!(
+            result.unwrap().as_str(),
+            Some(r#"{"user": {"id": "alice"}}"#)
+        );
+    }
+
+    #[test]
+    fn select_into_payload() {
+        // DW: payload.items[0].sku
+        let pel = r#"
+                [".", "0-21",
+                    [".", "0-17",
+                        [".", "0-13",
+                            [":ref", "0-7", "payload"],
+                            [":str", "8-13", "items"]
+                        ],
+                        [":nbr", "14-15", "0"]
+                    ],
+                    [":str", "18-21", "sku"]
+                ]
+            "#;
+        let resolver = Expression::new(parse(pel));
+        let payload = r#"{"items": [{"sku": "A-1"}, {"sku": "B-2"}]}"#;
+
+        let result = resolver.resolve_on_payload(payload.to_string()).unwrap();
+        assert_eq!(result.as_str(), Some("A-1"));
+
+        // The payload given as a string has no content type, it is detached parsed too.
+        let pel = r#"[":ref", "0-7", "payload"]"#;
+        let resolver = Expression::new(parse(pel));
+        let result = resolver.resolve_on_payload(payload.to_string()).unwrap();
+        assert_eq!(result.as_object().map(|items| items.len()), Some(1));
+        let result = resolver.resolve_on_payload("not json".to_string()).unwrap();
+        assert_eq!(result.as_str(), Some("not json"));
+    }
+
+    #[test]
+    fn select_into_json_content_only() {
+        // DW: payload.user
+        let pel = r#"
+                [".", "0-12",
+                    [":ref", "0-7", "payload"],
+                    [":str", "8-12", "user"]
+                ]
+            "#;
+        // DW: sizeOf(payload)
+        let size = r#"
+                [":apply", "0-15",
+                    [":ref", "0-6", "sizeOf"],
+                    [":ref", "7-14", "payload"]
+                ]
+            "#;
+        let json = r#"{"user": "alice"}"#;
+        let ops = |content_type: &'static str| {
+            let mut ops = MockAccessor::new();
+            ops.expect_header()
+                .with(eq("content-type"))
+                .returning(move |_| Some(content_type.to_string()));
+            ops
+        };
+
+        let resolve = |pel: &str, content_type| {
+            let body = BufferedBody::new(json);
+            Expression::new(parse(pel))
+                .__resolve_on_request_body(&MockPolicyContext, &ops(content_type), &body)
+                .unwrap()
+        };
+
+        assert_eq!(resolve(pel, "application/json").as_str(), Some("alice"));
+        assert_eq!(resolve(size, "application/json").as_f64(), Some(1.0));
+
+        // A body that only looks like JSON is a string, neither selected into nor sized as one.
+        assert!(resolve(pel, "text/plain").is_null());
+        assert_eq!(
+            resolve(size, "text/plain").as_f64(),
+            Some(json.len() as f64)
+        );
+    }
+
+    #[test]
+    fn select_into_non_json_payload() {
+        // DW: payload.items
+        let pel = r#"
+                [".", "0-13",
+                    [":ref", "0-7", "payload"],
+                    [":str", "8-13", "items"]
+                ]
+            "#;
+        let resolver = Expression::new(parse(pel));
+
+        let result = resolver.resolve_on_payload("not json".to_string()).unwrap();
+
+        assert!(result.is_null());
+    }
+
+    #[test]
+    fn payload_size() {
+        // DW: sizeOf(payload.items)
+        let pel = r#"
+                [":apply", "0-21",
+                    [":ref", "0-6", "sizeOf"],
+                    [".", "7-20",
+                        [":ref", "7-14", "payload"],
+                        [":str", "15-20", "items"]
+                    ]
+                ]
+            "#;
+        let resolver = Expression::new(parse(pel));
+        let payload = r#"{"items": [1, 2, 3]}"#;
+
+        let result = resolver.resolve_on_payload(payload.to_string()).unwrap();
+
+        assert_eq!(result.as_f64(), Some(3.0));
+    }
+
+    #[test]
+    fn body_not_read_without_payload() {
+        let pel = r#"
+                [".", "0-24",
+                    [".", "0-22",
+                        [":ref", "0-10", "attributes"],
+                        [":str", "11-22", "headers"]
+                    ],
+                    [":str", "23-24", "Content-Length"]
+                ]
+            "#;
+        let resolver = Expression::new(parse(pel));
+        let mut ops = MockAccessor::new();
+        ops.expect_header()
+            .with(eq("Content-Length"))
+            .returning(|_| Some("1024".to_string()));
+        let body = BufferedBody::new("ignored");
+
+        let result = resolver.__resolve_on_request_body(&MockPolicyContext, &ops, &body);
+
+        assert_eq!(result.unwrap().as_str(), Some("1024"));
+        assert_eq!(body.reads.get(), 0);
+    }
+
     #[test]
     fn resolve_on_response_headers() {
         let pel = r#"
@@ -570,6 +1071,88 @@ mod tests {
         assert_eq!(Some("bar"), result.unwrap().as_str());
     }
 
+    #[test]
+    fn resolve_with_template_captures() {
+        // DW: vars.petId
+        let pel = r#"
+                [".", "0-10",
+                    [":ref", "0-4", "vars"],
+                    [":str", "5-10", "petId"]
+                ]
+            "#;
+        let template = UrlTemplate::parse("/pets/{petId:int}/toys/{toyId}").unwrap();
+        let captures = template.matches("/pets/12/toys/ball").unwrap();
+        let expression = Expression::new(parse(pel));
+        let ops = MockAccessor::new();
+        let result = expression
+            .with_vars(captures.iter())
+            .__resolve_on_request_headers(&MockPolicyContext, &ops);
+
+        assert_eq!(Some(12.0), result.unwrap().as_f64());
+    }
+
+    #[test]
+    fn resolve_with_tracing() {
+        // DW: vars.petId
+        let pel = r#"
+                [".", "0-10",
+                    [":ref", "0-4", "vars"],
+                    [":str", "5-10", "petId"]
+                ]
+            "#;
+        let expression = Expression::new(parse(pel));
+        let ops = MockAccessor::new();
+
+        set_tracing(true);
+        let result = expression
+            .with_var("petId", "12")
+            .__resolve_on_request_headers(&MockPolicyContext, &ops);
+        set_tracing(false);
+
+        assert_eq!(Some("12"), result.unwrap().as_str());
+    }
+
+    #[test]
+    fn resolve_with_registered_function() {
+        // DW: signContext(vars.petId)
+        let pel = r#"
+                [":apply", "0-24",
+                    [":ref", "0-11", "signContext"],
+                    [".", "12-23",
+                        [":ref", "12-16", "vars"],
+                        [":str", "17-23", "petId"]
+                    ]
+                ]
+            "#;
+        register_function("signContext", 1, |_, _, arguments| {
+            let context = arguments[0].as_str().unwrap_or_default();
+            Ok(Value::string(format!("signed:{context}")))
+        })
+        .unwrap();
+
+        let expression = Expression::new(parse(pel));
+        let ops = MockAccessor::new();
+        let result = expression
+            .with_var("petId", "12")
+            .__resolve_on_request_headers(&MockPolicyContext, &ops);
+
+        assert_eq!(Some("signed:12"), result.unwrap().as_str());
+    }
+
+    #[test]
+    fn register_function_collision() {
+        let function = |_: Location, _: &dyn Context, _: &[Value]| Ok(Value::null());
+
+        assert_eq!(
+            register_function("sizeOf", 1, function),
+            Err(RegistryError::Collision("sizeOf".to_string()))
+        );
+        assert_eq!(
+            register_function("vars", 0, function),
+            Err(RegistryError::Collision("vars".to_string()))
+        );
+    }
+
     fn resolve_partial<F>(resolve: F)
     where
         F: Fn(&mut PartialResolver) -> Result<Option<Value>, ExpressionError>,
@@ -0,0 +1,119 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Evaluates arbitrary well formed expressions, so the runtime is reached past the parser.
+//! Run with `cargo +nightly fuzz run eval` from `pel`.
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use pel::{parser::Parser, runtime::Runtime};
+use serde_json::{json, Value};
+
+const SYMBOLS: &[&str] = &[
+    "++",
+    "byteSize",
+    "byteSubstring",
+    "contains",
+    "hashBucket",
+    "isDecimal",
+    "lower",
+    "sizeOf",
+    "splitBy",
+    "substring",
+    "substringAfter",
+    "substringAfterLast",
+    "substringBefore",
+    "substringBeforeLast",
+    "toDecimal",
+    "trim",
+    "upper",
+    "uuid",
+    "unknown",
+];
+
+const OPERATORS: &[&str] = &[
+    "!", "==", "!=", "<", ">", "<=", ">=", "&&", "||", "+", "-", "*", "/", "%",
+];
+
+#[derive(Arbitrary, Debug)]
+enum Pattern {
+    Case(Node),
+    Regex(String),
+    Range(Node, Node),
+}
+
+#[derive(Arbitrary, Debug)]
+enum Node {
+    Null,
+    Bool(bool),
+    Number(f64),
+    Text(String),
+    Str(String),
+    Ref(u8),
+    Array(Vec<Node>),
+    Apply(Box<Node>, Vec<Node>),
+    Select(Box<Node>, Box<Node>),
+    If(Box<Node>, Box<Node>, Box<Node>),
+    Default(Box<Node>, Box<Node>),
+    Match(Box<Node>, Vec<(Pattern, Node)>, Option<Box<Node>>),
+    Operator(u8, Vec<Node>),
+}
+
+fn to_json(node: &Node) -> Value {
+    // The locations need not match any source.
+    let at = json!("0-8");
+    match node {
+        Node::Null => json!([":null", at]),
+        Node::Bool(b) => json!([":bool", at, b.to_string()]),
+        Node::Number(n) => json!([":nbr", at, n.to_string()]),
+        Node::Text(s) => json!([":nbr", at, s]),
+        Node::Str(s) => json!([":str", at, s]),
+        Node::Ref(i) => json!([":ref", at, SYMBOLS[*i as usize % SYMBOLS.len()]]),
+        Node::Array(items) => {
+            let mut array = vec![json!(":array"), at];
+            array.extend(items.iter().map(to_json));
+            Value::Array(array)
+        }
+        Node::Apply(function, arguments) => {
+            let mut apply = vec![json!(":apply"), at, to_json(function)];
+            apply.extend(arguments.iter().map(to_json));
+            Value::Array(apply)
+        }
+        Node::Select(target, selector) => json!([".", at, to_json(target), to_json(selector)]),
+        Node::If(condition, then, otherwise) => json!([
+            ":if",
+            at,
+            to_json(condition),
+            to_json(then),
+            to_json(otherwise)
+        ]),
+        Node::Default(value, default) => json!([":default", at, to_json(value), to_json(default)]),
+        Node::Match(target, cases, default) => {
+            let mut matching = vec![json!(":match"), at, to_json(target)];
+            matching.extend(cases.iter().map(|(pattern, result)| match pattern {
+                Pattern::Case(case) => json!([":case", to_json(case), to_json(result)]),
+                Pattern::Regex(regex) => json!([":regex", regex, to_json(result)]),
+                Pattern::Range(from, to) => {
+                    json!([":range", to_json(from), to_json(to), to_json(result)])
+                }
+            }));
+            if let Some(default) = default {
+                matching.push(json!([":else", to_json(default)]));
+            }
+            Value::Array(matching)
+        }
+        Node::Operator(i, operands) => {
+            let mut operation = vec![json!(OPERATORS[*i as usize % OPERATORS.len()]), at];
+            operation.extend(operands.iter().map(to_json));
+            Value::Array(operation)
+        }
+    }
+}
+
+fuzz_target!(|node: Node| {
+    let parser = Parser::new();
+    let runtime = Runtime::new();
+
+    if let Ok(expression) = parser.parse_str(&to_json(&node).to_string()) {
+        let _ = runtime.eval(&expression);
+    }
+});
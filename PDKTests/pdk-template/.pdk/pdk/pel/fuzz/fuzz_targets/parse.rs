@@ -0,0 +1,25 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Parses arbitrary inputs as expressions and units, evaluating those that parse.
+//! Run with `cargo +nightly fuzz run parse` from `pel`.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pel::{parser::Parser, runtime::Runtime};
+
+fuzz_target!(|data: &[u8]| {
+    let parser = Parser::new();
+    let runtime = Runtime::new();
+
+    if let Ok(expression) = parser.parse_slice(data) {
+        let _ = runtime.eval(&expression);
+    }
+
+    if let Ok(source) = std::str::from_utf8(data) {
+        if let Ok(expression) = parser.parse_str(source) {
+            let _ = runtime.eval(&expression);
+        }
+        if let Ok((expression, _)) = parser.parse_unit(source) {
+            let _ = runtime.eval(&expression);
+        }
+    }
+});
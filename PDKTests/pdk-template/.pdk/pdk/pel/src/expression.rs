@@ -31,6 +31,8 @@ pub enum Body {
     DefaultOperator(DefaultOperator),
     Selection(Selection),
     IfElse(IfElse),
+    Match(Match),
+    Do(Do),
     UnaryOperation(UnaryOperation),
     Operation(Operation),
     Value(Value),
@@ -42,6 +44,8 @@ impl_from! {
         Apply,
         Selection,
         IfElse,
+        Match,
+        Do,
         DefaultOperator,
         UnaryOperation,
         Operation,
@@ -102,6 +106,51 @@ pub struct IfElse {
     pub false_branch: Box<Expression>,
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub struct Match {
+    pub target: Box<Expression>,
+    pub cases: Vec<Case>,
+    pub default: Option<Box<Expression>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Case {
+    pub pattern: Pattern,
+    pub result: Expression,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Pattern {
+    /// Matches values equal to the evaluated expression.
+    Literal(Expression),
+    /// Matches strings, numbers and booleans whose text matches the regex.
+    Regex(Regex),
+    /// Matches values between both evaluated bounds, inclusive.
+    Range(Expression, Expression),
+}
+
+#[derive(Clone, Debug)]
+pub struct Regex(pub regex_lite::Regex);
+
+impl PartialEq for Regex {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+/// Evaluates the body with local variables, each one visible to the next ones and the body.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Do {
+    pub variables: Vec<Variable>,
+    pub body: Box<Expression>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Variable {
+    pub symbol: Symbol,
+    pub value: Expression,
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Operation {
     pub operator: Operator,
@@ -119,6 +168,11 @@ pub enum Operator {
     Get,
     And,
     Or,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -3,10 +3,10 @@ use std::{collections::HashMap, str::Split, vec::IntoIter};
 
 use crate::{
     expression::{
-        Apply, Body, DefaultOperator, Expression, IfElse, Operation, Operator, Ref, Selection,
-        Symbol, UnaryOperation, UnaryOperator,
+        Apply, Body, Case, DefaultOperator, Do, Expression, IfElse, Match, Operation, Operator,
+        Pattern, Ref, Regex, Selection, Symbol, UnaryOperation, UnaryOperator, Variable,
     },
-    runtime::value::Value,
+    runtime::{number, value::Value},
     Location,
 };
 
@@ -112,6 +112,24 @@ pub enum ParsingErrorKind {
 
     #[error("Missing right operand")]
     MissingRightOperand,
+
+    #[error("Missing match target")]
+    MissingMatchTarget,
+
+    #[error("Bad formed case")]
+    BadFormedCase,
+
+    #[error("Bad regex format")]
+    BadRegexFormat,
+
+    #[error("Misplaced default case")]
+    MisplacedDefaultCase,
+
+    #[error("Missing body")]
+    MissingBody,
+
+    #[error("Bad formed variable")]
+    BadFormedVariable,
 }
 
 fn position(s: &mut Split<char>) -> Result<usize, ParsingError> {
@@ -293,7 +311,7 @@ fn number(
         kind: ParsingErrorKind::MissingConstructorArgument,
     })?;
     if let serde_json::Value::String(nbr) = nbr {
-        let value: f64 = nbr.parse().map_err(|_| ParsingError {
+        let value = number::parse(&nbr).ok_or(ParsingError {
             kind: ParsingErrorKind::BadNumberFormat,
         })?;
         Ok(Expression {
@@ -381,6 +399,154 @@ fn if_else(
     })
 }
 
+fn case_argument(
+    case: &mut IntoIter<serde_json::Value>,
+) -> Result<serde_json::Value, ParsingError> {
+    case.next().ok_or(ParsingError {
+        kind: ParsingErrorKind::BadFormedCase,
+    })
+}
+
+fn regex(json_value: serde_json::Value) -> Result<Regex, ParsingError> {
+    if let serde_json::Value::String(regex) = json_value {
+        regex_lite::Regex::new(&regex)
+            .map(Regex)
+            .map_err(|_| ParsingError {
+                kind: ParsingErrorKind::BadRegexFormat,
+            })
+    } else {
+        Err(ParsingError {
+            kind: ParsingErrorKind::BadFormedCase,
+        })
+    }
+}
+
+/// Cases are arrays of one of the forms `[":case", literal, result]`,
+/// `[":regex", "regex", result]`, `[":range", from, until, result]` or `[":else", result]`,
+/// being `:else` the last one when present.
+fn match_expression(
+    parser: &Parser,
+    location: Location,
+    mut arguments: IntoIter<serde_json::Value>,
+) -> Result<Expression, ParsingError> {
+    let target = arguments
+        .next()
+        .ok_or(ParsingError {
+            kind: ParsingErrorKind::MissingMatchTarget,
+        })
+        .and_then(|value| parser.expression(value))?;
+
+    let mut cases = Vec::new();
+    let mut default = None;
+    for case in arguments {
+        if default.is_some() {
+            return Err(ParsingError {
+                kind: ParsingErrorKind::MisplacedDefaultCase,
+            });
+        }
+
+        let mut case = if let serde_json::Value::Array(case) = case {
+            case.into_iter()
+        } else {
+            return Err(ParsingError {
+                kind: ParsingErrorKind::BadFormedCase,
+            });
+        };
+
+        let kind = case_argument(&mut case)?;
+        let pattern = match kind.as_str() {
+            Some(":case") => Pattern::Literal(parser.expression(case_argument(&mut case)?)?),
+            Some(":regex") => Pattern::Regex(regex(case_argument(&mut case)?)?),
+            Some(":range") => Pattern::Range(
+                parser.expression(case_argument(&mut case)?)?,
+                parser.expression(case_argument(&mut case)?)?,
+            ),
+            Some(":else") => {
+                default = Some(Box::new(parser.expression(case_argument(&mut case)?)?));
+                continue;
+            }
+            _ => {
+                return Err(ParsingError {
+                    kind: ParsingErrorKind::BadFormedCase,
+                })
+            }
+        };
+        let result = parser.expression(case_argument(&mut case)?)?;
+
+        if case.next().is_some() {
+            return Err(ParsingError {
+                kind: ParsingErrorKind::BadFormedCase,
+            });
+        }
+        cases.push(Case { pattern, result });
+    }
+
+    Ok(Expression {
+        location,
+        body: Body::Match(Match {
+            target: Box::new(target),
+            cases,
+            default,
+        }),
+    })
+}
+
+fn variable(parser: &Parser, json_value: serde_json::Value) -> Result<Variable, ParsingError> {
+    let bad_formed = || ParsingError {
+        kind: ParsingErrorKind::BadFormedVariable,
+    };
+
+    let mut variable = if let serde_json::Value::Array(variable) = json_value {
+        variable.into_iter()
+    } else {
+        return Err(bad_formed());
+    };
+
+    if variable.next().as_ref().and_then(serde_json::Value::as_str) != Some(":var") {
+        return Err(bad_formed());
+    }
+    let symbol = match variable.next() {
+        Some(serde_json::Value::String(symbol)) if !symbol.is_empty() => Symbol::new(symbol),
+        _ => return Err(bad_formed()),
+    };
+    let value = variable
+        .next()
+        .ok_or_else(bad_formed)
+        .and_then(|value| parser.expression(value))?;
+
+    if variable.next().is_some() {
+        return Err(bad_formed());
+    }
+    Ok(Variable { symbol, value })
+}
+
+/// Variables are arrays of the form `[":var", "name", value]`, followed by the body.
+fn do_block(
+    parser: &Parser,
+    location: Location,
+    arguments: IntoIter<serde_json::Value>,
+) -> Result<Expression, ParsingError> {
+    let mut arguments: Vec<_> = arguments.collect();
+    let body = arguments
+        .pop()
+        .ok_or(ParsingError {
+            kind: ParsingErrorKind::MissingBody,
+        })
+        .and_then(|value| parser.expression(value))?;
+    let variables = arguments
+        .into_iter()
+        .map(|value| variable(parser, value))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Expression {
+        location,
+        body: Body::Do(Do {
+            variables,
+            body: Box::new(body),
+        }),
+    })
+}
+
 fn unary_operation(
     operator: UnaryOperator,
     parser: &Parser,
@@ -469,6 +635,8 @@ static CONSTRUCTORS: &[(&str, Constructor)] = &[
     (":array", array),
     (":ref", reference),
     (":if", if_else),
+    (":match", match_expression),
+    (":do", do_block),
     (":default", default),
     ("!", unary_operation!(UnaryOperator::Not)),
     ("==", operation!(Operator::Eq)),
@@ -479,6 +647,11 @@ static CONSTRUCTORS: &[(&str, Constructor)] = &[
     (">=", operation!(Operator::Get)),
     ("&&", operation!(Operator::And)),
     ("||", operation!(Operator::Or)),
+    ("+", operation!(Operator::Add)),
+    ("-", operation!(Operator::Sub)),
+    ("*", operation!(Operator::Mul)),
+    ("/", operation!(Operator::Div)),
+    ("%", operation!(Operator::Rem)),
 ];
 
 pub struct Parser {
@@ -692,4 +865,17 @@ mod tests {
 
         assert_eq!(error, ParsingUnitError::EmptyContent);
     }
+
+    #[test]
+    fn parse_deeply_nested_expression() {
+        let depth = 100_000;
+        let pel = format!(
+            "{}[\":null\", \"0-4\"]{}",
+            r#"[":array", "0-4", "#.repeat(depth),
+            "]".repeat(depth)
+        );
+        let error = Parser::new().parse_str(&pel).unwrap_err();
+
+        assert_eq!(error.kind, ParsingErrorKind::BadFormedPelExpression);
+    }
 }
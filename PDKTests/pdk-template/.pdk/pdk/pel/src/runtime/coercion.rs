@@ -1,7 +1,7 @@
 // Copyright 2023 Salesforce, Inc. All rights reserved.
 use crate::Location;
 
-use crate::runtime::{value::Value, RuntimeError, RuntimeErrorKind};
+use crate::runtime::{decimal::Decimal, number, value::Value, RuntimeError, RuntimeErrorKind};
 
 pub trait Coerce<T> {
     fn coerce(&self, location: Location) -> Result<T, RuntimeError> {
@@ -19,7 +19,34 @@ impl Coerce<f64> for Value {
         if let Some(n) = self.as_f64() {
             Some(n)
         } else if let Some(s) = self.as_str() {
-            s.parse().ok()
+            number::parse(s)
+        } else {
+            None
+        }
+    }
+}
+
+impl Coerce<i64> for Value {
+    fn cast(&self) -> Option<i64> {
+        if let Some(n) = self.as_i64() {
+            Some(n)
+        } else if let Some(s) = self.as_str() {
+            s.trim().parse().ok()
+        } else {
+            None
+        }
+    }
+}
+
+impl Coerce<Decimal> for Value {
+    fn cast(&self) -> Option<Decimal> {
+        if let Some(d) = self.as_decimal() {
+            Some(d)
+        } else if let Some(n) = self.as_number() {
+            // The representation keeps the digits as written, before any f64 rounding.
+            n.representation().parse().ok()
+        } else if let Some(s) = self.as_str() {
+            s.trim().parse().ok()
         } else {
             None
         }
@@ -48,6 +75,8 @@ impl Coerce<String> for Value {
             Some(b.to_string())
         } else if let Some(n) = self.as_number() {
             Some(n.representation().to_string())
+        } else if let Some(d) = self.as_decimal() {
+            Some(d.to_string())
         } else {
             None
         }
@@ -0,0 +1,391 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Fixed-point decimal numbers, for values like monetary amounts that must not suffer
+//! binary floating point rounding.
+
+use std::{cmp::Ordering, convert::TryFrom, fmt, str::FromStr};
+use thiserror::Error;
+
+/// The maximum number of fractional digits a [`Decimal`] can hold.
+pub const MAX_SCALE: u32 = 18;
+
+/// The number of fractional digits a quotient is computed with, before trailing zeros are
+/// removed.
+const DIVISION_SCALE: u32 = 10;
+
+/// A decimal number represented as `mantissa * 10^-scale`.
+///
+/// The scale given when parsing is preserved, so `19.90` is displayed as `19.90`, but
+/// comparisons are made by value, so `19.90 == 19.9`.
+#[derive(Clone, Copy, Debug)]
+pub struct Decimal {
+    mantissa: i128,
+    scale: u32,
+}
+
+#[derive(Error, Clone, Copy, Debug, PartialEq, Eq)]
+#[error("invalid decimal number")]
+pub struct ParseDecimalError;
+
+impl Decimal {
+    pub const ZERO: Decimal = Decimal {
+        mantissa: 0,
+        scale: 0,
+    };
+
+    /// Creates the decimal `mantissa * 10^-scale`. Returns `None` if `scale` is greater
+    /// than [`MAX_SCALE`].
+    pub fn new(mantissa: i128, scale: u32) -> Option<Self> {
+        (scale <= MAX_SCALE).then_some(Self { mantissa, scale })
+    }
+
+    pub fn mantissa(&self) -> i128 {
+        self.mantissa
+    }
+
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mantissa == 0
+    }
+
+    /// Returns the same value without trailing fractional zeros.
+    pub fn normalize(&self) -> Self {
+        let mut result = *self;
+        while result.scale > 0 && result.mantissa % 10 == 0 {
+            result.mantissa /= 10;
+            result.scale -= 1;
+        }
+        result
+    }
+
+    /// Rounds to `scale` fractional digits, with ties going away from zero. Values that
+    /// already have fewer digits are left untouched.
+    pub fn round(&self, scale: u32) -> Self {
+        if scale >= self.scale {
+            return *self;
+        }
+        let divisor = 10i128.pow(self.scale - scale);
+        let quotient = self.mantissa / divisor;
+        let remainder = self.mantissa % divisor;
+        let mantissa = if remainder.abs() * 2 >= divisor {
+            quotient + self.mantissa.signum()
+        } else {
+            quotient
+        };
+        Self { mantissa, scale }
+    }
+
+    /// Returns the value with exactly `scale` fractional digits, rounding or padding with
+    /// zeros as needed. Returns `None` if `scale` is greater than [`MAX_SCALE`] or the
+    /// padded value overflows.
+    pub fn with_scale(&self, scale: u32) -> Option<Self> {
+        if scale > MAX_SCALE {
+            None
+        } else if scale < self.scale {
+            Some(self.round(scale))
+        } else {
+            self.rescale(scale).map(|mantissa| Self { mantissa, scale })
+        }
+    }
+
+    /// Returns the mantissa of the value with `scale` fractional digits, or `None` if it
+    /// overflows.
+    fn rescale(&self, scale: u32) -> Option<i128> {
+        10i128
+            .checked_pow(scale - self.scale)
+            .and_then(|factor| self.mantissa.checked_mul(factor))
+    }
+
+    /// Returns both mantissas at the same scale, or `None` if it overflows.
+    fn align(&self, other: &Self) -> Option<(i128, i128, u32)> {
+        let scale = self.scale.max(other.scale);
+        Some((self.rescale(scale)?, other.rescale(scale)?, scale))
+    }
+
+    pub fn checked_add(&self, other: &Self) -> Option<Self> {
+        let (left, right, scale) = self.align(other)?;
+        left.checked_add(right)
+            .map(|mantissa| Self { mantissa, scale })
+    }
+
+    pub fn checked_sub(&self, other: &Self) -> Option<Self> {
+        let (left, right, scale) = self.align(other)?;
+        left.checked_sub(right)
+            .map(|mantissa| Self { mantissa, scale })
+    }
+
+    /// Multiplies two decimals. Products with more than [`MAX_SCALE`] fractional digits
+    /// are rounded.
+    pub fn checked_mul(&self, other: &Self) -> Option<Self> {
+        let left = self.normalize();
+        let right = other.normalize();
+        let mantissa = left.mantissa.checked_mul(right.mantissa)?;
+        let product = Self {
+            mantissa,
+            scale: left.scale + right.scale,
+        };
+        Some(product.round(MAX_SCALE.min(self.scale + other.scale)))
+    }
+
+    /// Divides two decimals, rounding the quotient to at least ten fractional digits.
+    /// Returns `None` when dividing by zero.
+    pub fn checked_div(&self, other: &Self) -> Option<Self> {
+        if other.is_zero() {
+            return None;
+        }
+        let left = self.normalize();
+        let right = other.normalize();
+        let scale = DIVISION_SCALE.max(left.scale).max(right.scale);
+
+        // left / right = (left.mantissa * 10^(scale + 1 + right.scale - left.scale)
+        //                 / right.mantissa) * 10^-(scale + 1)
+        let factor = 10i128.checked_pow(scale + 1 + right.scale - left.scale)?;
+        let numerator = left.mantissa.checked_mul(factor)?;
+        let quotient = Self {
+            mantissa: numerator / right.mantissa,
+            scale: scale + 1,
+        };
+        Some(quotient.round(scale).normalize())
+    }
+
+    /// Returns the remainder of the truncated division, which has the sign of `self`.
+    /// Returns `None` when dividing by zero.
+    pub fn checked_rem(&self, other: &Self) -> Option<Self> {
+        if other.is_zero() {
+            return None;
+        }
+        let (left, right, scale) = self.align(other)?;
+        left.checked_rem(right)
+            .map(|mantissa| Self { mantissa, scale })
+    }
+
+    #[cfg(not(feature = "integer-numbers"))]
+    pub fn to_f64(&self) -> f64 {
+        // The decimal representation is exactly what `f64` parsing rounds at best.
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+
+    /// Returns a close `f64`, without the float parsing code. It may differ from the
+    /// closest one in the last bit.
+    #[cfg(feature = "integer-numbers")]
+    pub fn to_f64(&self) -> f64 {
+        self.mantissa as f64 / 10f64.powi(self.scale as i32)
+    }
+}
+
+impl FromStr for Decimal {
+    type Err = ParseDecimalError;
+
+    /// Parses numbers like `-12`, `19.99`, `.5` or `1.5e3`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (number, exponent) = match s.find(['e', 'E']) {
+            Some(index) => {
+                let exponent: i32 = s[index + 1..].parse().map_err(|_| ParseDecimalError)?;
+                (&s[..index], exponent)
+            }
+            None => (s, 0),
+        };
+
+        let (negative, number) = match number.as_bytes().first() {
+            Some(b'-') => (true, &number[1..]),
+            Some(b'+') => (false, &number[1..]),
+            _ => (false, number),
+        };
+
+        let (integer, fraction) = number.split_once('.').unwrap_or((number, ""));
+        let all_digits = |part: &str| part.bytes().all(|byte| byte.is_ascii_digit());
+        if integer.is_empty() && fraction.is_empty()
+            || !all_digits(integer)
+            || !all_digits(fraction)
+        {
+            return Err(ParseDecimalError);
+        }
+
+        let mut mantissa: i128 = 0;
+        for digit in integer.bytes().chain(fraction.bytes()) {
+            mantissa = mantissa
+                .checked_mul(10)
+                .and_then(|mantissa| mantissa.checked_add(i128::from(digit - b'0')))
+                .ok_or(ParseDecimalError)?;
+        }
+        if negative {
+            mantissa = -mantissa;
+        }
+
+        let scale = fraction.len() as i64 - i64::from(exponent);
+        let decimal = if scale < 0 {
+            let factor = u32::try_from(-scale)
+                .ok()
+                .and_then(|exponent| 10i128.checked_pow(exponent))
+                .ok_or(ParseDecimalError)?;
+            Decimal {
+                mantissa: mantissa.checked_mul(factor).ok_or(ParseDecimalError)?,
+                scale: 0,
+            }
+        } else {
+            let scale = u32::try_from(scale).map_err(|_| ParseDecimalError)?;
+            if scale > MAX_SCALE {
+                return Err(ParseDecimalError);
+            }
+            Decimal { mantissa, scale }
+        };
+        Ok(decimal)
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = self.mantissa.unsigned_abs().to_string();
+        let sign = if self.mantissa < 0 { "-" } else { "" };
+        let scale = self.scale as usize;
+
+        if scale == 0 {
+            write!(f, "{sign}{digits}")
+        } else if digits.len() > scale {
+            let (integer, fraction) = digits.split_at(digits.len() - scale);
+            write!(f, "{sign}{integer}.{fraction}")
+        } else {
+            write!(f, "{sign}0.{digits:0>scale$}")
+        }
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match self.align(other) {
+            Some((left, right, _)) => left.cmp(&right),
+            // Rescaling only overflows when the value with less fractional digits has a
+            // magnitude larger than anything the other can represent.
+            None if self.scale < other.scale => self.mantissa.cmp(&0),
+            None => 0.cmp(&other.mantissa),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Decimal;
+
+    fn decimal(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_and_display() {
+        assert_eq!(decimal("19.90").to_string(), "19.90");
+        assert_eq!(decimal("-0.05").to_string(), "-0.05");
+        assert_eq!(decimal(".5").to_string(), "0.5");
+        assert_eq!(decimal("+7").to_string(), "7");
+        assert_eq!(decimal("1.5e3").to_string(), "1500");
+        assert_eq!(decimal("25e-3").to_string(), "0.025");
+        assert_eq!(
+            decimal("170141183460469231731687303715884105727").to_string(),
+            "170141183460469231731687303715884105727"
+        );
+    }
+
+    #[test]
+    fn parse_invalid() {
+        for invalid in ["", ".", "-", "1.2.3", "12a", "1e", "NaN", "inf", "0.1e-30"] {
+            assert!(invalid.parse::<Decimal>().is_err(), "{}", invalid);
+        }
+        assert!("170141183460469231731687303715884105728"
+            .parse::<Decimal>()
+            .is_err());
+    }
+
+    #[test]
+    fn compare_by_value() {
+        assert_eq!(decimal("19.90"), decimal("19.9"));
+        assert!(decimal("0.1") < decimal("0.10000001"));
+        assert!(decimal("-2") < decimal("-1.99"));
+        assert!(
+            decimal("170141183460469231731687303715884105727") > decimal("0.000000000000000001")
+        );
+    }
+
+    #[test]
+    fn exact_arithmetic() {
+        let sum = decimal("0.1").checked_add(&decimal("0.2")).unwrap();
+        assert_eq!(sum, decimal("0.3"));
+        assert_eq!(sum.to_string(), "0.3");
+
+        assert_eq!(
+            decimal("10.00")
+                .checked_sub(&decimal("0.01"))
+                .unwrap()
+                .to_string(),
+            "9.99"
+        );
+        assert_eq!(
+            decimal("19.99")
+                .checked_mul(&decimal("3"))
+                .unwrap()
+                .to_string(),
+            "59.97"
+        );
+        assert_eq!(
+            decimal("10")
+                .checked_div(&decimal("4"))
+                .unwrap()
+                .to_string(),
+            "2.5"
+        );
+        assert_eq!(
+            decimal("1").checked_div(&decimal("3")).unwrap().to_string(),
+            "0.3333333333"
+        );
+        assert_eq!(
+            decimal("-7.5")
+                .checked_rem(&decimal("2"))
+                .unwrap()
+                .to_string(),
+            "-1.5"
+        );
+    }
+
+    #[test]
+    fn division_by_zero() {
+        assert_eq!(decimal("1").checked_div(&Decimal::ZERO), None);
+        assert_eq!(decimal("1").checked_rem(&decimal("0.00")), None);
+    }
+
+    #[test]
+    fn overflow() {
+        let max = decimal("170141183460469231731687303715884105727");
+        assert_eq!(max.checked_add(&decimal("1")), None);
+        assert_eq!(max.checked_mul(&decimal("2")), None);
+
+        let min = decimal("-170141183460469231731687303715884105727")
+            .checked_sub(&decimal("1"))
+            .unwrap();
+        assert_eq!(min.checked_rem(&decimal("-1")), None);
+    }
+
+    #[test]
+    fn round_half_away_from_zero() {
+        assert_eq!(decimal("2.345").round(2).to_string(), "2.35");
+        assert_eq!(decimal("-2.345").round(2).to_string(), "-2.35");
+        assert_eq!(decimal("2.344").round(2).to_string(), "2.34");
+        assert_eq!(decimal("2.5").round(4).to_string(), "2.5");
+        assert_eq!(decimal("2.5").with_scale(2).unwrap().to_string(), "2.50");
+        assert_eq!(decimal("2.555").with_scale(0).unwrap().to_string(), "3");
+        assert_eq!(decimal("0.1").to_f64(), 0.1);
+    }
+}
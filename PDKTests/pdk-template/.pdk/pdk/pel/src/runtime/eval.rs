@@ -1,14 +1,14 @@
 // Copyright 2023 Salesforce, Inc. All rights reserved.
 use crate::{
     expression::{
-        Apply, Body, DefaultOperator, Expression, IfElse, Operation, Operator, Ref, Selection,
-        UnaryOperation, UnaryOperator,
+        Apply, Body, Case, DefaultOperator, Do, Expression, IfElse, Match, Operation, Operator,
+        Pattern, Ref, Regex, Selection, Symbol, UnaryOperation, UnaryOperator, Variable,
     },
     runtime::{
-        coercion::Coerce, Binding, Context, Eval, Evaluation, RuntimeError, RuntimeErrorKind,
-        Value, ValueHandler,
+        coercion::Coerce, decimal::Decimal, Binding, Context, Eval, Evaluation, RuntimeError,
+        RuntimeErrorKind, Value, ValueHandler,
     },
-    Location,
+    Location, Reference,
 };
 
 trait BodyEval {
@@ -243,6 +243,227 @@ impl BodyEval for IfElse {
     }
 }
 
+impl BodyEval for Match {
+    fn body_eval(
+        &self,
+        location: Location,
+        context: &dyn Context,
+    ) -> Result<Evaluation, RuntimeError> {
+        let (target_location, target) = match self.target.eval(context)? {
+            Evaluation::Complete(target_location, target) => (target_location, target),
+            Evaluation::Partial(target) => {
+                return self.partial_eval(location, context, target, 0);
+            }
+        };
+
+        for (index, case) in self.cases.iter().enumerate() {
+            let matches = match &case.pattern {
+                Pattern::Literal(literal) => match literal.eval(context)? {
+                    Evaluation::Complete(_, literal) => eval_equality(&target, &literal),
+                    Evaluation::Partial(_) => {
+                        let target = Expression::new(target_location, target);
+                        return self.partial_eval(location, context, target, index);
+                    }
+                },
+                Pattern::Regex(Regex(regex)) => Coerce::<String>::cast(&target)
+                    .map(|text| regex.is_match(&text))
+                    .unwrap_or(false),
+                Pattern::Range(from, until) => match (from.eval(context)?, until.eval(context)?) {
+                    (Evaluation::Complete(_, from), Evaluation::Complete(_, until)) => {
+                        // Values that can not be compared with the bounds are out of the range.
+                        let within = |operator, bound| {
+                            eval_coercible_operation(location, operator, target.clone(), bound)
+                                .ok()
+                                .and_then(|result| result.as_bool())
+                                .unwrap_or(false)
+                        };
+                        within(Operator::Get, from) && within(Operator::Let, until)
+                    }
+                    _ => {
+                        let target = Expression::new(target_location, target);
+                        return self.partial_eval(location, context, target, index);
+                    }
+                },
+            };
+
+            if matches {
+                return case.result.eval(context);
+            }
+        }
+
+        match &self.default {
+            Some(default) => default.eval(context),
+            None => Err(RuntimeError {
+                location,
+                kind: RuntimeErrorKind::NoMatchingCase,
+            }),
+        }
+    }
+
+    fn body_bind(
+        &self,
+        location: Location,
+        context: &dyn Context,
+    ) -> Result<Expression, RuntimeError> {
+        let target = self.target.bind(context)?;
+        self.partial_bind(location, context, target, 0)
+    }
+}
+
+impl Match {
+    /// Binds the cases starting at `from`, as the previous ones are known not to match.
+    fn partial_bind(
+        &self,
+        location: Location,
+        context: &dyn Context,
+        target: Expression,
+        from: usize,
+    ) -> Result<Expression, RuntimeError> {
+        let default = match &self.default {
+            Some(default) => Some(Box::new(default.bind(context)?)),
+            None => None,
+        };
+        Ok(Expression::new(
+            location,
+            Self {
+                target: Box::new(target),
+                cases: self.cases[from..]
+                    .iter()
+                    .map(|case| case.bind(context))
+                    .collect::<Result<Vec<_>, _>>()?,
+                default,
+            },
+        ))
+    }
+
+    fn partial_eval(
+        &self,
+        location: Location,
+        context: &dyn Context,
+        target: Expression,
+        from: usize,
+    ) -> Result<Evaluation, RuntimeError> {
+        self.partial_bind(location, context, target, from)
+            .map(Evaluation::Partial)
+    }
+}
+
+impl Case {
+    fn bind(&self, context: &dyn Context) -> Result<Self, RuntimeError> {
+        let pattern = match &self.pattern {
+            Pattern::Literal(literal) => Pattern::Literal(literal.bind(context)?),
+            Pattern::Regex(regex) => Pattern::Regex(regex.clone()),
+            Pattern::Range(from, until) => {
+                Pattern::Range(from.bind(context)?, until.bind(context)?)
+            }
+        };
+        Ok(Self {
+            pattern,
+            result: self.result.bind(context)?,
+        })
+    }
+}
+
+/// The local variables of a [`Do`], where `None` stands for a variable that is still pending.
+struct Scope<'a> {
+    parent: &'a dyn Context,
+    variables: Vec<(Symbol, Option<Value>)>,
+}
+
+impl<'a> Scope<'a> {
+    fn new(parent: &'a dyn Context) -> Self {
+        Self {
+            parent,
+            variables: Vec::new(),
+        }
+    }
+}
+
+impl Context for Scope<'_> {
+    fn resolve(&self, symbol: &Symbol) -> Binding {
+        match self.variables.iter().rev().find(|(s, _)| s == symbol) {
+            Some((_, Some(value))) => Binding::Available(value.clone()),
+            Some((_, None)) => Binding::Pending,
+            None => self.parent.resolve(symbol),
+        }
+    }
+
+    fn value_handler(&self, reference: Reference) -> Option<&dyn ValueHandler> {
+        self.parent.value_handler(reference)
+    }
+
+    fn trace(&self, location: Location, result: Result<&Evaluation, &RuntimeError>) {
+        self.parent.trace(location, result)
+    }
+}
+
+impl BodyEval for Do {
+    fn body_eval(
+        &self,
+        location: Location,
+        context: &dyn Context,
+    ) -> Result<Evaluation, RuntimeError> {
+        let mut scope = Scope::new(context);
+        let mut pending = Vec::new();
+
+        // Each variable is evaluated once, and only the pending ones remain in the partial
+        // expression.
+        for variable in &self.variables {
+            match variable.value.eval(&scope)? {
+                Evaluation::Complete(_, value) => {
+                    scope.variables.push((variable.symbol.clone(), Some(value)))
+                }
+                Evaluation::Partial(value) => {
+                    scope.variables.push((variable.symbol.clone(), None));
+                    pending.push(Variable {
+                        symbol: variable.symbol.clone(),
+                        value,
+                    });
+                }
+            }
+        }
+
+        let result = match self.body.eval(&scope)? {
+            Evaluation::Partial(body) if !pending.is_empty() => {
+                Evaluation::Partial(Expression::new(
+                    location,
+                    Self {
+                        variables: pending,
+                        body: Box::new(body),
+                    },
+                ))
+            }
+            body => body,
+        };
+        Ok(result)
+    }
+
+    fn body_bind(
+        &self,
+        location: Location,
+        context: &dyn Context,
+    ) -> Result<Expression, RuntimeError> {
+        // Local variables shadow the context, so they are bound as pending.
+        let mut scope = Scope::new(context);
+        let mut variables = Vec::with_capacity(self.variables.len());
+        for variable in &self.variables {
+            variables.push(Variable {
+                symbol: variable.symbol.clone(),
+                value: variable.value.bind(&scope)?,
+            });
+            scope.variables.push((variable.symbol.clone(), None));
+        }
+
+        Ok(Expression::new(
+            location,
+            Self {
+                variables,
+                body: Box::new(self.body.bind(&scope)?),
+            },
+        ))
+    }
+}
+
 impl BodyEval for Selection {
     fn body_eval(
         &self,
@@ -270,15 +491,19 @@ impl BodyEval for Selection {
                         location: target_location,
                         kind: RuntimeErrorKind::UnsupportedSelection,
                     })?;
+                    // Negative indexes count from the end, those before the start select nothing.
                     let index = if index < 0 {
-                        size - -index as usize
+                        size.checked_sub(index.unsigned_abs() as usize)
                     } else {
-                        index as usize
+                        Some(index as usize)
                     };
-                    target.select_by_index(index).ok_or(RuntimeError {
-                        location,
-                        kind: RuntimeErrorKind::UnsupportedSelection,
-                    })?
+                    match index {
+                        Some(index) => target.select_by_index(index).ok_or(RuntimeError {
+                            location,
+                            kind: RuntimeErrorKind::UnsupportedSelection,
+                        })?,
+                        None => Value::null(),
+                    }
                 } else {
                     return Err(RuntimeError {
                         location: selector_location,
@@ -363,11 +588,11 @@ impl BodyEval for Operation {
                     Operator::Eq => self
                         .right
                         .eval(context)?
-                        .map(|right| Value::bool(left == right)),
+                        .map(|right| Value::bool(eval_equality(&left, &right))),
                     Operator::Neq => self
                         .right
                         .eval(context)?
-                        .map(|right| Value::bool(left != right)),
+                        .map(|right| Value::bool(!eval_equality(&left, &right))),
                     Operator::And => {
                         if left.coerce(left_location)? {
                             match self.right.eval(context)? {
@@ -398,6 +623,17 @@ impl BodyEval for Operation {
                             }
                         }
                     }
+                    Operator::Add
+                    | Operator::Sub
+                    | Operator::Mul
+                    | Operator::Div
+                    | Operator::Rem => match self.right.eval(context)? {
+                        Evaluation::Complete(_, right) => Evaluation::Complete(
+                            location,
+                            eval_arithmetic_operation(location, self.operator, left, right)?,
+                        ),
+                        right => right,
+                    },
                     operator => match self.right.eval(context)? {
                         Evaluation::Complete(_, right) => Evaluation::Complete(
                             location,
@@ -453,13 +689,35 @@ impl BodyEval for Value {
     }
 }
 
+/// Decimals are equal to the numbers and numeric strings of the same value, both coerced to
+/// decimals as the relational operators do, so numbers are compared by the digits they were
+/// written with. Any other pair of values is equal only when they are identical.
+fn eval_equality(left: &Value, right: &Value) -> bool {
+    if left.as_decimal().is_none() && right.as_decimal().is_none() {
+        return left == right;
+    }
+
+    match (
+        Coerce::<Decimal>::cast(left),
+        Coerce::<Decimal>::cast(right),
+    ) {
+        (Some(left), Some(right)) => left == right,
+        _ => false,
+    }
+}
+
 fn eval_coercible_operation(
     location: Location,
     operator: Operator,
     left: Value,
     right: Value,
 ) -> Result<Value, RuntimeError> {
-    let result = if let Some(right) = right.as_str() {
+    // Decimals take precedence so that comparisons against them are exact.
+    let result = if left.as_decimal().is_some() || right.as_decimal().is_some() {
+        let left: Decimal = left.coerce(location)?;
+        let right: Decimal = right.coerce(location)?;
+        eval_coerced_operation(operator, &left, &right)
+    } else if let Some(right) = right.as_str() {
         let left: String = left.coerce(location)?;
         eval_coerced_operation(operator, left.as_str(), right)
     } else if let Some(right) = right.as_f64() {
@@ -488,9 +746,65 @@ where
     }
 }
 
+/// Both operands are coerced to numbers, or to decimals when any of them is a decimal so
+/// that the result is exact.
+fn eval_arithmetic_operation(
+    location: Location,
+    operator: Operator,
+    left: Value,
+    right: Value,
+) -> Result<Value, RuntimeError> {
+    let error = |kind| RuntimeError { location, kind };
+
+    if left.as_decimal().is_some() || right.as_decimal().is_some() {
+        let left: Decimal = left.coerce(location)?;
+        let right: Decimal = right.coerce(location)?;
+
+        if matches!(operator, Operator::Div | Operator::Rem) && right.is_zero() {
+            return Err(error(RuntimeErrorKind::DivisionByZero));
+        }
+
+        let result = match operator {
+            Operator::Add => left.checked_add(&right),
+            Operator::Sub => left.checked_sub(&right),
+            Operator::Mul => left.checked_mul(&right),
+            Operator::Div => left.checked_div(&right),
+            Operator::Rem => left.checked_rem(&right),
+            _ => unreachable!(),
+        };
+        return result
+            .map(Value::decimal)
+            .ok_or_else(|| error(RuntimeErrorKind::NumericOverflow));
+    }
+
+    let left: f64 = left.coerce(location)?;
+    let right: f64 = right.coerce(location)?;
+
+    if matches!(operator, Operator::Div | Operator::Rem) && right == 0.0 {
+        return Err(error(RuntimeErrorKind::DivisionByZero));
+    }
+
+    let result = match operator {
+        Operator::Add => left + right,
+        Operator::Sub => left - right,
+        Operator::Mul => left * right,
+        Operator::Div => left / right,
+        Operator::Rem => left % right,
+        _ => unreachable!(),
+    };
+
+    if result.is_finite() {
+        Ok(Value::number(result))
+    } else {
+        Err(error(RuntimeErrorKind::NumericOverflow))
+    }
+}
+
 impl Eval for Expression {
     fn eval(&self, context: &dyn Context) -> Result<Evaluation, RuntimeError> {
-        self.body().body_eval(self.location, context)
+        let result = self.body().body_eval(self.location, context);
+        context.trace(self.location, result.as_ref());
+        result
     }
 }
 
@@ -501,6 +815,8 @@ impl Expression {
             Body::Apply(a) => a,
             Body::Array(a) => a,
             Body::IfElse(ie) => ie,
+            Body::Match(m) => m,
+            Body::Do(d) => d,
             Body::Selection(s) => s,
             Body::DefaultOperator(d) => d,
             Body::UnaryOperation(u) => u,
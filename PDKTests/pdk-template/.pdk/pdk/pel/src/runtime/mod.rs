@@ -2,8 +2,13 @@
 mod coercion;
 mod eval;
 mod prelude;
+mod registry;
+mod text;
 mod value_handler;
 
+pub mod decimal;
+pub(crate) mod number;
+pub mod trace;
 pub mod value;
 
 use std::collections::HashMap;
@@ -11,10 +16,14 @@ use thiserror::Error;
 
 use crate::{
     expression::{Expression, Symbol},
-    runtime::value::Value,
+    runtime::{
+        trace::{Trace, Tracer},
+        value::Value,
+    },
     Location, Reference,
 };
 
+pub use registry::{FunctionRegistry, RegistryError};
 pub use value_handler::ValueHandler;
 
 #[derive(Error, Debug)]
@@ -66,6 +75,15 @@ pub enum RuntimeErrorKind {
 
     #[error("Uncomparable types")]
     UncomparableTypes,
+
+    #[error("Division by zero")]
+    DivisionByZero,
+
+    #[error("Numeric overflow")]
+    NumericOverflow,
+
+    #[error("No matching case")]
+    NoMatchingCase,
 }
 
 pub enum Binding {
@@ -78,6 +96,10 @@ pub trait Context {
     fn resolve(&self, symbol: &Symbol) -> Binding;
 
     fn value_handler(&self, reference: Reference) -> Option<&dyn ValueHandler>;
+
+    /// Called after evaluating each node of an expression. Contexts wrapping another one
+    /// must forward it.
+    fn trace(&self, _location: Location, _result: Result<&Evaluation, &RuntimeError>) {}
 }
 
 type Prelude = HashMap<&'static str, Value>;
@@ -131,6 +153,10 @@ impl Context for MergedContext<'_> {
             .value_handler(reference)
             .or_else(|| self.root.value_handler(reference))
     }
+
+    fn trace(&self, location: Location, result: Result<&Evaluation, &RuntimeError>) {
+        self.current.trace(location, result)
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -197,9 +223,15 @@ impl<P: Context> Runtime<P> {
     pub fn with_prelude(prelude: P) -> Self {
         Self { prelude }
     }
-}
 
-impl Runtime {
+    pub fn prelude(&self) -> &P {
+        &self.prelude
+    }
+
+    pub fn prelude_mut(&mut self) -> &mut P {
+        &mut self.prelude
+    }
+
     pub fn eval_with_context(
         &self,
         e: &dyn Eval,
@@ -215,6 +247,18 @@ impl Runtime {
         })
     }
 
+    /// Like [`Runtime::eval_with_context`], also returning the result of every evaluated
+    /// node. Tracing has a cost, so it is meant for debugging.
+    pub fn eval_with_trace(
+        &self,
+        e: &dyn Eval,
+        context: &dyn Context,
+    ) -> (Result<Evaluation, RuntimeError>, Trace) {
+        let tracer = Tracer::new(context);
+        let result = self.eval_with_context(e, &tracer);
+        (result, tracer.into_trace())
+    }
+
     pub fn eval(&self, e: &dyn Eval) -> Result<Evaluation, RuntimeError> {
         self.eval_with_context(e, &EmptyContext)
     }
@@ -233,6 +277,15 @@ mod tests {
         ContextId, Reference,
     };
 
+    #[cfg(feature = "prelude-strings")]
+    use crate::{
+        runtime::{
+            trace::{TraceEntry, TraceOutcome},
+            EmptyContext,
+        },
+        Location,
+    };
+
     use super::Runtime;
 
     #[test]
@@ -420,6 +473,28 @@ mod tests {
         assert!(result.is_null());
     }
 
+    #[test]
+    fn array_negative_out_of_bounds_index() {
+        // DW: ["accept","accept-encoding","user-agent"][-5]
+        let pel = r#"
+            [".", "0-46", 
+                [":array", "0-41", 
+                    [":str", "1-9", "accept"], 
+                    [":str", "10-27", "accept-encoding"], 
+                    [":str", "28-40", "user-agent"]
+                ], [":nbr", "42-45", "-5"]
+            ]"#;
+
+        let expression = Parser::new().parse_str(pel).unwrap();
+        let result = Runtime::new()
+            .eval(&expression)
+            .unwrap()
+            .complete()
+            .unwrap();
+
+        assert!(result.is_null());
+    }
+
     #[test]
     fn string_positive_index() {
         // DW: "peregrine expression language"[10]
@@ -657,6 +732,33 @@ mod tests {
         assert!(!result.as_bool().unwrap());
     }
 
+    #[test]
+    fn operation_eq_integer() {
+        // DW: 200 == 200.0
+        let json = r#"["==", "0-12", [":nbr", "0-3", "200"], [":nbr", "7-12", "200.0"]]"#;
+
+        let expression = Parser::new().parse_str(json).unwrap();
+        let result = Runtime::new()
+            .eval(&expression)
+            .unwrap()
+            .complete()
+            .unwrap();
+
+        assert!(result.as_bool().unwrap());
+        assert_eq!(Value::integer(200), Value::number(200.0));
+    }
+
+    #[test]
+    fn integer_semantics() {
+        let integer = Value::integer(200);
+
+        assert_eq!(integer.as_i64(), Some(200));
+        assert_eq!(integer.as_f64(), Some(200.0));
+        assert_eq!(integer.as_number().unwrap().representation(), "200");
+        assert_eq!(Value::number(2.5).as_i64(), None);
+        assert_eq!(Value::string("200".to_string()).as_i64(), None);
+    }
+
     #[test]
     fn operation_neq() {
         // DW: 10 != 10.0
@@ -805,6 +907,148 @@ mod tests {
         assert!(result.as_bool().unwrap());
     }
 
+    #[test]
+    fn operation_add() {
+        // DW: "40" + 2
+        let json = r#"["+", "0-8", [":str", "0-4", "40"], [":nbr", "7-8", "2"]]"#;
+
+        let expression = Parser::new().parse_str(json).unwrap();
+        let result = Runtime::new()
+            .eval(&expression)
+            .unwrap()
+            .complete()
+            .unwrap();
+
+        assert_eq!(result.as_f64(), Some(42.0));
+    }
+
+    #[test]
+    fn operation_sub() {
+        // DW: 10 - 12.5
+        let json = r#"["-", "0-9", [":nbr", "0-2", "10"], [":nbr", "5-9", "12.5"]]"#;
+
+        let expression = Parser::new().parse_str(json).unwrap();
+        let result = Runtime::new()
+            .eval(&expression)
+            .unwrap()
+            .complete()
+            .unwrap();
+
+        assert_eq!(result.as_f64(), Some(-2.5));
+    }
+
+    #[test]
+    fn operation_mul() {
+        // DW: 1.5 * 4
+        let json = r#"["*", "0-7", [":nbr", "0-3", "1.5"], [":nbr", "6-7", "4"]]"#;
+
+        let expression = Parser::new().parse_str(json).unwrap();
+        let result = Runtime::new()
+            .eval(&expression)
+            .unwrap()
+            .complete()
+            .unwrap();
+
+        assert_eq!(result.as_f64(), Some(6.0));
+    }
+
+    #[test]
+    fn operation_div() {
+        // DW: 7 / 2
+        let json = r#"["/", "0-5", [":nbr", "0-1", "7"], [":nbr", "4-5", "2"]]"#;
+
+        let expression = Parser::new().parse_str(json).unwrap();
+        let result = Runtime::new()
+            .eval(&expression)
+            .unwrap()
+            .complete()
+            .unwrap();
+
+        assert_eq!(result.as_f64(), Some(3.5));
+    }
+
+    #[test]
+    fn operation_rem() {
+        // DW: 7 % 3
+        let json = r#"["%", "0-5", [":nbr", "0-1", "7"], [":nbr", "4-5", "3"]]"#;
+
+        let expression = Parser::new().parse_str(json).unwrap();
+        let result = Runtime::new()
+            .eval(&expression)
+            .unwrap()
+            .complete()
+            .unwrap();
+
+        assert_eq!(result.as_f64(), Some(1.0));
+    }
+
+    #[test]
+    fn operation_div_by_zero_fail() {
+        // DW: 1 / 0
+        let json = r#"["/", "0-5", [":nbr", "0-1", "1"], [":nbr", "4-5", "0"]]"#;
+
+        let expression = Parser::new().parse_str(json).unwrap();
+        let error = Runtime::new().eval(&expression).err().unwrap();
+
+        assert_eq!(error.kind(), &RuntimeErrorKind::DivisionByZero);
+        assert_eq!(error.location().start, 0);
+        assert_eq!(error.location().end, 5);
+    }
+
+    #[test]
+    fn operation_arithmetic_type_mismatch_fail() {
+        // DW: "ten" * 2
+        let json = r#"["*", "0-9", [":str", "0-5", "ten"], [":nbr", "8-9", "2"]]"#;
+
+        let expression = Parser::new().parse_str(json).unwrap();
+        let error = Runtime::new().eval(&expression).err().unwrap();
+
+        assert_eq!(error.kind(), &RuntimeErrorKind::TypeMismatch);
+    }
+
+    #[cfg(feature = "prelude-decimals")]
+    #[test]
+    fn operation_decimal_add() {
+        // DW: toDecimal("0.1") + 0.2
+        let json = r#"
+            ["+", "0-22",
+                [":apply", "0-16",
+                    [":ref", "0-9", "toDecimal"],
+                    [":str", "10-15", "0.1"]
+                ],
+                [":nbr", "19-22", "0.2"]
+            ]"#;
+
+        let expression = Parser::new().parse_str(json).unwrap();
+        let result = Runtime::new()
+            .eval(&expression)
+            .unwrap()
+            .complete()
+            .unwrap();
+
+        assert_eq!(result.as_decimal().unwrap().to_string(), "0.3");
+    }
+
+    #[cfg(feature = "prelude-decimals")]
+    #[test]
+    fn operation_decimal_div_by_zero_fail() {
+        // DW: toDecimal("10.00") % 0
+        let json = r#"
+            ["%", "0-22",
+                [":apply", "0-18",
+                    [":ref", "0-9", "toDecimal"],
+                    [":str", "10-17", "10.00"]
+                ],
+                [":nbr", "21-22", "0"]
+            ]"#;
+
+        let expression = Parser::new().parse_str(json).unwrap();
+        let error = Runtime::new().eval(&expression).err().unwrap();
+
+        assert_eq!(error.kind(), &RuntimeErrorKind::DivisionByZero);
+    }
+
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn lower_null() {
         // DW: lower(null)
@@ -825,6 +1069,7 @@ mod tests {
         assert!(result.is_null());
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn lower_string() {
         // DW: lower("Peregrine Expression Language")
@@ -845,6 +1090,7 @@ mod tests {
         assert_eq!("peregrine expression language", result.as_str().unwrap());
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn lower_boolean() {
         // DW: lower(true)
@@ -865,6 +1111,7 @@ mod tests {
         assert_eq!("true", result.as_str().unwrap());
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn lower_number() {
         // DW: lower(1944.07)
@@ -885,6 +1132,7 @@ mod tests {
         assert_eq!("1944.07", result.as_str().unwrap());
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn lower_array_fail() {
         // DW: lower(["accept", "accept-encoding", "user-agent"])
@@ -1159,6 +1407,7 @@ mod tests {
         assert!(!result.as_bool().unwrap());
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn trim_array() {
         // DW: trim(["one", 2, false])
@@ -1179,6 +1428,7 @@ mod tests {
         assert_eq!(error.kind, RuntimeErrorKind::TypeMismatch);
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn trim_boolean() {
         // DW: trim(true)
@@ -1199,6 +1449,7 @@ mod tests {
         assert_eq!("true", result.as_str().unwrap());
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn trim_number() {
         // DW: trim(1000.0)
@@ -1220,6 +1471,7 @@ mod tests {
         assert_eq!("1000.0", result.as_str().unwrap());
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn trim_string() {
         // DW: trim("   hello world   ")
@@ -1240,6 +1492,7 @@ mod tests {
         assert_eq!("hello world", result.as_str().unwrap());
     }
 
+    #[cfg(feature = "prelude-uuid")]
     #[test]
     fn generate_uuid() {
         // DW: uuid()
@@ -1280,6 +1533,113 @@ mod tests {
         assert_eq!(11, result.as_f64().unwrap() as usize);
     }
 
+    #[test]
+    fn size_of_multibyte_string() {
+        // DW: sizeOf("año")
+        let pel = r#"
+            [":apply", "0-15",
+                [":ref", "0-6", "sizeOf"],
+                [":str", "7-14", "año"]
+            ]"#;
+
+        let expression = Parser::new().parse_str(pel).unwrap();
+        let result = Runtime::new()
+            .eval(&expression)
+            .unwrap()
+            .complete()
+            .unwrap();
+
+        assert_eq!(3, result.as_f64().unwrap() as usize);
+    }
+
+    #[cfg(feature = "prelude-decimals")]
+    #[test]
+    fn decimal_comparison_is_exact() {
+        // DW: toDecimal("9007199254740993") > 9007199254740992
+        let pel = r#"
+            [">", "0-52",
+                [":apply", "0-29",
+                    [":ref", "0-9", "toDecimal"],
+                    [":str", "10-28", "9007199254740993"]
+                ],
+                [":nbr", "32-52", "9007199254740992"]
+            ]"#;
+
+        let expression = Parser::new().parse_str(pel).unwrap();
+        let result = Runtime::new()
+            .eval(&expression)
+            .unwrap()
+            .complete()
+            .unwrap();
+
+        assert!(result.as_bool().unwrap());
+    }
+
+    #[cfg(feature = "prelude-decimals")]
+    #[test]
+    fn decimal_equals_number() {
+        // DW: toDecimal("19.90") == 19.9
+        let pel = r#"
+            ["==", "0-28",
+                [":apply", "0-20",
+                    [":ref", "0-9", "toDecimal"],
+                    [":str", "10-19", "19.90"]
+                ],
+                [":nbr", "24-28", "19.9"]
+            ]"#;
+
+        let expression = Parser::new().parse_str(pel).unwrap();
+        let result = Runtime::new()
+            .eval(&expression)
+            .unwrap()
+            .complete()
+            .unwrap();
+
+        assert!(result.as_bool().unwrap());
+    }
+
+    #[cfg(feature = "prelude-decimals")]
+    #[test]
+    fn decimal_equals_numeric_string() {
+        // DW: toDecimal("19.90") == " 19.9"
+        let pel = r#"
+            ["==", "0-29",
+                [":apply", "0-20",
+                    [":ref", "0-9", "toDecimal"],
+                    [":str", "10-19", "19.90"]
+                ],
+                [":str", "24-29", " 19.9"]
+            ]"#;
+
+        let expression = Parser::new().parse_str(pel).unwrap();
+        let result = Runtime::new()
+            .eval(&expression)
+            .unwrap()
+            .complete()
+            .unwrap();
+
+        assert!(result.as_bool().unwrap());
+
+        // DW: toDecimal("19.90") == "19.9 EUR"
+        let pel = r#"
+            ["==", "0-32",
+                [":apply", "0-20",
+                    [":ref", "0-9", "toDecimal"],
+                    [":str", "10-19", "19.90"]
+                ],
+                [":str", "24-32", "19.9 EUR"]
+            ]"#;
+
+        let expression = Parser::new().parse_str(pel).unwrap();
+        let result = Runtime::new()
+            .eval(&expression)
+            .unwrap()
+            .complete()
+            .unwrap();
+
+        assert!(!result.as_bool().unwrap());
+    }
+
     #[test]
     fn size_of_array() {
         // DW: sizeOf(["accept", "accept-encoding", "user-agent"])
@@ -1326,6 +1686,7 @@ mod tests {
         assert_eq!(1, result.as_f64().unwrap() as usize);
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn split_by_string_string() {
         // DW: splitBy("Peregrine Expression Language", "re")
@@ -1353,6 +1714,7 @@ mod tests {
         assert_eq!(&expected, result.as_slice().unwrap());
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn split_by_number_string() {
         // DW: splitBy(1946.03, ".")
@@ -1379,6 +1741,7 @@ mod tests {
         assert_eq!(&expected, result.as_slice().unwrap());
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn split_by_boolean_string() {
         // DW: splitBy(true, "r")
@@ -1405,6 +1768,7 @@ mod tests {
         assert_eq!(&expected, result.as_slice().unwrap());
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn split_by_null_string() {
         // DW: splitBy(null, "re")
@@ -1426,6 +1790,7 @@ mod tests {
         assert!(result.is_null());
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn split_by_string_null_faiil() {
         // DW: splitBy("Peregrine", null)
@@ -1443,6 +1808,7 @@ mod tests {
         assert_eq!(error.kind, RuntimeErrorKind::TypeMismatch);
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn split_by_array_fail() {
         // DW: splitBy(["accept", "accept-encoding", "user-agent"], "re")
@@ -1464,6 +1830,7 @@ mod tests {
         assert_eq!(error.kind, RuntimeErrorKind::TypeMismatch);
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn substring_after_null_string() {
         // DW: substringAfter(null, "peregrine")
@@ -1484,6 +1851,7 @@ mod tests {
         assert!(result.is_null());
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn substring_after_string_string() {
         // DW: substringAfter("peregrine", "gr")
@@ -1504,6 +1872,7 @@ mod tests {
         assert_eq!(result.as_str().unwrap(), "ine");
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn substring_after_string_null_fail() {
         // DW: substringAfter("peregrine", null)
@@ -1520,6 +1889,7 @@ mod tests {
         assert_eq!(error.kind, RuntimeErrorKind::TypeMismatch);
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn substring_after_boolean_string() {
         // DW: substringAfter(true, "r")
@@ -1540,6 +1910,7 @@ mod tests {
         assert_eq!(result.as_str().unwrap(), "ue");
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn substring_after_number_number() {
         // DW: substringAfter(1234.567, 4.5)
@@ -1560,6 +1931,7 @@ mod tests {
         assert_eq!(result.as_str().unwrap(), "67");
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn substring_after_array_fail() {
         // DW: substringAfter(["accept", "accept-encoding", "user-agent"], "re")
@@ -1581,6 +1953,7 @@ mod tests {
         assert_eq!(result.kind, RuntimeErrorKind::TypeMismatch);
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn substring_after_last_null_string() {
         // DW: substringAfterLast(null, "peregrine")
@@ -1601,6 +1974,7 @@ mod tests {
         assert!(result.is_null());
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn substring_after_last_string_string() {
         // DW: substringAfterLast("Peregrine Expression Language", "re")
@@ -1621,6 +1995,7 @@ mod tests {
         assert_eq!(result.as_str().unwrap(), "ssion Language");
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn substring_after_last_string_null_fail() {
         // DW: substringAfterLast("peregrine", null)
@@ -1637,6 +2012,7 @@ mod tests {
         assert_eq!(error.kind, RuntimeErrorKind::TypeMismatch);
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn substring_after_last_boolean_string() {
         // DW: substringAfterLast(true, "r")
@@ -1657,6 +2033,7 @@ mod tests {
         assert_eq!(result.as_str().unwrap(), "ue");
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn substring_after_last_number_number() {
         // DW: substringAfterLast(12123512.3512, 35)
@@ -1677,6 +2054,7 @@ mod tests {
         assert_eq!(result.as_str().unwrap(), "12");
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn substring_after_last_array_fail() {
         // DW: substringAfterLast(["accept", "accept-encoding", "user-agent"], "re")
@@ -1698,6 +2076,7 @@ mod tests {
         assert_eq!(result.kind, RuntimeErrorKind::TypeMismatch);
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn substring_before_null_string() {
         // DW: substringBefore(null, "peregrine")
@@ -1718,6 +2097,7 @@ mod tests {
         assert!(result.is_null());
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn substring_before_string_string() {
         // DW: substringBefore("peregrine", "gr")
@@ -1738,6 +2118,7 @@ mod tests {
         assert_eq!(result.as_str().unwrap(), "pere");
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn substring_before_string_null_fail() {
         // DW: substringBefore("peregrine", null)
@@ -1754,6 +2135,7 @@ mod tests {
         assert_eq!(result.kind, RuntimeErrorKind::TypeMismatch);
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn substring_before_boolean_string() {
         // DW: substringBefore(true, "ue")
@@ -1774,6 +2156,7 @@ mod tests {
         assert_eq!(result.as_str().unwrap(), "tr");
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn substring_before_number_number() {
         // DW: substringBefore(1234.56, 4.5)
@@ -1794,6 +2177,7 @@ mod tests {
         assert_eq!(result.as_str().unwrap(), "123");
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn substring_before_array_fail() {
         // DW: substringBefore(["accept", "accept-encoding", "user-agent"], "re")
@@ -1815,6 +2199,7 @@ mod tests {
         assert_eq!(result.kind, RuntimeErrorKind::TypeMismatch);
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn substring_before_last_null_string() {
         // DW: substringBeforeLast(null, "peregrine")
@@ -1835,6 +2220,7 @@ mod tests {
         assert!(result.is_null());
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn substring_before_last_string_string() {
         // DW: substringBeforeLast("Peregrine Expression Language", "re")
@@ -1855,6 +2241,7 @@ mod tests {
         assert_eq!(result.as_str().unwrap(), "Peregrine Exp");
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn substring_before_last_string_null_fail() {
         // DW: substringBeforeLast("peregrine", null)
@@ -1871,6 +2258,7 @@ mod tests {
         assert_eq!(result.kind, RuntimeErrorKind::TypeMismatch);
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn substring_before_last_boolean_string() {
         // DW: substringBeforeLast(true, "ue")
@@ -1891,6 +2279,7 @@ mod tests {
         assert_eq!(result.as_str().unwrap(), "tr");
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn substring_before_last_number_number() {
         // DW: substringBeforeLast(121235.123512, 4.5)
@@ -1911,6 +2300,7 @@ mod tests {
         assert_eq!(result.as_str().unwrap(), "121235.1235");
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn substring_before_last_array_fail() {
         // DW: substringBeforeLast(["accept", "accept-encoding", "user-agent"], "re")
@@ -1932,6 +2322,7 @@ mod tests {
         assert_eq!(result.kind, RuntimeErrorKind::TypeMismatch);
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn upper_null() {
         // DW: upper(null)
@@ -1952,6 +2343,7 @@ mod tests {
         assert!(result.is_null());
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn upper_string() {
         // DW: upper("Peregrine Expression Language")
@@ -1972,6 +2364,7 @@ mod tests {
         assert_eq!("PEREGRINE EXPRESSION LANGUAGE", result.as_str().unwrap());
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn upper_boolean() {
         // DW: upper(true)
@@ -1992,6 +2385,7 @@ mod tests {
         assert_eq!("TRUE", result.as_str().unwrap());
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn upper_number() {
         // DW: upper(123.4)
@@ -2012,6 +2406,7 @@ mod tests {
         assert_eq!("123.4", result.as_str().unwrap());
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn upper_array_fail() {
         // DW: upper(["accept", "accept-encoding", "user-agent"], "re")
@@ -2032,6 +2427,211 @@ mod tests {
         assert_eq!(error.kind, RuntimeErrorKind::TypeMismatch);
     }
 
+    #[test]
+    fn match_range() {
+        // DW: 404 match { case 200 -> "ok" case 400 to 499 -> "client" else -> "server" }
+        let pel = r#"
+            [":match", "0-80",
+                [":nbr", "0-3", "404"],
+                [":case", [":nbr", "17-20", "200"], [":str", "24-28", "ok"]],
+                [":range", [":nbr", "34-37", "400"], [":nbr", "41-44", "499"], [":str", "48-56", "client"]],
+                [":else", [":str", "65-73", "server"]]
+            ]"#;
+
+        let expression = Parser::new().parse_str(pel).unwrap();
+        let result = Runtime::new()
+            .eval(&expression)
+            .unwrap()
+            .complete()
+            .unwrap();
+
+        assert_eq!(result.as_str(), Some("client"));
+    }
+
+    #[test]
+    fn match_literal() {
+        // DW: "200" match { case 200 -> "ok" else -> "error" }
+        let pel = r#"
+            [":match", "0-45",
+                [":str", "0-5", "200"],
+                [":case", [":nbr", "19-22", "200"], [":str", "26-30", "ok"]],
+                [":else", [":str", "36-43", "error"]]
+            ]"#;
+
+        let expression = Parser::new().parse_str(pel).unwrap();
+        let result = Runtime::new()
+            .eval(&expression)
+            .unwrap()
+            .complete()
+            .unwrap();
+
+        // Literals are compared like `==`, without coercion.
+        assert_eq!(result.as_str(), Some("error"));
+    }
+
+    #[test]
+    fn match_regex() {
+        // DW: "/api/v2/orders" match { case matches /^\/api\/v1\// -> "v1" case matches /^\/api\/v2\// -> "v2" }
+        let pel = r#"
+            [":match", "0-90",
+                [":str", "0-16", "/api/v2/orders"],
+                [":regex", "^/api/v1/", [":str", "45-49", "v1"]],
+                [":regex", "^/api/v2/", [":str", "80-84", "v2"]]
+            ]"#;
+
+        let expression = Parser::new().parse_str(pel).unwrap();
+        let result = Runtime::new()
+            .eval(&expression)
+            .unwrap()
+            .complete()
+            .unwrap();
+
+        assert_eq!(result.as_str(), Some("v2"));
+    }
+
+    #[test]
+    fn match_without_matching_case_fail() {
+        // DW: true match { case false -> "off" }
+        let pel = r#"
+            [":match", "0-36",
+                [":bool", "0-4", "true"],
+                [":case", [":bool", "18-23", "false"], [":str", "27-32", "off"]]
+            ]"#;
+
+        let expression = Parser::new().parse_str(pel).unwrap();
+        let error = Runtime::new().eval(&expression).unwrap_err();
+
+        assert_eq!(error.kind, RuntimeErrorKind::NoMatchingCase);
+    }
+
+    #[test]
+    fn match_invalid_regex_fail() {
+        let pel =
+            r#"[":match", "0-10", [":str", "0-1", "a"], [":regex", "(", [":str", "2-3", "b"]]]"#;
+
+        assert!(Parser::new().parse_str(pel).is_err());
+    }
+
+    #[test]
+    fn match_misplaced_default_fail() {
+        let pel = r#"
+            [":match", "0-10",
+                [":str", "0-1", "a"],
+                [":else", [":str", "2-3", "b"]],
+                [":case", [":str", "4-5", "a"], [":str", "6-7", "c"]]
+            ]"#;
+
+        assert!(Parser::new().parse_str(pel).is_err());
+    }
+
+    #[test]
+    fn do_variables() {
+        // DW: do { var a = "ab" var b = a ++ "c" --- b ++ a }
+        let pel = r#"
+            [":do", "0-46",
+                [":var", "a", [":str", "13-17", "ab"]],
+                [":var", "b", [":apply", "26-34",
+                    [":ref", "28-30", "++"],
+                    [":ref", "26-27", "a"],
+                    [":str", "31-34", "c"]
+                ]],
+                [":apply", "39-45",
+                    [":ref", "41-43", "++"],
+                    [":ref", "39-40", "b"],
+                    [":ref", "44-45", "a"]
+                ]
+            ]"#;
+
+        let expression = Parser::new().parse_str(pel).unwrap();
+        let result = Runtime::new()
+            .eval(&expression)
+            .unwrap()
+            .complete()
+            .unwrap();
+
+        assert_eq!(result.as_str(), Some("abcab"));
+    }
+
+    #[test]
+    fn do_variable_shadows_prelude() {
+        // DW: do { var upper = "shadowed" --- upper }
+        let pel = r#"
+            [":do", "0-40",
+                [":var", "upper", [":str", "17-27", "shadowed"]],
+                [":ref", "32-37", "upper"]
+            ]"#;
+
+        let expression = Parser::new().parse_str(pel).unwrap();
+        let result = Runtime::new()
+            .eval(&expression)
+            .unwrap()
+            .complete()
+            .unwrap();
+
+        assert_eq!(result.as_str(), Some("shadowed"));
+    }
+
+    #[test]
+    fn do_bad_formed_variable_fail() {
+        let pel = r#"[":do", "0-10", [":var", [":str", "0-1", "a"]], [":str", "2-3", "b"]]"#;
+
+        assert!(Parser::new().parse_str(pel).is_err());
+        assert!(Parser::new().parse_str(r#"[":do", "0-10"]"#).is_err());
+    }
+
+    #[cfg(feature = "prelude-strings")]
+    #[test]
+    fn eval_with_trace() {
+        // DW: upper(a) == "B"
+        let pel = r#"
+            ["==", "0-15",
+                [":apply", "0-8",
+                    [":ref", "0-5", "upper"],
+                    [":ref", "6-7", "a"]
+                ],
+                [":str", "12-15", "B"]
+            ]"#;
+
+        let mut context = HashMap::new();
+        context.insert("a", Value::string("a".to_string()));
+
+        let expression = Parser::new().parse_str(pel).unwrap();
+        let (result, trace) = Runtime::new().eval_with_trace(&expression, &context);
+
+        assert!(!result.unwrap().complete().unwrap().as_bool().unwrap());
+        assert_eq!(
+            trace.to_string(),
+            "0-5: <function>\n6-7: \"a\"\n0-8: \"A\"\n12-15: \"B\"\n0-15: false\n"
+        );
+    }
+
+    #[cfg(feature = "prelude-strings")]
+    #[test]
+    fn eval_with_trace_records_error_once() {
+        // DW: upper(a) == "A"
+        let pel = r#"
+            ["==", "0-15",
+                [":apply", "0-8",
+                    [":ref", "0-5", "upper"],
+                    [":ref", "6-7", "a"]
+                ],
+                [":str", "12-15", "A"]
+            ]"#;
+
+        let expression = Parser::new().parse_str(pel).unwrap();
+        let (result, trace) = Runtime::new().eval_with_trace(&expression, &EmptyContext);
+
+        assert!(result.is_err());
+        assert_eq!(
+            trace.entries().last().unwrap(),
+            &TraceEntry {
+                location: Location::new(6, 7),
+                outcome: TraceOutcome::Error(RuntimeErrorKind::UnknownSymbol("a".to_string())),
+            }
+        );
+        assert_eq!(trace.entries().len(), 2);
+    }
+
     mod partial_evaluation {
         use std::collections::HashMap;
 
@@ -2263,5 +2863,109 @@ mod tests {
 
             assert_eq!(result.as_str().unwrap(), "ctx1");
         }
+
+        #[test]
+        fn match_pending_case() {
+            let runtime = Runtime::new();
+            let parser = Parser::new();
+
+            let context_1 = TestContextChain::new([("status", Value::number(503.0))])
+                .then([("expected", Value::number(503.0))]);
+
+            // DW: status match { case 200 -> "ok" case expected -> "expected" else -> "other" }
+            let pel_1 = r#"
+                [":match", "0-70",
+                    [":ref", "0-6", "status"],
+                    [":case", [":nbr", "20-23", "200"], [":str", "27-31", "ok"]],
+                    [":case", [":ref", "37-45", "expected"], [":str", "49-59", "expected"]],
+                    [":else", [":str", "68-75", "other"]]
+                ]
+            "#;
+
+            let expression_1 = parser.parse_str(pel_1).unwrap();
+            let expression_2 = runtime
+                .eval_with_context(&expression_1, &context_1)
+                .unwrap()
+                .partial()
+                .unwrap();
+
+            // DW: 503 match { case expected -> "expected" else -> "other" }
+            let pel_2 = r#"
+                [":match", "0-70",
+                    [":nbr", "0-6", "503"],
+                    [":case", [":ref", "37-45", "expected"], [":str", "49-59", "expected"]],
+                    [":else", [":str", "68-75", "other"]]
+                ]
+            "#;
+
+            assert_eq!(expression_2, parser.parse_str(pel_2).unwrap());
+
+            let context_2 = context_1.next();
+            let result = runtime
+                .eval_with_context(&expression_2, &context_2)
+                .unwrap()
+                .complete()
+                .unwrap();
+
+            assert_eq!(result.as_str().unwrap(), "expected");
+        }
+
+        #[test]
+        fn do_pending_variable() {
+            let runtime = Runtime::new();
+            let parser = Parser::new();
+
+            let context_1 = TestContextChain::new([("a", Value::string("ctx1".to_string()))])
+                .then([("b", Value::string("ctx2".to_string()))]);
+
+            // DW: do { var x = a var y = b --- [x, y, y] }
+            let pel_1 = r#"
+                [":do", "0-40",
+                    [":var", "x", [":ref", "13-14", "a"]],
+                    [":var", "y", [":ref", "23-24", "b"]],
+                    [":array", "29-38",
+                        [":ref", "30-31", "x"],
+                        [":ref", "33-34", "y"],
+                        [":ref", "36-37", "y"]
+                    ]
+                ]
+            "#;
+
+            let expression_1 = parser.parse_str(pel_1).unwrap();
+            let expression_2 = runtime
+                .eval_with_context(&expression_1, &context_1)
+                .unwrap()
+                .partial()
+                .unwrap();
+
+            // DW: do { var y = b --- ["ctx1", y, y] }
+            let pel_2 = r#"
+                [":do", "0-40",
+                    [":var", "y", [":ref", "23-24", "b"]],
+                    [":array", "29-38",
+                        [":str", "30-31", "ctx1"],
+                        [":ref", "33-34", "y"],
+                        [":ref", "36-37", "y"]
+                    ]
+                ]
+            "#;
+
+            assert_eq!(expression_2, parser.parse_str(pel_2).unwrap());
+
+            let context_2 = context_1.next();
+            let result = runtime
+                .eval_with_context(&expression_2, &context_2)
+                .unwrap()
+                .complete()
+                .unwrap();
+
+            let result: Vec<_> = result
+                .as_slice()
+                .unwrap()
+                .iter()
+                .map(|value| value.as_str().unwrap())
+                .collect();
+            assert_eq!(result, ["ctx1", "ctx2", "ctx2"]);
+        }
     }
 }
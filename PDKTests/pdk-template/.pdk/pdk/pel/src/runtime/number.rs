@@ -0,0 +1,98 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Conversions between numbers and their textual representation.
+//!
+//! Numbers are parsed and formatted with the `f64` routines of the standard library, or
+//! with integer routines when the `integer-numbers` feature is enabled. The latter keeps the
+//! float parsing and formatting code out of the binary, for policies that never handle
+//! fractional numbers. Fractional numbers still work, with up to 9 fractional digits.
+
+#[cfg(not(feature = "integer-numbers"))]
+pub(crate) fn parse(text: &str) -> Option<f64> {
+    text.parse().ok()
+}
+
+#[cfg(feature = "integer-numbers")]
+pub(crate) fn parse(text: &str) -> Option<f64> {
+    use crate::runtime::decimal::Decimal;
+
+    match text.parse::<i64>() {
+        Ok(integer) => Some(integer as f64),
+        Err(_) => text.parse::<Decimal>().ok().map(|decimal| decimal.to_f64()),
+    }
+}
+
+#[cfg(not(feature = "integer-numbers"))]
+pub(crate) fn format(value: f64) -> String {
+    value.to_string()
+}
+
+#[cfg(feature = "integer-numbers")]
+pub(crate) fn format(value: f64) -> String {
+    use crate::runtime::decimal::Decimal;
+
+    /// The number of fractional digits fractional numbers are formatted with.
+    const SCALE: u32 = 9;
+
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "inf" } else { "-inf" }.to_string()
+    } else if value.fract() != 0.0 {
+        // Every f64 with a fractional part is below 2^52, so the mantissa fits.
+        let mantissa = (value * 10f64.powi(SCALE as i32)).round() as i128;
+        Decimal::new(mantissa, SCALE)
+            .map(|decimal| decimal.normalize().to_string())
+            .unwrap_or_default()
+    } else if value.abs() < 1e38 {
+        (value as i128).to_string()
+    } else {
+        // Beyond the i128 range, only the leading digits are significant.
+        let exponent = value.abs().log10().floor() as i32 - 37;
+        let leading = (value / 10f64.powi(exponent)) as i128;
+        format!("{leading}e{exponent}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format, parse};
+
+    #[test]
+    fn parse_numbers() {
+        assert_eq!(parse("42"), Some(42.0));
+        assert_eq!(parse("-7"), Some(-7.0));
+        assert_eq!(parse("1.5"), Some(1.5));
+        assert_eq!(parse("2e3"), Some(2000.0));
+        assert_eq!(parse("abc"), None);
+    }
+
+    #[test]
+    fn format_numbers() {
+        assert_eq!(format(42.0), "42");
+        assert_eq!(format(-7.0), "-7");
+        assert_eq!(format(1.5), "1.5");
+        assert_eq!(format(0.25), "0.25");
+    }
+
+    #[cfg(feature = "integer-numbers")]
+    #[test]
+    fn parse_integer_numbers() {
+        assert_eq!(parse("9007199254740993"), Some(9007199254740992.0));
+        assert_eq!(parse(".5"), Some(0.5));
+        assert_eq!(parse("-1.25e2"), Some(-125.0));
+        assert_eq!(parse("inf"), None);
+        assert_eq!(parse("NaN"), None);
+    }
+
+    #[cfg(feature = "integer-numbers")]
+    #[test]
+    fn format_integer_numbers() {
+        assert_eq!(format(f64::NAN), "NaN");
+        assert_eq!(format(f64::INFINITY), "inf");
+        assert_eq!(format(f64::NEG_INFINITY), "-inf");
+        assert_eq!(format(-0.5), "-0.5");
+        assert_eq!(format(1e20), "100000000000000000000");
+        assert_eq!(format(1.0 / 3.0), "0.333333333");
+        assert_eq!(format(1e-10), "0");
+    }
+}
@@ -1,4 +1,6 @@
 // Copyright 2023 Salesforce, Inc. All rights reserved.
+#[cfg(feature = "prelude-decimals")]
+use crate::runtime::decimal::{Decimal, MAX_SCALE};
 use crate::{
     runtime::{
         coercion::CoerceArguments, value::Value, Context, Prelude, RuntimeError, RuntimeErrorKind,
@@ -8,13 +10,61 @@ use crate::{
 };
 
 use super::coercion::Coerce;
+#[cfg(feature = "prelude-strings")]
+use super::text;
 
-fn concat(location: Location, _: &dyn Context, arguments: &[Value]) -> Result<Value, RuntimeError> {
-    let (a, b): (String, String) = arguments.coerce_arguments(location)?;
+fn concat(
+    location: Location,
+    context: &dyn Context,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    let arguments = detach_references(context, arguments);
+    let (a, b): (String, String) = arguments.as_slice().coerce_arguments(location)?;
     let b = b.as_str();
     Ok(Value::string(a + b))
 }
 
+// References, like a `payload` given as text, are concatenated as the values they point to.
+fn detach_references(context: &dyn Context, arguments: &[Value]) -> Vec<Value> {
+    arguments
+        .iter()
+        .map(|argument| match argument.as_reference() {
+            Some(_) => argument
+                .to_value_handler(context)
+                .and_then(|handler| handler.detach())
+                .unwrap_or_else(Value::null),
+            None => argument.clone(),
+        })
+        .collect()
+}
+
+#[cfg(feature = "prelude-strings")]
+fn byte_size(
+    location: Location,
+    _: &dyn Context,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    match arguments {
+        [text] if text.is_null() => Ok(Value::null()),
+        _ => {
+            let (text,): (String,) = arguments.coerce_arguments(location)?;
+            Ok(Value::number(text.len() as f64))
+        }
+    }
+}
+
+#[cfg(feature = "prelude-strings")]
+fn byte_substring(
+    location: Location,
+    _: &dyn Context,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    match substring_arguments(location, arguments)? {
+        Some((text, from, until)) => Ok(Value::string(text::byte_substring(&text, from, until))),
+        None => Ok(Value::null()),
+    }
+}
+
 fn contains(
     location: Location,
     _: &dyn Context,
@@ -45,6 +95,90 @@ fn contains(
     }
 }
 
+// `pdk_core::hash::bucket` with a seed of 0, the buckets of the policies. pel stands apart from the
+// pdk crates, so it is restated here, and the hashBucket test of pel-binding checks that both agree.
+#[cfg(feature = "prelude-hash")]
+fn hash_bucket(
+    location: Location,
+    _: &dyn Context,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    if let [key, _] = arguments {
+        if key.is_null() {
+            return Ok(Value::null());
+        }
+    }
+
+    let (key, buckets): (String, f64) = arguments.coerce_arguments(location)?;
+
+    if !buckets.is_finite() || buckets < 1.0 {
+        return Ok(Value::null());
+    }
+
+    let mut hash = key.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+
+    let buckets = buckets.min(u32::MAX as f64) as i64;
+    let mut bucket: i64 = -1;
+    let mut next: i64 = 0;
+    while next < buckets {
+        bucket = next;
+        hash = hash.wrapping_mul(2_862_933_555_777_941_757).wrapping_add(1);
+        next = ((bucket + 1) as f64 * ((1u64 << 31) as f64 / ((hash >> 33) + 1) as f64)) as i64;
+    }
+
+    Ok(Value::number(bucket as f64))
+}
+
+#[cfg(feature = "prelude-decimals")]
+fn is_decimal(
+    location: Location,
+    _: &dyn Context,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    match arguments {
+        [value] => Ok(Value::bool(Coerce::<Decimal>::cast(value).is_some())),
+        [] => Err(RuntimeError {
+            location,
+            kind: RuntimeErrorKind::NotEnoughArguments,
+        }),
+        _ => Err(RuntimeError {
+            location,
+            kind: RuntimeErrorKind::TooManyArguments,
+        }),
+    }
+}
+
+#[cfg(feature = "prelude-decimals")]
+fn to_decimal(
+    location: Location,
+    _: &dyn Context,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    match arguments {
+        [value] | [value, _] if value.is_null() => Ok(Value::null()),
+        [value, scale] => {
+            let value: Decimal = value.coerce(location)?;
+            let scale: f64 = scale.coerce(location)?;
+
+            if !(0.0..=MAX_SCALE as f64).contains(&scale) {
+                return Ok(Value::null());
+            }
+
+            Ok(value
+                .with_scale(scale as u32)
+                .map(Value::decimal)
+                .unwrap_or_else(Value::null))
+        }
+        _ => {
+            let (value,): (Decimal,) = arguments.coerce_arguments(location)?;
+            Ok(Value::decimal(value))
+        }
+    }
+}
+
+#[cfg(feature = "prelude-strings")]
 fn lower(location: Location, _: &dyn Context, arguments: &[Value]) -> Result<Value, RuntimeError> {
     match arguments {
         [text] => {
@@ -66,11 +200,13 @@ fn lower(location: Location, _: &dyn Context, arguments: &[Value]) -> Result<Val
     }
 }
 
+#[cfg(feature = "prelude-strings")]
 fn trim(location: Location, _: &dyn Context, arguments: &[Value]) -> Result<Value, RuntimeError> {
     let (s,): (String,) = arguments.coerce_arguments(location)?;
     Ok(Value::string(s.trim().to_string()))
 }
 
+#[cfg(feature = "prelude-uuid")]
 fn uuid_v4(
     location: Location,
     _: &dyn Context,
@@ -117,6 +253,7 @@ fn size_of(
     Ok(Value::number(size as f64))
 }
 
+#[cfg(feature = "prelude-strings")]
 fn split_by(
     location: Location,
     _: &dyn Context,
@@ -129,10 +266,16 @@ fn split_by(
             } else {
                 let text: String = text.coerce(location)?;
                 let separator: String = separator.coerce(location)?;
-                let s = text
-                    .split(&separator)
-                    .map(|s| Value::string(s.to_string()))
-                    .collect();
+                let s = if separator.is_empty() {
+                    text::units(&text)
+                        .into_iter()
+                        .map(|s| Value::string(s.to_string()))
+                        .collect()
+                } else {
+                    text.split(&separator)
+                        .map(|s| Value::string(s.to_string()))
+                        .collect()
+                };
                 Ok(Value::array(s))
             }
         }
@@ -147,6 +290,58 @@ fn split_by(
     }
 }
 
+#[cfg(feature = "prelude-strings")]
+/// Converts a numeric index to a bound for slicing, clamping negative indexes to 0.
+fn index(location: Location, value: &Value) -> Result<usize, RuntimeError> {
+    let index: f64 = value.coerce(location)?;
+    Ok(if index.is_nan() || index <= 0.0 {
+        0
+    } else {
+        index as usize
+    })
+}
+
+#[cfg(feature = "prelude-strings")]
+fn substring_arguments(
+    location: Location,
+    arguments: &[Value],
+) -> Result<Option<(String, usize, usize)>, RuntimeError> {
+    match arguments {
+        [text, _] | [text, _, _] if text.is_null() => Ok(None),
+        [text, from] => Ok(Some((
+            text.coerce(location)?,
+            index(location, from)?,
+            usize::MAX,
+        ))),
+        [text, from, until] => Ok(Some((
+            text.coerce(location)?,
+            index(location, from)?,
+            index(location, until)?,
+        ))),
+        [] | [_] => Err(RuntimeError {
+            location,
+            kind: RuntimeErrorKind::NotEnoughArguments,
+        }),
+        _ => Err(RuntimeError {
+            location,
+            kind: RuntimeErrorKind::TooManyArguments,
+        }),
+    }
+}
+
+#[cfg(feature = "prelude-strings")]
+fn substring(
+    location: Location,
+    _: &dyn Context,
+    arguments: &[Value],
+) -> Result<Value, RuntimeError> {
+    match substring_arguments(location, arguments)? {
+        Some((text, from, until)) => Ok(Value::string(text::substring(&text, from, until))),
+        None => Ok(Value::null()),
+    }
+}
+
+#[cfg(feature = "prelude-strings")]
 fn substring_after(
     location: Location,
     _: &dyn Context,
@@ -177,6 +372,7 @@ fn substring_after(
     }
 }
 
+#[cfg(feature = "prelude-strings")]
 fn substring_after_last(
     location: Location,
     _: &dyn Context,
@@ -207,6 +403,7 @@ fn substring_after_last(
     }
 }
 
+#[cfg(feature = "prelude-strings")]
 fn substring_before(
     location: Location,
     _: &dyn Context,
@@ -237,6 +434,7 @@ fn substring_before(
     }
 }
 
+#[cfg(feature = "prelude-strings")]
 fn substring_before_last(
     location: Location,
     _: &dyn Context,
@@ -267,6 +465,7 @@ fn substring_before_last(
     }
 }
 
+#[cfg(feature = "prelude-strings")]
 fn upper(location: Location, _: &dyn Context, arguments: &[Value]) -> Result<Value, RuntimeError> {
     match arguments {
         [text] => {
@@ -292,16 +491,37 @@ type PreludeFunction = fn(Location, &dyn Context, &[Value]) -> Result<Value, Run
 
 static PRELUDE: &[(&str, PreludeFunction)] = &[
     ("++", concat),
+    #[cfg(feature = "prelude-strings")]
+    ("byteSize", byte_size),
+    #[cfg(feature = "prelude-strings")]
+    ("byteSubstring", byte_substring),
     ("contains", contains),
+    #[cfg(feature = "prelude-hash")]
+    ("hashBucket", hash_bucket),
+    #[cfg(feature = "prelude-decimals")]
+    ("isDecimal", is_decimal),
+    #[cfg(feature = "prelude-strings")]
     ("lower", lower),
     ("sizeOf", size_of),
+    #[cfg(feature = "prelude-strings")]
     ("splitBy", split_by),
+    #[cfg(feature = "prelude-strings")]
+    ("substring", substring),
+    #[cfg(feature = "prelude-strings")]
     ("substringAfter", substring_after),
+    #[cfg(feature = "prelude-strings")]
     ("substringAfterLast", substring_after_last),
+    #[cfg(feature = "prelude-strings")]
     ("substringBefore", substring_before),
+    #[cfg(feature = "prelude-strings")]
     ("substringBeforeLast", substring_before_last),
+    #[cfg(feature = "prelude-decimals")]
+    ("toDecimal", to_decimal),
+    #[cfg(feature = "prelude-strings")]
     ("trim", trim),
+    #[cfg(feature = "prelude-strings")]
     ("upper", upper),
+    #[cfg(feature = "prelude-uuid")]
     ("uuid", uuid_v4),
 ];
 
@@ -316,7 +536,14 @@ pub fn prelude() -> Prelude {
 mod tests {
     use crate::runtime::{Binding, ValueHandler};
 
-    use super::{concat, split_by, trim, Context, Location, Value};
+    use super::{concat, Context, Location, Value, PRELUDE};
+
+    #[cfg(feature = "prelude-hash")]
+    use super::hash_bucket;
+    #[cfg(feature = "prelude-strings")]
+    use super::{byte_size, byte_substring, split_by, substring, trim};
+    #[cfg(feature = "prelude-decimals")]
+    use super::{is_decimal, to_decimal};
 
     struct TestContext;
 
@@ -337,6 +564,7 @@ mod tests {
 
     const CONTEXT: &dyn Context = &TestContext;
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn trim_string() {
         let result = trim(
@@ -385,6 +613,7 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[cfg(feature = "prelude-strings")]
     #[test]
     fn split_by_strings() {
         let result = split_by(
@@ -403,4 +632,208 @@ mod tests {
         ];
         assert_eq!(expected, result.as_slice().unwrap());
     }
+
+    #[cfg(feature = "prelude-hash")]
+    #[test]
+    fn hash_bucket_in_range() {
+        for i in 0..100 {
+            let result = hash_bucket(
+                LOCATION,
+                CONTEXT,
+                &[Value::string(format!("client-{i}")), Value::number(5.0)],
+            )
+            .unwrap();
+            let bucket = result.as_f64().unwrap();
+            assert!((0.0..5.0).contains(&bucket));
+        }
+    }
+
+    #[cfg(feature = "prelude-hash")]
+    #[test]
+    fn hash_bucket_is_deterministic() {
+        let arguments = &[Value::string("client".to_string()), Value::number(10.0)];
+        let first = hash_bucket(LOCATION, CONTEXT, arguments).unwrap();
+        let second = hash_bucket(LOCATION, CONTEXT, arguments).unwrap();
+        assert_eq!(first, second);
+    }
+
+    #[cfg(feature = "prelude-hash")]
+    #[test]
+    fn hash_bucket_with_no_buckets() {
+        let result = hash_bucket(
+            LOCATION,
+            CONTEXT,
+            &[Value::string("client".to_string()), Value::number(0.0)],
+        )
+        .unwrap();
+        assert!(result.is_null());
+    }
+
+    #[cfg(feature = "prelude-strings")]
+    #[test]
+    fn split_by_empty_separator() {
+        let result = split_by(
+            LOCATION,
+            CONTEXT,
+            &[
+                Value::string("año".to_string()),
+                Value::string("".to_string()),
+            ],
+        )
+        .unwrap();
+
+        let expected = &[
+            Value::string("a".to_string()),
+            Value::string("ñ".to_string()),
+            Value::string("o".to_string()),
+        ];
+        assert_eq!(expected, result.as_slice().unwrap());
+    }
+
+    #[cfg(feature = "prelude-strings")]
+    #[test]
+    fn substring_by_chars() {
+        let text = Value::string("¿Qué tal?".to_string());
+
+        let result = substring(
+            LOCATION,
+            CONTEXT,
+            &[text.clone(), Value::number(1.0), Value::number(4.0)],
+        )
+        .unwrap();
+        assert_eq!(result.as_str(), Some("Qué"));
+
+        let result = substring(LOCATION, CONTEXT, &[text, Value::number(5.0)]).unwrap();
+        assert_eq!(result.as_str(), Some("tal?"));
+    }
+
+    #[cfg(feature = "prelude-strings")]
+    #[test]
+    fn substring_clamps_bounds() {
+        let text = Value::string("año".to_string());
+
+        let result = substring(
+            LOCATION,
+            CONTEXT,
+            &[text.clone(), Value::number(-3.0), Value::number(100.0)],
+        )
+        .unwrap();
+        assert_eq!(result.as_str(), Some("año"));
+
+        let result = substring(
+            LOCATION,
+            CONTEXT,
+            &[text, Value::number(2.0), Value::number(1.0)],
+        )
+        .unwrap();
+        assert_eq!(result.as_str(), Some(""));
+
+        let result = substring(LOCATION, CONTEXT, &[Value::null(), Value::number(1.0)]).unwrap();
+        assert!(result.is_null());
+    }
+
+    #[cfg(feature = "prelude-strings")]
+    #[test]
+    fn byte_variants() {
+        let text = Value::string("año".to_string());
+
+        let result = byte_size(LOCATION, CONTEXT, std::slice::from_ref(&text)).unwrap();
+        assert_eq!(result.as_f64(), Some(4.0));
+
+        let result = byte_substring(
+            LOCATION,
+            CONTEXT,
+            &[text, Value::number(0.0), Value::number(2.0)],
+        )
+        .unwrap();
+        assert_eq!(result.as_str(), Some("a\u{fffd}"));
+    }
+
+    #[cfg(feature = "prelude-decimals")]
+    #[test]
+    fn to_decimal_keeps_digits() {
+        let result = to_decimal(
+            LOCATION,
+            CONTEXT,
+            &[Value::string("0.1234567890123456789".to_string())],
+        );
+        assert!(result.is_err());
+
+        let result = to_decimal(
+            LOCATION,
+            CONTEXT,
+            &[Value::string("12345678901234567.89".to_string())],
+        )
+        .unwrap();
+        assert_eq!(
+            result.as_decimal().unwrap().to_string(),
+            "12345678901234567.89"
+        );
+
+        let result = to_decimal(LOCATION, CONTEXT, &[Value::number(0.1)]).unwrap();
+        assert_eq!(result.as_decimal().unwrap().to_string(), "0.1");
+    }
+
+    #[cfg(feature = "prelude-decimals")]
+    #[test]
+    fn to_decimal_with_scale() {
+        let result = to_decimal(
+            LOCATION,
+            CONTEXT,
+            &[Value::string("19.9".to_string()), Value::number(2.0)],
+        )
+        .unwrap();
+        assert_eq!(result.as_decimal().unwrap().to_string(), "19.90");
+
+        let result = to_decimal(
+            LOCATION,
+            CONTEXT,
+            &[Value::string("2.345".to_string()), Value::number(2.0)],
+        )
+        .unwrap();
+        assert_eq!(result.as_decimal().unwrap().to_string(), "2.35");
+
+        let result = to_decimal(
+            LOCATION,
+            CONTEXT,
+            &[Value::number(1.0), Value::number(-1.0)],
+        )
+        .unwrap();
+        assert!(result.is_null());
+
+        let result = to_decimal(LOCATION, CONTEXT, &[Value::null()]).unwrap();
+        assert!(result.is_null());
+    }
+
+    #[cfg(feature = "prelude-decimals")]
+    #[test]
+    fn is_decimal_value() {
+        for (value, expected) in [
+            (Value::string(" 10.50 ".to_string()), true),
+            (Value::number(3.0), true),
+            (Value::string("10,50".to_string()), false),
+            (Value::bool(true), false),
+            (Value::null(), false),
+        ] {
+            let result = is_decimal(LOCATION, CONTEXT, &[value]).unwrap();
+            assert_eq!(result.as_bool(), Some(expected));
+        }
+    }
+
+    #[test]
+    fn prelude_holds_the_enabled_groups() {
+        let holds = |name: &str| PRELUDE.iter().any(|(key, _)| *key == name);
+
+        for name in ["++", "contains", "sizeOf"] {
+            assert!(holds(name), "{}", name);
+        }
+        for name in ["isDecimal", "toDecimal"] {
+            assert_eq!(holds(name), cfg!(feature = "prelude-decimals"), "{}", name);
+        }
+        assert_eq!(holds("hashBucket"), cfg!(feature = "prelude-hash"));
+        for name in ["byteSize", "lower", "splitBy", "trim", "upper"] {
+            assert_eq!(holds(name), cfg!(feature = "prelude-strings"), "{}", name);
+        }
+        assert_eq!(holds("uuid"), cfg!(feature = "prelude-uuid"));
+    }
 }
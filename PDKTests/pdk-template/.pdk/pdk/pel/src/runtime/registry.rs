@@ -0,0 +1,188 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Extension of the prelude with functions provided by policies.
+
+use thiserror::Error;
+
+use crate::{
+    expression::Symbol,
+    runtime::{
+        prelude::prelude, value::Value, Binding, Context, Prelude, RuntimeError, RuntimeErrorKind,
+        ValueHandler,
+    },
+    Location, Reference,
+};
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum RegistryError {
+    #[error("Function `{0}` is already registered")]
+    Collision(String),
+
+    #[error("Invalid function name `{0}`")]
+    InvalidName(String),
+}
+
+/// The functions available to expressions: the prelude plus the registered ones.
+/// It is meant to be used with [`Runtime::with_prelude`](super::Runtime::with_prelude).
+pub struct FunctionRegistry {
+    functions: Prelude,
+    reserved: Vec<&'static str>,
+}
+
+impl FunctionRegistry {
+    /// Creates a registry with the prelude functions.
+    pub fn new() -> Self {
+        Self {
+            functions: prelude(),
+            reserved: Vec::new(),
+        }
+    }
+
+    /// Reserves names, like the ones of the variables provided by a context, so that no
+    /// function registered with them gets shadowed.
+    pub fn with_reserved(mut self, names: &[&'static str]) -> Self {
+        self.reserved.extend_from_slice(names);
+        self
+    }
+
+    /// Registers a function taking exactly `arity` arguments. Calls with a different number
+    /// of arguments fail without invoking `function`.
+    pub fn register<F>(
+        &mut self,
+        name: &'static str,
+        arity: usize,
+        function: F,
+    ) -> Result<(), RegistryError>
+    where
+        F: 'static + Fn(Location, &dyn Context, &[Value]) -> Result<Value, RuntimeError>,
+    {
+        let valid = name.chars().next().map_or(false, char::is_alphabetic)
+            && name.chars().all(|c| c.is_alphanumeric() || c == '_');
+        if !valid {
+            return Err(RegistryError::InvalidName(name.to_string()));
+        }
+        if self.contains(name) {
+            return Err(RegistryError::Collision(name.to_string()));
+        }
+
+        let function = Value::function_from_fn(move |location, context, arguments| {
+            let kind = match arguments.len() {
+                n if n < arity => RuntimeErrorKind::NotEnoughArguments,
+                n if n > arity => RuntimeErrorKind::TooManyArguments,
+                _ => return function(location, context, arguments),
+            };
+            Err(RuntimeError::new(location, kind))
+        });
+        self.functions.insert(name, function);
+        Ok(())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.functions.contains_key(name) || self.reserved.contains(&name)
+    }
+}
+
+impl Default for FunctionRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Context for FunctionRegistry {
+    fn resolve(&self, symbol: &Symbol) -> Binding {
+        self.functions.resolve(symbol)
+    }
+
+    fn value_handler(&self, _reference: Reference) -> Option<&dyn ValueHandler> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        parser::Parser,
+        runtime::{value::Value, Context, Runtime, RuntimeErrorKind},
+        Location,
+    };
+
+    use super::{FunctionRegistry, RegistryError};
+
+    fn registry() -> FunctionRegistry {
+        let mut registry = FunctionRegistry::new().with_reserved(&["payload"]);
+        registry
+            .register("double", 1, |_, _, arguments| {
+                Ok(Value::number(
+                    arguments[0].as_f64().unwrap_or_default() * 2.0,
+                ))
+            })
+            .unwrap();
+        registry
+    }
+
+    #[test]
+    fn eval_registered_function() {
+        // DW: double(21)
+        let pel = r#"[":apply", "0-9", [":ref", "0-6", "double"], [":nbr", "7-8", "21"]]"#;
+
+        let expression = Parser::new().parse_str(pel).unwrap();
+        let result = Runtime::with_prelude(registry())
+            .eval(&expression)
+            .unwrap()
+            .complete()
+            .unwrap();
+
+        assert_eq!(result.as_f64(), Some(42.0));
+    }
+
+    #[cfg(feature = "prelude-strings")]
+    #[test]
+    fn prelude_is_available() {
+        // DW: upper("a")
+        let pel = r#"[":apply", "0-10", [":ref", "0-5", "upper"], [":str", "6-9", "a"]]"#;
+
+        let expression = Parser::new().parse_str(pel).unwrap();
+        let result = Runtime::with_prelude(registry())
+            .eval(&expression)
+            .unwrap()
+            .complete()
+            .unwrap();
+
+        assert_eq!(result.as_str(), Some("A"));
+    }
+
+    #[test]
+    fn arity_is_checked() {
+        // DW: double()
+        let pel = r#"[":apply", "0-8", [":ref", "0-6", "double"]]"#;
+
+        let expression = Parser::new().parse_str(pel).unwrap();
+        let error = Runtime::with_prelude(registry())
+            .eval(&expression)
+            .unwrap_err();
+
+        assert_eq!(error.kind(), &RuntimeErrorKind::NotEnoughArguments);
+    }
+
+    #[test]
+    fn collisions_are_rejected() {
+        let mut registry = registry();
+        let function = |_: Location, _: &dyn Context, _: &[Value]| Ok(Value::null());
+
+        assert_eq!(
+            registry.register("sizeOf", 1, function),
+            Err(RegistryError::Collision("sizeOf".to_string()))
+        );
+        assert_eq!(
+            registry.register("double", 2, function),
+            Err(RegistryError::Collision("double".to_string()))
+        );
+        assert_eq!(
+            registry.register("payload", 0, function),
+            Err(RegistryError::Collision("payload".to_string()))
+        );
+        assert_eq!(
+            registry.register("sign-context", 0, function),
+            Err(RegistryError::InvalidName("sign-context".to_string()))
+        );
+    }
+}
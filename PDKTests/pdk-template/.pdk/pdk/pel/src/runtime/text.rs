@@ -0,0 +1,64 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! The units strings are indexed and measured by.
+//!
+//! Strings are handled as sequences of chars, or of extended grapheme clusters when the
+//! `graphemes` feature is enabled, so they are never split inside a multi-byte character.
+
+#[cfg(not(feature = "graphemes"))]
+pub(crate) fn units(text: &str) -> Vec<&str> {
+    text.char_indices()
+        .map(|(index, c)| &text[index..index + c.len_utf8()])
+        .collect()
+}
+
+#[cfg(feature = "graphemes")]
+pub(crate) fn units(text: &str) -> Vec<&str> {
+    use unicode_segmentation::UnicodeSegmentation;
+
+    text.graphemes(true).collect()
+}
+
+/// Returns the number of units of `text`.
+pub(crate) fn length(text: &str) -> usize {
+    units(text).len()
+}
+
+/// Returns the units of `text` in `from..until`, clamping both bounds to the text.
+#[cfg_attr(not(feature = "prelude-strings"), allow(dead_code))]
+pub(crate) fn substring(text: &str, from: usize, until: usize) -> String {
+    let units = units(text);
+    let until = until.min(units.len());
+    let from = from.min(until);
+    units[from..until].concat()
+}
+
+/// Returns the UTF-8 bytes of `text` in `from..until`, clamping both bounds to the text.
+/// Characters cut by the bounds are replaced by U+FFFD.
+#[cfg_attr(not(feature = "prelude-strings"), allow(dead_code))]
+pub(crate) fn byte_substring(text: &str, from: usize, until: usize) -> String {
+    let bytes = text.as_bytes();
+    let until = until.min(bytes.len());
+    let from = from.min(until);
+    String::from_utf8_lossy(&bytes[from..until]).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{byte_substring, length, substring};
+
+    #[test]
+    fn substring_by_chars() {
+        assert_eq!(substring("añoß", 1, 3), "ño");
+        assert_eq!(substring("añoß", 2, 100), "oß");
+        assert_eq!(substring("añoß", 3, 1), "");
+        assert_eq!(length("añoß"), 4);
+    }
+
+    #[test]
+    fn byte_substring_is_lossy() {
+        assert_eq!(byte_substring("año", 0, 1), "a");
+        assert_eq!(byte_substring("año", 0, 2), "a\u{fffd}");
+        assert_eq!(byte_substring("año", 1, 3), "ñ");
+        assert_eq!(byte_substring("año", 5, 10), "");
+    }
+}
@@ -0,0 +1,165 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Records the result of evaluating every node of an expression, to find out why an
+//! expression yielded an unexpected value.
+
+use std::{cell::RefCell, fmt};
+
+use crate::{
+    expression::Symbol,
+    runtime::{
+        value::{InternalValue, Value},
+        Binding, Context, Evaluation, RuntimeError, RuntimeErrorKind, ValueHandler,
+    },
+    Location, Reference,
+};
+
+/// The maximum number of characters of a string, or of items of an array or object, that are
+/// displayed for a traced value.
+const DISPLAY_LIMIT: usize = 32;
+
+/// What a node of the expression evaluated to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TraceOutcome {
+    /// The node was completely evaluated. References are resolved to their values.
+    Value(Value),
+    /// The node depends on data that is not available yet.
+    Partial,
+    /// The evaluation failed at this node.
+    Error(RuntimeErrorKind),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEntry {
+    pub location: Location,
+    pub outcome: TraceOutcome,
+}
+
+/// The entries of an evaluation, in the order the nodes finished, so inner nodes come
+/// before the nodes containing them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Trace {
+    entries: Vec<TraceEntry>,
+}
+
+impl Trace {
+    pub fn entries(&self) -> &[TraceEntry] {
+        &self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in &self.entries {
+            write!(f, "{}-{}: ", entry.location.start, entry.location.end)?;
+            match &entry.outcome {
+                TraceOutcome::Value(value) => write_value(f, value)?,
+                TraceOutcome::Partial => f.write_str("<pending>")?,
+                TraceOutcome::Error(kind) => write!(f, "<error: {kind}>")?,
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_value(f: &mut fmt::Formatter<'_>, value: &Value) -> fmt::Result {
+    match &value.internal {
+        InternalValue::Null => f.write_str("null"),
+        InternalValue::Bool(b) => write!(f, "{b}"),
+        InternalValue::Number(n) => f.write_str(n.representation()),
+        InternalValue::Decimal(d) => write!(f, "{d}"),
+        InternalValue::String(s) if s.chars().count() > DISPLAY_LIMIT => {
+            let prefix: String = s.chars().take(DISPLAY_LIMIT).collect();
+            write!(f, "{prefix:?}...")
+        }
+        InternalValue::String(s) => write!(f, "{s:?}"),
+        InternalValue::Array(array) => {
+            f.write_str("[")?;
+            for (index, item) in array.iter().take(DISPLAY_LIMIT).enumerate() {
+                if index > 0 {
+                    f.write_str(", ")?;
+                }
+                write_value(f, item)?;
+            }
+            if array.len() > DISPLAY_LIMIT {
+                f.write_str(", ...")?;
+            }
+            f.write_str("]")
+        }
+        InternalValue::Object(object) => {
+            // Keys are sorted to get a stable output.
+            let mut entries: Vec<_> = object.iter().collect();
+            entries.sort_by_key(|(key, _)| *key);
+
+            f.write_str("{")?;
+            for (index, (key, item)) in entries.iter().take(DISPLAY_LIMIT).enumerate() {
+                if index > 0 {
+                    f.write_str(", ")?;
+                }
+                write!(f, "{key:?}: ")?;
+                write_value(f, item)?;
+            }
+            if entries.len() > DISPLAY_LIMIT {
+                f.write_str(", ...")?;
+            }
+            f.write_str("}")
+        }
+        InternalValue::Function(_) => f.write_str("<function>"),
+        InternalValue::Reference(_) => f.write_str("<reference>"),
+    }
+}
+
+/// A context that records the evaluation of every node while delegating to another context.
+pub(super) struct Tracer<'a> {
+    inner: &'a dyn Context,
+    entries: RefCell<Vec<TraceEntry>>,
+}
+
+impl<'a> Tracer<'a> {
+    pub(super) fn new(inner: &'a dyn Context) -> Self {
+        Self {
+            inner,
+            entries: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub(super) fn into_trace(self) -> Trace {
+        Trace {
+            entries: self.entries.into_inner(),
+        }
+    }
+}
+
+impl Context for Tracer<'_> {
+    fn resolve(&self, symbol: &Symbol) -> Binding {
+        self.inner.resolve(symbol)
+    }
+
+    fn value_handler(&self, reference: Reference) -> Option<&dyn ValueHandler> {
+        self.inner.value_handler(reference)
+    }
+
+    fn trace(&self, location: Location, result: Result<&Evaluation, &RuntimeError>) {
+        let outcome = match result {
+            Ok(Evaluation::Complete(_, value)) => TraceOutcome::Value(
+                value
+                    .to_value_handler(self)
+                    .and_then(|handler| handler.detach())
+                    .unwrap_or_else(|| value.clone()),
+            ),
+            Ok(Evaluation::Partial(_)) => TraceOutcome::Partial,
+            // Errors are only recorded by the node that raised them.
+            Err(error) if error.location() == location => TraceOutcome::Error(error.kind().clone()),
+            Err(_) => return,
+        };
+
+        self.entries
+            .borrow_mut()
+            .push(TraceEntry { location, outcome });
+        self.inner.trace(location, result);
+    }
+}
@@ -2,7 +2,7 @@
 use std::{collections::HashMap, fmt::Debug, rc::Rc};
 
 use crate::{
-    runtime::{Context, RuntimeError},
+    runtime::{decimal::Decimal, number, Context, RuntimeError},
     Location, Reference,
 };
 
@@ -72,6 +72,7 @@ pub(super) enum InternalValue {
     Null,
     Bool(bool),
     Number(RuntimeNumber),
+    Decimal(Decimal),
     String(String),
     Array(Rc<Array>),
     Object(Rc<Object>),
@@ -99,7 +100,13 @@ impl Value {
 
     pub fn number(value: f64) -> Self {
         // TODO: AGW-5356 - Improve number coercion
-        Self::number_with_representation(value, value.to_string())
+        Self::number_with_representation(value, number::format(value))
+    }
+
+    /// Integers keep their integer semantics: they compare equal to numbers of the same value,
+    /// and are represented without a fractional part.
+    pub fn integer(value: i64) -> Self {
+        Self::number_with_representation(value as f64, value.to_string())
     }
 
     pub(crate) fn number_with_representation(value: f64, representation: String) -> Self {
@@ -111,6 +118,12 @@ impl Value {
         }
     }
 
+    pub fn decimal(d: Decimal) -> Self {
+        Self {
+            internal: InternalValue::Decimal(d),
+        }
+    }
+
     pub fn string(s: String) -> Self {
         Self {
             internal: InternalValue::String(s),
@@ -162,9 +175,26 @@ impl Value {
         }
     }
 
+    /// Returns the value of numbers, and the closest `f64` to decimals.
     pub fn as_f64(&self) -> Option<f64> {
         match &self.internal {
             InternalValue::Number(n) => Some(n.value),
+            InternalValue::Decimal(d) => Some(d.to_f64()),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of numbers without a fractional part that fit in an `i64`.
+    pub fn as_i64(&self) -> Option<i64> {
+        match &self.internal {
+            InternalValue::Number(n) => integral(n.value),
+            _ => None,
+        }
+    }
+
+    pub fn as_decimal(&self) -> Option<Decimal> {
+        match &self.internal {
+            InternalValue::Decimal(d) => Some(*d),
             _ => None,
         }
     }
@@ -211,3 +241,10 @@ impl Value {
         }
     }
 }
+
+/// The `i64` of the same value, when `value` has no fractional part and is within range.
+fn integral(value: f64) -> Option<i64> {
+    // i64::MAX is not representable as an f64, so the upper bound is exclusive.
+    let in_range = value >= i64::MIN as f64 && value < i64::MAX as f64;
+    (value.fract() == 0.0 && in_range).then_some(value as i64)
+}
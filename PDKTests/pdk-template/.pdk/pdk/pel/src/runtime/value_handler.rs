@@ -1,5 +1,6 @@
 // Copyright 2023 Salesforce, Inc. All rights reserved.
 use super::{
+    text,
     value::{Array, InternalValue, Object, Value},
     Context,
 };
@@ -99,15 +100,15 @@ impl ValueHandler for String {
 
     fn select_by_index(&self, index: usize) -> Option<Value> {
         Some(
-            self.chars()
-                .nth(index)
-                .map(|c| Value::string(c.to_string()))
+            text::units(self)
+                .get(index)
+                .map(|unit| Value::string(unit.to_string()))
                 .unwrap_or_else(Value::null),
         )
     }
 
     fn size(&self) -> Option<usize> {
-        Some(self.len())
+        Some(text::length(self))
     }
 }
 
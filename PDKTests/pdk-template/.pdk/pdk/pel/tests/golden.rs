@@ -0,0 +1,159 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Evaluates the cases of the `tests/golden` files. Every file holds an array of cases:
+//!
+//! ```json
+//! {
+//!     "name": "rate limit tier",
+//!     "dw": "if (vars.tier == \"gold\") 100 else 10",
+//!     "expression": [":if", "0-37", ...],
+//!     "vars": {"tier": "gold"},
+//!     "requires": "prelude-strings",
+//!     "expected": 100
+//! }
+//! ```
+//!
+//! `dw` is the source of the expression, for reference only. `vars` are available under the
+//! `vars` symbol, as the policies see them. `requires` names the feature a case needs, the
+//! case is skipped without it. Instead of `expected`, a case can expect the `error` message of
+//! a failed parsing or evaluation.
+//!
+//! pel-binding evaluates the same files on its request context.
+use std::fs;
+
+use pel::{
+    expression::Symbol,
+    parser::Parser,
+    runtime::{value::Value, Binding, Context, Runtime, ValueHandler},
+    Reference,
+};
+
+const GOLDEN_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden");
+
+struct VarsContext {
+    vars: Value,
+}
+
+impl Context for VarsContext {
+    fn resolve(&self, symbol: &Symbol) -> Binding {
+        match symbol.as_str() {
+            "vars" => Binding::Available(self.vars.clone()),
+            _ => Binding::Unknown,
+        }
+    }
+
+    fn value_handler(&self, _reference: Reference) -> Option<&dyn ValueHandler> {
+        None
+    }
+}
+
+fn to_value(json: &serde_json::Value) -> Value {
+    match json {
+        serde_json::Value::Null => Value::null(),
+        serde_json::Value::Bool(b) => Value::bool(*b),
+        serde_json::Value::Number(n) => Value::number(n.as_f64().unwrap_or_default()),
+        serde_json::Value::String(s) => Value::string(s.clone()),
+        serde_json::Value::Array(a) => Value::array(a.iter().map(to_value).collect()),
+        serde_json::Value::Object(o) => {
+            Value::object(o.iter().map(|(k, v)| (k.clone(), to_value(v))).collect())
+        }
+    }
+}
+
+// Numbers and decimals match by their f64 value.
+fn matches(actual: &Value, expected: &serde_json::Value) -> bool {
+    match expected {
+        serde_json::Value::Null => actual.is_null(),
+        serde_json::Value::Bool(b) => actual.as_bool() == Some(*b),
+        serde_json::Value::Number(n) => actual.as_f64() == n.as_f64(),
+        serde_json::Value::String(s) => actual.as_str() == Some(s.as_str()),
+        serde_json::Value::Array(expected) => actual.as_slice().map_or(false, |actual| {
+            actual.len() == expected.len()
+                && actual.iter().zip(expected).all(|(a, e)| matches(a, e))
+        }),
+        serde_json::Value::Object(expected) => actual.as_object().map_or(false, |actual| {
+            actual.len() == expected.len()
+                && expected
+                    .iter()
+                    .all(|(k, e)| actual.get(k).map_or(false, |a| matches(a, e)))
+        }),
+    }
+}
+
+// The arms are all true when every feature is enabled, which does not make it a `matches!`.
+#[allow(clippy::match_like_matches_macro)]
+fn feature_enabled(feature: &str) -> bool {
+    match feature {
+        "prelude-decimals" => cfg!(feature = "prelude-decimals"),
+        "prelude-hash" => cfg!(feature = "prelude-hash"),
+        "prelude-strings" => cfg!(feature = "prelude-strings"),
+        "prelude-uuid" => cfg!(feature = "prelude-uuid"),
+        "graphemes" => cfg!(feature = "graphemes"),
+        "integer-numbers" => cfg!(feature = "integer-numbers"),
+        _ => false,
+    }
+}
+
+// Returns the failure of the case, if any.
+fn check(parser: &Parser, runtime: &Runtime, case: &serde_json::Value) -> Option<String> {
+    let vars = case
+        .get("vars")
+        .map(to_value)
+        .unwrap_or_else(|| Value::object(Default::default()));
+    let context = VarsContext { vars };
+
+    let result = parser
+        .parse_str(&case["expression"].to_string())
+        .map_err(|e| e.to_string())
+        .and_then(|expression| {
+            runtime
+                .eval_with_context(&expression, &context)
+                .map_err(|e| e.kind().to_string())
+        })
+        .map(|evaluation| evaluation.complete());
+
+    match (result, case.get("expected"), case.get("error")) {
+        (Ok(Some(actual)), Some(expected), None) if matches(&actual, expected) => None,
+        (Err(actual), None, Some(expected)) if expected.as_str() == Some(actual.as_str()) => None,
+        (actual, expected, error) => Some(format!(
+            "expected {:?}, error {:?}, got {:?}",
+            expected, error, actual
+        )),
+    }
+}
+
+#[test]
+fn golden_files() {
+    let parser = Parser::new();
+    let runtime = Runtime::new();
+    let mut evaluated = 0;
+    let mut failures = vec![];
+
+    let mut files: Vec<_> = fs::read_dir(GOLDEN_DIR)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |e| e == "json"))
+        .collect();
+    files.sort();
+
+    for file in &files {
+        let content = fs::read_to_string(file).unwrap();
+        let cases: Vec<serde_json::Value> = serde_json::from_str(&content)
+            .unwrap_or_else(|e| panic!("Bad formed golden file {}: {}", file.display(), e));
+
+        for case in &cases {
+            let required = case.get("requires").and_then(serde_json::Value::as_str);
+            if !required.map_or(true, feature_enabled) {
+                continue;
+            }
+
+            evaluated += 1;
+            if let Some(failure) = check(&parser, &runtime, case) {
+                let name = file.file_name().unwrap().to_string_lossy();
+                failures.push(format!("{} / {}: {}", name, case["name"], failure));
+            }
+        }
+    }
+
+    assert!(evaluated > 0, "No golden case evaluated");
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
@@ -0,0 +1,44 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use pdk::api::expression::Expression;
+use pdk::api::policy_context::metadata::Tier;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+pub struct PolicyConfiguration {
+    /// What the requests are counted by, like `#[authentication.clientId]`. All the requests
+    /// share the same counters without it. Every value gets counters that are never freed.
+    #[serde(default)]
+    pub key: Option<Expression>,
+    /// The limits of the requests without an SLA tier, each one a `maximumRequests` over a
+    /// `timePeriodInMilliseconds`.
+    #[serde(alias = "rateLimits", default)]
+    pub rate_limits: Vec<Tier>,
+    /// The id of the SLA tier of the request, like `#[authentication.properties.tier]`. The
+    /// limits of the tier of the API apply instead of the rate limits.
+    #[serde(alias = "slaTier", default)]
+    pub sla_tier: Option<Expression>,
+    /// The buckets each window is split into, at most 60. More buckets slide the windows more
+    /// smoothly, but take more shared data.
+    #[serde(default = "default_buckets")]
+    pub buckets: usize,
+    /// The path the gateway answers with the counters and limits of every key, like
+    /// `/.well-known/rate-limits`, never reaching the upstream. No report without it.
+    #[serde(alias = "reportPath", default)]
+    pub report_path: Option<String>,
+    /// The token the requests to the report must send in the `x-rate-limit-report-token`
+    /// header, required with a `report_path`.
+    #[serde(alias = "reportToken", default)]
+    pub report_token: Option<String>,
+    /// The keys in the report, at most. The keys counted once as many are reported are left
+    /// out of it.
+    #[serde(alias = "maxReportedKeys", default = "default_max_reported_keys")]
+    pub max_reported_keys: usize,
+}
+
+fn default_buckets() -> usize {
+    10
+}
+
+fn default_max_reported_keys() -> usize {
+    1000
+}
@@ -0,0 +1,532 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use crate::config::PolicyConfiguration;
+use crate::report::{KeyIndex, KeyReport, LimitReport, Report};
+use pdk::api::classy::bootstrap::Launcher;
+use pdk::api::classy::event::{EventData, Exchange, HeadersAccessor, RequestHeaders};
+use pdk::api::classy::{Configuration, Host};
+use pdk::api::error::{codes, PolicyError};
+use pdk::api::expression::Expression;
+use pdk::api::logger::{debug, info, warn};
+use pdk::api::metrics::MetricsAccessor;
+use pdk::api::policy_context::decisions::{Decision, Outcome};
+use pdk::api::policy_context::metadata::Tier;
+use pdk::api::policy_context::PolicyContext;
+use pdk::api::property::{record_decision, retry_after_seconds};
+use pdk::api::shared_data::SharedDataAccessor;
+use pdk::api::window::SharedSlidingWindowCounter;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, SystemTime};
+
+mod config;
+mod report;
+
+const POLICY_NAME: &str = "rate-limiting";
+const DECISION_KIND: &str = "rateLimit";
+const LIMITED_COUNTER: &str = "rate_limited_requests";
+
+const TOO_MANY_REQUESTS: u32 = 429;
+const LIMITED_BODY: &[u8] = br#"{"error":"Too many requests"}"#;
+
+const REPORT_TOKEN_HEADER: &str = "x-rate-limit-report-token";
+const NOT_FOUND: u32 = 404;
+
+/// The tier of the requests limited by the rate limits of the configuration.
+const DEFAULT_TIER: &str = "default";
+
+/// The most buckets a window is split into, as every bucket is in the counters of every key.
+const MAX_BUCKETS: usize = 60;
+
+/// What the limiter decided for a request.
+#[derive(Debug)]
+enum Verdict {
+    /// The request is counted and goes on.
+    Allowed,
+    /// The request is over the `limit` of its window, which makes room for it after
+    /// `retry_after`.
+    Limited { limit: u64, retry_after: Duration },
+    /// No limit applies to the request.
+    Unlimited,
+}
+
+/// The limits of the SLA tiers of the API, and the ones of the configuration for the requests
+/// without a tier.
+struct Limiter {
+    prefix: String,
+    rate_limits: Vec<Tier>,
+    tiers: HashMap<String, Vec<Tier>>,
+    buckets: usize,
+    /// The index of the keys counted, only kept when they are reported.
+    index: Option<KeyIndex>,
+}
+
+impl Limiter {
+    /// The tier of the request and its limits, none when it is not limited.
+    fn limits(&self, tier: Option<&str>) -> Option<(&str, &[Tier])> {
+        match tier.and_then(|tier| self.tiers.get_key_value(tier)) {
+            Some((tier, limits)) => Some((tier.as_str(), limits.as_slice())),
+            None if self.rate_limits.is_empty() => None,
+            None => Some((DEFAULT_TIER, self.rate_limits.as_slice())),
+        }
+    }
+
+    /// Counts the request on the windows of every limit of its tier, unless it is over one of
+    /// them. The requests over a limit are not counted, so the clients retrying are not kept out
+    /// past the window. Workers counting at the same time can let a few more requests through.
+    ///
+    /// Every key counted gets a counter per limit of its tier in the shared data, which is never
+    /// freed, so the keys must be bounded, like the client ids of an authentication policy.
+    fn check(
+        &self,
+        shared_data: &dyn SharedDataAccessor,
+        tier: Option<&str>,
+        key: &str,
+        now: SystemTime,
+    ) -> Verdict {
+        let (tier, limits) = match self.limits(tier) {
+            Some(limits) => limits,
+            None => return Verdict::Unlimited,
+        };
+
+        let counters = self.counters(shared_data, tier, limits, key);
+        for (limit, counter) in &counters {
+            let snapshot = counter.snapshot();
+            if snapshot.count(now) >= limit.requests() {
+                return Verdict::Limited {
+                    limit: limit.requests(),
+                    retry_after: snapshot.reset_after(now),
+                };
+            }
+        }
+
+        for (_, counter) in &counters {
+            if counter.add(1, now).is_none() {
+                warn!(
+                    "Unable to count the request on {}, it kept changing.",
+                    counter.key()
+                );
+            }
+        }
+        if let Some(index) = &self.index {
+            index.add(shared_data, tier, key);
+        }
+        Verdict::Allowed
+    }
+
+    /// The counters of the key on the windows of the limits of its tier.
+    fn counters<'a, 'l>(
+        &self,
+        shared_data: &'a dyn SharedDataAccessor,
+        tier: &str,
+        limits: &'l [Tier],
+        key: &str,
+    ) -> Vec<(&'l Tier, SharedSlidingWindowCounter<'a>)> {
+        limits
+            .iter()
+            .enumerate()
+            .map(|(index, limit)| {
+                let key = format!("{}:{}:{}:{}", self.prefix, tier, index, key);
+                let window = Duration::from_millis(limit.period_in_millis());
+                let counter =
+                    SharedSlidingWindowCounter::new(shared_data, key, window, self.buckets);
+                (limit, counter)
+            })
+            .collect()
+    }
+
+    /// The counters and limits of the keys indexed. The keys of the tiers no longer in the API
+    /// are left out.
+    fn report(
+        &self,
+        shared_data: &dyn SharedDataAccessor,
+        policy_id: &str,
+        now: SystemTime,
+    ) -> Report {
+        let (keys, truncated) = self
+            .index
+            .as_ref()
+            .map_or((Vec::new(), false), |index| index.keys(shared_data));
+        let keys = keys
+            .into_iter()
+            .filter_map(|(tier, key)| {
+                let limits = if tier == DEFAULT_TIER {
+                    self.rate_limits.as_slice()
+                } else {
+                    self.tiers.get(&tier)?.as_slice()
+                };
+                let limits = self
+                    .counters(shared_data, &tier, limits, &key)
+                    .into_iter()
+                    .map(|(limit, counter)| {
+                        let snapshot = counter.snapshot();
+                        let count = snapshot.count(now);
+                        LimitReport {
+                            maximum_requests: limit.requests(),
+                            time_period_in_milliseconds: limit.period_in_millis(),
+                            count,
+                            remaining: limit.requests().saturating_sub(count),
+                            reset_after_milliseconds: snapshot.reset_after(now).as_millis() as u64,
+                        }
+                    })
+                    .collect();
+                Some(KeyReport { tier, key, limits })
+            })
+            .collect();
+
+        Report {
+            policy_id: policy_id.to_string(),
+            truncated,
+            keys,
+        }
+    }
+}
+
+/// The string or number the expression resolves to on the request.
+fn resolve(expression: &Expression, event: &EventData<RequestHeaders>) -> Option<String> {
+    match expression.resolve_on_request_headers(event) {
+        Ok(value) => value
+            .as_str()
+            .map(str::to_string)
+            .or_else(|| value.as_f64().map(|number| number.to_string())),
+        Err(err) => {
+            warn!(
+                "Error resolving an expression of the rate limiting. {}.",
+                err
+            );
+            None
+        }
+    }
+}
+
+/// Whether the request to the reporting path sends the report token, none when the request is
+/// not to the reporting path. The configurations with a path have a token.
+fn report_authorization(
+    config: &PolicyConfiguration,
+    event: &EventData<RequestHeaders>,
+) -> Option<bool> {
+    let report_path = config.report_path.as_deref()?;
+    let path = event.path();
+    if path.split('?').next() != Some(report_path) {
+        return None;
+    }
+    let token = event.header(REPORT_TOKEN_HEADER);
+    Some(token.is_some() && token == config.report_token)
+}
+
+/// Answers the request to the reporting path, not found without the report token.
+fn send_report(
+    exchange: Exchange<RequestHeaders>,
+    limiter: &Limiter,
+    authorized: bool,
+    shared_data: &dyn SharedDataAccessor,
+    now: SystemTime,
+) {
+    if !authorized {
+        warn!("Rejecting a request to the rate limiting report without its token.");
+        exchange.send_response(NOT_FOUND, vec![], None);
+        return;
+    }
+
+    let policy_id = <dyn PolicyContext>::default()
+        .policy_metadata()
+        .policy_id()
+        .to_string();
+    match serde_json::to_vec(&limiter.report(shared_data, &policy_id, now)) {
+        Ok(body) => exchange.send_response(
+            200,
+            vec![
+                ("content-type", "application/json"),
+                ("cache-control", "no-store"),
+            ],
+            Some(&body),
+        ),
+        Err(err) => {
+            warn!("Unable to serialize the rate limiting report. {}.", err);
+            exchange.send_response(500, vec![], None);
+        }
+    }
+}
+
+async fn filter(
+    exchange: Exchange<RequestHeaders>,
+    config: &PolicyConfiguration,
+    limiter: &Limiter,
+    host: Rc<dyn Host>,
+    shared_data: &'static dyn SharedDataAccessor,
+    metrics: &'static dyn MetricsAccessor,
+) {
+    // The event borrows the exchange, so the request is answered once it is released.
+    let report = match exchange.event_data() {
+        Some(event) => report_authorization(config, &event),
+        None => return,
+    };
+    if let Some(authorized) = report {
+        let now = host.get_current_time();
+        return send_report(exchange, limiter, authorized, shared_data, now);
+    }
+
+    let verdict = match exchange.event_data() {
+        Some(event) => {
+            let tier = config
+                .sla_tier
+                .as_ref()
+                .and_then(|tier| resolve(tier, &event));
+            // The requests without a key share the same counters.
+            let key = config
+                .key
+                .as_ref()
+                .and_then(|key| resolve(key, &event))
+                .unwrap_or_default();
+            limiter.check(shared_data, tier.as_deref(), &key, host.get_current_time())
+        }
+        None => return,
+    };
+
+    match verdict {
+        Verdict::Allowed => {
+            record_decision(Decision::new(POLICY_NAME, DECISION_KIND, Outcome::Allow));
+        }
+        Verdict::Unlimited => {
+            debug!("No rate limit applies to the request.");
+            record_decision(Decision::new(POLICY_NAME, DECISION_KIND, Outcome::Skip));
+        }
+        Verdict::Limited { limit, retry_after } => {
+            let retry_after = retry_after_seconds(retry_after);
+            info!(
+                "Rejecting a request over the limit of {} requests, retry after {}s.",
+                limit, retry_after
+            );
+            metrics.increment_counter(LIMITED_COUNTER, 1);
+            record_decision(
+                Decision::new(POLICY_NAME, DECISION_KIND, Outcome::Deny)
+                    .with_reason("quota exceeded")
+                    .with_detail("limit", limit)
+                    .with_detail("retryAfter", retry_after)
+                    .with_detail("status", TOO_MANY_REQUESTS),
+            );
+
+            let retry_after = retry_after.to_string();
+            let headers = vec![
+                ("retry-after", retry_after.as_str()),
+                ("content-type", "application/json"),
+            ];
+            exchange.send_response(TOO_MANY_REQUESTS, headers, Some(LIMITED_BODY));
+        }
+    }
+}
+
+fn validate(limits: &[Tier]) -> Result<(), PolicyError> {
+    match limits.iter().find(|limit| limit.period_in_millis() == 0) {
+        Some(limit) => Err(PolicyError::new(
+            codes::INVALID_CONFIGURATION,
+            format!(
+                "The limit of {} requests has an empty time period",
+                limit.requests()
+            ),
+        )),
+        None => Ok(()),
+    }
+}
+
+fn validate_buckets(buckets: usize) -> Result<(), PolicyError> {
+    if buckets == 0 || buckets > MAX_BUCKETS {
+        return Err(PolicyError::new(
+            codes::INVALID_CONFIGURATION,
+            format!("The windows need between 1 and {} buckets", MAX_BUCKETS),
+        ));
+    }
+    Ok(())
+}
+
+/// The limiter of the configuration, with the SLA tiers of the API.
+fn limiter(config: &PolicyConfiguration) -> Result<Limiter, PolicyError> {
+    validate_buckets(config.buckets)?;
+    validate(&config.rate_limits)?;
+    if config.report_path.is_some() && config.report_token.is_none() {
+        return Err(PolicyError::new(
+            codes::INVALID_CONFIGURATION,
+            "The report lists the keys of every client, it needs a reportToken",
+        ));
+    }
+
+    let metadata = <dyn PolicyContext>::default().policy_metadata();
+    let tiers: HashMap<String, Vec<Tier>> = match &config.sla_tier {
+        Some(_) => metadata
+            .api_tiers()
+            .into_iter()
+            .flatten()
+            .filter(|sla| match validate(sla.tiers()) {
+                Ok(()) => true,
+                Err(err) => {
+                    warn!("Ignoring the SLA tier {}. {}.", sla.id(), err);
+                    false
+                }
+            })
+            .map(|sla| (sla.id().to_string(), sla.tiers().clone()))
+            .collect(),
+        None => HashMap::new(),
+    };
+
+    if config.rate_limits.is_empty() && tiers.is_empty() {
+        return Err(PolicyError::new(
+            codes::INVALID_CONFIGURATION,
+            "Neither rate limits nor SLA tiers are configured",
+        ));
+    }
+
+    let prefix = format!("{}:{}", POLICY_NAME, metadata.policy_id());
+    let index = config
+        .report_path
+        .as_ref()
+        .map(|_| KeyIndex::new(format!("{}:keys", prefix), config.max_reported_keys));
+    Ok(Limiter {
+        index,
+        prefix,
+        rate_limits: config.rate_limits.clone(),
+        tiers,
+        buckets: config.buckets,
+    })
+}
+
+#[pdk::api::entrypoint]
+async fn configure(
+    launcher: Launcher,
+    Configuration(bytes): Configuration,
+) -> Result<(), PolicyError> {
+    let config: PolicyConfiguration = serde_json::from_slice(&bytes)?;
+    let limiter = limiter(&config)?;
+    info!(
+        "Limiting the requests with {} rate limits and {} SLA tiers.",
+        limiter.rate_limits.len(),
+        limiter.tiers.len()
+    );
+
+    launcher
+        .launch(|exchange, host, shared_data, metrics| {
+            filter(exchange, &config, &limiter, host, shared_data, metrics)
+        })
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{retry_after_seconds, validate, validate_buckets, Limiter, Verdict, MAX_BUCKETS};
+    use crate::report::tests::MockSharedData;
+    use crate::report::KeyIndex;
+    use pdk::api::error::codes;
+    use pdk::api::policy_context::metadata::Tier;
+    use std::collections::HashMap;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    fn at(millis: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(millis)
+    }
+
+    /// Two requests a second by default, three for the gold tier.
+    fn limiter(index: Option<KeyIndex>) -> Limiter {
+        let mut tiers = HashMap::new();
+        tiers.insert("gold".to_string(), vec![Tier::new(3, 1000)]);
+        Limiter {
+            prefix: "rate-limiting:test".to_string(),
+            rate_limits: vec![Tier::new(2, 1000)],
+            tiers,
+            buckets: 10,
+            index,
+        }
+    }
+
+    fn allowed(limiter: &Limiter, shared_data: &MockSharedData, tier: Option<&str>) -> u64 {
+        (0..10)
+            .take_while(|_| {
+                matches!(
+                    limiter.check(shared_data, tier, "alice", at(0)),
+                    Verdict::Allowed
+                )
+            })
+            .count() as u64
+    }
+
+    #[test]
+    fn requests_over_the_limit_are_limited_until_the_window_slides() {
+        let shared_data = MockSharedData::default();
+        let limiter = limiter(None);
+
+        assert_eq!(allowed(&limiter, &shared_data, None), 2);
+        match limiter.check(&shared_data, None, "alice", at(250)) {
+            Verdict::Limited { limit, retry_after } => {
+                assert_eq!(limit, 2);
+                assert_eq!(retry_after, Duration::from_millis(750));
+                assert_eq!(retry_after_seconds(retry_after), 1);
+            }
+            verdict => panic!("unexpected verdict {:?}", verdict),
+        }
+
+        // The other keys have their own counters, and the window makes room once it slid.
+        assert!(matches!(
+            limiter.check(&shared_data, None, "bob", at(250)),
+            Verdict::Allowed
+        ));
+        assert!(matches!(
+            limiter.check(&shared_data, None, "alice", at(1000)),
+            Verdict::Allowed
+        ));
+    }
+
+    #[test]
+    fn tiers_fall_back_to_the_rate_limits() {
+        let shared_data = MockSharedData::default();
+        let limiter = limiter(None);
+
+        assert_eq!(allowed(&limiter, &shared_data, Some("gold")), 3);
+        // The unknown tiers share the counters of the requests without a tier.
+        assert_eq!(allowed(&limiter, &shared_data, Some("unknown")), 2);
+        assert_eq!(allowed(&limiter, &shared_data, None), 0);
+
+        let tiers_only = Limiter {
+            rate_limits: vec![],
+            ..limiter
+        };
+        assert!(matches!(
+            tiers_only.check(&shared_data, Some("unknown"), "alice", at(0)),
+            Verdict::Unlimited
+        ));
+    }
+
+    #[test]
+    fn keys_are_only_indexed_when_reported() {
+        let shared_data = MockSharedData::default();
+        limiter(None).check(&shared_data, None, "alice", at(0));
+        assert_eq!(
+            KeyIndex::new("rate-limiting:test:keys".to_string(), 10).keys(&shared_data),
+            (vec![], false)
+        );
+
+        let index = KeyIndex::new("rate-limiting:test:keys".to_string(), 10);
+        let limiter = limiter(Some(index));
+        limiter.check(&shared_data, Some("gold"), "alice", at(0));
+        let report = limiter.report(&shared_data, "policy", at(0));
+        assert_eq!(report.keys.len(), 1);
+        assert_eq!(report.keys[0].tier, "gold");
+        assert_eq!(report.keys[0].limits[0].count, 1);
+        assert_eq!(report.keys[0].limits[0].remaining, 2);
+    }
+
+    #[test]
+    fn limits_need_a_time_period() {
+        assert!(validate(&[Tier::new(10, 1000)]).is_ok());
+        assert!(validate(&[]).is_ok());
+
+        let err = validate(&[Tier::new(10, 1000), Tier::new(5, 0)]).unwrap_err();
+        assert_eq!(err.code(), codes::INVALID_CONFIGURATION);
+        assert!(err.message().contains("5 requests"));
+    }
+
+    #[test]
+    fn buckets_are_bounded() {
+        assert!(validate_buckets(1).is_ok());
+        assert!(validate_buckets(MAX_BUCKETS).is_ok());
+        assert!(validate_buckets(0).is_err());
+        assert!(validate_buckets(MAX_BUCKETS + 1).is_err());
+    }
+}
@@ -0,0 +1,235 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! The report of the counters of the limiter, served on the reporting path. The shared data can
+//! not be listed, so the keys counted are also kept in an index, up to a maximum.
+use pdk::api::logger::warn;
+use pdk::api::shared_data::SharedDataAccessor;
+use serde::Serialize;
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+
+const MAX_CAS_ATTEMPTS: usize = 8;
+
+/// The tiers and keys counted, in the order they were first counted.
+pub struct KeyIndex {
+    key: String,
+    max_keys: usize,
+    /// The keys this worker already knows are indexed, to not read the index on every request.
+    indexed: RefCell<HashSet<(String, String)>>,
+    full: Cell<bool>,
+}
+
+impl KeyIndex {
+    pub fn new(key: String, max_keys: usize) -> Self {
+        Self {
+            key,
+            max_keys,
+            indexed: RefCell::new(HashSet::new()),
+            full: Cell::new(max_keys == 0),
+        }
+    }
+
+    fn decode(bytes: Option<Vec<u8>>) -> Vec<(String, String)> {
+        bytes
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    /// The tiers and keys indexed, and whether the index is full, so the keys counted since
+    /// were left out of it.
+    pub fn keys(&self, shared_data: &dyn SharedDataAccessor) -> (Vec<(String, String)>, bool) {
+        let keys = Self::decode(shared_data.read_shared_data(&self.key).0);
+        let truncated = keys.len() >= self.max_keys;
+        (keys, truncated)
+    }
+
+    /// Adds the key of the tier to the index, unless it is full.
+    pub fn add(&self, shared_data: &dyn SharedDataAccessor, tier: &str, key: &str) {
+        let entry = (tier.to_string(), key.to_string());
+        if self.full.get() || self.indexed.borrow().contains(&entry) {
+            return;
+        }
+
+        for _ in 0..MAX_CAS_ATTEMPTS {
+            let (bytes, cas) = shared_data.read_shared_data(&self.key);
+            let mut keys = Self::decode(bytes);
+            if keys.contains(&entry) {
+                self.indexed.borrow_mut().insert(entry);
+                return;
+            }
+            if keys.len() >= self.max_keys {
+                self.full.set(true);
+                return;
+            }
+
+            keys.push(entry.clone());
+            let bytes = match serde_json::to_vec(&keys) {
+                Ok(bytes) => bytes,
+                Err(_) => return,
+            };
+            if shared_data.write_shared_data(&self.key, &bytes, cas) {
+                self.indexed.borrow_mut().insert(entry);
+                return;
+            }
+        }
+        warn!(
+            "Unable to index the key of the tier {}, it kept changing.",
+            tier
+        );
+    }
+}
+
+/// The consumption of a limit by a key.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LimitReport {
+    pub maximum_requests: u64,
+    pub time_period_in_milliseconds: u64,
+    pub count: u64,
+    pub remaining: u64,
+    pub reset_after_milliseconds: u64,
+}
+
+/// The consumption of the limits of its tier by a key.
+#[derive(Serialize, Debug)]
+pub struct KeyReport {
+    pub tier: String,
+    pub key: String,
+    pub limits: Vec<LimitReport>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    pub policy_id: String,
+    /// Whether the index of the keys is full, so some keys counted may be missing.
+    pub truncated: bool,
+    pub keys: Vec<KeyReport>,
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::{KeyIndex, KeyReport, LimitReport, Report};
+    use pdk::api::shared_data::SharedDataAccessor;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    /// The shared data of a single worker, with its compare-and-swap tokens.
+    #[derive(Default)]
+    pub(crate) struct MockSharedData {
+        data: RefCell<HashMap<String, (Vec<u8>, u32)>>,
+    }
+
+    impl SharedDataAccessor for MockSharedData {
+        fn read_shared_data(&self, key: &str) -> (Option<Vec<u8>>, Option<u32>) {
+            match self.data.borrow().get(key) {
+                Some((value, cas)) => (Some(value.clone()), Some(*cas)),
+                None => (None, None),
+            }
+        }
+
+        fn write_shared_data(&self, key: &str, value: &[u8], cas: Option<u32>) -> bool {
+            let mut data = self.data.borrow_mut();
+            let current = data.get(key).map(|(_, cas)| *cas);
+            if cas.is_some() && cas != current {
+                return false;
+            }
+            let next = current.unwrap_or_default() + 1;
+            data.insert(key.to_string(), (value.to_vec(), next));
+            true
+        }
+    }
+
+    fn keys(keys: &[(&str, &str)]) -> Vec<(String, String)> {
+        keys.iter()
+            .map(|(tier, key)| (tier.to_string(), key.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn keys_are_indexed_once_in_order() {
+        let shared_data = MockSharedData::default();
+        let index = KeyIndex::new("index".to_string(), 10);
+
+        index.add(&shared_data, "default", "alice");
+        index.add(&shared_data, "gold", "bob");
+        index.add(&shared_data, "default", "alice");
+
+        assert_eq!(
+            index.keys(&shared_data),
+            (keys(&[("default", "alice"), ("gold", "bob")]), false)
+        );
+    }
+
+    #[test]
+    fn keys_indexed_by_other_workers_are_kept() {
+        let shared_data = MockSharedData::default();
+        let worker = KeyIndex::new("index".to_string(), 10);
+        let other = KeyIndex::new("index".to_string(), 10);
+
+        worker.add(&shared_data, "default", "alice");
+        other.add(&shared_data, "default", "bob");
+        other.add(&shared_data, "default", "alice");
+
+        assert_eq!(
+            worker.keys(&shared_data),
+            (keys(&[("default", "alice"), ("default", "bob")]), false)
+        );
+    }
+
+    #[test]
+    fn full_index_is_truncated() {
+        let shared_data = MockSharedData::default();
+        let index = KeyIndex::new("index".to_string(), 2);
+
+        index.add(&shared_data, "default", "alice");
+        index.add(&shared_data, "default", "bob");
+        index.add(&shared_data, "default", "carol");
+
+        assert_eq!(
+            index.keys(&shared_data),
+            (keys(&[("default", "alice"), ("default", "bob")]), true)
+        );
+
+        let disabled = KeyIndex::new("disabled".to_string(), 0);
+        disabled.add(&shared_data, "default", "alice");
+        assert_eq!(disabled.keys(&shared_data), (vec![], true));
+    }
+
+    #[test]
+    fn report_is_camel_case() {
+        let report = Report {
+            policy_id: "policy".to_string(),
+            truncated: false,
+            keys: vec![KeyReport {
+                tier: "default".to_string(),
+                key: "alice".to_string(),
+                limits: vec![LimitReport {
+                    maximum_requests: 10,
+                    time_period_in_milliseconds: 1000,
+                    count: 4,
+                    remaining: 6,
+                    reset_after_milliseconds: 250,
+                }],
+            }],
+        };
+
+        assert_eq!(
+            serde_json::to_value(&report).unwrap(),
+            serde_json::json!({
+                "policyId": "policy",
+                "truncated": false,
+                "keys": [{
+                    "tier": "default",
+                    "key": "alice",
+                    "limits": [{
+                        "maximumRequests": 10,
+                        "timePeriodInMilliseconds": 1000,
+                        "count": 4,
+                        "remaining": 6,
+                        "resetAfterMilliseconds": 250
+                    }]
+                }]
+            })
+        );
+    }
+}
@@ -0,0 +1,37 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use serde::Deserialize;
+
+/// A field of the exported context.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Field {
+    ApiId,
+    Policies,
+    RequestId,
+    ClientId,
+}
+
+/// A header of a context header policy replaced by this one, still sent with a single field
+/// of the context until its upstreams read the exported header.
+#[derive(Deserialize, Debug, Clone)]
+pub struct LegacyHeader {
+    pub name: String,
+    pub field: Field,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PolicyConfiguration {
+    /// The ids of the environments the context is exported in, the internal ones. The header
+    /// is only removed from the requests of the APIs of any other environment.
+    pub environments: Vec<String>,
+    /// The header with the exported context.
+    #[serde(default = "default_header")]
+    pub header: String,
+    /// The headers of the context header policies replaced by this one.
+    #[serde(alias = "legacyHeaders", default)]
+    pub legacy_headers: Vec<LegacyHeader>,
+}
+
+fn default_header() -> String {
+    "x-request-context".to_string()
+}
@@ -0,0 +1,350 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use crate::config::{Field, PolicyConfiguration};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use pdk::api::classy::bootstrap::Launcher;
+use pdk::api::classy::event::{Exchange, HeadersAccessor, RequestHeaders};
+use pdk::api::classy::Configuration;
+use pdk::api::error::{codes, PolicyError};
+use pdk::api::flags::FLAG_PREFIX;
+use pdk::api::logger::{debug, info, warn};
+use pdk::api::policy_context::PolicyContext;
+use pdk::api::property::PropertyAccessor;
+use serde::Serialize;
+
+mod config;
+
+const REQUEST_ID: &str = "x-request-id";
+
+/// The context exported to the upstream, with the fields unknown left out.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct RequestContext<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    api_id: Option<&'a str>,
+    policies: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_id: Option<String>,
+}
+
+impl RequestContext<'_> {
+    /// The value of a single field, the policies separated by commas.
+    fn field(&self, field: Field) -> Option<String> {
+        match field {
+            Field::ApiId => self.api_id.map(str::to_string),
+            Field::Policies => Some(self.policies.join(",")),
+            Field::RequestId => self.request_id.clone(),
+            Field::ClientId => self.client_id.clone(),
+        }
+    }
+}
+
+/// The context of the API, the same for every request.
+#[derive(Debug)]
+struct ApiContext {
+    api_id: Option<String>,
+    policies: Vec<String>,
+}
+
+/// The context of the API, none when its environment is not one of the internal ones.
+fn api_context(config: &PolicyConfiguration) -> Option<ApiContext> {
+    let metadata = <dyn PolicyContext>::default().policy_metadata();
+    let environment = metadata
+        .anypoint_environment()
+        .map(|environment| environment.environment_id().to_string());
+
+    match &environment {
+        Some(environment) if config.environments.contains(environment) => {}
+        _ => {
+            info!(
+                "Not exporting the request context in the environment {:?}.",
+                environment
+            );
+            return None;
+        }
+    }
+
+    // The flags share the map of the platform policy ids, but are not policies.
+    let policies = metadata
+        .platform_policy_ids()
+        .into_iter()
+        .flatten()
+        .filter(|(key, _)| !key.starts_with(FLAG_PREFIX))
+        .map(|(_, id)| id.clone())
+        .collect();
+
+    Some(ApiContext {
+        api_id: metadata.api_info().map(|api| api.id().to_string()),
+        policies,
+    })
+}
+
+fn request_id(headers: &dyn HeadersAccessor, properties: &dyn PropertyAccessor) -> Option<String> {
+    match properties.request().id() {
+        Ok(Some(id)) => Some(id),
+        Ok(None) => headers.header(REQUEST_ID),
+        Err(err) => {
+            warn!("Error reading the request id. {}.", err);
+            headers.header(REQUEST_ID)
+        }
+    }
+}
+
+fn client_id() -> Option<String> {
+    <dyn PolicyContext>::default()
+        .authentication_handler()
+        .authentication()
+        .and_then(|authentication| authentication.client_id().map(str::to_string))
+}
+
+/// Removes the context headers sent by the client, in every environment, as the upstreams
+/// reading them would otherwise take a context made up by the client for the one of the
+/// gateway.
+fn strip(headers: &dyn HeadersAccessor, config: &PolicyConfiguration) {
+    headers.remove_header(&config.header);
+    for legacy in &config.legacy_headers {
+        headers.remove_header(&legacy.name);
+    }
+}
+
+fn export(headers: &dyn HeadersAccessor, config: &PolicyConfiguration, context: &RequestContext) {
+    match serde_json::to_vec(context) {
+        Ok(json) => {
+            debug!("Exporting the request context {:?}.", context);
+            headers.set_header(&config.header, &STANDARD.encode(json));
+        }
+        Err(err) => warn!("Error serializing the request context. {}.", err),
+    }
+
+    for legacy in &config.legacy_headers {
+        if let Some(value) = context.field(legacy.field) {
+            headers.set_header(&legacy.name, &value);
+        }
+    }
+}
+
+async fn filter(
+    exchange: Exchange<RequestHeaders>,
+    config: &PolicyConfiguration,
+    api: Option<&ApiContext>,
+    properties: &'static dyn PropertyAccessor,
+) {
+    let event = match exchange.event_data() {
+        Some(event) => event,
+        None => return,
+    };
+
+    strip(&event, config);
+    if let Some(api) = api {
+        let context = RequestContext {
+            api_id: api.api_id.as_deref(),
+            policies: &api.policies,
+            request_id: request_id(&event, properties),
+            client_id: client_id(),
+        };
+        export(&event, config, &context);
+    }
+}
+
+fn validate(config: &PolicyConfiguration) -> Result<(), PolicyError> {
+    if config.header.trim().is_empty() {
+        return Err(PolicyError::new(
+            codes::INVALID_CONFIGURATION,
+            "The request context header is empty",
+        ));
+    }
+    if let Some(legacy) = config.legacy_headers.iter().find(|legacy| {
+        legacy.name.trim().is_empty() || legacy.name.eq_ignore_ascii_case(&config.header)
+    }) {
+        return Err(PolicyError::new(
+            codes::INVALID_CONFIGURATION,
+            format!("The legacy context header {:?} is invalid", legacy.name),
+        ));
+    }
+    if config.environments.is_empty() {
+        return Err(PolicyError::new(
+            codes::INVALID_CONFIGURATION,
+            "No environment to export the request context in",
+        ));
+    }
+    Ok(())
+}
+
+#[pdk::api::entrypoint]
+async fn configure(
+    launcher: Launcher,
+    Configuration(bytes): Configuration,
+) -> Result<(), PolicyError> {
+    let config: PolicyConfiguration = serde_json::from_slice(&bytes)?;
+    validate(&config)?;
+    let api = api_context(&config);
+
+    launcher
+        .launch(|exchange, properties| filter(exchange, &config, api.as_ref(), properties))
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{export, strip, validate, RequestContext};
+    use crate::config::{Field, PolicyConfiguration};
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use pdk::api::classy::event::HeadersAccessor;
+    use pdk::api::error::codes;
+    use std::cell::RefCell;
+
+    struct Headers(RefCell<Vec<(String, String)>>);
+
+    impl Headers {
+        fn new(headers: &[(&str, &str)]) -> Self {
+            Self(RefCell::new(
+                headers
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect(),
+            ))
+        }
+    }
+
+    impl HeadersAccessor for Headers {
+        fn header(&self, name: &str) -> Option<String> {
+            self.0
+                .borrow()
+                .iter()
+                .find(|(header, _)| header.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.clone())
+        }
+
+        fn headers(&self) -> Vec<(String, String)> {
+            self.0.borrow().clone()
+        }
+
+        fn add_header(&self, name: &str, value: &str) {
+            self.0
+                .borrow_mut()
+                .push((name.to_string(), value.to_string()));
+        }
+
+        fn set_header(&self, name: &str, value: &str) {
+            self.remove_header(name);
+            self.add_header(name, value);
+        }
+
+        fn set_headers(&self, headers: Vec<(&str, &str)>) {
+            *self.0.borrow_mut() = headers
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect();
+        }
+
+        fn remove_header(&self, name: &str) {
+            self.0
+                .borrow_mut()
+                .retain(|(header, _)| !header.eq_ignore_ascii_case(name));
+        }
+    }
+
+    fn config() -> PolicyConfiguration {
+        serde_json::from_str(
+            r#"{
+                "environments": ["internal"],
+                "legacyHeaders": [
+                    {"name": "x-api-id", "field": "apiId"},
+                    {"name": "x-policy-ids", "field": "policies"},
+                    {"name": "x-client-id", "field": "clientId"}
+                ]
+            }"#,
+        )
+        .unwrap()
+    }
+
+    fn decoded(headers: &Headers) -> String {
+        let value = headers.header("x-request-context").unwrap();
+        String::from_utf8(STANDARD.decode(value).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn unknown_fields_are_left_out() {
+        let policies = vec!["4917313".to_string(), "4917314".to_string()];
+        let headers = Headers::new(&[]);
+        let context = RequestContext {
+            api_id: Some("18835521"),
+            policies: &policies,
+            request_id: Some("9f0c7e5a".to_string()),
+            client_id: None,
+        };
+
+        export(&headers, &config(), &context);
+        assert_eq!(
+            decoded(&headers),
+            r#"{"apiId":"18835521","policies":["4917313","4917314"],"requestId":"9f0c7e5a"}"#
+        );
+
+        let context = RequestContext {
+            api_id: None,
+            policies: &[],
+            request_id: None,
+            client_id: None,
+        };
+        export(&headers, &config(), &context);
+        assert_eq!(decoded(&headers), r#"{"policies":[]}"#);
+    }
+
+    #[test]
+    fn legacy_headers_get_a_single_field() {
+        let policies = vec!["4917313".to_string(), "4917314".to_string()];
+        let headers = Headers::new(&[("x-client-id", "stale")]);
+        let context = RequestContext {
+            api_id: Some("18835521"),
+            policies: &policies,
+            request_id: None,
+            client_id: None,
+        };
+
+        export(&headers, &config(), &context);
+        assert_eq!(headers.header("x-api-id").as_deref(), Some("18835521"));
+        assert_eq!(
+            headers.header("x-policy-ids").as_deref(),
+            Some("4917313,4917314")
+        );
+        // Unknown fields are not sent, stripping the headers of the client is up to the filter.
+        assert_eq!(headers.header("x-client-id").as_deref(), Some("stale"));
+        assert_eq!(context.field(Field::RequestId), None);
+    }
+
+    #[test]
+    fn context_sent_by_the_client_is_removed() {
+        let headers = Headers::new(&[
+            ("X-Request-Context", "e30="),
+            ("x-api-id", "forged"),
+            ("x-client-id", "forged"),
+            ("accept", "*/*"),
+        ]);
+
+        strip(&headers, &config());
+        assert_eq!(
+            headers.headers(),
+            vec![("accept".to_string(), "*/*".to_string())]
+        );
+    }
+
+    #[test]
+    fn invalid_configurations_are_rejected() {
+        assert!(validate(&config()).is_ok());
+
+        for invalid in [
+            r#"{"environments": []}"#,
+            r#"{"environments": ["internal"], "header": " "}"#,
+            r#"{"environments": ["internal"], "legacyHeaders": [{"name": "", "field": "apiId"}]}"#,
+            r#"{"environments": ["internal"], "legacyHeaders": [{"name": "X-Request-Context", "field": "apiId"}]}"#,
+        ] {
+            let config: PolicyConfiguration = serde_json::from_str(invalid).unwrap();
+            let error = validate(&config).unwrap_err();
+            assert_eq!(error.code(), codes::INVALID_CONFIGURATION, "{}", invalid);
+        }
+    }
+}
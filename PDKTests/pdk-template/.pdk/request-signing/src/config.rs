@@ -0,0 +1,69 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use pdk::api::http::signature::SignedElement;
+use serde::Deserialize;
+use std::convert::TryFrom;
+use std::fmt;
+
+/// Whether the policy signs the requests or verifies their signature.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// Signs the requests to the upstream, which verifies them.
+    Egress,
+    /// Verifies the signature the clients send, rejecting the requests without a valid one.
+    Ingress,
+}
+
+/// A secret value, never printed by `Debug`.
+#[derive(Deserialize, Clone, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(<redacted>)")
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PolicyConfiguration {
+    pub mode: Mode,
+    /// The secret shared with the other end, at least 32 bytes.
+    pub secret: Secret,
+    /// The elements of the request signed, in order: `@method`, `@path`, `@body` or the name of
+    /// a header.
+    #[serde(default = "default_elements")]
+    pub elements: Vec<SignedElement>,
+    /// The header of the signature.
+    #[serde(alias = "signatureHeader", default = "default_signature_header")]
+    pub signature_header: String,
+    /// The query parameter of the signature of presigned URLs, read in `ingress` mode when
+    /// the header is missing.
+    #[serde(alias = "signatureQueryParam", default)]
+    pub signature_query_param: Option<String>,
+    /// How far the signed `date` of the requests verified can be from the time of the gateway,
+    /// in either direction.
+    #[serde(alias = "maxSkewSeconds", default = "default_max_skew_seconds")]
+    pub max_skew_seconds: u64,
+}
+
+fn default_elements() -> Vec<SignedElement> {
+    ["@method", "@path", "date", "@body"]
+        .iter()
+        .map(|element| SignedElement::try_from(element.to_string()).expect("valid element"))
+        .collect()
+}
+
+fn default_signature_header() -> String {
+    "x-signature".to_string()
+}
+
+fn default_max_skew_seconds() -> u64 {
+    300
+}
@@ -0,0 +1,305 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use crate::config::{Mode, PolicyConfiguration};
+use pdk::api::classy::bootstrap::Launcher;
+use pdk::api::classy::event::{
+    After, Before, BodyAccessor, Exchange, HeadersAccessor, RequestHeaders, ResponseHeaders, Start,
+};
+use pdk::api::classy::{Configuration, Host};
+use pdk::api::error::PolicyError;
+use pdk::api::http::query::{QueryDecoding, QueryParams};
+use pdk::api::http::signature::{RequestSigner, SignatureError};
+use pdk::api::logger::{debug, info};
+use pdk::api::policy_context::decisions::{Decision, Outcome};
+use pdk::api::property::record_decision;
+use std::rc::Rc;
+use std::time::{Duration, SystemTime};
+
+mod config;
+
+const POLICY_NAME: &str = "request-signing";
+const DECISION_KIND: &str = "signature";
+
+const UNAUTHORIZED: u32 = 401;
+const UNAUTHORIZED_BODY: &[u8] = br#"{"error":"Invalid request signature"}"#;
+
+/// The signer of the requests, and what is done with their signature.
+struct Signing {
+    mode: Mode,
+    signer: RequestSigner,
+    header: String,
+    query_param: Option<String>,
+    max_skew: Duration,
+}
+
+impl Signing {
+    /// Signs the request, or verifies its signature and the age of its signed date at `now`,
+    /// an error when the request is rejected.
+    fn apply(
+        &self,
+        headers: &dyn HeadersAccessor,
+        body: &[u8],
+        now: SystemTime,
+    ) -> Result<Outcome, SignatureError> {
+        match self.mode {
+            Mode::Egress => {
+                let signature = self.signer.sign(headers, body);
+                headers.set_header(&self.header, &signature);
+                Ok(Outcome::Modify)
+            }
+            Mode::Ingress => {
+                let signature = headers
+                    .header(&self.header)
+                    .or_else(|| self.take_query_signature(headers))
+                    .ok_or(SignatureError::Missing)?;
+                self.signer.verify(headers, body, &signature)?;
+                self.signer.verify_date(headers, now, self.max_skew)?;
+                Ok(Outcome::Allow)
+            }
+        }
+    }
+
+    /// Removes the signature from the query of a presigned URL, which was signed without it.
+    /// The query is decoded strictly, as form decoding would turn the `+` of the base64 into
+    /// spaces, and the parameters left keep their encoding.
+    fn take_query_signature(&self, headers: &dyn HeadersAccessor) -> Option<String> {
+        let name = self.query_param.as_deref()?;
+        let mut params = QueryParams::parse_with(&headers.header(":path")?, QueryDecoding::Strict);
+        let signature = params.get(name)?;
+        params.remove(name);
+        headers.set_header(":path", &params.to_uri());
+        Some(signature)
+    }
+}
+
+/// Rejects the request whose signature was not verified, from its headers or its body.
+fn reject<S>(exchange: Exchange<S>, error: SignatureError)
+where
+    S: After<Start> + Before<ResponseHeaders>,
+{
+    debug!("Rejecting a request. {}.", error);
+    record_decision(
+        Decision::new(POLICY_NAME, DECISION_KIND, Outcome::Deny)
+            .with_reason(&error.to_string())
+            .with_detail("status", UNAUTHORIZED),
+    );
+    let headers = vec![("content-type", "application/json")];
+    exchange.send_response(UNAUTHORIZED, headers, Some(UNAUTHORIZED_BODY));
+}
+
+/// Goes on with the request signed or verified, or rejects it.
+fn conclude<S>(exchange: Exchange<S>, result: Result<Outcome, SignatureError>)
+where
+    S: After<Start> + Before<ResponseHeaders>,
+{
+    match result {
+        Ok(outcome) => record_decision(Decision::new(POLICY_NAME, DECISION_KIND, outcome)),
+        Err(error) => reject(exchange, error),
+    }
+}
+
+async fn filter(exchange: Exchange<RequestHeaders>, signing: &Signing, host: Rc<dyn Host>) {
+    if !signing.signer.signs_body() {
+        let result = match exchange.event_data() {
+            Some(event) => signing.apply(&event, &[], host.get_current_time()),
+            None => return,
+        };
+        return conclude(exchange, result);
+    }
+
+    // The headers are held along the body, so the signature is still set or verified before
+    // the request reaches the upstream.
+    let exchange = exchange.wait_for_request_body().await;
+    let result = match exchange.event_data() {
+        Some(event) => signing.apply(&event, &event.body(), host.get_current_time()),
+        None => return,
+    };
+    conclude(exchange, result);
+}
+
+#[pdk::api::entrypoint]
+async fn configure(
+    launcher: Launcher,
+    Configuration(bytes): Configuration,
+) -> Result<(), PolicyError> {
+    let config: PolicyConfiguration = serde_json::from_slice(&bytes)?;
+    let signing = Signing {
+        mode: config.mode,
+        signer: RequestSigner::new(config.secret.expose(), config.elements)?,
+        header: config.signature_header.to_ascii_lowercase(),
+        query_param: config.signature_query_param,
+        max_skew: Duration::from_secs(config.max_skew_seconds),
+    };
+    info!(
+        "Signing mode {:?} over {:?}.",
+        signing.mode,
+        signing.signer.elements()
+    );
+
+    launcher
+        .launch(|exchange, host| filter(exchange, &signing, host))
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Mode, Signing};
+    use pdk::api::classy::event::HeadersAccessor;
+    use pdk::api::http::signature::{RequestSigner, SignatureError};
+    use pdk::api::policy_context::decisions::Outcome;
+    use std::cell::RefCell;
+    use std::convert::TryFrom;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    const SECRET: &str = "0123456789abcdef0123456789abcdef";
+    const DATE: &str = "Tue, 15 Oct 2024 10:00:00 GMT";
+
+    struct Headers(RefCell<Vec<(String, String)>>);
+
+    impl Headers {
+        fn new(headers: &[(&str, &str)]) -> Self {
+            Self(RefCell::new(
+                headers
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect(),
+            ))
+        }
+    }
+
+    impl HeadersAccessor for Headers {
+        fn header(&self, name: &str) -> Option<String> {
+            self.0
+                .borrow()
+                .iter()
+                .find(|(header, _)| header.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.clone())
+        }
+
+        fn headers(&self) -> Vec<(String, String)> {
+            self.0.borrow().clone()
+        }
+
+        fn add_header(&self, name: &str, value: &str) {
+            self.0
+                .borrow_mut()
+                .push((name.to_string(), value.to_string()));
+        }
+
+        fn set_header(&self, name: &str, value: &str) {
+            self.remove_header(name);
+            self.add_header(name, value);
+        }
+
+        fn set_headers(&self, headers: Vec<(&str, &str)>) {
+            *self.0.borrow_mut() = headers
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect();
+        }
+
+        fn remove_header(&self, name: &str) {
+            self.0
+                .borrow_mut()
+                .retain(|(header, _)| !header.eq_ignore_ascii_case(name));
+        }
+    }
+
+    fn signing(mode: Mode) -> Signing {
+        let elements = ["@method", "@path", "date", "@body"]
+            .iter()
+            .map(|element| TryFrom::try_from(element.to_string()).unwrap())
+            .collect();
+        Signing {
+            mode,
+            signer: RequestSigner::new(SECRET, elements).unwrap(),
+            header: "x-signature".to_string(),
+            query_param: Some("signature".to_string()),
+            max_skew: Duration::from_secs(300),
+        }
+    }
+
+    fn at(seconds: u64) -> SystemTime {
+        // The time of DATE.
+        UNIX_EPOCH + Duration::from_secs(1_728_986_400 + seconds)
+    }
+
+    /// A request dated `date`, signed as a client of the gateway would.
+    fn signed_request(date: &str) -> Headers {
+        let request = Headers::new(&[(":method", "POST"), (":path", "/orders"), ("date", date)]);
+        signing(Mode::Egress).apply(&request, b"{}", at(0)).unwrap();
+        request
+    }
+
+    #[test]
+    fn egress_signs_the_requests() {
+        let request = Headers::new(&[(":method", "POST"), (":path", "/orders")]);
+        assert_eq!(
+            signing(Mode::Egress).apply(&request, b"{}", at(0)),
+            Ok(Outcome::Modify)
+        );
+        assert!(request.header("x-signature").is_some());
+    }
+
+    #[test]
+    fn ingress_allows_the_recent_signed_requests() {
+        let ingress = signing(Mode::Ingress);
+        let request = signed_request(DATE);
+
+        assert_eq!(ingress.apply(&request, b"{}", at(0)), Ok(Outcome::Allow));
+        assert_eq!(ingress.apply(&request, b"{}", at(300)), Ok(Outcome::Allow));
+        assert_eq!(
+            ingress.apply(&request, b"{\"total\":1}", at(0)),
+            Err(SignatureError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn ingress_rejects_the_replayed_or_undated_requests() {
+        let ingress = signing(Mode::Ingress);
+
+        let request = signed_request(DATE);
+        assert_eq!(
+            ingress.apply(&request, b"{}", at(301)),
+            Err(SignatureError::StaleDate)
+        );
+        assert_eq!(
+            ingress.apply(&request, b"{}", at(0) - Duration::from_secs(301)),
+            Err(SignatureError::StaleDate)
+        );
+
+        // The date is signed, so it is checked once the signature is verified.
+        let undated = signed_request("");
+        assert_eq!(
+            ingress.apply(&undated, b"{}", at(0)),
+            Err(SignatureError::MissingDate)
+        );
+
+        let unsigned = Headers::new(&[(":method", "POST"), (":path", "/orders"), ("date", DATE)]);
+        assert_eq!(
+            ingress.apply(&unsigned, b"{}", at(0)),
+            Err(SignatureError::Missing)
+        );
+    }
+
+    #[test]
+    fn ingress_verifies_the_presigned_urls() {
+        let ingress = signing(Mode::Ingress);
+        let signed = signed_request(DATE);
+        let signature = signed.header("x-signature").unwrap();
+        assert!(signature.contains('+'));
+
+        // The `+` of the signature is sent as is, or percent-encoded.
+        for sent in [signature.clone(), signature.replace('+', "%2B")] {
+            let path = format!("/orders?signature={}", sent);
+            let request = Headers::new(&[(":method", "POST"), (":path", &path), ("date", DATE)]);
+            assert_eq!(ingress.apply(&request, b"{}", at(0)), Ok(Outcome::Allow));
+            assert_eq!(request.header(":path").as_deref(), Some("/orders"));
+        }
+
+        // Form decoding would have turned it into a space.
+        let path = format!("/orders?signature={}", signature.replace('+', " "));
+        let request = Headers::new(&[(":method", "POST"), (":path", &path), ("date", DATE)]);
+        assert!(ingress.apply(&request, b"{}", at(0)).is_err());
+    }
+}
@@ -0,0 +1,49 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use pdk::api::expression::Expression;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+pub struct PolicyConfiguration {
+    /// The key the responses are cached by, like `#[attributes.headers['x-tenant'] ++
+    /// attributes.requestPath]`. The path, with its query, without it. The method is always
+    /// part of the key.
+    #[serde(default)]
+    pub key: Option<Expression>,
+    /// How long the responses are served from the cache, at most. Responses with a shorter
+    /// `max-age` or `s-maxage` are cached for as long.
+    #[serde(alias = "ttlSeconds", default = "default_ttl_seconds")]
+    pub ttl_seconds: u64,
+    /// The methods of the requests cached.
+    #[serde(default = "default_methods")]
+    pub methods: Vec<String>,
+    /// The status codes of the responses cached.
+    #[serde(alias = "statusCodes", default = "default_status_codes")]
+    pub status_codes: Vec<u32>,
+    /// The larger responses are not cached, so they are not buffered.
+    #[serde(alias = "maxBodySize", default = "default_max_body_size")]
+    pub max_body_size: usize,
+    /// The most responses cached at once. The keys share this many slots, a response replaces
+    /// the one cached for another key in its slot.
+    #[serde(alias = "maxEntries", default = "default_max_entries")]
+    pub max_entries: u64,
+}
+
+fn default_ttl_seconds() -> u64 {
+    60
+}
+
+fn default_methods() -> Vec<String> {
+    vec!["GET".to_string(), "HEAD".to_string()]
+}
+
+fn default_status_codes() -> Vec<u32> {
+    vec![200]
+}
+
+fn default_max_body_size() -> usize {
+    64 * 1024
+}
+
+fn default_max_entries() -> u64 {
+    1024
+}
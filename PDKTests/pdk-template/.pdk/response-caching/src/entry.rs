@@ -0,0 +1,210 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! The responses cached, encoded to be stored as shared data.
+use std::convert::TryInto;
+
+/// The headers of the upstream response not replayed from the cache, as they are about the
+/// connection it came on or are set again when sending it.
+const UNCACHED_HEADERS: &[&str] = &[
+    "connection",
+    "content-length",
+    "keep-alive",
+    "proxy-authenticate",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// A cached response, the times in milliseconds since the epoch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    /// The cache key of the request, as the entries of other keys can share its slot.
+    pub key: String,
+    pub stored: u64,
+    pub expires: u64,
+    pub status: u32,
+    pub headers: Vec<(String, String)>,
+    /// The request headers the response varies on, with their values in the request it was
+    /// the response of.
+    pub vary: Vec<(String, Option<String>)>,
+    pub body: Vec<u8>,
+}
+
+impl Entry {
+    /// The response headers worth replaying, without the pseudo-headers.
+    pub fn cached_headers(headers: Vec<(String, String)>) -> Vec<(String, String)> {
+        headers
+            .into_iter()
+            .filter(|(name, _)| !name.starts_with(':'))
+            .filter(|(name, _)| {
+                !UNCACHED_HEADERS
+                    .iter()
+                    .any(|uncached| name.eq_ignore_ascii_case(uncached))
+            })
+            .collect()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.body.len() + 256);
+        bytes.extend_from_slice(&self.stored.to_le_bytes());
+        bytes.extend_from_slice(&self.expires.to_le_bytes());
+        bytes.extend_from_slice(&self.status.to_le_bytes());
+        put_bytes(&mut bytes, self.key.as_bytes());
+
+        put_len(&mut bytes, self.headers.len());
+        for (name, value) in &self.headers {
+            put_bytes(&mut bytes, name.as_bytes());
+            put_bytes(&mut bytes, value.as_bytes());
+        }
+
+        put_len(&mut bytes, self.vary.len());
+        for (name, value) in &self.vary {
+            put_bytes(&mut bytes, name.as_bytes());
+            match value {
+                Some(value) => {
+                    bytes.push(1);
+                    put_bytes(&mut bytes, value.as_bytes());
+                }
+                None => bytes.push(0),
+            }
+        }
+
+        put_bytes(&mut bytes, &self.body);
+        bytes
+    }
+
+    /// The entry of the `bytes`, none when they are not one.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut reader = Reader { bytes };
+        let stored = u64::from_le_bytes(reader.take(8)?.try_into().ok()?);
+        let expires = u64::from_le_bytes(reader.take(8)?.try_into().ok()?);
+        let status = u32::from_le_bytes(reader.take(4)?.try_into().ok()?);
+        let key = reader.string()?;
+
+        let headers = (0..reader.length()?)
+            .map(|_| Some((reader.string()?, reader.string()?)))
+            .collect::<Option<_>>()?;
+
+        let vary = (0..reader.length()?)
+            .map(|_| {
+                let name = reader.string()?;
+                let value = match reader.take(1)? {
+                    [0] => None,
+                    _ => Some(reader.string()?),
+                };
+                Some((name, value))
+            })
+            .collect::<Option<_>>()?;
+
+        let body = reader.bytes()?.to_vec();
+        Some(Self {
+            key,
+            stored,
+            expires,
+            status,
+            headers,
+            vary,
+            body,
+        })
+    }
+}
+
+fn put_len(bytes: &mut Vec<u8>, len: usize) {
+    bytes.extend_from_slice(&(len as u32).to_le_bytes());
+}
+
+fn put_bytes(bytes: &mut Vec<u8>, value: &[u8]) {
+    put_len(bytes, value.len());
+    bytes.extend_from_slice(value);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.bytes.len() < len {
+            return None;
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Some(taken)
+    }
+
+    fn length(&mut self) -> Option<usize> {
+        let len = u32::from_le_bytes(self.take(4)?.try_into().ok()?);
+        Some(len as usize)
+    }
+
+    fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.length()?;
+        self.take(len)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        String::from_utf8(self.bytes()?.to_vec()).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Entry;
+
+    fn entry() -> Entry {
+        Entry {
+            key: "GET:/orders".to_string(),
+            stored: 1_000,
+            expires: 61_000,
+            status: 200,
+            headers: vec![("content-type".to_string(), "application/json".to_string())],
+            vary: vec![
+                ("accept".to_string(), Some("application/json".to_string())),
+                ("x-tenant".to_string(), None),
+            ],
+            body: b"[]".to_vec(),
+        }
+    }
+
+    #[test]
+    fn entries_are_decoded_as_encoded() {
+        let full = entry();
+        assert_eq!(Entry::from_bytes(&full.to_bytes()), Some(full));
+
+        let empty = Entry {
+            headers: vec![],
+            vary: vec![],
+            body: vec![],
+            ..entry()
+        };
+        assert_eq!(Entry::from_bytes(&empty.to_bytes()), Some(empty));
+    }
+
+    #[test]
+    fn truncated_or_invalid_bytes_are_not_entries() {
+        let bytes = entry().to_bytes();
+        for len in 0..bytes.len() {
+            assert_eq!(Entry::from_bytes(&bytes[..len]), None, "{}", len);
+        }
+        assert_eq!(Entry::from_bytes(&[]), None);
+
+        let mut invalid = bytes;
+        // The first byte of the key, which is not UTF-8 anymore.
+        invalid[24] = 0xff;
+        assert_eq!(Entry::from_bytes(&invalid), None);
+    }
+
+    #[test]
+    fn connection_headers_are_not_cached() {
+        let headers = vec![
+            (":status".to_string(), "200".to_string()),
+            ("Content-Length".to_string(), "2".to_string()),
+            ("transfer-encoding".to_string(), "chunked".to_string()),
+            ("etag".to_string(), "\"1\"".to_string()),
+        ];
+        assert_eq!(
+            Entry::cached_headers(headers),
+            vec![("etag".to_string(), "\"1\"".to_string())]
+        );
+    }
+}
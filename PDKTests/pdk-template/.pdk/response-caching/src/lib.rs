@@ -0,0 +1,411 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use crate::config::PolicyConfiguration;
+use crate::entry::Entry;
+use pdk::api::classy::bootstrap::Launcher;
+use pdk::api::classy::event::{BodyAccessor, EventData, Exchange, HeadersAccessor, RequestHeaders};
+use pdk::api::classy::{Configuration, Host};
+use pdk::api::error::{codes, PolicyError};
+use pdk::api::expression::Expression;
+use pdk::api::http::cache_control::{CacheControl, Vary};
+use pdk::api::logger::{debug, info, warn};
+use pdk::api::metrics::MetricsAccessor;
+use pdk::api::policy_context::PolicyContext;
+use pdk::api::shared_data::SharedDataAccessor;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+mod config;
+mod entry;
+
+const HITS_COUNTER: &str = "response_cache_hits";
+const MISSES_COUNTER: &str = "response_cache_misses";
+
+const HEAD: &str = "HEAD";
+
+/// The responses cached by the policy, and the ones it would cache.
+struct Cache {
+    prefix: String,
+    key: Option<Expression>,
+    ttl: Duration,
+    methods: Vec<String>,
+    status_codes: Vec<u32>,
+    max_body_size: usize,
+    max_entries: u64,
+}
+
+impl Cache {
+    /// The cache key of the request, none when the `key` expression does not resolve to a
+    /// string or a number, so the request is not cached. The method is always part of it, so
+    /// the responses to `HEAD` requests, without a body, are never served to other requests.
+    fn key(&self, event: &EventData<RequestHeaders>, method: &str) -> Option<String> {
+        let key = match &self.key {
+            Some(key) => match key.resolve_on_request_headers(event) {
+                Ok(value) => value
+                    .as_str()
+                    .map(str::to_string)
+                    .or_else(|| value.as_f64().map(|number| number.to_string()))?,
+                Err(err) => {
+                    warn!("Error resolving the cache key. {}.", err);
+                    return None;
+                }
+            },
+            None => event.path(),
+        };
+        Some(format!("{}:{}", method, key))
+    }
+
+    /// The shared data key of the slot of the cache key. The keys are hashed into at most
+    /// `max_entries` slots, so the requests can not grow the shared data without bounds.
+    fn slot(&self, key: &str) -> String {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        format!("{}:{}", self.prefix, hasher.finish() % self.max_entries)
+    }
+
+    /// The response cached for the request, none when it expired or was the response to a
+    /// request with another key or other values of the headers it varies on. The expired
+    /// entries are cleared, so they do not hold the shared data until their slot is reused.
+    fn lookup(
+        &self,
+        shared_data: &dyn SharedDataAccessor,
+        key: &str,
+        event: &EventData<RequestHeaders>,
+        now: u64,
+    ) -> Option<Entry> {
+        let slot = self.slot(key);
+        let (bytes, cas) = shared_data.read_shared_data(&slot);
+        let entry = bytes.and_then(|bytes| Entry::from_bytes(&bytes))?;
+        if entry.expires <= now {
+            shared_data.write_shared_data(&slot, &[], cas);
+            return None;
+        }
+        let varies = entry
+            .vary
+            .iter()
+            .any(|(name, value)| event.header(name) != *value);
+        (entry.key == key && !varies).then_some(entry)
+    }
+
+    /// How long the response with the `status` and the headers read by `header` can be cached,
+    /// none when it can not. The responses without a `Content-Length` are not cached, as their
+    /// size is only known once they are buffered.
+    fn freshness<H>(&self, status: u32, header: H, authorized: bool) -> Option<Duration>
+    where
+        H: Fn(&str) -> Option<String>,
+    {
+        if !self.status_codes.contains(&status) {
+            return None;
+        }
+
+        let cache_control = header("cache-control")
+            .map(|value| CacheControl::parse(&value))
+            .unwrap_or_default();
+        // The responses to authorized requests are personal, unless told otherwise.
+        let shareable = cache_control.public
+            || cache_control.s_maxage.is_some()
+            || cache_control.must_revalidate;
+        if !cache_control.is_storable(true)
+            || cache_control.no_cache
+            || (authorized && !shareable)
+            || header("set-cookie").is_some()
+        {
+            return None;
+        }
+        if let Some(Vary::Any) = header("vary").map(|vary| Vary::parse(&vary)) {
+            return None;
+        }
+        match header("content-length").and_then(|length| length.trim().parse::<usize>().ok()) {
+            Some(length) if length <= self.max_body_size => {}
+            _ => return None,
+        }
+
+        let ttl = cache_control
+            .freshness(true)
+            .map_or(self.ttl, |freshness| freshness.min(self.ttl));
+        (!ttl.is_zero()).then_some(ttl)
+    }
+}
+
+fn millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis() as u64)
+}
+
+/// Sends the cached response, with its `Age`.
+fn serve(exchange: Exchange<RequestHeaders>, entry: &Entry, head: bool, now: u64) {
+    let age = (now.saturating_sub(entry.stored) / 1000).to_string();
+    let mut headers: Vec<(&str, &str)> = entry
+        .headers
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect();
+    headers.push(("age", &age));
+
+    let body = (!head).then_some(entry.body.as_slice());
+    exchange.send_response(entry.status, headers, body);
+}
+
+async fn filter(
+    exchange: Exchange<RequestHeaders>,
+    cache: &Cache,
+    host: Rc<dyn Host>,
+    shared_data: &'static dyn SharedDataAccessor,
+    metrics: &'static dyn MetricsAccessor,
+) {
+    // The event borrows the exchange, so the cached response is sent once it is released.
+    let (key, vary_source, authorized, cached, head) = match exchange.event_data() {
+        Some(event) => {
+            let method = event.method();
+            if !cache
+                .methods
+                .iter()
+                .any(|cached| cached.eq_ignore_ascii_case(&method))
+            {
+                return;
+            }
+            let cache_control = event
+                .header("cache-control")
+                .map(|value| CacheControl::parse(&value))
+                .unwrap_or_default();
+            if cache_control.no_store {
+                return;
+            }
+
+            let key = match cache.key(&event, &method.to_ascii_uppercase()) {
+                Some(key) => key,
+                None => return,
+            };
+            // A no-cache request gets a fresh response, which is cached for the next ones.
+            let cached = if cache_control.no_cache {
+                None
+            } else {
+                cache.lookup(shared_data, &key, &event, millis(host.get_current_time()))
+            };
+            let authorized = event.header("authorization").is_some();
+            (
+                key,
+                event.headers(),
+                authorized,
+                cached,
+                method.eq_ignore_ascii_case(HEAD),
+            )
+        }
+        None => return,
+    };
+
+    if let Some(entry) = cached {
+        debug!("Serving the response of {} from the cache.", key);
+        metrics.increment_counter(HITS_COUNTER, 1);
+        return serve(exchange, &entry, head, millis(host.get_current_time()));
+    }
+    metrics.increment_counter(MISSES_COUNTER, 1);
+
+    let exchange = exchange.wait_for_response_headers().await;
+    let (ttl, status, headers, vary) = match exchange.event_data() {
+        Some(event) => {
+            match cache.freshness(event.status_code(), |name| event.header(name), authorized) {
+                Some(ttl) => {
+                    let vary = event
+                        .header("vary")
+                        .map(|vary| Vary::parse(&vary))
+                        .unwrap_or_default();
+                    (ttl, event.status_code(), event.headers(), vary)
+                }
+                None => return,
+            }
+        }
+        None => return,
+    };
+
+    // The headers are held until the whole body is received, to cache it. Its length is known
+    // and bounded, the body is checked again in case the upstream sent more.
+    let exchange = exchange.wait_for_response_body().await;
+    let body = match exchange.event_data() {
+        Some(event) => event.body(),
+        None => return,
+    };
+    if body.len() > cache.max_body_size {
+        debug!("Not caching a response of {} bytes.", body.len());
+        return;
+    }
+
+    let vary = match vary {
+        Vary::Headers(names) => names
+            .into_iter()
+            .map(|name| {
+                let value = vary_source
+                    .iter()
+                    .find(|(header, _)| header.eq_ignore_ascii_case(&name))
+                    .map(|(_, value)| value.clone());
+                (name, value)
+            })
+            .collect(),
+        Vary::Any => return,
+    };
+    let stored = millis(host.get_current_time());
+    let slot = cache.slot(&key);
+    let entry = Entry {
+        key,
+        stored,
+        expires: stored + ttl.as_millis() as u64,
+        status,
+        headers: Entry::cached_headers(headers),
+        vary,
+        body,
+    };
+    if !shared_data.write_shared_data(&slot, &entry.to_bytes(), None) {
+        warn!("Unable to cache the response of {}.", entry.key);
+    }
+}
+
+#[pdk::api::entrypoint]
+async fn configure(
+    launcher: Launcher,
+    Configuration(bytes): Configuration,
+) -> Result<(), PolicyError> {
+    let config: PolicyConfiguration = serde_json::from_slice(&bytes)?;
+    if config.ttl_seconds == 0 {
+        return Err(PolicyError::new(
+            codes::INVALID_CONFIGURATION,
+            "The responses must be cached for at least a second",
+        ));
+    }
+    if config.max_entries == 0 {
+        return Err(PolicyError::new(
+            codes::INVALID_CONFIGURATION,
+            "The cache must hold at least one entry",
+        ));
+    }
+
+    let metadata = <dyn PolicyContext>::default().policy_metadata();
+    let cache = Cache {
+        prefix: format!("response-caching:{}", metadata.policy_id()),
+        key: config.key,
+        ttl: Duration::from_secs(config.ttl_seconds),
+        methods: config.methods,
+        status_codes: config.status_codes,
+        max_body_size: config.max_body_size,
+        max_entries: config.max_entries,
+    };
+    info!(
+        "Caching the responses to {:?} for up to {}s.",
+        cache.methods,
+        cache.ttl.as_secs()
+    );
+
+    launcher
+        .launch(|exchange, host, shared_data, metrics| {
+            filter(exchange, &cache, host, shared_data, metrics)
+        })
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Cache;
+    use std::time::Duration;
+
+    fn cache() -> Cache {
+        Cache {
+            prefix: "response-caching:test".to_string(),
+            key: None,
+            ttl: Duration::from_secs(60),
+            methods: vec!["GET".to_string()],
+            status_codes: vec![200],
+            max_body_size: 1024,
+            max_entries: 16,
+        }
+    }
+
+    fn freshness(status: u32, headers: &[(&str, &str)], authorized: bool) -> Option<Duration> {
+        cache().freshness(
+            status,
+            |name| {
+                headers
+                    .iter()
+                    .find(|(header, _)| header.eq_ignore_ascii_case(name))
+                    .map(|(_, value)| value.to_string())
+            },
+            authorized,
+        )
+    }
+
+    #[test]
+    fn responses_are_cached_up_to_the_ttl() {
+        let length = ("content-length", "2");
+        assert_eq!(
+            freshness(200, &[length], false),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(
+            freshness(200, &[length, ("cache-control", "max-age=10")], false),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(
+            freshness(200, &[length, ("cache-control", "s-maxage=600")], false),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(
+            freshness(200, &[length, ("cache-control", "max-age=0")], false),
+            None
+        );
+    }
+
+    #[test]
+    fn uncacheable_responses() {
+        let length = ("content-length", "2");
+        assert_eq!(freshness(404, &[length], false), None);
+        assert_eq!(
+            freshness(200, &[length, ("cache-control", "no-store")], false),
+            None
+        );
+        assert_eq!(
+            freshness(200, &[length, ("cache-control", "no-cache")], false),
+            None
+        );
+        assert_eq!(
+            freshness(200, &[length, ("cache-control", "private")], false),
+            None
+        );
+        assert_eq!(
+            freshness(200, &[length, ("set-cookie", "id=1")], false),
+            None
+        );
+        assert_eq!(freshness(200, &[length, ("vary", "*")], false), None);
+    }
+
+    #[test]
+    fn responses_of_unknown_or_large_length_are_not_cached() {
+        assert_eq!(freshness(200, &[], false), None);
+        assert_eq!(
+            freshness(200, &[("content-length", "chunked")], false),
+            None
+        );
+        assert_eq!(freshness(200, &[("content-length", "1025")], false), None);
+        assert!(freshness(200, &[("content-length", "1024")], false).is_some());
+    }
+
+    #[test]
+    fn authorized_responses_are_only_cached_when_shareable() {
+        let length = ("content-length", "2");
+        assert_eq!(freshness(200, &[length], true), None);
+        assert!(freshness(200, &[length, ("cache-control", "public")], true).is_some());
+        assert!(freshness(200, &[length, ("cache-control", "s-maxage=5")], true).is_some());
+    }
+
+    #[test]
+    fn keys_share_a_bounded_number_of_slots() {
+        let cache = cache();
+        let slots: std::collections::HashSet<String> = (0..1000)
+            .map(|id| cache.slot(&format!("GET:/orders/{}", id)))
+            .collect();
+        assert!(slots.len() <= 16);
+        assert!(slots
+            .iter()
+            .all(|slot| slot.starts_with("response-caching:test:")));
+        assert_eq!(cache.slot("GET:/orders"), cache.slot("GET:/orders"));
+    }
+}
@@ -0,0 +1,46 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use serde::Deserialize;
+
+/// What is done with the responses breaking the schema.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// The violations are logged and counted, the response reaches the client as is.
+    #[default]
+    Log,
+    /// The response is replaced with a `502`, for the contracts that must hold.
+    Block,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PolicyConfiguration {
+    /// The JSON Schema of the response bodies, as a JSON document.
+    pub schema: String,
+    /// The share of the responses validated, from 0 to 1.
+    #[serde(alias = "sampleRate", default = "default_sample_rate")]
+    pub sample_rate: f64,
+    /// The status codes of the responses validated, the `2xx` ones when empty.
+    #[serde(alias = "statusCodes", default)]
+    pub status_codes: Vec<u32>,
+    #[serde(default)]
+    pub mode: Mode,
+    /// The responses with a larger `content-length`, or without one, are not sampled, so they
+    /// are not buffered.
+    #[serde(alias = "maxBodySize", default = "default_max_body_size")]
+    pub max_body_size: usize,
+    /// The violations logged for a single response.
+    #[serde(alias = "maxViolations", default = "default_max_violations")]
+    pub max_violations: usize,
+}
+
+fn default_sample_rate() -> f64 {
+    0.1
+}
+
+fn default_max_body_size() -> usize {
+    1024 * 1024
+}
+
+fn default_max_violations() -> usize {
+    10
+}
@@ -0,0 +1,278 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use crate::config::{Mode, PolicyConfiguration};
+use crate::schema::{Schema, Violation};
+use pdk::api::classy::bootstrap::Launcher;
+use pdk::api::classy::event::{BodyAccessor, Exchange, HeadersAccessor, RequestHeaders};
+use pdk::api::classy::Configuration;
+use pdk::api::error::{codes, PolicyError};
+use pdk::api::http::request_class::RequestClass;
+use pdk::api::logger::{debug, info, warn};
+use pdk::api::metrics::MetricsAccessor;
+use serde_json::Value;
+use std::cell::Cell;
+
+mod config;
+mod schema;
+
+const VALIDATED_COUNTER: &str = "response_schema_validated";
+const VIOLATIONS_COUNTER: &str = "response_schema_violations";
+
+const BLOCKED_BODY: &[u8] = br#"{"error":"The upstream response breaks its contract"}"#;
+
+/// Picks the sampled responses, spread evenly over the traffic rather than at random, so low
+/// rates still validate the responses of quiet APIs.
+struct Sampler {
+    rate: f64,
+    credit: Cell<f64>,
+}
+
+impl Sampler {
+    fn new(rate: f64) -> Self {
+        Self {
+            rate,
+            credit: Cell::new(0.0),
+        }
+    }
+
+    fn sample(&self) -> bool {
+        let credit = self.credit.get() + self.rate;
+        let sampled = credit >= 1.0;
+        self.credit.set(if sampled { credit - 1.0 } else { credit });
+        sampled
+    }
+}
+
+/// Whether the response is one the schema describes, small enough to be buffered. The streamed
+/// responses, without a `content-length`, are never buffered as their size is unknown.
+fn validated<H>(status: u32, header: H, config: &PolicyConfiguration) -> bool
+where
+    H: Fn(&str) -> Option<String>,
+{
+    let status_matches = if config.status_codes.is_empty() {
+        (200..300).contains(&status)
+    } else {
+        config.status_codes.contains(&status)
+    };
+    let json = header("content-type")
+        .and_then(|content_type| RequestClass::from_content_type(&content_type))
+        == Some(RequestClass::Json);
+    if !status_matches || !json {
+        return false;
+    }
+
+    match header("content-length").and_then(|length| length.trim().parse::<usize>().ok()) {
+        Some(length) if length > config.max_body_size => {
+            debug!("Not validating a response of {} bytes.", length);
+            false
+        }
+        Some(_) => true,
+        None => {
+            debug!("Not validating a response of unknown length.");
+            false
+        }
+    }
+}
+
+fn violations(body: &[u8], schema: &Schema, config: &PolicyConfiguration) -> Vec<Violation> {
+    if body.len() > config.max_body_size {
+        debug!("Not validating a response of {} bytes.", body.len());
+        return Vec::new();
+    }
+    match serde_json::from_slice::<Value>(body) {
+        Ok(value) => schema.validate(&value, config.max_violations),
+        Err(err) => vec![Violation {
+            pointer: String::new(),
+            keyword: "json",
+            message: format!("the body is not JSON: {}", err),
+        }],
+    }
+}
+
+async fn filter(
+    exchange: Exchange<RequestHeaders>,
+    config: &PolicyConfiguration,
+    schema: &Schema,
+    sampler: &Sampler,
+    metrics: &'static dyn MetricsAccessor,
+) {
+    let path = match exchange.event_data() {
+        Some(event) => event.path(),
+        None => return,
+    };
+
+    let exchange = exchange.wait_for_response_headers().await;
+    match exchange.event_data() {
+        Some(event)
+            if validated(event.status_code(), |name| event.header(name), config)
+                && sampler.sample() => {}
+        _ => return,
+    }
+
+    // The headers are held until the whole body is received, so the response can still be blocked.
+    let exchange = exchange.wait_for_response_body().await;
+    let event = match exchange.event_data() {
+        Some(event) => event,
+        None => return,
+    };
+
+    let violations = violations(&event.body(), schema, config);
+    metrics.increment_counter(VALIDATED_COUNTER, 1);
+    if violations.is_empty() {
+        return;
+    }
+
+    metrics.increment_counter(VIOLATIONS_COUNTER, 1);
+    for violation in &violations {
+        warn!(
+            "The response of {} breaks the schema at {:?}, {}: {}.",
+            path, violation.pointer, violation.keyword, violation.message
+        );
+    }
+
+    if config.mode == Mode::Block {
+        event.set_header(":status", "502");
+        event.set_header("content-type", "application/json");
+        event.set_header("content-length", &BLOCKED_BODY.len().to_string());
+        event.set_body(BLOCKED_BODY);
+    }
+}
+
+fn validate(config: &PolicyConfiguration) -> Result<Schema, PolicyError> {
+    if !(0.0..=1.0).contains(&config.sample_rate) {
+        return Err(PolicyError::new(
+            codes::INVALID_CONFIGURATION,
+            format!(
+                "The sample rate {} is not between 0 and 1",
+                config.sample_rate
+            ),
+        ));
+    }
+    if config.max_violations == 0 {
+        return Err(PolicyError::new(
+            codes::INVALID_CONFIGURATION,
+            "At least one violation must be logged",
+        ));
+    }
+
+    let schema = serde_json::from_str(&config.schema).map_err(|err| {
+        PolicyError::new(
+            codes::INVALID_CONFIGURATION,
+            format!("The schema is not JSON: {}", err),
+        )
+    })?;
+    Schema::new(schema)
+}
+
+#[pdk::api::entrypoint]
+async fn configure(
+    launcher: Launcher,
+    Configuration(bytes): Configuration,
+) -> Result<(), PolicyError> {
+    let config: PolicyConfiguration = serde_json::from_slice(&bytes)?;
+    let schema = validate(&config)?;
+    let sampler = Sampler::new(config.sample_rate);
+    info!(
+        "Validating {}% of the responses in {:?} mode.",
+        config.sample_rate * 100.0,
+        config.mode
+    );
+
+    launcher
+        .launch(|exchange, metrics| filter(exchange, &config, &schema, &sampler, metrics))
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate, validated, violations, Sampler};
+    use crate::config::PolicyConfiguration;
+
+    fn config(json: &str) -> PolicyConfiguration {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn headers<'a>(headers: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |name| {
+            headers
+                .iter()
+                .find(|(header, _)| *header == name)
+                .map(|(_, value)| value.to_string())
+        }
+    }
+
+    #[test]
+    fn sampler_spreads_the_responses() {
+        let sampler = Sampler::new(0.25);
+        let sampled: Vec<bool> = (0..8).map(|_| sampler.sample()).collect();
+        assert_eq!(
+            sampled,
+            [false, false, false, true, false, false, false, true]
+        );
+
+        let sampler = Sampler::new(0.0);
+        assert!((0..100).all(|_| !sampler.sample()));
+    }
+
+    #[test]
+    fn only_json_responses_of_known_size_are_validated() {
+        let config = config(r#"{"schema": "{}", "maxBodySize": 100}"#);
+        let json = [
+            ("content-type", "application/json"),
+            ("content-length", "100"),
+        ];
+
+        assert!(validated(200, headers(&json), &config));
+        assert!(!validated(404, headers(&json), &config));
+        assert!(!validated(
+            200,
+            headers(&[("content-type", "text/html"), ("content-length", "10")]),
+            &config
+        ));
+        assert!(!validated(
+            200,
+            headers(&[
+                ("content-type", "application/json"),
+                ("content-length", "101")
+            ]),
+            &config
+        ));
+        assert!(!validated(
+            200,
+            headers(&[("content-type", "application/json")]),
+            &config
+        ));
+    }
+
+    #[test]
+    fn listed_status_codes_replace_the_2xx() {
+        let config = config(r#"{"schema": "{}", "statusCodes": [200, 404]}"#);
+        let json = [
+            ("content-type", "application/json"),
+            ("content-length", "2"),
+        ];
+
+        assert!(validated(404, headers(&json), &config));
+        assert!(!validated(201, headers(&json), &config));
+    }
+
+    #[test]
+    fn bodies_not_json_are_violations() {
+        let config = config(r#"{"schema": "{}", "maxBodySize": 8}"#);
+        let schema = validate(&config).unwrap();
+
+        assert!(violations(b"{}", &schema, &config).is_empty());
+        assert_eq!(violations(b"{", &schema, &config)[0].keyword, "json");
+        // the bodies found larger once received are not validated
+        assert!(violations(b"[0, 1, 2, 3]", &schema, &config).is_empty());
+    }
+
+    #[test]
+    fn invalid_configurations_are_rejected() {
+        assert!(validate(&config(r#"{"schema": "{}", "sampleRate": 1.5}"#)).is_err());
+        assert!(validate(&config(r#"{"schema": "{}", "maxViolations": 0}"#)).is_err());
+        assert!(validate(&config(r#"{"schema": "{"}"#)).is_err());
+        assert!(validate(&config(r#"{"schema": "{\"type\": \"date\"}"}"#)).is_err());
+        assert!(validate(&config(r#"{"schema": "true"}"#)).is_ok());
+    }
+}
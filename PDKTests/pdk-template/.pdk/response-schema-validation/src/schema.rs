@@ -0,0 +1,601 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Validates JSON values against the subset of JSON Schema the policy supports. The other
+//! keywords are ignored.
+use pdk::api::error::{codes, PolicyError};
+use pdk::api::regex_cache;
+use serde_json::{Map, Value};
+
+/// The `$ref` nesting the validation follows before giving up, as references can loop.
+const MAX_DEPTH: usize = 64;
+
+const TYPES: [&str; 7] = [
+    "null", "boolean", "object", "array", "number", "integer", "string",
+];
+
+/// A value breaking a keyword of the schema.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    /// The JSON pointer of the value in the body, empty for the whole body.
+    pub pointer: String,
+    pub keyword: &'static str,
+    pub message: String,
+}
+
+/// A JSON Schema, checked once at configure time.
+pub struct Schema {
+    root: Value,
+}
+
+impl Schema {
+    pub fn new(root: Value) -> Result<Self, PolicyError> {
+        check(&root, &root, "#")?;
+        Ok(Self { root })
+    }
+
+    /// The violations of the value, at most `limit` of them.
+    pub fn validate(&self, value: &Value, limit: usize) -> Vec<Violation> {
+        let mut validation = Validation {
+            root: &self.root,
+            violations: Vec::new(),
+            limit,
+        };
+        validation.validate(&self.root, value, "", 0);
+        validation.violations
+    }
+}
+
+fn invalid_schema(location: &str, message: String) -> PolicyError {
+    PolicyError::new(
+        codes::INVALID_CONFIGURATION,
+        format!("Invalid schema at {}: {}", location, message),
+    )
+}
+
+/// Checks the types, patterns and references of the schema and of all its subschemas.
+fn check(schema: &Value, root: &Value, location: &str) -> Result<(), PolicyError> {
+    let keywords = match schema {
+        Value::Bool(_) => return Ok(()),
+        Value::Object(keywords) => keywords,
+        _ => {
+            return Err(invalid_schema(
+                location,
+                "a schema is an object or a boolean".to_string(),
+            ))
+        }
+    };
+
+    if let Some(types) = keywords.get("type") {
+        let names: Vec<&Value> = match types {
+            Value::Array(names) => names.iter().collect(),
+            name => vec![name],
+        };
+        for name in names {
+            if !name.as_str().is_some_and(|name| TYPES.contains(&name)) {
+                return Err(invalid_schema(location, format!("unknown type {}", name)));
+            }
+        }
+    }
+    if let Some(pattern) = keywords.get("pattern") {
+        let pattern = pattern
+            .as_str()
+            .ok_or_else(|| invalid_schema(location, "the pattern is not a string".to_string()))?;
+        regex_cache::compile(pattern).map_err(|err| invalid_schema(location, err.to_string()))?;
+    }
+    if let Some(reference) = keywords.get("$ref") {
+        let resolved = reference
+            .as_str()
+            .and_then(|reference| resolve(root, reference));
+        if resolved.is_none() {
+            return Err(invalid_schema(
+                location,
+                format!("the reference {} is not in the schema", reference),
+            ));
+        }
+    }
+
+    for keyword in ["properties", "definitions", "$defs"] {
+        if let Some(Value::Object(schemas)) = keywords.get(keyword) {
+            for (name, schema) in schemas {
+                check(
+                    schema,
+                    root,
+                    &format!("{}/{}/{}", location, keyword, escape(name)),
+                )?;
+            }
+        }
+    }
+    for keyword in ["allOf", "anyOf", "oneOf"] {
+        if let Some(Value::Array(schemas)) = keywords.get(keyword) {
+            for (index, schema) in schemas.iter().enumerate() {
+                check(schema, root, &format!("{}/{}/{}", location, keyword, index))?;
+            }
+        }
+    }
+    for keyword in ["items", "additionalProperties", "not"] {
+        if let Some(schema) = keywords.get(keyword) {
+            check(schema, root, &format!("{}/{}", location, keyword))?;
+        }
+    }
+    Ok(())
+}
+
+/// The subschema of a local reference, like `#/$defs/order`.
+fn resolve<'a>(root: &'a Value, reference: &str) -> Option<&'a Value> {
+    let pointer = reference.strip_prefix('#')?;
+    root.pointer(pointer)
+}
+
+/// Escapes a key as a JSON pointer token.
+fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Object(_) => "object",
+        Value::Array(_) => "array",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+    }
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match (name, value) {
+        ("integer", Value::Number(number)) => {
+            number.is_i64()
+                || number.is_u64()
+                || number.as_f64().is_some_and(|n| n.fract() == 0.0)
+        }
+        (name, value) => type_name(value) == name,
+    }
+}
+
+struct Validation<'a> {
+    root: &'a Value,
+    violations: Vec<Violation>,
+    limit: usize,
+}
+
+impl<'a> Validation<'a> {
+    fn full(&self) -> bool {
+        self.violations.len() >= self.limit
+    }
+
+    fn violation(&mut self, pointer: &str, keyword: &'static str, message: String) {
+        if !self.full() {
+            self.violations.push(Violation {
+                pointer: pointer.to_string(),
+                keyword,
+                message,
+            });
+        }
+    }
+
+    /// Whether the value matches the subschema, without reporting its violations.
+    fn is_valid(&self, schema: &Value, value: &Value, pointer: &str, depth: usize) -> bool {
+        let mut validation = Validation {
+            root: self.root,
+            violations: Vec::new(),
+            limit: 1,
+        };
+        validation.validate(schema, value, pointer, depth);
+        validation.violations.is_empty()
+    }
+
+    fn validate(&mut self, schema: &Value, value: &Value, pointer: &str, depth: usize) {
+        let keywords = match schema {
+            Value::Bool(true) => return,
+            Value::Bool(false) => {
+                return self.violation(pointer, "false", "no value is allowed".to_string())
+            }
+            Value::Object(keywords) => keywords,
+            _ => return,
+        };
+        if depth > MAX_DEPTH {
+            return self.violation(
+                pointer,
+                "$ref",
+                "the schema references nest too deep".to_string(),
+            );
+        }
+
+        if let Some(schema) = keywords
+            .get("$ref")
+            .and_then(Value::as_str)
+            .and_then(|reference| resolve(self.root, reference))
+        {
+            self.validate(schema, value, pointer, depth + 1);
+        }
+
+        self.validate_type(keywords, value, pointer);
+        self.validate_values(keywords, value, pointer);
+        match value {
+            Value::Object(object) => self.validate_object(keywords, object, pointer, depth),
+            Value::Array(items) => self.validate_array(keywords, items, pointer, depth),
+            Value::String(string) => self.validate_string(keywords, string, pointer),
+            Value::Number(_) => self.validate_number(keywords, value, pointer),
+            _ => {}
+        }
+        self.validate_combinations(keywords, value, pointer, depth);
+    }
+
+    fn validate_type(&mut self, keywords: &Map<String, Value>, value: &Value, pointer: &str) {
+        let names: Vec<&str> = match keywords.get("type") {
+            Some(Value::String(name)) => vec![name.as_str()],
+            Some(Value::Array(names)) => names.iter().filter_map(Value::as_str).collect(),
+            _ => return,
+        };
+        if !names.iter().any(|name| has_type(value, name)) {
+            self.violation(
+                pointer,
+                "type",
+                format!(
+                    "expected {}, found {}",
+                    names.join(" or "),
+                    type_name(value)
+                ),
+            );
+        }
+    }
+
+    fn validate_values(&mut self, keywords: &Map<String, Value>, value: &Value, pointer: &str) {
+        if let Some(Value::Array(allowed)) = keywords.get("enum") {
+            if !allowed.contains(value) {
+                self.violation(
+                    pointer,
+                    "enum",
+                    format!("{} is not one of the allowed values", value),
+                );
+            }
+        }
+        if let Some(constant) = keywords.get("const") {
+            if constant != value {
+                self.violation(pointer, "const", format!("expected {}", constant));
+            }
+        }
+    }
+
+    fn validate_object(
+        &mut self,
+        keywords: &Map<String, Value>,
+        object: &Map<String, Value>,
+        pointer: &str,
+        depth: usize,
+    ) {
+        if let Some(Value::Array(required)) = keywords.get("required") {
+            for name in required.iter().filter_map(Value::as_str) {
+                if !object.contains_key(name) {
+                    self.violation(
+                        pointer,
+                        "required",
+                        format!("the property {} is missing", name),
+                    );
+                }
+            }
+        }
+
+        let properties = keywords.get("properties").and_then(Value::as_object);
+        for (name, property) in object {
+            if self.full() {
+                return;
+            }
+            let property_pointer = format!("{}/{}", pointer, escape(name));
+            match properties.and_then(|properties| properties.get(name)) {
+                Some(schema) => self.validate(schema, property, &property_pointer, depth),
+                None => match keywords.get("additionalProperties") {
+                    Some(Value::Bool(false)) => self.violation(
+                        &property_pointer,
+                        "additionalProperties",
+                        format!("the property {} is not allowed", name),
+                    ),
+                    Some(schema) => self.validate(schema, property, &property_pointer, depth),
+                    None => {}
+                },
+            }
+        }
+    }
+
+    fn validate_array(
+        &mut self,
+        keywords: &Map<String, Value>,
+        items: &[Value],
+        pointer: &str,
+        depth: usize,
+    ) {
+        self.validate_count(
+            keywords,
+            "minItems",
+            "maxItems",
+            items.len(),
+            "items",
+            pointer,
+        );
+        if let Some(schema) = keywords.get("items") {
+            for (index, item) in items.iter().enumerate() {
+                if self.full() {
+                    return;
+                }
+                self.validate(schema, item, &format!("{}/{}", pointer, index), depth);
+            }
+        }
+    }
+
+    fn validate_string(&mut self, keywords: &Map<String, Value>, string: &str, pointer: &str) {
+        let length = string.chars().count();
+        self.validate_count(
+            keywords,
+            "minLength",
+            "maxLength",
+            length,
+            "characters",
+            pointer,
+        );
+
+        if let Some(pattern) = keywords.get("pattern").and_then(Value::as_str) {
+            match regex_cache::compile(pattern) {
+                Ok(regex) if !regex.is_match(string) => {
+                    self.violation(pointer, "pattern", format!("does not match {}", pattern))
+                }
+                Ok(_) => {}
+                Err(err) => self.violation(pointer, "pattern", err.to_string()),
+            }
+        }
+    }
+
+    fn validate_count(
+        &mut self,
+        keywords: &Map<String, Value>,
+        min: &'static str,
+        max: &'static str,
+        count: usize,
+        unit: &str,
+        pointer: &str,
+    ) {
+        if let Some(minimum) = keywords.get(min).and_then(Value::as_u64) {
+            if (count as u64) < minimum {
+                self.violation(
+                    pointer,
+                    min,
+                    format!("{} {} is fewer than {}", count, unit, minimum),
+                );
+            }
+        }
+        if let Some(maximum) = keywords.get(max).and_then(Value::as_u64) {
+            if count as u64 > maximum {
+                self.violation(
+                    pointer,
+                    max,
+                    format!("{} {} is more than {}", count, unit, maximum),
+                );
+            }
+        }
+    }
+
+    fn validate_number(&mut self, keywords: &Map<String, Value>, value: &Value, pointer: &str) {
+        let number = match value.as_f64() {
+            Some(number) => number,
+            None => return,
+        };
+        let bound = |keyword: &str| keywords.get(keyword).and_then(Value::as_f64);
+
+        if let Some(minimum) = bound("minimum").filter(|minimum| number < *minimum) {
+            self.violation(
+                pointer,
+                "minimum",
+                format!("{} is less than {}", number, minimum),
+            );
+        }
+        if let Some(maximum) = bound("maximum").filter(|maximum| number > *maximum) {
+            self.violation(
+                pointer,
+                "maximum",
+                format!("{} is greater than {}", number, maximum),
+            );
+        }
+        if let Some(minimum) = bound("exclusiveMinimum").filter(|minimum| number <= *minimum) {
+            self.violation(
+                pointer,
+                "exclusiveMinimum",
+                format!("{} is not greater than {}", number, minimum),
+            );
+        }
+        if let Some(maximum) = bound("exclusiveMaximum").filter(|maximum| number >= *maximum) {
+            self.violation(
+                pointer,
+                "exclusiveMaximum",
+                format!("{} is not less than {}", number, maximum),
+            );
+        }
+        if let Some(divisor) = bound("multipleOf").filter(|divisor| *divisor > 0.0) {
+            if (number / divisor).fract() != 0.0 {
+                self.violation(
+                    pointer,
+                    "multipleOf",
+                    format!("{} is not a multiple of {}", number, divisor),
+                );
+            }
+        }
+    }
+
+    fn validate_combinations(
+        &mut self,
+        keywords: &Map<String, Value>,
+        value: &Value,
+        pointer: &str,
+        depth: usize,
+    ) {
+        if let Some(Value::Array(schemas)) = keywords.get("allOf") {
+            for schema in schemas {
+                self.validate(schema, value, pointer, depth);
+            }
+        }
+        if let Some(Value::Array(schemas)) = keywords.get("anyOf") {
+            if !schemas
+                .iter()
+                .any(|schema| self.is_valid(schema, value, pointer, depth))
+            {
+                self.violation(pointer, "anyOf", "matches none of the schemas".to_string());
+            }
+        }
+        if let Some(Value::Array(schemas)) = keywords.get("oneOf") {
+            let matching = schemas
+                .iter()
+                .filter(|schema| self.is_valid(schema, value, pointer, depth))
+                .count();
+            if matching != 1 {
+                self.violation(
+                    pointer,
+                    "oneOf",
+                    format!("matches {} of the schemas instead of one", matching),
+                );
+            }
+        }
+        if let Some(schema) = keywords.get("not") {
+            if self.is_valid(schema, value, pointer, depth) {
+                self.violation(pointer, "not", "matches the schema it must not".to_string());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::{Schema, Violation};
+
+    fn schema(schema: Value) -> Schema {
+        Schema::new(schema).unwrap()
+    }
+
+    fn pointers(violations: Vec<Violation>) -> Vec<(String, &'static str)> {
+        violations
+            .into_iter()
+            .map(|violation| (violation.pointer, violation.keyword))
+            .collect()
+    }
+
+    #[test]
+    fn invalid_schemas_are_rejected() {
+        assert!(Schema::new(json!(true)).is_ok());
+        assert!(Schema::new(json!(42)).is_err());
+        assert!(Schema::new(json!({"type": "date"})).is_err());
+        assert!(Schema::new(json!({"type": ["string", 1]})).is_err());
+        assert!(Schema::new(json!({"pattern": "("})).is_err());
+        assert!(Schema::new(json!({"$ref": "#/$defs/missing"})).is_err());
+        assert!(Schema::new(json!({"properties": {"id": {"type": "uuid"}}})).is_err());
+        assert!(Schema::new(json!({"items": {"anyOf": [{"type": "date"}]}})).is_err());
+    }
+
+    #[test]
+    fn types() {
+        let schema = schema(json!({"type": ["integer", "null"]}));
+
+        assert!(schema.validate(&json!(1), 10).is_empty());
+        assert!(schema.validate(&json!(1.0), 10).is_empty());
+        assert!(schema.validate(&json!(null), 10).is_empty());
+        let violations = schema.validate(&json!(1.5), 10);
+        assert_eq!(
+            violations[0].message,
+            "expected integer or null, found number"
+        );
+    }
+
+    #[test]
+    fn objects_are_validated_with_their_pointers() {
+        let schema = schema(json!({
+            "type": "object",
+            "required": ["id", "items"],
+            "properties": {
+                "id": {"type": "string"},
+                "items": {"type": "array", "maxItems": 2, "items": {"$ref": "#/$defs/item"}}
+            },
+            "additionalProperties": false,
+            "$defs": {
+                "item": {"type": "object", "properties": {"price": {"minimum": 0}}}
+            }
+        }));
+
+        assert!(schema
+            .validate(&json!({"id": "42", "items": [{"price": 1}]}), 10)
+            .is_empty());
+        assert_eq!(
+            pointers(schema.validate(&json!({"a/b": 1, "items": [{"price": -1}, {}, {}]}), 10)),
+            [
+                ("".to_string(), "required"),
+                ("/a~1b".to_string(), "additionalProperties"),
+                ("/items".to_string(), "maxItems"),
+                ("/items/0/price".to_string(), "minimum"),
+            ]
+        );
+    }
+
+    #[test]
+    fn violations_are_limited() {
+        let schema = schema(json!({"items": {"type": "string"}}));
+        assert_eq!(schema.validate(&json!([1, 2, 3, 4]), 2).len(), 2);
+    }
+
+    #[test]
+    fn strings_and_numbers() {
+        let schema = schema(json!({
+            "properties": {
+                "code": {"minLength": 2, "maxLength": 3, "pattern": "^[A-Z]+$"},
+                "rate": {"exclusiveMinimum": 0, "exclusiveMaximum": 1, "multipleOf": 0.25}
+            }
+        }));
+
+        assert!(schema
+            .validate(&json!({"code": "EUR", "rate": 0.5}), 10)
+            .is_empty());
+        assert_eq!(
+            pointers(schema.validate(&json!({"code": "eu", "rate": 1}), 10)),
+            [
+                ("/code".to_string(), "pattern"),
+                ("/rate".to_string(), "exclusiveMaximum"),
+            ]
+        );
+        assert_eq!(
+            pointers(schema.validate(&json!({"code": "EURO", "rate": 0.3}), 10)),
+            [
+                ("/code".to_string(), "maxLength"),
+                ("/rate".to_string(), "multipleOf"),
+            ]
+        );
+    }
+
+    #[test]
+    fn values_and_combinations() {
+        let one_of = schema(json!({
+            "oneOf": [{"type": "integer"}, {"type": "number"}],
+            "not": {"const": 7}
+        }));
+        assert!(one_of.validate(&json!(1.5), 10).is_empty());
+        assert_eq!(
+            pointers(one_of.validate(&json!(1), 10)),
+            [("".to_string(), "oneOf")]
+        );
+        assert_eq!(
+            one_of.validate(&json!(7), 10)[0].message,
+            "matches 2 of the schemas instead of one"
+        );
+
+        let any_of = schema(json!({"anyOf": [{"enum": ["a", "b"]}, {"const": 1}]}));
+        assert!(any_of.validate(&json!("b"), 10).is_empty());
+        assert!(any_of.validate(&json!(1), 10).is_empty());
+        assert_eq!(any_of.validate(&json!("c"), 10)[0].keyword, "anyOf");
+        assert_eq!(any_of.validate(&json!(false), 10)[0].keyword, "anyOf");
+    }
+
+    #[test]
+    fn looping_references_give_up() {
+        let schema = schema(json!({"$defs": {"a": {"$ref": "#/$defs/a"}}, "$ref": "#/$defs/a"}));
+        assert_eq!(schema.validate(&json!(1), 10)[0].keyword, "$ref");
+        assert_eq!(
+            pointers(self::schema(json!(false)).validate(&json!(1), 10)),
+            [("".to_string(), "false")]
+        );
+    }
+}
@@ -0,0 +1,30 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+pub struct PolicyConfiguration {
+    /// The metric of the time from the first byte of the request to the response headers.
+    #[serde(alias = "gatewayName", default = "default_gateway_name")]
+    pub gateway_name: String,
+    /// The metric of the time the upstream took, as reported by the proxy.
+    #[serde(alias = "upstreamName", default = "default_upstream_name")]
+    pub upstream_name: String,
+    /// Adds the metrics recorded by the other policies in the filter state.
+    #[serde(alias = "includePolicies", default = "default_true")]
+    pub include_policies: bool,
+    /// Keeps the `Server-Timing` headers sent by the upstream.
+    #[serde(alias = "forwardUpstream", default = "default_true")]
+    pub forward_upstream: bool,
+}
+
+fn default_gateway_name() -> String {
+    "gateway".to_string()
+}
+
+fn default_upstream_name() -> String {
+    "upstream".to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
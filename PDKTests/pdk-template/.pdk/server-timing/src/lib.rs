@@ -0,0 +1,206 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use crate::config::PolicyConfiguration;
+use pdk::api::classy::bootstrap::Launcher;
+use pdk::api::classy::event::{Exchange, HeadersAccessor, RequestHeaders};
+use pdk::api::classy::{Configuration, Host};
+use pdk::api::error::PolicyError;
+use pdk::api::http::server_timing::{Metric, ServerTiming, SERVER_TIMING};
+use pdk::api::logger::{debug, warn};
+use pdk::api::property::PropertyAccessor;
+use std::rc::Rc;
+use std::time::{Duration, SystemTime};
+
+mod config;
+
+const UPSTREAM_SERVICE_TIME: &str = "x-envoy-upstream-service-time";
+
+/// When the gateway received the first byte of the request, or when the policy saw the
+/// request headers if the proxy does not tell.
+fn request_start(properties: &dyn PropertyAccessor, seen: SystemTime) -> SystemTime {
+    match properties.request().time() {
+        Ok(Some(time)) => time,
+        Ok(None) => seen,
+        Err(err) => {
+            warn!("Error reading the request time. {}.", err);
+            seen
+        }
+    }
+}
+
+async fn filter(
+    exchange: Exchange<RequestHeaders>,
+    config: &PolicyConfiguration,
+    host: Rc<dyn Host>,
+    properties: &'static dyn PropertyAccessor,
+) {
+    let start = request_start(properties, host.get_current_time());
+
+    let exchange = exchange.wait_for_response_headers().await;
+    let event = match exchange.event_data() {
+        Some(event) => event,
+        None => return,
+    };
+
+    let total = host
+        .get_current_time()
+        .duration_since(start)
+        .unwrap_or_default();
+    let mut timing = ServerTiming::new().with(Metric::new(&config.gateway_name, total));
+
+    let upstream = event
+        .header(UPSTREAM_SERVICE_TIME)
+        .and_then(|millis| millis.trim().parse::<u64>().ok());
+    if let Some(millis) = upstream {
+        timing.add(Metric::new(
+            &config.upstream_name,
+            Duration::from_millis(millis),
+        ));
+    }
+
+    // The other policies were done with their response by now, this one is the outermost.
+    if config.include_policies {
+        timing.extend(properties.filter_state().server_timing());
+    }
+
+    debug!("Server timing of the response: {}.", timing);
+    if !config.forward_upstream {
+        event.remove_header(SERVER_TIMING);
+    }
+    event.add_header(SERVER_TIMING, &timing.to_string());
+}
+
+#[pdk::api::entrypoint]
+async fn configure(
+    launcher: Launcher,
+    Configuration(bytes): Configuration,
+) -> Result<(), PolicyError> {
+    let config: PolicyConfiguration = serde_json::from_slice(&bytes)?;
+
+    launcher
+        .launch(|exchange, host, properties| filter(exchange, &config, host, properties))
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::configure;
+    use pdk::api::classy::testing::{SimulatedHost, Simulator};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    fn at(micros: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_micros(micros)
+    }
+
+    fn header(name: &str, value: &str) -> (String, String) {
+        (name.to_string(), value.to_string())
+    }
+
+    // The response headers of an exchange taking from 100 ms to `end` on the simulated host.
+    fn response_headers(
+        config: &[u8],
+        end: SystemTime,
+        headers: Vec<(&str, &str)>,
+    ) -> Vec<(String, String)> {
+        let mut simulator =
+            Simulator::new(SimulatedHost::new().with_configuration(config), configure);
+        let mut exchange = simulator.exchange();
+
+        simulator.host().set_time(at(100_000));
+        exchange.request_headers(vec![(":path", "/")]);
+        simulator.host().set_time(end);
+        exchange.response_headers(headers);
+        simulator.host().response_headers()
+    }
+
+    #[test]
+    fn timing_of_the_gateway_and_the_upstream_is_added() {
+        let headers = response_headers(
+            b"{}",
+            at(125_000),
+            vec![(":status", "200"), ("x-envoy-upstream-service-time", "20")],
+        );
+
+        assert_eq!(
+            headers,
+            vec![
+                header(":status", "200"),
+                header("x-envoy-upstream-service-time", "20"),
+                header("server-timing", "gateway;dur=25, upstream;dur=20"),
+            ]
+        );
+    }
+
+    #[test]
+    fn metrics_are_formatted_as_tokens_in_milliseconds() {
+        let config = br#"{"gatewayName": "edge gw", "upstreamName": "orders/api"}"#;
+        let headers = response_headers(
+            config,
+            at(100_500),
+            vec![(":status", "200"), ("x-envoy-upstream-service-time", "0")],
+        );
+
+        assert_eq!(
+            headers.last(),
+            Some(&header(
+                "server-timing",
+                "edge-gw;dur=0.5, orders-api;dur=0"
+            ))
+        );
+    }
+
+    #[test]
+    fn unreadable_upstream_time_is_left_out() {
+        let headers = response_headers(
+            b"{}",
+            at(102_250),
+            vec![
+                (":status", "200"),
+                ("x-envoy-upstream-service-time", "soon"),
+            ],
+        );
+
+        assert_eq!(
+            headers.last(),
+            Some(&header("server-timing", "gateway;dur=2.25"))
+        );
+    }
+
+    #[test]
+    fn timing_is_appended_to_the_one_of_the_upstream() {
+        let headers = response_headers(
+            b"{}",
+            at(110_000),
+            vec![
+                (":status", "200"),
+                ("server-timing", "db;dur=5, cache;desc=\"hit\""),
+            ],
+        );
+
+        assert_eq!(
+            headers,
+            vec![
+                header(":status", "200"),
+                header("server-timing", "db;dur=5, cache;desc=\"hit\""),
+                header("server-timing", "gateway;dur=10"),
+            ]
+        );
+    }
+
+    #[test]
+    fn timing_of_the_upstream_is_dropped_when_not_forwarded() {
+        let headers = response_headers(
+            br#"{"forwardUpstream": false}"#,
+            at(110_000),
+            vec![(":status", "200"), ("Server-Timing", "db;dur=5")],
+        );
+
+        assert_eq!(
+            headers,
+            vec![
+                header(":status", "200"),
+                header("server-timing", "gateway;dur=10")
+            ]
+        );
+    }
+}
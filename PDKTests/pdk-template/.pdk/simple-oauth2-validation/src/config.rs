@@ -10,4 +10,10 @@ pub struct PolicyConfiguration {
     pub host: String,
     pub path: String,
     pub authorization: String,
+    #[serde(alias = "rejectSuspiciousPaths", default)]
+    pub reject_suspicious_paths: bool,
+    /// The `Server-Timing` metric of the validation of the token, recorded for the
+    /// server-timing policy when set.
+    #[serde(alias = "serverTimingName", default)]
+    pub server_timing_name: Option<String>,
 }
@@ -1,5 +1,6 @@
 // Copyright 2023 Salesforce, Inc. All rights reserved.
 use pdk::api::classy::client::{HttpClientRequestError, HttpClientResponseError};
+use pdk::api::http::path::Finding;
 
 pub enum FilterError {
     Unexpected,
@@ -7,6 +8,7 @@ pub enum FilterError {
     InactiveToken,
     ExpiredToken,
     NotYetActive,
+    SuspiciousPath(Vec<Finding>),
     ClientRequestError(HttpClientRequestError),
     ClientResponseError(HttpClientResponseError),
     NonParsableIntrospectionBody(serde_json::Error),
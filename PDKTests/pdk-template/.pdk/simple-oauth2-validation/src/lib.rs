@@ -4,15 +4,28 @@ use crate::error::FilterError;
 use crate::introspection::{IntrospectionResponse, IntrospectionResponseExtractor};
 use pdk::api::classy::bootstrap::Launcher;
 use pdk::api::classy::client::HttpClient;
-use pdk::api::classy::event::{Exchange, RequestHeaders};
-use pdk::api::classy::Configuration;
+use pdk::api::classy::event::{Exchange, HeadersAccessor, RequestHeaders};
+use pdk::api::classy::{Configuration, Host};
+use pdk::api::http::path::normalize;
+use pdk::api::http::server_timing::Metric;
 use pdk::api::logger::{debug, warn};
+use pdk::api::policy_context::decisions::{Decision, Outcome};
+use pdk::api::property::{record_decision, PropertyAccessor};
+use std::rc::Rc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 mod config;
 mod error;
 mod introspection;
 
+const POLICY_NAME: &str = "simple-oauth2-validation";
+const AUTHENTICATION_KIND: &str = "authentication";
+const THREAT_KIND: &str = "threat";
+
+const BAD_REQUEST: u32 = 400;
+const UNAUTHORIZED: u32 = 401;
+const SERVER_ERROR: u32 = 500;
+
 async fn introspect_token(
     token: &str,
     config: &PolicyConfiguration,
@@ -45,6 +58,13 @@ async fn do_filter(
 ) -> Result<(), FilterError> {
     let event_data = exchange.event_data().ok_or(FilterError::Unexpected)?;
 
+    if config.reject_suspicious_paths {
+        let path = normalize(&event_data.header(":path").unwrap_or_default());
+        if path.is_suspicious() {
+            return Err(FilterError::SuspiciousPath(path.findings().to_vec()));
+        }
+    }
+
     let result = config
         .token_extractor
         .resolve_on_request_headers(&event_data)
@@ -73,24 +93,66 @@ async fn do_filter(
     Ok(())
 }
 
+/// The decision on the request rejected for `error`, for the access log. The suspicious paths
+/// are threats, the other rejections failed authentications.
+fn rejection_decision(error: &FilterError) -> Decision {
+    let (kind, reason, status) = match error {
+        FilterError::NoToken => (AUTHENTICATION_KIND, "no token", UNAUTHORIZED),
+        FilterError::InactiveToken => (AUTHENTICATION_KIND, "inactive token", UNAUTHORIZED),
+        FilterError::ExpiredToken => (AUTHENTICATION_KIND, "expired token", UNAUTHORIZED),
+        FilterError::NotYetActive => (AUTHENTICATION_KIND, "token not yet active", UNAUTHORIZED),
+        FilterError::SuspiciousPath(_) => (THREAT_KIND, "suspicious path", BAD_REQUEST),
+        _ => (AUTHENTICATION_KIND, "token not validated", SERVER_ERROR),
+    };
+    Decision::new(POLICY_NAME, kind, Outcome::Deny)
+        .with_reason(reason)
+        .with_detail("status", status)
+}
+
 fn unauthorized_response(exchange: Exchange<RequestHeaders>) {
     exchange.send_response(
-        401,
+        UNAUTHORIZED,
         vec![("WWW-Authenticate", "Bearer realm=\"oauth2\"")],
         None,
     );
 }
 
+fn bad_request_response(exchange: Exchange<RequestHeaders>) {
+    exchange.send_response(BAD_REQUEST, vec![], None);
+}
+
 fn server_error_response(exchange: Exchange<RequestHeaders>) {
-    exchange.send_response(500, vec![], None);
+    exchange.send_response(SERVER_ERROR, vec![], None);
 }
 
 async fn filter(
     exchange: Exchange<RequestHeaders>,
     config: &PolicyConfiguration,
     client: HttpClient,
+    host: Rc<dyn Host>,
+    properties: &'static dyn PropertyAccessor,
 ) {
-    if let Err(err) = do_filter(&exchange, config, client).await {
+    let start = host.get_current_time();
+    let result = do_filter(&exchange, config, client).await;
+
+    // The introspection call included, so the clients see what the validation costs them.
+    if let Some(name) = &config.server_timing_name {
+        let spent = host
+            .get_current_time()
+            .duration_since(start)
+            .unwrap_or_default();
+        properties
+            .filter_state()
+            .add_server_timing(Metric::new(name, spent));
+    }
+
+    let decision = match &result {
+        Ok(()) => Decision::new(POLICY_NAME, AUTHENTICATION_KIND, Outcome::Allow),
+        Err(err) => rejection_decision(err),
+    };
+    record_decision(decision);
+
+    if let Err(err) = result {
         match err {
             FilterError::Unexpected => {
                 warn!("Unexpected error occurred while processing the request.");
@@ -114,6 +176,10 @@ async fn filter(
                 );
                 unauthorized_response(exchange);
             }
+            FilterError::SuspiciousPath(findings) => {
+                debug!("Request path was rejected as suspicious: {:?}.", findings);
+                bad_request_response(exchange);
+            }
             FilterError::ClientRequestError(err) => {
                 warn!(
                     "Error sending the request to the introspection endpoint. {:?}.",
@@ -144,7 +210,139 @@ async fn configure(launcher: Launcher, Configuration(bytes): Configuration) -> a
     let config = serde_json::from_slice(&bytes)?;
 
     launcher
-        .launch(|exchange, client| filter(exchange, &config, client))
+        .launch(|exchange, client, host, properties| {
+            filter(exchange, &config, client, host, properties)
+        })
         .await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{configure, rejection_decision};
+    use crate::error::FilterError;
+    use pdk::api::classy::proxy_wasm::types::Action;
+    use pdk::api::classy::testing::{LocalResponse, SimulatedHost, Simulator};
+    use pdk::api::policy_context::decisions::Outcome;
+    use serde_json::json;
+
+    // The token is read with the compiled #[attributes.headers['token']].
+    const CONFIG: &[u8] = br#"{
+        "tokenExtractor": "P[[\".\", \"0-25\", [\".\", \"0-18\", [\":ref\", \"0-10\", \"attributes\"], [\":str\", \"11-18\", \"headers\"]], [\":str\", \"19-25\", \"token\"]]]",
+        "upstream": "introspection",
+        "host": "auth.local",
+        "path": "/introspect",
+        "authorization": "Basic dXNlcjpwYXNz",
+        "rejectSuspiciousPaths": true
+    }"#;
+
+    fn header(name: &str, value: &str) -> (String, String) {
+        (name.to_string(), value.to_string())
+    }
+
+    fn simulator() -> Simulator {
+        Simulator::new(SimulatedHost::new().with_configuration(CONFIG), configure)
+    }
+
+    fn unauthorized() -> Option<LocalResponse> {
+        Some(LocalResponse {
+            status_code: 401,
+            headers: vec![header("WWW-Authenticate", "Bearer realm=\"oauth2\"")],
+            body: None,
+        })
+    }
+
+    #[test]
+    fn active_token_is_let_through() {
+        let mut simulator = simulator();
+        let mut exchange = simulator.exchange();
+
+        let action = exchange.request_headers(vec![(":path", "/orders"), ("token", "abc")]);
+        assert_eq!(action, Action::Pause);
+
+        let calls = simulator.host().http_calls();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].upstream, "introspection");
+        assert!(calls[0].headers.contains(&header(":path", "/introspect")));
+        assert!(calls[0]
+            .headers
+            .contains(&header("Authorization", "Basic dXNlcjpwYXNz")));
+        assert_eq!(calls[0].body.as_deref(), Some(&b"token=abc"[..]));
+
+        exchange.http_call_response(
+            calls[0].id,
+            vec![(":status", "200")],
+            br#"{"active": true, "exp": 4102444800}"#,
+        );
+
+        assert_eq!(simulator.host().resumed(), (1, 0));
+        assert_eq!(simulator.host().local_response(), None);
+    }
+
+    #[test]
+    fn inactive_token_is_rejected() {
+        let mut simulator = simulator();
+        let mut exchange = simulator.exchange();
+
+        exchange.request_headers(vec![(":path", "/orders"), ("token", "abc")]);
+        let id = simulator.host().http_calls()[0].id;
+        exchange.http_call_response(id, vec![(":status", "200")], br#"{"active": false}"#);
+
+        assert_eq!(simulator.host().local_response(), unauthorized());
+    }
+
+    #[test]
+    fn expired_token_is_rejected() {
+        let mut simulator = simulator();
+        let mut exchange = simulator.exchange();
+
+        exchange.request_headers(vec![(":path", "/orders"), ("token", "abc")]);
+        let id = simulator.host().http_calls()[0].id;
+        exchange.http_call_response(
+            id,
+            vec![(":status", "200")],
+            br#"{"active": true, "exp": 1}"#,
+        );
+
+        assert_eq!(simulator.host().local_response(), unauthorized());
+    }
+
+    #[test]
+    fn request_without_a_token_is_rejected_without_introspection() {
+        let mut simulator = simulator();
+        let mut exchange = simulator.exchange();
+
+        exchange.request_headers(vec![(":path", "/orders")]);
+
+        assert_eq!(simulator.host().local_response(), unauthorized());
+        assert!(simulator.host().http_calls().is_empty());
+    }
+
+    #[test]
+    fn suspicious_path_is_a_bad_request() {
+        let mut simulator = simulator();
+        let mut exchange = simulator.exchange();
+
+        exchange.request_headers(vec![(":path", "/orders/%2e%2e/admin"), ("token", "abc")]);
+
+        let response = simulator.host().local_response().unwrap();
+        assert_eq!(response.status_code, 400);
+        assert!(simulator.host().http_calls().is_empty());
+    }
+
+    #[test]
+    fn rejections_are_authentication_or_threat_decisions() {
+        let expired = rejection_decision(&FilterError::ExpiredToken);
+        assert_eq!(expired.kind(), "authentication");
+        assert_eq!(expired.outcome(), Outcome::Deny);
+        assert_eq!(expired.reason(), Some("expired token"));
+        assert_eq!(expired.detail("status"), Some(&json!(401)));
+
+        let suspicious = rejection_decision(&FilterError::SuspiciousPath(vec![]));
+        assert_eq!(suspicious.kind(), "threat");
+        assert_eq!(suspicious.detail("status"), Some(&json!(400)));
+
+        let unexpected = rejection_decision(&FilterError::Unexpected);
+        assert_eq!(unexpected.detail("status"), Some(&json!(500)));
+    }
+}
@@ -0,0 +1,49 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use serde::Deserialize;
+
+/// Where the latency of a request is taken from.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LatencySource {
+    /// The `x-envoy-upstream-service-time` of the response, or the measured latency when
+    /// the upstream did not report it.
+    #[default]
+    Upstream,
+    /// The time between the request headers and the response headers, as seen by the policy.
+    Measured,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Webhook {
+    pub upstream: String,
+    pub host: String,
+    pub path: String,
+    #[serde(alias = "timeoutMs", default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PolicyConfiguration {
+    #[serde(alias = "thresholdMs")]
+    pub threshold_ms: u64,
+    #[serde(default)]
+    pub source: LatencySource,
+    #[serde(alias = "headerName", default = "default_header_name")]
+    pub header_name: String,
+    #[serde(alias = "auditLog", default = "default_audit_log")]
+    pub audit_log: bool,
+    #[serde(default)]
+    pub webhook: Option<Webhook>,
+}
+
+fn default_timeout_ms() -> u64 {
+    1000
+}
+
+fn default_header_name() -> String {
+    "x-slow-request".to_string()
+}
+
+fn default_audit_log() -> bool {
+    true
+}
@@ -0,0 +1,221 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use crate::config::{LatencySource, PolicyConfiguration};
+use pdk::api::classy::bootstrap::Launcher;
+use pdk::api::classy::client::HttpClient;
+use pdk::api::classy::event::{Exchange, HeadersAccessor, RequestHeaders};
+use pdk::api::classy::{Configuration, Host};
+use pdk::api::error::PolicyError;
+use pdk::api::logger::{debug, info, warn};
+use serde::Serialize;
+use std::rc::Rc;
+use std::time::{Duration, SystemTime};
+
+mod config;
+
+const UPSTREAM_SERVICE_TIME: &str = "x-envoy-upstream-service-time";
+
+/// The record of a slow request, logged for auditing and posted to the webhook.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct SlowRequest<'a> {
+    event: &'static str,
+    method: &'a str,
+    path: &'a str,
+    status: &'a str,
+    latency_ms: u64,
+    threshold_ms: u64,
+    source: &'static str,
+}
+
+fn elapsed_millis(start: SystemTime, now: SystemTime) -> u64 {
+    now.duration_since(start).unwrap_or_default().as_millis() as u64
+}
+
+/// The latency of the request and where it was taken from.
+fn latency(
+    config: &PolicyConfiguration,
+    upstream_time: Option<String>,
+    measured: u64,
+) -> (u64, &'static str) {
+    let upstream = upstream_time.and_then(|time| time.trim().parse::<u64>().ok());
+    match (config.source, upstream) {
+        (LatencySource::Upstream, Some(upstream)) => (upstream, "upstream"),
+        _ => (measured, "measured"),
+    }
+}
+
+/// Posts the record to the webhook without waiting for its response, so it adds no latency
+/// to the already slow request.
+fn notify(config: &PolicyConfiguration, client: &HttpClient, body: &[u8]) {
+    if let Some(webhook) = &config.webhook {
+        let request = client
+            .request(webhook.upstream.as_str(), webhook.host.as_str())
+            .path(webhook.path.as_str())
+            .headers(vec![("content-type", "application/json")])
+            .body(body)
+            .timeout(Duration::from_millis(webhook.timeout_ms))
+            .post();
+
+        if let Err(err) = request {
+            warn!(
+                "Error notifying the slow request to the webhook. {:?}.",
+                err
+            );
+        }
+    }
+}
+
+async fn filter(
+    exchange: Exchange<RequestHeaders>,
+    config: &PolicyConfiguration,
+    client: HttpClient,
+    host: Rc<dyn Host>,
+) {
+    let start = host.get_current_time();
+
+    let (method, path) = match exchange.event_data() {
+        Some(event) => (
+            event.header(":method").unwrap_or_default(),
+            event.header(":path").unwrap_or_default(),
+        ),
+        None => return,
+    };
+
+    let exchange = exchange.wait_for_response_headers().await;
+    let event = match exchange.event_data() {
+        Some(event) => event,
+        None => return,
+    };
+
+    let measured = elapsed_millis(start, host.get_current_time());
+    let (latency_ms, source) = latency(config, event.header(UPSTREAM_SERVICE_TIME), measured);
+    if latency_ms < config.threshold_ms {
+        return;
+    }
+
+    debug!(
+        "Request took {} ms, above the {} ms threshold.",
+        latency_ms, config.threshold_ms
+    );
+    event.add_header(config.header_name.as_str(), latency_ms.to_string().as_str());
+
+    let status = event.header(":status").unwrap_or_default();
+    let record = SlowRequest {
+        event: "slow_request",
+        method: &method,
+        path: &path,
+        status: &status,
+        latency_ms,
+        threshold_ms: config.threshold_ms,
+        source,
+    };
+
+    let body = match serde_json::to_string(&record) {
+        Ok(body) => body,
+        Err(err) => {
+            warn!("Error serializing the slow request record. {}.", err);
+            return;
+        }
+    };
+
+    if config.audit_log {
+        info!("{}", body);
+    }
+    notify(config, &client, body.as_bytes());
+}
+
+#[pdk::api::entrypoint]
+async fn configure(
+    launcher: Launcher,
+    Configuration(bytes): Configuration,
+) -> Result<(), PolicyError> {
+    let config: PolicyConfiguration = serde_json::from_slice(&bytes)?;
+
+    launcher
+        .launch(|exchange, client, host| filter(exchange, &config, client, host))
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::configure;
+    use pdk::api::classy::proxy_wasm::types::Action;
+    use pdk::api::classy::testing::{SimulatedHost, Simulator};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    const CONFIG: &[u8] = br#"{
+        "thresholdMs": 100,
+        "webhook": {"upstream": "audit", "host": "audit.local", "path": "/slow"}
+    }"#;
+
+    fn at(millis: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(millis)
+    }
+
+    fn header(name: &str, value: &str) -> (String, String) {
+        (name.to_string(), value.to_string())
+    }
+
+    fn simulator() -> Simulator {
+        Simulator::new(SimulatedHost::new().with_configuration(CONFIG), configure)
+    }
+
+    #[test]
+    fn slow_request_is_tagged_and_notified() {
+        let mut simulator = simulator();
+        let mut exchange = simulator.exchange();
+
+        simulator.host().set_time(at(1000));
+        assert_eq!(
+            exchange.request_headers(vec![(":method", "GET"), (":path", "/orders")]),
+            Action::Continue
+        );
+
+        simulator.host().set_time(at(1250));
+        assert_eq!(
+            exchange.response_headers(vec![(":status", "200")]),
+            Action::Continue
+        );
+        assert_eq!(
+            simulator.host().response_headers(),
+            vec![header(":status", "200"), header("x-slow-request", "250")]
+        );
+
+        let calls = simulator.host().http_calls();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].upstream, "audit");
+        assert!(calls[0].headers.contains(&header(":path", "/slow")));
+        assert_eq!(calls[0].timeout, Duration::from_millis(1000));
+
+        let record: serde_json::Value =
+            serde_json::from_slice(calls[0].body.as_deref().unwrap()).unwrap();
+        assert_eq!(record["path"], "/orders");
+        assert_eq!(record["latencyMs"], 250);
+        assert_eq!(record["source"], "measured");
+    }
+
+    #[test]
+    fn upstream_time_is_preferred_to_the_measured_one() {
+        let mut simulator = simulator();
+        let mut exchange = simulator.exchange();
+
+        simulator.host().set_time(at(1000));
+        exchange.request_headers(vec![(":method", "GET"), (":path", "/orders")]);
+
+        simulator.host().set_time(at(1500));
+        exchange.response_headers(vec![
+            (":status", "200"),
+            ("x-envoy-upstream-service-time", "20"),
+        ]);
+
+        assert_eq!(
+            simulator.host().response_headers(),
+            vec![
+                header(":status", "200"),
+                header("x-envoy-upstream-service-time", "20"),
+            ]
+        );
+        assert!(simulator.host().http_calls().is_empty());
+    }
+}
@@ -0,0 +1,46 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use serde::Deserialize;
+
+/// What to do with the requests over plaintext connections, without a fingerprint.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum PlaintextAction {
+    Allow,
+    Deny,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PolicyConfiguration {
+    /// The fingerprints rejected, exact or ending with `*` to match a prefix.
+    #[serde(default)]
+    pub deny: Vec<String>,
+    /// The only fingerprints accepted, all of them when empty.
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// The header with the fingerprint computed by a TLS terminator in front of the gateway,
+    /// like a JA3 hash, used instead of the one of the gateway when present.
+    #[serde(alias = "trustedHeader", default)]
+    pub trusted_header: Option<String>,
+    /// The header telling the upstream the fingerprint of the client, none when empty.
+    #[serde(alias = "annotateHeader", default = "default_annotate_header")]
+    pub annotate_header: String,
+    #[serde(default = "default_plaintext")]
+    pub plaintext: PlaintextAction,
+    /// Logs the rejections without rejecting the requests, to tune the lists.
+    #[serde(default)]
+    pub monitor: bool,
+    #[serde(alias = "denyStatus", default = "default_deny_status")]
+    pub deny_status: u32,
+}
+
+fn default_annotate_header() -> String {
+    "x-tls-fingerprint".to_string()
+}
+
+fn default_plaintext() -> PlaintextAction {
+    PlaintextAction::Allow
+}
+
+fn default_deny_status() -> u32 {
+    403
+}
@@ -0,0 +1,325 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use crate::config::{PlaintextAction, PolicyConfiguration};
+use pdk::api::classy::bootstrap::Launcher;
+use pdk::api::classy::event::{Exchange, HeadersAccessor, RequestHeaders};
+use pdk::api::classy::Configuration;
+use pdk::api::error::{codes, PolicyError};
+use pdk::api::http::tls_fingerprint::TlsFingerprint;
+use pdk::api::logger::{debug, info, warn};
+use pdk::api::policy_context::decisions::{Decision, Outcome};
+use pdk::api::property::{record_decision, PropertyAccessor};
+
+mod config;
+
+const POLICY_NAME: &str = "tls-fingerprint";
+const DECISION_KIND: &str = "threat";
+
+/// Matches the fingerprint exactly, or by prefix for the patterns ending with `*`.
+fn matches(pattern: &str, fingerprint: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => fingerprint.starts_with(prefix),
+        None => pattern == fingerprint,
+    }
+}
+
+/// The reason to reject the client with the fingerprint, none when it is accepted.
+fn rejection(config: &PolicyConfiguration, fingerprint: Option<&str>) -> Option<String> {
+    let fingerprint = match fingerprint {
+        Some(fingerprint) => fingerprint,
+        None if config.plaintext == PlaintextAction::Deny => {
+            return Some("the connection is not over TLS".to_string())
+        }
+        None => return None,
+    };
+
+    if config
+        .deny
+        .iter()
+        .any(|pattern| matches(pattern, fingerprint))
+    {
+        return Some(format!("the fingerprint {} is denied", fingerprint));
+    }
+    if !config.allow.is_empty()
+        && !config
+            .allow
+            .iter()
+            .any(|pattern| matches(pattern, fingerprint))
+    {
+        return Some(format!("the fingerprint {} is not allowed", fingerprint));
+    }
+    None
+}
+
+/// The decision on the client rejected for `reason`, if any. Monitored rejections are allowed
+/// with their reason, so the access log tells what enforcing would reject.
+fn decision(config: &PolicyConfiguration, reason: Option<&str>) -> Decision {
+    match reason {
+        None => Decision::new(POLICY_NAME, DECISION_KIND, Outcome::Allow),
+        Some(reason) if config.monitor => Decision::new(POLICY_NAME, DECISION_KIND, Outcome::Allow)
+            .with_reason(reason)
+            .with_detail("monitor", true),
+        Some(reason) => Decision::new(POLICY_NAME, DECISION_KIND, Outcome::Deny)
+            .with_reason(reason)
+            .with_detail("status", config.deny_status),
+    }
+}
+
+/// The fingerprint of the client, forwarded by a trusted TLS terminator or taken from the
+/// connection, and annotated for the upstream.
+fn fingerprint(
+    headers: &dyn HeadersAccessor,
+    config: &PolicyConfiguration,
+    connection: impl FnOnce() -> Option<String>,
+) -> Option<String> {
+    let forwarded = config
+        .trusted_header
+        .as_deref()
+        .and_then(|header| headers.header(header))
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty());
+    let fingerprint = forwarded.or_else(connection);
+    debug!("TLS fingerprint of the client: {:?}.", fingerprint);
+
+    // A client sending the annotation itself could pass for an allowed one with the upstreams
+    // filtering on it, so it is set or removed on every request.
+    if !config.annotate_header.is_empty() {
+        match &fingerprint {
+            Some(fingerprint) => headers.set_header(&config.annotate_header, fingerprint),
+            None => headers.remove_header(&config.annotate_header),
+        }
+    }
+    fingerprint
+}
+
+async fn filter(
+    exchange: Exchange<RequestHeaders>,
+    config: &PolicyConfiguration,
+    properties: &'static dyn PropertyAccessor,
+) {
+    let reason = match exchange.event_data() {
+        Some(event) => {
+            let connection = || TlsFingerprint::read(properties).map(|tls| tls.fingerprint());
+            rejection(config, fingerprint(&event, config, connection).as_deref())
+        }
+        None => return,
+    };
+
+    record_decision(decision(config, reason.as_deref()));
+    match reason {
+        Some(reason) if config.monitor => info!("Monitoring only, not rejecting: {}.", reason),
+        Some(reason) => {
+            warn!("Rejecting the request: {}.", reason);
+            exchange.send_response(config.deny_status, vec![], None);
+        }
+        None => {}
+    }
+}
+
+fn validate(config: &PolicyConfiguration) -> Result<(), PolicyError> {
+    if !(400..600).contains(&config.deny_status) {
+        return Err(PolicyError::new(
+            codes::INVALID_CONFIGURATION,
+            format!(
+                "The deny status {} is not an error status",
+                config.deny_status
+            ),
+        ));
+    }
+    if let Some(pattern) = config
+        .deny
+        .iter()
+        .chain(&config.allow)
+        .find(|pattern| pattern.trim().is_empty())
+    {
+        return Err(PolicyError::new(
+            codes::INVALID_CONFIGURATION,
+            format!("The fingerprint pattern {:?} is empty", pattern),
+        ));
+    }
+    Ok(())
+}
+
+#[pdk::api::entrypoint]
+async fn configure(
+    launcher: Launcher,
+    Configuration(bytes): Configuration,
+) -> Result<(), PolicyError> {
+    let config: PolicyConfiguration = serde_json::from_slice(&bytes)?;
+    validate(&config)?;
+
+    launcher
+        .launch(|exchange, properties| filter(exchange, &config, properties))
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decision, fingerprint, matches, rejection, validate};
+    use crate::config::{PlaintextAction, PolicyConfiguration};
+    use pdk::api::classy::event::HeadersAccessor;
+    use pdk::api::error::codes;
+    use pdk::api::policy_context::decisions::Outcome;
+    use serde_json::json;
+    use std::cell::RefCell;
+
+    struct Headers(RefCell<Vec<(String, String)>>);
+
+    impl Headers {
+        fn new(headers: &[(&str, &str)]) -> Self {
+            Self(RefCell::new(
+                headers
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect(),
+            ))
+        }
+    }
+
+    impl HeadersAccessor for Headers {
+        fn header(&self, name: &str) -> Option<String> {
+            self.0
+                .borrow()
+                .iter()
+                .find(|(header, _)| header.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.clone())
+        }
+
+        fn headers(&self) -> Vec<(String, String)> {
+            self.0.borrow().clone()
+        }
+
+        fn add_header(&self, name: &str, value: &str) {
+            self.0
+                .borrow_mut()
+                .push((name.to_string(), value.to_string()));
+        }
+
+        fn set_header(&self, name: &str, value: &str) {
+            self.remove_header(name);
+            self.add_header(name, value);
+        }
+
+        fn set_headers(&self, headers: Vec<(&str, &str)>) {
+            *self.0.borrow_mut() = headers
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect();
+        }
+
+        fn remove_header(&self, name: &str) {
+            self.0
+                .borrow_mut()
+                .retain(|(header, _)| !header.eq_ignore_ascii_case(name));
+        }
+    }
+
+    fn config(json: &str) -> PolicyConfiguration {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn patterns_match_exactly_or_by_prefix() {
+        assert!(matches("t13dh2", "t13dh2"));
+        assert!(!matches("t13dh2", "t13dh2q"));
+        assert!(matches("t12*", "t12ih1"));
+        assert!(!matches("t12*", "t13dh2"));
+        assert!(matches("*", "anything"));
+    }
+
+    #[test]
+    fn denied_fingerprints_are_rejected_before_the_allowed_ones() {
+        let config = config(r#"{"deny": ["bad*"], "allow": ["good", "bad-but-allowed"]}"#);
+
+        assert_eq!(rejection(&config, Some("good")), None);
+        assert_eq!(
+            rejection(&config, Some("bad-but-allowed")),
+            Some("the fingerprint bad-but-allowed is denied".to_string())
+        );
+        assert_eq!(
+            rejection(&config, Some("other")),
+            Some("the fingerprint other is not allowed".to_string())
+        );
+    }
+
+    #[test]
+    fn plaintext_connections_follow_the_configured_action() {
+        let allow = config("{}");
+        assert_eq!(allow.plaintext, PlaintextAction::Allow);
+        assert_eq!(rejection(&allow, None), None);
+
+        let deny = config(r#"{"plaintext": "deny", "allow": ["good"]}"#);
+        assert_eq!(
+            rejection(&deny, None),
+            Some("the connection is not over TLS".to_string())
+        );
+    }
+
+    #[test]
+    fn decisions_tell_the_monitored_rejections() {
+        let enforcing = config(r#"{"deny": ["bad*"], "denyStatus": 429}"#);
+        let reason = rejection(&enforcing, Some("bad"));
+
+        assert_eq!(decision(&enforcing, None).outcome(), Outcome::Allow);
+        let denied = decision(&enforcing, reason.as_deref());
+        assert_eq!(denied.outcome(), Outcome::Deny);
+        assert_eq!(denied.reason(), Some("the fingerprint bad is denied"));
+        assert_eq!(denied.detail("status"), Some(&json!(429)));
+
+        let monitoring = config(r#"{"deny": ["bad*"], "monitor": true}"#);
+        let monitored = decision(&monitoring, reason.as_deref());
+        assert_eq!(monitored.outcome(), Outcome::Allow);
+        assert_eq!(monitored.detail("monitor"), Some(&json!(true)));
+    }
+
+    #[test]
+    fn forwarded_fingerprint_is_preferred_and_annotated() {
+        let config = config(r#"{"trustedHeader": "x-ja3"}"#);
+        let headers = Headers::new(&[
+            ("x-ja3", " 771,4865-4866 "),
+            ("x-tls-fingerprint", "forged"),
+        ]);
+
+        let fingerprint = fingerprint(&headers, &config, || unreachable!());
+        assert_eq!(fingerprint.as_deref(), Some("771,4865-4866"));
+        assert_eq!(
+            headers.header("x-tls-fingerprint").as_deref(),
+            Some("771,4865-4866")
+        );
+    }
+
+    #[test]
+    fn forged_annotation_is_removed_without_fingerprint() {
+        let config = config(r#"{"trustedHeader": "x-ja3"}"#);
+        let headers = Headers::new(&[("x-ja3", " "), ("X-TLS-Fingerprint", "forged")]);
+
+        assert_eq!(fingerprint(&headers, &config, || None), None);
+        assert_eq!(
+            headers.headers(),
+            vec![("x-ja3".to_string(), " ".to_string())]
+        );
+
+        let headers = Headers::new(&[]);
+        let fingerprint = fingerprint(&headers, &config, || Some("t13dh2".to_string()));
+        assert_eq!(fingerprint.as_deref(), Some("t13dh2"));
+    }
+
+    #[test]
+    fn annotation_can_be_disabled() {
+        let config = config(r#"{"annotateHeader": ""}"#);
+        let headers = Headers::new(&[("x-tls-fingerprint", "kept")]);
+
+        fingerprint(&headers, &config, || Some("t13dh2".to_string()));
+        assert_eq!(headers.header("x-tls-fingerprint").as_deref(), Some("kept"));
+    }
+
+    #[test]
+    fn invalid_configurations_are_rejected() {
+        assert!(validate(&config(r#"{"deny": ["bad"]}"#)).is_ok());
+
+        for invalid in [r#"{"denyStatus": 200}"#, r#"{"allow": ["good", " "]}"#] {
+            let error = validate(&config(invalid)).unwrap_err();
+            assert_eq!(error.code(), codes::INVALID_CONFIGURATION, "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,66 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use pdk::api::http::template::UrlTemplate;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+
+/// A secret value, never printed by `Debug`.
+#[derive(Deserialize, Clone, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Secret(value.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(<redacted>)")
+    }
+}
+
+/// Where an api key is sent.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyLocation {
+    #[default]
+    Header,
+    Query,
+}
+
+/// The credentials of a route, naming the secrets they are made of.
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Credentials {
+    Basic {
+        username: String,
+        password: String,
+    },
+    Bearer {
+        token: String,
+    },
+    ApiKey {
+        name: String,
+        #[serde(rename = "in", default)]
+        location: KeyLocation,
+        key: String,
+    },
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Route {
+    pub path: UrlTemplate,
+    pub credentials: Credentials,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PolicyConfiguration {
+    pub secrets: HashMap<String, Secret>,
+    pub routes: Vec<Route>,
+}
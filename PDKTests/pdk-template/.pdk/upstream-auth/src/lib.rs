@@ -0,0 +1,301 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use crate::config::{Credentials, KeyLocation, PolicyConfiguration, Secret};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use pdk::api::classy::bootstrap::Launcher;
+use pdk::api::classy::event::{Exchange, HeadersAccessor, RequestHeaders};
+use pdk::api::classy::Configuration;
+use pdk::api::error::{ErrorCategory, ErrorCode, PolicyError};
+use pdk::api::http::query::QueryParamsAccessor;
+use pdk::api::http::template::UrlTemplates;
+use pdk::api::logger::{debug, info};
+use std::collections::HashMap;
+
+mod config;
+
+const AUTHORIZATION: &str = "authorization";
+
+const UNKNOWN_SECRET: ErrorCode = ErrorCode::new(ErrorCategory::Config, 50);
+const EMPTY_SECRET: ErrorCode = ErrorCode::new(ErrorCategory::Config, 51);
+
+/// The credentials of a route, with their secrets resolved.
+#[derive(Debug)]
+enum Injection {
+    Header { name: String, value: Secret },
+    Query { name: String, value: Secret },
+}
+
+fn secret<'a>(secrets: &'a HashMap<String, Secret>, name: &str) -> Result<&'a Secret, PolicyError> {
+    let secret = secrets.get(name).ok_or_else(|| {
+        PolicyError::new(UNKNOWN_SECRET, format!("secret {} is not configured", name))
+    })?;
+    if secret.expose().is_empty() {
+        return Err(PolicyError::new(
+            EMPTY_SECRET,
+            format!("secret {} is empty", name),
+        ));
+    }
+    Ok(secret)
+}
+
+fn resolve(
+    credentials: &Credentials,
+    secrets: &HashMap<String, Secret>,
+) -> Result<Injection, PolicyError> {
+    let injection = match credentials {
+        Credentials::Basic { username, password } => {
+            let password = secret(secrets, password)?;
+            let encoded = STANDARD.encode(format!("{}:{}", username, password.expose()));
+            Injection::Header {
+                name: AUTHORIZATION.to_string(),
+                value: Secret::new(format!("Basic {}", encoded)),
+            }
+        }
+        Credentials::Bearer { token } => Injection::Header {
+            name: AUTHORIZATION.to_string(),
+            value: Secret::new(format!("Bearer {}", secret(secrets, token)?.expose())),
+        },
+        Credentials::ApiKey {
+            name,
+            location: KeyLocation::Header,
+            key,
+        } => Injection::Header {
+            name: name.to_ascii_lowercase(),
+            value: secret(secrets, key)?.clone(),
+        },
+        Credentials::ApiKey {
+            name,
+            location: KeyLocation::Query,
+            key,
+        } => Injection::Query {
+            name: name.clone(),
+            value: secret(secrets, key)?.clone(),
+        },
+    };
+    Ok(injection)
+}
+
+async fn filter(exchange: Exchange<RequestHeaders>, routes: &UrlTemplates<Injection>) {
+    let event = match exchange.event_data() {
+        Some(event) => event,
+        None => return,
+    };
+
+    let path = event.path();
+    let (template, injection, _) = match routes.matches(&path) {
+        Some(route) => route,
+        None => {
+            debug!("No upstream credentials for {}.", path);
+            return;
+        }
+    };
+
+    // Only the names are logged, the values stay redacted.
+    match injection {
+        Injection::Header { name, value } => {
+            debug!("Injecting the {} header for route {}.", name, template);
+            event.set_header(name, value.expose());
+        }
+        Injection::Query { name, value } => {
+            debug!(
+                "Injecting the {} query parameter for route {}.",
+                name, template
+            );
+            // The parameters sent by the client keep their encoding, for signed URLs.
+            let mut params = event.query_params();
+            params.set(name, value.expose());
+            event.set_query_params(&params);
+        }
+    }
+}
+
+#[pdk::api::entrypoint]
+async fn configure(
+    launcher: Launcher,
+    Configuration(bytes): Configuration,
+) -> Result<(), PolicyError> {
+    let config: PolicyConfiguration = serde_json::from_slice(&bytes)?;
+
+    let mut routes = UrlTemplates::new();
+    for route in &config.routes {
+        let injection =
+            resolve(&route.credentials, &config.secrets).map_err(|err| err.context(&route.path))?;
+        routes.add(route.path.clone(), injection);
+    }
+    info!(
+        "Upstream credentials configured for {} routes.",
+        routes.len()
+    );
+
+    launcher
+        .launch(|exchange| filter(exchange, &routes))
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{configure, resolve, Injection, EMPTY_SECRET, UNKNOWN_SECRET};
+    use crate::config::{Credentials, KeyLocation, Secret};
+    use pdk::api::classy::proxy_wasm::types::Action;
+    use pdk::api::classy::testing::{SimulatedHost, Simulator};
+    use std::collections::HashMap;
+
+    const CONFIG: &[u8] = br#"{
+        "secrets": {"partner-token": "s3cret", "search-key": "k3y"},
+        "routes": [
+            {"path": "/partners/{rest:path}", "credentials": {"type": "bearer", "token": "partner-token"}},
+            {"path": "/search", "credentials": {"type": "apiKey", "name": "key", "in": "query", "key": "search-key"}}
+        ]
+    }"#;
+
+    fn secrets(secrets: &[(&str, &str)]) -> HashMap<String, Secret> {
+        secrets
+            .iter()
+            .map(|(name, value)| (name.to_string(), Secret::new(*value)))
+            .collect()
+    }
+
+    fn header(name: &str, value: &str) -> (String, String) {
+        (name.to_string(), value.to_string())
+    }
+
+    fn simulator(config: &[u8]) -> Simulator {
+        Simulator::new(SimulatedHost::new().with_configuration(config), configure)
+    }
+
+    #[test]
+    fn credentials_are_resolved() {
+        let secrets = secrets(&[("password", "open sesame"), ("key", "k3y")]);
+        let basic = Credentials::Basic {
+            username: "gateway".to_string(),
+            password: "password".to_string(),
+        };
+        match resolve(&basic, &secrets).unwrap() {
+            Injection::Header { name, value } => {
+                assert_eq!(name, "authorization");
+                assert_eq!(value.expose(), "Basic Z2F0ZXdheTpvcGVuIHNlc2FtZQ==");
+            }
+            injection => panic!("header expected, got {:?}", injection),
+        }
+
+        let api_key = Credentials::ApiKey {
+            name: "X-Api-Key".to_string(),
+            location: KeyLocation::Header,
+            key: "key".to_string(),
+        };
+        match resolve(&api_key, &secrets).unwrap() {
+            Injection::Header { name, value } => {
+                assert_eq!(name, "x-api-key");
+                assert_eq!(value.expose(), "k3y");
+            }
+            injection => panic!("header expected, got {:?}", injection),
+        }
+    }
+
+    #[test]
+    fn missing_and_empty_secrets_are_rejected() {
+        let bearer = Credentials::Bearer {
+            token: "token".to_string(),
+        };
+
+        let error = resolve(&bearer, &secrets(&[])).unwrap_err();
+        assert_eq!(error.code(), UNKNOWN_SECRET);
+        let error = resolve(&bearer, &secrets(&[("token", "")])).unwrap_err();
+        assert_eq!(error.code(), EMPTY_SECRET);
+    }
+
+    #[test]
+    fn secrets_are_not_printed() {
+        let secrets = secrets(&[("token", "s3cret")]);
+        let bearer = Credentials::Bearer {
+            token: "token".to_string(),
+        };
+
+        let injection = resolve(&bearer, &secrets).unwrap();
+        assert!(!format!("{:?}", injection).contains("s3cret"));
+    }
+
+    #[test]
+    fn header_of_the_route_is_set() {
+        let mut simulator = simulator(CONFIG);
+        let mut exchange = simulator.exchange();
+
+        let action = exchange.request_headers(vec![(":path", "/partners/orders/1")]);
+        assert_eq!(action, Action::Continue);
+        assert_eq!(
+            simulator.host().request_headers(),
+            vec![
+                header(":path", "/partners/orders/1"),
+                header("authorization", "Bearer s3cret"),
+            ]
+        );
+    }
+
+    #[test]
+    fn credentials_of_the_client_are_overwritten() {
+        let mut simulator = simulator(CONFIG);
+
+        let mut exchange = simulator.exchange();
+        exchange.request_headers(vec![
+            (":path", "/partners/orders"),
+            ("Authorization", "Bearer forged"),
+        ]);
+        assert_eq!(
+            simulator.host().request_headers(),
+            vec![
+                header(":path", "/partners/orders"),
+                header("authorization", "Bearer s3cret"),
+            ]
+        );
+        drop(exchange);
+
+        let mut exchange = simulator.exchange();
+        exchange.request_headers(vec![(":path", "/search?q=a%20b&key=forged")]);
+        assert_eq!(
+            simulator.host().request_headers(),
+            vec![header(":path", "/search?q=a%20b&key=k3y")]
+        );
+    }
+
+    #[test]
+    fn requests_of_other_routes_are_forwarded_as_they_are() {
+        let mut simulator = simulator(CONFIG);
+        let mut exchange = simulator.exchange();
+
+        exchange.request_headers(vec![(":path", "/health"), ("authorization", "Basic abc")]);
+        assert_eq!(
+            simulator.host().request_headers(),
+            vec![
+                header(":path", "/health"),
+                header("authorization", "Basic abc")
+            ]
+        );
+    }
+
+    #[test]
+    fn configuration_with_an_empty_secret_rejects_the_requests() {
+        let config = br#"{
+            "secrets": {"partner-token": ""},
+            "routes": [{"path": "/partners", "credentials": {"type": "bearer", "token": "partner-token"}}]
+        }"#;
+        let mut simulator = simulator(config);
+        let mut exchange = simulator.exchange();
+
+        assert_eq!(
+            exchange.request_headers(vec![(":path", "/partners")]),
+            Action::Pause
+        );
+        assert_eq!(simulator.host().local_response().unwrap().status_code, 503);
+    }
+
+    #[test]
+    fn configuration_without_secrets_is_rejected() {
+        let config = br#"{"routes": []}"#;
+        let mut simulator = simulator(config);
+        let mut exchange = simulator.exchange();
+
+        exchange.request_headers(vec![(":path", "/partners")]);
+        assert_eq!(simulator.host().local_response().unwrap().status_code, 503);
+    }
+}
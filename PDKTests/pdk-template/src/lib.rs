@@ -3,10 +3,10 @@
 {% if useconfig -%}
 mod config;
 
-use anyhow::Result;
 use pdk::api::classy::bootstrap::Launcher;
 use pdk::api::classy::event::{Exchange, HeadersAccessor, RequestHeaders};
 use pdk::api::classy::Configuration;
+use pdk::api::error::PolicyError;
 use pdk::api::logger;
 use crate::config::Config;
 
@@ -28,7 +28,7 @@ async fn filter(exchange: Exchange<RequestHeaders>, config: &Config) {
 }
 
 #[pdk::api::entrypoint]
-async fn configure(launcher: Launcher, Configuration(bytes): Configuration) -> Result<()> {
+async fn configure(launcher: Launcher, Configuration(bytes): Configuration) -> Result<(), PolicyError> {
     let config = serde_json::from_slice(&bytes)?;
     launcher.launch(|e| filter(e, &config)).await?;
     Ok(())
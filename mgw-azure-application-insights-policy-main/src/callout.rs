@@ -0,0 +1,112 @@
+use classy::client::CalloutTarget;
+use classy::client::Endpoint;
+use classy::client::Selection;
+use serde::Deserialize;
+use std::time::Duration;
+
+use crate::tracking::AI_SERVICE_HOST_SUFFIX;
+use crate::tracking::AI_SERVICE_NAME;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(15);
+
+
+// a callout endpoint as configured in the policy, selected through the classy CalloutTarget
+#[derive(Clone, Deserialize, Debug, PartialEq, Eq)]
+pub struct EndpointConfig {
+    pub service: String,
+    pub authority: String,
+    pub port: Option<u16>,
+
+    #[serde(alias = "timeoutMs")]
+    pub timeout_ms: Option<u64>,
+
+    // share of the tracking calls while round-robin balancing, 1 by default
+    #[serde(default)]
+    pub weight: Option<u32>,
+}
+
+impl EndpointConfig {
+
+    // the default endpoint, following the naming of the upstreams registered in tracking.rs
+    pub fn for_region(region: &str) -> EndpointConfig {
+        EndpointConfig {
+            service: format!("{}-{}.default.svc", AI_SERVICE_NAME, region),
+            authority: format!("{}.{}", region, AI_SERVICE_HOST_SUFFIX),
+            port: None,
+            timeout_ms: None,
+            weight: None,
+        }
+    }
+
+    pub fn endpoint(&self) -> Endpoint {
+        let endpoint = Endpoint::new(self.service.as_str(), self.authority.as_str())
+            .with_timeout(self.timeout_ms.map(Duration::from_millis).unwrap_or(DEFAULT_TIMEOUT))
+            .with_weight(self.weight.unwrap_or(1));
+
+        match self.port {
+            Some(port) => endpoint.with_port(port),
+            None => endpoint
+        }
+    }
+}
+
+
+// how the tracking calls are spread over the healthy endpoints
+#[derive(Clone, Copy, Deserialize, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum Balancing {
+    // the primary endpoint, the alternates only while it is unhealthy
+    #[default]
+    Failover,
+    // every healthy endpoint by its weight
+    RoundRobin
+}
+
+impl Balancing {
+    fn selection(self) -> Selection {
+        match self {
+            Balancing::Failover => Selection::Failover,
+            Balancing::RoundRobin => Selection::RoundRobin
+        }
+    }
+}
+
+
+// the primary endpoint with the alternates, balanced as configured
+pub fn callout_target(primary: &EndpointConfig, alternates: &[EndpointConfig], balancing: Balancing) -> CalloutTarget {
+    CalloutTarget::new(primary.endpoint())
+        .with_alternates(alternates.iter().map(EndpointConfig::endpoint))
+        .selection(balancing.selection())
+}
+
+
+#[test]
+fn test_configured_endpoints() {
+    let config: EndpointConfig = serde_json::from_str(r#"{"service": "ai.svc", "authority": "ai.example.com", "port": 8443, "timeoutMs": 500}"#).unwrap();
+    let endpoint = config.endpoint();
+    assert_eq!(endpoint.name(), "ai.svc");
+    assert_eq!(endpoint.authority(), "ai.example.com:8443");
+    assert_eq!(endpoint.timeout(), Some(Duration::from_millis(500)));
+
+    assert_eq!(endpoint.weight(), 1);
+
+    let endpoint = EndpointConfig::for_region("westeurope").endpoint();
+    assert_eq!(endpoint.timeout(), Some(DEFAULT_TIMEOUT));
+}
+
+#[test]
+fn test_weighted_round_robin() {
+    let endpoints: Vec<EndpointConfig> = serde_json::from_str(r#"[
+        {"service": "a.svc", "authority": "a.example.com", "weight": 2},
+        {"service": "b.svc", "authority": "b.example.com"}
+    ]"#).unwrap();
+    let balancing: Balancing = serde_json::from_str(r#""roundRobin""#).unwrap();
+    let target = callout_target(&endpoints[0], &endpoints[1..], balancing);
+
+    let now = std::time::SystemTime::now();
+    let selected: Vec<String> = (0..3).map(|_| target.select(now).name().to_string()).collect();
+    assert_eq!(selected, vec!["a.svc", "b.svc", "a.svc"]);
+
+    let failover = callout_target(&endpoints[0], &endpoints[1..], Balancing::default());
+    assert!((0..3).all(|_| failover.select(now).name() == "a.svc"));
+}
@@ -0,0 +1,63 @@
+use flate2::write::GzEncoder;
+use serde::Deserialize;
+use std::io::Write;
+
+// bodies smaller than this are sent as they are, compressing them saves little
+const DEFAULT_THRESHOLD_BYTES: usize = 1024;
+
+
+// gzip compression of the track request bodies, accepted by the ingestion endpoint
+#[derive(Clone, Deserialize, Debug)]
+pub struct Compression {
+    #[serde(default)]
+    pub enabled: bool,
+
+    #[serde(alias = "thresholdBytes", default = "default_threshold_bytes")]
+    pub threshold_bytes: usize
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold_bytes: DEFAULT_THRESHOLD_BYTES
+        }
+    }
+}
+
+fn default_threshold_bytes() -> usize {
+    DEFAULT_THRESHOLD_BYTES
+}
+
+impl Compression {
+
+    // the gzipped body when enabled and above the threshold, None to send it as it is
+    pub fn compress(&self, body: &[u8]) -> Option<Vec<u8>> {
+        if !self.enabled || body.len() < self.threshold_bytes {
+            return None;
+        }
+
+        let mut encoder = GzEncoder::new(Vec::with_capacity(body.len() / 4), flate2::Compression::default());
+        encoder.write_all(body).and_then(|_| encoder.finish()).ok()
+    }
+}
+
+
+#[test]
+fn test_compress_above_threshold() {
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    let body = "{\"name\":\"Microsoft.ApplicationInsights.Request\"}".repeat(100);
+    let compression = Compression { enabled: true, threshold_bytes: 1024 };
+
+    let compressed = compression.compress(body.as_bytes()).unwrap();
+    assert!(compressed.len() < body.len());
+
+    let mut decompressed = String::new();
+    GzDecoder::new(compressed.as_slice()).read_to_string(&mut decompressed).unwrap();
+    assert_eq!(decompressed, body);
+
+    assert_eq!(compression.compress(&body.as_bytes()[..100]), None);
+    assert_eq!(Compression::default().compress(body.as_bytes()), None);
+}
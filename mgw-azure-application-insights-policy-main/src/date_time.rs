@@ -1,29 +1,259 @@
 
-use astrolabe::DateTime;
-use astrolabe::Precision;
+use std::time::Duration;
 use std::time::SystemTime;
 use std::time::UNIX_EPOCH;
 use sha1::{Digest, Sha1};
 
 
-pub fn format_duration(duration_ms: u64) -> String {
-    let duration = std::time::Duration::from_millis(duration_ms);
+// formats as DD.HH:MM:SS.MMMMMM
+pub fn format_duration(duration: Duration) -> String {
     let seconds = duration.as_secs();
 
     let days = seconds / 86400;
     let hours = (seconds % 86400) / 3600;
     let minutes = (seconds % 3600) / 60;
     let seconds = seconds % 60;
-    let milliseconds = duration.subsec_micros();
+    let microseconds = duration.subsec_micros();
 
-    format!("{:02}.{:02}:{:02}:{:02}.{:06}", days, hours, minutes, seconds, milliseconds)
+    format!("{:02}.{:02}:{:02}:{:02}.{:06}", days, hours, minutes, seconds, microseconds)
 }
 
+// the telemetry envelopes time, in UTC with milliseconds
 pub fn to_iso8601_utc(now: SystemTime) -> String {
-    let timestamp =  now.duration_since(UNIX_EPOCH).unwrap().as_secs();
-    let date_time = DateTime::from_timestamp(timestamp.try_into().unwrap()).unwrap();
-    
-    date_time.format_rfc3339(Precision::Millis)
+    to_rfc3339(now, UtcOffset::UTC, Precision::Millis)
+}
+
+
+// digits of the fraction of the seconds
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Precision {
+    Seconds,
+    Millis,
+    Micros,
+    Nanos
+}
+
+
+// fixed offset from UTC, as in the +02:00 of a timestamp
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UtcOffset {
+    minutes: i32
+}
+
+impl UtcOffset {
+    pub const UTC: UtcOffset = UtcOffset { minutes: 0 };
+
+    pub fn from_minutes(minutes: i32) -> Option<UtcOffset> {
+        if minutes.abs() < 24 * 60 {
+            Some(UtcOffset { minutes })
+        } else {
+            None
+        }
+    }
+
+    // parses Z, +HH:MM, -HH:MM or +HHMM
+    pub fn parse(text: &str) -> Option<UtcOffset> {
+        if text.eq_ignore_ascii_case("z") {
+            return Some(UtcOffset::UTC);
+        }
+
+        let sign = match text.as_bytes().first()? {
+            b'+' => 1,
+            b'-' => -1,
+            _ => return None
+        };
+        let digits = text[1..].replacen(':', "", 1);
+        if digits.len() != 4 || !digits.bytes().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+
+        let hours: i32 = digits[..2].parse().ok()?;
+        let minutes: i32 = digits[2..].parse().ok()?;
+        if minutes >= 60 {
+            return None;
+        }
+        UtcOffset::from_minutes(sign * (hours * 60 + minutes))
+    }
+
+    fn seconds(&self) -> i64 {
+        self.minutes as i64 * 60
+    }
+}
+
+
+impl Precision {
+
+    fn digits(&self) -> u32 {
+        match self {
+            Precision::Seconds => 0,
+            Precision::Millis => 3,
+            Precision::Micros => 6,
+            Precision::Nanos => 9
+        }
+    }
+}
+
+
+// formats as 2023-01-31T23:59:59.123+01:00, or with a Z in UTC, the fraction rounded half up
+// to the precision, so 59.9995 seconds are the next minute in milliseconds
+pub fn to_rfc3339(time: SystemTime, offset: UtcOffset, precision: Precision) -> String {
+    let (seconds, nanos) = to_unix(time);
+
+    let digits = precision.digits();
+    let unit = 10u32.pow(9 - digits);
+    let rounded = (nanos + unit / 2) / unit;
+    let (seconds, fraction) = if rounded == 10u32.pow(digits) {
+        (seconds + 1, 0)
+    } else {
+        (seconds, rounded)
+    };
+    let local = seconds + offset.seconds();
+
+    let (year, month, day) = civil_from_days(local.div_euclid(86400));
+    let second_of_day = local.rem_euclid(86400);
+
+    let fraction = match digits {
+        0 => String::new(),
+        digits => format!(".{:0width$}", fraction, width = digits as usize)
+    };
+
+    let zone = if offset.minutes == 0 {
+        "Z".to_string()
+    } else {
+        let sign = if offset.minutes < 0 { '-' } else { '+' };
+        let minutes = offset.minutes.abs();
+        format!("{}{:02}:{:02}", sign, minutes / 60, minutes % 60)
+    };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}{}",
+        year, month, day,
+        second_of_day / 3600, (second_of_day % 3600) / 60, second_of_day % 60,
+        fraction, zone
+    )
+}
+
+// parses an RFC3339 timestamp, a leap second is read as the first second of the next minute
+pub fn parse_rfc3339(text: &str) -> Option<SystemTime> {
+    let text = text.trim();
+    if text.len() < 20 || !text.is_char_boundary(19) {
+        return None;
+    }
+    let (date_time, rest) = text.split_at(19);
+    let bytes = date_time.as_bytes();
+    let separators = [(4, b'-'), (7, b'-'), (13, b':'), (16, b':')];
+    if separators.iter().any(|(index, separator)| bytes[*index] != *separator)
+        || !matches!(bytes[10], b'T' | b't' | b' ') {
+        return None;
+    }
+
+    let number = |from: usize, to: usize| -> Option<u32> {
+        let digits = &date_time[from..to];
+        if digits.bytes().all(|c| c.is_ascii_digit()) { digits.parse().ok() } else { None }
+    };
+    let year = number(0, 4)? as i64;
+    let month = number(5, 7)?;
+    let day = number(8, 10)?;
+    let hour = number(11, 13)?;
+    let minute = number(14, 16)?;
+    let second = number(17, 19)?;
+
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month)
+        || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    // the fraction of the seconds, keeping up to nanoseconds
+    let (fraction, zone) = match rest.strip_prefix('.') {
+        Some(rest) => {
+            let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+            if digits == 0 {
+                return None;
+            }
+            let kept = &rest[..digits.min(9)];
+            let nanos: u32 = kept.parse().ok()?;
+            (nanos * 10u32.pow(9 - kept.len() as u32), &rest[digits..])
+        },
+        None => (0, rest)
+    };
+    let offset = UtcOffset::parse(zone)?;
+
+    let seconds = days_from_civil(year, month, day) * 86400
+        + (hour * 3600 + minute * 60 + second) as i64
+        - offset.seconds();
+    from_unix(seconds, fraction)
+}
+
+// the milliseconds since the epoch, as the dates of the PEL expressions, truncated towards the past
+pub fn to_epoch_millis(time: SystemTime) -> i64 {
+    let (seconds, nanos) = to_unix(time);
+    seconds * 1000 + (nanos / 1_000_000) as i64
+}
+
+pub fn from_epoch_millis(millis: i64) -> Option<SystemTime> {
+    from_unix(millis.div_euclid(1000), (millis.rem_euclid(1000) * 1_000_000) as u32)
+}
+
+// seconds since the epoch, negative before it, and the nanoseconds after them
+fn to_unix(time: SystemTime) -> (i64, u32) {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => (since.as_secs() as i64, since.subsec_nanos()),
+        Err(err) => {
+            let before = err.duration();
+            match before.subsec_nanos() {
+                0 => (-(before.as_secs() as i64), 0),
+                nanos => (-(before.as_secs() as i64) - 1, 1_000_000_000 - nanos)
+            }
+        }
+    }
+}
+
+fn from_unix(seconds: i64, nanos: u32) -> Option<SystemTime> {
+    if seconds >= 0 {
+        UNIX_EPOCH.checked_add(Duration::new(seconds as u64, nanos))
+    } else {
+        UNIX_EPOCH.checked_sub(Duration::from_secs(seconds.unsigned_abs()))?
+            .checked_add(Duration::from_nanos(nanos as u64))
+    }
+}
+
+fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31
+    }
+}
+
+// the civil date of the days since the epoch, from http://howardhinnant.github.io/date_algorithms.html
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 } as u32;
+    let year = year_of_era + era * 400;
+
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let shifted_month = (month as i64 + 9) % 12;
+    let day_of_year = (153 * shifted_month + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
 }
 
 pub fn uuid(now: SystemTime) -> String {
@@ -40,8 +270,90 @@ pub fn uuid(now: SystemTime) -> String {
     format!("sha1 hash: {}-{}-{}-{}-{}", part1, part2, part3, part4, part5)       
 }
 
+#[test]
+fn test_format_duration() {
+    assert_eq!(format_duration(Duration::from_micros(1_500)), "00.00:00:00.001500");
+    assert_eq!(format_duration(Duration::from_millis(90_061_250)), "01.01:01:01.250000");
+}
+
 #[test]
 fn test_now() {
     println!("now: {}", to_iso8601_utc(SystemTime::now()));
 }
 
+#[test]
+fn test_to_rfc3339() {
+    let time = UNIX_EPOCH + Duration::new(1_675_209_599, 123_456_789);
+    assert_eq!(to_iso8601_utc(time), "2023-01-31T23:59:59.123Z");
+    assert_eq!(to_rfc3339(time, UtcOffset::UTC, Precision::Seconds), "2023-01-31T23:59:59Z");
+    assert_eq!(to_rfc3339(time, UtcOffset::UTC, Precision::Nanos), "2023-01-31T23:59:59.123456789Z");
+
+    // the offset rolls the date over
+    let plus_one = UtcOffset::parse("+01:00").unwrap();
+    assert_eq!(to_rfc3339(time, plus_one, Precision::Millis), "2023-02-01T00:59:59.123+01:00");
+    let minus = UtcOffset::parse("-0930").unwrap();
+    assert_eq!(to_rfc3339(UNIX_EPOCH, minus, Precision::Seconds), "1969-12-31T14:30:00-09:30");
+
+    // leap years and dates before the epoch
+    let leap_day = UNIX_EPOCH + Duration::from_secs(951_782_400);
+    assert_eq!(to_rfc3339(leap_day, UtcOffset::UTC, Precision::Seconds), "2000-02-29T00:00:00Z");
+    let before = UNIX_EPOCH - Duration::from_millis(1);
+    assert_eq!(to_iso8601_utc(before), "1969-12-31T23:59:59.999Z");
+}
+
+#[test]
+fn test_parse_rfc3339() {
+    let time = UNIX_EPOCH + Duration::new(1_675_209_599, 123_000_000);
+    assert_eq!(parse_rfc3339("2023-01-31T23:59:59.123Z"), Some(time));
+    assert_eq!(parse_rfc3339("2023-02-01T00:59:59.123+01:00"), Some(time));
+    assert_eq!(parse_rfc3339("2023-01-31 23:59:59.1230000000z"), Some(time));
+
+    // the leap second is the first second of the next minute
+    let leap_second = parse_rfc3339("2016-12-31T23:59:60Z").unwrap();
+    assert_eq!(to_rfc3339(leap_second, UtcOffset::UTC, Precision::Seconds), "2017-01-01T00:00:00Z");
+
+    assert_eq!(parse_rfc3339("2023-02-29T00:00:00Z"), None);
+    assert_eq!(parse_rfc3339("2023-01-31T24:00:00Z"), None);
+    assert_eq!(parse_rfc3339("2023-01-31T23:59:59"), None);
+    assert_eq!(parse_rfc3339("2023-01-31T23:59:59.Z"), None);
+    assert_eq!(parse_rfc3339("2023-01-31T23:59:59+25:00"), None);
+}
+
+#[test]
+fn test_epoch_millis() {
+    let time = parse_rfc3339("1969-12-31T23:59:59.750Z").unwrap();
+    assert_eq!(to_epoch_millis(time), -250);
+    assert_eq!(from_epoch_millis(-250), Some(time));
+    assert_eq!(from_epoch_millis(1_675_209_599_123), parse_rfc3339("2023-01-31T23:59:59.123Z"));
+}
+
+
+#[test]
+fn test_leap_second() {
+    // the :60 second crosses the year, with its fraction and offset kept
+    let leap_second = parse_rfc3339("2016-12-31T23:59:60.500Z").unwrap();
+    assert_eq!(to_iso8601_utc(leap_second), "2017-01-01T00:00:00.500Z");
+    assert_eq!(parse_rfc3339("2017-01-01T00:59:60.500+01:00"), Some(leap_second));
+    assert_eq!(parse_rfc3339("2016-12-31T23:59:60-00:30"), parse_rfc3339("2017-01-01T00:30:00Z"));
+
+    assert_eq!(parse_rfc3339("2016-12-31T23:59:61Z"), None);
+}
+
+#[test]
+fn test_millis_rounding() {
+    // 999.5 milliseconds round up to the next second, crossing midnight and the year
+    let new_year = parse_rfc3339("2023-01-01T00:00:00Z").unwrap();
+    let last_millis = new_year - Duration::from_micros(500);
+    assert_eq!(to_iso8601_utc(last_millis), "2023-01-01T00:00:00.000Z");
+    assert_eq!(to_rfc3339(last_millis, UtcOffset::parse("-01:00").unwrap(), Precision::Millis), "2022-12-31T23:00:00.000-01:00");
+    assert_eq!(to_rfc3339(last_millis, UtcOffset::UTC, Precision::Micros), "2022-12-31T23:59:59.999500Z");
+    assert_eq!(to_iso8601_utc(last_millis - Duration::from_nanos(1)), "2022-12-31T23:59:59.999Z");
+
+    let midnight = parse_rfc3339("2023-01-31T23:59:59.9995Z").unwrap();
+    assert_eq!(to_iso8601_utc(midnight), "2023-02-01T00:00:00.000Z");
+    assert_eq!(to_rfc3339(midnight, UtcOffset::UTC, Precision::Seconds), "2023-02-01T00:00:00Z");
+
+    // before the epoch too
+    assert_eq!(to_iso8601_utc(UNIX_EPOCH - Duration::from_micros(500)), "1970-01-01T00:00:00.000Z");
+    assert_eq!(to_iso8601_utc(UNIX_EPOCH - Duration::from_micros(501)), "1969-12-31T23:59:59.999Z");
+}
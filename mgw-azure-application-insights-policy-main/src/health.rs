@@ -0,0 +1,27 @@
+use pdk_core::http::health::HealthChecks;
+use serde::Deserialize;
+
+
+// the health probes as told by the pdk, left out of the tracking unless track is set
+#[derive(Default, Clone, Deserialize, Debug)]
+pub struct HealthTracking {
+    #[serde(default)]
+    pub track: bool,
+
+    #[serde(flatten)]
+    pub checks: HealthChecks
+}
+
+
+#[test]
+fn test_health_probes() {
+    let health: HealthTracking = serde_json::from_str(r#"{"paths": ["/status"], "userAgents": ["monitor/"]}"#).unwrap();
+    assert!(!health.track);
+    assert!(health.checks.matches("/status", None));
+    assert!(health.checks.matches("/orders", Some("monitor/1.0")));
+    assert!(!health.checks.matches("/healthz", None));
+
+    let health: HealthTracking = serde_json::from_str(r#"{"track": true}"#).unwrap();
+    assert!(health.track);
+    assert!(health.checks.matches("/healthz", None));
+}
@@ -1,31 +1,61 @@
 mod tracking;
 mod model;
+// the parsing, offsets and epoch conversions are there for the PEL dates, the envelopes are
+// only formatted in UTC
+#[allow(dead_code)]
 mod date_time;
-
+mod callout;
+mod health;
+mod compression;
+mod queue;
+mod retry;
+mod status;
+mod tracing;
+
+use classy::client::CalloutTarget;
 use log::debug;
 use log::error;
 use log::info;
 use log::warn;
+use model::RemoteDependencyData;
 use model::RequestData;
-use model::TrackResponse;
 use proxy_wasm::traits::*;
 use proxy_wasm::types::*;
 use serde::Deserialize;
+use serde::Serialize;
 use std::time::Duration;
+use std::time::SystemTime;
 
+use crate::callout::Balancing;
+use crate::callout::EndpointConfig;
+use crate::compression::Compression;
+use crate::queue::QueueConfig;
+use crate::retry::RetryConfig;
 use crate::date_time::format_duration;
 use crate::date_time::uuid;
-use crate::tracking::AI_SERVICE_HOST_SUFFIX;
-use crate::tracking::AI_SERVICE_NAME;
-use crate::tracking::AI_SERVICE_PATH;
+use crate::health::HealthTracking;
+use crate::status::SuccessStatus;
+use crate::tracking::Batching;
+use crate::tracking::Tracker;
+use crate::tracking::CONFIGURATION_ERROR;
+use crate::tracking::MALFORMED_RESPONSE;
+use crate::tracking::SERIALIZATION_ERROR;
+use crate::tracing::TraceContext;
+use crate::model::TrackDependency;
 use crate::model::TrackRequest;
 
+// filter state keys of the durations, read by the next filters and by PEL expressions
+// as attributes.filterState['appinsights.totalDurationMs']
+const TOTAL_DURATION_KEY: &str = "appinsights.totalDurationMs";
+const UPSTREAM_DURATION_KEY: &str = "appinsights.upstreamDurationMs";
+
 
 proxy_wasm::main! {{
     proxy_wasm::set_log_level(LogLevel::Trace);
     proxy_wasm::set_root_context(|_| -> Box<dyn RootContext> {
         Box::new(PolicyRootContext {
             config: PolicyConfig::default(),
+            tracker: None,
         })
     });
 }}
@@ -33,6 +63,7 @@ proxy_wasm::main! {{
 
 struct PolicyRootContext {
     config: PolicyConfig,
+    tracker: Option<Tracker>,
 }
 
 
@@ -51,26 +82,144 @@ struct PolicyConfig {
     request_id_header: String,
 
     #[serde(alias = "correlationIdHeader")]
-    correlation_id_header: String
+    correlation_id_header: String,
+
+    // overrides the upstream derived from the azure region
+    #[serde(default)]
+    upstream: Option<EndpointConfig>,
+
+    #[serde(alias = "alternateUpstreams", default)]
+    alternate_upstreams: Vec<EndpointConfig>,
+
+    // failover to the alternates by default, or round-robin by the weights of the upstreams
+    #[serde(default)]
+    balancing: Balancing,
+
+    // health probes are not tracked by default
+    #[serde(alias = "healthChecks", default)]
+    health_checks: HealthTracking,
+
+    // statuses tracked as successful, 1xx to 3xx by default
+    #[serde(alias = "successStatus", default)]
+    success_status: SuccessStatus,
+
+    // gzip of the track request bodies above the threshold, disabled by default
+    #[serde(default)]
+    compression: Compression,
+
+    // tracking items kept while the upstream is unreachable
+    #[serde(default)]
+    queue: QueueConfig,
+
+    // retries of the failed tracking calls, with an exponential backoff
+    #[serde(default)]
+    retry: RetryConfig,
+
+    // tracking items sent in batches, disabled by default
+    #[serde(default)]
+    batching: Batching,
+
+    // the calls to the upstream tracked as dependencies, enabled by default
+    #[serde(alias = "trackDependencies", default = "default_track_dependencies")]
+    track_dependencies: bool
 }
 
-impl Context for PolicyRootContext {}
+fn default_track_dependencies() -> bool {
+    true
+}
+
+impl PolicyConfig {
+
+    // get region prefix from configured region
+    fn region(&self) -> String {
+        self.azure_region.replace(" ", "").replace("(", "").replace(")", "").to_lowercase()
+    }
+
+    // the keys of a configuration failing to parse, to report the failure when they are valid
+    fn partial(config_bytes: &[u8]) -> Option<PolicyConfig> {
+        let config: serde_json::Value = serde_json::from_slice(config_bytes).ok()?;
+        let field = |name: &str| config.get(name).and_then(|value| value.as_str()).map(str::to_string);
+
+        Some(PolicyConfig {
+            azure_region: field("azureRegion")?,
+            api_key: field("apiKey")?,
+            instrumentation_key: field("instrumentationKey")?,
+            ..PolicyConfig::default()
+        })
+    }
+
+    fn callout_target(&self) -> CalloutTarget {
+        let primary = match &self.upstream {
+            Some(upstream) => upstream.clone(),
+            None => EndpointConfig::for_region(&self.region())
+        };
+
+        callout::callout_target(&primary, &self.alternate_upstreams, self.balancing)
+    }
+}
+
+impl Context for PolicyRootContext {
+
+    // Handler of the tracking calls, all sent on the ticks
+    fn on_http_call_response(&mut self, token_id: u32, _: usize, body_size: usize, _: usize) {
+        if let Some(tracker) = &self.tracker {
+            let status = self.get_http_call_response_header(":status").unwrap_or_default();
+            let body = self.get_http_call_response_body(0, body_size);
+            tracker.complete(token_id, &status, body, self.get_current_time());
+        }
+    }
+}
+
+impl PolicyRootContext {
+
+    // reports the invalid configuration right away, the policy does not start
+    fn track_configuration_error(&self, config_bytes: &[u8], err: &serde_json::Error) {
+        if let Some(config) = PolicyConfig::partial(config_bytes) {
+            let tracker = Tracker::new(&config);
+            let now = self.get_current_time();
+            tracker.track_exception(CONFIGURATION_ERROR, &format!("Invalid policy configuration: {}", err), None, now);
+            tracker.flush(now);
+        }
+    }
+}
 
 impl RootContext for PolicyRootContext {
 
     fn on_configure(&mut self, _: usize) -> bool {
         if let Some(config_bytes) = self.get_plugin_configuration() {
-            self.config = serde_json::from_slice(config_bytes.as_slice()).unwrap()
+            match serde_json::from_slice(config_bytes.as_slice()) {
+                Ok(config) => self.config = config,
+                Err(err) => {
+                    error!("Invalid policy configuration: {}", err);
+                    self.track_configuration_error(&config_bytes, &err);
+                    return false;
+                }
+            }
         }
         info!("Policy configuration values: {:?}", self.config);
+        self.tracker = Some(Tracker::new(&self.config));
+
+        // sends the tracked items on every interval
+        self.set_tick_period(self.config.batching.flush_interval());
         true
     }
 
+    fn on_tick(&mut self) {
+        if let Some(tracker) = &self.tracker {
+            tracker.flush(self.get_current_time());
+        }
+    }
+
     fn create_http_context(&self, _: u32) -> Option<Box<dyn HttpContext>> {
         Some(Box::new(CustomHttpContext {
             config: self.config.clone(),
+            tracker: self.tracker.clone().unwrap_or_else(|| Tracker::new(&self.config)),
             correlation_id: None,
-            traceparent: None,
+            trace: None,
+            dependency: None,
+            health_check: false,
+            path: String::default(),
+            request_start: None,
             request_data: RequestData::default()
         }))
     }
@@ -82,39 +231,50 @@ impl RootContext for PolicyRootContext {
 
 struct CustomHttpContext {
     config: PolicyConfig,
+    tracker: Tracker,
     correlation_id: Option<String>,
-    traceparent: Option<String>,
+    // the span of the request, its traceparent is propagated to the upstream
+    trace: Option<TraceContext>,
+    // the span of the call to the upstream, a child of the request one, None when the
+    // dependencies are not tracked
+    dependency: Option<TraceContext>,
+    health_check: bool,
+    path: String,
+    request_start: Option<SystemTime>,
     request_data: RequestData
 }
 
 
-impl Context for CustomHttpContext {
+impl Context for CustomHttpContext {}
 
-    // Handler of the external service call
-    fn on_http_call_response(&mut self, _: u32, _: usize, body_size: usize, _: usize) {
-        
-        // process the auth service response body
-        if let Some(body) = self.get_http_call_response_body(0, body_size) {
-
-            // get the http status code
-            let response_status = self.get_http_call_response_header(":status").unwrap();
-            
-
-            // validate http status
-            if response_status != "200" {
-                // parse response body as raw string
-                let payload: String = serde_json::from_slice(body.as_slice()).unwrap();
-                error!("Azure Application Insights track request error: {:?}", payload);
-            }
-            else {
-                // parse response body as TrackResponse
-                let payload: TrackResponse = serde_json::from_slice(body.as_slice()).unwrap();
-                debug!("Azure response payload: {:?}", payload);
-
-                let rejected = payload.items_received - payload.items_accepted;
-                if rejected != 0 {
-                    warn!("{} tracking items rejected, errors: {:?} ", rejected, payload.errors);
-                }
+
+impl CustomHttpContext {
+
+    // the address of the upstream called, the authority of the request when unknown
+    fn upstream_target(&self) -> String {
+        self.get_property(vec!["upstream", "address"])
+            .and_then(|address| String::from_utf8(address).ok())
+            .filter(|address| !address.is_empty())
+            .unwrap_or_else(|| self.get_http_request_header(":authority").unwrap_or_default())
+    }
+
+    // tracks the duration as a custom dimension and exposes it in the filter state
+    fn set_duration(&mut self, key: &str, dimension: &str, duration: Duration) {
+        let millis = duration.as_millis().to_string();
+        if let Err(err) = proxy_wasm::hostcalls::set_property(vec![key], Some(millis.as_bytes())) {
+            warn!("Error setting the filter state {}: {:?}", key, err);
+        }
+        self.request_data.properties.insert(dimension.to_string(), millis);
+    }
+
+    // adds the serialized item, a failure is tracked as an exception instead
+    fn serialize<T: Serialize>(&self, item: &T, kind: &str, items: &mut Vec<String>) {
+        match serde_json::to_string(item) {
+            Ok(item) => items.push(item),
+            Err(err) => {
+                let message = format!("Error serializing the {} tracking: {}", kind, err);
+                error!("{}", message);
+                self.tracker.track_exception(SERIALIZATION_ERROR, &message, self.trace.as_ref(), self.get_current_time());
             }
         }
     }
@@ -124,6 +284,21 @@ impl Context for CustomHttpContext {
 impl HttpContext for CustomHttpContext {
 
     fn on_http_request_headers(&mut self, _num_headers: usize, _end_of_stream: bool) -> Action {
+
+        // leaves the health probes out, unless they are tracked
+        let health_checks = &self.config.health_checks;
+        if !health_checks.track {
+            let path = self.get_http_request_header(":path").unwrap_or_default();
+            let user_agent = self.get_http_request_header("user-agent");
+            if health_checks.checks.matches(&path, user_agent.as_deref()) {
+                debug!("Not tracking health probe: {}", path);
+                self.health_check = true;
+                return Action::Continue;
+            }
+        }
+
+        // the start of the request, for the duration including the gateway side
+        self.request_start = Some(self.get_current_time());
         
         // gets the request id or generates a uuid
         let request_id_header = self.config.request_id_header.as_str();
@@ -132,8 +307,21 @@ impl HttpContext for CustomHttpContext {
             None => uuid(self.get_current_time())
         };
 
-        // propagates the current request-id as the upstream's traceparent
-        self.set_http_request_header("traceparent", Some(&request_id));
+        // continues the trace of the caller, or starts one, with a span for this hop
+        let now = self.get_current_time();
+        let tracestate = self.get_http_request_header("tracestate");
+        let trace = match self.get_http_request_header("traceparent")
+            .and_then(|traceparent| TraceContext::parse(&traceparent, tracestate.as_deref())) {
+            Some(parent) => parent.child(now, &request_id),
+            None => TraceContext::root(now, &request_id)
+        };
+
+        // propagates the span of the call to the upstream as its parent, the request one when the
+        // dependencies are not tracked, as no telemetry of that span is sent
+        let dependency = Some(trace.child(now, &request_id)).filter(|_| self.config.track_dependencies);
+        let parent = dependency.as_ref().unwrap_or(&trace);
+        self.set_http_request_header("traceparent", Some(&parent.traceparent()));
+        self.set_http_request_header("tracestate", parent.tracestate.as_deref());
 
         info!("Processing request id: {}", request_id);
         
@@ -153,21 +341,25 @@ impl HttpContext for CustomHttpContext {
             }
         };
 
-        // get the traceparent
-        match self.get_http_request_header("traceparent") {
-            Some(value) => self.traceparent = Some(value),
-            None => {},
-        };
+        // keeps the path to pick the route of the success statuses
+        self.path = self.get_http_request_header(":path").unwrap_or_default();
 
-        // initializing the request data
+        // initializing the request data, identified by its span as App Insights correlates the
+        // telemetry by their W3C trace context
         self.request_data = RequestData::from_request_headers(
-            request_id.clone(),
-            self.get_http_request_header(":method").unwrap(), 
-            self.get_http_request_header(":scheme").unwrap(),
-            self.get_http_request_header(":authority").unwrap(),
-            self.get_http_request_header(":path").unwrap(),
+            trace.span_id.clone(),
+            self.get_http_request_header(":method").unwrap_or_default(),
+            self.get_http_request_header(":scheme").unwrap_or_default(),
+            self.get_http_request_header(":authority").unwrap_or_default(),
+            self.path.clone(),
             self.get_http_request_header("user-agent").unwrap_or("default".to_string())
         );
+        self.request_data.properties.insert("RequestId".to_string(), request_id);
+        if let Some(correlation_id) = &self.correlation_id {
+            self.request_data.properties.insert("CorrelationId".to_string(), correlation_id.clone());
+        }
+        self.trace = Some(trace);
+        self.dependency = dependency;
 
         debug!("Tracking request data initialized with: {:?}", self.request_data);
 
@@ -178,73 +370,69 @@ impl HttpContext for CustomHttpContext {
     
     fn on_http_response_headers(&mut self, _: usize, _: bool) -> Action {
 
-        info!("Processing response");
-
-        // update response status code
-        self.request_data.response_code = self.get_http_response_header(":status").unwrap();
+        if self.health_check {
+            return Action::Continue;
+        }
 
-        // update success from status < 300
-        self.request_data.success = self.request_data.response_code.parse::<i32>().unwrap().cmp(&300).is_lt();
+        info!("Processing response");
 
-        // extracts the upstream service time to set the request duration
-        let upstream_service_time: u64 = match self.get_http_response_header("x-envoy-upstream-service-time") {
-            Some(value) => value.parse::<u64>().unwrap(),
-            None => 0
+        // update response status code, a response without one is reported
+        self.request_data.response_code = match self.get_http_response_header(":status") {
+            Some(status) => status,
+            None => {
+                warn!("Response without a status");
+                let trace = self.trace.as_ref();
+                self.tracker.track_exception(MALFORMED_RESPONSE, "Upstream response without a :status", trace, self.get_current_time());
+                String::default()
+            }
         };
 
-        // format as DD.HH:MM:SS.MMMMMM
-        self.request_data.duration = format_duration(upstream_service_time);
-
-        // get region prefix from configured region
-        let region = self.config.azure_region.replace(" ", "").replace("(", "").replace(")", "").to_lowercase();
-        
-        // generates the authority from the configured region
-        let authority = format!("{}.{}", region, AI_SERVICE_HOST_SUFFIX);
-
-        // define http headers pairs
-        let headers: Vec<(&str, &str)> = vec![
-            (":method", "POST"),
-            (":authority", &authority),
-            (":path", AI_SERVICE_PATH),
-            ("x-api-key", &self.config.api_key),
-            ("content-type", "application/json")
-        ];
-
-        info!("Tracking request headers: {:?}", headers);
+        // update success from the configured statuses, the path of the request picks the route
+        let category = self.config.success_status.classify(&self.path, &self.request_data.response_code);
+        self.request_data.success = category.is_success();
+        self.request_data.properties.insert("StatusCategory".to_string(), category.name().to_string());
+
+        // the total duration since the request headers, the gateway side included
+        let now = self.get_current_time();
+        let request_start = self.request_start.unwrap_or(now);
+        let total = now.duration_since(request_start).unwrap_or_default();
+        self.request_data.duration = format_duration(total);
+        self.set_duration(TOTAL_DURATION_KEY, "TotalDurationMs", total);
+
+        // the upstream service time, missing when the upstream was not reached
+        let upstream = self.get_http_response_header("x-envoy-upstream-service-time")
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .map(Duration::from_millis);
+        if let Some(upstream) = upstream {
+            self.set_duration(UPSTREAM_DURATION_KEY, "UpstreamDurationMs", upstream);
+        }
 
+        let trace = self.trace.clone().unwrap_or_else(|| TraceContext::root(now, &self.request_data.id));
         let track_req = TrackRequest::new(
-            self.get_current_time(),
+            request_start,
             self.config.instrumentation_key.clone(),
             self.request_data.clone(),
-            self.correlation_id.clone(),
-            self.traceparent.clone()
+            &trace
         );
-    
-        let body = serde_json::to_string(&vec![&track_req]).unwrap();
-        
-        debug!("Track request body: {}", body);
-        
-        // sets the flex upstream service
-        let upstream = format!("{}-{}.default.svc", AI_SERVICE_NAME, region);
-
-        debug!("Azure App Insights upstream: {}", upstream);
-
-        // request azure app insights upstream service
-        match self.dispatch_http_call(
-            &upstream,
-            headers,
-            Some(body.as_bytes()),
-            vec![],
-            Duration::from_secs(15)
-        ){
-            Ok(_) => {
-                debug!("Tracking sent OK");
-            },
-            Err(err) => {
-                error!("Error calling App Insights API: ({:?})", err);
-            }
+        let mut items = Vec::new();
+        self.serialize(&track_req, "request", &mut items);
+
+        // the call to the upstream, when it was reached, started as long ago as it lasted
+        let dependency = self.dependency.as_ref();
+        if let (Some(upstream), Some(dependency)) = (upstream, dependency) {
+            let target = self.upstream_target();
+            let dependency_data = RemoteDependencyData::http(&self.request_data, target, format_duration(upstream));
+            let track_dependency = TrackDependency::new(
+                now.checked_sub(upstream).unwrap_or(now),
+                self.config.instrumentation_key.clone(),
+                dependency_data,
+                dependency
+            );
+            self.serialize(&track_dependency, "dependency", &mut items);
         }
 
+        // buffers the items, sent by the root context on its next tick
+        self.tracker.track(items);
 
         Action::Continue
 
@@ -1,7 +1,9 @@
+use std::collections::BTreeMap;
 use std::time::SystemTime;
 use serde::{Deserialize, Serialize};
 
 use crate::date_time::to_iso8601_utc;
+use crate::tracing::TraceContext;
 
 
 #[derive(Default, Clone, Deserialize, Debug, Serialize)]
@@ -21,7 +23,8 @@ pub struct Tags {
     #[serde(rename = "ai.operation.id")]
     pub ai_operation_id: Option<String>,
 
-    #[serde(rename = "ai.operation.name")]
+    // empty for the exceptions outside of a request
+    #[serde(rename = "ai.operation.name", skip_serializing_if = "String::is_empty", default)]
     pub ai_operation_name: String,
 
     #[serde(rename = "ai.operation.parentId")]
@@ -49,7 +52,11 @@ pub struct RequestData {
     pub response_code: String,
     
     pub source: String,
-    pub url: String
+    pub url: String,
+
+    // custom dimensions
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", default)]
+    pub properties: BTreeMap<String, String>
 }
 
 
@@ -71,23 +78,25 @@ impl RequestData {
                 success: false,
                 response_code: String::default(),
                 source,
-            url: format!("{}://{}{}", scheme, authority, path)
+                url: format!("{}://{}{}", scheme, authority, path),
+                properties: BTreeMap::new()
          }         
     }
 }
 
 
 impl TrackRequest {
-    pub fn new(time: SystemTime, instrumentation_key: String, request_data: RequestData, correlation_id: Option<String>, parent_id: Option<String>) -> Self {
+    // the request is the span of the trace context, its parent is the span of the caller
+    pub fn new(time: SystemTime, instrumentation_key: String, request_data: RequestData, trace: &TraceContext) -> Self {
         Self { 
             ver: 1, 
             name: "Microsoft.ApplicationInsights.Request".to_string(), 
             time: to_iso8601_utc(time),
             instrumentation_key,
             tags: Tags { 
-                ai_operation_id: correlation_id.clone(),
+                ai_operation_id: Some(trace.trace_id.clone()),
                 ai_operation_name: request_data.name.clone(), 
-                ai_operation_parent_id: parent_id
+                ai_operation_parent_id: trace.parent_id.clone()
             },
             data: TrackRequestData {
                 base_type: "RequestData".to_string(),
@@ -97,6 +106,184 @@ impl TrackRequest {
     }    
 }
 
+// the call of the gateway to the upstream, shown as a dependency in the application map
+#[derive(Default, Clone, Deserialize, Debug, Serialize)]
+pub struct TrackDependency {
+    pub ver: i32,
+    pub name: String,
+    pub time: String,
+
+    #[serde(rename = "iKey")]
+    pub instrumentation_key: String,
+    pub tags: Tags,
+    pub data: TrackDependencyData
+}
+
+#[derive(Default, Clone, Deserialize, Debug, Serialize)]
+pub struct TrackDependencyData {
+    #[serde(rename = "baseType")]
+    pub base_type: String,
+
+    #[serde(rename = "baseData")]
+    pub base_data: RemoteDependencyData
+}
+
+#[derive(Default, Clone, Deserialize, Debug, Serialize)]
+pub struct RemoteDependencyData {
+    pub ver: i32,
+    pub id: String,
+    pub name: String,
+    pub duration: String,
+    pub success: bool,
+
+    #[serde(rename = "resultCode")]
+    pub result_code: String,
+
+    #[serde(rename = "type")]
+    pub dependency_type: String,
+
+    // the host called
+    pub target: String,
+
+    // the url called
+    pub data: String,
+
+    // custom dimensions
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", default)]
+    pub properties: BTreeMap<String, String>
+}
+
+
+impl RemoteDependencyData {
+
+    // the http call of the request to the target, named as the request
+    pub fn http(request_data: &RequestData, target: String, duration: String) -> Self {
+        Self {
+            ver: 2,
+            id: String::default(),
+            name: request_data.name.clone(),
+            duration,
+            success: request_data.success,
+            result_code: request_data.response_code.clone(),
+            dependency_type: "Http".to_string(),
+            target,
+            data: request_data.url.clone(),
+            properties: BTreeMap::new()
+        }
+    }
+}
+
+
+impl TrackDependency {
+    // the dependency is the span of the trace context, its parent is the span of the request
+    pub fn new(time: SystemTime, instrumentation_key: String, dependency_data: RemoteDependencyData, trace: &TraceContext) -> Self {
+        Self {
+            ver: 1,
+            name: "Microsoft.ApplicationInsights.RemoteDependency".to_string(),
+            time: to_iso8601_utc(time),
+            instrumentation_key,
+            tags: Tags {
+                ai_operation_id: Some(trace.trace_id.clone()),
+                ai_operation_name: dependency_data.name.clone(),
+                ai_operation_parent_id: trace.parent_id.clone()
+            },
+            data: TrackDependencyData {
+                base_type: "RemoteDependencyData".to_string(),
+                base_data: RemoteDependencyData {
+                    id: trace.span_id.clone(),
+                    ..dependency_data
+                }
+            }
+        }
+    }
+}
+
+// a failure of the policy itself, reported instead of only logged
+#[derive(Default, Clone, Deserialize, Debug, Serialize)]
+pub struct TrackException {
+    pub ver: i32,
+    pub name: String,
+    pub time: String,
+
+    #[serde(rename = "iKey")]
+    pub instrumentation_key: String,
+    pub tags: Tags,
+    pub data: TrackExceptionData
+}
+
+#[derive(Default, Clone, Deserialize, Debug, Serialize)]
+pub struct TrackExceptionData {
+    #[serde(rename = "baseType")]
+    pub base_type: String,
+
+    #[serde(rename = "baseData")]
+    pub base_data: ExceptionData
+}
+
+#[derive(Default, Clone, Deserialize, Debug, Serialize)]
+pub struct ExceptionData {
+    pub ver: i32,
+    pub exceptions: Vec<ExceptionDetails>,
+
+    #[serde(rename = "severityLevel")]
+    pub severity_level: String,
+
+    // custom dimensions
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", default)]
+    pub properties: BTreeMap<String, String>
+}
+
+#[derive(Default, Clone, Deserialize, Debug, Serialize)]
+pub struct ExceptionDetails {
+    #[serde(rename = "typeName")]
+    pub type_name: String,
+
+    pub message: String,
+
+    // the policy has no stack to report
+    #[serde(rename = "hasFullStack")]
+    pub has_full_stack: bool
+}
+
+
+impl ExceptionData {
+
+    pub fn error(type_name: &str, message: &str) -> Self {
+        Self {
+            ver: 2,
+            exceptions: vec![ExceptionDetails {
+                type_name: type_name.to_string(),
+                message: message.to_string(),
+                has_full_stack: false
+            }],
+            severity_level: "Error".to_string(),
+            properties: BTreeMap::new()
+        }
+    }
+}
+
+
+impl TrackException {
+    // the exception belongs to the span of the trace context, when raised by a request
+    pub fn new(time: SystemTime, instrumentation_key: String, exception_data: ExceptionData, trace: Option<&TraceContext>) -> Self {
+        Self {
+            ver: 1,
+            name: "Microsoft.ApplicationInsights.Exception".to_string(),
+            time: to_iso8601_utc(time),
+            instrumentation_key,
+            tags: Tags {
+                ai_operation_id: trace.map(|trace| trace.trace_id.clone()),
+                ai_operation_name: String::default(),
+                ai_operation_parent_id: trace.map(|trace| trace.span_id.clone())
+            },
+            data: TrackExceptionData {
+                base_type: "ExceptionData".to_string(),
+                base_data: exception_data
+            }
+        }
+    }
+}
+
 #[derive(Default, Clone, Deserialize, Debug, Serialize)]
 pub struct TrackResponse {
     
@@ -118,4 +305,49 @@ pub struct ErrorDetails {
     pub status_code: i32,
     
     pub message: String
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_dependency_of_the_request() {
+    let mut request_data = RequestData::from_request_headers(
+        "b7ad6b7169203331".to_string(),
+        "GET".to_string(),
+        "https".to_string(),
+        "api.example.com".to_string(),
+        "/orders".to_string(),
+        "curl".to_string()
+    );
+    request_data.response_code = "503".to_string();
+
+    let request = TraceContext::parse("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01", None).unwrap();
+    let dependency = request.child(SystemTime::now(), "request");
+    let dependency_data = RemoteDependencyData::http(&request_data, "10.0.0.5:8080".to_string(), "00.00:00:00.250000".to_string());
+    let track = serde_json::to_value(TrackDependency::new(SystemTime::now(), "key".to_string(), dependency_data, &dependency)).unwrap();
+
+    assert_eq!(track["tags"]["ai.operation.id"], "0af7651916cd43dd8448eb211c80319c");
+    assert_eq!(track["tags"]["ai.operation.parentId"], "b7ad6b7169203331");
+    assert_eq!(track["data"]["baseType"], "RemoteDependencyData");
+
+    let base_data = &track["data"]["baseData"];
+    assert_eq!(base_data["id"], dependency.span_id.as_str());
+    assert_eq!(base_data["name"], "GET /orders");
+    assert_eq!(base_data["type"], "Http");
+    assert_eq!(base_data["target"], "10.0.0.5:8080");
+    assert_eq!(base_data["resultCode"], "503");
+    assert_eq!(base_data["data"], "https://api.example.com/orders");
+}
+
+
+#[test]
+fn test_exception_outside_of_a_request() {
+    let exception_data = ExceptionData::error("DispatchError", "BadArgument");
+    let track = serde_json::to_value(TrackException::new(SystemTime::now(), "key".to_string(), exception_data, None)).unwrap();
+
+    assert_eq!(track["name"], "Microsoft.ApplicationInsights.Exception");
+    assert_eq!(track["tags"], serde_json::json!({"ai.operation.id": null, "ai.operation.parentId": null}));
+    assert_eq!(track["data"]["baseType"], "ExceptionData");
+    assert_eq!(track["data"]["baseData"]["severityLevel"], "Error");
+    assert_eq!(track["data"]["baseData"]["exceptions"], serde_json::json!([
+        {"typeName": "DispatchError", "message": "BadArgument", "hasFullStack": false}
+    ]));
+}
@@ -0,0 +1,166 @@
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+const DEFAULT_MAX_ITEMS: usize = 100;
+
+// items resent along with the next tracking, so a call body stays small
+pub const MAX_BATCH: usize = 50;
+
+
+// what to do with a new item when the queue is full
+#[derive(Clone, Copy, Deserialize, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum Overflow {
+    // drops the oldest queued item
+    #[default]
+    DropOldest,
+    // drops the new item
+    DropNewest,
+    // drops every other queued item, keeping a sample of the whole outage
+    Downsample
+}
+
+
+// buffering of the tracking items while the upstream is unreachable
+#[derive(Clone, Deserialize, Debug)]
+pub struct QueueConfig {
+    #[serde(alias = "maxItems", default = "default_max_items")]
+    pub max_items: usize,
+
+    #[serde(default)]
+    pub overflow: Overflow
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            max_items: DEFAULT_MAX_ITEMS,
+            overflow: Overflow::default()
+        }
+    }
+}
+
+fn default_max_items() -> usize {
+    DEFAULT_MAX_ITEMS
+}
+
+
+#[derive(Debug, Default)]
+struct Queued {
+    items: VecDeque<String>,
+    dropped: u64
+}
+
+
+// bounded queue of the serialized tracking items that failed to be sent, shared by all http contexts
+#[derive(Clone, Debug)]
+pub struct TelemetryQueue {
+    config: QueueConfig,
+    queued: Rc<RefCell<Queued>>
+}
+
+impl TelemetryQueue {
+
+    pub fn new(config: QueueConfig) -> TelemetryQueue {
+        TelemetryQueue {
+            config,
+            queued: Rc::new(RefCell::new(Queued::default()))
+        }
+    }
+
+    pub fn max_items(&self) -> usize {
+        self.config.max_items
+    }
+
+    pub fn len(&self) -> usize {
+        self.queued.borrow().items.len()
+    }
+
+    // queues the item, applying the overflow strategy when full
+    pub fn push(&self, item: String) {
+        let mut queued = self.queued.borrow_mut();
+        let max_items = self.config.max_items;
+
+        if max_items == 0 {
+            queued.dropped += 1;
+            return;
+        }
+
+        if queued.items.len() >= max_items {
+            match self.config.overflow {
+                Overflow::DropOldest => {
+                    queued.items.pop_front();
+                    queued.dropped += 1;
+                },
+                Overflow::DropNewest => {
+                    queued.dropped += 1;
+                    return;
+                },
+                Overflow::Downsample => {
+                    let before = queued.items.len();
+                    let mut index = 0;
+                    queued.items.retain(|_| {
+                        index += 1;
+                        index % 2 == 0
+                    });
+                    queued.dropped += (before - queued.items.len()) as u64;
+                }
+            }
+        }
+
+        queued.items.push_back(item);
+    }
+
+    // takes up to max of the oldest items
+    pub fn drain(&self, max: usize) -> Vec<String> {
+        let mut queued = self.queued.borrow_mut();
+        let count = max.min(queued.items.len());
+        queued.items.drain(..count).collect()
+    }
+
+    // drops the items given up on, counted with the items dropped by the overflow
+    pub fn discard(&self, count: usize) {
+        self.queued.borrow_mut().dropped += count as u64;
+    }
+
+    // the items dropped since the last call
+    pub fn take_dropped(&self) -> u64 {
+        std::mem::take(&mut self.queued.borrow_mut().dropped)
+    }
+}
+
+
+#[cfg(test)]
+fn queue(max_items: usize, overflow: Overflow) -> TelemetryQueue {
+    let queue = TelemetryQueue::new(QueueConfig { max_items, overflow });
+    for item in 1..=max_items + 1 {
+        queue.push(item.to_string());
+    }
+    queue
+}
+
+#[test]
+fn test_drop_oldest() {
+    let queue = queue(3, Overflow::DropOldest);
+    assert_eq!(queue.drain(10), vec!["2", "3", "4"]);
+    assert_eq!(queue.take_dropped(), 1);
+    assert_eq!(queue.take_dropped(), 0);
+}
+
+#[test]
+fn test_drop_newest() {
+    let queue = queue(3, Overflow::DropNewest);
+    assert_eq!(queue.drain(2), vec!["1", "2"]);
+    assert_eq!(queue.drain(2), vec!["3"]);
+    assert_eq!(queue.take_dropped(), 1);
+}
+
+#[test]
+fn test_downsample() {
+    let queue = queue(4, Overflow::Downsample);
+    assert_eq!(queue.len(), 3);
+    assert_eq!(queue.drain(10), vec!["2", "4", "5"]);
+    assert_eq!(queue.take_dropped(), 2);
+}
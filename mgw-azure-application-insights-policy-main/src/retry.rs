@@ -0,0 +1,217 @@
+use classy::client::Retry;
+use classy::client::RetryPolicy;
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use crate::status::StatusRange;
+use crate::status::DEFAULT_RETRY_ON;
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+const DEFAULT_INITIAL_BACKOFF_MS: u64 = 1000;
+const DEFAULT_MAX_BACKOFF_MS: u64 = 60_000;
+
+
+// retries of the failed tracking calls with an exponential backoff, run by the RetryPolicy of
+// the classy client
+#[derive(Clone, Deserialize, Debug)]
+pub struct RetryConfig {
+    // attempts of the tracking of an item, the first one included
+    #[serde(alias = "maxAttempts", default = "default_max_attempts")]
+    pub max_attempts: u32,
+
+    #[serde(alias = "initialBackoffMs", default = "default_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+
+    #[serde(alias = "maxBackoffMs", default = "default_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+
+    // statuses retried, the calls not reaching Application Insights always are
+    #[serde(alias = "retryOn", default = "default_retry_on")]
+    pub retry_on: Vec<StatusRange>
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            initial_backoff_ms: DEFAULT_INITIAL_BACKOFF_MS,
+            max_backoff_ms: DEFAULT_MAX_BACKOFF_MS,
+            retry_on: default_retry_on()
+        }
+    }
+}
+
+fn default_max_attempts() -> u32 {
+    DEFAULT_MAX_ATTEMPTS
+}
+
+fn default_initial_backoff_ms() -> u64 {
+    DEFAULT_INITIAL_BACKOFF_MS
+}
+
+fn default_max_backoff_ms() -> u64 {
+    DEFAULT_MAX_BACKOFF_MS
+}
+
+fn default_retry_on() -> Vec<StatusRange> {
+    DEFAULT_RETRY_ON.to_vec()
+}
+
+impl RetryConfig {
+
+    // the wait doubles after each failed attempt, up to the maximum
+    pub fn policy(&self) -> RetryPolicy {
+        let retry_on = self.retry_on.clone();
+        RetryPolicy::new(self.max_attempts)
+            .initial_backoff(Duration::from_millis(self.initial_backoff_ms))
+            .max_backoff(Duration::from_millis(self.max_backoff_ms))
+            .multiplier(2)
+            .retry_on(move |status| match status.and_then(|status| u16::try_from(status).ok()) {
+                Some(status) => retry_on.iter().any(|range| range.contains(status)),
+                None => true
+            })
+    }
+}
+
+// the status of a tracking call, none when Application Insights was not reached
+fn status_code(status: &str) -> Option<u32> {
+    status.trim().parse().ok()
+}
+
+
+// a batch of a failed tracking call, waiting for its next attempt
+#[derive(Debug, PartialEq, Eq)]
+pub struct Pending {
+    pub items: Vec<String>,
+    // the attempt the batch is sent with, counted from 1
+    pub attempt: u32,
+    due: SystemTime
+}
+
+
+// the batches of the failed tracking calls, each one backing off after its own failed attempts,
+// shared by all http contexts
+#[derive(Clone, Debug)]
+pub struct Backoff {
+    policy: RetryPolicy,
+    // the most items waiting, the oldest batches are dropped beyond
+    max_items: usize,
+    pending: Rc<RefCell<Vec<Pending>>>
+}
+
+impl Backoff {
+
+    pub fn new(config: &RetryConfig, max_items: usize) -> Backoff {
+        Backoff {
+            policy: config.policy(),
+            max_items,
+            pending: Rc::new(RefCell::new(Vec::new()))
+        }
+    }
+
+    // records the status of the attempt of a batch, the batch waits for its next attempt when
+    // retried, the batches dropped to make room for it are returned
+    pub fn record(&self, items: Vec<String>, attempt: u32, status: &str, now: SystemTime) -> (Retry, Vec<Pending>) {
+        let retry = self.policy.next(attempt, status_code(status), now);
+        let due = match retry {
+            Retry::At(due) => due,
+            Retry::Done | Retry::GiveUp => return (retry, Vec::new())
+        };
+
+        let mut pending = self.pending.borrow_mut();
+        let mut waiting: usize = pending.iter().map(|batch| batch.items.len()).sum();
+        let mut dropped = Vec::new();
+        while !pending.is_empty() && waiting + items.len() > self.max_items {
+            let oldest = pending.remove(0);
+            waiting -= oldest.items.len();
+            dropped.push(oldest);
+        }
+        pending.push(Pending { items, attempt: attempt + 1, due });
+        (retry, dropped)
+    }
+
+    // takes the batches due for their next attempt
+    pub fn take_due(&self, now: SystemTime) -> Vec<Pending> {
+        let mut pending = self.pending.borrow_mut();
+        let (due, waiting): (Vec<Pending>, Vec<Pending>) = pending.drain(..).partition(|batch| batch.due <= now);
+        *pending = waiting;
+        due
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.borrow().len()
+    }
+}
+
+
+#[cfg(test)]
+fn at(millis: u64) -> SystemTime {
+    std::time::UNIX_EPOCH + Duration::from_millis(millis)
+}
+
+#[test]
+fn test_backoff_doubles_up_to_the_maximum() {
+    let policy = RetryConfig { max_backoff_ms: 5000, ..RetryConfig::default() }.policy();
+    assert_eq!(policy.backoff(1), Duration::from_millis(1000));
+    assert_eq!(policy.backoff(2), Duration::from_millis(2000));
+    assert_eq!(policy.backoff(3), Duration::from_millis(4000));
+    assert_eq!(policy.backoff(4), Duration::from_millis(5000));
+    assert_eq!(policy.backoff(80), Duration::from_millis(5000));
+}
+
+#[test]
+fn test_retried_statuses() {
+    let retried = |config: &RetryConfig, status: &str| config.policy().should_retry(1, status_code(status));
+
+    let config = RetryConfig::default();
+    assert!(retried(&config, ""));
+    assert!(retried(&config, "429"));
+    assert!(retried(&config, "503"));
+    assert!(!retried(&config, "200"));
+    assert!(!retried(&config, "400"));
+
+    let config: RetryConfig = serde_json::from_str(r#"{"retryOn": ["408", "502-504"]}"#).unwrap();
+    assert!(retried(&config, "408"));
+    assert!(!retried(&config, "429"));
+    assert!(!retried(&config, "500"));
+}
+
+#[test]
+fn test_backoff_per_batch() {
+    let backoff = Backoff::new(&RetryConfig::default(), 100);
+    let items = |item: &str| vec![item.to_string()];
+
+    assert_eq!(backoff.record(items("a"), 1, "503", at(0)).0, Retry::At(at(1000)));
+    assert_eq!(backoff.record(items("b"), 1, "", at(500)).0, Retry::At(at(1500)));
+    assert_eq!(backoff.len(), 2);
+    assert_eq!(backoff.take_due(at(999)), vec![]);
+
+    // the second attempt of a batch does not delay the first one of another
+    let due = backoff.take_due(at(1000));
+    assert_eq!(due, vec![Pending { items: items("a"), attempt: 2, due: at(1000) }]);
+    assert_eq!(backoff.record(items("a"), 2, "503", at(1000)).0, Retry::At(at(3000)));
+    assert_eq!(backoff.take_due(at(1500)).len(), 1);
+
+    assert_eq!(backoff.record(items("b"), 2, "200", at(1500)).0, Retry::Done);
+    assert_eq!(backoff.take_due(at(3000))[0].attempt, 3);
+    assert_eq!(backoff.record(items("a"), 3, "429", at(3000)).0, Retry::GiveUp);
+    assert_eq!(backoff.len(), 0);
+}
+
+#[test]
+fn test_backoff_drops_the_oldest_batches() {
+    let backoff = Backoff::new(&RetryConfig::default(), 3);
+    let items = |count: usize| vec![String::new(); count];
+
+    assert!(backoff.record(items(2), 1, "503", at(0)).1.is_empty());
+    assert!(backoff.record(items(1), 1, "503", at(1)).1.is_empty());
+
+    let (retry, dropped) = backoff.record(items(2), 1, "503", at(2));
+    assert_eq!(retry, Retry::At(at(1002)));
+    assert_eq!(dropped.len(), 1);
+    assert_eq!(dropped[0].items.len(), 2);
+    assert_eq!(backoff.len(), 2);
+}
@@ -0,0 +1,160 @@
+use serde::Deserialize;
+use std::convert::TryFrom;
+
+// statuses counted as successful when none is configured: the informational, successful
+// and redirection ones, so a 304 is not reported as a failure
+const DEFAULT_SUCCESS: StatusRange = StatusRange { from: 100, to: 399 };
+
+// statuses of the tracking calls retried when none is configured: too many requests and the
+// server errors
+pub const DEFAULT_RETRY_ON: [StatusRange; 2] = [
+    StatusRange { from: 429, to: 429 },
+    StatusRange { from: 500, to: 599 }
+];
+
+
+// outcome of a request, tracked as the StatusCategory custom dimension
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatusCategory {
+    Success,
+    ClientError,
+    ServerError
+}
+
+impl StatusCategory {
+
+    pub fn is_success(&self) -> bool {
+        *self == StatusCategory::Success
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            StatusCategory::Success => "Success",
+            StatusCategory::ClientError => "ClientError",
+            StatusCategory::ServerError => "ServerError"
+        }
+    }
+}
+
+
+// an inclusive range of statuses, configured as "404", "2xx" or "200-299"
+#[derive(Clone, Copy, Deserialize, Debug, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub struct StatusRange {
+    from: u16,
+    to: u16
+}
+
+impl StatusRange {
+
+    pub fn contains(&self, status: u16) -> bool {
+        self.from <= status && status <= self.to
+    }
+}
+
+impl TryFrom<String> for StatusRange {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("invalid status range: {}", value);
+        let parse = |status: &str| status.trim().parse::<u16>().map_err(|_| invalid());
+
+        let range = match value.trim().to_lowercase().as_str() {
+            class if class.len() == 3 && class.ends_with("xx") => {
+                let hundreds = parse(&class[..1])? * 100;
+                StatusRange { from: hundreds, to: hundreds + 99 }
+            },
+            range => match range.split_once('-') {
+                Some((from, to)) => StatusRange { from: parse(from)?, to: parse(to)? },
+                None => {
+                    let status = parse(range)?;
+                    StatusRange { from: status, to: status }
+                }
+            }
+        };
+
+        if range.from > range.to || range.to > 599 {
+            return Err(invalid());
+        }
+        Ok(range)
+    }
+}
+
+
+// successful statuses of the requests whose path starts with the prefix
+#[derive(Clone, Deserialize, Debug)]
+pub struct RouteSuccess {
+    pub path: String,
+    pub success: Vec<StatusRange>
+}
+
+
+// which statuses are successful, overridden per route
+#[derive(Default, Clone, Deserialize, Debug)]
+pub struct SuccessStatus {
+    #[serde(default)]
+    pub success: Option<Vec<StatusRange>>,
+
+    // the first route matching the path wins
+    #[serde(default)]
+    pub routes: Vec<RouteSuccess>
+}
+
+impl SuccessStatus {
+
+    pub fn classify(&self, path: &str, status: &str) -> StatusCategory {
+        let status = match status.trim().parse::<u16>() {
+            Ok(status) => status,
+            Err(_) => return StatusCategory::ServerError
+        };
+
+        let path = path.split(['?', '#']).next().unwrap_or_default();
+        let ranges = self.routes.iter()
+            .find(|route| path.starts_with(route.path.as_str()))
+            .map(|route| route.success.as_slice())
+            .or(self.success.as_deref());
+
+        let success = match ranges {
+            Some(ranges) => ranges.iter().any(|range| range.contains(status)),
+            None => DEFAULT_SUCCESS.contains(status)
+        };
+
+        if success {
+            StatusCategory::Success
+        } else if status >= 500 {
+            StatusCategory::ServerError
+        } else {
+            StatusCategory::ClientError
+        }
+    }
+}
+
+
+#[test]
+fn test_status_ranges() {
+    let range = |value: &str| StatusRange::try_from(value.to_string());
+
+    assert_eq!(range("404"), Ok(StatusRange { from: 404, to: 404 }));
+    assert_eq!(range("2xx"), Ok(StatusRange { from: 200, to: 299 }));
+    assert_eq!(range(" 200 - 304 "), Ok(StatusRange { from: 200, to: 304 }));
+    assert!(range("299-200").is_err());
+    assert!(range("7xx").is_err());
+    assert!(range("ok").is_err());
+}
+
+#[test]
+fn test_classify_statuses() {
+    let status = SuccessStatus::default();
+    assert_eq!(status.classify("/orders", "304"), StatusCategory::Success);
+    assert_eq!(status.classify("/orders", "404"), StatusCategory::ClientError);
+    assert_eq!(status.classify("/orders", "503"), StatusCategory::ServerError);
+    assert_eq!(status.classify("/orders", ""), StatusCategory::ServerError);
+
+    let status: SuccessStatus = serde_json::from_str(
+        r#"{"success": ["2xx"], "routes": [{"path": "/lookup", "success": ["2xx", "404"]}]}"#
+    ).unwrap();
+    assert_eq!(status.classify("/orders", "304"), StatusCategory::ClientError);
+    assert_eq!(status.classify("/orders", "404"), StatusCategory::ClientError);
+    assert_eq!(status.classify("/lookup/42?full", "404"), StatusCategory::Success);
+    assert!(serde_json::from_str::<SuccessStatus>(r#"{"success": ["2xy"]}"#).is_err());
+}
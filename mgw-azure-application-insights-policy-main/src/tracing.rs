@@ -0,0 +1,188 @@
+use sha1::Digest;
+use sha1::Sha1;
+use std::cell::Cell;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+// the only version generated, the later ones are parsed as this one
+const VERSION: &str = "00";
+const INVALID_VERSION: &str = "ff";
+const TRACEPARENT_LENGTH: usize = 55;
+const TRACE_ID_LENGTH: usize = 32;
+const SPAN_ID_LENGTH: usize = 16;
+const SAMPLED: u8 = 0x01;
+const MAX_TRACESTATE_MEMBERS: usize = 32;
+
+thread_local! {
+    // tells apart the ids generated in the same nanosecond
+    static SEQUENCE: Cell<u64> = const { Cell::new(0) };
+}
+
+
+// the W3C trace context of a hop, as in https://www.w3.org/TR/trace-context/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceContext {
+    pub trace_id: String,
+    pub span_id: String,
+    // the span of the caller, None for the first hop of the trace
+    pub parent_id: Option<String>,
+    pub flags: u8,
+    pub tracestate: Option<String>
+}
+
+impl TraceContext {
+
+    // starts a trace, sampled, the seed makes the ids of the requests of the same time differ
+    pub fn root(now: SystemTime, seed: &str) -> TraceContext {
+        TraceContext {
+            trace_id: random_id(now, seed, TRACE_ID_LENGTH),
+            span_id: random_id(now, seed, SPAN_ID_LENGTH),
+            parent_id: None,
+            flags: SAMPLED,
+            tracestate: None
+        }
+    }
+
+    // the context received in the traceparent and tracestate headers, None when the traceparent
+    // is invalid, its tracestate is then ignored
+    pub fn parse(traceparent: &str, tracestate: Option<&str>) -> Option<TraceContext> {
+        let traceparent = traceparent.trim();
+        let version = traceparent.get(..2)?;
+        if !is_hex(version) || version == INVALID_VERSION {
+            return None;
+        }
+        // the later versions can be longer, with their fields after a dash
+        let valid_length = match traceparent.len() {
+            TRACEPARENT_LENGTH => true,
+            length if length > TRACEPARENT_LENGTH => {
+                version != VERSION && traceparent.as_bytes()[TRACEPARENT_LENGTH] == b'-'
+            },
+            _ => false
+        };
+        if !valid_length {
+            return None;
+        }
+
+        let mut fields = traceparent[..TRACEPARENT_LENGTH].split('-').skip(1);
+        let trace_id = fields.next().filter(|id| is_id(id, TRACE_ID_LENGTH))?;
+        let parent_id = fields.next().filter(|id| is_id(id, SPAN_ID_LENGTH))?;
+        let flags = fields.next().filter(|flags| flags.len() == 2 && is_hex(flags))?;
+
+        Some(TraceContext {
+            trace_id: trace_id.to_string(),
+            span_id: parent_id.to_string(),
+            parent_id: None,
+            flags: u8::from_str_radix(flags, 16).ok()?,
+            tracestate: tracestate.and_then(parse_tracestate)
+        })
+    }
+
+    // the span of the next hop, in the same trace
+    pub fn child(&self, now: SystemTime, seed: &str) -> TraceContext {
+        TraceContext {
+            trace_id: self.trace_id.clone(),
+            span_id: random_id(now, seed, SPAN_ID_LENGTH),
+            parent_id: Some(self.span_id.clone()),
+            flags: self.flags,
+            tracestate: self.tracestate.clone()
+        }
+    }
+
+    // the traceparent header of the calls made in this span
+    pub fn traceparent(&self) -> String {
+        format!("{}-{}-{}-{:02x}", VERSION, self.trace_id, self.span_id, self.flags)
+    }
+}
+
+
+fn is_hex(value: &str) -> bool {
+    value.bytes().all(|byte| byte.is_ascii_digit() || (b'a'..=b'f').contains(&byte))
+}
+
+// lowercase hex of the length, not all zeros
+fn is_id(id: &str, length: usize) -> bool {
+    id.len() == length && is_hex(id) && id.bytes().any(|byte| byte != b'0')
+}
+
+// the list members kept, up to the maximum, without the empty ones
+fn parse_tracestate(tracestate: &str) -> Option<String> {
+    let members: Vec<&str> = tracestate.split(',')
+        .map(str::trim)
+        .filter(|member| member.contains('='))
+        .take(MAX_TRACESTATE_MEMBERS)
+        .collect();
+
+    if members.is_empty() {
+        None
+    } else {
+        Some(members.join(","))
+    }
+}
+
+// lowercase hex id of the length, up to 40, hashed as the wasm target has no random source
+fn random_id(now: SystemTime, seed: &str, length: usize) -> String {
+    let nanos = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    let sequence = SEQUENCE.with(|sequence| {
+        sequence.set(sequence.get().wrapping_add(1));
+        sequence.get()
+    });
+
+    let mut hasher = Sha1::new();
+    hasher.update(format!("{}:{}:{}", nanos, sequence, seed));
+    let mut id = format!("{:x}", hasher.finalize());
+    id.truncate(length);
+
+    // an all zeros id is invalid
+    if !id.bytes().any(|byte| byte != b'0') {
+        id.replace_range(length - 1.., "1");
+    }
+    id
+}
+
+
+#[test]
+fn test_parse_traceparent() {
+    let trace = TraceContext::parse("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01", Some("rojo=00f067aa0ba902b7, ,congo=t61rcWkgMzE")).unwrap();
+    assert_eq!(trace.trace_id, "0af7651916cd43dd8448eb211c80319c");
+    assert_eq!(trace.span_id, "b7ad6b7169203331");
+    assert_eq!(trace.flags & SAMPLED, SAMPLED);
+    assert_eq!(trace.tracestate.as_deref(), Some("rojo=00f067aa0ba902b7,congo=t61rcWkgMzE"));
+
+    // a later version with more fields
+    let trace = TraceContext::parse("01-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-00-extra", None).unwrap();
+    assert_eq!(trace.flags & SAMPLED, 0);
+}
+
+#[test]
+fn test_invalid_traceparents() {
+    let valid = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+    assert!(TraceContext::parse(valid, None).is_some());
+
+    for invalid in [
+        "ff-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+        "00-0AF7651916CD43DD8448EB211C80319C-b7ad6b7169203331-01",
+        "00-00000000000000000000000000000000-b7ad6b7169203331-01",
+        "00-0af7651916cd43dd8448eb211c80319c-0000000000000000-01",
+        "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01-extra",
+        "00-0af7651916cd43dd8448eb211c80319c-b7ad6b716920333-01",
+        "sha1 hash: 4b1c3a8e",
+        ""
+    ] {
+        assert_eq!(TraceContext::parse(invalid, Some("rojo=1")), None, "{}", invalid);
+    }
+}
+
+#[test]
+fn test_child_spans() {
+    let now = SystemTime::now();
+    let root = TraceContext::root(now, "request");
+    assert!(TraceContext::parse(&root.traceparent(), None).is_some());
+    assert_eq!(root.flags & SAMPLED, SAMPLED);
+    assert_eq!(root.parent_id, None);
+
+    let child = root.child(now, "request");
+    assert_eq!(child.trace_id, root.trace_id);
+    assert_eq!(child.parent_id.as_deref(), Some(root.span_id.as_str()));
+    assert_ne!(child.span_id, root.span_id);
+    assert_eq!(&child.traceparent()[..36], &root.traceparent()[..36]);
+}
@@ -1,11 +1,322 @@
+use classy::client::CalloutTarget;
+use classy::client::Endpoint;
+use classy::client::Retry;
+use log::debug;
+use log::error;
 use log::info;
+use log::warn;
+use proxy_wasm::hostcalls;
 use proxy_wasm::types::*;
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::ptr::null_mut;
+use std::rc::Rc;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use crate::compression::Compression;
+use crate::model::ExceptionData;
+use crate::model::TrackException;
+use crate::model::TrackResponse;
+use crate::queue::TelemetryQueue;
+use crate::queue::MAX_BATCH;
+use crate::retry::Backoff;
+use crate::tracing::TraceContext;
+use crate::PolicyConfig;
 
 pub const AI_SERVICE_NAME: &str = "appinsights";
 pub const AI_SERVICE_HOST_SUFFIX: &str = "in.applicationinsights.azure.com";
 pub const AI_SERVICE_PATH: &str = "/v2/track";
 
+// the type names of the failures of the policy tracked as exceptions
+pub const CONFIGURATION_ERROR: &str = "ConfigurationError";
+pub const DISPATCH_ERROR: &str = "DispatchError";
+pub const MALFORMED_RESPONSE: &str = "MalformedResponse";
+pub const SERIALIZATION_ERROR: &str = "SerializationError";
+
+const DEFAULT_BATCH_MAX_ITEMS: usize = 50;
+const DEFAULT_FLUSH_INTERVAL_MS: u64 = 1000;
+
+
+// batching of the tracking items flushed on every interval, in calls of up to max_items,
+// disabled by default so every request is tracked by a call of its own on the next flush
+#[derive(Clone, Deserialize, Debug)]
+pub struct Batching {
+    #[serde(default)]
+    pub enabled: bool,
+
+    #[serde(alias = "maxItems", default = "default_batch_max_items")]
+    pub max_items: usize,
+
+    #[serde(alias = "flushIntervalMs", default = "default_flush_interval_ms")]
+    pub flush_interval_ms: u64
+}
+
+impl Default for Batching {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_items: DEFAULT_BATCH_MAX_ITEMS,
+            flush_interval_ms: DEFAULT_FLUSH_INTERVAL_MS
+        }
+    }
+}
+
+fn default_batch_max_items() -> usize {
+    DEFAULT_BATCH_MAX_ITEMS
+}
+
+fn default_flush_interval_ms() -> u64 {
+    DEFAULT_FLUSH_INTERVAL_MS
+}
+
+impl Batching {
+
+    // the tick period of the root context, which sends all the tracking calls as their
+    // responses only reach the context that sent them
+    pub fn flush_interval(&self) -> Duration {
+        match self.flush_interval_ms {
+            0 => Duration::from_millis(DEFAULT_FLUSH_INTERVAL_MS),
+            flush_interval_ms => Duration::from_millis(flush_interval_ms)
+        }
+    }
+}
+
+
+// a tracking call waiting for its response
+#[derive(Debug)]
+struct InFlight {
+    endpoint: Endpoint,
+    items: Vec<String>,
+    attempt: u32
+}
+
+
+// sends the tracking items to Application Insights, shared by all http contexts, tracking the
+// items, and the root context, flushing them on its ticks
+#[derive(Clone, Debug)]
+pub struct Tracker {
+    api_key: String,
+    instrumentation_key: String,
+    target: Rc<CalloutTarget>,
+    queue: TelemetryQueue,
+    backoff: Backoff,
+    compression: Compression,
+    batching: Batching,
+    // the items of each request tracked since the last flush
+    tracked: Rc<RefCell<Vec<Vec<String>>>>,
+    in_flight: Rc<RefCell<HashMap<u32, InFlight>>>
+}
+
+impl Tracker {
+
+    pub fn new(config: &PolicyConfig) -> Tracker {
+        Tracker {
+            api_key: config.api_key.clone(),
+            instrumentation_key: config.instrumentation_key.clone(),
+            target: Rc::new(config.callout_target()),
+            queue: TelemetryQueue::new(config.queue.clone()),
+            backoff: Backoff::new(&config.retry, config.queue.max_items),
+            compression: config.compression.clone(),
+            batching: config.batching.clone(),
+            tracked: Rc::new(RefCell::new(Vec::new())),
+            in_flight: Rc::new(RefCell::new(HashMap::new()))
+        }
+    }
+
+    // buffers the items of a request until the next flush, the requests beyond the queue
+    // max_items wait in the queue along with the failed items
+    pub fn track(&self, items: Vec<String>) {
+        let mut tracked = self.tracked.borrow_mut();
+        if tracked.len() < self.queue.max_items() {
+            tracked.push(items);
+        } else {
+            items.into_iter().for_each(|item| self.queue.push(item));
+        }
+    }
+
+    // sends the failed batches once their backoff elapsed and the items tracked since the last
+    // flush, called from the root context only
+    pub fn flush(&self, now: SystemTime) {
+        // the failed batches due for their next attempt are sent on their own, keeping their attempts
+        for pending in self.backoff.take_due(now) {
+            self.dispatch(pending.items, pending.attempt, now);
+        }
+
+        let tracked = std::mem::take(&mut *self.tracked.borrow_mut());
+        if self.batching.enabled {
+            let items: Vec<String> = tracked.into_iter().flatten().collect();
+            for batch in items.chunks(self.batching.max_items.max(1)) {
+                self.send(batch.to_vec(), now);
+            }
+        } else {
+            for items in tracked {
+                self.send(items, now);
+            }
+        }
+
+        // the queued items are sent even when no request was tracked
+        if self.queue.len() != 0 {
+            self.send(Vec::new(), now);
+        }
+    }
+
+    fn send(&self, items: Vec<String>, now: SystemTime) {
+        // sends the queued items along with these ones
+        let mut batch = self.queue.drain(MAX_BATCH);
+        batch.extend(items);
+        if !batch.is_empty() {
+            self.dispatch(batch, 1, now);
+        }
+    }
+
+    fn dispatch(&self, batch: Vec<String>, attempt: u32, now: SystemTime) {
+        let body = format!("[{}]", batch.join(","));
+
+        debug!("Track request body: {}", body);
+
+        // selects the healthiest configured endpoint
+        let endpoint = self.target.select(now).clone();
+        let authority = endpoint.authority();
+
+        // define http headers pairs
+        let mut headers: Vec<(&str, &str)> = vec![
+            (":method", "POST"),
+            (":authority", authority),
+            (":path", AI_SERVICE_PATH),
+            ("x-api-key", &self.api_key),
+            ("content-type", "application/json")
+        ];
+
+        // the api key is left out of the logs
+        debug!("Tracking request headers: {:?}", headers.iter().filter(|(name, _)| *name != "x-api-key").collect::<Vec<_>>());
+
+        // compresses the large bodies, when enabled
+        let compressed = self.compression.compress(body.as_bytes());
+        let body = match &compressed {
+            Some(compressed) => {
+                debug!("Track request body compressed from {} to {} bytes", body.len(), compressed.len());
+                headers.push(("content-encoding", "gzip"));
+                compressed.as_slice()
+            },
+            None => body.as_bytes()
+        };
+
+        debug!("Azure App Insights upstream: {}", endpoint.name());
+
+        // request azure app insights upstream service, the response goes to the root context
+        match hostcalls::dispatch_http_call(
+            endpoint.name(),
+            headers,
+            Some(body),
+            vec![],
+            endpoint.timeout().unwrap_or_default()
+        ){
+            Ok(token) => {
+                debug!("Tracking of {} items sent OK", batch.len());
+                self.in_flight.borrow_mut().insert(token, InFlight { endpoint, items: batch, attempt });
+            },
+            Err(err) => {
+                error!("Error calling App Insights API: ({:?})", err);
+                self.target.report_failure(&endpoint, now);
+                self.retry(batch, attempt, "", now);
+                self.track_exception(DISPATCH_ERROR, &format!("Error calling App Insights API: {:?}", err), None, now);
+            }
+        }
+    }
+
+    // handles the response to the tracking call, the status is empty when the upstream could
+    // not be reached
+    pub fn complete(&self, token: u32, status: &str, body: Option<Bytes>, now: SystemTime) {
+        let in_flight = match self.in_flight.borrow_mut().remove(&token) {
+            Some(in_flight) => in_flight,
+            None => return
+        };
+
+        // feed the endpoint health used for the next callouts
+        let unreachable = status.is_empty() || status.starts_with('5');
+        if unreachable {
+            self.target.report_failure(&in_flight.endpoint, now);
+        } else {
+            self.target.report_success(&in_flight.endpoint);
+        }
+
+        // keeps the items of the failed calls for the next tracking, once the backoff elapsed
+        self.retry(in_flight.items, in_flight.attempt, status, now);
+
+        // process the auth service response body
+        if let Some(body) = body {
+
+            // validate http status
+            if status != "200" {
+                // the error is usually a json string, logged as it is otherwise
+                let payload = serde_json::from_slice::<String>(body.as_slice())
+                    .unwrap_or_else(|_| String::from_utf8_lossy(&body).into_owned());
+                error!("Azure Application Insights track request error: {:?}", payload);
+            }
+            else {
+                // parse response body as TrackResponse
+                match serde_json::from_slice::<TrackResponse>(body.as_slice()) {
+                    Ok(payload) => {
+                        debug!("Azure response payload: {:?}", payload);
+
+                        let rejected = payload.items_received.saturating_sub(payload.items_accepted);
+                        if rejected != 0 {
+                            warn!("{} tracking items rejected, errors: {:?} ", rejected, payload.errors);
+                        }
+                    },
+                    Err(err) => {
+                        error!("Malformed Azure Application Insights track response: {}", err);
+                        self.track_exception(MALFORMED_RESPONSE, &format!("Malformed track response: {}", err), None, now);
+                    }
+                }
+            }
+        }
+    }
+
+    // reports a failure of the policy, sent along with the next tracking as it may be the
+    // tracking itself failing
+    pub fn track_exception(&self, type_name: &str, message: &str, trace: Option<&TraceContext>, now: SystemTime) {
+        let exception = TrackException::new(
+            now,
+            self.instrumentation_key.clone(),
+            ExceptionData::error(type_name, message),
+            trace
+        );
+        match serde_json::to_string(&exception) {
+            Ok(item) => self.queue.push(item),
+            Err(err) => error!("Error serializing the exception {}: {}", type_name, err)
+        }
+    }
+
+    // keeps the items of a failed call for their next attempt until the attempts are exhausted
+    fn retry(&self, items: Vec<String>, attempt: u32, status: &str, now: SystemTime) {
+        let count = items.len();
+        let (retry, dropped) = self.backoff.record(items, attempt, status, now);
+        for batch in dropped {
+            self.queue.discard(batch.items.len());
+        }
+        match retry {
+            Retry::Done => {
+                let dropped = self.queue.take_dropped();
+                if dropped != 0 {
+                    warn!("{} tracking items dropped while Application Insights was unreachable", dropped);
+                }
+            },
+            Retry::At(retry_at) => {
+                let backoff = retry_at.duration_since(now).unwrap_or_default();
+                debug!("Tracking attempt {} of {} items failed with status {:?}, retrying in {} ms", attempt, count, status, backoff.as_millis());
+                debug!("{} failed batches and {} queued items waiting", self.backoff.len(), self.queue.len());
+            },
+            Retry::GiveUp => {
+                warn!("Dropping {} tracking items after {} failed attempts", count, attempt);
+                self.queue.discard(count);
+            }
+        }
+    }
+}
+
 
 #[no_mangle]
 pub extern "C" fn flex_abi_version_0_1_0() {}
@@ -35,7 +346,7 @@ pub extern "C" fn flex_on_policy_initialize() -> bool {
         // register the endpoint upstream
         match call_foreign_function("flex_create_service", args) {
             Ok(resp) => match resp{
-                Some(res)=>info!("RESP: {}",String::from_utf8(res).unwrap()),
+                Some(res)=>info!("RESP: {}",String::from_utf8_lossy(&res)),
                 None => info!("NONE")
             }
             Err(e) => info!("E: {:?}", e)
@@ -72,7 +383,7 @@ fn call_foreign_function(name: &str, args: &[&str]) -> Result<Option<Bytes>, Sta
                 }
             }
             Status::NotFound => Ok(None),
-            status => panic!("unexpected status: {}", status as u32),
+            status => Err(status),
         }
     }
 }
@@ -88,3 +399,16 @@ extern "C" {
         return_size: *mut usize,
     ) -> Status;
 }
+
+
+#[test]
+fn test_flush_interval() {
+    assert_eq!(Batching::default().flush_interval(), Duration::from_millis(1000));
+
+    let batching: Batching = serde_json::from_str(r#"{"enabled": true, "maxItems": 20, "flushIntervalMs": 200}"#).unwrap();
+    assert_eq!(batching.max_items, 20);
+    assert_eq!(batching.flush_interval(), Duration::from_millis(200));
+
+    let batching: Batching = serde_json::from_str(r#"{"enabled": true, "flushIntervalMs": 0}"#).unwrap();
+    assert_eq!(batching.flush_interval(), Duration::from_millis(1000));
+}
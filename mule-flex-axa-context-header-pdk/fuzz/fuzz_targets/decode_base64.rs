@@ -0,0 +1,9 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Run with `cargo +nightly fuzz run decode_base64`.
+#![no_main]
+use axa_context_header::fuzzing::decode_base64;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: &str| {
+    let _ = decode_base64(input);
+});
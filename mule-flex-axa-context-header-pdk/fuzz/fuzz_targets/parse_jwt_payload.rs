@@ -0,0 +1,13 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Parses arbitrary access tokens and maps the parsed ones into context claims.
+//! Run with `cargo +nightly fuzz run parse_jwt_payload`.
+#![no_main]
+use axa_context_header::fuzzing::{AccessTokenPayload, JwtClaims};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|token: &str| {
+    if let Ok(payload) = AccessTokenPayload::parse_jwt_payload(token) {
+        let claims = JwtClaims::from_access_token_payloads(payload);
+        let _ = serde_json::to_string(&claims);
+    }
+});
@@ -0,0 +1,35 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+//! Builds tokens from arbitrary parts, base64 encoded like real ones, so the payload reaches
+//! the JSON parsing: truncated documents, unexpected types, absurd numbers.
+//! Run with `cargo +nightly fuzz run token_parts`.
+#![no_main]
+use arbitrary::Arbitrary;
+use axa_context_header::fuzzing::{AccessTokenPayload, JwtClaims};
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Arbitrary, Debug)]
+struct Token {
+    header: Vec<u8>,
+    payload: String,
+    signature: Vec<u8>,
+    padded: bool,
+}
+
+fuzz_target!(|token: Token| {
+    let config = if token.padded {
+        base64::URL_SAFE
+    } else {
+        base64::URL_SAFE_NO_PAD
+    };
+    let token = format!(
+        "{}.{}.{}",
+        base64::encode_config(&token.header, config),
+        base64::encode_config(&token.payload, config),
+        base64::encode_config(&token.signature, config)
+    );
+
+    if let Ok(payload) = AccessTokenPayload::parse_jwt_payload(&token) {
+        let claims = JwtClaims::from_access_token_payloads(payload);
+        let _ = serde_json::to_string(&claims);
+    }
+});
@@ -0,0 +1,135 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Binds the context token to the request it is minted for, so the upstream services can
+/// detect it replayed onto another request.
+#[derive(Debug, Default, Deserialize)]
+pub struct RequestBinding {
+    /// Adds the method and the hash of the normalized path of the request to the token.
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Adds the digest of the body too, holding the request until its whole body is received.
+    #[serde(default, alias = "bufferBody")]
+    pub buffer_body: bool,
+}
+
+impl RequestBinding {
+    pub fn binds_body(&self) -> bool {
+        self.enabled && self.buffer_body
+    }
+}
+
+/// The `req` claim of the context token.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RequestClaims {
+    /// The method, uppercased.
+    pub method: String,
+
+    /// The base64url SHA-256 of the normalized path.
+    pub path_hash: String,
+
+    /// The base64url SHA-256 of the body, when buffered.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body_digest: Option<String>,
+}
+
+impl RequestClaims {
+    pub fn new(method: &str, path: &str) -> Self {
+        RequestClaims {
+            method: method.to_ascii_uppercase(),
+            path_hash: sha256(normalize_path(path).as_bytes()),
+            body_digest: None,
+        }
+    }
+
+    pub fn bind_body(&mut self, body: &[u8]) {
+        self.body_digest = Some(sha256(body));
+    }
+}
+
+fn sha256(bytes: &[u8]) -> String {
+    base64::encode_config(Sha256::digest(bytes), base64::URL_SAFE_NO_PAD)
+}
+
+// the path without its query and fragment, with the unreserved characters decoded, the other
+// escapes uppercased, the empty and dot segments removed and without a trailing slash
+pub fn normalize_path(path: &str) -> String {
+    let path = path.split(|c| c == '?' || c == '#').next().unwrap_or_default();
+
+    let mut segments: Vec<String> = Vec::new();
+    for segment in path.split('/') {
+        match normalize_escapes(segment).as_str() {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment.to_string()),
+        }
+    }
+
+    format!("/{}", segments.join("/"))
+}
+
+fn normalize_escapes(segment: &str) -> String {
+    let bytes = segment.as_bytes();
+    let mut normalized = String::with_capacity(segment.len());
+    let mut index = 0;
+
+    while index < bytes.len() {
+        let escaped = bytes.get(index + 1..index + 3)
+            .filter(|_| bytes[index] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) => {
+                normalized.push(byte as char);
+                index += 3;
+            }
+            Some(byte) => {
+                normalized.push_str(&format!("%{:02X}", byte));
+                index += 3;
+            }
+            None => {
+                // the bytes are copied as they are, the path is valid utf-8
+                let next = segment[index..].chars().next().unwrap_or_default();
+                normalized.push(next);
+                index += next.len_utf8().max(1);
+            }
+        }
+    }
+
+    normalized
+}
+
+
+#[test]
+fn test_normalize_path() {
+    assert_eq!(normalize_path("/orders/42?expand=items#top"), "/orders/42");
+    assert_eq!(normalize_path("//orders/./items/../42/"), "/orders/42");
+    assert_eq!(normalize_path("/%6Frders/a%2fb/%7e"), "/orders/a%2Fb/~");
+    assert_eq!(normalize_path("/../"), "/");
+    assert_eq!(normalize_path("/caf%C3%A9/é"), "/caf%C3%A9/é");
+}
+
+#[test]
+fn test_request_claims() {
+    let mut claims = RequestClaims::new("post", "/orders/42/?dryRun=true");
+
+    assert_eq!(claims, RequestClaims::new("POST", "/orders/./42"));
+    assert_ne!(claims.path_hash, RequestClaims::new("POST", "/orders/43").path_hash);
+
+    claims.bind_body(b"");
+    assert_eq!(claims.body_digest.as_deref(), Some("47DEQpj8HBSa-_TImW-5JCeuQeRkm5NMpJWZG3hSuFU"));
+    assert_eq!(serde_json::to_value(&claims).unwrap()["method"], "POST");
+}
+
+#[test]
+fn test_binding_config() {
+    let binding: RequestBinding = serde_json::from_str(r#"{"enabled": true, "bufferBody": true}"#).unwrap();
+    assert!(binding.binds_body());
+
+    let binding: RequestBinding = serde_json::from_str(r#"{"bufferBody": true}"#).unwrap();
+    assert!(!binding.binds_body());
+}
@@ -0,0 +1,218 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use std::cell::RefCell;
+use std::time::{Duration, SystemTime};
+
+use log::{info, warn};
+use pdk::api::cache::LruTtlCache;
+use pdk::api::classy::client::{HttpCallResponse, HttpClient, ResponseBuffers};
+use pdk::api::error::{codes, PolicyError};
+use pdk_core::policy_context::metadata::IdentityManagementContext;
+use pdk_core::policy_context::PolicyContext;
+use serde::Deserialize;
+
+/// Configuration of the lookup of the api keys in the Anypoint client registry.
+#[derive(Debug, Deserialize)]
+pub struct ApiKeyLookup {
+    /// The Flex service of the Anypoint platform.
+    pub upstream: String,
+
+    /// The host of the Anypoint platform, e.g. anypoint.mulesoft.com.
+    pub host: String,
+
+    /// The path of the client applications, where {clientId} is replaced with the api key.
+    #[serde(default = "default_path")]
+    pub path: String,
+
+    /// How long the looked up applications are cached, in seconds.
+    #[serde(default = "default_cache_ttl", alias = "cacheTtl")]
+    pub cache_ttl: u64,
+
+    #[serde(default = "default_cache_capacity", alias = "cacheCapacity")]
+    pub cache_capacity: usize,
+}
+
+fn default_path() -> String {
+    "/accounts/api/v2/clients/{clientId}".to_string()
+}
+
+fn default_cache_ttl() -> u64 {
+    300
+}
+
+fn default_cache_capacity() -> usize {
+    1000
+}
+
+/// A client application of the Anypoint client registry.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ClientApplication {
+    pub client_id: String,
+    pub name: Option<String>,
+}
+
+impl ClientApplication {
+    /// An application known by its api key only.
+    pub fn unresolved(api_key: String) -> Self {
+        Self { client_id: api_key, name: None }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+}
+
+/// Resolves api keys into client applications with the credentials of the identity management
+/// context of the policy, caching the applications and the access token of the registry.
+pub struct ClientRegistry<'a> {
+    lookup: &'a ApiKeyLookup,
+    applications: RefCell<LruTtlCache<String, Option<ClientApplication>>>,
+    access_token: RefCell<Option<(String, SystemTime)>>,
+}
+
+impl<'a> ClientRegistry<'a> {
+    pub fn new(lookup: &'a ApiKeyLookup) -> Self {
+        Self {
+            lookup,
+            applications: RefCell::new(LruTtlCache::new(lookup.cache_capacity, Duration::from_secs(lookup.cache_ttl))),
+            access_token: RefCell::new(None),
+        }
+    }
+
+    /// Returns the application of the api key, or None when the registry does not know it.
+    pub async fn resolve(&self, api_key: &str, client: &HttpClient) -> Result<Option<ClientApplication>, PolicyError> {
+        let now = SystemTime::now();
+        if let Some(application) = self.applications.borrow_mut().get(&api_key.to_string(), now) {
+            return Ok(application.clone());
+        }
+
+        // the keys which can not be client ids are never sent to the registry, nor cached
+        let path = match client_path(&self.lookup.path, api_key) {
+            Some(path) => path,
+            None => {
+                warn!("Api key with unexpected characters, not looked up");
+                return Ok(None);
+            }
+        };
+        let access_token = self.access_token(client).await?;
+        let authorization = format!("Bearer {}", access_token);
+
+        let application = client
+            .request(&self.lookup.upstream, &self.lookup.host)
+            .path(&path)
+            .headers(vec![("Authorization", authorization.as_str())])
+            .extract_with(|event, buffers| match buffers.status_code() {
+                200 => parse_body::<ClientApplication>(event, buffers).map(Some),
+                404 => Ok(None),
+                status => Err(unexpected_status("the client registry", status)),
+            })
+            .get()
+            .map_err(|err| PolicyError::from(err).context("requesting the client registry"))?
+            .await
+            .map_err(|err| PolicyError::from(err).context("reading the client registry response"))??;
+
+        info!("Api key resolved to {:?}", application);
+        self.applications.borrow_mut().insert(api_key.to_string(), application.clone(), now);
+        Ok(application)
+    }
+
+    // returns the access token of the registry, requesting a new one when the cached one expired
+    async fn access_token(&self, client: &HttpClient) -> Result<String, PolicyError> {
+        if let Some((token, expiration)) = self.access_token.borrow().as_ref() {
+            if SystemTime::now() < *expiration {
+                return Ok(token.clone());
+            }
+        }
+
+        let context: IdentityManagementContext = <dyn PolicyContext>::default()
+            .policy_metadata()
+            .identity_management_context()
+            .cloned()
+            .ok_or_else(|| PolicyError::new(codes::INVALID_CONFIGURATION, "the policy has no identity management context"))?;
+
+        let (authority, path) = split_url(context.token_url())
+            .ok_or_else(|| PolicyError::new(codes::INVALID_CONFIGURATION, format!("invalid token url {}", context.token_url())))?;
+        let body = serde_json::json!({
+            "grant_type": "client_credentials",
+            "client_id": context.client_id(),
+            "client_secret": context.client_secret(),
+        }).to_string();
+
+        let response = client
+            .request(&self.lookup.upstream, authority)
+            .path(path)
+            .headers(vec![("content-type", "application/json")])
+            .body(body.as_bytes())
+            .extract_with(|event, buffers| match buffers.status_code() {
+                200 => parse_body::<TokenResponse>(event, buffers),
+                status => Err(unexpected_status("the token endpoint", status)),
+            })
+            .post()
+            .map_err(|err| PolicyError::from(err).context("requesting the token endpoint"))?
+            .await
+            .map_err(|err| PolicyError::from(err).context("reading the token endpoint response"))??;
+
+        // renew the token a minute before it expires
+        let lifetime = response.expires_in.unwrap_or(0).saturating_sub(60);
+        let expiration = SystemTime::now() + Duration::from_secs(lifetime);
+        self.access_token.replace(Some((response.access_token.clone(), expiration)));
+
+        Ok(response.access_token)
+    }
+}
+
+fn parse_body<T: for<'de> Deserialize<'de>>(event: &HttpCallResponse, buffers: &dyn ResponseBuffers) -> Result<T, PolicyError> {
+    let body = buffers.body(0, event.body_size).unwrap_or_default();
+    serde_json::from_slice(&body).map_err(|err| {
+        warn!("Unexpected response body: {}", err);
+        PolicyError::new(codes::MALFORMED_RESPONSE, format!("unexpected response body: {}", err))
+    })
+}
+
+pub(crate) fn unexpected_status(service: &str, status: u32) -> PolicyError {
+    PolicyError::new(codes::UNEXPECTED_STATUS, format!("{} answered with status {}", service, status))
+}
+
+// the path of the application of the api key, None when the key is not made of the letters,
+// digits and dashes of the client ids, so it can not change the path or add a query
+pub(crate) fn client_path(template: &str, api_key: &str) -> Option<String> {
+    let valid = !api_key.is_empty() && api_key.bytes().all(|byte| byte.is_ascii_alphanumeric() || byte == b'-');
+    valid.then(|| template.replace("{clientId}", api_key))
+}
+
+// splits an url into its authority and path
+pub(crate) fn split_url(url: &str) -> Option<(&str, &str)> {
+    let (_, rest) = url.split_once("://")?;
+    match rest.find('/') {
+        Some(index) => Some((&rest[..index], &rest[index..])),
+        None => Some((rest, "/")),
+    }
+}
+
+
+#[test]
+fn test_split_url() {
+    assert_eq!(split_url("https://anypoint.mulesoft.com/accounts/api/v2/oauth2/token"),
+        Some(("anypoint.mulesoft.com", "/accounts/api/v2/oauth2/token")));
+    assert_eq!(split_url("http://localhost:8080"), Some(("localhost:8080", "/")));
+    assert_eq!(split_url("anypoint.mulesoft.com/token"), None);
+}
+
+#[test]
+fn test_unresolved_application() {
+    let application = ClientApplication::unresolved("key".to_string());
+
+    assert_eq!(application.client_id, "key");
+    assert_eq!(application.name, None);
+}
+
+#[test]
+fn test_client_path() {
+    let template = default_path();
+    assert_eq!(client_path(&template, "0a1b2c3d-4e5f").as_deref(), Some("/accounts/api/v2/clients/0a1b2c3d-4e5f"));
+
+    for api_key in ["", "../../oauth2/token", "key?admin=true", "key#", "key%2F", "key/other", "k\u{e9}y", "key value"] {
+        assert_eq!(client_path(&template, api_key), None, "{}", api_key);
+    }
+}
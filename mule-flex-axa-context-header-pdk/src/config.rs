@@ -1,13 +1,77 @@
 // Copyright 2023 Salesforce, Inc. All rights reserved.
 use serde::Deserialize;
 
+use crate::binding::RequestBinding;
+use crate::clients::ApiKeyLookup;
+use crate::header::HeaderLimit;
+use crate::identity::{default_identity_sources, AnonymousMode, IdentitySource};
+use crate::issuers::TrustedIssuer;
+use crate::limits::ClaimLimits;
+use crate::mapping::ClaimMapping;
+use crate::signing::SigningAlgorithm;
+use crate::validation::TokenValidation;
+
 #[derive(Debug, Deserialize)]
 pub struct Config {
     pub issuer: String,
 
     #[serde(alias = "privateKey")]
     pub private_key: String,
+
+    /// The algorithm signing the context token with the private key.
+    #[serde(default)]
+    pub algorithm: SigningAlgorithm,
     
     #[serde(alias = "audienceHeaderName")]
-    pub audience_header_name: String
+    pub audience_header_name: String,
+
+    #[serde(default = "default_identity_sources", alias = "identitySources")]
+    pub identity_sources: Vec<IdentitySource>,
+
+    #[serde(default, alias = "apiKeyLookup")]
+    pub api_key_lookup: Option<ApiKeyLookup>,
+
+    #[serde(default, alias = "anonymousMode")]
+    pub anonymous_mode: AnonymousMode,
+
+    #[serde(default, alias = "capExpiration")]
+    pub cap_expiration: bool,
+
+    #[serde(default, alias = "tokenValidation")]
+    pub token_validation: Option<TokenValidation>,
+
+    /// The JSON Web Key Set verifying the signature of the access tokens.
+    #[serde(default, alias = "jwksUrl")]
+    pub jwks_url: Option<String>,
+
+    /// The Flex service the JWKS is requested through.
+    #[serde(default, alias = "jwksUpstream")]
+    pub jwks_upstream: Option<String>,
+
+    /// How long the JWKS is cached, in seconds.
+    #[serde(default = "default_jwks_cache_ttl", alias = "jwksCacheTtl")]
+    pub jwks_cache_ttl: u64,
+
+    /// The issuers trusted to sign the access tokens, each with its own keys, selected by the iss
+    /// claim of the access token. The tokens of other issuers are invalid.
+    #[serde(default, alias = "trustedIssuers")]
+    pub trusted_issuers: Vec<TrustedIssuer>,
+
+    /// The claims of the access token set in the context token, over the default ones.
+    #[serde(default, alias = "claimMapping")]
+    pub claim_mapping: Vec<ClaimMapping>,
+
+    #[serde(default, alias = "claimLimits")]
+    pub claim_limits: ClaimLimits,
+
+    #[serde(default, alias = "headerLimit")]
+    pub header_limit: HeaderLimit,
+
+    /// Binds the context token to the method, path and body of the request.
+    #[serde(default, alias = "requestBinding")]
+    pub request_binding: RequestBinding
+}
+
+pub fn default_jwks_cache_ttl() -> u64 {
+    3600
 }
@@ -0,0 +1,222 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use jwt_simple::prelude::JWTClaims;
+use log::warn;
+use pdk::api::classy::event::HeadersAccessor;
+use pdk::api::error::PolicyError;
+use serde::Deserialize;
+
+use crate::jwt::JwtClaims;
+
+pub const AXA_CONTEXT_HEADER_NAME: &str = "X-AXA-CONTEXT";
+pub const AXA_CONTEXT_COUNT_HEADER_NAME: &str = "X-AXA-CONTEXT-COUNT";
+
+/// What to do when the context token exceeds the header size limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum HeaderLimitAction {
+    /// Reject the request.
+    #[serde(alias = "reject")]
+    Reject,
+
+    /// Drop the claims copied from the access token, from the least important, until the token fits.
+    /// The request is rejected when the token does not fit without them.
+    #[serde(alias = "dropClaims")]
+    DropClaims,
+
+    /// Split the token across the X-AXA-CONTEXT-1..N headers, with their count in X-AXA-CONTEXT-COUNT.
+    #[serde(alias = "split")]
+    Split,
+}
+
+impl Default for HeaderLimitAction {
+    fn default() -> Self {
+        HeaderLimitAction::Reject
+    }
+}
+
+/// Why the context headers cannot be made.
+#[derive(Debug)]
+pub enum HeaderError {
+    /// The token does not fit the size limit, with the reason of the rejection.
+    TooLarge(String),
+
+    /// The claims cannot be signed.
+    Signing(PolicyError),
+}
+
+impl From<PolicyError> for HeaderError {
+    fn from(error: PolicyError) -> Self {
+        HeaderError::Signing(error)
+    }
+}
+
+/// Size limit for the X-AXA-CONTEXT header. No limit applies by default.
+#[derive(Debug, Default, Deserialize)]
+pub struct HeaderLimit {
+    /// The maximum size of the header value, in bytes.
+    #[serde(default, alias = "maxSize")]
+    pub max_size: Option<usize>,
+
+    #[serde(default, alias = "onLimitExceeded")]
+    pub on_limit_exceeded: HeaderLimitAction,
+}
+
+impl HeaderLimit {
+    /// Signs the claims with `sign` and returns the headers carrying the resulting token.
+    /// Fails with the reason of the rejection when the token cannot be made to fit.
+    pub fn context_headers(&self, claims: &mut JWTClaims<JwtClaims>, sign: impl Fn(&JWTClaims<JwtClaims>) -> Result<String, PolicyError>)
+        -> Result<Vec<(String, String)>, HeaderError> {
+
+        let mut token = sign(claims)?;
+        let max = match self.max_size {
+            Some(max) if token.len() > max => max,
+            _ => return Ok(vec![(AXA_CONTEXT_HEADER_NAME.to_string(), token)])
+        };
+
+        let reason = format!("{} has {} bytes, over the limit of {}", AXA_CONTEXT_HEADER_NAME, token.len(), max);
+        match self.on_limit_exceeded {
+            HeaderLimitAction::Reject => Err(HeaderError::TooLarge(reason)),
+            HeaderLimitAction::DropClaims => {
+                warn!("Dropping claims: {}", reason);
+                let count = claims.custom.copied_claims().len();
+                for index in (0..count).rev() {
+                    let dropped = {
+                        let (name, claim) = claims.custom.copied_claims().swap_remove(index);
+                        claim.take().map(|_| name)
+                    };
+                    if let Some(name) = dropped {
+                        warn!("Dropping claim {}", name);
+                        token = sign(claims)?;
+                        if token.len() <= max {
+                            return Ok(vec![(AXA_CONTEXT_HEADER_NAME.to_string(), token)]);
+                        }
+                    }
+                }
+                Err(HeaderError::TooLarge(format!("{} has {} bytes without the optional claims, over the limit of {}",
+                    AXA_CONTEXT_HEADER_NAME, token.len(), max)))
+            }
+            HeaderLimitAction::Split => {
+                if max == 0 {
+                    return Err(HeaderError::TooLarge(reason));
+                }
+                warn!("Splitting the token: {}", reason);
+
+                // tokens are base64url encoded, so every chunk ends at a character boundary
+                let chunks: Vec<&str> = token.as_bytes()
+                    .chunks(max)
+                    .map(|chunk| std::str::from_utf8(chunk).unwrap_or_default())
+                    .collect();
+
+                let mut headers = vec![(AXA_CONTEXT_COUNT_HEADER_NAME.to_string(), chunks.len().to_string())];
+                headers.extend(chunks.iter()
+                    .enumerate()
+                    .map(|(index, chunk)| (format!("{}-{}", AXA_CONTEXT_HEADER_NAME, index + 1), chunk.to_string())));
+                Ok(headers)
+            }
+        }
+    }
+}
+
+// whether the header is the context header, one of its chunks or their count
+pub fn is_context_header(name: &str) -> bool {
+    let (name, prefix) = (name.as_bytes(), AXA_CONTEXT_HEADER_NAME.as_bytes());
+    name.eq_ignore_ascii_case(prefix)
+        || (name.len() > prefix.len() + 1 && name[..prefix.len()].eq_ignore_ascii_case(prefix) && name[prefix.len()] == b'-')
+}
+
+// removes the context headers of the request, so only the ones of the policy reach the upstream
+pub fn remove_context_headers(event: &dyn HeadersAccessor) {
+    for (name, _) in event.headers() {
+        if is_context_header(&name) {
+            warn!("Removing the {} header sent by the client", name);
+            event.remove_header(&name);
+        }
+    }
+}
+
+
+#[cfg(test)]
+fn sample_claims() -> JWTClaims<JwtClaims> {
+    use jwt_simple::prelude::{Claims, Duration};
+
+    let custom = JwtClaims {
+        subject_id: Some("subject".to_string()),
+        scope: Some("openid profile".to_string()),
+        ..JwtClaims::default()
+    };
+    Claims::with_custom_claims(custom, Duration::from_hours(2))
+}
+
+// a fake token made of the serialized custom claims, so its size follows the claims
+#[cfg(test)]
+fn fake_sign(claims: &JWTClaims<JwtClaims>) -> Result<String, PolicyError> {
+    Ok(serde_json::to_string(&claims.custom).unwrap())
+}
+
+#[test]
+fn test_no_header_limit_by_default() {
+    let mut claims = sample_claims();
+    let headers = HeaderLimit::default().context_headers(&mut claims, fake_sign).unwrap();
+
+    assert_eq!(headers, vec![(AXA_CONTEXT_HEADER_NAME.to_string(), fake_sign(&claims).unwrap())]);
+}
+
+#[test]
+fn test_reject_oversized_header() {
+    let limit = HeaderLimit { max_size: Some(10), on_limit_exceeded: HeaderLimitAction::Reject };
+
+    assert!(limit.context_headers(&mut sample_claims(), fake_sign).is_err());
+}
+
+#[test]
+fn test_drop_claims_until_header_fits() {
+    let mut claims = sample_claims();
+    let size = fake_sign(&claims).unwrap().len();
+    let limit = HeaderLimit { max_size: Some(size - 1), on_limit_exceeded: HeaderLimitAction::DropClaims };
+
+    let headers = limit.context_headers(&mut claims, fake_sign).unwrap();
+
+    assert_eq!(headers.len(), 1);
+    assert!(headers[0].1.len() < size);
+    assert_eq!(claims.custom.scope, None);
+    assert_eq!(claims.custom.subject_id.as_deref(), Some("subject"));
+
+    let limit = HeaderLimit { max_size: Some(10), on_limit_exceeded: HeaderLimitAction::DropClaims };
+
+    assert!(limit.context_headers(&mut sample_claims(), fake_sign).is_err());
+}
+
+#[test]
+fn test_split_oversized_header() {
+    let mut claims = sample_claims();
+    let token = fake_sign(&claims).unwrap();
+    let limit = HeaderLimit { max_size: Some(10), on_limit_exceeded: HeaderLimitAction::Split };
+
+    let headers = limit.context_headers(&mut claims, fake_sign).unwrap();
+    let count = (token.len() + 9) / 10;
+
+    assert_eq!(headers[0], (AXA_CONTEXT_COUNT_HEADER_NAME.to_string(), count.to_string()));
+    assert_eq!(headers.len(), count + 1);
+    assert_eq!(headers[1].0, "X-AXA-CONTEXT-1");
+    assert_eq!(headers[1..].iter().map(|(_, chunk)| chunk.as_str()).collect::<String>(), token);
+}
+
+#[test]
+fn test_signing_error() {
+    use pdk::api::error::codes;
+
+    let error = HeaderLimit::default()
+        .context_headers(&mut sample_claims(), |_| Err(PolicyError::new(codes::SIGNING_FAILED, "no key")))
+        .unwrap_err();
+
+    assert!(matches!(error, HeaderError::Signing(error) if error.code() == codes::SIGNING_FAILED));
+}
+
+#[test]
+fn test_context_headers() {
+    for name in ["X-AXA-CONTEXT", "x-axa-context", "X-AXA-CONTEXT-COUNT", "x-axa-context-1", "X-Axa-Context-12"] {
+        assert!(is_context_header(name), "{}", name);
+    }
+    for name in ["X-AXA-CONTEXTS", "X-AXA-CONTEXT-", "X-AXA", "x-axa-contexté", "authorization"] {
+        assert!(!is_context_header(name), "{}", name);
+    }
+}
@@ -0,0 +1,191 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use serde::Deserialize;
+
+use crate::clients::ClientApplication;
+use crate::jwt::AccessTokenPayload;
+
+/// Where the identity of the caller is taken from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum SourceKind {
+    /// The claims of the access_token header.
+    #[serde(alias = "token")]
+    Token,
+
+    /// The client application of the api-key header.
+    #[serde(alias = "apiKey")]
+    ApiKey,
+
+    /// The client_id from the subject of the client certificate.
+    #[serde(alias = "mtls")]
+    Mtls,
+
+    /// The default claims, when no other source provides an identity.
+    #[serde(alias = "anonymous")]
+    Anonymous,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct IdentitySource {
+    pub source: SourceKind,
+
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
+
+    /// Rejects the request when the source provides no identity. Ignored for anonymous.
+    #[serde(default)]
+    pub required: bool,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+impl IdentitySource {
+    fn new(source: SourceKind) -> Self {
+        Self { source, enabled: true, required: false }
+    }
+}
+
+/// The identity sources, from the highest to the lowest precedence, as the policy always used them:
+/// the client_id of mTLS overrides the one of the api key, which overrides the one of the access token.
+pub fn default_identity_sources() -> Vec<IdentitySource> {
+    vec![
+        IdentitySource::new(SourceKind::Mtls),
+        IdentitySource::new(SourceKind::ApiKey),
+        IdentitySource::new(SourceKind::Token),
+        IdentitySource::new(SourceKind::Anonymous),
+    ]
+}
+
+/// What to do with the requests identified by the anonymous source only.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum AnonymousMode {
+    /// Forward the request without the X-AXA-CONTEXT header.
+    #[serde(alias = "skip")]
+    Skip,
+
+    /// Reject the request.
+    #[serde(alias = "reject")]
+    Reject,
+
+    /// Mint a token with the default claims and an explicit anonymous amr claim.
+    #[serde(alias = "mintAnonymous")]
+    MintAnonymous,
+}
+
+impl Default for AnonymousMode {
+    fn default() -> Self {
+        AnonymousMode::MintAnonymous
+    }
+}
+
+/// An identity provided by a source.
+#[derive(Debug)]
+pub enum Identity {
+    Token(AccessTokenPayload),
+    ApiKey(ClientApplication),
+    Mtls(String),
+    Anonymous,
+}
+
+/// The identities found in the request.
+#[derive(Debug, Default)]
+pub struct FoundIdentities {
+    pub token: Option<AccessTokenPayload>,
+    pub api_key: Option<ClientApplication>,
+    pub mtls: Option<String>,
+}
+
+/// Picks the identities of the enabled sources, in the order they must be applied to the claims:
+/// from the lowest to the highest precedence, so the identities of the first sources have the last word.
+/// Returns the reason of the rejection when a required source or every source provides no identity.
+pub fn resolve(sources: &[IdentitySource], mut found: FoundIdentities) -> Result<Vec<Identity>, String> {
+    let mut identities = vec![];
+
+    for source in sources.iter().filter(|source| source.enabled) {
+        let identity = match source.source {
+            SourceKind::Token => found.token.take().map(Identity::Token),
+            SourceKind::ApiKey => found.api_key.take().map(Identity::ApiKey),
+            SourceKind::Mtls => found.mtls.take().map(Identity::Mtls),
+            SourceKind::Anonymous if identities.is_empty() => Some(Identity::Anonymous),
+            SourceKind::Anonymous => None,
+        };
+
+        match identity {
+            Some(identity) => identities.push(identity),
+            None if source.required && source.source != SourceKind::Anonymous => {
+                return Err(format!("required identity source {:?} is not present", source.source));
+            }
+            None => {}
+        }
+    }
+
+    if identities.is_empty() {
+        return Err("no identity source is present".to_string());
+    }
+
+    identities.reverse();
+    Ok(identities)
+}
+
+
+#[cfg(test)]
+fn all_found() -> FoundIdentities {
+    let payload = r#"{"client_id": "token-client", "iss": "issuer", "jti": "id", "sub": "subject", "exp": 0}"#;
+    FoundIdentities {
+        token: Some(serde_json::from_str(payload).unwrap()),
+        api_key: Some(ClientApplication::unresolved("api-key-client".to_string())),
+        mtls: Some("CN=client".to_string()),
+    }
+}
+
+#[cfg(test)]
+fn kinds(identities: &[Identity]) -> Vec<SourceKind> {
+    identities.iter()
+        .map(|identity| match identity {
+            Identity::Token(_) => SourceKind::Token,
+            Identity::ApiKey(_) => SourceKind::ApiKey,
+            Identity::Mtls(_) => SourceKind::Mtls,
+            Identity::Anonymous => SourceKind::Anonymous,
+        })
+        .collect()
+}
+
+#[test]
+fn test_default_precedence() {
+    let identities = resolve(&default_identity_sources(), all_found()).unwrap();
+    assert_eq!(kinds(&identities), vec![SourceKind::Token, SourceKind::ApiKey, SourceKind::Mtls]);
+
+    let identities = resolve(&default_identity_sources(), FoundIdentities::default()).unwrap();
+    assert_eq!(kinds(&identities), vec![SourceKind::Anonymous]);
+}
+
+#[test]
+fn test_configured_precedence() {
+    let sources: Vec<IdentitySource> = serde_json::from_str(r#"[
+        {"source": "token"},
+        {"source": "mtls"},
+        {"source": "apiKey", "enabled": false}
+    ]"#).unwrap();
+
+    let identities = resolve(&sources, all_found()).unwrap();
+    assert_eq!(kinds(&identities), vec![SourceKind::Mtls, SourceKind::Token]);
+}
+
+#[test]
+fn test_anonymous_modes() {
+    let modes: Vec<AnonymousMode> = serde_json::from_str(r#"["skip", "reject", "mintAnonymous"]"#).unwrap();
+    assert_eq!(modes, vec![AnonymousMode::Skip, AnonymousMode::Reject, AnonymousMode::MintAnonymous]);
+}
+
+#[test]
+fn test_missing_identity() {
+    let sources: Vec<IdentitySource> = serde_json::from_str(r#"[
+        {"source": "token", "required": true},
+        {"source": "anonymous"}
+    ]"#).unwrap();
+    assert!(resolve(&sources, FoundIdentities::default()).is_err());
+
+    let sources: Vec<IdentitySource> = serde_json::from_str(r#"[{"source": "apiKey"}]"#).unwrap();
+    assert!(resolve(&sources, FoundIdentities::default()).is_err());
+}
@@ -0,0 +1,245 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use std::collections::HashMap;
+
+use jwt_simple::prelude::RS256PublicKey;
+use pdk::api::classy::event::{EventData, RequestHeaders};
+use pdk::api::error::{codes, PolicyError};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::config::{default_jwks_cache_ttl, Config};
+use crate::jwks::Jwks;
+use crate::jwt::{now_in_secs, ClaimSet};
+use crate::mapping::{self, ClaimMapping};
+use crate::validation::{InvalidTokenAction, TokenValidation, TokenVerifier};
+
+/// An issuer of access tokens, trusted with its own keys, audiences and claim mappings.
+#[derive(Debug, Deserialize)]
+pub struct TrustedIssuer {
+    /// The iss claim of its access tokens.
+    pub issuer: String,
+
+    /// The aud claim of its access tokens must have one of them, any audience when empty.
+    #[serde(default)]
+    pub audiences: Vec<String>,
+
+    #[serde(default, alias = "tokenValidation")]
+    pub token_validation: TokenValidation,
+
+    /// The JSON Web Key Set verifying the signature of its access tokens.
+    #[serde(default, alias = "jwksUrl")]
+    pub jwks_url: Option<String>,
+
+    /// The Flex service the JWKS is requested through.
+    #[serde(default, alias = "jwksUpstream")]
+    pub jwks_upstream: Option<String>,
+
+    /// How long the JWKS is cached, in seconds.
+    #[serde(default = "default_jwks_cache_ttl", alias = "jwksCacheTtl")]
+    pub jwks_cache_ttl: u64,
+
+    /// The claims of its access tokens set in the context token, after the ones of the configuration.
+    #[serde(default, alias = "claimMapping")]
+    pub claim_mapping: Vec<ClaimMapping>,
+}
+
+/// How the access tokens of an issuer are validated, with the keys parsed once at configure time.
+pub struct IssuerTrust<'a> {
+    verifier: Option<TokenVerifier<'a>>,
+    jwks: Option<Jwks<'a>>,
+    audiences: &'a [String],
+    claim_mapping: &'a [ClaimMapping],
+}
+
+impl<'a> IssuerTrust<'a> {
+    pub fn jwks(&self) -> Option<&Jwks<'a>> {
+        self.jwks.as_ref()
+    }
+
+    pub fn claim_mapping(&self) -> &'a [ClaimMapping] {
+        self.claim_mapping
+    }
+
+    pub fn on_invalid(&self) -> InvalidTokenAction {
+        self.verifier.as_ref().map_or(InvalidTokenAction::default(), TokenVerifier::on_invalid)
+    }
+
+    /// Validates the raw access token, with the key selected for it in the JWKS if any, its
+    /// condition and its audience. Without a verifier, the access token is not validated.
+    pub fn validate(&self, token: &str, jwks_key: Option<Result<RS256PublicKey, String>>, event: &EventData<'_, RequestHeaders>) -> Result<(), String> {
+        let verifier = match &self.verifier {
+            Some(verifier) => verifier,
+            None => return Ok(()),
+        };
+
+        let claims = match jwks_key {
+            Some(jwks_key) => jwks_key.and_then(|jwks_key| verifier.verify_with(token, &jwks_key)),
+            None => verifier.verify(token, now_in_secs()),
+        }?;
+        verifier.check_condition(&claims, event)?;
+        self.check_audience(&claims)
+    }
+
+    fn check_audience(&self, claims: &ClaimSet) -> Result<(), String> {
+        if self.audiences.is_empty() {
+            return Ok(());
+        }
+
+        // the aud claim is a single audience or a list of them
+        let trusted = |audience: &Value| audience.as_str().map_or(false, |audience| self.audiences.iter().any(|trusted| trusted == audience));
+        let matches = match claims.as_value().get("aud") {
+            Some(Value::Array(audiences)) => audiences.iter().any(trusted),
+            Some(audience) => trusted(audience),
+            None => false,
+        };
+        if matches {
+            Ok(())
+        } else {
+            Err("the access token is not issued for a trusted audience".to_string())
+        }
+    }
+}
+
+/// An access token of an issuer that is not trusted, handled like the invalid ones.
+pub struct UntrustedIssuer {
+    pub reason: String,
+    pub on_invalid: InvalidTokenAction,
+}
+
+/// The issuers trusted to sign the access tokens. Without trusted issuers, every access token is
+/// validated with the `tokenValidation` and JWKS of the configuration.
+pub struct Trust<'a> {
+    default: IssuerTrust<'a>,
+    issuers: HashMap<&'a str, IssuerTrust<'a>>,
+}
+
+impl<'a> Trust<'a> {
+    pub fn new(config: &'a Config, default_validation: &'a TokenValidation) -> Result<Self, PolicyError> {
+        // the tokens are validated with the defaults when the JWKS is the only validation configured
+        let validation = config.token_validation.as_ref()
+            .or_else(|| config.jwks_url.as_ref().map(|_| default_validation));
+        let default = IssuerTrust {
+            verifier: validation.map(TokenVerifier::new).transpose()?,
+            jwks: Jwks::new(config)?,
+            audiences: &[],
+            claim_mapping: &[],
+        };
+
+        let mut issuers = HashMap::new();
+        for trusted in &config.trusted_issuers {
+            mapping::validate(&trusted.claim_mapping)?;
+            // without a key, any token claiming the issuer would be accepted
+            let validation = &trusted.token_validation;
+            if trusted.jwks_url.is_none() && validation.public_key.is_none() && validation.shared_secret.is_none() {
+                return Err(PolicyError::new(codes::INVALID_CONFIGURATION, format!("The issuer {} needs a jwksUrl, publicKey or sharedSecret", trusted.issuer)));
+            }
+            let jwks = Jwks::with_url(trusted.jwks_url.as_deref(), trusted.jwks_upstream.as_deref(), trusted.jwks_cache_ttl, Some(&trusted.token_validation))
+                .map_err(|err| err.context(format!("configuring the issuer {}", trusted.issuer)))?;
+            let verifier = TokenVerifier::new(&trusted.token_validation)
+                .map_err(|err| err.context(format!("configuring the issuer {}", trusted.issuer)))?;
+            let trust = IssuerTrust {
+                verifier: Some(verifier),
+                jwks,
+                audiences: &trusted.audiences,
+                claim_mapping: &trusted.claim_mapping,
+            };
+            if issuers.insert(trusted.issuer.as_str(), trust).is_some() {
+                return Err(PolicyError::new(codes::INVALID_CONFIGURATION, format!("The issuer {} is trusted more than once", trusted.issuer)));
+            }
+        }
+
+        Ok(Trust { default, issuers })
+    }
+
+    /// The trust of the issuer of the raw access token, selected by its iss claim.
+    pub fn select(&self, token: &str) -> Result<&IssuerTrust<'a>, UntrustedIssuer> {
+        if self.issuers.is_empty() {
+            return Ok(&self.default);
+        }
+
+        let untrusted = |reason: String| UntrustedIssuer { reason, on_invalid: self.default.on_invalid() };
+        let claims = ClaimSet::parse(token).map_err(|err| untrusted(err.to_string()))?;
+        let issuer = claims.as_value().get("iss").and_then(Value::as_str)
+            .ok_or_else(|| untrusted("the access token has no issuer".to_string()))?;
+        self.issuers.get(issuer)
+            .ok_or_else(|| untrusted(format!("the issuer {} of the access token is not trusted", issuer)))
+    }
+}
+
+
+#[cfg(test)]
+fn unsigned_token(claims: serde_json::Value) -> String {
+    let encode = |value: &str| base64::encode_config(value, base64::URL_SAFE_NO_PAD);
+    format!("{}.{}.", encode(r#"{"alg":"none"}"#), encode(&claims.to_string()))
+}
+
+#[cfg(test)]
+fn trusted_issuers() -> Config {
+    serde_json::from_value(serde_json::json!({
+        "issuer": "axa",
+        "privateKey": "key",
+        "audienceHeaderName": "x-audience",
+        "trustedIssuers": [
+            {"issuer": "https://login.axa.com", "audiences": ["orders"], "tokenValidation": {"sharedSecret": "secret"}},
+            {"issuer": "https://partners.example.com", "tokenValidation": {"sharedSecret": "partners"}, "claimMapping": [{"source": "partner", "target": "partner"}]}
+        ]
+    })).unwrap()
+}
+
+#[test]
+fn test_select_issuer_by_iss() {
+    let config = trusted_issuers();
+    let default_validation = TokenValidation::default();
+    let trust = Trust::new(&config, &default_validation).unwrap();
+
+    let partner = trust.select(&unsigned_token(serde_json::json!({"iss": "https://partners.example.com"}))).ok().unwrap();
+    assert_eq!(partner.claim_mapping().len(), 1);
+
+    let unknown = trust.select(&unsigned_token(serde_json::json!({"iss": "https://evil.example.com"}))).err().unwrap();
+    assert_eq!(unknown.on_invalid, InvalidTokenAction::Reject);
+    assert!(unknown.reason.contains("https://evil.example.com"));
+    assert!(trust.select(&unsigned_token(serde_json::json!({"sub": "subject"}))).is_err());
+}
+
+#[test]
+fn test_single_issuer_without_trusted_issuers() {
+    let config: Config = serde_json::from_str(r#"{"issuer": "axa", "privateKey": "key", "audienceHeaderName": "x-audience"}"#).unwrap();
+    let default_validation = TokenValidation::default();
+    let trust = Trust::new(&config, &default_validation).unwrap();
+
+    assert!(trust.select("not a token").is_ok());
+}
+
+#[test]
+fn test_check_audience() {
+    let config = trusted_issuers();
+    let default_validation = TokenValidation::default();
+    let trust = Trust::new(&config, &default_validation).unwrap();
+    let axa = trust.select(&unsigned_token(serde_json::json!({"iss": "https://login.axa.com"}))).ok().unwrap();
+    let audience = |aud: serde_json::Value| axa.check_audience(&ClaimSet::parse(&unsigned_token(serde_json::json!({"aud": aud}))).unwrap());
+
+    assert!(audience(serde_json::json!("orders")).is_ok());
+    assert!(audience(serde_json::json!(["billing", "orders"])).is_ok());
+    assert!(audience(serde_json::json!("billing")).is_err());
+    assert!(audience(serde_json::Value::Null).is_err());
+}
+
+#[test]
+fn test_issuer_trusted_twice() {
+    let mut config = trusted_issuers();
+    config.trusted_issuers[1].issuer = config.trusted_issuers[0].issuer.clone();
+    let default_validation = TokenValidation::default();
+
+    assert_eq!(Trust::new(&config, &default_validation).err().unwrap().code(), codes::INVALID_CONFIGURATION);
+}
+
+#[test]
+fn test_issuer_without_key() {
+    let mut config = trusted_issuers();
+    config.trusted_issuers[1].token_validation.shared_secret = None;
+    let default_validation = TokenValidation::default();
+
+    let err = Trust::new(&config, &default_validation).err().unwrap();
+    assert_eq!(err.code(), codes::INVALID_CONFIGURATION);
+    assert!(err.message().contains("https://partners.example.com"));
+}
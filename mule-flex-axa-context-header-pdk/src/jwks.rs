@@ -0,0 +1,346 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, SystemTime};
+
+use jwt_simple::prelude::{RS256PublicKey, Token};
+use log::{info, warn};
+use pdk::api::classy::client::{HttpClient, SingleFlight};
+use pdk::api::error::{codes, PolicyError};
+use serde::Deserialize;
+
+use crate::clients::{split_url, unexpected_status};
+use crate::config::Config;
+use crate::validation::TokenValidation;
+
+/// A key of a JSON Web Key Set, only the RSA ones verify access tokens.
+#[derive(Debug, Deserialize)]
+struct Jwk {
+    kty: String,
+    kid: Option<String>,
+    alg: Option<String>,
+    #[serde(rename = "use")]
+    key_use: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+impl Jwk {
+    // the RS256 public key, none for the keys of other types or uses
+    fn rs256_key(&self) -> Option<Result<RS256PublicKey, PolicyError>> {
+        if self.kty != "RSA"
+            || self.alg.as_deref().map_or(false, |alg| alg != "RS256")
+            || self.key_use.as_deref().map_or(false, |key_use| key_use != "sig") {
+            return None;
+        }
+
+        let component = |value: &Option<String>, name: &str| {
+            let value = value.as_deref()
+                .ok_or_else(|| invalid_key(format!("the key has no {} component", name)))?;
+            base64::decode_config(value, base64::URL_SAFE_NO_PAD)
+                .map_err(|err| invalid_key(format!("invalid {} component: {}", name, err)))
+        };
+        let key = component(&self.n, "n").and_then(|n| {
+            let e = component(&self.e, "e")?;
+            RS256PublicKey::from_components(&n, &e)
+                .map_err(|err| invalid_key(format!("invalid key: {}", err)))
+        });
+        Some(key)
+    }
+}
+
+// the signing keys of a JSON Web Key Set, by kid
+pub struct KeySet {
+    keys: HashMap<String, RS256PublicKey>,
+    // the single key without a kid, which verifies the tokens without one
+    anonymous: Option<RS256PublicKey>,
+}
+
+impl KeySet {
+    fn from_json(body: &[u8]) -> Result<Self, PolicyError> {
+        let set: JwkSet = serde_json::from_slice(body)
+            .map_err(|err| PolicyError::new(codes::MALFORMED_RESPONSE, format!("invalid JWKS: {}", err)))?;
+
+        let mut keys = HashMap::new();
+        let mut anonymous = Vec::new();
+        for jwk in set.keys {
+            match jwk.rs256_key() {
+                Some(Ok(key)) => match jwk.kid {
+                    Some(kid) => {
+                        keys.insert(kid, key);
+                    }
+                    None => anonymous.push(key),
+                },
+                Some(Err(err)) => warn!("Ignoring the JWKS key {:?}: {}", jwk.kid, err),
+                None => {}
+            }
+        }
+
+        let anonymous = match anonymous.len() {
+            1 => anonymous.pop(),
+            _ => None,
+        };
+        Ok(KeySet { keys, anonymous })
+    }
+
+    // the key of the kid, or the only key of the set for the tokens without a kid
+    fn get(&self, kid: Option<&str>) -> Option<&RS256PublicKey> {
+        match kid {
+            Some(kid) => self.keys.get(kid),
+            None => self.anonymous.as_ref().or_else(|| match self.keys.len() {
+                1 => self.keys.values().next(),
+                _ => None,
+            }),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.keys.len() + self.anonymous.iter().count()
+    }
+}
+
+type Fetched = Result<Rc<KeySet>, PolicyError>;
+
+/// The fetches of the key sets in flight, by url. The requests arriving while the set of their
+/// issuer is fetched wait for that fetch instead of calling the JWKS endpoint too.
+pub type JwksFlights = SingleFlight<String, Fetched>;
+
+/// Fetches the key set of the `jwksUrl` through the `jwksUpstream` service and caches it, to
+/// select the key verifying each access token by its kid.
+pub struct Jwks<'a> {
+    upstream: &'a str,
+    authority: &'a str,
+    path: &'a str,
+    cache_ttl: Duration,
+    keys: RefCell<Option<(Rc<KeySet>, SystemTime)>>,
+    // the error of the last fetch, returned without calling the endpoint until FAILURE_TTL passed
+    failure: RefCell<Option<(PolicyError, SystemTime)>>,
+}
+
+/// A kid missing from the cached set refetches it, at most once within this interval, so
+/// rotated keys are found without every forged kid calling the JWKS endpoint.
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How long a failed fetch is remembered, so an endpoint that is down is not called by every
+/// request in the meantime.
+const FAILURE_TTL: Duration = Duration::from_secs(30);
+
+// what the cache tells about the key set at some time
+enum Cached {
+    Keys { keys: Rc<KeySet>, refreshable: bool },
+    Failed(PolicyError),
+    Missing,
+}
+
+impl<'a> Jwks<'a> {
+    /// The key set of the configuration, none without a `jwksUrl`.
+    pub fn new(config: &'a Config) -> Result<Option<Self>, PolicyError> {
+        Self::with_url(config.jwks_url.as_deref(), config.jwks_upstream.as_deref(), config.jwks_cache_ttl, config.token_validation.as_ref())
+    }
+
+    /// The key set of the `url` requested through the `upstream` service, none without a `url`.
+    pub fn with_url(url: Option<&'a str>, upstream: Option<&'a str>, cache_ttl: u64, validation: Option<&TokenValidation>) -> Result<Option<Self>, PolicyError> {
+        let url = match url {
+            Some(url) => url,
+            None => return Ok(None),
+        };
+        let upstream = upstream
+            .ok_or_else(|| PolicyError::new(codes::INVALID_CONFIGURATION, "jwksUpstream is required with jwksUrl"))?;
+        let (authority, path) = split_url(url)
+            .ok_or_else(|| PolicyError::new(codes::INVALID_CONFIGURATION, format!("invalid jwks url {}", url)))?;
+        if let Some(validation) = validation {
+            if validation.public_key.is_some() || validation.shared_secret.is_some() {
+                return Err(PolicyError::new(codes::INVALID_CONFIGURATION, "Only one of jwksUrl, publicKey and sharedSecret can verify the access token"));
+            }
+        }
+
+        Ok(Some(Jwks {
+            upstream,
+            authority,
+            path,
+            cache_ttl: Duration::from_secs(cache_ttl),
+            keys: RefCell::new(None),
+            failure: RefCell::new(None),
+        }))
+    }
+
+    /// Returns the key verifying the access token, selected by the kid of its header.
+    pub async fn key_for(&self, token: &str, client: &HttpClient, flights: &JwksFlights) -> Result<RS256PublicKey, PolicyError> {
+        let metadata = Token::decode_metadata(token)
+            .map_err(|err| PolicyError::new(codes::MALFORMED_TOKEN, format!("Invalid token header: {}", err)))?;
+        let kid = metadata.key_id();
+
+        let now = SystemTime::now();
+        let (keys, refreshable) = match self.cached(now) {
+            Cached::Keys { keys, refreshable } => (keys, refreshable),
+            Cached::Failed(err) => return Err(err),
+            Cached::Missing => (self.fetch(client, flights, now).await?, false),
+        };
+        if let Some(key) = keys.get(kid) {
+            return Ok(key.clone());
+        }
+
+        // the issuer may have rotated its keys since the set was fetched
+        let keys = if refreshable { self.fetch(client, flights, now).await? } else { keys };
+        keys.get(kid).cloned().ok_or_else(|| {
+            PolicyError::new(codes::INVALID_SIGNATURE, format!("No key of the JWKS has the kid {:?}", kid))
+        })
+    }
+
+    // the keys fetched within the cache ttl, refreshable unless fetched or failed lately, or
+    // else the failure of the last fetch within its own ttl
+    fn cached(&self, now: SystemTime) -> Cached {
+        let failure = self.failure.borrow().clone()
+            .filter(|(_, failed_at)| age(*failed_at, now) < FAILURE_TTL);
+        let keys = self.keys.borrow().clone()
+            .filter(|(_, fetched_at)| age(*fetched_at, now) < self.cache_ttl);
+        match (keys, failure) {
+            (Some((keys, fetched_at)), failure) => Cached::Keys {
+                keys,
+                refreshable: failure.is_none() && age(fetched_at, now) >= MIN_REFRESH_INTERVAL,
+            },
+            (None, Some((err, _))) => Cached::Failed(err),
+            (None, None) => Cached::Missing,
+        }
+    }
+
+    // fetches the key set, or waits for the fetch in flight of another request
+    async fn fetch(&self, client: &HttpClient, flights: &JwksFlights, now: SystemTime) -> Result<Rc<KeySet>, PolicyError> {
+        let url = format!("{}{}", self.authority, self.path);
+        let fetched = flights.run(url, || self.request(client)).await
+            .map_err(|err| PolicyError::new(codes::REQUEST_FAILED, format!("requesting the JWKS endpoint: {}", err)))?;
+        self.store(&fetched, now);
+        fetched
+    }
+
+    async fn request(&self, client: &HttpClient) -> Fetched {
+        let keys = client
+            .request(self.upstream, self.authority)
+            .path(self.path)
+            .headers(vec![("accept", "application/json")])
+            .extract_with(|event, buffers| match buffers.status_code() {
+                200 => KeySet::from_json(&buffers.body(0, event.body_size).unwrap_or_default()),
+                status => Err(unexpected_status("the JWKS endpoint", status)),
+            })
+            .get()
+            .map_err(|err| PolicyError::from(err).context("requesting the JWKS endpoint"))?
+            .await
+            .map_err(|err| PolicyError::from(err).context("reading the JWKS endpoint response"))??;
+
+        info!("Fetched {} keys from the JWKS endpoint", keys.len());
+        Ok(Rc::new(keys))
+    }
+
+    fn store(&self, fetched: &Fetched, now: SystemTime) {
+        match fetched {
+            Ok(keys) => {
+                self.keys.replace(Some((keys.clone(), now)));
+                self.failure.replace(None);
+            }
+            Err(err) => {
+                warn!("Fetching the JWKS failed, retrying in {}s: {}", FAILURE_TTL.as_secs(), err);
+                self.failure.replace(Some((err.clone(), now)));
+            }
+        }
+    }
+}
+
+fn age(fetched_at: SystemTime, now: SystemTime) -> Duration {
+    now.duration_since(fetched_at).unwrap_or_default()
+}
+
+fn invalid_key(message: String) -> PolicyError {
+    PolicyError::new(codes::INVALID_KEY, message)
+}
+
+
+#[cfg(test)]
+fn jwk_set(keys: &[(Option<&str>, &RS256PublicKey)]) -> Vec<u8> {
+    let keys: Vec<serde_json::Value> = keys.iter()
+        .map(|(kid, key)| {
+            let components = key.to_components();
+            serde_json::json!({
+                "kty": "RSA",
+                "kid": kid,
+                "use": "sig",
+                "n": base64::encode_config(components.n, base64::URL_SAFE_NO_PAD),
+                "e": base64::encode_config(components.e, base64::URL_SAFE_NO_PAD),
+            })
+        })
+        .collect();
+    serde_json::json!({ "keys": keys }).to_string().into_bytes()
+}
+
+#[test]
+fn test_select_key_by_kid() {
+    use jwt_simple::prelude::{Claims, Duration, RS256KeyPair, RSAKeyPairLike};
+
+    let first = RS256KeyPair::generate(2048).unwrap().with_key_id("first");
+    let second = RS256KeyPair::generate(2048).unwrap().with_key_id("second");
+    let keys = KeySet::from_json(&jwk_set(&[(Some("first"), &first.public_key()), (Some("second"), &second.public_key())])).unwrap();
+
+    let token = second.sign(Claims::create(Duration::from_mins(5))).unwrap();
+    let kid = Token::decode_metadata(&token).unwrap().key_id().map(str::to_string);
+    let key = keys.get(kid.as_deref()).unwrap();
+
+    assert!(crate::jwt::validate_rs256(&token, key, 60).is_ok());
+    assert!(crate::jwt::validate_rs256(&token, keys.get(Some("first")).unwrap(), 60).is_err());
+    assert!(keys.get(Some("third")).is_none());
+    assert!(keys.get(None).is_none());
+}
+
+#[test]
+fn test_single_key_without_kid() {
+    use jwt_simple::prelude::RS256KeyPair;
+
+    let key_pair = RS256KeyPair::generate(2048).unwrap();
+    let keys = KeySet::from_json(&jwk_set(&[(None, &key_pair.public_key())])).unwrap();
+
+    assert_eq!(keys.len(), 1);
+    assert!(keys.get(None).is_some());
+}
+
+#[test]
+fn test_skip_keys_of_other_types() {
+    let body = br#"{"keys": [
+        {"kty": "EC", "kid": "ec", "crv": "P-256", "x": "AA", "y": "AA"},
+        {"kty": "RSA", "kid": "enc", "use": "enc", "n": "AQAB", "e": "AQAB"},
+        {"kty": "RSA", "kid": "broken", "n": "***", "e": "AQAB"}
+    ]}"#;
+    let keys = KeySet::from_json(body).unwrap();
+
+    assert_eq!(keys.len(), 0);
+    assert!(KeySet::from_json(b"{\"keys\": 1}").is_err());
+}
+
+#[test]
+fn test_cache_failed_fetches() {
+    use jwt_simple::prelude::RS256KeyPair;
+
+    let jwks = Jwks::with_url(Some("https://login.example.com/jwks"), Some("login"), 3600, None).unwrap().unwrap();
+    let at = |seconds: u64| std::time::UNIX_EPOCH + Duration::from_secs(seconds);
+    assert!(matches!(jwks.cached(at(0)), Cached::Missing));
+
+    let failure = unexpected_status("the JWKS endpoint", 503);
+    jwks.store(&Err(failure.clone()), at(0));
+    assert!(matches!(jwks.cached(at(29)), Cached::Failed(err) if err == failure));
+    assert!(matches!(jwks.cached(at(30)), Cached::Missing));
+
+    let key_pair = RS256KeyPair::generate(2048).unwrap();
+    let keys = KeySet::from_json(&jwk_set(&[(None, &key_pair.public_key())])).unwrap();
+    jwks.store(&Ok(Rc::new(keys)), at(100));
+    assert!(matches!(jwks.cached(at(159)), Cached::Keys { refreshable: false, .. }));
+    assert!(matches!(jwks.cached(at(160)), Cached::Keys { refreshable: true, .. }));
+
+    // a failed refresh keeps the keys, without refreshing them again for a while
+    jwks.store(&Err(failure), at(200));
+    assert!(matches!(jwks.cached(at(229)), Cached::Keys { refreshable: false, .. }));
+    assert!(matches!(jwks.cached(at(230)), Cached::Keys { refreshable: true, .. }));
+    assert!(matches!(jwks.cached(at(3700)), Cached::Missing));
+}
@@ -1,5 +1,12 @@
 use jwt_simple::prelude::*;
-use std::{error::Error, time::{SystemTime, UNIX_EPOCH}};
+use jwt_simple::JWTError;
+use pdk::api::error::{codes, PolicyError};
+use serde_json::{Map, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::binding::RequestClaims;
+#[cfg(test)]
+use proptest::prelude::*;
 
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -26,18 +33,19 @@ pub struct AccessTokenPayload {
     #[serde(rename = "axa-upn")]
     pub axa_upn: Option<String>,
     pub exp: i64,
+    pub nbf: Option<i64>,
     pub part_nr_ansp_person: Option<String>,
     #[serde(rename = "pi.sri")]
     pub pi_sri: Option<String>,
     pub part_nr_org: Option<String>
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Actor {
     pub client_id: String
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct JwtClaims {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub scope: Option<String>,
@@ -58,6 +66,9 @@ pub struct JwtClaims {
     pub expiration: Option<u64>,
     
     pub client_id: String,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_name: Option<String>,
     
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(rename = "jti")]
@@ -79,7 +90,20 @@ pub struct JwtClaims {
     
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(rename = "act")]
-    pub actor: Option<Actor>
+    pub actor: Option<Actor>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "amr")]
+    pub authentication_methods: Option<Vec<String>>,
+
+    /// The request the token is bound to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "req")]
+    pub request: Option<RequestClaims>,
+
+    /// The claims added by the claim mapping, beyond the ones above.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>
 }
 
 impl Default for JwtClaims {
@@ -96,11 +120,15 @@ impl Default for JwtClaims {
             expiration: Some(two_hours),
             token_id: Some(uuid()),
             client_id: Default::default(),
+            client_name: Default::default(),
             part_nr_ansp_person: Default::default(),
             pi_sri: Default::default(),
             part_nr_org: Default::default(),
             audience: Default::default(),
-            actor: Default::default()
+            actor: Default::default(),
+            authentication_methods: Default::default(),
+            request: Default::default(),
+            extra: Default::default()
         }
     }
 }
@@ -120,39 +148,137 @@ impl JwtClaims {
             expiration: Some(two_hours),
             token_id: Some(uuid()),
             client_id: access_payload.client_id, // Set appropriately if needed
+            client_name: None,
             part_nr_ansp_person: access_payload.part_nr_ansp_person,
             pi_sri: access_payload.pi_sri,
             part_nr_org: access_payload.part_nr_org, // Set appropriately if needed
             audience: None,
-            actor: None
+            actor: None,
+            authentication_methods: None,
+            request: None,
+            extra: Map::new()
         }
     }
 }
 
 
+impl JwtClaims {
+    /// The optional claims copied from the access token, from the most to the least important.
+    pub fn copied_claims(&mut self) -> Vec<(&'static str, &mut Option<String>)> {
+        vec![
+            ("sub", &mut self.subject_id),
+            ("scope", &mut self.scope),
+            ("part_nr_org", &mut self.part_nr_org),
+            ("part_nr_ansp_person", &mut self.part_nr_ansp_person),
+            ("pi.sri", &mut self.pi_sri),
+        ]
+    }
+}
+
+
+/// Tokens over this size are rejected before being decoded.
+pub const MAX_TOKEN_SIZE: usize = 64 * 1024;
+
 impl AccessTokenPayload {
-    pub fn parse_jwt_payload(token: &str) -> Result<Self, Box<dyn Error>> {
-        let parts: Vec<&str> = token.split('.').collect();
-        if parts.len() != 3 {
-            return Err("Invalid token format".into());
+    pub fn parse_jwt_payload(token: &str) -> Result<Self, PolicyError> {
+        let decoded_payload = decode_payload(token)?;
+
+        let payload: Self = serde_json::from_str(&decoded_payload)
+            .map_err(|err| malformed_token(format!("Invalid token payload: {}", err)))?;
+
+        Ok(payload)
+    }
+}
+
+// decodes the payload of the token, without verifying its signature
+fn decode_payload(token: &str) -> Result<String, PolicyError> {
+    check_size(token)?;
+
+    let parts: Vec<&str> = token.split('.').collect();
+    if parts.len() != 3 {
+        return Err(malformed_token("Invalid token format"));
+    }
+
+    decode_base64(parts[1])
+}
+
+
+/// All the claims of an access token, as they came, given to expressions as `vars.claimSet`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClaimSet(Value);
+
+impl ClaimSet {
+    /// The claims of the token, without verifying its signature.
+    pub fn parse(token: &str) -> Result<Self, PolicyError> {
+        let claims: Value = serde_json::from_str(&decode_payload(token)?)
+            .map_err(|err| malformed_token(format!("Invalid token payload: {}", err)))?;
+        if !claims.is_object() {
+            return Err(malformed_token("The token payload is not an object"));
         }
+        Ok(ClaimSet(claims))
+    }
 
-        let encoded_payload = parts[1];
-        let decoded_payload = decode_base64(encoded_payload)?;
+    pub fn as_value(&self) -> &Value {
+        &self.0
+    }
+}
 
-        let payload: Self = serde_json::from_str(&decoded_payload)?;
+/// Verifies the RS256 signature of the token, and its exp and nbf claims against the clock, give
+/// or take the clock skew in seconds.
+pub fn validate_rs256(token: &str, key: &RS256PublicKey, clock_skew: u64) -> Result<ClaimSet, PolicyError> {
+    check_size(token)?;
+    let verification = key.verify_token::<NoCustomClaims>(token, Some(verification_options(clock_skew)));
+    verified(token, verification)
+}
 
-        Ok(payload)
+/// Verifies the HS256 signature of the token, and its exp and nbf claims against the clock, give
+/// or take the clock skew in seconds.
+pub fn validate_hs256(token: &str, key: &HS256Key, clock_skew: u64) -> Result<ClaimSet, PolicyError> {
+    check_size(token)?;
+    let verification = key.verify_token::<NoCustomClaims>(token, Some(verification_options(clock_skew)));
+    verified(token, verification)
+}
+
+fn check_size(token: &str) -> Result<(), PolicyError> {
+    if token.len() > MAX_TOKEN_SIZE {
+        return Err(malformed_token(format!("Token of {} bytes is over the limit of {}", token.len(), MAX_TOKEN_SIZE)));
+    }
+    Ok(())
+}
+
+fn verification_options(clock_skew: u64) -> VerificationOptions {
+    VerificationOptions {
+        time_tolerance: Some(Duration::from_secs(clock_skew)),
+        ..Default::default()
     }
 }
 
-pub fn decode_base64(input: &str) -> Result<String, Box<dyn Error>> {
-    let decoded_bytes = base64::decode_config(input, base64::URL_SAFE)?;
-    let decoded_string = String::from_utf8(decoded_bytes)?;
+// the claims of a token, once the signature and the times are verified
+fn verified(token: &str, verification: Result<JWTClaims<NoCustomClaims>, jwt_simple::Error>) -> Result<ClaimSet, PolicyError> {
+    match verification {
+        Ok(_) => ClaimSet::parse(token),
+        Err(err) => match err.downcast_ref::<JWTError>() {
+            Some(JWTError::TokenHasExpired) | Some(JWTError::TokenNotValidYet) => {
+                Err(PolicyError::new(codes::EXPIRED_TOKEN, format!("Token not valid now: {}", err)))
+            }
+            _ => Err(PolicyError::new(codes::INVALID_SIGNATURE, format!("Invalid token signature: {}", err))),
+        },
+    }
+}
+
+pub fn decode_base64(input: &str) -> Result<String, PolicyError> {
+    let decoded_bytes = base64::decode_config(input, base64::URL_SAFE)
+        .map_err(|err| malformed_token(format!("Invalid base64: {}", err)))?;
+    let decoded_string = String::from_utf8(decoded_bytes)
+        .map_err(|err| malformed_token(format!("Invalid UTF-8: {}", err)))?;
     Ok(decoded_string)
 }
 
-fn now_in_secs() -> u64 {
+fn malformed_token(message: impl Into<String>) -> PolicyError {
+    PolicyError::new(codes::MALFORMED_TOKEN, message)
+}
+
+pub fn now_in_secs() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap()
@@ -191,4 +317,152 @@ fn test_uuid() {
     let uuid = uuid::Builder::from_bytes(bytes).into_uuid().to_string();
 
     println!("{}", uuid);
-}
\ No newline at end of file
+}
+
+
+#[cfg(test)]
+fn encode_token(payload: &AccessTokenPayload) -> String {
+    let payload = serde_json::to_string(payload).unwrap();
+    format!("eyJhbGciOiJSUzI1NiJ9.{}.c2lnbmF0dXJl", base64::encode_config(payload, base64::URL_SAFE_NO_PAD))
+}
+
+#[cfg(test)]
+fn optional_claim() -> impl Strategy<Value = Option<String>> {
+    proptest::option::of("\\PC{0,64}")
+}
+
+#[cfg(test)]
+prop_compose! {
+    fn access_token_payload()(
+        (client_id, iss, jti, sub) in ("\\PC{1,64}", "\\PC{1,64}", "\\PC{1,64}", "\\PC{0,64}"),
+        (scope, part_nr_ansp_person, pi_sri, part_nr_org) in (optional_claim(), optional_claim(), optional_claim(), optional_claim()),
+        (name, email, axa_upn, member_of) in (optional_claim(), optional_claim(), optional_claim(), optional_claim()),
+        (exp, iat, nbf) in (any::<i64>(), proptest::option::of(any::<u64>()), proptest::option::of(any::<i64>())),
+    ) -> AccessTokenPayload {
+        AccessTokenPayload {
+            scope,
+            client_id,
+            iss,
+            jti,
+            axa_department: None,
+            sub,
+            preferred_language: None,
+            axa_company: None,
+            axa_company_ou: None,
+            name,
+            given_name: None,
+            member_of,
+            family_name: None,
+            iat,
+            email,
+            axa_upn,
+            exp,
+            nbf,
+            part_nr_ansp_person,
+            pi_sri,
+            part_nr_org,
+        }
+    }
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn test_parse_jwt_payload_round_trip(payload in access_token_payload()) {
+        let parsed = AccessTokenPayload::parse_jwt_payload(&encode_token(&payload)).unwrap();
+
+        prop_assert_eq!(&parsed.client_id, &payload.client_id);
+        prop_assert_eq!(&parsed.sub, &payload.sub);
+        prop_assert_eq!(&parsed.scope, &payload.scope);
+        prop_assert_eq!(&parsed.part_nr_ansp_person, &payload.part_nr_ansp_person);
+        prop_assert_eq!(&parsed.pi_sri, &payload.pi_sri);
+        prop_assert_eq!(&parsed.part_nr_org, &payload.part_nr_org);
+        prop_assert_eq!(parsed.exp, payload.exp);
+        prop_assert_eq!(parsed.nbf, payload.nbf);
+    }
+
+    #[test]
+    fn test_claims_mapping_round_trip(payload in access_token_payload()) {
+        let expected = (
+            payload.client_id.clone(),
+            Some(payload.sub.clone()),
+            payload.scope.clone(),
+            payload.part_nr_ansp_person.clone(),
+            payload.pi_sri.clone(),
+            payload.part_nr_org.clone(),
+        );
+
+        let claims = JwtClaims::from_access_token_payloads(payload);
+        let claims: JwtClaims = serde_json::from_str(&serde_json::to_string(&claims).unwrap()).unwrap();
+
+        prop_assert_eq!(claims.issuer.as_str(), "MS_FLEX");
+        prop_assert_eq!(
+            (claims.client_id, claims.subject_id, claims.scope, claims.part_nr_ansp_person, claims.pi_sri, claims.part_nr_org),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_parse_jwt_payload_never_panics(token in "[A-Za-z0-9_=.-]{0,256}|\\PC{0,256}") {
+        let _ = AccessTokenPayload::parse_jwt_payload(&token);
+    }
+}
+
+#[test]
+fn test_parse_oversized_token() {
+    let token = format!("a.{}.c", "A".repeat(MAX_TOKEN_SIZE));
+    assert!(AccessTokenPayload::parse_jwt_payload(&token).is_err());
+}
+
+#[test]
+fn test_validate_hs256() {
+    let key = HS256Key::generate();
+    let claims = Claims::with_custom_claims(serde_json::json!({"client_id": "client"}), Duration::from_mins(5))
+        .with_subject("subject");
+    let token = key.authenticate(claims).unwrap();
+
+    let claim_set = validate_hs256(&token, &key, 60).unwrap();
+    assert_eq!(claim_set.as_value().get("client_id"), Some(&serde_json::json!("client")));
+    assert_eq!(claim_set.as_value().get("sub"), Some(&serde_json::json!("subject")));
+
+    let error = validate_hs256(&token, &HS256Key::generate(), 60).err().unwrap();
+    assert_eq!(error.code(), codes::INVALID_SIGNATURE);
+}
+
+#[test]
+fn test_validate_times_with_skew() {
+    let key = HS256Key::generate();
+    let now = Clock::now_since_epoch();
+
+    let mut expired = Claims::create(Duration::from_mins(5));
+    expired.expires_at = Some(now - Duration::from_mins(10));
+    let token = key.authenticate(expired).unwrap();
+    assert_eq!(validate_hs256(&token, &key, 60).err().unwrap().code(), codes::EXPIRED_TOKEN);
+    assert!(validate_hs256(&token, &key, 3600).is_ok());
+
+    let mut not_yet_valid = Claims::create(Duration::from_mins(30));
+    not_yet_valid.invalid_before = Some(now + Duration::from_mins(10));
+    let token = key.authenticate(not_yet_valid).unwrap();
+    assert_eq!(validate_hs256(&token, &key, 60).err().unwrap().code(), codes::EXPIRED_TOKEN);
+}
+
+#[test]
+fn test_validate_rs256() {
+    let key_pair = RS256KeyPair::generate(2048).unwrap();
+    let token = key_pair.sign(Claims::create(Duration::from_mins(5)).with_issuer("issuer")).unwrap();
+
+    let claim_set = validate_rs256(&token, &key_pair.public_key(), 60).unwrap();
+    assert_eq!(claim_set.as_value().get("iss"), Some(&serde_json::json!("issuer")));
+
+    // the algorithm of the token must be the one of the key, whatever the bytes of the key
+    let public_pem = key_pair.public_key().to_pem().unwrap();
+    let error = validate_hs256(&token, &HS256Key::from_bytes(public_pem.as_bytes()), 60).err().unwrap();
+    assert_eq!(error.code(), codes::INVALID_SIGNATURE);
+}
+
+#[test]
+fn test_claim_set_of_non_object_payload() {
+    let token = format!("e30.{}.c2lnbmF0dXJl", base64::encode_config("[1, 2]", base64::URL_SAFE_NO_PAD));
+
+    assert_eq!(ClaimSet::parse(&token).err().unwrap().code(), codes::MALFORMED_TOKEN);
+}
@@ -0,0 +1,178 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use log::warn;
+use serde::Deserialize;
+
+use crate::jwt::JwtClaims;
+
+/// What to do when the claims copied from the access token exceed a limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum LimitAction {
+    /// Truncate the oversized claims and drop the least important ones.
+    #[serde(alias = "truncate")]
+    Truncate,
+
+    /// Reject the request.
+    #[serde(alias = "reject")]
+    Reject,
+}
+
+impl Default for LimitAction {
+    fn default() -> Self {
+        LimitAction::Truncate
+    }
+}
+
+/// Limits for the claims copied from the access token into the context token.
+/// Every limit is optional, no limit applies by default.
+#[derive(Debug, Default, Deserialize)]
+pub struct ClaimLimits {
+    /// The maximum size of a claim, in bytes.
+    #[serde(default, alias = "maxClaimSize")]
+    pub max_claim_size: Option<usize>,
+
+    /// The maximum size of all the claims together, in bytes.
+    #[serde(default, alias = "maxTotalSize")]
+    pub max_total_size: Option<usize>,
+
+    /// The maximum number of claims.
+    #[serde(default, alias = "maxClaims")]
+    pub max_claims: Option<usize>,
+
+    #[serde(default, alias = "onLimitExceeded")]
+    pub on_limit_exceeded: LimitAction,
+}
+
+impl ClaimLimits {
+    /// Applies the limits to the claims copied from the access token.
+    /// Returns the reason of the rejection when a limit is exceeded and the action is to reject.
+    pub fn apply(&self, claims: &mut JwtClaims) -> Result<(), String> {
+        let mut copied = claims.copied_claims();
+
+        if let Some(max) = self.max_claim_size {
+            for (name, claim) in copied.iter_mut() {
+                if let Some(value) = claim.as_mut().filter(|value| value.len() > max) {
+                    self.exceeded(format!("claim {} has {} bytes, over the limit of {}", name, value.len(), max))?;
+                    warn!("Truncating claim {} to {} bytes", name, max);
+                    truncate(value, max);
+                }
+            }
+        }
+
+        if let Some(max) = self.max_claims {
+            let count = copied.iter().filter(|(_, claim)| claim.is_some()).count();
+            if count > max {
+                self.exceeded(format!("{} claims, over the limit of {}", count, max))?;
+                drop_least_important(&mut copied, |present| present.len() > max);
+            }
+        }
+
+        if let Some(max) = self.max_total_size {
+            let size = total_size(&copied);
+            if size > max {
+                self.exceeded(format!("claims have {} bytes, over the limit of {}", size, max))?;
+                drop_least_important(&mut copied, |present| present.iter().sum::<usize>() > max);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn exceeded(&self, reason: String) -> Result<(), String> {
+        match self.on_limit_exceeded {
+            LimitAction::Reject => Err(reason),
+            LimitAction::Truncate => {
+                warn!("Limiting the copied claims: {}", reason);
+                Ok(())
+            }
+        }
+    }
+}
+
+fn total_size(claims: &[(&str, &mut Option<String>)]) -> usize {
+    claims.iter().filter_map(|(_, claim)| claim.as_ref()).map(String::len).sum()
+}
+
+// drops the present claims from the last one while `too_many` holds for the sizes of the present claims
+fn drop_least_important(claims: &mut [(&str, &mut Option<String>)], too_many: impl Fn(&[usize]) -> bool) {
+    for index in (0..claims.len()).rev() {
+        let present: Vec<usize> = claims.iter()
+            .filter_map(|(_, claim)| claim.as_ref())
+            .map(String::len)
+            .collect();
+        if !too_many(&present) {
+            break;
+        }
+
+        let (name, claim) = &mut claims[index];
+        if claim.take().is_some() {
+            warn!("Dropping claim {}", name);
+        }
+    }
+}
+
+// truncates the value to at most `max` bytes, without splitting a character
+fn truncate(value: &mut String, max: usize) {
+    let mut end = max;
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    value.truncate(end);
+}
+
+
+#[cfg(test)]
+fn sample_claims() -> JwtClaims {
+    JwtClaims {
+        subject_id: Some("subject".to_string()),
+        scope: Some("openid profile".to_string()),
+        part_nr_org: Some("organization".to_string()),
+        ..JwtClaims::default()
+    }
+}
+
+#[test]
+fn test_no_limits_by_default() {
+    let mut claims = sample_claims();
+
+    assert_eq!(ClaimLimits::default().apply(&mut claims), Ok(()));
+    assert_eq!(claims.scope.as_deref(), Some("openid profile"));
+}
+
+#[test]
+fn test_truncate_oversized_claim() {
+    let limits = ClaimLimits { max_claim_size: Some(6), ..ClaimLimits::default() };
+    let mut claims = sample_claims();
+    claims.pi_sri = Some("ééé".to_string());
+
+    assert_eq!(limits.apply(&mut claims), Ok(()));
+    assert_eq!(claims.scope.as_deref(), Some("openid"));
+    assert_eq!(claims.pi_sri.as_deref(), Some("ééé"));
+    assert_eq!(claims.subject_id.as_deref(), Some("subjec"));
+}
+
+#[test]
+fn test_drop_least_important_claims() {
+    let limits = ClaimLimits { max_claims: Some(2), ..ClaimLimits::default() };
+    let mut claims = sample_claims();
+
+    assert_eq!(limits.apply(&mut claims), Ok(()));
+    assert_eq!(claims.subject_id.as_deref(), Some("subject"));
+    assert_eq!(claims.scope.as_deref(), Some("openid profile"));
+    assert_eq!(claims.part_nr_org, None);
+
+    let limits = ClaimLimits { max_total_size: Some(10), ..ClaimLimits::default() };
+    let mut claims = sample_claims();
+
+    assert_eq!(limits.apply(&mut claims), Ok(()));
+    assert_eq!(claims.subject_id.as_deref(), Some("subject"));
+    assert_eq!(claims.scope, None);
+}
+
+#[test]
+fn test_reject_exceeded_limit() {
+    let limits = ClaimLimits { max_total_size: Some(10), on_limit_exceeded: LimitAction::Reject, ..ClaimLimits::default() };
+    let mut claims = sample_claims();
+
+    assert!(limits.apply(&mut claims).is_err());
+    assert_eq!(claims.scope.as_deref(), Some("openid profile"));
+}
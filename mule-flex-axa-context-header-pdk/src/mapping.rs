@@ -0,0 +1,170 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use log::{info, warn};
+use pdk::api::classy::event::{EventData, RequestHeaders};
+use pdk::api::error::{codes, PolicyError};
+use pdk::api::expression::{Expression, Value as ExpressionValue};
+use serde::Deserialize;
+use serde_json::{Map, Number, Value};
+
+use crate::jwt::{ClaimSet, JwtClaims};
+
+/// The var holding the value of the source claim given to the transforms.
+const CLAIM_VAR: &str = "claim";
+
+/// The claims the policy sets itself, whatever the access token.
+const RESERVED_CLAIMS: [&str; 6] = ["iss", "exp", "iat", "nbf", "jti", "aud"];
+
+/// Sets a claim of the context token from a claim of the access token.
+#[derive(Debug, Deserialize)]
+pub struct ClaimMapping {
+    /// The claim of the access token, taken as is, dots included.
+    #[serde(default)]
+    pub source: Option<String>,
+
+    /// The claim of the context token.
+    pub target: String,
+
+    /// An expression computing the claim from the source one, given as `vars.claim`, and all the
+    /// claims of the access token, given as `vars.claimSet`.
+    #[serde(default)]
+    pub transform: Option<Expression>,
+}
+
+impl ClaimMapping {
+    // the value of the target claim, none to remove it
+    fn value(&self, claims: &ClaimSet, event: &EventData<'_, RequestHeaders>) -> Result<Option<Value>, String> {
+        let source = self.source.as_deref().and_then(|source| claims.as_value().get(source));
+        let transform = match &self.transform {
+            Some(transform) => transform,
+            None => return Ok(source.cloned()),
+        };
+
+        let value = transform
+            .with_claim_set(claims.as_value())
+            .with_var(CLAIM_VAR, source.cloned().unwrap_or(Value::Null))
+            .resolve_on_request_headers(event)
+            .map_err(|err| format!("the transform of claim {} could not be evaluated: {}", self.target, err))?;
+        to_json(&value)
+            .map(|value| Some(value).filter(|value| !value.is_null()))
+            .ok_or_else(|| format!("the transform of claim {} is not a json value", self.target))
+    }
+}
+
+/// Checks the mappings leave alone the claims the policy sets itself.
+pub fn validate(mappings: &[ClaimMapping]) -> Result<(), PolicyError> {
+    match mappings.iter().find(|mapping| RESERVED_CLAIMS.contains(&mapping.target.as_str())) {
+        Some(mapping) => Err(PolicyError::new(
+            codes::INVALID_CONFIGURATION,
+            format!("The claim {} is set by the policy and cannot be mapped", mapping.target),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Applies the mappings in order over the claims copied from the access token. The mappings
+/// that fail, or give a value of the wrong type to a claim, are logged and skipped.
+pub fn apply(mappings: &[ClaimMapping], claims: &mut JwtClaims, claim_set: &ClaimSet, event: &EventData<'_, RequestHeaders>) {
+    for mapping in mappings {
+        let result = mapping.value(claim_set, event)
+            .and_then(|value| set_claim(claims, &mapping.target, value));
+        match result {
+            Ok(()) => info!("Claim {} mapped", mapping.target),
+            Err(reason) => warn!("Skipping the mapping of claim {}: {}", mapping.target, reason),
+        }
+    }
+}
+
+// sets the target claim, into its field when it has one, or removes it without a value
+fn set_claim(claims: &mut JwtClaims, target: &str, value: Option<Value>) -> Result<(), String> {
+    let mut fields = match serde_json::to_value(&*claims) {
+        Ok(Value::Object(fields)) => fields,
+        _ => return Err("the claims are not an object".to_string()),
+    };
+    match value {
+        Some(value) => fields.insert(target.to_string(), value),
+        None => fields.remove(target),
+    };
+
+    *claims = serde_json::from_value(Value::Object(fields))
+        .map_err(|err| format!("invalid value: {}", err))?;
+    Ok(())
+}
+
+// the json of the value of an expression, none for functions and references
+fn to_json(value: &ExpressionValue) -> Option<Value> {
+    if value.is_null() {
+        return Some(Value::Null);
+    }
+    if let Some(value) = value.as_bool() {
+        return Some(Value::Bool(value));
+    }
+    if let Some(value) = value.as_str() {
+        return Some(Value::String(value.to_string()));
+    }
+    if let Some(value) = value.as_f64() {
+        // the integral numbers stay integers, like the times of the claims
+        let number = if value.fract() == 0.0 && value.abs() < 9_007_199_254_740_992.0 {
+            Number::from(value as i64)
+        } else {
+            Number::from_f64(value)?
+        };
+        return Some(Value::Number(number));
+    }
+    if let Some(items) = value.as_slice() {
+        return items.iter().map(to_json).collect::<Option<Vec<_>>>().map(Value::Array);
+    }
+    if let Some(object) = value.as_object() {
+        return object.iter()
+            .map(|(key, value)| to_json(value).map(|value| (key.clone(), value)))
+            .collect::<Option<Map<_, _>>>()
+            .map(Value::Object);
+    }
+    None
+}
+
+
+#[test]
+fn test_set_known_and_extra_claims() {
+    let mut claims = JwtClaims::default();
+
+    set_claim(&mut claims, "sub", Some(serde_json::json!("subject"))).unwrap();
+    set_claim(&mut claims, "axa-upn", Some(serde_json::json!("user@axa.ch"))).unwrap();
+    assert_eq!(claims.subject_id.as_deref(), Some("subject"));
+    assert_eq!(claims.extra.get("axa-upn"), Some(&serde_json::json!("user@axa.ch")));
+
+    set_claim(&mut claims, "sub", None).unwrap();
+    set_claim(&mut claims, "axa-upn", None).unwrap();
+    assert_eq!(claims.subject_id, None);
+    assert!(claims.extra.is_empty());
+}
+
+#[test]
+fn test_set_claim_of_wrong_type() {
+    let mut claims = JwtClaims { scope: Some("openid".to_string()), ..JwtClaims::default() };
+
+    assert!(set_claim(&mut claims, "scope", Some(serde_json::json!(["openid"]))).is_err());
+    assert_eq!(claims.scope.as_deref(), Some("openid"));
+}
+
+#[test]
+fn test_reserved_targets() {
+    let mapping = |target: &str| ClaimMapping { source: Some("sub".to_string()), target: target.to_string(), transform: None };
+
+    assert!(validate(&[mapping("sub"), mapping("department")]).is_ok());
+    assert_eq!(validate(&[mapping("exp")]).err().unwrap().code(), codes::INVALID_CONFIGURATION);
+}
+
+#[test]
+fn test_expression_values_to_json() {
+    let mut object = std::collections::HashMap::new();
+    object.insert("ou".to_string(), ExpressionValue::string("IT".to_string()));
+    let value = ExpressionValue::array(vec![
+        ExpressionValue::number(1700000000.0),
+        ExpressionValue::number(0.5),
+        ExpressionValue::bool(true),
+        ExpressionValue::null(),
+        ExpressionValue::object(object),
+    ]);
+
+    assert_eq!(to_json(&value), Some(serde_json::json!([1700000000, 0.5, true, null, {"ou": "IT"}])));
+}
@@ -0,0 +1,237 @@
+// Copyright 2023 Salesforce, Inc. All rights reserved.
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use jwt_simple::prelude::{HS256Key, RS256PublicKey};
+use pdk::api::classy::event::{EventData, RequestHeaders};
+use pdk::api::error::{codes, PolicyError};
+use pdk::api::expression::Expression;
+use pdk::api::keys::{KeyCache, PemKey};
+use serde::Deserialize;
+
+use crate::jwt::{self, AccessTokenPayload, ClaimSet};
+
+/// What to do with the access tokens that are expired or not valid yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum InvalidTokenAction {
+    /// Reject the request.
+    #[serde(alias = "reject")]
+    Reject,
+
+    /// Ignore the access token, as if the request had none.
+    #[serde(alias = "anonymous")]
+    Anonymous,
+}
+
+impl Default for InvalidTokenAction {
+    fn default() -> Self {
+        InvalidTokenAction::Reject
+    }
+}
+
+/// Validation of the exp and nbf claims of the access token against the host clock, and of its
+/// signature when a key is configured.
+#[derive(Debug, Deserialize)]
+pub struct TokenValidation {
+    /// The difference tolerated between the clocks of the token issuer and the host, in seconds.
+    #[serde(default = "default_clock_skew", alias = "clockSkew")]
+    pub clock_skew: u64,
+
+    #[serde(default, alias = "onInvalid")]
+    pub on_invalid: InvalidTokenAction,
+
+    /// The public key verifying the RS256 signature of the access token, in pem format.
+    #[serde(default, alias = "publicKey")]
+    pub public_key: Option<String>,
+
+    /// The secret verifying the HS256 signature of the access token.
+    #[serde(default, alias = "sharedSecret")]
+    pub shared_secret: Option<String>,
+
+    /// An expression the claims of the access token, given as `vars.claimSet`, must make true.
+    #[serde(default)]
+    pub condition: Option<Expression>,
+}
+
+fn default_clock_skew() -> u64 {
+    60
+}
+
+impl Default for TokenValidation {
+    fn default() -> Self {
+        TokenValidation {
+            clock_skew: default_clock_skew(),
+            on_invalid: InvalidTokenAction::default(),
+            public_key: None,
+            shared_secret: None,
+            condition: None,
+        }
+    }
+}
+
+impl TokenValidation {
+    /// Checks the access token is valid at `now`, in seconds since the epoch, give or take the clock skew.
+    pub fn validate(&self, payload: &AccessTokenPayload, now: u64) -> Result<(), String> {
+        let now = now as i64;
+        let skew = self.clock_skew as i64;
+
+        if payload.exp.saturating_add(skew) <= now {
+            return Err(format!("the access token expired at {}", payload.exp));
+        }
+
+        if let Some(not_before) = payload.nbf {
+            if not_before.saturating_sub(skew) > now {
+                return Err(format!("the access token is not valid before {}", not_before));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// the key verifying the signature of the access tokens
+thread_local! {
+    // the parsed public keys, shared by the issuers trusting the same key and reused when the
+    // policy is configured again
+    static PUBLIC_KEYS: RefCell<KeyCache<RS256PublicKey>> = RefCell::new(KeyCache::default());
+}
+
+enum SignatureKey {
+    Rs256(Rc<RS256PublicKey>),
+    Hs256(HS256Key),
+}
+
+/// The validation of the access tokens, with the key parsed once at configure time.
+pub struct TokenVerifier<'a> {
+    validation: &'a TokenValidation,
+    key: Option<SignatureKey>,
+}
+
+impl<'a> TokenVerifier<'a> {
+    pub fn new(validation: &'a TokenValidation) -> Result<Self, PolicyError> {
+        let key = match (&validation.public_key, &validation.shared_secret) {
+            (Some(_), Some(_)) => {
+                return Err(PolicyError::new(codes::INVALID_CONFIGURATION, "Only one of publicKey and sharedSecret can verify the access token"));
+            }
+            (Some(public_key), None) => {
+                // pkcs#1 public keys are normalized to spki, the format jwt-simple reads
+                let key = PemKey::parse(public_key)?;
+                if key.is_private() {
+                    return Err(PolicyError::new(codes::INVALID_KEY, "The publicKey is a private key"));
+                }
+                let key = PUBLIC_KEYS.with(|keys| {
+                    keys.borrow_mut().get_or_parse(&key, |key| {
+                        RS256PublicKey::from_pem(&key.to_pem())
+                            .map_err(|err| PolicyError::new(codes::INVALID_KEY, format!("Error creating public key: {}", err)))
+                    })
+                })?;
+                Some(SignatureKey::Rs256(key))
+            }
+            (None, Some(secret)) => Some(SignatureKey::Hs256(HS256Key::from_bytes(secret.as_bytes()))),
+            (None, None) => None,
+        };
+        Ok(TokenVerifier { validation, key })
+    }
+
+    pub fn on_invalid(&self) -> InvalidTokenAction {
+        self.validation.on_invalid
+    }
+
+    /// Validates the raw access token at `now`, in seconds since the epoch, returning its claims.
+    /// Without a key, the signature of the token is not verified.
+    pub fn verify(&self, token: &str, now: u64) -> Result<ClaimSet, String> {
+        let skew = self.validation.clock_skew;
+        let claims = match &self.key {
+            Some(SignatureKey::Rs256(key)) => jwt::validate_rs256(token, key, skew),
+            Some(SignatureKey::Hs256(key)) => jwt::validate_hs256(token, key, skew),
+            None => {
+                let payload = AccessTokenPayload::parse_jwt_payload(token).map_err(|err| err.to_string())?;
+                self.validation.validate(&payload, now)?;
+                ClaimSet::parse(token)
+            }
+        };
+        claims.map_err(|err| err.to_string())
+    }
+
+    /// Validates the raw access token against the key selected for it in the JWKS, returning its claims.
+    pub fn verify_with(&self, token: &str, key: &RS256PublicKey) -> Result<ClaimSet, String> {
+        jwt::validate_rs256(token, key, self.validation.clock_skew).map_err(|err| err.to_string())
+    }
+
+    /// Checks the claims of the access token make the condition true, if any.
+    pub fn check_condition(&self, claims: &ClaimSet, event: &EventData<'_, RequestHeaders>) -> Result<(), String> {
+        let condition = match &self.validation.condition {
+            Some(condition) => condition,
+            None => return Ok(()),
+        };
+
+        match condition.with_claim_set(claims.as_value()).resolve_on_request_headers(event) {
+            Ok(value) if value.as_bool() == Some(true) => Ok(()),
+            Ok(_) => Err("the access token does not meet the condition".to_string()),
+            Err(err) => Err(format!("the condition on the access token could not be evaluated: {}", err)),
+        }
+    }
+}
+
+
+#[cfg(test)]
+fn payload(exp: i64, nbf: Option<i64>) -> AccessTokenPayload {
+    let payload = serde_json::json!({"client_id": "client", "iss": "issuer", "jti": "id", "sub": "subject", "exp": exp, "nbf": nbf});
+    serde_json::from_value(payload).unwrap()
+}
+
+#[test]
+fn test_validate_expiration_with_skew() {
+    let validation = TokenValidation { clock_skew: 60, on_invalid: InvalidTokenAction::Reject, ..TokenValidation::default() };
+
+    assert_eq!(validation.validate(&payload(1000, None), 999), Ok(()));
+    assert_eq!(validation.validate(&payload(1000, None), 1059), Ok(()));
+    assert!(validation.validate(&payload(1000, None), 1060).is_err());
+}
+
+#[test]
+fn test_validate_not_before_with_skew() {
+    let validation = TokenValidation { clock_skew: 60, on_invalid: InvalidTokenAction::Reject, ..TokenValidation::default() };
+
+    assert_eq!(validation.validate(&payload(2000, Some(1000)), 940), Ok(()));
+    assert!(validation.validate(&payload(2000, Some(1000)), 939).is_err());
+}
+
+#[test]
+fn test_default_validation() {
+    let validation: TokenValidation = serde_json::from_str("{}").unwrap();
+
+    assert_eq!(validation.clock_skew, 60);
+    assert_eq!(validation.on_invalid, InvalidTokenAction::Reject);
+}
+
+#[test]
+fn test_verify_shared_secret() {
+    use jwt_simple::prelude::{Claims, Duration, MACLike};
+
+    let validation = TokenValidation { shared_secret: Some("secret".to_string()), ..TokenValidation::default() };
+    let verifier = TokenVerifier::new(&validation).unwrap();
+    let token = HS256Key::from_bytes(b"secret").authenticate(Claims::create(Duration::from_mins(5))).unwrap();
+    let forged = HS256Key::from_bytes(b"other").authenticate(Claims::create(Duration::from_mins(5))).unwrap();
+
+    assert!(verifier.verify(&token, 0).is_ok());
+    assert!(verifier.verify(&forged, 0).is_err());
+}
+
+#[test]
+fn test_verifier_with_two_keys() {
+    let validation = TokenValidation {
+        public_key: Some("-----BEGIN PUBLIC KEY-----".to_string()),
+        shared_secret: Some("secret".to_string()),
+        ..TokenValidation::default()
+    };
+
+    assert_eq!(TokenVerifier::new(&validation).err().unwrap().code(), codes::INVALID_CONFIGURATION);
+}
+
+#[test]
+fn test_invalid_public_key() {
+    let validation = TokenValidation { public_key: Some("not a key".to_string()), ..TokenValidation::default() };
+
+    assert_eq!(TokenVerifier::new(&validation).err().unwrap().code(), codes::INVALID_KEY);
+}
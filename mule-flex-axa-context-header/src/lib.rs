@@ -1,14 +1,14 @@
 use proxy_wasm::traits::*;
 use proxy_wasm::types::*;
 
-use log::info;
+use log::{error, info};
 
 
 use jwt_simple::prelude::*;
 use base64::decode;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::error::Error;
+use std::fmt;
 
 
 
@@ -71,24 +71,45 @@ struct JwtClaims {
 }
 
 
-fn decode_base64(input: &str) -> Result<String, Box<dyn Error>> {
-    let decoded_bytes = base64::decode_config(input, base64::URL_SAFE)?;
-    let decoded_string = String::from_utf8(decoded_bytes)?;
-    Ok(decoded_string)
+// why the access token could not be read, displayed as the MALFORMED_TOKEN PolicyError of the
+// pdk, FLTR-302, as this policy does not depend on it
+#[derive(Debug)]
+enum TokenError {
+    Format,
+    Base64(base64::DecodeError),
+    Utf8(std::string::FromUtf8Error),
+    Payload(serde_json::Error),
 }
 
-fn parse_jwt_payload(token: &str) -> Result<AccessTokenPayload, Box<dyn Error>> {
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FLTR-302 crypto: ")?;
+        match self {
+            TokenError::Format => write!(f, "the access token is not a JWT"),
+            TokenError::Base64(err) => write!(f, "the access token payload is not base64url: {}", err),
+            TokenError::Utf8(err) => write!(f, "the access token payload is not UTF-8: {}", err),
+            TokenError::Payload(err) => write!(f, "the access token payload is invalid: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for TokenError {}
+
+fn decode_base64(input: &str) -> Result<String, TokenError> {
+    let decoded_bytes = base64::decode_config(input, base64::URL_SAFE).map_err(TokenError::Base64)?;
+    String::from_utf8(decoded_bytes).map_err(TokenError::Utf8)
+}
+
+fn parse_jwt_payload(token: &str) -> Result<AccessTokenPayload, TokenError> {
     let parts: Vec<&str> = token.split('.').collect();
     if parts.len() != 3 {
-        return Err("Invalid token format".into());
+        return Err(TokenError::Format);
     }
 
     let encoded_payload = parts[1];
     let decoded_payload = decode_base64(encoded_payload)?;
 
-    let payload: AccessTokenPayload = serde_json::from_str(&decoded_payload)?;
-
-    Ok(payload)
+    serde_json::from_str(&decoded_payload).map_err(TokenError::Payload)
 }
 
 fn create_jwt_claims_from_payloads(
@@ -151,7 +172,13 @@ impl Context for CustomPolicyHeaderRoot {}
 impl RootContext for CustomPolicyHeaderRoot {
     fn on_configure(&mut self, _: usize) -> bool {
         if let Some(config_bytes) = self.get_plugin_configuration() {
-            self.config = serde_json::from_slice(config_bytes.as_slice()).unwrap()
+            match serde_json::from_slice(config_bytes.as_slice()) {
+                Ok(config) => self.config = config,
+                Err(err) => {
+                    error!("FLTR-101 config: {}", err);
+                    return false;
+                }
+            }
         }
         true
     }
@@ -234,6 +261,8 @@ wVIXRfPThZaZmjZRSPiXpXfUU47WlOhStZ7btWhrgRHuntUf3GVT84gv0uHyOSMX
             
             
             
+                // out of scope of the pdk keys module: this raw proxy-wasm policy does not depend on pdk,
+                // and its PKCS#8 key is parsed as is, mule-flex-axa-context-header-pdk replaces it
                 let key = RS256KeyPair::from_pem(&pem);
                 println!("Key Created");
                 match key {
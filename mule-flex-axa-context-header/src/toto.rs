@@ -402,6 +402,7 @@ impl HttpContext for CustomPolicyHeader {
     
     
     
+        // a scratch copy of the signing, kept on the embedded PKCS#8 key rather than pdk::api::keys
         let key = RS256KeyPair::from_pem(&pem);
         println!("Key Created");
         match key {
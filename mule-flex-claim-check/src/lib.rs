@@ -0,0 +1,355 @@
+use proxy_wasm::traits::*;
+use proxy_wasm::types::*;
+use log::{debug, error, info, warn};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::cell::Cell;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+thread_local! {
+    // tells apart the claims of the requests of the same nanosecond
+    static SEQUENCE: Cell<u64> = Cell::new(0);
+}
+
+proxy_wasm::main! {{
+    proxy_wasm::set_log_level(LogLevel::Trace);
+    proxy_wasm::set_root_context(|_| -> Box<dyn RootContext> {
+        Box::new(ClaimCheckRoot {
+            config: None,
+        })
+    });
+}}
+
+#[derive(Clone, Deserialize, Debug)]
+struct PolicyConfig {
+    // bodies with a larger content-length are stored
+    #[serde(alias = "thresholdBytes")]
+    threshold_bytes: usize,
+
+    // object store http api
+    upstream: String,
+    authority: String,
+    #[serde(alias = "pathPrefix", default = "default_path_prefix")]
+    path_prefix: String,
+    #[serde(default)]
+    authorization: Option<String>,
+
+    #[serde(alias = "headerName", default = "default_header_name")]
+    header_name: String,
+
+    // keys the hash of the claims, so they can not be guessed from the time of the requests
+    #[serde(alias = "claimSecret")]
+    claim_secret: String,
+
+    // replaces the responses carrying a claim check by the stored body
+    #[serde(alias = "rehydrateResponse", default)]
+    rehydrate_response: bool,
+
+    // forwards the original body when the object store fails, instead of responding 502
+    #[serde(alias = "failOpen", default)]
+    fail_open: bool,
+
+    #[serde(alias = "timeoutMs", default = "default_timeout_ms")]
+    timeout_ms: u64,
+}
+
+fn default_path_prefix() -> String {
+    "/claims".to_string()
+}
+
+fn default_header_name() -> String {
+    "x-claim-check".to_string()
+}
+
+fn default_timeout_ms() -> u64 {
+    5000
+}
+
+// where the exchange is at, as the object store calls pause it
+#[derive(Debug, PartialEq)]
+enum State {
+    Idle,
+    // the request body is larger than the threshold, waiting for all of it
+    Buffering,
+    // the request body is being stored
+    Storing { claim: String, body_size: usize },
+    // the body of the response claim is being fetched
+    Fetching { claim: String },
+}
+
+struct ClaimCheck {
+    context_id: u32,
+    config: PolicyConfig,
+    state: State,
+}
+
+impl ClaimCheck {
+
+    fn object_store_call(&self, method: &str, claim: &str, content_type: Option<&str>, body: Option<&[u8]>) -> Result<u32, Status> {
+        let path = claim_path(&self.config.path_prefix, claim);
+        let mut headers = vec![
+            (":method", method),
+            (":path", path.as_str()),
+            (":authority", self.config.authority.as_str()),
+        ];
+        if let Some(content_type) = content_type {
+            headers.push(("content-type", content_type));
+        }
+        if let Some(authorization) = &self.config.authorization {
+            headers.push(("authorization", authorization.as_str()));
+        }
+
+        self.dispatch_http_call(
+            &self.config.upstream,
+            headers,
+            body,
+            vec![],
+            Duration::from_millis(self.config.timeout_ms),
+        )
+    }
+
+    // the object store failed, the exchange goes on untouched when failing open
+    fn object_store_failed(&mut self) -> Action {
+        self.state = State::Idle;
+        if self.config.fail_open {
+            warn!("Object store unavailable, forwarding the exchange untouched");
+            Action::Continue
+        } else {
+            self.send_http_response(502, vec![("content-type", "application/json")], Some(b"{\"message\":\"Claim check store unavailable.\"}"));
+            Action::Pause
+        }
+    }
+
+    fn stored(&mut self, claim: String, body_size: usize, success: bool) {
+        if !success {
+            if self.object_store_failed() == Action::Continue {
+                self.resume_http_request();
+            }
+            return;
+        }
+
+        info!("Request body of {} bytes stored as claim {}", body_size, claim);
+
+        // the downstream policies and the upstream get the claim instead of the body
+        self.set_http_request_body(0, body_size, &[]);
+        self.set_http_request_header("content-length", Some("0"));
+        self.set_http_request_header(&self.config.header_name, Some(&claim));
+        self.resume_http_request();
+    }
+
+    fn fetched(&mut self, claim: String, body_size: usize, success: bool) {
+        if !success {
+            if self.object_store_failed() == Action::Continue {
+                self.resume_http_response();
+            }
+            return;
+        }
+
+        let body = self.get_http_call_response_body(0, body_size).unwrap_or_default();
+        let content_type = self.get_http_call_response_header("content-type");
+        info!("Response rehydrated from claim {} with {} bytes", claim, body.len());
+
+        // the upstream response headers, but the ones describing the claim
+        let status = self.get_http_response_header(":status")
+            .and_then(|status| status.parse::<u32>().ok())
+            .unwrap_or(200);
+        let upstream_headers = self.get_http_response_headers();
+        let mut headers: Vec<(&str, &str)> = upstream_headers.iter()
+            .filter(|(name, _)| !is_claim_header(name, &self.config.header_name))
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        if let Some(content_type) = &content_type {
+            headers.push(("content-type", content_type.as_str()));
+        }
+
+        self.send_http_response(status, headers, Some(&body));
+    }
+}
+
+impl Context for ClaimCheck {
+
+    fn on_http_call_response(&mut self, _: u32, _: usize, body_size: usize, _: usize) {
+        let status = self.get_http_call_response_header(":status").unwrap_or_default();
+        let success = status.starts_with('2');
+        debug!("Object store responded {:?}", status);
+
+        match std::mem::replace(&mut self.state, State::Idle) {
+            State::Storing { claim, body_size: stored } => self.stored(claim, stored, success),
+            State::Fetching { claim } => self.fetched(claim, body_size, success),
+            state => warn!("Unexpected object store response in state {:?}", state),
+        }
+    }
+}
+
+impl HttpContext for ClaimCheck {
+
+    fn on_http_request_headers(&mut self, _num_headers: usize, end_of_stream: bool) -> Action {
+        // only the claims of this policy reach the upstream, never the ones sent by the client
+        self.set_http_request_header(&self.config.header_name, None);
+
+        if end_of_stream {
+            return Action::Continue;
+        }
+
+        // bodies without a content-length are streamed, and never stored
+        let length = self.get_http_request_header("content-length")
+            .and_then(|length| length.trim().parse::<usize>().ok());
+
+        match length {
+            Some(length) if length > self.config.threshold_bytes => {
+                debug!("Buffering request body of {} bytes", length);
+                self.state = State::Buffering;
+                // the headers wait for the claim check
+                Action::Pause
+            }
+            _ => Action::Continue
+        }
+    }
+
+    fn on_http_request_body(&mut self, body_size: usize, end_of_stream: bool) -> Action {
+        if self.state != State::Buffering {
+            return Action::Continue;
+        }
+        if !end_of_stream {
+            // buffers until the whole body is received
+            return Action::Pause;
+        }
+
+        let body = self.get_http_request_body(0, body_size).unwrap_or_default();
+        let content_type = self.get_http_request_header("content-type");
+        let claim = claim_id(&self.config.claim_secret, self.get_current_time(), self.context_id);
+
+        match self.object_store_call("PUT", &claim, content_type.as_deref(), Some(&body)) {
+            Ok(_) => {
+                self.state = State::Storing { claim, body_size };
+                Action::Pause
+            }
+            Err(err) => {
+                error!("Error storing the request body: {:?}", err);
+                self.object_store_failed()
+            }
+        }
+    }
+
+    fn on_http_response_headers(&mut self, _num_headers: usize, _end_of_stream: bool) -> Action {
+        if !self.config.rehydrate_response {
+            return Action::Continue;
+        }
+
+        let claim = match self.get_http_response_header(&self.config.header_name) {
+            Some(claim) => claim,
+            None => return Action::Continue
+        };
+
+        match self.object_store_call("GET", &claim, None, None) {
+            Ok(_) => {
+                self.state = State::Fetching { claim };
+                Action::Pause
+            }
+            Err(err) => {
+                error!("Error fetching the claim {}: {:?}", claim, err);
+                self.object_store_failed()
+            }
+        }
+    }
+}
+
+struct ClaimCheckRoot {
+    config: Option<PolicyConfig>,
+}
+
+impl Context for ClaimCheckRoot {}
+
+impl RootContext for ClaimCheckRoot {
+    fn on_configure(&mut self, _: usize) -> bool {
+        if let Some(config_bytes) = self.get_plugin_configuration() {
+            match serde_json::from_slice::<PolicyConfig>(config_bytes.as_slice()) {
+                Ok(config) => {
+                    info!("Claim check configured for bodies above {} bytes", config.threshold_bytes);
+                    self.config = Some(config);
+                }
+                Err(err) => {
+                    error!("Invalid claim check configuration: {}", err);
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    fn create_http_context(&self, context_id: u32) -> Option<Box<dyn HttpContext>> {
+        let config = self.config.clone()?;
+        Some(Box::new(ClaimCheck {
+            context_id,
+            config,
+            state: State::Idle,
+        }))
+    }
+
+    fn get_type(&self) -> Option<ContextType> {
+        Some(ContextType::HttpContext)
+    }
+}
+
+// unique per request, the hash of the time, the context id and a sequence keyed by the secret,
+// as the wasm target has no random source
+fn claim_id(secret: &str, now: SystemTime, context_id: u32) -> String {
+    let nanos = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    let sequence = SEQUENCE.with(|sequence| {
+        sequence.set(sequence.get().wrapping_add(1));
+        sequence.get()
+    });
+
+    let mut hasher = Sha256::new();
+    hasher.update(secret.as_bytes());
+    hasher.update(format!(":{}:{}:{}", nanos, context_id, sequence));
+    format!("{:x}", hasher.finalize())
+}
+
+// the claim is percent-encoded, as the claims of the upstream responses are not ours
+fn claim_path(prefix: &str, claim: &str) -> String {
+    let mut path = format!("{}/", prefix.trim_end_matches('/'));
+    for byte in claim.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            path.push(byte as char);
+        } else {
+            path.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    path
+}
+
+fn is_claim_header(name: &str, claim_header: &str) -> bool {
+    name.starts_with(':')
+        || name.eq_ignore_ascii_case("content-length")
+        || name.eq_ignore_ascii_case("content-type")
+        || name.eq_ignore_ascii_case(claim_header)
+}
+
+
+#[test]
+fn test_claim_ids() {
+    let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let claim = claim_id("secret", now, 7);
+    assert_eq!(claim.len(), 64);
+    assert!(claim.bytes().all(|byte| byte.is_ascii_hexdigit()));
+
+    // neither the same request time and context nor another secret give the same claim
+    assert_ne!(claim_id("secret", now, 7), claim);
+    assert_ne!(claim_id("other", now, 7), claim_id("secret", now, 7));
+}
+
+#[test]
+fn test_claim_paths() {
+    assert_eq!(claim_path("/claims/", "0a1b-2c"), "/claims/0a1b-2c");
+    assert_eq!(claim_path("/claims", "../admin?x=1#y"), "/claims/..%2Fadmin%3Fx%3D1%23y");
+    assert_eq!(claim_path("/claims", "a b%"), "/claims/a%20b%25");
+}
+
+#[test]
+fn test_claim_headers() {
+    assert!(is_claim_header(":status", "x-claim-check"));
+    assert!(is_claim_header("Content-Length", "x-claim-check"));
+    assert!(is_claim_header("X-Claim-Check", "x-claim-check"));
+    assert!(!is_claim_header("etag", "x-claim-check"));
+}
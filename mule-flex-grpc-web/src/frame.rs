@@ -0,0 +1,142 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+// the flag of the frame carrying the trailers, at the end of the gRPC-web response body
+const TRAILER_FLAG: u8 = 0x80;
+
+// how the messages of a gRPC-web exchange are framed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    // application/grpc-web, the gRPC framing as it is
+    Binary,
+    // application/grpc-web-text, the gRPC framing in base64
+    Text,
+}
+
+// the content types of a gRPC-web exchange, like application/grpc-web-text+proto
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContentType {
+    pub mode: Mode,
+    // the message format, like +proto, empty when not given
+    pub format: String,
+}
+
+impl ContentType {
+
+    pub fn parse(content_type: &str) -> Option<Self> {
+        let media_type = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+        let rest = media_type.strip_prefix("application/grpc-web")?;
+
+        let (mode, format) = match rest.strip_prefix("-text") {
+            Some(format) => (Mode::Text, format),
+            None => (Mode::Binary, rest),
+        };
+        if !(format.is_empty() || format.starts_with('+')) {
+            return None;
+        }
+
+        Some(ContentType { mode, format: format.to_string() })
+    }
+
+    // the content type of the gRPC requests toward the upstream
+    pub fn grpc(&self) -> String {
+        format!("application/grpc{}", self.format)
+    }
+
+    // the content type of the responses to the client, in the mode of its request
+    pub fn grpc_web(&self) -> String {
+        match self.mode {
+            Mode::Binary => format!("application/grpc-web{}", self.format),
+            Mode::Text => format!("application/grpc-web-text{}", self.format),
+        }
+    }
+}
+
+// text bodies may be several base64 chunks, each padded, as streamed by the client
+pub fn decode_text(body: &[u8]) -> Result<Vec<u8>, base64::DecodeError> {
+    let body: Vec<u8> = body.iter().copied().filter(|byte| !byte.is_ascii_whitespace()).collect();
+
+    let mut decoded = Vec::with_capacity(body.len() * 3 / 4);
+    let mut start = 0;
+    let mut index = 0;
+    while index < body.len() {
+        if body[index] == b'=' {
+            // the padding ends the chunk
+            while index < body.len() && body[index] == b'=' {
+                index += 1;
+            }
+            decoded.extend(STANDARD.decode(&body[start..index])?);
+            start = index;
+        } else {
+            index += 1;
+        }
+    }
+    if start < body.len() {
+        decoded.extend(STANDARD.decode(&body[start..])?);
+    }
+
+    Ok(decoded)
+}
+
+pub fn encode_text(body: &[u8]) -> Vec<u8> {
+    STANDARD.encode(body).into_bytes()
+}
+
+// the frame carrying the trailers as an http/1 header block, with lowercase names
+pub fn trailer_frame(trailers: &[(String, String)]) -> Vec<u8> {
+    let block: String = trailers.iter()
+        .map(|(name, value)| format!("{}:{}\r\n", name.to_ascii_lowercase(), value))
+        .collect();
+
+    let mut frame = Vec::with_capacity(5 + block.len());
+    frame.push(TRAILER_FLAG);
+    frame.extend_from_slice(&(block.len() as u32).to_be_bytes());
+    frame.extend_from_slice(block.as_bytes());
+    frame
+}
+
+
+#[test]
+fn test_content_types() {
+    let content_type = ContentType::parse("application/grpc-web-text+proto; charset=utf-8").unwrap();
+    assert_eq!(content_type, ContentType { mode: Mode::Text, format: "+proto".to_string() });
+    assert_eq!(content_type.grpc(), "application/grpc+proto");
+    assert_eq!(content_type.grpc_web(), "application/grpc-web-text+proto");
+
+    let content_type = ContentType::parse("Application/gRPC-Web").unwrap();
+    assert_eq!(content_type.mode, Mode::Binary);
+    assert_eq!(content_type.grpc(), "application/grpc");
+    assert_eq!(content_type.grpc_web(), "application/grpc-web");
+
+    assert_eq!(ContentType::parse("application/grpc"), None);
+    assert_eq!(ContentType::parse("application/grpc-webby"), None);
+    assert_eq!(ContentType::parse("application/json"), None);
+}
+
+#[test]
+fn test_decode_text_chunks() {
+    // two padded chunks, as streamed by the client, with line breaks
+    assert_eq!(decode_text(b"AAAAAAE=\r\nAAAAAAE=").unwrap(), [0, 0, 0, 0, 1, 0, 0, 0, 0, 1]);
+    assert_eq!(decode_text(b"YWJj").unwrap(), b"abc");
+    assert_eq!(decode_text(b"YQ==YmM=").unwrap(), b"abc");
+    assert_eq!(decode_text(b"").unwrap(), b"");
+    assert!(decode_text(b"Y!==").is_err());
+
+    assert_eq!(decode_text(&encode_text(b"\x00\x00\x00\x00\x02hi")).unwrap(), b"\x00\x00\x00\x00\x02hi");
+}
+
+#[test]
+fn test_trailer_frame() {
+    let trailers = vec![
+        ("Grpc-Status".to_string(), "0".to_string()),
+        ("grpc-message".to_string(), "OK".to_string()),
+    ];
+    let frame = trailer_frame(&trailers);
+    let block = b"grpc-status:0\r\ngrpc-message:OK\r\n";
+
+    assert_eq!(frame[0], TRAILER_FLAG);
+    assert_eq!(frame[1..5], (block.len() as u32).to_be_bytes());
+    assert_eq!(&frame[5..], block);
+
+    assert_eq!(trailer_frame(&[]), [TRAILER_FLAG, 0, 0, 0, 0]);
+}
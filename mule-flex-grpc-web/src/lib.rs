@@ -0,0 +1,230 @@
+use proxy_wasm::traits::*;
+use proxy_wasm::types::*;
+use log::{debug, error, info, warn};
+use serde::Deserialize;
+
+mod frame;
+use frame::{decode_text, encode_text, trailer_frame, ContentType, Mode};
+
+proxy_wasm::main! {{
+    proxy_wasm::set_log_level(LogLevel::Trace);
+    proxy_wasm::set_root_context(|_| -> Box<dyn RootContext> {
+        Box::new(GrpcWebRoot {
+            config: None,
+        })
+    });
+}}
+
+// the gRPC status codes answered by the policy
+const INVALID_ARGUMENT: &str = "3";
+const RESOURCE_EXHAUSTED: &str = "8";
+
+#[derive(Clone, Deserialize, Debug)]
+struct PolicyConfig {
+    // requests and responses are buffered to be translated
+    #[serde(alias = "maxBodyBytes", default = "default_max_body_bytes")]
+    max_body_bytes: usize,
+}
+
+fn default_max_body_bytes() -> usize {
+    4 * 1024 * 1024
+}
+
+struct GrpcWeb {
+    config: PolicyConfig,
+    // the content type of the gRPC-web request, None for any other request
+    content_type: Option<ContentType>,
+    // the gRPC response is being buffered, waiting for its trailers
+    translating: bool,
+    response_body_size: usize,
+}
+
+impl GrpcWeb {
+
+    // a trailers-only gRPC-web response, with the status in the headers
+    fn send_status(&self, content_type: &ContentType, status: &str, message: &str) {
+        let grpc_web = content_type.grpc_web();
+        self.send_http_response(200, vec![
+            ("content-type", grpc_web.as_str()),
+            ("grpc-status", status),
+            ("grpc-message", message),
+        ], None);
+    }
+
+    // the buffered gRPC body followed by the frame of the trailers, in the mode of the request
+    fn translate_response(&mut self, trailers: &[(String, String)]) {
+        self.translating = false;
+        let content_type = match &self.content_type {
+            Some(content_type) => content_type,
+            None => return
+        };
+
+        let mut body = self.get_http_response_body(0, self.response_body_size).unwrap_or_default();
+        if !trailers.is_empty() {
+            body.extend(trailer_frame(trailers));
+        }
+        if content_type.mode == Mode::Text {
+            body = encode_text(&body);
+        }
+
+        debug!("Translated gRPC response of {} bytes", body.len());
+        self.set_http_response_body(0, self.response_body_size, &body);
+    }
+}
+
+impl Context for GrpcWeb {}
+
+impl HttpContext for GrpcWeb {
+
+    fn on_http_request_headers(&mut self, _num_headers: usize, _end_of_stream: bool) -> Action {
+        let content_type = match self.get_http_request_header("content-type").as_deref().and_then(ContentType::parse) {
+            Some(content_type) => content_type,
+            None => return Action::Continue
+        };
+
+        debug!("Translating gRPC-web request {:?}", content_type);
+        self.set_http_request_header("content-type", Some(&content_type.grpc()));
+        self.set_http_request_header("te", Some("trailers"));
+        self.set_http_request_header("x-grpc-web", None);
+        if content_type.mode == Mode::Text {
+            // the decoded body is shorter
+            self.set_http_request_header("content-length", None);
+        }
+
+        self.content_type = Some(content_type);
+        Action::Continue
+    }
+
+    fn on_http_request_body(&mut self, body_size: usize, end_of_stream: bool) -> Action {
+        let content_type = match &self.content_type {
+            Some(content_type) if content_type.mode == Mode::Text => content_type.clone(),
+            _ => return Action::Continue
+        };
+
+        if body_size > self.config.max_body_bytes {
+            info!("gRPC-web request body of {} bytes is above the maximum", body_size);
+            self.send_status(&content_type, RESOURCE_EXHAUSTED, "Request body too large.");
+            return Action::Pause;
+        }
+        if !end_of_stream {
+            // buffers until the whole body is received
+            return Action::Pause;
+        }
+
+        let body = self.get_http_request_body(0, body_size).unwrap_or_default();
+        match decode_text(&body) {
+            Ok(decoded) => {
+                self.set_http_request_body(0, body_size, &decoded);
+                Action::Continue
+            }
+            Err(err) => {
+                info!("Invalid gRPC-web text request body: {}", err);
+                self.send_status(&content_type, INVALID_ARGUMENT, "Request body is not valid base64.");
+                Action::Pause
+            }
+        }
+    }
+
+    fn on_http_response_headers(&mut self, _num_headers: usize, end_of_stream: bool) -> Action {
+        let content_type = match &self.content_type {
+            Some(content_type) => content_type,
+            None => return Action::Continue
+        };
+
+        // errors not coming from the gRPC service, like the ones of other policies, go as they are
+        let is_grpc = self.get_http_response_header("content-type")
+            .map_or(false, |upstream| upstream.trim().to_ascii_lowercase().starts_with("application/grpc"));
+        if !is_grpc {
+            return Action::Continue;
+        }
+
+        let grpc_web = content_type.grpc_web();
+        self.set_http_response_header("content-type", Some(&grpc_web));
+        self.set_http_response_header("content-length", None);
+
+        if end_of_stream {
+            // a trailers-only response, its status already in the headers
+            return Action::Continue;
+        }
+
+        // the headers wait for the trailers, which end up in the body
+        self.translating = true;
+        Action::Pause
+    }
+
+    fn on_http_response_body(&mut self, body_size: usize, end_of_stream: bool) -> Action {
+        if !self.translating {
+            return Action::Continue;
+        }
+
+        self.response_body_size = body_size;
+        if body_size > self.config.max_body_bytes {
+            warn!("gRPC response body of {} bytes is above the maximum", body_size);
+            self.translating = false;
+            if let Some(content_type) = self.content_type.clone() {
+                self.send_status(&content_type, RESOURCE_EXHAUSTED, "Response body too large.");
+            }
+            return Action::Pause;
+        }
+        if !end_of_stream {
+            return Action::Pause;
+        }
+
+        // the response ended without trailers
+        self.translate_response(&[]);
+        Action::Continue
+    }
+
+    fn on_http_response_trailers(&mut self, _num_trailers: usize) -> Action {
+        if !self.translating {
+            return Action::Continue;
+        }
+
+        let trailers = self.get_http_response_trailers();
+        self.translate_response(&trailers);
+
+        // gRPC-web clients read the trailers from the body
+        self.set_http_response_trailers(vec![]);
+        Action::Continue
+    }
+}
+
+struct GrpcWebRoot {
+    config: Option<PolicyConfig>,
+}
+
+impl Context for GrpcWebRoot {}
+
+impl RootContext for GrpcWebRoot {
+    fn on_configure(&mut self, _: usize) -> bool {
+        let config = match self.get_plugin_configuration() {
+            Some(config_bytes) => serde_json::from_slice::<PolicyConfig>(config_bytes.as_slice()),
+            None => serde_json::from_str::<PolicyConfig>("{}"),
+        };
+
+        match config {
+            Ok(config) => {
+                self.config = Some(config);
+                true
+            }
+            Err(err) => {
+                error!("Invalid gRPC-web configuration: {}", err);
+                false
+            }
+        }
+    }
+
+    fn create_http_context(&self, _: u32) -> Option<Box<dyn HttpContext>> {
+        let config = self.config.clone()?;
+        Some(Box::new(GrpcWeb {
+            config,
+            content_type: None,
+            translating: false,
+            response_body_size: 0,
+        }))
+    }
+
+    fn get_type(&self) -> Option<ContextType> {
+        Some(ContextType::HttpContext)
+    }
+}
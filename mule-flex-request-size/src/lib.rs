@@ -1,71 +1,162 @@
 use proxy_wasm::traits::*;
 use proxy_wasm::types::*;
-use log::info;
-use serde::{Deserialize, Serialize};
+use log::{error, info};
+use pdk_decisions::{Decision, Outcome, POLICY_DECISIONS};
+use serde::Deserialize;
+use std::rc::Rc;
+
+mod migration;
+mod rejection;
+mod routes;
+
+use crate::rejection::{append_decision, decision, Direction, Rejection, RejectionConfig, Violation};
+use crate::routes::{route_max_kb, RouteLimit};
 
 proxy_wasm::main! {{
     proxy_wasm::set_log_level(LogLevel::Trace);
     proxy_wasm::set_root_context(|_| -> Box<dyn RootContext> {
         Box::new(HttpConfigHeaderRoot {
-            field_name: String::new(),
-            max_body_size: 0,
+            limits: None,
         })
     });
 }}
 
+// counts the bytes of a body against its limit
+#[derive(Clone, Copy, Debug)]
+struct Accumulator {
+    direction: Direction,
+    max_kb: usize,
+    current_body_size: usize,
+}
+
+impl Accumulator {
+    fn new(direction: Direction, max_kb: usize) -> Self {
+        Accumulator { direction, max_kb, current_body_size: 0 }
+    }
+
+    fn max_body_size(&self) -> usize {
+        self.max_kb.saturating_mul(1024)
+    }
+
+    // adds the bytes received, the violation once they exceed the limit
+    fn add(&mut self, body_size: usize) -> Option<Violation> {
+        self.current_body_size = self.current_body_size.saturating_add(body_size);
+        self.check()
+    }
+
+    // the body is buffered, the bytes received are the whole buffer
+    fn buffered(&mut self, body_size: usize) -> Option<Violation> {
+        self.current_body_size = body_size;
+        self.check()
+    }
+
+    // the body was received whole, within its limit
+    fn allowed(&self) -> Decision {
+        decision(self.direction, Outcome::Allow)
+            .with_detail("maxKb", self.max_kb)
+            .with_detail("receivedBytes", self.current_body_size)
+    }
+
+    fn check(&self) -> Option<Violation> {
+        if self.current_body_size > self.max_body_size() {
+            Some(Violation {
+                direction: self.direction,
+                max_kb: self.max_kb,
+                received_bytes: self.current_body_size,
+            })
+        } else {
+            None
+        }
+    }
+}
+
 struct HttpConfigHeader {
-    max_body_size: usize, // Store the maximum body size directly.
-    current_body_size: usize, // Store the accumulated body size.
+    limits: Rc<Limits>,
+    // the limit of the route, known once the request headers are
+    request: Option<Accumulator>,
+    response: Option<Accumulator>,
 }
 
 impl Context for HttpConfigHeader {}
 
-// A simple struct to represent a JSON response message.
-#[derive(Serialize, Deserialize)]
-struct JsonResponse {
-    message: String,
-}
+// the context of the requests while no configuration was applied, they are not limited
+struct PassThrough;
 
-impl HttpContext for HttpConfigHeader {
-    fn on_http_request_headers(&mut self, _num_headers: usize, _end_of_stream: bool) -> Action {
-        info!("on_http_request_headers");
-        Action::Continue
-    }
+impl Context for PassThrough {}
 
-    fn on_http_request_body(&mut self, _body_size: usize, _end_of_stream: bool) -> Action {
-        info!("on_http_request_body");
+impl HttpContext for PassThrough {}
 
-        if self.current_body_size + _body_size > self.max_body_size {
-            // If the accumulated body size exceeds the maximum allowed size, return BadRequest.
-            info!("Received an HTTP request with a body size larger than the maximum allowed.");
+impl HttpConfigHeader {
+    fn reject(&self, violation: Violation) -> Action {
+        info!("Received an HTTP {:?} with a body size larger than the maximum allowed.", violation.direction);
 
-            // Create a JSON response message.
-            let json_response = JsonResponse {
-                message: "Body size exceeds the maximum allowed.".to_string(),
-            };
+        let rejection = &self.limits.rejection;
+        let body = rejection.body(&violation);
+        self.record_decision(violation.decision(rejection.status_code()));
+        self.send_http_response(rejection.status_code(), rejection.headers(), Some(body.as_bytes()));
+        Action::Pause
+    }
 
-            // Convert the JSON response to a string.
-            let response_body = serde_json::to_string(&json_response).unwrap();
+    // records the decision on a body in the filter state, for the access log to read with
+    // %FILTER_STATE(wasm.exchange.policy_decisions:PLAIN)%
+    fn record_decision(&self, decision: Decision) {
+        let previous = self.get_property(vec![POLICY_DECISIONS]);
+        let decisions = append_decision(previous.as_deref(), decision);
+        self.set_property(vec![POLICY_DECISIONS], Some(decisions.as_bytes()));
+    }
+}
 
-            // Convert the JSON response to a byte slice (u8 slice).
-            let response_body_bytes = response_body.as_bytes();
+// the content-length of a body, when known upfront
+fn content_length(value: Option<String>) -> Option<usize> {
+    value.and_then(|length| length.trim().parse::<usize>().ok())
+}
 
-            // Send the HTTP response with the JSON message.
-            self.send_http_response(401, Vec::new(), Some(response_body_bytes));
+impl HttpContext for HttpConfigHeader {
+    fn on_http_request_headers(&mut self, _num_headers: usize, end_of_stream: bool) -> Action {
+        info!("on_http_request_headers");
 
-            // Reset the accumulated body size for the next request.
-            self.current_body_size = 0;
+        let path = self.get_http_request_header(":path").unwrap_or_default();
+        let method = self.get_http_request_header(":method").unwrap_or_default();
+        let max_kb = match self.limits.request_max_kb(&path, &method) {
+            Some(max_kb) => max_kb,
+            None => {
+                info!("No maximum size for {} {}", method, path);
+                self.record_decision(decision(Direction::Request, Outcome::Skip));
+                return Action::Continue;
+            }
+        };
+        let request = Accumulator::new(Direction::Request, max_kb);
+        self.request = Some(request);
 
-            return Action::Pause;
+        // the bodies announcing a larger size are rejected before being streamed
+        if let Some(length) = content_length(self.get_http_request_header("content-length")) {
+            let mut accumulator = request;
+            if let Some(violation) = accumulator.add(length) {
+                return self.reject(violation);
+            }
         }
 
-        // Update the accumulated body size.
-        self.current_body_size += _body_size;
+        if end_of_stream {
+            self.record_decision(request.allowed());
+        }
+        Action::Continue
+    }
 
-        if _end_of_stream {
+    fn on_http_request_body(&mut self, body_size: usize, end_of_stream: bool) -> Action {
+        info!("on_http_request_body");
+
+        let request = match self.request.as_mut() {
+            Some(request) => request,
+            None => return Action::Continue,
+        };
+        if let Some(violation) = request.add(body_size) {
+            return self.reject(violation);
+        }
+
+        if end_of_stream {
             info!("Received the full HTTP request body.");
-            // Reset the accumulated body size for the next request.
-            self.current_body_size = 0;
+            let allowed = request.allowed();
+            self.record_decision(allowed);
         } else {
             info!("Received a part of the HTTP request body.");
         }
@@ -73,26 +164,114 @@ impl HttpContext for HttpConfigHeader {
         Action::Continue
     }
 
-    fn on_http_response_headers(&mut self, _num_headers: usize, _end_of_stream: bool) -> Action {
+    fn on_http_response_headers(&mut self, _num_headers: usize, end_of_stream: bool) -> Action {
         info!("on_http_response_headers");
-        Action::Continue
+
+        let mut response = match self.response {
+            Some(response) => response,
+            None => return Action::Continue,
+        };
+        if let Some(length) = content_length(self.get_http_response_header("content-length")) {
+            if let Some(violation) = response.add(length) {
+                return self.reject(violation);
+            }
+        }
+
+        if end_of_stream {
+            self.record_decision(response.allowed());
+            Action::Continue
+        } else {
+            // the headers wait for the body, so it can still be replaced by the rejection
+            Action::Pause
+        }
     }
 
-    fn on_http_response_body(&mut self, _body_size: usize, _end_of_stream: bool) -> Action {
+    fn on_http_response_body(&mut self, body_size: usize, end_of_stream: bool) -> Action {
         info!("on_http_response_body");
-        Action::Continue
+
+        let response = match self.response.as_mut() {
+            Some(response) => response,
+            None => return Action::Continue,
+        };
+        if let Some(violation) = response.buffered(body_size) {
+            return self.reject(violation);
+        }
+
+        if end_of_stream {
+            info!("Received the full HTTP response body.");
+            let allowed = response.allowed();
+            self.record_decision(allowed);
+            Action::Continue
+        } else {
+            // buffers until the whole body is received, at most the maximum size
+            Action::Pause
+        }
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Deserialize)]
 struct PolicyConfig {
-    #[serde(alias = "field-name")]
-    field_name: String,
+    // maximum size of the request body, in KB, of the requests no route matches, the string
+    // field-name of the configurations of the version 0
+    #[serde(alias = "requestMaxKb", default)]
+    request_max_kb: Option<usize>,
+
+    // maximum sizes of the request bodies by path and method, the first matching applies
+    #[serde(default)]
+    routes: Vec<RouteLimit>,
+
+    // maximum size of the response body, in KB, the responses are not limited without it
+    #[serde(alias = "responseMaxKb", default)]
+    response_max_kb: Option<usize>,
+
+    #[serde(default)]
+    rejection: RejectionConfig,
+}
+
+// the configuration every request shares
+struct Limits {
+    request_max_kb: Option<usize>,
+    routes: Vec<RouteLimit>,
+    response_max_kb: Option<usize>,
+    rejection: Rejection,
+}
+
+// parses the configuration, migrated to the current version
+fn parse_config(bytes: &[u8]) -> Result<PolicyConfig, String> {
+    let config = serde_json::from_slice(bytes).map_err(|err| err.to_string())?;
+    let (config, applied) = migration::migrate(config)?;
+    for description in applied {
+        info!("Migrated the request size configuration: {}", description);
+    }
+    serde_json::from_value(config).map_err(|err| err.to_string())
+}
+
+impl Limits {
+    fn new(config: PolicyConfig) -> Result<Self, String> {
+        let request_max_kb = config.request_max_kb;
+        if request_max_kb.is_none() && config.routes.is_empty() {
+            return Err("No maximum size, neither requestMaxKb nor routes are configured".to_string());
+        }
+        for route in &config.routes {
+            route.validate()?;
+        }
+
+        Ok(Limits {
+            request_max_kb,
+            routes: config.routes,
+            response_max_kb: config.response_max_kb,
+            rejection: Rejection::new(config.rejection)?,
+        })
+    }
+
+    // the maximum size of the request body, none when the request is not limited
+    fn request_max_kb(&self, path: &str, method: &str) -> Option<usize> {
+        route_max_kb(&self.routes, path, method).or(self.request_max_kb)
+    }
 }
 
 struct HttpConfigHeaderRoot {
-    field_name: String,
-    max_body_size: usize, // Store the maximum body size directly.
+    limits: Option<Rc<Limits>>,
 }
 
 impl Context for HttpConfigHeaderRoot {}
@@ -100,18 +279,30 @@ impl Context for HttpConfigHeaderRoot {}
 impl RootContext for HttpConfigHeaderRoot {
     fn on_configure(&mut self, _: usize) -> bool {
         if let Some(config_bytes) = self.get_plugin_configuration() {
-            let config: PolicyConfig = serde_json::from_slice(config_bytes.as_slice()).unwrap();
-            self.field_name = config.field_name;
-            self.max_body_size = self.field_name.parse::<usize>().unwrap() * 1024; // Initialize max_body_size once.
-            info!("field name is {}", self.field_name);
+            let limits = parse_config(config_bytes.as_slice()).and_then(Limits::new);
+            match limits {
+                Ok(limits) => {
+                    info!("Maximum request size is {:?} KB with {} routes, maximum response size is {:?} KB", limits.request_max_kb, limits.routes.len(), limits.response_max_kb);
+                    self.limits = Some(Rc::new(limits));
+                }
+                Err(err) => {
+                    error!("Invalid request size configuration: {}", err);
+                    return false;
+                }
+            }
         }
         true
     }
 
     fn create_http_context(&self, _: u32) -> Option<Box<dyn HttpContext>> {
+        let limits = match self.limits.clone() {
+            Some(limits) => limits,
+            None => return Some(Box::new(PassThrough)),
+        };
         Some(Box::new(HttpConfigHeader {
-            max_body_size: self.max_body_size, // Pass max_body_size to the HttpConfigHeader context.
-            current_body_size: 0, // Initialize the accumulated body size to zero.
+            request: None,
+            response: limits.response_max_kb.map(|max_kb| Accumulator::new(Direction::Response, max_kb)),
+            limits,
         }))
     }
 
@@ -119,3 +310,48 @@ impl RootContext for HttpConfigHeaderRoot {
         Some(ContextType::HttpContext)
     }
 }
+
+#[test]
+fn test_accumulator() {
+    let mut streamed = Accumulator::new(Direction::Request, 1);
+    assert!(streamed.add(600).is_none());
+    let violation = streamed.add(600).unwrap();
+    assert_eq!((violation.direction, violation.max_kb, violation.received_bytes), (Direction::Request, 1, 1200));
+
+    // the buffered sizes are the whole body so far, not added up
+    let mut buffered = Accumulator::new(Direction::Response, 1);
+    assert!(buffered.buffered(600).is_none());
+    assert!(buffered.buffered(1000).is_none());
+    assert_eq!(buffered.buffered(1025).unwrap().received_bytes, 1025);
+
+    // the bodies within their limit are allowed with the bytes received
+    let allowed = Accumulator::new(Direction::Request, 1).allowed();
+    assert_eq!(allowed.outcome(), Outcome::Allow);
+    assert_eq!(allowed.detail("receivedBytes"), Some(&serde_json::json!(0)));
+
+    // the largest sizes do not overflow
+    let mut largest = Accumulator::new(Direction::Request, usize::MAX);
+    assert_eq!(largest.max_body_size(), usize::MAX);
+    assert!(largest.add(usize::MAX).is_none());
+    assert!(largest.add(1).is_none());
+}
+
+#[test]
+fn test_route_limits() {
+    let config = parse_config(br#"{
+        "field-name": "64",
+        "routes": [{"pathPattern": "/upload", "method": "POST", "maxKb": 10240}]
+    }"#).unwrap();
+    let limits = Limits::new(config).unwrap();
+
+    assert_eq!(limits.request_max_kb("/upload", "POST"), Some(10240));
+    assert_eq!(limits.request_max_kb("/search", "GET"), Some(64));
+
+    let routes_only = parse_config(br#"{"routes": [{"pathPattern": "/search", "maxKb": 4}]}"#).unwrap();
+    assert_eq!(Limits::new(routes_only).unwrap().request_max_kb("/upload", "POST"), None);
+    let unlimited = parse_config(b"{}").unwrap();
+    assert!(Limits::new(unlimited).is_err());
+
+    let current = parse_config(br#"{"configVersion": 1, "requestMaxKb": 64}"#).unwrap();
+    assert_eq!(Limits::new(current).unwrap().request_max_kb("/upload", "POST"), Some(64));
+}
@@ -0,0 +1,75 @@
+use serde_json::{Map, Value};
+
+// the field of the version of the configuration, 0 when absent, as in the pdk
+pub const CONFIG_VERSION: &str = "configVersion";
+
+type Upgrade = fn(&mut Map<String, Value>) -> Result<(), String>;
+
+// the migration at index n upgrades the configurations of the version n to the version n + 1,
+// migrations are only ever appended
+const MIGRATIONS: &[(&str, Upgrade)] = &[("field-name to requestMaxKb", field_name_to_request_max_kb)];
+
+// the version 0 had the maximum size of the requests as a string of KB in field-name
+fn field_name_to_request_max_kb(config: &mut Map<String, Value>) -> Result<(), String> {
+    let max_kb = match config.remove("field-name") {
+        Some(max_kb) => max_kb,
+        None => return Ok(()),
+    };
+    let parsed = match &max_kb {
+        Value::String(max_kb) => max_kb.trim().parse::<u64>().ok(),
+        Value::Number(max_kb) => max_kb.as_u64(),
+        _ => None,
+    };
+    match parsed {
+        Some(max_kb) => {
+            config.entry("requestMaxKb").or_insert_with(|| max_kb.into());
+            Ok(())
+        }
+        None => Err(format!("Invalid maximum size {}", max_kb)),
+    }
+}
+
+pub fn current_version() -> u64 {
+    MIGRATIONS.len() as u64
+}
+
+// upgrades the configuration to the current version, with the descriptions of the migrations applied
+pub fn migrate(config: Value) -> Result<(Value, Vec<&'static str>), String> {
+    let mut config = match config {
+        Value::Object(config) => config,
+        _ => return Err("The configuration is not an object".to_string()),
+    };
+    let version = match config.get(CONFIG_VERSION) {
+        None => 0,
+        Some(version) => version.as_u64().ok_or_else(|| format!("Invalid {} {}", CONFIG_VERSION, version))?,
+    };
+    if version > current_version() {
+        return Err(format!("The configuration version {} is newer than the version {} of the policy", version, current_version()));
+    }
+
+    let mut applied = Vec::new();
+    for (description, upgrade) in &MIGRATIONS[version as usize..] {
+        upgrade(&mut config).map_err(|err| format!("Migration '{}' failed: {}", description, err))?;
+        applied.push(*description);
+    }
+    config.insert(CONFIG_VERSION.to_string(), current_version().into());
+    Ok((Value::Object(config), applied))
+}
+
+#[test]
+fn test_field_name_migrated() {
+    let legacy = serde_json::json!({"field-name": " 512 ", "responseMaxKb": 64});
+    let (config, applied) = migrate(legacy).unwrap();
+    assert_eq!(config, serde_json::json!({"requestMaxKb": 512, "responseMaxKb": 64, "configVersion": 1}));
+    assert_eq!(applied, vec!["field-name to requestMaxKb"]);
+
+    let current = serde_json::json!({"requestMaxKb": 512, "configVersion": 1});
+    assert_eq!(migrate(current.clone()).unwrap(), (current, vec![]));
+}
+
+#[test]
+fn test_invalid_versions_rejected() {
+    assert!(migrate(serde_json::json!({"field-name": "big"})).is_err());
+    assert!(migrate(serde_json::json!({"configVersion": 2})).is_err());
+    assert!(migrate(serde_json::json!({"configVersion": "1"})).is_err());
+}
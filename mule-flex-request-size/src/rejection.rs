@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+
+use pel::expression::{Expression, Symbol};
+use pel::parser::Parser;
+use pel::runtime::value::Value;
+use pel::runtime::{Binding, Context, Runtime, ValueHandler};
+use pel::Reference;
+use pdk_decisions::{Decision, Outcome, PolicyDecisions};
+use serde::Deserialize;
+
+const DEFAULT_BODY: &str = "{\"message\":\"Body size exceeds the maximum allowed.\"}";
+
+const POLICY_NAME: &str = "request-size";
+const DECISION_KIND: &str = "size";
+
+// the body that exceeded its limit
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    Request,
+    Response,
+}
+
+impl Direction {
+    fn as_str(self) -> &'static str {
+        match self {
+            Direction::Request => "request",
+            Direction::Response => "response",
+        }
+    }
+}
+
+// what the limit was exceeded by, the placeholders of the body template and the vars of its expression
+#[derive(Clone, Copy, Debug)]
+pub struct Violation {
+    pub direction: Direction,
+    pub max_kb: usize,
+    pub received_bytes: usize,
+}
+
+#[derive(Clone, Deserialize, Debug)]
+pub struct RejectionConfig {
+    #[serde(alias = "statusCode", default = "default_status_code")]
+    pub status_code: u32,
+
+    #[serde(default = "default_headers")]
+    pub headers: Vec<Header>,
+
+    // ${maxKb}, ${receivedBytes} and ${direction} are replaced by the violation
+    #[serde(default = "default_body")]
+    pub body: String,
+
+    // a PEL expression with the violation in vars, preferred to the body template
+    #[serde(alias = "bodyExpression", default)]
+    pub body_expression: Option<String>,
+}
+
+impl Default for RejectionConfig {
+    fn default() -> Self {
+        RejectionConfig {
+            status_code: default_status_code(),
+            headers: default_headers(),
+            body: default_body(),
+            body_expression: None,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Debug)]
+pub struct Header {
+    pub name: String,
+    pub value: String,
+}
+
+fn default_status_code() -> u32 {
+    401
+}
+
+fn default_headers() -> Vec<Header> {
+    vec![Header { name: "content-type".to_string(), value: "application/json".to_string() }]
+}
+
+fn default_body() -> String {
+    DEFAULT_BODY.to_string()
+}
+
+// the response sent instead of the bodies exceeding their limit, with the body expression parsed once
+pub struct Rejection {
+    config: RejectionConfig,
+    expression: Option<Expression>,
+}
+
+impl Rejection {
+    pub fn new(config: RejectionConfig) -> Result<Self, String> {
+        if !(200..600).contains(&config.status_code) {
+            return Err(format!("Invalid rejection status code {}", config.status_code));
+        }
+
+        let expression = match &config.body_expression {
+            Some(unit) => {
+                let (expression, _source) = Parser::new().parse_unit(unit)
+                    .map_err(|err| format!("Invalid rejection body expression: {}", err))?;
+                Some(expression)
+            }
+            None => None,
+        };
+
+        Ok(Rejection { config, expression })
+    }
+
+    pub fn status_code(&self) -> u32 {
+        self.config.status_code
+    }
+
+    pub fn headers(&self) -> Vec<(&str, &str)> {
+        self.config.headers.iter()
+            .map(|header| (header.name.as_str(), header.value.as_str()))
+            .collect()
+    }
+
+    // the body of the expression, falling back to the template when it fails to evaluate
+    pub fn body(&self, violation: &Violation) -> String {
+        match &self.expression {
+            Some(expression) => match evaluate(expression, violation) {
+                Ok(body) => body,
+                Err(err) => {
+                    log::warn!("Error evaluating the rejection body expression: {}", err);
+                    render(&self.config.body, violation)
+                }
+            },
+            None => render(&self.config.body, violation),
+        }
+    }
+}
+
+fn render(template: &str, violation: &Violation) -> String {
+    template
+        .replace("${maxKb}", &violation.max_kb.to_string())
+        .replace("${receivedBytes}", &violation.received_bytes.to_string())
+        .replace("${direction}", violation.direction.as_str())
+}
+
+// resolves vars to the violation, the only thing the expression sees
+struct ViolationContext {
+    vars: Value,
+}
+
+impl ViolationContext {
+    fn new(violation: &Violation) -> Self {
+        let mut vars = HashMap::new();
+        vars.insert("maxKb".to_string(), Value::integer(violation.max_kb as i64));
+        vars.insert("receivedBytes".to_string(), Value::integer(violation.received_bytes as i64));
+        vars.insert("direction".to_string(), Value::string(violation.direction.as_str().to_string()));
+        ViolationContext { vars: Value::object(vars) }
+    }
+}
+
+impl Context for ViolationContext {
+    fn resolve(&self, symbol: &Symbol) -> Binding {
+        match symbol.as_str() {
+            "vars" => Binding::Available(self.vars.clone()),
+            _ => Binding::Unknown,
+        }
+    }
+
+    fn value_handler(&self, _reference: Reference) -> Option<&dyn ValueHandler> {
+        None
+    }
+}
+
+fn evaluate(expression: &Expression, violation: &Violation) -> Result<String, String> {
+    let value = Runtime::new()
+        .eval_with_context(expression, &ViolationContext::new(violation))
+        .map_err(|err| err.to_string())?
+        .complete()
+        .ok_or_else(|| "the expression is not complete".to_string())?;
+
+    // strings are the body as is, the other values their json
+    match value.as_str() {
+        Some(body) => Ok(body.to_string()),
+        None => Ok(to_json(&value).to_string()),
+    }
+}
+
+fn to_json(value: &Value) -> serde_json::Value {
+    if let Some(s) = value.as_str() {
+        serde_json::Value::from(s)
+    } else if let Some(b) = value.as_bool() {
+        serde_json::Value::from(b)
+    } else if let Some(n) = value.as_i64() {
+        serde_json::Value::from(n)
+    } else if let Some(n) = value.as_f64() {
+        serde_json::Value::from(n)
+    } else if let Some(array) = value.as_slice() {
+        array.iter().map(to_json).collect()
+    } else if let Some(object) = value.as_object() {
+        serde_json::Value::Object(object.iter().map(|(key, value)| (key.clone(), to_json(value))).collect())
+    } else {
+        serde_json::Value::Null
+    }
+}
+
+// the decision of the policy on a body, for the access log
+pub fn decision(direction: Direction, outcome: Outcome) -> Decision {
+    Decision::new(POLICY_NAME, DECISION_KIND, outcome).with_detail("direction", direction.as_str())
+}
+
+impl Violation {
+    pub fn decision(&self, status_code: u32) -> Decision {
+        decision(self.direction, Outcome::Deny)
+            .with_reason(&format!("the {} body exceeds the maximum size", self.direction.as_str()))
+            .with_detail("maxKb", self.max_kb)
+            .with_detail("receivedBytes", self.received_bytes)
+            .with_detail("status", status_code)
+    }
+}
+
+// the decisions of the policies before it with the one of the policy after them, as the pdk
+// FilterState::add_policy_decision records them
+pub fn append_decision(decisions: Option<&[u8]>, decision: Decision) -> String {
+    let mut decisions = decisions
+        .and_then(|decisions| std::str::from_utf8(decisions).ok())
+        .map(PolicyDecisions::parse)
+        .unwrap_or_default();
+    decisions.add(decision);
+    decisions.to_json()
+}
+
+#[cfg(test)]
+const VIOLATION: Violation = Violation { direction: Direction::Request, max_kb: 10, received_bytes: 12288 };
+
+#[test]
+fn test_default_rejection() {
+    let rejection = Rejection::new(serde_json::from_str("{}").unwrap()).unwrap();
+
+    assert_eq!(rejection.status_code(), 401);
+    assert_eq!(rejection.headers(), vec![("content-type", "application/json")]);
+    assert_eq!(rejection.body(&VIOLATION), DEFAULT_BODY);
+}
+
+#[test]
+fn test_body_template() {
+    let config: RejectionConfig = serde_json::from_str(r#"{
+        "statusCode": 400,
+        "headers": [{"name": "content-type", "value": "text/plain"}],
+        "body": "The ${direction} of ${receivedBytes} bytes exceeds ${maxKb} KB"
+    }"#).unwrap();
+    let rejection = Rejection::new(config).unwrap();
+
+    assert_eq!(rejection.status_code(), 400);
+    assert_eq!(rejection.body(&VIOLATION), "The request of 12288 bytes exceeds 10 KB");
+}
+
+#[test]
+fn test_body_expression() {
+    // #[if (vars.direction == "request") "Request too large" else "Response too large"]
+    let config = RejectionConfig {
+        body_expression: Some(r#"P[[":if", "0-79", ["==", "4-33", [".", "4-18", [":ref", "4-8", "vars"], [":str", "9-18", "direction"]], [":str", "22-31", "request"]], [":str", "34-53", "Request too large"], [":str", "59-79", "Response too large"]]]"#.to_string()),
+        ..RejectionConfig::default()
+    };
+    let rejection = Rejection::new(config).unwrap();
+    let response = Violation { direction: Direction::Response, ..VIOLATION };
+
+    assert_eq!(rejection.body(&VIOLATION), "Request too large");
+    assert_eq!(rejection.body(&response), "Response too large");
+}
+
+#[test]
+fn test_body_expression_values_as_json() {
+    // #[vars.maxKb]
+    let config = RejectionConfig {
+        body_expression: Some(r#"P[[".", "0-10", [":ref", "0-4", "vars"], [":str", "5-10", "maxKb"]]]"#.to_string()),
+        ..RejectionConfig::default()
+    };
+
+    assert_eq!(Rejection::new(config).unwrap().body(&VIOLATION), "10");
+}
+
+#[test]
+fn test_invalid_rejection() {
+    let invalid_status = RejectionConfig { status_code: 99, ..RejectionConfig::default() };
+    let invalid_expression = RejectionConfig { body_expression: Some("#[vars.maxKb]".to_string()), ..RejectionConfig::default() };
+
+    assert!(Rejection::new(invalid_status).is_err());
+    assert!(Rejection::new(invalid_expression).is_err());
+}
+
+#[test]
+fn test_decision_appended() {
+    let previous = br#"[{"policy":"jwt-validation","kind":"authentication","outcome":"allow"}]"#;
+    let decisions = append_decision(Some(previous), VIOLATION.decision(413));
+    let decisions = PolicyDecisions::parse(&decisions);
+
+    assert_eq!(decisions.decisions()[0].policy(), "jwt-validation");
+    let denial = &decisions.decisions()[1];
+    assert_eq!(denial.outcome(), Outcome::Deny);
+    assert_eq!(denial.reason(), Some("the request body exceeds the maximum size"));
+    assert_eq!(denial.detail("receivedBytes"), Some(&serde_json::json!(12288)));
+    assert_eq!(denial.detail("status"), Some(&serde_json::json!(413)));
+
+    let first = append_decision(Some(b"not json"), decision(Direction::Response, Outcome::Skip));
+    assert_eq!(first, r#"[{"policy":"request-size","kind":"size","outcome":"skip","details":{"direction":"response"}}]"#);
+}
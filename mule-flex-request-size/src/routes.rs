@@ -0,0 +1,119 @@
+use serde::Deserialize;
+
+// the maximum size of the request bodies of the paths and method matching
+#[derive(Clone, Deserialize, Debug)]
+pub struct RouteLimit {
+    // * matches within a path segment, ** across segments
+    #[serde(alias = "pathPattern")]
+    pub path_pattern: String,
+
+    // any method when absent
+    #[serde(default)]
+    pub method: Option<String>,
+
+    #[serde(alias = "maxKb")]
+    pub max_kb: usize,
+}
+
+impl RouteLimit {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.path_pattern.starts_with('/') && !self.path_pattern.starts_with('*') {
+            return Err(format!("Invalid path pattern {:?}, it must start with / or *", self.path_pattern));
+        }
+        Ok(())
+    }
+
+    pub fn matches(&self, path: &str, method: &str) -> bool {
+        let method_matches = self.method.as_ref()
+            .is_none_or(|expected| expected.eq_ignore_ascii_case(method));
+        method_matches && glob_matches(self.path_pattern.as_bytes(), without_query(path).as_bytes())
+    }
+}
+
+// the limit of the first route matching, in KB
+pub fn route_max_kb(routes: &[RouteLimit], path: &str, method: &str) -> Option<usize> {
+    routes.iter()
+        .find(|route| route.matches(path, method))
+        .map(|route| route.max_kb)
+}
+
+fn without_query(path: &str) -> &str {
+    path.split(['?', '#']).next().unwrap_or_default()
+}
+
+// on a mismatch the last * takes one more byte of its segment, or the last ** one more byte when
+// the * can not, so the matching is bounded by the pattern times the path lengths
+fn glob_matches(pattern: &[u8], path: &[u8]) -> bool {
+    let (mut p, mut s) = (0, 0);
+    // the pattern after the last * and **, and the path they consumed up to
+    let mut star: Option<(usize, usize)> = None;
+    let mut double_star: Option<(usize, usize)> = None;
+
+    while s < path.len() {
+        if pattern[p..].starts_with(b"**") {
+            p += 2;
+            double_star = Some((p, s));
+            star = None;
+        } else if pattern.get(p) == Some(&b'*') {
+            p += 1;
+            star = Some((p, s));
+        } else if pattern.get(p) == Some(&path[s]) {
+            p += 1;
+            s += 1;
+        } else if let Some((star_p, star_s)) = star.filter(|&(_, star_s)| path[star_s] != b'/') {
+            p = star_p;
+            s = star_s + 1;
+            star = Some((star_p, s));
+        } else if let Some((star_p, star_s)) = double_star {
+            p = star_p;
+            s = star_s + 1;
+            double_star = Some((star_p, s));
+            star = None;
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+
+#[test]
+fn test_route_patterns() {
+    let route = |pattern: &str| RouteLimit { path_pattern: pattern.to_string(), method: None, max_kb: 1 };
+
+    assert!(route("/upload").matches("/upload?resumable=true", "POST"));
+    assert!(!route("/upload").matches("/upload/parts", "POST"));
+    assert!(route("/orders/*/items").matches("/orders/42/items", "POST"));
+    assert!(!route("/orders/*/items").matches("/orders/42/x/items", "POST"));
+    assert!(route("/files/**").matches("/files/a/b/c.txt", "PUT"));
+    assert!(route("**").matches("/anything", "GET"));
+    assert!(route("/files/**/*.txt").matches("/files/a/b/c.txt", "PUT"));
+    assert!(!route("/files/**/*.txt").matches("/files/a/b.txt/c", "PUT"));
+    assert!(route("**/items/*").matches("/orders/42/items/7", "GET"));
+    assert!(route("/orders/*").matches("/orders/", "GET"));
+    assert!(!route("/orders/*").matches("/orders", "GET"));
+    assert!(route("/upload").validate().is_ok());
+    assert!(route("upload").validate().is_err());
+}
+
+#[test]
+fn test_glob_does_not_backtrack_exponentially() {
+    let pattern = "/**a*a*a*a*a*a*a*a*a*a*a*b".to_string();
+    let path = format!("/{}", "a".repeat(10_000));
+    let route = RouteLimit { path_pattern: pattern, method: None, max_kb: 1 };
+    assert!(!route.matches(&path, "POST"));
+}
+
+#[test]
+fn test_first_matching_route() {
+    let routes: Vec<RouteLimit> = serde_json::from_str(r#"[
+        {"pathPattern": "/upload", "method": "post", "maxKb": 10240},
+        {"pathPattern": "/search", "maxKb": 4},
+        {"pathPattern": "/**", "maxKb": 64}
+    ]"#).unwrap();
+
+    assert_eq!(route_max_kb(&routes, "/upload", "POST"), Some(10240));
+    assert_eq!(route_max_kb(&routes, "/upload", "PUT"), Some(64));
+    assert_eq!(route_max_kb(&routes, "/search?q=flex", "GET"), Some(4));
+    assert_eq!(route_max_kb(&routes[..2], "/orders", "GET"), None);
+}
@@ -0,0 +1,318 @@
+use serde::Deserialize;
+use std::io::{self, Read, Write};
+
+#[derive(Clone, Copy, Deserialize, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    #[serde(rename = "br")]
+    Brotli,
+    #[serde(rename = "zstd")]
+    Zstd,
+    #[serde(rename = "gzip")]
+    Gzip,
+}
+
+impl Encoding {
+
+    // the content-encoding of the responses
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zstd",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    // whether the encoder is compiled in the policy, see the features
+    pub fn is_available(self) -> bool {
+        match self {
+            Encoding::Brotli => cfg!(feature = "brotli"),
+            Encoding::Zstd => cfg!(feature = "zstd"),
+            Encoding::Gzip => true,
+        }
+    }
+
+    fn accepts(self, coding: &str) -> bool {
+        coding == self.name() || (self == Encoding::Gzip && coding == "x-gzip")
+    }
+
+    // the encoding of a content-encoding header with a single coding, None for identity, a list
+    // of codings or unknown ones
+    pub fn from_content_encoding(content_encoding: &str) -> Option<Encoding> {
+        let coding = content_encoding.trim().to_ascii_lowercase();
+        [Encoding::Brotli, Encoding::Zstd, Encoding::Gzip]
+            .into_iter()
+            .find(|encoding| encoding.accepts(&coding))
+    }
+
+    // level goes from 1, faster, to 9, smaller, and is mapped to the range of each encoding
+    pub fn compress(self, body: &[u8], level: u32) -> io::Result<Vec<u8>> {
+        let level = level.clamp(1, 9);
+        match self {
+            Encoding::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(Vec::with_capacity(body.len() / 4), flate2::Compression::new(level));
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+            Encoding::Brotli => brotli(body, level),
+            Encoding::Zstd => zstd(body, level),
+        }
+    }
+
+    // fails when the body is malformed or decompresses to more than max_size bytes, so a small
+    // compressed body can not exhaust the memory of the vm
+    pub fn decompress(self, body: &[u8], max_size: usize) -> io::Result<Vec<u8>> {
+        match self {
+            Encoding::Gzip => read_capped(flate2::read::MultiGzDecoder::new(body), max_size),
+            Encoding::Brotli => unbrotli(body, max_size),
+            Encoding::Zstd => unzstd(body, max_size),
+        }
+    }
+}
+
+fn read_capped(decoder: impl Read, max_size: usize) -> io::Result<Vec<u8>> {
+    let mut decompressed = Vec::new();
+    decoder.take(max_size as u64 + 1).read_to_end(&mut decompressed)?;
+    if decompressed.len() > max_size {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("body decompresses to more than {} bytes", max_size)));
+    }
+    Ok(decompressed)
+}
+
+pub fn default_encodings() -> Vec<Encoding> {
+    vec![Encoding::Brotli, Encoding::Zstd, Encoding::Gzip]
+}
+
+#[cfg(feature = "brotli")]
+fn brotli(body: &[u8], level: u32) -> io::Result<Vec<u8>> {
+    // quality goes from 0 to 11, with a 4MB window
+    let mut encoder = brotli::CompressorWriter::new(Vec::with_capacity(body.len() / 4), 4096, level * 11 / 9, 22);
+    encoder.write_all(body)?;
+    Ok(encoder.into_inner())
+}
+
+#[cfg(not(feature = "brotli"))]
+fn brotli(_: &[u8], _: u32) -> io::Result<Vec<u8>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "br encoding is not compiled in"))
+}
+
+#[cfg(feature = "brotli")]
+fn unbrotli(body: &[u8], max_size: usize) -> io::Result<Vec<u8>> {
+    read_capped(brotli::Decompressor::new(body, 4096), max_size)
+}
+
+#[cfg(not(feature = "brotli"))]
+fn unbrotli(_: &[u8], _: usize) -> io::Result<Vec<u8>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "br encoding is not compiled in"))
+}
+
+#[cfg(feature = "zstd")]
+fn zstd(body: &[u8], level: u32) -> io::Result<Vec<u8>> {
+    // levels go from 1 to 19 without the ultra ones
+    zstd::stream::encode_all(body, (level * 2) as i32)
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zstd(_: &[u8], _: u32) -> io::Result<Vec<u8>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "zstd encoding is not compiled in"))
+}
+
+#[cfg(feature = "zstd")]
+fn unzstd(body: &[u8], max_size: usize) -> io::Result<Vec<u8>> {
+    read_capped(zstd::stream::read::Decoder::new(body)?, max_size)
+}
+
+#[cfg(not(feature = "zstd"))]
+fn unzstd(_: &[u8], _: usize) -> io::Result<Vec<u8>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "zstd encoding is not compiled in"))
+}
+
+// the q-value of an accept-encoding element, 1 when missing and 0 when malformed
+fn quality(parameters: std::str::Split<'_, char>) -> f32 {
+    for parameter in parameters {
+        let parameter = parameter.trim();
+        if let Some(value) = parameter.strip_prefix("q=").or_else(|| parameter.strip_prefix("Q=")) {
+            return value.trim().parse::<f32>()
+                .ok()
+                .filter(|quality| quality.is_finite())
+                .map(|quality| quality.clamp(0.0, 1.0))
+                .unwrap_or(0.0);
+        }
+    }
+    1.0
+}
+
+// the available encoding with the highest q-value of the accept-encoding header, ties going
+// to the first in the preference. Encodings with a q-value of 0 are never picked.
+pub fn negotiate(accept_encoding: &str, preference: &[Encoding]) -> Option<Encoding> {
+    let accepted: Vec<(String, f32)> = accept_encoding.split(',')
+        .filter_map(|element| {
+            let mut parts = element.split(';');
+            let coding = parts.next()?.trim().to_ascii_lowercase();
+            if coding.is_empty() {
+                return None;
+            }
+            Some((coding, quality(parts)))
+        })
+        .collect();
+
+    let quality_of = |encoding: Encoding| {
+        accepted.iter()
+            .find(|(coding, _)| encoding.accepts(coding))
+            .or_else(|| accepted.iter().find(|(coding, _)| coding == "*"))
+            .map(|(_, quality)| *quality)
+    };
+
+    let mut best: Option<(Encoding, f32)> = None;
+    for encoding in preference.iter().copied().filter(|encoding| encoding.is_available()) {
+        let quality = match quality_of(encoding) {
+            Some(quality) if quality > 0.0 => quality,
+            _ => continue
+        };
+        if best.map_or(true, |(_, best)| quality > best) {
+            best = Some((encoding, quality));
+        }
+    }
+    best.map(|(encoding, _)| encoding)
+}
+
+// entries starting with + match the suffix of the media type, like application/problem+json for
+// +json, the other ones its start
+fn matches_any(media_type: &str, content_types: &[String]) -> bool {
+    content_types.iter()
+        .map(|content_type| content_type.to_ascii_lowercase())
+        .any(|content_type| if content_type.starts_with('+') {
+            media_type.ends_with(&content_type)
+        } else {
+            media_type.starts_with(&content_type)
+        })
+}
+
+// content types are matched by their media type. The excluded ones win, as the streams, like
+// server-sent events, are only complete once closed and can not be held back until then.
+pub fn is_compressible(content_type: &str, content_types: &[String], excluded: &[String]) -> bool {
+    let media_type = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    matches_any(&media_type, content_types) && !matches_any(&media_type, excluded)
+}
+
+// the vary header of a compressible response must name accept-encoding, so caches keep a
+// representation per encoding. None when it is fine as it is.
+pub fn vary_with_accept_encoding(vary: Option<&str>) -> Option<String> {
+    match vary.map(str::trim) {
+        None | Some("") => Some("Accept-Encoding".to_string()),
+        Some(vary) if vary.split(',').any(|name| matches!(name.trim(), "*") || name.trim().eq_ignore_ascii_case("accept-encoding")) => None,
+        Some(vary) => Some(format!("{}, Accept-Encoding", vary)),
+    }
+}
+
+// a compressed response is no longer byte for byte the one of a strong etag
+pub fn weak_etag(etag: &str) -> Option<String> {
+    let etag = etag.trim();
+    if etag.starts_with("W/") || etag.is_empty() {
+        None
+    } else {
+        Some(format!("W/{}", etag))
+    }
+}
+
+
+#[cfg(test)]
+fn strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|value| value.to_string()).collect()
+}
+
+#[test]
+fn test_content_encodings() {
+    assert_eq!(Encoding::from_content_encoding(" GZIP "), Some(Encoding::Gzip));
+    assert_eq!(Encoding::from_content_encoding("x-gzip"), Some(Encoding::Gzip));
+    assert_eq!(Encoding::from_content_encoding("br"), Some(Encoding::Brotli));
+    assert_eq!(Encoding::from_content_encoding("zstd"), Some(Encoding::Zstd));
+    assert_eq!(Encoding::from_content_encoding("identity"), None);
+    assert_eq!(Encoding::from_content_encoding("gzip, br"), None);
+    assert_eq!(Encoding::from_content_encoding("deflate"), None);
+}
+
+#[test]
+fn test_gzip_round_trip() {
+    let body = "a compressible body, ".repeat(100);
+    let compressed = Encoding::Gzip.compress(body.as_bytes(), 9).unwrap();
+    assert!(compressed.len() < body.len());
+    assert_eq!(Encoding::Gzip.decompress(&compressed, body.len()).unwrap(), body.as_bytes());
+
+    // the level is clamped
+    let fastest = Encoding::Gzip.compress(body.as_bytes(), 0).unwrap();
+    assert_eq!(Encoding::Gzip.decompress(&fastest, body.len()).unwrap(), body.as_bytes());
+}
+
+#[cfg(feature = "brotli")]
+#[test]
+fn test_brotli_round_trip() {
+    let body = "a compressible body, ".repeat(100);
+    let compressed = Encoding::Brotli.compress(body.as_bytes(), 5).unwrap();
+    assert!(compressed.len() < body.len());
+    assert_eq!(Encoding::Brotli.decompress(&compressed, body.len()).unwrap(), body.as_bytes());
+}
+
+#[test]
+fn test_decompression_is_capped() {
+    let body = vec![b'0'; 1024 * 1024];
+    let compressed = Encoding::Gzip.compress(&body, 9).unwrap();
+    assert!(compressed.len() < 4096);
+
+    let err = Encoding::Gzip.decompress(&compressed, body.len() - 1).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(Encoding::Gzip.decompress(&compressed, body.len()).unwrap().len(), body.len());
+
+    assert!(Encoding::Gzip.decompress(b"not gzip", 1024).is_err());
+}
+
+#[test]
+fn test_negotiate_by_quality() {
+    let preference = [Encoding::Zstd, Encoding::Gzip];
+    assert_eq!(negotiate("gzip", &preference), Some(Encoding::Gzip));
+    assert_eq!(negotiate("identity", &preference), None);
+    // zstd is only picked by the wildcard when compiled in
+    assert_eq!(negotiate("gzip;q=0, *", &preference), Some(Encoding::Zstd).filter(|encoding| encoding.is_available()));
+    assert_eq!(negotiate("gzip;q=0", &preference), None);
+    assert_eq!(negotiate("gzip;q=0.5, deflate;q=1", &preference), Some(Encoding::Gzip));
+    assert_eq!(negotiate("gzip;q=nan", &preference), None);
+    assert_eq!(negotiate("x-gzip;Q=0.8", &preference), Some(Encoding::Gzip));
+}
+
+#[cfg(feature = "brotli")]
+#[test]
+fn test_negotiate_breaks_ties_by_preference() {
+    assert_eq!(negotiate("gzip, br", &[Encoding::Brotli, Encoding::Gzip]), Some(Encoding::Brotli));
+    assert_eq!(negotiate("gzip, br", &[Encoding::Gzip, Encoding::Brotli]), Some(Encoding::Gzip));
+    assert_eq!(negotiate("gzip;q=1, br;q=0.9", &[Encoding::Brotli, Encoding::Gzip]), Some(Encoding::Gzip));
+    assert_eq!(negotiate("*", &[Encoding::Brotli, Encoding::Gzip]), Some(Encoding::Brotli));
+}
+
+#[test]
+fn test_compressible_content_types() {
+    let content_types = strings(&["text/", "application/json", "+json"]);
+    let excluded = strings(&["text/event-stream", "application/x-ndjson"]);
+
+    assert!(is_compressible("text/html; charset=utf-8", &content_types, &excluded));
+    assert!(is_compressible("Application/JSON", &content_types, &excluded));
+    assert!(is_compressible("application/problem+json", &content_types, &excluded));
+    assert!(!is_compressible("image/png", &content_types, &excluded));
+    assert!(!is_compressible("", &content_types, &excluded));
+
+    // the streams are never buffered
+    assert!(!is_compressible("text/event-stream", &content_types, &excluded));
+    assert!(!is_compressible("TEXT/EVENT-STREAM;charset=utf-8", &content_types, &excluded));
+    assert!(is_compressible("text/event-stream", &content_types, &[]));
+}
+
+#[test]
+fn test_vary_and_etag() {
+    assert_eq!(vary_with_accept_encoding(None).as_deref(), Some("Accept-Encoding"));
+    assert_eq!(vary_with_accept_encoding(Some(" ")).as_deref(), Some("Accept-Encoding"));
+    assert_eq!(vary_with_accept_encoding(Some("Origin")).as_deref(), Some("Origin, Accept-Encoding"));
+    assert_eq!(vary_with_accept_encoding(Some("origin, accept-encoding")), None);
+    assert_eq!(vary_with_accept_encoding(Some("*")), None);
+
+    assert_eq!(weak_etag("\"abc\"").as_deref(), Some("W/\"abc\""));
+    assert_eq!(weak_etag("W/\"abc\""), None);
+    assert_eq!(weak_etag(""), None);
+}
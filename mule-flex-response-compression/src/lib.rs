@@ -0,0 +1,348 @@
+use proxy_wasm::traits::*;
+use proxy_wasm::types::*;
+use log::{debug, info, warn, error};
+use serde::Deserialize;
+
+mod encoding;
+use encoding::{default_encodings, is_compressible, negotiate, vary_with_accept_encoding, weak_etag, Encoding};
+
+proxy_wasm::main! {{
+    proxy_wasm::set_log_level(LogLevel::Trace);
+    proxy_wasm::set_root_context(|_| -> Box<dyn RootContext> {
+        Box::new(ResponseCompressionRoot {
+            config: None,
+        })
+    });
+}}
+
+// a policy binding either compresses the responses for the client, or decompresses the ones
+// of the upstream for the policies inspecting their bodies
+#[derive(Clone, Copy, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+enum Mode {
+    #[default]
+    Compress,
+    Decompress,
+}
+
+#[derive(Clone, Deserialize, Debug)]
+struct PolicyConfig {
+    #[serde(default)]
+    mode: Mode,
+
+    // by preference, breaking the ties between equal q-values
+    #[serde(default = "default_encodings")]
+    encodings: Vec<Encoding>,
+
+    #[serde(alias = "minSizeBytes", default = "default_min_size_bytes")]
+    min_size_bytes: usize,
+
+    #[serde(alias = "contentTypes", default = "default_content_types")]
+    content_types: Vec<String>,
+
+    // never buffered, even when matching the content types
+    #[serde(alias = "excludedContentTypes", default = "default_excluded_content_types")]
+    excluded_content_types: Vec<String>,
+
+    #[serde(default = "default_level")]
+    level: u32,
+
+    // larger decompressed bodies are sent compressed as they came
+    #[serde(alias = "maxDecompressedBytes", default = "default_max_decompressed_bytes")]
+    max_decompressed_bytes: usize,
+}
+
+fn default_min_size_bytes() -> usize {
+    1024
+}
+
+fn default_content_types() -> Vec<String> {
+    ["text/", "application/json", "application/xml", "application/javascript", "+json", "+xml"]
+        .iter()
+        .map(|content_type| content_type.to_string())
+        .collect()
+}
+
+fn default_excluded_content_types() -> Vec<String> {
+    ["text/event-stream", "application/x-ndjson", "application/grpc", "multipart/x-mixed-replace"]
+        .iter()
+        .map(|content_type| content_type.to_string())
+        .collect()
+}
+
+fn default_level() -> u32 {
+    5
+}
+
+fn default_max_decompressed_bytes() -> usize {
+    10 * 1024 * 1024
+}
+
+struct ResponseCompression {
+    config: PolicyConfig,
+    // negotiated from the accept-encoding of the request
+    encoding: Option<Encoding>,
+    // the response body is being buffered to be compressed
+    buffering: bool,
+    // the encoding of the upstream response being buffered to be decompressed
+    decoding: Option<Encoding>,
+}
+
+// the encoding of the upstream response to decompress, None to send it as it came
+fn decoding<H: Fn(&str) -> Option<String>>(header: H, config: &PolicyConfig) -> Option<Encoding> {
+    if !compressible(&header, config) {
+        return None;
+    }
+
+    // a range of a compressed body can not be decompressed on its own
+    let status = header(":status").unwrap_or_default();
+    if matches!(status.as_str(), "204" | "206" | "304") {
+        return None;
+    }
+
+    header("content-encoding")
+        .as_deref()
+        .and_then(Encoding::from_content_encoding)
+        .filter(|encoding| encoding.is_available())
+}
+
+fn compressible<H: Fn(&str) -> Option<String>>(header: &H, config: &PolicyConfig) -> bool {
+    header("content-type")
+        .map_or(false, |content_type| is_compressible(&content_type, &config.content_types, &config.excluded_content_types))
+}
+
+impl ResponseCompression {
+
+    fn compressible(&self) -> bool {
+        compressible(&|name: &str| self.get_http_response_header(name), &self.config)
+    }
+
+    fn on_upstream_response_headers(&mut self, end_of_stream: bool) -> Action {
+        if end_of_stream {
+            return Action::Continue;
+        }
+
+        // the headers wait for the whole body, as its encoding and length change
+        self.decoding = decoding(|name| self.get_http_response_header(name), &self.config);
+        if self.decoding.is_none() {
+            return Action::Continue;
+        }
+        Action::Pause
+    }
+
+    fn on_upstream_response_body(&mut self, body_size: usize, end_of_stream: bool) -> Action {
+        let encoding = match self.decoding {
+            Some(encoding) => encoding,
+            None => return Action::Continue
+        };
+        if !end_of_stream {
+            return Action::Pause;
+        }
+        self.decoding = None;
+
+        let body = self.get_http_response_body(0, body_size).unwrap_or_default();
+        match encoding.decompress(&body, self.config.max_decompressed_bytes) {
+            Ok(decompressed) => {
+                info!("Response decompressed from {} from {} to {} bytes", encoding.name(), body.len(), decompressed.len());
+                self.set_http_response_body(0, body_size, &decompressed);
+                self.set_http_response_header("content-encoding", None);
+                self.set_http_response_header("content-length", Some(&decompressed.len().to_string()));
+                if let Some(etag) = self.get_http_response_header("etag").as_deref().and_then(weak_etag) {
+                    self.set_http_response_header("etag", Some(&etag));
+                }
+            }
+            Err(err) => warn!("Error decompressing the response with {}, sending it as it is: {}", encoding.name(), err),
+        }
+        Action::Continue
+    }
+}
+
+impl Context for ResponseCompression {}
+
+impl HttpContext for ResponseCompression {
+
+    fn on_http_request_headers(&mut self, _num_headers: usize, _end_of_stream: bool) -> Action {
+        if self.config.mode == Mode::Decompress {
+            return Action::Continue;
+        }
+        self.encoding = self.get_http_request_header("accept-encoding")
+            .and_then(|accept_encoding| negotiate(&accept_encoding, &self.config.encodings));
+        debug!("Negotiated encoding {:?}", self.encoding);
+        Action::Continue
+    }
+
+    fn on_http_response_headers(&mut self, _num_headers: usize, end_of_stream: bool) -> Action {
+        if self.config.mode == Mode::Decompress {
+            return self.on_upstream_response_headers(end_of_stream);
+        }
+        if !self.compressible() {
+            return Action::Continue;
+        }
+
+        // compressed or not, the response now depends on the accept-encoding of the request
+        if let Some(vary) = vary_with_accept_encoding(self.get_http_response_header("vary").as_deref()) {
+            self.set_http_response_header("vary", Some(&vary));
+        }
+
+        if self.encoding.is_none() || end_of_stream || self.get_http_response_header("content-encoding").is_some() {
+            return Action::Continue;
+        }
+
+        // no body to compress, or a range of it
+        let status = self.get_http_response_header(":status").unwrap_or_default();
+        if matches!(status.as_str(), "204" | "206" | "304") {
+            return Action::Continue;
+        }
+
+        let length = self.get_http_response_header("content-length")
+            .and_then(|length| length.trim().parse::<usize>().ok());
+        if length.map_or(false, |length| length < self.config.min_size_bytes) {
+            return Action::Continue;
+        }
+
+        // the headers wait for the whole body, as its encoding and length change
+        self.buffering = true;
+        Action::Pause
+    }
+
+    fn on_http_response_body(&mut self, body_size: usize, end_of_stream: bool) -> Action {
+        if self.config.mode == Mode::Decompress {
+            return self.on_upstream_response_body(body_size, end_of_stream);
+        }
+        if !self.buffering {
+            return Action::Continue;
+        }
+        if !end_of_stream {
+            return Action::Pause;
+        }
+        self.buffering = false;
+
+        // negotiated before buffering
+        let encoding = match self.encoding {
+            Some(encoding) => encoding,
+            None => return Action::Continue
+        };
+        let body = self.get_http_response_body(0, body_size).unwrap_or_default();
+        if body.len() < self.config.min_size_bytes {
+            return Action::Continue;
+        }
+
+        match encoding.compress(&body, self.config.level) {
+            Ok(compressed) if compressed.len() < body.len() => {
+                info!("Response compressed with {} from {} to {} bytes", encoding.name(), body.len(), compressed.len());
+                self.set_http_response_body(0, body_size, &compressed);
+                self.set_http_response_header("content-encoding", Some(encoding.name()));
+                self.set_http_response_header("content-length", Some(&compressed.len().to_string()));
+                if let Some(etag) = self.get_http_response_header("etag").as_deref().and_then(weak_etag) {
+                    self.set_http_response_header("etag", Some(&etag));
+                }
+            }
+            Ok(_) => debug!("Response of {} bytes is not smaller compressed, sending it as it is", body.len()),
+            Err(err) => warn!("Error compressing the response with {}: {}", encoding.name(), err),
+        }
+        Action::Continue
+    }
+}
+
+struct ResponseCompressionRoot {
+    config: Option<PolicyConfig>,
+}
+
+impl Context for ResponseCompressionRoot {}
+
+impl RootContext for ResponseCompressionRoot {
+    fn on_configure(&mut self, _: usize) -> bool {
+        let config = match self.get_plugin_configuration() {
+            Some(config_bytes) => serde_json::from_slice::<PolicyConfig>(config_bytes.as_slice()),
+            None => serde_json::from_str::<PolicyConfig>("{}"),
+        };
+
+        match config {
+            Ok(config) => {
+                let unavailable: Vec<&str> = config.encodings.iter()
+                    .filter(|encoding| !encoding.is_available())
+                    .map(|encoding| encoding.name())
+                    .collect();
+                if !unavailable.is_empty() {
+                    warn!("Encodings {:?} are not compiled in the policy and are ignored", unavailable);
+                }
+                self.config = Some(config);
+                true
+            }
+            Err(err) => {
+                error!("Invalid response compression configuration: {}", err);
+                false
+            }
+        }
+    }
+
+    fn create_http_context(&self, _: u32) -> Option<Box<dyn HttpContext>> {
+        let config = self.config.clone()?;
+        Some(Box::new(ResponseCompression {
+            config,
+            encoding: None,
+            buffering: false,
+            decoding: None,
+        }))
+    }
+
+    fn get_type(&self) -> Option<ContextType> {
+        Some(ContextType::HttpContext)
+    }
+}
+
+
+#[cfg(test)]
+fn config(json: &str) -> PolicyConfig {
+    serde_json::from_str(json).unwrap()
+}
+
+#[cfg(test)]
+fn headers(headers: &'static [(&'static str, &'static str)]) -> impl Fn(&str) -> Option<String> {
+    move |name| headers.iter()
+        .find(|(header, _)| header.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.to_string())
+}
+
+#[test]
+fn test_default_config() {
+    let config = config("{}");
+    assert_eq!(config.mode, Mode::Compress);
+    assert_eq!(config.max_decompressed_bytes, 10 * 1024 * 1024);
+    assert!(config.excluded_content_types.contains(&"text/event-stream".to_string()));
+
+    let config = self::config(r#"{"mode": "decompress", "maxDecompressedBytes": 1024, "excludedContentTypes": []}"#);
+    assert_eq!(config.mode, Mode::Decompress);
+    assert_eq!(config.max_decompressed_bytes, 1024);
+    assert!(config.excluded_content_types.is_empty());
+}
+
+#[test]
+fn test_decoding_of_upstream_responses() {
+    let config = config(r#"{"mode": "decompress"}"#);
+
+    let gzip = headers(&[(":status", "200"), ("content-type", "application/json"), ("content-encoding", "gzip")]);
+    assert_eq!(decoding(gzip, &config), Some(Encoding::Gzip));
+
+    // not encoded, or encoded in a way not decompressed
+    assert_eq!(decoding(headers(&[(":status", "200"), ("content-type", "text/plain")]), &config), None);
+    assert_eq!(decoding(headers(&[(":status", "200"), ("content-type", "text/plain"), ("content-encoding", "gzip, br")]), &config), None);
+    assert_eq!(decoding(headers(&[(":status", "200"), ("content-type", "text/plain"), ("content-encoding", "deflate")]), &config), None);
+
+    // not inspected, a range, or a stream
+    assert_eq!(decoding(headers(&[(":status", "200"), ("content-type", "image/png"), ("content-encoding", "gzip")]), &config), None);
+    assert_eq!(decoding(headers(&[(":status", "206"), ("content-type", "text/plain"), ("content-encoding", "gzip")]), &config), None);
+    assert_eq!(decoding(headers(&[(":status", "200"), ("content-type", "text/event-stream"), ("content-encoding", "gzip")]), &config), None);
+    assert_eq!(decoding(headers(&[(":status", "200"), ("content-encoding", "gzip")]), &config), None);
+}
+
+#[test]
+fn test_streams_are_not_compressed() {
+    let config = config("{}");
+    assert!(compressible(&headers(&[("content-type", "text/html")]), &config));
+    assert!(!compressible(&headers(&[("content-type", "text/event-stream")]), &config));
+
+    let config = self::config(r#"{"contentTypes": ["application/"]}"#);
+    assert!(compressible(&headers(&[("content-type", "application/json")]), &config));
+    assert!(!compressible(&headers(&[("content-type", "application/x-ndjson")]), &config));
+}
@@ -0,0 +1,75 @@
+use serde::Deserialize;
+
+// the response flags envoy sets in the response.flags property, as a bitmask, named like
+// in its access logs
+#[derive(Clone, Copy, Deserialize, Debug, PartialEq, Eq)]
+pub enum ResponseFlag {
+    // no healthy upstream host
+    UH,
+    // upstream request timeout
+    UT,
+    // local reset of the upstream connection
+    LR,
+    // upstream remote reset
+    UR,
+    // upstream connection failure
+    UF,
+    // upstream connection termination
+    UC,
+    // upstream overflow, the circuit breaker is open
+    UO,
+    // upstream retry limit exceeded
+    URX,
+}
+
+impl ResponseFlag {
+    fn bit(self) -> u64 {
+        match self {
+            ResponseFlag::UH => 0x2,
+            ResponseFlag::UT => 0x4,
+            ResponseFlag::LR => 0x8,
+            ResponseFlag::UR => 0x10,
+            ResponseFlag::UF => 0x20,
+            ResponseFlag::UC => 0x40,
+            ResponseFlag::UO => 0x80,
+            ResponseFlag::URX => 0x8000,
+        }
+    }
+}
+
+// the upstream could not be reached, the request was not served by it
+pub fn default_flags() -> Vec<ResponseFlag> {
+    vec![ResponseFlag::UH, ResponseFlag::UF, ResponseFlag::UC, ResponseFlag::UR, ResponseFlag::URX]
+}
+
+pub fn mask(flags: &[ResponseFlag]) -> u64 {
+    flags.iter().fold(0, |mask, flag| mask | flag.bit())
+}
+
+// the property is a little endian 64 bits integer, missing until the response is known
+pub fn parse(bytes: &[u8]) -> Option<u64> {
+    let bytes: [u8; 8] = bytes.try_into().ok()?;
+    Some(u64::from_le_bytes(bytes))
+}
+
+
+#[test]
+fn test_mask() {
+    assert_eq!(mask(&default_flags()), 0x2 | 0x20 | 0x40 | 0x10 | 0x8000);
+    assert_eq!(mask(&[]), 0);
+    assert_eq!(mask(&[ResponseFlag::UT, ResponseFlag::UT]), 0x4);
+}
+
+#[test]
+fn test_parse() {
+    assert_eq!(parse(&0x8020u64.to_le_bytes()), Some(0x8020));
+    assert_eq!(parse(&[0x20, 0, 0, 0]), None);
+    assert_eq!(parse(&[]), None);
+}
+
+#[test]
+fn test_deserialize_flags() {
+    let flags: Vec<ResponseFlag> = serde_json::from_str(r#"["UH", "URX"]"#).unwrap();
+    assert_eq!(flags, vec![ResponseFlag::UH, ResponseFlag::URX]);
+    assert!(serde_json::from_str::<Vec<ResponseFlag>>(r#"["XX"]"#).is_err());
+}
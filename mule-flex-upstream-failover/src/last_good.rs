@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+// headers not replayed, they belong to the connection or are set again for the stored body
+const SKIPPED_HEADERS: [&str; 7] = [
+    ":status",
+    "content-length",
+    "transfer-encoding",
+    "connection",
+    "keep-alive",
+    "date",
+    "set-cookie",
+];
+
+// the requests with credentials get responses of their own, never replayed to other clients
+pub fn is_personal_request(authorization: Option<&str>, cookie: Option<&str>) -> bool {
+    authorization.is_some() || cookie.is_some()
+}
+
+// the responses the upstream marks as not to be kept or shared
+pub fn is_shareable_response(cache_control: Option<&str>) -> bool {
+    let cache_control = match cache_control {
+        Some(cache_control) => cache_control,
+        None => return true
+    };
+    !cache_control.split(',')
+        .map(|directive| directive.split('=').next().unwrap_or_default().trim())
+        .any(|directive| directive.eq_ignore_ascii_case("private") || directive.eq_ignore_ascii_case("no-store"))
+}
+
+#[derive(Clone, Debug)]
+pub struct StoredResponse {
+    pub status: u32,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    pub stored_at: SystemTime,
+}
+
+impl StoredResponse {
+    pub fn new(status: u32, headers: Vec<(String, String)>, body: Vec<u8>, stored_at: SystemTime) -> Self {
+        let headers = headers.into_iter()
+            .filter(|(name, _)| !SKIPPED_HEADERS.contains(&name.to_ascii_lowercase().as_str()))
+            .collect();
+        StoredResponse { status, headers, body, stored_at }
+    }
+
+    pub fn age(&self, now: SystemTime) -> Duration {
+        now.duration_since(self.stored_at).unwrap_or_default()
+    }
+}
+
+// the last good responses of the endpoints, kept by each worker of the gateway on its own
+pub struct LastGood {
+    max_entries: usize,
+    max_age: Duration,
+    responses: HashMap<String, StoredResponse>,
+}
+
+impl LastGood {
+    pub fn new(max_entries: usize, max_age: Duration) -> Self {
+        LastGood { max_entries, max_age, responses: HashMap::new() }
+    }
+
+    pub fn get(&self, key: &str, now: SystemTime) -> Option<&StoredResponse> {
+        self.responses.get(key)
+            .filter(|response| response.age(now) <= self.max_age)
+    }
+
+    pub fn store(&mut self, key: String, response: StoredResponse) {
+        if self.max_entries == 0 {
+            return;
+        }
+        if !self.responses.contains_key(&key) && self.responses.len() >= self.max_entries {
+            self.evict(response.stored_at);
+        }
+        self.responses.insert(key, response);
+    }
+
+    // the stale responses go first, then the oldest one when all are fresh
+    fn evict(&mut self, now: SystemTime) {
+        let max_age = self.max_age;
+        self.responses.retain(|_, response| response.age(now) <= max_age);
+        if self.responses.len() < self.max_entries {
+            return;
+        }
+        let oldest = self.responses.iter()
+            .min_by_key(|(_, response)| response.stored_at)
+            .map(|(key, _)| key.clone());
+        if let Some(oldest) = oldest {
+            self.responses.remove(&oldest);
+        }
+    }
+}
+
+
+#[cfg(test)]
+fn at(seconds: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)
+}
+
+#[cfg(test)]
+fn response(stored_at: SystemTime) -> StoredResponse {
+    StoredResponse::new(200, vec![("content-type".to_string(), "application/json".to_string())], b"{}".to_vec(), stored_at)
+}
+
+#[test]
+fn test_personal_requests_and_responses() {
+    assert!(!is_personal_request(None, None));
+    assert!(is_personal_request(Some("Bearer token"), None));
+    assert!(is_personal_request(None, Some("session=1")));
+
+    assert!(is_shareable_response(None));
+    assert!(is_shareable_response(Some("public, max-age=60")));
+    assert!(!is_shareable_response(Some("max-age=60, Private")));
+    assert!(!is_shareable_response(Some("no-store")));
+    assert!(!is_shareable_response(Some("private=\"set-cookie\"")));
+}
+
+#[test]
+fn test_stored_headers() {
+    let headers = vec![
+        (":status".to_string(), "200".to_string()),
+        ("Set-Cookie".to_string(), "session=1".to_string()),
+        ("content-length".to_string(), "2".to_string()),
+        ("etag".to_string(), "\"1\"".to_string()),
+    ];
+    let stored = StoredResponse::new(200, headers, b"{}".to_vec(), at(0));
+    assert_eq!(stored.headers, vec![("etag".to_string(), "\"1\"".to_string())]);
+    assert_eq!(stored.age(at(30)), Duration::from_secs(30));
+}
+
+#[test]
+fn test_stale_responses_not_served() {
+    let mut last_good = LastGood::new(10, Duration::from_secs(60));
+    last_good.store("GET host/orders".to_string(), response(at(0)));
+
+    assert!(last_good.get("GET host/orders", at(60)).is_some());
+    assert!(last_good.get("GET host/orders", at(61)).is_none());
+    assert!(last_good.get("GET host/items", at(0)).is_none());
+}
+
+#[test]
+fn test_eviction() {
+    let mut last_good = LastGood::new(2, Duration::from_secs(60));
+    last_good.store("a".to_string(), response(at(0)));
+    last_good.store("b".to_string(), response(at(10)));
+
+    // the oldest goes first when all are fresh
+    last_good.store("c".to_string(), response(at(20)));
+    assert!(last_good.get("a", at(20)).is_none());
+    assert!(last_good.get("b", at(20)).is_some());
+
+    // the stale ones go first
+    last_good.store("d".to_string(), response(at(75)));
+    assert!(last_good.get("c", at(75)).is_some());
+    assert!(last_good.get("d", at(75)).is_some());
+
+    // nothing is kept without entries
+    let mut disabled = LastGood::new(0, Duration::from_secs(60));
+    disabled.store("a".to_string(), response(at(0)));
+    assert!(disabled.get("a", at(0)).is_none());
+}
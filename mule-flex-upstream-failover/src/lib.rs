@@ -0,0 +1,317 @@
+use proxy_wasm::traits::*;
+use proxy_wasm::types::*;
+use log::{debug, info, warn, error};
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use std::time::Duration;
+
+mod flags;
+mod last_good;
+use flags::{default_flags, ResponseFlag};
+use last_good::{is_personal_request, is_shareable_response, LastGood, StoredResponse};
+
+const RESPONSE_FLAGS: [&str; 2] = ["response", "flags"];
+
+proxy_wasm::main! {{
+    proxy_wasm::set_log_level(LogLevel::Trace);
+    proxy_wasm::set_root_context(|_| -> Box<dyn RootContext> {
+        Box::new(UpstreamFailoverRoot {
+            config: None,
+            last_good: Rc::new(RefCell::new(LastGood::new(0, Duration::ZERO))),
+        })
+    });
+}}
+
+#[derive(Clone, Deserialize, Debug)]
+struct Fallback {
+    #[serde(alias = "statusCode", default = "default_status_code")]
+    status_code: u32,
+
+    #[serde(alias = "contentType", default = "default_content_type")]
+    content_type: String,
+
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+
+    #[serde(default)]
+    body: String,
+}
+
+#[derive(Clone, Deserialize, Debug)]
+struct PolicyConfig {
+    // the flags telling the upstream could not be reached
+    #[serde(alias = "responseFlags", default = "default_flags")]
+    response_flags: Vec<ResponseFlag>,
+
+    // only the read-only endpoints fail over by default
+    #[serde(default = "default_methods")]
+    methods: Vec<String>,
+
+    #[serde(default)]
+    fallback: Option<Fallback>,
+
+    // serves the last good response of the endpoint, before the fallback
+    #[serde(alias = "lastGood", default)]
+    last_good: bool,
+
+    #[serde(alias = "lastGoodMaxEntries", default = "default_last_good_max_entries")]
+    last_good_max_entries: usize,
+
+    #[serde(alias = "lastGoodMaxBodyBytes", default = "default_last_good_max_body_bytes")]
+    last_good_max_body_bytes: usize,
+
+    #[serde(alias = "lastGoodMaxAgeSeconds", default = "default_last_good_max_age_seconds")]
+    last_good_max_age_seconds: u64,
+
+    // tells the client the response did not come from the upstream, none when empty
+    #[serde(alias = "failoverHeader", default = "default_failover_header")]
+    failover_header: String,
+}
+
+fn default_status_code() -> u32 {
+    200
+}
+
+fn default_content_type() -> String {
+    "application/json".to_string()
+}
+
+fn default_methods() -> Vec<String> {
+    vec!["GET".to_string(), "HEAD".to_string()]
+}
+
+fn default_last_good_max_entries() -> usize {
+    100
+}
+
+fn default_last_good_max_body_bytes() -> usize {
+    64 * 1024
+}
+
+fn default_last_good_max_age_seconds() -> u64 {
+    300
+}
+
+fn default_failover_header() -> String {
+    "x-failover".to_string()
+}
+
+// a good response of the upstream, kept as it goes to the client
+struct Capture {
+    status: u32,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+struct UpstreamFailover {
+    config: Rc<PolicyConfig>,
+    mask: u64,
+    last_good: Rc<RefCell<LastGood>>,
+    // the endpoint of a request allowed to fail over
+    key: Option<String>,
+    // a request with credentials, whose responses are neither kept nor replayed
+    personal: bool,
+    // the body of the response served instead of the one of the proxy
+    replacement: Option<Vec<u8>>,
+    capture: Option<Capture>,
+}
+
+impl UpstreamFailover {
+
+    fn response_flags(&self) -> u64 {
+        self.get_property(RESPONSE_FLAGS.to_vec())
+            .as_deref()
+            .and_then(flags::parse)
+            .unwrap_or(0)
+    }
+
+    fn fail_over(&mut self, key: &str, flags: u64, end_of_stream: bool) {
+        let now = self.get_current_time();
+        let stored = if self.config.last_good && !self.personal {
+            self.last_good.borrow().get(key, now).cloned()
+        } else {
+            None
+        };
+
+        let (status, mut headers, body, source) = match (stored, &self.config.fallback) {
+            (Some(stored), _) => {
+                let mut headers = stored.headers;
+                headers.push(("age".to_string(), stored.age(now).as_secs().to_string()));
+                (stored.status, headers, stored.body, "last good")
+            }
+            (None, Some(fallback)) => {
+                let mut headers: Vec<(String, String)> = fallback.headers.iter()
+                    .map(|(name, value)| (name.to_ascii_lowercase(), value.clone()))
+                    .collect();
+                headers.push(("content-type".to_string(), fallback.content_type.clone()));
+                (fallback.status_code, headers, fallback.body.clone().into_bytes(), "fallback")
+            }
+            (None, None) => {
+                info!("Upstream of {} unreachable with flags {:#x}, no last good response to serve", key, flags);
+                return;
+            }
+        };
+        info!("Upstream of {} unreachable with flags {:#x}, serving the {} response", key, flags, source);
+
+        // a reply without a body can not get one
+        let body = if end_of_stream && !body.is_empty() {
+            warn!("The response of {} has no body, serving the {} response without it", key, source);
+            Vec::new()
+        } else {
+            body
+        };
+
+        headers.push((":status".to_string(), status.to_string()));
+        headers.push(("content-length".to_string(), body.len().to_string()));
+        if !self.config.failover_header.is_empty() {
+            headers.push((self.config.failover_header.clone(), source.replace(' ', "-")));
+        }
+        self.set_http_response_headers(headers.iter().map(|(name, value)| (name.as_str(), value.as_str())).collect());
+
+        if !end_of_stream {
+            self.replacement = Some(body);
+        }
+    }
+
+    fn capture_chunk(&mut self, body_size: usize, end_of_stream: bool) {
+        let chunk = self.get_http_response_body(0, body_size).unwrap_or_default();
+        let capture = match self.capture.as_mut() {
+            Some(capture) => capture,
+            None => return
+        };
+        if capture.body.len() + chunk.len() > self.config.last_good_max_body_bytes {
+            debug!("Response larger than {} bytes, not kept as the last good one", self.config.last_good_max_body_bytes);
+            self.capture = None;
+            return;
+        }
+        capture.body.extend_from_slice(&chunk);
+
+        if end_of_stream {
+            if let (Some(capture), Some(key)) = (self.capture.take(), self.key.clone()) {
+                let response = StoredResponse::new(capture.status, capture.headers, capture.body, self.get_current_time());
+                self.last_good.borrow_mut().store(key, response);
+            }
+        }
+    }
+}
+
+impl Context for UpstreamFailover {}
+
+impl HttpContext for UpstreamFailover {
+
+    fn on_http_request_headers(&mut self, _num_headers: usize, _end_of_stream: bool) -> Action {
+        let method = self.get_http_request_header(":method").unwrap_or_default();
+        if !self.config.methods.iter().any(|allowed| allowed.eq_ignore_ascii_case(&method)) {
+            return Action::Continue;
+        }
+
+        let authority = self.get_http_request_header(":authority").unwrap_or_default();
+        let path = self.get_http_request_header(":path").unwrap_or_default();
+        self.key = Some(format!("{} {}{}", method.to_ascii_uppercase(), authority, path));
+        self.personal = is_personal_request(
+            self.get_http_request_header("authorization").as_deref(),
+            self.get_http_request_header("cookie").as_deref(),
+        );
+        Action::Continue
+    }
+
+    fn on_http_response_headers(&mut self, _num_headers: usize, end_of_stream: bool) -> Action {
+        let key = match self.key.clone() {
+            Some(key) => key,
+            None => return Action::Continue
+        };
+
+        let flags = self.response_flags();
+        if flags & self.mask != 0 {
+            self.fail_over(&key, flags, end_of_stream);
+            return Action::Continue;
+        }
+
+        let status = self.get_http_response_header(":status")
+            .and_then(|status| status.parse::<u32>().ok())
+            .unwrap_or_default();
+        let shareable = !self.personal
+            && is_shareable_response(self.get_http_response_header("cache-control").as_deref());
+        if self.config.last_good && shareable && (200..300).contains(&status) && !end_of_stream {
+            self.capture = Some(Capture {
+                status,
+                headers: self.get_http_response_headers(),
+                body: Vec::new(),
+            });
+        }
+        Action::Continue
+    }
+
+    fn on_http_response_body(&mut self, body_size: usize, end_of_stream: bool) -> Action {
+        if self.capture.is_some() {
+            self.capture_chunk(body_size, end_of_stream);
+            return Action::Continue;
+        }
+        if self.replacement.is_none() {
+            return Action::Continue;
+        }
+
+        // the body of the proxy is buffered to be replaced at once
+        if !end_of_stream {
+            return Action::Pause;
+        }
+        if let Some(body) = self.replacement.take() {
+            self.set_http_response_body(0, body_size, &body);
+        }
+        Action::Continue
+    }
+}
+
+struct UpstreamFailoverRoot {
+    config: Option<Rc<PolicyConfig>>,
+    last_good: Rc<RefCell<LastGood>>,
+}
+
+impl Context for UpstreamFailoverRoot {}
+
+impl RootContext for UpstreamFailoverRoot {
+    fn on_configure(&mut self, _: usize) -> bool {
+        let config = match self.get_plugin_configuration() {
+            Some(config_bytes) => serde_json::from_slice::<PolicyConfig>(config_bytes.as_slice()),
+            None => serde_json::from_str::<PolicyConfig>("{}"),
+        };
+
+        match config {
+            Ok(config) if config.fallback.is_none() && !config.last_good => {
+                error!("Invalid upstream failover configuration: neither a fallback nor the last good responses to serve");
+                false
+            }
+            Ok(config) => {
+                self.last_good = Rc::new(RefCell::new(LastGood::new(
+                    config.last_good_max_entries,
+                    Duration::from_secs(config.last_good_max_age_seconds),
+                )));
+                self.config = Some(Rc::new(config));
+                true
+            }
+            Err(err) => {
+                error!("Invalid upstream failover configuration: {}", err);
+                false
+            }
+        }
+    }
+
+    fn create_http_context(&self, _: u32) -> Option<Box<dyn HttpContext>> {
+        let config = self.config.clone()?;
+        Some(Box::new(UpstreamFailover {
+            mask: flags::mask(&config.response_flags),
+            config,
+            last_good: self.last_good.clone(),
+            key: None,
+            personal: false,
+            replacement: None,
+            capture: None,
+        }))
+    }
+
+    fn get_type(&self) -> Option<ContextType> {
+        Some(ContextType::HttpContext)
+    }
+}
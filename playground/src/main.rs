@@ -154,6 +154,8 @@ wVIXRfPThZaZmjZRSPiXpXfUU47WlOhStZ7btWhrgRHuntUf3GVT84gv0uHyOSMX
 
 
 
+    // the playground signs with a fixed PKCS#8 key and has no pdk dependency, so it keeps
+    // jwt-simple's own parsing instead of pdk::api::keys
     let key = RS256KeyPair::from_pem(&pem);
     println!("Key Created");
     match key {